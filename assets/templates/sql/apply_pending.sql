@@ -0,0 +1,249 @@
+-- apply_pending.sql
+-- Assumes parameters :scan_id and :staging_table are passed in.
+-- Promotes the deltas review_staging.sql left in filesystem.pending_file_changes,
+-- as reviewed: a pending row deleted during review is not applied, and the
+-- tracked row of its file is left as it was. The staging rows only supply the
+-- names and types of added and modified files, and bump last_seen_scan of the
+-- files without a pending change.
+BEGIN;
+
+WITH pending AS (
+    SELECT
+        p.*
+    FROM
+        filesystem.pending_file_changes AS p
+    WHERE
+        p.scan_id = :scan_id
+),
+staged AS (
+    SELECT
+        s.*
+    FROM
+        :staging_table AS s
+    WHERE
+        s.scan_id = :scan_id
+),
+-- 1) record the changes, with the type of the files deleted or modified as
+-- tracked before this statement
+ins_changes AS (
+    INSERT INTO
+        filesystem.file_changes (
+            scan_id,
+            file_path,
+            change_type,
+            old_size_bytes,
+            new_size_bytes,
+            old_mtime,
+            new_mtime,
+            old_file_type,
+            old_security_label,
+            new_security_label,
+            old_fingerprint,
+            new_fingerprint,
+            old_symlink_target,
+            new_symlink_target,
+            old_mode,
+            new_mode,
+            old_uid,
+            new_uid,
+            old_gid,
+            new_gid
+        )
+    SELECT
+        :scan_id,
+        p.file_path,
+        p.change_type,
+        p.old_size_bytes,
+        p.new_size_bytes,
+        p.old_mtime,
+        p.new_mtime,
+        CASE
+            WHEN p.change_type IN ('deleted', 'modified') THEN f.file_type
+        END,
+        p.old_security_label,
+        p.new_security_label,
+        p.old_fingerprint,
+        p.new_fingerprint,
+        p.old_symlink_target,
+        p.new_symlink_target,
+        p.old_mode,
+        p.new_mode,
+        p.old_uid,
+        p.new_uid,
+        p.old_gid,
+        p.new_gid
+    FROM
+        pending AS p
+        LEFT JOIN filesystem.files AS f ON f.file_path = p.file_path
+),
+-- 2) deleted files
+deleted AS (
+    DELETE FROM
+        filesystem.files AS f USING pending AS p
+    WHERE
+        p.change_type = 'deleted'
+        AND f.file_path = p.file_path
+),
+-- 3) added files
+ins_new AS (
+    INSERT INTO
+        filesystem.files (
+            file_name,
+            file_type,
+            file_size_bytes,
+            file_path,
+            file_mtime,
+            file_fingerprint,
+            security_label,
+            symlink_target,
+            file_mode,
+            file_uid,
+            file_gid,
+            last_seen_scan,
+            last_updated
+        )
+    SELECT
+        s.file_name,
+        s.file_type,
+        p.new_size_bytes,
+        p.file_path,
+        p.new_mtime,
+        p.new_fingerprint,
+        p.new_security_label,
+        p.new_symlink_target,
+        p.new_mode,
+        p.new_uid,
+        p.new_gid,
+        :scan_id,
+        now()
+    FROM
+        pending AS p
+        JOIN staged AS s ON s.file_path = p.file_path
+    WHERE
+        p.change_type = 'added'
+),
+-- 4) modified files, keeping the label and ownership where this scan
+-- recorded none
+upd_mod AS (
+    UPDATE
+        filesystem.files AS f
+    SET
+        file_name = s.file_name,
+        file_type = s.file_type,
+        file_size_bytes = p.new_size_bytes,
+        file_mtime = p.new_mtime,
+        security_label = COALESCE(p.new_security_label, f.security_label),
+        -- NULL unless this scan hashed the contents
+        file_fingerprint = p.new_fingerprint,
+        symlink_target = p.new_symlink_target,
+        file_mode = COALESCE(p.new_mode, f.file_mode),
+        file_uid = COALESCE(p.new_uid, f.file_uid),
+        file_gid = COALESCE(p.new_gid, f.file_gid),
+        last_seen_scan = :scan_id,
+        last_updated = now()
+    FROM
+        pending AS p
+        JOIN staged AS s ON s.file_path = p.file_path
+    WHERE
+        p.change_type = 'modified'
+        AND f.file_path = p.file_path
+),
+-- 5) relabeled and permission-changed files
+upd_relabel AS (
+    UPDATE
+        filesystem.files AS f
+    SET
+        file_mtime = p.new_mtime,
+        security_label = COALESCE(p.new_security_label, f.security_label),
+        file_mode = COALESCE(p.new_mode, f.file_mode),
+        file_uid = COALESCE(p.new_uid, f.file_uid),
+        file_gid = COALESCE(p.new_gid, f.file_gid),
+        last_seen_scan = :scan_id,
+        last_updated = now()
+    FROM
+        pending AS p
+    WHERE
+        p.change_type IN ('relabeled', 'permissions_changed')
+        AND f.file_path = p.file_path
+),
+-- 6) files without a pending change: bump last_seen_scan and fill in the
+-- label, ownership and fingerprint where none was tracked, never replacing
+-- one; the files of rejected modifications stay as they were
+upd_unchanged AS (
+    UPDATE
+        filesystem.files AS f
+    SET
+        file_mtime = s.file_mtime,
+        security_label = COALESCE(f.security_label, s.security_label),
+        file_mode = COALESCE(f.file_mode, s.file_mode),
+        file_uid = COALESCE(f.file_uid, s.file_uid),
+        file_gid = COALESCE(f.file_gid, s.file_gid),
+        file_fingerprint = COALESCE(s.file_fingerprint, f.file_fingerprint),
+        last_seen_scan = :scan_id,
+        last_updated = now()
+    FROM
+        staged AS s
+    WHERE
+        s.file_path = f.file_path
+        AND NOT EXISTS (
+            SELECT
+                1
+            FROM
+                pending AS p
+            WHERE
+                p.file_path = s.file_path
+        )
+        AND NOT (
+            filesystem.is_modified(
+                f.file_size_bytes,
+                f.file_mtime,
+                f.file_fingerprint,
+                s.file_size_bytes,
+                s.file_mtime,
+                s.file_fingerprint
+            )
+            OR s.symlink_target IS DISTINCT FROM f.symlink_target
+        )
+) -- kick off the CTEs
+SELECT
+    1;
+
+-- 7) per-extension totals of this scan, for charting extension trends
+INSERT INTO
+    filesystem.extension_stats (
+        scan_id,
+        file_type,
+        file_count,
+        total_size_bytes
+    )
+SELECT
+    :scan_id,
+    s.file_type,
+    COUNT(*),
+    SUM(s.file_size_bytes)
+FROM
+    :staging_table AS s
+WHERE
+    s.scan_id = :scan_id
+GROUP BY
+    s.file_type ON CONFLICT (scan_id, file_type) DO
+UPDATE
+SET
+    file_count = EXCLUDED.file_count,
+    total_size_bytes = EXCLUDED.total_size_bytes;
+
+-- 8) the pending rows are promoted
+DELETE FROM
+    filesystem.pending_file_changes
+WHERE
+    scan_id = :scan_id;
+
+-- 9) record the deltas as applied in the same transaction
+UPDATE
+    filesystem.scan_runs
+SET
+    scan_phase = 'processed'
+WHERE
+    scan_id = :scan_id;
+
+COMMIT;
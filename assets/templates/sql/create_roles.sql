@@ -0,0 +1,31 @@
+-- create_roles.sql
+-- Group roles separating read-only consumers (dashboards, `search`,
+-- `list_scans`) from the hosts and operators that scan, apply, roll back and
+-- maintain. Grant them to login roles, e.g. `GRANT fs_delta_tracker_read TO grafana;`.
+-- Safe to re-run; initialize_db and upgrade_db re-apply it after creating tables.
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'fs_delta_tracker_read') THEN
+        CREATE ROLE fs_delta_tracker_read NOLOGIN;
+    END IF;
+    IF NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'fs_delta_tracker_admin') THEN
+        CREATE ROLE fs_delta_tracker_admin NOLOGIN;
+    END IF;
+END $$;
+
+-- Read-only: every table and view, nothing else
+GRANT USAGE ON SCHEMA filesystem TO fs_delta_tracker_read;
+GRANT SELECT ON ALL TABLES IN SCHEMA filesystem TO fs_delta_tracker_read;
+GRANT EXECUTE ON ALL FUNCTIONS IN SCHEMA filesystem TO fs_delta_tracker_read;
+
+-- Admin: everything the tools write. The audit log stays append-only for
+-- admins too, enforced by its triggers.
+GRANT USAGE, CREATE ON SCHEMA filesystem TO fs_delta_tracker_admin;
+GRANT ALL ON ALL TABLES IN SCHEMA filesystem TO fs_delta_tracker_admin;
+GRANT ALL ON ALL SEQUENCES IN SCHEMA filesystem TO fs_delta_tracker_admin;
+GRANT EXECUTE ON ALL FUNCTIONS IN SCHEMA filesystem TO fs_delta_tracker_admin;
+
+-- Tables and views created later by the same owner
+ALTER DEFAULT PRIVILEGES IN SCHEMA filesystem GRANT SELECT ON TABLES TO fs_delta_tracker_read;
+ALTER DEFAULT PRIVILEGES IN SCHEMA filesystem GRANT ALL ON TABLES TO fs_delta_tracker_admin;
+ALTER DEFAULT PRIVILEGES IN SCHEMA filesystem GRANT ALL ON SEQUENCES TO fs_delta_tracker_admin;
@@ -0,0 +1,7 @@
+-- create_trigram_index.sql
+-- Optional trigram index for `search`: makes LIKE/ILIKE matches on path
+-- fragments such as '%fastq%' use an index instead of a sequential scan of
+-- filesystem.files. Needs the pg_trgm extension; safe to re-run.
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS files_path_trgm_idx ON filesystem.files USING GIN (file_path gin_trgm_ops);
@@ -0,0 +1,136 @@
+-- create_views.sql
+-- Reporting views for dashboards (e.g. Grafana). Safe to re-run: every view is
+-- created with CREATE OR REPLACE so their names stay stable across upgrades.
+-- Views run with the caller's privileges, so tenant isolation applies to them.
+
+-- One row per scan with its results flattened out of scan_metadata
+CREATE OR REPLACE VIEW filesystem.scan_summary WITH (security_invoker = true) AS
+SELECT
+    r.scan_id,
+    r.scan_root,
+    r.scan_status,
+    r.started_at,
+    r.finished_at,
+    EXTRACT(EPOCH FROM (r.finished_at - r.started_at))::float8 AS duration_s,
+    r.total_paths_count,
+    r.added_files_count,
+    r.modified_files_count,
+    r.removed_files_count,
+    r.new_data_mb,
+    r.modified_data_mb,
+    r.deleted_data_mb,
+    COALESCE(r.new_data_mb, 0) - COALESCE(r.deleted_data_mb, 0) AS net_growth_mb,
+    r.scan_metadata ->> 'hostname' AS hostname,
+    (r.scan_metadata ->> 'crawler_files_per_second')::float8 AS crawler_files_per_second,
+    r.anomaly_flags,
+    r.notes
+FROM
+    filesystem.scan_runs AS r;
+
+-- Growth per root per day, over completed scans
+CREATE OR REPLACE VIEW filesystem.daily_growth WITH (security_invoker = true) AS
+SELECT
+    date_trunc('day', r.started_at)::date AS day,
+    r.scan_root,
+    COUNT(*) AS scans_count,
+    SUM(COALESCE(r.added_files_count, 0)) AS added_files_count,
+    SUM(COALESCE(r.modified_files_count, 0)) AS modified_files_count,
+    SUM(COALESCE(r.removed_files_count, 0)) AS removed_files_count,
+    SUM(COALESCE(r.new_data_mb, 0)) AS new_data_mb,
+    SUM(COALESCE(r.deleted_data_mb, 0)) AS deleted_data_mb,
+    SUM(COALESCE(r.new_data_mb, 0) - COALESCE(r.deleted_data_mb, 0)) AS net_growth_mb,
+    MAX(r.total_paths_count) AS max_total_paths_count
+FROM
+    filesystem.scan_runs AS r
+WHERE
+    r.scan_status = 'completed'
+GROUP BY
+    1,
+    2;
+
+-- Changes per scan per parent directory, ranked by number of changed files
+CREATE OR REPLACE VIEW filesystem.top_changed_dirs WITH (security_invoker = true) AS
+WITH per_dir AS (
+    SELECT
+        c.scan_id,
+        c.parent_dir AS dir_path,
+        COUNT(*) FILTER (WHERE c.change_type = 'added') AS added_files_count,
+        COUNT(*) FILTER (WHERE c.change_type = 'modified') AS modified_files_count,
+        COUNT(*) FILTER (WHERE c.change_type = 'deleted') AS removed_files_count,
+        COUNT(*) AS changed_files_count,
+        SUM(COALESCE(c.new_size_bytes, 0) - COALESCE(c.old_size_bytes, 0)) AS net_change_bytes
+    FROM
+        filesystem.file_changes AS c
+    GROUP BY
+        1,
+        2
+)
+SELECT
+    per_dir.*,
+    rank() OVER (
+        PARTITION BY per_dir.scan_id
+        ORDER BY
+            per_dir.changed_files_count DESC
+    ) AS change_rank
+FROM
+    per_dir;
+
+-- Current usage attributed to owners (see `load_owners`), falling back to the
+-- top-level directory under each scan root
+CREATE OR REPLACE VIEW filesystem.per_owner_usage WITH (security_invoker = true) AS
+SELECT
+    r.scan_root,
+    filesystem.path_owner(f.file_path, r.scan_root) AS owner,
+    COUNT(*) AS file_count,
+    SUM(f.file_size_bytes) AS total_bytes,
+    SUM(f.file_size_bytes)::float8 / 1024.0 / 1024.0 / 1024.0 AS total_gb
+FROM
+    filesystem.files AS f
+    JOIN filesystem.scan_runs AS r ON r.scan_id = f.last_seen_scan
+GROUP BY
+    1,
+    2;
+
+-- Size of each root over time, stitched from completed full scans and finished
+-- quick scans (see `--cadence`), with the change since the previous point of
+-- either kind. total_size_bytes is NULL for full scans that did not record it.
+CREATE OR REPLACE VIEW filesystem.growth_timeline WITH (security_invoker = true) AS
+WITH points AS (
+    SELECT
+        r.scan_root,
+        'full' AS scan_kind,
+        r.scan_id AS scan_ref,
+        r.started_at,
+        COALESCE(
+            r.total_paths_count,
+            (r.scan_metadata ->> 'total_files_processed')::float8::bigint
+        ) AS total_files,
+        (r.scan_metadata ->> 'total_bytes')::bigint AS total_size_bytes
+    FROM
+        filesystem.scan_runs AS r
+    WHERE
+        r.scan_status = 'completed'
+    UNION ALL
+    SELECT
+        q.scan_root,
+        'quick' AS scan_kind,
+        q.quick_scan_id AS scan_ref,
+        q.started_at,
+        q.total_files,
+        q.total_size_bytes
+    FROM
+        filesystem.quick_scans AS q
+    WHERE
+        q.finished_at IS NOT NULL
+)
+SELECT
+    points.*,
+    points.total_files - lag(points.total_files) OVER w AS files_change,
+    points.total_size_bytes - lag(points.total_size_bytes) OVER w AS size_change_bytes
+FROM
+    points
+WINDOW w AS (
+    PARTITION BY points.scan_root
+    ORDER BY
+        points.started_at
+);
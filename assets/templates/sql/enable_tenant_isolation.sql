@@ -0,0 +1,169 @@
+-- enable_tenant_isolation.sql
+-- Row-level security so members of fs_delta_tracker_tenant_<tenant> only see
+-- scans, files and changes of their tenant's roots (and of untenanted ones).
+-- Members of fs_delta_tracker_admin, the table owner and superusers see
+-- everything. Requires the roles of create_roles.sql; safe to re-run.
+
+-- Tenants the current user belongs to, through fs_delta_tracker_tenant_<tenant> roles
+CREATE
+OR REPLACE FUNCTION filesystem.visible_tenants() RETURNS TEXT [] LANGUAGE sql STABLE AS $$
+SELECT
+    COALESCE(
+        array_agg(substr(r.rolname, length('fs_delta_tracker_tenant_') + 1)),
+        '{}'
+    )
+FROM
+    pg_roles AS r
+WHERE
+    r.rolname LIKE 'fs\_delta\_tracker\_tenant\_%'
+    AND pg_has_role(r.oid, 'MEMBER') $$;
+
+CREATE
+OR REPLACE FUNCTION filesystem.is_tenant_admin() RETURNS BOOLEAN LANGUAGE sql STABLE AS $$
+SELECT
+    pg_has_role('fs_delta_tracker_admin', 'MEMBER') $$;
+
+GRANT EXECUTE ON FUNCTION filesystem.visible_tenants() TO fs_delta_tracker_read, fs_delta_tracker_admin;
+GRANT EXECUTE ON FUNCTION filesystem.is_tenant_admin() TO fs_delta_tracker_read, fs_delta_tracker_admin;
+
+-- Scans: by their tenant. The sub-selects are evaluated once per query.
+ALTER TABLE filesystem.scan_runs ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.scan_runs;
+
+CREATE POLICY tenant_isolation ON filesystem.scan_runs USING (
+    tenant IS NULL
+    OR tenant IN (SELECT unnest(filesystem.visible_tenants()))
+    OR (SELECT filesystem.is_tenant_admin())
+);
+
+-- Everything else: by the scan it belongs to, itself filtered by the policy above
+ALTER TABLE filesystem.files ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.files;
+
+CREATE POLICY tenant_isolation ON filesystem.files USING (
+    (SELECT filesystem.is_tenant_admin())
+    OR last_seen_scan IN (SELECT scan_id FROM filesystem.scan_runs)
+);
+
+ALTER TABLE filesystem.file_changes ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.file_changes;
+
+CREATE POLICY tenant_isolation ON filesystem.file_changes USING (
+    (SELECT filesystem.is_tenant_admin())
+    OR scan_id IN (SELECT scan_id FROM filesystem.scan_runs)
+);
+
+ALTER TABLE filesystem.pending_file_changes ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.pending_file_changes;
+
+CREATE POLICY tenant_isolation ON filesystem.pending_file_changes USING (
+    (SELECT filesystem.is_tenant_admin())
+    OR scan_id IN (SELECT scan_id FROM filesystem.scan_runs)
+);
+
+ALTER TABLE filesystem.directories ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.directories;
+
+CREATE POLICY tenant_isolation ON filesystem.directories USING (
+    (SELECT filesystem.is_tenant_admin())
+    OR last_seen_scan IN (SELECT scan_id FROM filesystem.scan_runs)
+);
+
+ALTER TABLE filesystem.dir_changes ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.dir_changes;
+
+CREATE POLICY tenant_isolation ON filesystem.dir_changes USING (
+    (SELECT filesystem.is_tenant_admin())
+    OR scan_id IN (SELECT scan_id FROM filesystem.scan_runs)
+);
+
+ALTER TABLE filesystem.hot_dirs ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.hot_dirs;
+
+CREATE POLICY tenant_isolation ON filesystem.hot_dirs USING (
+    (SELECT filesystem.is_tenant_admin())
+    OR scan_id IN (SELECT scan_id FROM filesystem.scan_runs)
+);
+
+ALTER TABLE filesystem.scan_errors ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.scan_errors;
+
+CREATE POLICY tenant_isolation ON filesystem.scan_errors USING (
+    (SELECT filesystem.is_tenant_admin())
+    OR scan_id IN (SELECT scan_id FROM filesystem.scan_runs)
+);
+
+ALTER TABLE filesystem.scan_shards ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.scan_shards;
+
+CREATE POLICY tenant_isolation ON filesystem.scan_shards USING (
+    (SELECT filesystem.is_tenant_admin())
+    OR scan_id IN (SELECT scan_id FROM filesystem.scan_runs)
+);
+
+ALTER TABLE filesystem.load_checkpoints ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.load_checkpoints;
+
+CREATE POLICY tenant_isolation ON filesystem.load_checkpoints USING (
+    (SELECT filesystem.is_tenant_admin())
+    OR scan_id IN (SELECT scan_id FROM filesystem.scan_runs)
+);
+
+ALTER TABLE filesystem.extension_stats ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.extension_stats;
+
+CREATE POLICY tenant_isolation ON filesystem.extension_stats USING (
+    (SELECT filesystem.is_tenant_admin())
+    OR scan_id IN (SELECT scan_id FROM filesystem.scan_runs)
+);
+
+ALTER TABLE filesystem.catalog_syncs ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.catalog_syncs;
+
+CREATE POLICY tenant_isolation ON filesystem.catalog_syncs USING (
+    (SELECT filesystem.is_tenant_admin())
+    OR scan_id IN (SELECT scan_id FROM filesystem.scan_runs)
+);
+
+-- Quick scans: by their tenant, like scans; their summaries by the quick scan
+ALTER TABLE filesystem.quick_scans ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.quick_scans;
+
+CREATE POLICY tenant_isolation ON filesystem.quick_scans USING (
+    tenant IS NULL
+    OR tenant IN (SELECT unnest(filesystem.visible_tenants()))
+    OR (SELECT filesystem.is_tenant_admin())
+);
+
+ALTER TABLE filesystem.dir_summaries ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.dir_summaries;
+
+CREATE POLICY tenant_isolation ON filesystem.dir_summaries USING (
+    (SELECT filesystem.is_tenant_admin())
+    OR quick_scan_id IN (SELECT quick_scan_id FROM filesystem.quick_scans)
+);
+
+-- Entries without a scan describe the deployment, not a tenant's paths
+ALTER TABLE filesystem.audit_log ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS tenant_isolation ON filesystem.audit_log;
+
+CREATE POLICY tenant_isolation ON filesystem.audit_log USING (
+    (SELECT filesystem.is_tenant_admin())
+    OR scan_id IS NULL
+    OR scan_id IN (SELECT scan_id FROM filesystem.scan_runs)
+);
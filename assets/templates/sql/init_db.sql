@@ -7,6 +7,38 @@ CREATE SCHEMA IF NOT EXISTS filesystem;
 -- Drop existing tables to ensure a clean slate
 DROP TABLE IF EXISTS filesystem.file_changes CASCADE;
 
+DROP TABLE IF EXISTS filesystem.dir_changes CASCADE;
+
+DROP TABLE IF EXISTS filesystem.staging_dirs CASCADE;
+
+DROP TABLE IF EXISTS filesystem.directories CASCADE;
+
+DROP TABLE IF EXISTS filesystem.dir_summaries CASCADE;
+
+DROP TABLE IF EXISTS filesystem.quick_scans CASCADE;
+
+DROP TABLE IF EXISTS filesystem.pending_file_changes CASCADE;
+
+DROP TABLE IF EXISTS filesystem.hot_dirs CASCADE;
+
+DROP TABLE IF EXISTS filesystem.scan_errors CASCADE;
+
+DROP TABLE IF EXISTS filesystem.scan_shards CASCADE;
+
+DROP TABLE IF EXISTS filesystem.load_checkpoints CASCADE;
+
+DROP TABLE IF EXISTS filesystem.catalog_syncs CASCADE;
+
+DROP TABLE IF EXISTS filesystem.event_outbox CASCADE;
+
+DROP TABLE IF EXISTS filesystem.change_subscriptions CASCADE;
+
+DROP TABLE IF EXISTS filesystem.extension_stats CASCADE;
+
+DROP TABLE IF EXISTS filesystem.delta_budgets CASCADE;
+
+DROP TABLE IF EXISTS filesystem.directory_owners CASCADE;
+
 DROP TABLE IF EXISTS filesystem.files CASCADE;
 
 DROP TABLE IF EXISTS filesystem.scan_runs CASCADE;
@@ -42,10 +74,51 @@ SELECT
         '.'
     ) :: ltree $$;
 
+-- Directory of a path, as used by the directory rollups ('/a/b/c.txt' -> '/a/b')
+CREATE
+OR REPLACE FUNCTION filesystem.parent_dir(path TEXT) RETURNS TEXT LANGUAGE sql IMMUTABLE AS $$
+SELECT
+    regexp_replace(path, '/[^/]*$', '') $$;
+
+-- Number of components of a path, ignoring leading, trailing and repeated
+-- slashes ('/a//b/c.txt/' -> 3)
+CREATE
+OR REPLACE FUNCTION filesystem.path_depth(path TEXT) RETURNS INT LANGUAGE sql IMMUTABLE AS $$
+SELECT
+    cardinality(regexp_split_to_array(btrim(path, '/'), '/+')) $$;
+
+-- Whether a file's contents changed between two sightings: by fingerprint
+-- when both were hashed with the same algorithm (`--content-hash`), so that
+-- touch-only updates do not count, by size and mtime otherwise
+CREATE
+OR REPLACE FUNCTION filesystem.is_modified(
+    old_size BIGINT,
+    old_mtime TIMESTAMPTZ,
+    old_fingerprint TEXT,
+    new_size BIGINT,
+    new_mtime TIMESTAMPTZ,
+    new_fingerprint TEXT
+) RETURNS BOOLEAN LANGUAGE sql IMMUTABLE AS $$
+SELECT
+    CASE
+        WHEN split_part(old_fingerprint, ':', 1) = split_part(new_fingerprint, ':', 1) THEN old_size <> new_size
+        OR old_fingerprint <> new_fingerprint
+        ELSE old_size <> new_size
+        OR old_mtime <> new_mtime
+    END $$;
+
 -- Create the tables (and indices) for the filesystem schema
 CREATE TABLE IF NOT EXISTS filesystem.scan_runs (
     scan_id SERIAL PRIMARY KEY,
     scan_root TEXT NOT NULL,
+    -- every root of a scan of several, scan_root first (`--extra-root`); NULL if one
+    scan_roots TEXT[] NULL,
+    -- department the root belongs to, see enable_tenant_isolation.sql; NULL if shared
+    tenant TEXT NULL,
+    -- fingerprint of the key paths below the root are encrypted with; NULL if plain
+    path_key_id TEXT NULL,
+    -- host that ran the scan, used to find scans orphaned by a crash on it
+    hostname TEXT NULL,
     started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
     finished_at TIMESTAMPTZ NULL,
     total_paths_count BIGINT NULL,
@@ -55,21 +128,46 @@ CREATE TABLE IF NOT EXISTS filesystem.scan_runs (
     new_data_mb FLOAT NULL,
     modified_data_mb FLOAT NULL,
     deleted_data_mb FLOAT NULL,
+    -- running, pending_review, completed, flagged, voided
+    scan_status TEXT NOT NULL DEFAULT 'running',
+    -- set while the crawl is paused with `pause_scan`
+    paused_at TIMESTAMPTZ NULL,
+    -- last pipeline phase completed: crawled, loaded, processed, finalized
+    scan_phase TEXT NULL,
+    -- sanity-guard findings, e.g. {"low_file_count": {"expected_min": 100, "actual": 3}}
+    anomaly_flags JSONB NULL,
+    -- operator notes explaining unusual deltas, see `annotate_scan`
+    notes TEXT[] NOT NULL DEFAULT '{}',
+    -- hex SHA-256 Merkle root over the scan's file_changes, see `verify_integrity`
+    merkle_root TEXT NULL,
     scan_metadata JSONB NULL
 );
 
+CREATE INDEX scan_runs_tenant_idx ON filesystem.scan_runs (tenant);
+
 CREATE TABLE IF NOT EXISTS filesystem.files (
     file_name TEXT NOT NULL,
     file_type TEXT NOT NULL,
     file_size_bytes BIGINT NOT NULL,
     file_path TEXT PRIMARY KEY,
     file_mtime TIMESTAMPTZ NOT NULL,
+    -- `<algorithm>:<hex digest>` of the contents, when scans hash them (`--content-hash`)
     file_fingerprint TEXT NULL,
+    -- SELinux context or SMACK label, when scans record them (`--security-labels`)
+    security_label TEXT NULL,
+    -- where the entry leads if it is a symlink, when scans record them (`--record-symlinks`)
+    symlink_target TEXT NULL,
+    -- permission bits, owner and group, when scans record them (`--record-ownership`)
+    file_mode INT NULL,
+    file_uid BIGINT NULL,
+    file_gid BIGINT NULL,
     last_seen_scan INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON UPDATE CASCADE ON DELETE CASCADE,
     last_updated TIMESTAMPTZ NOT NULL DEFAULT now(),
     path_ltree ltree GENERATED ALWAYS AS (
         filesystem.text_to_ltree(file_path)
     ) STORED,
+    parent_dir TEXT GENERATED ALWAYS AS (filesystem.parent_dir(file_path)) STORED,
+    path_depth INT GENERATED ALWAYS AS (filesystem.path_depth(file_path)) STORED,
     CONSTRAINT file_path_unique UNIQUE (file_path)
 );
 
@@ -77,6 +175,15 @@ CREATE INDEX ON filesystem.files (last_seen_scan);
 
 CREATE INDEX ON filesystem.files USING GIST (path_ltree);
 
+CREATE INDEX files_parent_dir_idx ON filesystem.files (parent_dir);
+
+CREATE INDEX files_path_depth_idx ON filesystem.files (path_depth, parent_dir);
+
+-- Lookups of content by fingerprint, e.g. the copies `report duplicates` finds
+CREATE INDEX files_fingerprint_idx ON filesystem.files (file_fingerprint)
+WHERE
+    file_fingerprint IS NOT NULL;
+
 CREATE TABLE IF NOT EXISTS filesystem.file_changes (
     scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
     file_path TEXT NOT NULL,
@@ -85,16 +192,51 @@ CREATE TABLE IF NOT EXISTS filesystem.file_changes (
     new_size_bytes BIGINT NULL,
     old_mtime TIMESTAMPTZ NULL,
     new_mtime TIMESTAMPTZ NULL,
+    -- kept so a rollback can restore deleted/modified rows
+    old_file_type TEXT NULL,
+    -- security labels before and after, see change_type 'relabeled'
+    old_security_label TEXT NULL,
+    new_security_label TEXT NULL,
+    old_fingerprint TEXT NULL,
+    new_fingerprint TEXT NULL,
+    old_symlink_target TEXT NULL,
+    new_symlink_target TEXT NULL,
+    -- permission bits and owner before and after, see change_type 'permissions_changed'
+    old_mode INT NULL,
+    new_mode INT NULL,
+    old_uid BIGINT NULL,
+    new_uid BIGINT NULL,
+    old_gid BIGINT NULL,
+    new_gid BIGINT NULL,
     recorded_at TIMESTAMPTZ NOT NULL DEFAULT now(),
     path_ltree ltree GENERATED ALWAYS AS (
         filesystem.text_to_ltree(file_path)
     ) STORED,
+    parent_dir TEXT GENERATED ALWAYS AS (filesystem.parent_dir(file_path)) STORED,
+    path_depth INT GENERATED ALWAYS AS (filesystem.path_depth(file_path)) STORED,
     PRIMARY KEY (scan_id, file_path)
 );
 
 CREATE INDEX ON filesystem.file_changes (change_type);
 CREATE INDEX ON filesystem.file_changes (scan_id, change_type);
 
+-- Partial indexes for the per-change-type directory rollups and top-N queries
+CREATE INDEX file_changes_added_dir_idx ON filesystem.file_changes (scan_id, parent_dir)
+WHERE
+    change_type = 'added';
+
+CREATE INDEX file_changes_modified_dir_idx ON filesystem.file_changes (scan_id, parent_dir)
+WHERE
+    change_type = 'modified';
+
+CREATE INDEX file_changes_deleted_dir_idx ON filesystem.file_changes (scan_id, parent_dir)
+WHERE
+    change_type = 'deleted';
+
+CREATE INDEX file_changes_added_size_idx ON filesystem.file_changes (scan_id, new_size_bytes DESC)
+WHERE
+    change_type = 'added';
+
 CREATE UNLOGGED TABLE filesystem.staging_files (
     scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
     file_path TEXT NOT NULL,
@@ -102,7 +244,336 @@ CREATE UNLOGGED TABLE filesystem.staging_files (
     file_type TEXT NOT NULL,
     file_size_bytes BIGINT NOT NULL,
     file_mtime TIMESTAMPTZ NOT NULL,
+    security_label TEXT NULL,
+    file_fingerprint TEXT NULL,
+    symlink_target TEXT NULL,
+    file_mode INT NULL,
+    file_uid BIGINT NULL,
+    file_gid BIGINT NULL,
+    PRIMARY KEY (scan_id, file_path)
+);
+
+CREATE INDEX ON filesystem.staging_files (scan_id, file_path);
+
+-- Directories of scans recording them (`--record-dirs`), with the totals of
+-- the files below them
+CREATE TABLE IF NOT EXISTS filesystem.directories (
+    dir_path TEXT PRIMARY KEY,
+    -- entries directly in the directory, of any type
+    entry_count BIGINT NOT NULL,
+    -- recorded files below the directory, at any depth
+    file_count BIGINT NOT NULL,
+    total_size_bytes BIGINT NOT NULL,
+    dir_mtime TIMESTAMPTZ NOT NULL,
+    last_seen_scan INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON UPDATE CASCADE ON DELETE CASCADE,
+    last_updated TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX ON filesystem.directories (last_seen_scan);
+
+CREATE TABLE IF NOT EXISTS filesystem.dir_changes (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    dir_path TEXT NOT NULL,
+    -- added, modified (any count, size or mtime changed) or deleted
+    change_type TEXT NOT NULL,
+    old_entry_count BIGINT NULL,
+    new_entry_count BIGINT NULL,
+    old_file_count BIGINT NULL,
+    new_file_count BIGINT NULL,
+    old_size_bytes BIGINT NULL,
+    new_size_bytes BIGINT NULL,
+    old_mtime TIMESTAMPTZ NULL,
+    new_mtime TIMESTAMPTZ NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (scan_id, dir_path)
+);
+
+CREATE INDEX ON filesystem.dir_changes (scan_id, change_type);
+
+CREATE UNLOGGED TABLE filesystem.staging_dirs (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    dir_path TEXT NOT NULL,
+    entry_count BIGINT NOT NULL,
+    file_count BIGINT NOT NULL,
+    total_size_bytes BIGINT NOT NULL,
+    dir_mtime TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (scan_id, dir_path)
+);
+
+-- Progress of a staging load committed in chunks, so that an interrupted load
+-- resumes after its last committed chunk
+CREATE TABLE IF NOT EXISTS filesystem.load_checkpoints (
+    scan_id INT PRIMARY KEY REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    tsv_file TEXT NOT NULL,
+    -- a TSV file of another size is loaded from the start
+    tsv_size_bytes BIGINT NOT NULL,
+    -- end of the last committed chunk
+    byte_offset BIGINT NOT NULL,
+    rows_loaded BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Scans whose changes were pushed to a data catalog by `catalog_sync`
+CREATE TABLE IF NOT EXISTS filesystem.catalog_syncs (
+    -- the catalog's URL, or `irods:` and its collection
+    catalog TEXT NOT NULL,
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    added_count BIGINT NOT NULL,
+    modified_count BIGINT NOT NULL,
+    removed_count BIGINT NOT NULL,
+    synced_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (catalog, scan_id)
+);
+
+-- Downstream consumers of the changes of completed scans, each notified of the
+-- changes matching its filters on the channel named after it, see `subscriptions`
+CREATE TABLE IF NOT EXISTS filesystem.change_subscriptions (
+    -- also the NOTIFY channel, so a plain lowercase identifier
+    consumer TEXT PRIMARY KEY CHECK (consumer ~ '^[a-z_][a-z0-9_]{0,62}$'),
+    -- only changes of this path or of paths below it; NULL for all
+    path_prefix TEXT NULL,
+    -- only changes of these types; NULL for all
+    change_types TEXT[] NULL,
+    -- POST the changes here instead of sending them with NOTIFY
+    webhook_url TEXT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Changes queued for the subscriptions they match, in the transaction that
+-- recorded them, until `subscriptions deliver` (or the scan's end) delivers them
+CREATE TABLE IF NOT EXISTS filesystem.event_outbox (
+    event_id BIGSERIAL PRIMARY KEY,
+    consumer TEXT NOT NULL REFERENCES filesystem.change_subscriptions(consumer) ON DELETE CASCADE,
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    payload JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_error TEXT NULL,
+    delivered_at TIMESTAMPTZ NULL,
+    -- given up on after too many failed attempts, until requeued
+    dead_lettered_at TIMESTAMPTZ NULL
+);
+
+CREATE INDEX IF NOT EXISTS event_outbox_due_idx ON filesystem.event_outbox (event_id)
+WHERE
+    delivered_at IS NULL
+    AND dead_lettered_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS event_outbox_consumer_idx ON filesystem.event_outbox (consumer);
+
+CREATE INDEX IF NOT EXISTS event_outbox_scan_id_idx ON filesystem.event_outbox (scan_id);
+
+-- Queue the changes each statement records for the subscriptions they match
+CREATE
+OR REPLACE FUNCTION filesystem.queue_change_events() RETURNS trigger LANGUAGE plpgsql AS $$
+BEGIN
+    INSERT INTO filesystem.event_outbox (consumer, scan_id, payload)
+    SELECT s.consumer, c.scan_id,
+           jsonb_build_object(
+               'consumer', s.consumer,
+               'scan_id', c.scan_id,
+               'scan_root', r.scan_root,
+               'change_type', c.change_type,
+               'path', c.file_path,
+               'size_bytes', COALESCE(c.new_size_bytes, c.old_size_bytes),
+               'mtime', COALESCE(c.new_mtime, c.old_mtime),
+               'fingerprint', COALESCE(c.new_fingerprint, c.old_fingerprint)
+           )
+    FROM recorded_changes AS c
+    JOIN filesystem.scan_runs AS r ON r.scan_id = c.scan_id
+    JOIN filesystem.change_subscriptions AS s
+      ON (s.path_prefix IS NULL
+          OR s.path_prefix = '/'
+          OR c.file_path = s.path_prefix
+          OR starts_with(c.file_path, s.path_prefix || '/'))
+     AND (s.change_types IS NULL OR c.change_type = ANY (s.change_types))
+    ORDER BY s.consumer, c.file_path;
+    RETURN NULL;
+END $$;
+
+CREATE
+OR REPLACE TRIGGER file_changes_queue_events
+AFTER
+INSERT
+    ON filesystem.file_changes REFERENCING NEW TABLE AS recorded_changes FOR EACH STATEMENT EXECUTE FUNCTION filesystem.queue_change_events();
+
+-- Deltas computed in review mode, awaiting promotion by `apply_scan`
+CREATE TABLE IF NOT EXISTS filesystem.pending_file_changes (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    file_path TEXT NOT NULL,
+    change_type TEXT NOT NULL,
+    old_size_bytes BIGINT NULL,
+    new_size_bytes BIGINT NULL,
+    old_mtime TIMESTAMPTZ NULL,
+    new_mtime TIMESTAMPTZ NULL,
+    old_security_label TEXT NULL,
+    new_security_label TEXT NULL,
+    old_fingerprint TEXT NULL,
+    new_fingerprint TEXT NULL,
+    old_symlink_target TEXT NULL,
+    new_symlink_target TEXT NULL,
+    old_mode INT NULL,
+    new_mode INT NULL,
+    old_uid BIGINT NULL,
+    new_uid BIGINT NULL,
+    old_gid BIGINT NULL,
+    new_gid BIGINT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now(),
     PRIMARY KEY (scan_id, file_path)
 );
 
-CREATE INDEX ON filesystem.staging_files (scan_id, file_path);
\ No newline at end of file
+-- Directories with a pathological number of entries, per scan
+CREATE TABLE IF NOT EXISTS filesystem.hot_dirs (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    dir_path TEXT NOT NULL,
+    entry_count BIGINT NOT NULL,
+    -- entries beyond --max-entries-per-dir were not recorded
+    truncated BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (scan_id, dir_path)
+);
+
+-- Entries the walker of a scan failed to read, left out of its crawl with
+-- whatever is below them, e.g. unreadable directories or stale NFS handles
+CREATE TABLE IF NOT EXISTS filesystem.scan_errors (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    entry_path TEXT NOT NULL,
+    -- permission_denied, stale_handle, not_found, fd_exhausted, name_too_long, loop or io
+    error_kind TEXT NOT NULL,
+    message TEXT NOT NULL,
+    PRIMARY KEY (scan_id, entry_path)
+);
+
+CREATE INDEX IF NOT EXISTS scan_errors_kind_idx ON filesystem.scan_errors (scan_id, error_kind);
+
+-- Shards of a sharded scan, each a set of top-level directories of the root
+-- crawled by whichever `shard_scan work` host claims it, see `shard_scan`
+CREATE TABLE IF NOT EXISTS filesystem.scan_shards (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    shard_index INT NOT NULL,
+    -- the root itself stands for its own files
+    batch_paths TEXT [] NOT NULL,
+    shard_status TEXT NOT NULL DEFAULT 'pending',
+    -- `host:pid` of the `shard_scan work` process that claimed it
+    worker TEXT NULL,
+    claimed_at TIMESTAMPTZ NULL,
+    -- bumped by the worker while it runs; a stale shard is requeued
+    heartbeat_at TIMESTAMPTZ NULL,
+    finished_at TIMESTAMPTZ NULL,
+    -- crawl and load metadata of the shard, summed into the scan's
+    shard_metadata JSONB NULL,
+    error TEXT NULL,
+    PRIMARY KEY (scan_id, shard_index)
+);
+
+-- Per-scan file count and volume by extension, computed during staging processing
+CREATE TABLE IF NOT EXISTS filesystem.extension_stats (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    file_type TEXT NOT NULL,
+    file_count BIGINT NOT NULL,
+    total_size_bytes BIGINT NOT NULL,
+    PRIMARY KEY (scan_id, file_type)
+);
+
+-- Per-root limits on the deltas accumulated over a rolling window, see `set_budget`
+CREATE TABLE IF NOT EXISTS filesystem.delta_budgets (
+    scan_root TEXT NOT NULL,
+    metric TEXT NOT NULL CHECK (
+        metric IN (
+            'new_data_mb',
+            'modified_data_mb',
+            'deleted_data_mb',
+            'added_files_count',
+            'modified_files_count',
+            'removed_files_count'
+        )
+    ),
+    max_value FLOAT NOT NULL,
+    window_days INT NOT NULL DEFAULT 7,
+    PRIMARY KEY (scan_root, metric)
+);
+
+-- Quick scans: per-directory file counts and sizes without per-file rows, for
+-- daily trend lines between full scans (`scan --quick`)
+CREATE TABLE IF NOT EXISTS filesystem.quick_scans (
+    quick_scan_id SERIAL PRIMARY KEY,
+    scan_root TEXT NOT NULL,
+    tenant TEXT NULL,
+    hostname TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    -- NULL while running, or if the quick scan failed
+    finished_at TIMESTAMPTZ NULL,
+    total_files BIGINT NULL,
+    total_size_bytes BIGINT NULL,
+    scan_metadata JSONB NULL
+);
+
+CREATE INDEX IF NOT EXISTS quick_scans_root_idx ON filesystem.quick_scans (scan_root, started_at);
+
+-- Files directly in each directory of a quick scan; directories without files are omitted
+CREATE TABLE IF NOT EXISTS filesystem.dir_summaries (
+    quick_scan_id INT NOT NULL REFERENCES filesystem.quick_scans(quick_scan_id) ON DELETE CASCADE,
+    dir_path TEXT NOT NULL,
+    file_count BIGINT NOT NULL,
+    total_size_bytes BIGINT NOT NULL,
+    PRIMARY KEY (quick_scan_id, dir_path)
+);
+
+-- Owners / teams of directory trees, loaded from a config file by `load_owners`
+CREATE TABLE IF NOT EXISTS filesystem.directory_owners (
+    dir_prefix TEXT PRIMARY KEY,
+    owner TEXT NOT NULL
+);
+
+-- Owner of a path: the longest matching prefix in directory_owners, falling back
+-- to the top-level directory under the scan root
+CREATE
+OR REPLACE FUNCTION filesystem.path_owner(path TEXT, root TEXT) RETURNS TEXT LANGUAGE sql STABLE AS $$
+SELECT
+    COALESCE(
+        (
+            SELECT
+                o.owner
+            FROM
+                filesystem.directory_owners AS o
+            WHERE
+                left(path, length(rtrim(o.dir_prefix, '/')) + 1) = rtrim(o.dir_prefix, '/') || '/'
+            ORDER BY
+                length(o.dir_prefix) DESC
+            LIMIT
+                1
+        ), CASE
+            WHEN strpos(substr(path, length(rtrim(root, '/')) + 2), '/') = 0 THEN '(root)'
+            ELSE split_part(substr(path, length(rtrim(root, '/')) + 2), '/', 1)
+        END
+    ) $$;
+
+-- Append-only record of scans and administrative actions, written through `data::audit`.
+-- Deliberately not dropped above: re-initializing the database keeps its chain of custody.
+CREATE TABLE IF NOT EXISTS filesystem.audit_log (
+    audit_id BIGSERIAL PRIMARY KEY,
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    action TEXT NOT NULL,
+    -- no foreign key: entries must outlive the scans they describe
+    scan_id INT NULL,
+    os_user TEXT NOT NULL,
+    db_user TEXT NOT NULL DEFAULT current_user,
+    hostname TEXT NOT NULL,
+    details JSONB NOT NULL DEFAULT '{}'::jsonb
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_scan_id_idx ON filesystem.audit_log (scan_id);
+
+CREATE
+OR REPLACE FUNCTION filesystem.audit_log_immutable() RETURNS trigger LANGUAGE plpgsql AS $$
+BEGIN
+    RAISE EXCEPTION 'filesystem.audit_log is append-only';
+END $$;
+
+CREATE
+OR REPLACE TRIGGER audit_log_no_modify BEFORE
+UPDATE
+    OR DELETE ON filesystem.audit_log FOR EACH ROW EXECUTE FUNCTION filesystem.audit_log_immutable();
+
+CREATE
+OR REPLACE TRIGGER audit_log_no_truncate BEFORE TRUNCATE ON filesystem.audit_log FOR EACH STATEMENT EXECUTE FUNCTION filesystem.audit_log_immutable();
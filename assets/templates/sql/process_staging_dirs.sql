@@ -0,0 +1,163 @@
+-- process_staging_dirs.sql
+-- Assumes parameter :scan_id is passed in.
+-- The directory counterpart of process_staging_v2.sql, for scans recording
+-- directories (`--record-dirs`).
+BEGIN;
+
+WITH -- 1) the scan's roots
+scan_info AS (
+    SELECT
+        r.scan_root,
+        rtrim(r.scan_root, '/') || '/' AS root_prefix
+    FROM
+        filesystem.scan_runs,
+        unnest(COALESCE(scan_roots, ARRAY [scan_root])) AS r(scan_root)
+    WHERE
+        scan_id = :scan_id
+),
+staged AS (
+    SELECT
+        s.*
+    FROM
+        filesystem.staging_dirs AS s
+    WHERE
+        s.scan_id = :scan_id
+),
+-- 2) directories under the roots that did NOT show up in staging; never all
+-- of them when staging is empty, e.g. an unlogged table emptied by a crash
+-- (a crawl records its roots at least)
+deleted AS (
+    DELETE FROM
+        filesystem.directories AS d USING scan_info
+    WHERE
+        (
+            d.dir_path = scan_info.scan_root
+            OR starts_with(d.dir_path, scan_info.root_prefix)
+        )
+        AND EXISTS (
+            SELECT
+                1
+            FROM
+                staged
+        )
+        AND NOT EXISTS (
+            SELECT
+                1
+            FROM
+                staged AS s2
+            WHERE
+                s2.dir_path = d.dir_path
+        ) RETURNING d.*
+),
+ins_deleted AS (
+    INSERT INTO
+        filesystem.dir_changes (
+            scan_id,
+            dir_path,
+            change_type,
+            old_entry_count,
+            old_file_count,
+            old_size_bytes,
+            old_mtime
+        )
+    SELECT
+        :scan_id,
+        dir_path,
+        'deleted',
+        entry_count,
+        file_count,
+        total_size_bytes,
+        dir_mtime
+    FROM
+        deleted
+),
+-- 3) new and changed directories
+compared AS (
+    SELECT
+        s.*,
+        d.dir_path IS NULL AS is_new,
+        d.entry_count AS old_entry_count,
+        d.file_count AS old_file_count,
+        d.total_size_bytes AS old_size_bytes,
+        d.dir_mtime AS old_mtime
+    FROM
+        staged AS s
+        LEFT JOIN filesystem.directories AS d ON d.dir_path = s.dir_path
+),
+ins_changes AS (
+    INSERT INTO
+        filesystem.dir_changes (
+            scan_id,
+            dir_path,
+            change_type,
+            old_entry_count,
+            new_entry_count,
+            old_file_count,
+            new_file_count,
+            old_size_bytes,
+            new_size_bytes,
+            old_mtime,
+            new_mtime
+        )
+    SELECT
+        :scan_id,
+        dir_path,
+        CASE
+            WHEN is_new THEN 'added'
+            ELSE 'modified'
+        END,
+        old_entry_count,
+        entry_count,
+        old_file_count,
+        file_count,
+        old_size_bytes,
+        total_size_bytes,
+        old_mtime,
+        dir_mtime
+    FROM
+        compared
+    WHERE
+        is_new
+        OR (old_entry_count, old_file_count, old_size_bytes, old_mtime)
+            <> (entry_count, file_count, total_size_bytes, dir_mtime)
+),
+-- 4) bring the tracked directories in line
+upserted AS (
+    INSERT INTO
+        filesystem.directories (
+            dir_path,
+            entry_count,
+            file_count,
+            total_size_bytes,
+            dir_mtime,
+            last_seen_scan,
+            last_updated
+        )
+    SELECT
+        dir_path,
+        entry_count,
+        file_count,
+        total_size_bytes,
+        dir_mtime,
+        :scan_id,
+        now()
+    FROM
+        staged ON CONFLICT (dir_path) DO
+    UPDATE
+    SET
+        entry_count = EXCLUDED.entry_count,
+        file_count = EXCLUDED.file_count,
+        total_size_bytes = EXCLUDED.total_size_bytes,
+        dir_mtime = EXCLUDED.dir_mtime,
+        last_seen_scan = EXCLUDED.last_seen_scan,
+        last_updated = EXCLUDED.last_updated
+) -- kick off the CTEs
+SELECT
+    1;
+
+DELETE FROM
+    filesystem.staging_dirs
+WHERE
+    scan_id = :scan_id;
+
+COMMIT;
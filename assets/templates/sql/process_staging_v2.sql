@@ -1,14 +1,17 @@
 -- process_staging.sql
--- Assumes parameter :scan_id is passed in.
+-- Assumes parameters :scan_id, :staging_table and :defer_deletes are passed in.
+-- With :defer_deletes (a batch of a batched scan) files missing from staging
+-- are left alone; sweep_deleted.sql deletes them once all batches are in.
 BEGIN;
 
-WITH -- 1) pull in the scan_root and turn it into an ltree
+WITH -- 1) pull in the scan's roots and turn them into ltrees
 scan_info AS (
     SELECT
-        scan_root,
-        replace(btrim(scan_root, '/'), '/', '.')::ltree AS root_ltree
+        r.scan_root,
+        replace(btrim(r.scan_root, '/'), '/', '.')::ltree AS root_ltree
     FROM
-        filesystem.scan_runs
+        filesystem.scan_runs,
+        unnest(COALESCE(scan_roots, ARRAY [scan_root])) AS r(scan_root)
     WHERE
         scan_id = :scan_id
 ),
@@ -17,17 +20,19 @@ staged AS (
     SELECT
         s.*
     FROM
-        filesystem.staging_files AS s
+        :staging_table AS s
     WHERE
         s.scan_id = :scan_id
 ),
--- 3) delete any files in 'filesystem.files' under this root that did NOT show up in staging
+-- 3) delete any files in 'filesystem.files' under the scan's roots that did NOT show up in staging,
+-- but those below directories whose entries the crawl capped (`--max-entries-per-dir`)
 deleted AS (
     DELETE FROM
         filesystem.files AS f USING scan_info
     WHERE
-        -- only delete things under this scan_root
+        -- only delete things under the scan's roots
         f.path_ltree <@ scan_info.root_ltree
+        AND NOT :defer_deletes
         AND NOT EXISTS (
             SELECT
                 1
@@ -35,11 +40,27 @@ deleted AS (
                 staged AS s2
             WHERE
                 s2.file_path = f.file_path
+        )
+        AND NOT EXISTS (
+            SELECT
+                1
+            FROM
+                filesystem.hot_dirs AS h
+            WHERE
+                h.scan_id = :scan_id
+                AND h.truncated
+                AND left(f.file_path, length(h.dir_path) + 1) = h.dir_path || '/'
         ) RETURNING f.file_path AS file_path,
         f.file_name AS old_file_name,
         f.file_type AS old_file_type,
         f.file_size_bytes AS old_size_bytes,
-        f.file_mtime AS old_mtime
+        f.file_mtime AS old_mtime,
+        f.security_label AS old_security_label,
+        f.file_fingerprint AS old_fingerprint,
+        f.symlink_target AS old_symlink_target,
+        f.file_mode AS old_mode,
+        f.file_uid AS old_uid,
+        f.file_gid AS old_gid
 ),
 ins_deleted AS (
     INSERT INTO
@@ -48,14 +69,28 @@ ins_deleted AS (
             file_path,
             change_type,
             old_size_bytes,
-            old_mtime
+            old_mtime,
+            old_file_type,
+            old_security_label,
+            old_fingerprint,
+            old_symlink_target,
+            old_mode,
+            old_uid,
+            old_gid
         )
     SELECT
         :scan_id,
         file_path,
         'deleted',
         old_size_bytes,
-        old_mtime
+        old_mtime,
+        old_file_type,
+        old_security_label,
+        old_fingerprint,
+        old_symlink_target,
+        old_mode,
+        old_uid,
+        old_gid
     FROM
         deleted
 ),
@@ -66,7 +101,13 @@ new_files AS (
         s.file_type,
         s.file_size_bytes,
         s.file_path,
-        s.file_mtime
+        s.file_mtime,
+        s.security_label,
+        s.file_fingerprint,
+        s.symlink_target,
+        s.file_mode,
+        s.file_uid,
+        s.file_gid
     FROM
         staged AS s
         LEFT JOIN filesystem.files AS f ON f.file_path = s.file_path
@@ -82,6 +123,11 @@ ins_new AS (
             file_path,
             file_mtime,
             file_fingerprint,
+            security_label,
+            symlink_target,
+            file_mode,
+            file_uid,
+            file_gid,
             last_seen_scan,
             last_updated
         )
@@ -91,14 +137,24 @@ ins_new AS (
         nf.file_size_bytes,
         nf.file_path,
         nf.file_mtime,
-        NULL,
-        -- fingerprint to be calculated later
+        nf.file_fingerprint,
+        nf.security_label,
+        nf.symlink_target,
+        nf.file_mode,
+        nf.file_uid,
+        nf.file_gid,
         :scan_id,
         now()
     FROM
         new_files AS nf RETURNING file_path,
         file_size_bytes AS new_size_bytes,
-        file_mtime AS new_mtime
+        file_mtime AS new_mtime,
+        security_label AS new_security_label,
+        file_fingerprint AS new_fingerprint,
+        symlink_target AS new_symlink_target,
+        file_mode AS new_mode,
+        file_uid AS new_uid,
+        file_gid AS new_gid
 ),
 rec_new AS (
     INSERT INTO
@@ -107,18 +163,32 @@ rec_new AS (
             file_path,
             change_type,
             new_size_bytes,
-            new_mtime
+            new_mtime,
+            new_security_label,
+            new_fingerprint,
+            new_symlink_target,
+            new_mode,
+            new_uid,
+            new_gid
         )
     SELECT
         :scan_id,
         file_path,
         'added',
         new_size_bytes,
-        new_mtime
+        new_mtime,
+        new_security_label,
+        new_fingerprint,
+        new_symlink_target,
+        new_mode,
+        new_uid,
+        new_gid
     FROM
         ins_new
 ),
--- 5) modified files (same path exists but size or mtime changed)
+-- 5) modified files (same path exists but size or mtime changed; size or
+-- fingerprint when both scans hashed the contents alike; or a symlink with
+-- another target)
 mods AS (
     SELECT
         s.file_path,
@@ -126,16 +196,37 @@ mods AS (
         s.file_type AS new_file_type,
         s.file_size_bytes AS new_size,
         s.file_mtime AS new_mtime,
+        s.security_label AS new_security_label,
+        s.file_fingerprint AS new_fingerprint,
+        s.symlink_target AS new_symlink_target,
+        s.file_mode AS new_mode,
+        s.file_uid AS new_uid,
+        s.file_gid AS new_gid,
         f.file_name AS old_file_name,
         f.file_type AS old_file_type,
         f.file_size_bytes AS old_size,
-        f.file_mtime AS old_mtime
+        f.file_mtime AS old_mtime,
+        f.security_label AS old_security_label,
+        f.file_fingerprint AS old_fingerprint,
+        f.symlink_target AS old_symlink_target,
+        f.file_mode AS old_mode,
+        f.file_uid AS old_uid,
+        f.file_gid AS old_gid
     FROM
         staged AS s
         JOIN filesystem.files AS f ON f.file_path = s.file_path
     WHERE
-        (s.file_size_bytes <> f.file_size_bytes)
-        OR (s.file_mtime <> f.file_mtime)
+        (
+            filesystem.is_modified(
+                f.file_size_bytes,
+                f.file_mtime,
+                f.file_fingerprint,
+                s.file_size_bytes,
+                s.file_mtime,
+                s.file_fingerprint
+            )
+            OR s.symlink_target IS DISTINCT FROM f.symlink_target
+        )
 ),
 ins_mod AS (
     INSERT INTO
@@ -146,7 +237,20 @@ ins_mod AS (
             old_size_bytes,
             new_size_bytes,
             old_mtime,
-            new_mtime
+            new_mtime,
+            old_file_type,
+            old_security_label,
+            new_security_label,
+            old_fingerprint,
+            new_fingerprint,
+            old_symlink_target,
+            new_symlink_target,
+            old_mode,
+            new_mode,
+            old_uid,
+            new_uid,
+            old_gid,
+            new_gid
         )
     SELECT
         :scan_id,
@@ -155,7 +259,20 @@ ins_mod AS (
         old_size,
         new_size,
         old_mtime,
-        new_mtime
+        new_mtime,
+        old_file_type,
+        old_security_label,
+        new_security_label,
+        old_fingerprint,
+        new_fingerprint,
+        old_symlink_target,
+        new_symlink_target,
+        old_mode,
+        new_mode,
+        old_uid,
+        new_uid,
+        old_gid,
+        new_gid
     FROM
         mods
 ),
@@ -167,30 +284,178 @@ upd_mod AS (
         file_type = m.new_file_type,
         file_size_bytes = m.new_size,
         file_mtime = m.new_mtime,
+        security_label = COALESCE(m.new_security_label, f.security_label),
         last_seen_scan = :scan_id,
-        file_fingerprint = NULL,
-        -- force re-hash
+        -- NULL unless this scan hashed the contents
+        file_fingerprint = m.new_fingerprint,
+        symlink_target = m.new_symlink_target,
+        file_mode = COALESCE(m.new_mode, f.file_mode),
+        file_uid = COALESCE(m.new_uid, f.file_uid),
+        file_gid = COALESCE(m.new_gid, f.file_gid),
         last_updated = now()
     FROM
         mods AS m
     WHERE
         f.file_path = m.file_path
 ),
--- 6) untouched files: just bump last_seen_scan
+-- 6) relabeled files (unmodified, another security label), else files whose
+-- permissions changed (another mode, owner or group); only between two scans
+-- that both recorded a label, or the ownership
+relabels AS (
+    SELECT
+        s.file_path,
+        CASE
+            WHEN s.security_label <> f.security_label THEN 'relabeled'
+            ELSE 'permissions_changed'
+        END AS change_type,
+        f.security_label AS old_security_label,
+        s.security_label AS new_security_label,
+        f.file_mode AS old_mode,
+        s.file_mode AS new_mode,
+        f.file_uid AS old_uid,
+        s.file_uid AS new_uid,
+        f.file_gid AS old_gid,
+        s.file_gid AS new_gid,
+        f.file_size_bytes AS size,
+        f.file_mtime AS old_mtime,
+        s.file_mtime AS new_mtime
+    FROM
+        staged AS s
+        JOIN filesystem.files AS f ON f.file_path = s.file_path
+    WHERE
+        NOT (
+            filesystem.is_modified(
+                f.file_size_bytes,
+                f.file_mtime,
+                f.file_fingerprint,
+                s.file_size_bytes,
+                s.file_mtime,
+                s.file_fingerprint
+            )
+            OR s.symlink_target IS DISTINCT FROM f.symlink_target
+        )
+        AND (
+            s.security_label <> f.security_label
+            OR s.file_mode <> f.file_mode
+            OR s.file_uid <> f.file_uid
+            OR s.file_gid <> f.file_gid
+        )
+),
+ins_relabel AS (
+    INSERT INTO
+        filesystem.file_changes (
+            scan_id,
+            file_path,
+            change_type,
+            old_size_bytes,
+            new_size_bytes,
+            old_mtime,
+            new_mtime,
+            old_security_label,
+            new_security_label,
+            old_mode,
+            new_mode,
+            old_uid,
+            new_uid,
+            old_gid,
+            new_gid
+        )
+    SELECT
+        :scan_id,
+        file_path,
+        change_type,
+        size,
+        size,
+        old_mtime,
+        new_mtime,
+        old_security_label,
+        new_security_label,
+        old_mode,
+        new_mode,
+        old_uid,
+        new_uid,
+        old_gid,
+        new_gid
+    FROM
+        relabels
+),
+-- 7) untouched, relabeled and permission-changed files: bump last_seen_scan,
+-- keeping the label, ownership and fingerprint where this scan recorded none;
+-- the mtime of files only touched follows the disk
 upd_unchanged AS (
     UPDATE
         filesystem.files AS f
     SET
+        file_mtime = s.file_mtime,
+        security_label = COALESCE(s.security_label, f.security_label),
+        file_mode = COALESCE(s.file_mode, f.file_mode),
+        file_uid = COALESCE(s.file_uid, f.file_uid),
+        file_gid = COALESCE(s.file_gid, f.file_gid),
+        file_fingerprint = COALESCE(s.file_fingerprint, f.file_fingerprint),
         last_seen_scan = :scan_id,
         last_updated = now()
     FROM
         staged AS s
     WHERE
         s.file_path = f.file_path
-        AND s.file_size_bytes = f.file_size_bytes
-        AND s.file_mtime = f.file_mtime
+        AND NOT (
+            filesystem.is_modified(
+                f.file_size_bytes,
+                f.file_mtime,
+                f.file_fingerprint,
+                s.file_size_bytes,
+                s.file_mtime,
+                s.file_fingerprint
+            )
+            OR s.symlink_target IS DISTINCT FROM f.symlink_target
+        )
 ) -- kick off the CTEs
 SELECT
     1;
 
+-- 8) per-extension totals of this scan, for charting extension trends; in a
+-- fixed order, so shards of a sharded scan applied concurrently take the row
+-- locks without deadlocking
+INSERT INTO
+    filesystem.extension_stats (
+        scan_id,
+        file_type,
+        file_count,
+        total_size_bytes
+    )
+SELECT
+    :scan_id,
+    s.file_type,
+    COUNT(*),
+    SUM(s.file_size_bytes)
+FROM
+    :staging_table AS s
+WHERE
+    s.scan_id = :scan_id
+GROUP BY
+    s.file_type
+ORDER BY
+    s.file_type ON CONFLICT (scan_id, file_type) DO
+UPDATE
+SET
+    -- batches of a batched scan add up
+    file_count = CASE
+        WHEN :defer_deletes THEN filesystem.extension_stats.file_count + EXCLUDED.file_count
+        ELSE EXCLUDED.file_count
+    END,
+    total_size_bytes = CASE
+        WHEN :defer_deletes THEN filesystem.extension_stats.total_size_bytes + EXCLUDED.total_size_bytes
+        ELSE EXCLUDED.total_size_bytes
+    END;
+
+-- 9) record the deltas as applied in the same transaction, so a scan resumed
+-- after a crash never applies them twice
+UPDATE
+    filesystem.scan_runs
+SET
+    scan_phase = 'processed'
+WHERE
+    scan_id = :scan_id
+    AND NOT :defer_deletes;
+
 COMMIT;
\ No newline at end of file
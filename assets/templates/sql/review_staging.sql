@@ -0,0 +1,212 @@
+-- review_staging.sql
+-- Assumes parameters :scan_id and :staging_table are passed in.
+-- Computes the deltas for a scan into filesystem.pending_file_changes without
+-- touching filesystem.files. Staging rows are kept so `apply_scan` can promote them.
+BEGIN;
+
+DELETE FROM
+    filesystem.pending_file_changes
+WHERE
+    scan_id = :scan_id;
+
+WITH -- 1) pull in the scan's roots and turn them into ltrees
+scan_info AS (
+    SELECT
+        r.scan_root,
+        replace(btrim(r.scan_root, '/'), '/', '.')::ltree AS root_ltree
+    FROM
+        filesystem.scan_runs,
+        unnest(COALESCE(scan_roots, ARRAY [scan_root])) AS r(scan_root)
+    WHERE
+        scan_id = :scan_id
+),
+-- 2) alias the staging rows for convenience
+staged AS (
+    SELECT
+        s.*
+    FROM
+        :staging_table AS s
+    WHERE
+        s.scan_id = :scan_id
+)
+INSERT INTO
+    filesystem.pending_file_changes (
+        scan_id,
+        file_path,
+        change_type,
+        old_size_bytes,
+        new_size_bytes,
+        old_mtime,
+        new_mtime,
+        old_security_label,
+        new_security_label,
+        old_fingerprint,
+        new_fingerprint,
+        old_symlink_target,
+        new_symlink_target,
+        old_mode,
+        new_mode,
+        old_uid,
+        new_uid,
+        old_gid,
+        new_gid
+    ) -- 3) files under the scan's roots that did NOT show up in staging, but
+    -- those below directories whose entries the crawl capped
+SELECT
+    :scan_id,
+    f.file_path,
+    'deleted',
+    f.file_size_bytes,
+    NULL,
+    f.file_mtime,
+    NULL,
+    f.security_label,
+    NULL,
+    f.file_fingerprint,
+    NULL,
+    f.symlink_target,
+    NULL,
+    f.file_mode,
+    NULL,
+    f.file_uid,
+    NULL,
+    f.file_gid,
+    NULL
+FROM
+    filesystem.files AS f,
+    scan_info
+WHERE
+    f.path_ltree <@ scan_info.root_ltree
+    AND NOT EXISTS (
+        SELECT
+            1
+        FROM
+            staged AS s2
+        WHERE
+            s2.file_path = f.file_path
+    )
+    AND NOT EXISTS (
+        SELECT
+            1
+        FROM
+            filesystem.hot_dirs AS h
+        WHERE
+            h.scan_id = :scan_id
+            AND h.truncated
+            AND left(f.file_path, length(h.dir_path) + 1) = h.dir_path || '/'
+    )
+UNION ALL
+-- 4) brand-new files in staging (no existing row in filesystem.files)
+SELECT
+    :scan_id,
+    s.file_path,
+    'added',
+    NULL,
+    s.file_size_bytes,
+    NULL,
+    s.file_mtime,
+    NULL,
+    s.security_label,
+    NULL,
+    s.file_fingerprint,
+    NULL,
+    s.symlink_target,
+    NULL,
+    s.file_mode,
+    NULL,
+    s.file_uid,
+    NULL,
+    s.file_gid
+FROM
+    staged AS s
+    LEFT JOIN filesystem.files AS f ON f.file_path = s.file_path
+WHERE
+    f.file_path IS NULL
+UNION ALL
+-- 5) modified files (same path exists but size or mtime changed; size or
+-- fingerprint when both scans hashed the contents alike; or a symlink with
+-- another target)
+SELECT
+    :scan_id,
+    s.file_path,
+    'modified',
+    f.file_size_bytes,
+    s.file_size_bytes,
+    f.file_mtime,
+    s.file_mtime,
+    f.security_label,
+    s.security_label,
+    f.file_fingerprint,
+    s.file_fingerprint,
+    f.symlink_target,
+    s.symlink_target,
+    f.file_mode,
+    s.file_mode,
+    f.file_uid,
+    s.file_uid,
+    f.file_gid,
+    s.file_gid
+FROM
+    staged AS s
+    JOIN filesystem.files AS f ON f.file_path = s.file_path
+WHERE
+    (
+        filesystem.is_modified(
+            f.file_size_bytes,
+            f.file_mtime,
+            f.file_fingerprint,
+            s.file_size_bytes,
+            s.file_mtime,
+            s.file_fingerprint
+        )
+        OR s.symlink_target IS DISTINCT FROM f.symlink_target
+    )
+UNION ALL
+-- 6) relabeled files (unmodified, another security label), else files whose
+-- permissions changed (another mode, owner or group)
+SELECT
+    :scan_id,
+    s.file_path,
+    CASE
+        WHEN s.security_label <> f.security_label THEN 'relabeled'
+        ELSE 'permissions_changed'
+    END,
+    f.file_size_bytes,
+    s.file_size_bytes,
+    f.file_mtime,
+    s.file_mtime,
+    f.security_label,
+    s.security_label,
+    NULL,
+    NULL,
+    NULL,
+    NULL,
+    f.file_mode,
+    s.file_mode,
+    f.file_uid,
+    s.file_uid,
+    f.file_gid,
+    s.file_gid
+FROM
+    staged AS s
+    JOIN filesystem.files AS f ON f.file_path = s.file_path
+WHERE
+    NOT (
+        filesystem.is_modified(
+            f.file_size_bytes,
+            f.file_mtime,
+            f.file_fingerprint,
+            s.file_size_bytes,
+            s.file_mtime,
+            s.file_fingerprint
+        )
+        OR s.symlink_target IS DISTINCT FROM f.symlink_target
+    )
+    AND (
+        s.security_label <> f.security_label
+        OR s.file_mode <> f.file_mode
+        OR s.file_uid <> f.file_uid
+        OR s.file_gid <> f.file_gid
+    );
+
+COMMIT;
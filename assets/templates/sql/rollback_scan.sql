@@ -0,0 +1,185 @@
+-- rollback_scan.sql
+-- Assumes parameters :scan_id and :previous_scan_id are passed in.
+-- Reverts the changes a completed scan applied to filesystem.files,
+-- removes its file_changes and marks the scan as voided; the same for
+-- filesystem.directories and dir_changes.
+BEGIN;
+
+-- 1) files the scan added
+DELETE FROM
+    filesystem.files AS f USING filesystem.file_changes AS c
+WHERE
+    c.scan_id = :scan_id
+    AND c.change_type = 'added'
+    AND f.file_path = c.file_path;
+
+-- 2) files the scan modified: restore their previous size / mtime / fingerprint
+-- / symlink target / ownership
+UPDATE
+    filesystem.files AS f
+SET
+    file_type = COALESCE(c.old_file_type, f.file_type),
+    file_size_bytes = c.old_size_bytes,
+    file_mtime = c.old_mtime,
+    file_fingerprint = c.old_fingerprint,
+    symlink_target = c.old_symlink_target,
+    security_label = COALESCE(c.old_security_label, f.security_label),
+    file_mode = COALESCE(c.old_mode, f.file_mode),
+    file_uid = COALESCE(c.old_uid, f.file_uid),
+    file_gid = COALESCE(c.old_gid, f.file_gid),
+    last_updated = now()
+FROM
+    filesystem.file_changes AS c
+WHERE
+    c.scan_id = :scan_id
+    AND c.change_type = 'modified'
+    AND f.file_path = c.file_path;
+
+-- 3) files the scan relabeled or changed the permissions of: restore their
+-- previous label and ownership (and mtime, of files only touched besides)
+UPDATE
+    filesystem.files AS f
+SET
+    security_label = COALESCE(c.old_security_label, f.security_label),
+    file_mode = COALESCE(c.old_mode, f.file_mode),
+    file_uid = COALESCE(c.old_uid, f.file_uid),
+    file_gid = COALESCE(c.old_gid, f.file_gid),
+    file_mtime = c.old_mtime,
+    last_updated = now()
+FROM
+    filesystem.file_changes AS c
+WHERE
+    c.scan_id = :scan_id
+    AND c.change_type IN ('relabeled', 'permissions_changed')
+    AND f.file_path = c.file_path;
+
+-- 4) files the scan deleted: bring them back
+INSERT INTO
+    filesystem.files (
+        file_name,
+        file_type,
+        file_size_bytes,
+        file_path,
+        file_mtime,
+        file_fingerprint,
+        security_label,
+        symlink_target,
+        file_mode,
+        file_uid,
+        file_gid,
+        last_seen_scan,
+        last_updated
+    )
+SELECT
+    regexp_replace(c.file_path, '^.*/', ''),
+    COALESCE(c.old_file_type, 'unknown'),
+    c.old_size_bytes,
+    c.file_path,
+    c.old_mtime,
+    c.old_fingerprint,
+    c.old_security_label,
+    c.old_symlink_target,
+    c.old_mode,
+    c.old_uid,
+    c.old_gid,
+    :previous_scan_id,
+    now()
+FROM
+    filesystem.file_changes AS c
+WHERE
+    c.scan_id = :scan_id
+    AND c.change_type = 'deleted' ON CONFLICT (file_path) DO NOTHING;
+
+-- 5) everything else the scan touched was last seen by the previous scan
+UPDATE
+    filesystem.files
+SET
+    last_seen_scan = :previous_scan_id
+WHERE
+    last_seen_scan = :scan_id;
+
+-- 6) directories the scan recorded (`--record-dirs`): the same, from dir_changes
+DELETE FROM
+    filesystem.directories AS d USING filesystem.dir_changes AS c
+WHERE
+    c.scan_id = :scan_id
+    AND c.change_type = 'added'
+    AND d.dir_path = c.dir_path;
+
+UPDATE
+    filesystem.directories AS d
+SET
+    entry_count = c.old_entry_count,
+    file_count = c.old_file_count,
+    total_size_bytes = c.old_size_bytes,
+    dir_mtime = c.old_mtime,
+    last_updated = now()
+FROM
+    filesystem.dir_changes AS c
+WHERE
+    c.scan_id = :scan_id
+    AND c.change_type = 'modified'
+    AND d.dir_path = c.dir_path;
+
+INSERT INTO
+    filesystem.directories (
+        dir_path,
+        entry_count,
+        file_count,
+        total_size_bytes,
+        dir_mtime,
+        last_seen_scan,
+        last_updated
+    )
+SELECT
+    c.dir_path,
+    c.old_entry_count,
+    c.old_file_count,
+    c.old_size_bytes,
+    c.old_mtime,
+    :previous_scan_id,
+    now()
+FROM
+    filesystem.dir_changes AS c
+WHERE
+    c.scan_id = :scan_id
+    AND c.change_type = 'deleted' ON CONFLICT (dir_path) DO NOTHING;
+
+UPDATE
+    filesystem.directories
+SET
+    last_seen_scan = :previous_scan_id
+WHERE
+    last_seen_scan = :scan_id;
+
+-- 7) drop the scan's history and void it
+DELETE FROM
+    filesystem.file_changes
+WHERE
+    scan_id = :scan_id;
+
+DELETE FROM
+    filesystem.dir_changes
+WHERE
+    scan_id = :scan_id;
+
+DELETE FROM
+    filesystem.extension_stats
+WHERE
+    scan_id = :scan_id;
+
+-- changes subscribers already received stay delivered
+DELETE FROM
+    filesystem.event_outbox
+WHERE
+    scan_id = :scan_id
+    AND delivered_at IS NULL;
+
+UPDATE
+    filesystem.scan_runs
+SET
+    scan_status = 'voided'
+WHERE
+    scan_id = :scan_id;
+
+COMMIT;
@@ -0,0 +1,97 @@
+-- init_db.sql for the SQLite backend (`--backend sqlite`): the tables of the
+-- PostgreSQL schema a plain scan needs, timestamps as RFC 3339 text
+PRAGMA journal_mode = WAL;
+
+CREATE TABLE IF NOT EXISTS scan_runs (
+    scan_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scan_root TEXT NOT NULL,
+    hostname TEXT NULL,
+    started_at TEXT NOT NULL,
+    finished_at TEXT NULL,
+    total_paths_count INTEGER NULL,
+    added_files_count INTEGER NULL,
+    modified_files_count INTEGER NULL,
+    removed_files_count INTEGER NULL,
+    new_data_mb REAL NULL,
+    modified_data_mb REAL NULL,
+    deleted_data_mb REAL NULL,
+    -- running, completed, aborted
+    scan_status TEXT NOT NULL DEFAULT 'running',
+    -- sanity-guard findings and failures, as JSON
+    anomaly_flags TEXT NULL,
+    -- JSON object
+    scan_metadata TEXT NULL
+);
+
+CREATE TABLE IF NOT EXISTS files (
+    file_path TEXT PRIMARY KEY,
+    file_name TEXT NOT NULL,
+    file_type TEXT NOT NULL,
+    file_size_bytes INTEGER NOT NULL,
+    file_mtime TEXT NOT NULL,
+    file_fingerprint TEXT NULL,
+    security_label TEXT NULL,
+    last_seen_scan INTEGER NOT NULL REFERENCES scan_runs(scan_id),
+    last_updated TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS files_last_seen_scan_idx ON files (last_seen_scan);
+
+CREATE TABLE IF NOT EXISTS file_changes (
+    scan_id INTEGER NOT NULL REFERENCES scan_runs(scan_id) ON DELETE CASCADE,
+    file_path TEXT NOT NULL,
+    change_type TEXT NOT NULL,
+    old_size_bytes INTEGER NULL,
+    new_size_bytes INTEGER NULL,
+    old_mtime TEXT NULL,
+    new_mtime TEXT NULL,
+    old_file_type TEXT NULL,
+    old_security_label TEXT NULL,
+    new_security_label TEXT NULL,
+    old_fingerprint TEXT NULL,
+    new_fingerprint TEXT NULL,
+    recorded_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    PRIMARY KEY (scan_id, file_path)
+);
+
+CREATE INDEX IF NOT EXISTS file_changes_change_type_idx ON file_changes (scan_id, change_type);
+
+CREATE TABLE IF NOT EXISTS staging_files (
+    scan_id INTEGER NOT NULL REFERENCES scan_runs(scan_id) ON DELETE CASCADE,
+    file_path TEXT NOT NULL,
+    file_name TEXT NOT NULL,
+    file_type TEXT NOT NULL,
+    file_size_bytes INTEGER NOT NULL,
+    file_mtime TEXT NOT NULL,
+    security_label TEXT NULL,
+    file_fingerprint TEXT NULL,
+    PRIMARY KEY (scan_id, file_path)
+);
+
+-- Staged files next to their tracked rows; `is_modified` follows
+-- filesystem.is_modified: size or fingerprint when both were hashed alike,
+-- size or mtime otherwise
+CREATE VIEW IF NOT EXISTS staged_comparisons AS
+SELECT
+    s.scan_id,
+    s.file_path,
+    s.file_size_bytes AS new_size,
+    s.file_mtime AS new_mtime,
+    s.security_label AS new_security_label,
+    s.file_fingerprint AS new_fingerprint,
+    f.file_type AS old_file_type,
+    f.file_size_bytes AS old_size,
+    f.file_mtime AS old_mtime,
+    f.security_label AS old_security_label,
+    f.file_fingerprint AS old_fingerprint,
+    CASE
+        WHEN substr(f.file_fingerprint, 1, instr(f.file_fingerprint, ':'))
+            = substr(s.file_fingerprint, 1, instr(s.file_fingerprint, ':'))
+        THEN f.file_size_bytes <> s.file_size_bytes
+            OR f.file_fingerprint <> s.file_fingerprint
+        ELSE f.file_size_bytes <> s.file_size_bytes
+            OR f.file_mtime <> s.file_mtime
+    END AS is_modified
+FROM
+    staging_files AS s
+    JOIN files AS f ON f.file_path = s.file_path;
@@ -0,0 +1,210 @@
+-- process_staging.sql for the SQLite backend, after process_staging_v2.sql.
+-- Assumes parameter :scan_id is passed in.
+BEGIN;
+
+-- 1) files under the scan's root that did NOT show up in staging are deleted;
+-- the root is trimmed of its trailing slash, so that '/' matches every path
+INSERT INTO
+    file_changes (
+        scan_id,
+        file_path,
+        change_type,
+        old_size_bytes,
+        old_mtime,
+        old_file_type,
+        old_security_label,
+        old_fingerprint
+    )
+SELECT
+    :scan_id,
+    f.file_path,
+    'deleted',
+    f.file_size_bytes,
+    f.file_mtime,
+    f.file_type,
+    f.security_label,
+    f.file_fingerprint
+FROM
+    files AS f,
+    scan_runs AS r
+WHERE
+    r.scan_id = :scan_id
+    AND substr(f.file_path, 1, length(rtrim(r.scan_root, '/')) + 1) = rtrim(r.scan_root, '/') || '/'
+    AND NOT EXISTS (
+        SELECT
+            1
+        FROM
+            staging_files AS s
+        WHERE
+            s.scan_id = :scan_id
+            AND s.file_path = f.file_path
+    );
+
+DELETE FROM
+    files
+WHERE
+    file_path IN (
+        SELECT
+            file_path
+        FROM
+            file_changes
+        WHERE
+            scan_id = :scan_id
+            AND change_type = 'deleted'
+    );
+
+-- 2) brand-new files (no existing row in files)
+INSERT INTO
+    file_changes (
+        scan_id,
+        file_path,
+        change_type,
+        new_size_bytes,
+        new_mtime,
+        new_security_label,
+        new_fingerprint
+    )
+SELECT
+    :scan_id,
+    s.file_path,
+    'added',
+    s.file_size_bytes,
+    s.file_mtime,
+    s.security_label,
+    s.file_fingerprint
+FROM
+    staging_files AS s
+WHERE
+    s.scan_id = :scan_id
+    AND NOT EXISTS (
+        SELECT
+            1
+        FROM
+            files AS f
+        WHERE
+            f.file_path = s.file_path
+    );
+
+-- 3) modified files
+INSERT INTO
+    file_changes (
+        scan_id,
+        file_path,
+        change_type,
+        old_size_bytes,
+        new_size_bytes,
+        old_mtime,
+        new_mtime,
+        old_file_type,
+        old_security_label,
+        new_security_label,
+        old_fingerprint,
+        new_fingerprint
+    )
+SELECT
+    :scan_id,
+    file_path,
+    'modified',
+    old_size,
+    new_size,
+    old_mtime,
+    new_mtime,
+    old_file_type,
+    old_security_label,
+    new_security_label,
+    old_fingerprint,
+    new_fingerprint
+FROM
+    staged_comparisons
+WHERE
+    scan_id = :scan_id
+    AND is_modified;
+
+-- 4) relabeled files (unmodified, another security label); only between
+-- two scans that both recorded a label
+INSERT INTO
+    file_changes (
+        scan_id,
+        file_path,
+        change_type,
+        old_size_bytes,
+        new_size_bytes,
+        old_mtime,
+        new_mtime,
+        old_security_label,
+        new_security_label
+    )
+SELECT
+    :scan_id,
+    file_path,
+    'relabeled',
+    old_size,
+    old_size,
+    old_mtime,
+    new_mtime,
+    old_security_label,
+    new_security_label
+FROM
+    staged_comparisons
+WHERE
+    scan_id = :scan_id
+    AND NOT is_modified
+    AND new_security_label <> old_security_label;
+
+-- 5) bring the tracked rows in line with staging, keeping the label, and the
+-- fingerprint of unmodified files, where this scan recorded none
+INSERT INTO
+    files (
+        file_path,
+        file_name,
+        file_type,
+        file_size_bytes,
+        file_mtime,
+        file_fingerprint,
+        security_label,
+        last_seen_scan,
+        last_updated
+    )
+SELECT
+    s.file_path,
+    s.file_name,
+    s.file_type,
+    s.file_size_bytes,
+    s.file_mtime,
+    s.file_fingerprint,
+    s.security_label,
+    :scan_id,
+    strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+FROM
+    staging_files AS s
+WHERE
+    s.scan_id = :scan_id ON CONFLICT (file_path) DO
+UPDATE
+SET
+    file_name = excluded.file_name,
+    file_type = excluded.file_type,
+    file_size_bytes = excluded.file_size_bytes,
+    file_mtime = excluded.file_mtime,
+    security_label = COALESCE(excluded.security_label, files.security_label),
+    file_fingerprint = CASE
+        WHEN EXISTS (
+            SELECT
+                1
+            FROM
+                file_changes AS c
+            WHERE
+                c.scan_id = :scan_id
+                AND c.file_path = excluded.file_path
+                AND c.change_type = 'modified'
+        ) THEN excluded.file_fingerprint
+        ELSE COALESCE(excluded.file_fingerprint, files.file_fingerprint)
+    END,
+    last_seen_scan = excluded.last_seen_scan,
+    last_updated = excluded.last_updated;
+
+DELETE FROM
+    staging_files
+WHERE
+    scan_id = :scan_id;
+
+COMMIT;
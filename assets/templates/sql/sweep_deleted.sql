@@ -0,0 +1,68 @@
+-- sweep_deleted.sql
+-- Assumes parameter :scan_id is passed in.
+-- Final step of a batched scan: every batch bumped last_seen_scan of the files
+-- it saw, so files under the root that were not seen by any batch are gone;
+-- but those below directories whose entries a batch capped, which were not
+-- all seen.
+BEGIN;
+
+WITH scan_info AS (
+    SELECT
+        replace(btrim(scan_root, '/'), '/', '.')::ltree AS root_ltree
+    FROM
+        filesystem.scan_runs
+    WHERE
+        scan_id = :scan_id
+),
+deleted AS (
+    DELETE FROM
+        filesystem.files AS f USING scan_info
+    WHERE
+        f.path_ltree <@ scan_info.root_ltree
+        AND f.last_seen_scan <> :scan_id
+        AND NOT EXISTS (
+            SELECT
+                1
+            FROM
+                filesystem.hot_dirs AS h
+            WHERE
+                h.scan_id = :scan_id
+                AND h.truncated
+                AND left(f.file_path, length(h.dir_path) + 1) = h.dir_path || '/'
+        ) RETURNING f.file_path AS file_path,
+        f.file_type AS old_file_type,
+        f.file_size_bytes AS old_size_bytes,
+        f.file_mtime AS old_mtime,
+        f.symlink_target AS old_symlink_target,
+        f.file_mode AS old_mode,
+        f.file_uid AS old_uid,
+        f.file_gid AS old_gid
+)
+INSERT INTO
+    filesystem.file_changes (
+        scan_id,
+        file_path,
+        change_type,
+        old_size_bytes,
+        old_mtime,
+        old_file_type,
+        old_symlink_target,
+        old_mode,
+        old_uid,
+        old_gid
+    )
+SELECT
+    :scan_id,
+    file_path,
+    'deleted',
+    old_size_bytes,
+    old_mtime,
+    old_file_type,
+    old_symlink_target,
+    old_mode,
+    old_uid,
+    old_gid
+FROM
+    deleted;
+
+COMMIT;
@@ -0,0 +1,579 @@
+-- upgrade_schema.sql
+-- Brings a database created by an older init_db.sql up to the current schema
+-- without dropping any data. Safe to re-run: every step is IF NOT EXISTS or
+-- CREATE OR REPLACE. Adding a stored generated column rewrites its table, so
+-- on large tables run it in a maintenance window, with no scans running.
+BEGIN;
+
+-- Status of each scan; scans recorded before statuses existed count as
+-- completed if they finished, aborted otherwise
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT
+            1
+        FROM
+            information_schema.columns
+        WHERE
+            table_schema = 'filesystem'
+            AND table_name = 'scan_runs'
+            AND column_name = 'scan_status'
+    ) THEN
+        ALTER TABLE
+            filesystem.scan_runs
+        ADD
+            COLUMN scan_status TEXT NOT NULL DEFAULT 'running';
+
+        UPDATE
+            filesystem.scan_runs
+        SET
+            scan_status = CASE
+                WHEN finished_at IS NULL THEN 'aborted'
+                ELSE 'completed'
+            END;
+    END IF;
+END $$;
+
+-- Host of each scan, sanity-guard findings, operator notes and Merkle roots
+ALTER TABLE
+    filesystem.scan_runs
+ADD
+    COLUMN IF NOT EXISTS hostname TEXT NULL,
+ADD
+    COLUMN IF NOT EXISTS anomaly_flags JSONB NULL,
+ADD
+    COLUMN IF NOT EXISTS notes TEXT [] NOT NULL DEFAULT '{}',
+ADD
+    COLUMN IF NOT EXISTS merkle_root TEXT NULL;
+
+-- File types of deleted and modified files, for rollbacks
+ALTER TABLE
+    filesystem.file_changes
+ADD
+    COLUMN IF NOT EXISTS old_file_type TEXT NULL;
+
+-- Deltas computed in review mode, awaiting promotion by `apply_scan`
+CREATE TABLE IF NOT EXISTS filesystem.pending_file_changes (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    file_path TEXT NOT NULL,
+    change_type TEXT NOT NULL,
+    old_size_bytes BIGINT NULL,
+    new_size_bytes BIGINT NULL,
+    old_mtime TIMESTAMPTZ NULL,
+    new_mtime TIMESTAMPTZ NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (scan_id, file_path)
+);
+
+-- Directories with a pathological number of entries, per scan
+CREATE TABLE IF NOT EXISTS filesystem.hot_dirs (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    dir_path TEXT NOT NULL,
+    entry_count BIGINT NOT NULL,
+    -- entries beyond --max-entries-per-dir were not recorded
+    truncated BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (scan_id, dir_path)
+);
+
+-- Per-scan file count and volume by extension
+CREATE TABLE IF NOT EXISTS filesystem.extension_stats (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    file_type TEXT NOT NULL,
+    file_count BIGINT NOT NULL,
+    total_size_bytes BIGINT NOT NULL,
+    PRIMARY KEY (scan_id, file_type)
+);
+
+-- Per-root limits on the deltas accumulated over a rolling window, see `set_budget`
+CREATE TABLE IF NOT EXISTS filesystem.delta_budgets (
+    scan_root TEXT NOT NULL,
+    metric TEXT NOT NULL CHECK (
+        metric IN (
+            'new_data_mb',
+            'modified_data_mb',
+            'deleted_data_mb',
+            'added_files_count',
+            'modified_files_count',
+            'removed_files_count'
+        )
+    ),
+    max_value FLOAT NOT NULL,
+    window_days INT NOT NULL DEFAULT 7,
+    PRIMARY KEY (scan_root, metric)
+);
+
+-- Owners / teams of directory trees, loaded from a config file by `load_owners`
+CREATE TABLE IF NOT EXISTS filesystem.directory_owners (
+    dir_prefix TEXT PRIMARY KEY,
+    owner TEXT NOT NULL
+);
+
+CREATE
+OR REPLACE FUNCTION filesystem.path_owner(path TEXT, root TEXT) RETURNS TEXT LANGUAGE sql STABLE AS $$
+SELECT
+    COALESCE(
+        (
+            SELECT
+                o.owner
+            FROM
+                filesystem.directory_owners AS o
+            WHERE
+                left(path, length(rtrim(o.dir_prefix, '/')) + 1) = rtrim(o.dir_prefix, '/') || '/'
+            ORDER BY
+                length(o.dir_prefix) DESC
+            LIMIT
+                1
+        ), CASE
+            WHEN strpos(substr(path, length(rtrim(root, '/')) + 2), '/') = 0 THEN '(root)'
+            ELSE split_part(substr(path, length(rtrim(root, '/')) + 2), '/', 1)
+        END
+    ) $$;
+
+-- Append-only record of scans and administrative actions
+CREATE TABLE IF NOT EXISTS filesystem.audit_log (
+    audit_id BIGSERIAL PRIMARY KEY,
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    action TEXT NOT NULL,
+    scan_id INT NULL,
+    os_user TEXT NOT NULL,
+    db_user TEXT NOT NULL DEFAULT current_user,
+    hostname TEXT NOT NULL,
+    details JSONB NOT NULL DEFAULT '{}'::jsonb
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_scan_id_idx ON filesystem.audit_log (scan_id);
+
+CREATE
+OR REPLACE FUNCTION filesystem.audit_log_immutable() RETURNS trigger LANGUAGE plpgsql AS $$
+BEGIN
+    RAISE EXCEPTION 'filesystem.audit_log is append-only';
+END $$;
+
+CREATE
+OR REPLACE TRIGGER audit_log_no_modify BEFORE
+UPDATE
+    OR DELETE ON filesystem.audit_log FOR EACH ROW EXECUTE FUNCTION filesystem.audit_log_immutable();
+
+CREATE
+OR REPLACE TRIGGER audit_log_no_truncate BEFORE TRUNCATE ON filesystem.audit_log FOR EACH STATEMENT EXECUTE FUNCTION filesystem.audit_log_immutable();
+
+-- Tenant of each scan
+ALTER TABLE
+    filesystem.scan_runs
+ADD
+    COLUMN IF NOT EXISTS tenant TEXT NULL;
+
+CREATE INDEX IF NOT EXISTS scan_runs_tenant_idx ON filesystem.scan_runs (tenant);
+
+-- Path encryption key of each scan
+ALTER TABLE
+    filesystem.scan_runs
+ADD
+    COLUMN IF NOT EXISTS path_key_id TEXT NULL;
+
+-- Pausing running scans
+ALTER TABLE
+    filesystem.scan_runs
+ADD
+    COLUMN IF NOT EXISTS paused_at TIMESTAMPTZ NULL;
+
+-- Last pipeline phase completed by each scan, for `resume_scan`
+ALTER TABLE
+    filesystem.scan_runs
+ADD
+    COLUMN IF NOT EXISTS scan_phase TEXT NULL;
+
+-- Roots of scans of several (`--extra-root`)
+ALTER TABLE
+    filesystem.scan_runs
+ADD
+    COLUMN IF NOT EXISTS scan_roots TEXT[] NULL;
+
+-- Security labels of files and their changes (`--security-labels`)
+ALTER TABLE
+    filesystem.files
+ADD
+    COLUMN IF NOT EXISTS security_label TEXT NULL;
+
+ALTER TABLE
+    filesystem.staging_files
+ADD
+    COLUMN IF NOT EXISTS security_label TEXT NULL;
+
+ALTER TABLE
+    filesystem.file_changes
+ADD
+    COLUMN IF NOT EXISTS old_security_label TEXT NULL,
+ADD
+    COLUMN IF NOT EXISTS new_security_label TEXT NULL;
+
+ALTER TABLE
+    filesystem.pending_file_changes
+ADD
+    COLUMN IF NOT EXISTS old_security_label TEXT NULL,
+ADD
+    COLUMN IF NOT EXISTS new_security_label TEXT NULL;
+
+-- Content fingerprints of staged files and changes (`--content-hash`)
+-- Whether a file's contents changed between two sightings: by fingerprint
+-- when both were hashed with the same algorithm (`--content-hash`), so that
+-- touch-only updates do not count, by size and mtime otherwise
+CREATE
+OR REPLACE FUNCTION filesystem.is_modified(
+    old_size BIGINT,
+    old_mtime TIMESTAMPTZ,
+    old_fingerprint TEXT,
+    new_size BIGINT,
+    new_mtime TIMESTAMPTZ,
+    new_fingerprint TEXT
+) RETURNS BOOLEAN LANGUAGE sql IMMUTABLE AS $$
+SELECT
+    CASE
+        WHEN split_part(old_fingerprint, ':', 1) = split_part(new_fingerprint, ':', 1) THEN old_size <> new_size
+        OR old_fingerprint <> new_fingerprint
+        ELSE old_size <> new_size
+        OR old_mtime <> new_mtime
+    END $$;
+
+ALTER TABLE
+    filesystem.staging_files
+ADD
+    COLUMN IF NOT EXISTS file_fingerprint TEXT NULL;
+
+ALTER TABLE
+    filesystem.file_changes
+ADD
+    COLUMN IF NOT EXISTS old_fingerprint TEXT NULL,
+ADD
+    COLUMN IF NOT EXISTS new_fingerprint TEXT NULL;
+
+ALTER TABLE
+    filesystem.pending_file_changes
+ADD
+    COLUMN IF NOT EXISTS old_fingerprint TEXT NULL,
+ADD
+    COLUMN IF NOT EXISTS new_fingerprint TEXT NULL;
+
+-- Targets of recorded symlinks and their changes (`--record-symlinks`)
+ALTER TABLE
+    filesystem.files
+ADD
+    COLUMN IF NOT EXISTS symlink_target TEXT NULL;
+
+ALTER TABLE
+    filesystem.staging_files
+ADD
+    COLUMN IF NOT EXISTS symlink_target TEXT NULL;
+
+ALTER TABLE
+    filesystem.file_changes
+ADD
+    COLUMN IF NOT EXISTS old_symlink_target TEXT NULL,
+ADD
+    COLUMN IF NOT EXISTS new_symlink_target TEXT NULL;
+
+ALTER TABLE
+    filesystem.pending_file_changes
+ADD
+    COLUMN IF NOT EXISTS old_symlink_target TEXT NULL,
+ADD
+    COLUMN IF NOT EXISTS new_symlink_target TEXT NULL;
+
+-- Permission bits and owners of files and their changes (`--record-ownership`)
+ALTER TABLE
+    filesystem.files
+ADD
+    COLUMN IF NOT EXISTS file_mode INT NULL,
+ADD
+    COLUMN IF NOT EXISTS file_uid BIGINT NULL,
+ADD
+    COLUMN IF NOT EXISTS file_gid BIGINT NULL;
+
+ALTER TABLE
+    filesystem.staging_files
+ADD
+    COLUMN IF NOT EXISTS file_mode INT NULL,
+ADD
+    COLUMN IF NOT EXISTS file_uid BIGINT NULL,
+ADD
+    COLUMN IF NOT EXISTS file_gid BIGINT NULL;
+
+ALTER TABLE
+    filesystem.file_changes
+ADD
+    COLUMN IF NOT EXISTS old_mode INT NULL,
+ADD
+    COLUMN IF NOT EXISTS new_mode INT NULL,
+ADD
+    COLUMN IF NOT EXISTS old_uid BIGINT NULL,
+ADD
+    COLUMN IF NOT EXISTS new_uid BIGINT NULL,
+ADD
+    COLUMN IF NOT EXISTS old_gid BIGINT NULL,
+ADD
+    COLUMN IF NOT EXISTS new_gid BIGINT NULL;
+
+ALTER TABLE
+    filesystem.pending_file_changes
+ADD
+    COLUMN IF NOT EXISTS old_mode INT NULL,
+ADD
+    COLUMN IF NOT EXISTS new_mode INT NULL,
+ADD
+    COLUMN IF NOT EXISTS old_uid BIGINT NULL,
+ADD
+    COLUMN IF NOT EXISTS new_uid BIGINT NULL,
+ADD
+    COLUMN IF NOT EXISTS old_gid BIGINT NULL,
+ADD
+    COLUMN IF NOT EXISTS new_gid BIGINT NULL;
+
+-- Directory rollup columns of filesystem.files and filesystem.file_changes
+CREATE
+OR REPLACE FUNCTION filesystem.parent_dir(path TEXT) RETURNS TEXT LANGUAGE sql IMMUTABLE AS $$
+SELECT
+    regexp_replace(path, '/[^/]*$', '') $$;
+
+CREATE
+OR REPLACE FUNCTION filesystem.path_depth(path TEXT) RETURNS INT LANGUAGE sql IMMUTABLE AS $$
+SELECT
+    cardinality(regexp_split_to_array(btrim(path, '/'), '/+')) $$;
+
+ALTER TABLE
+    filesystem.files
+ADD
+    COLUMN IF NOT EXISTS parent_dir TEXT GENERATED ALWAYS AS (filesystem.parent_dir(file_path)) STORED,
+ADD
+    COLUMN IF NOT EXISTS path_depth INT GENERATED ALWAYS AS (filesystem.path_depth(file_path)) STORED;
+
+ALTER TABLE
+    filesystem.file_changes
+ADD
+    COLUMN IF NOT EXISTS parent_dir TEXT GENERATED ALWAYS AS (filesystem.parent_dir(file_path)) STORED,
+ADD
+    COLUMN IF NOT EXISTS path_depth INT GENERATED ALWAYS AS (filesystem.path_depth(file_path)) STORED;
+
+CREATE INDEX IF NOT EXISTS files_parent_dir_idx ON filesystem.files (parent_dir);
+
+CREATE INDEX IF NOT EXISTS files_path_depth_idx ON filesystem.files (path_depth, parent_dir);
+
+CREATE INDEX IF NOT EXISTS files_fingerprint_idx ON filesystem.files (file_fingerprint)
+WHERE
+    file_fingerprint IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS file_changes_added_dir_idx ON filesystem.file_changes (scan_id, parent_dir)
+WHERE
+    change_type = 'added';
+
+CREATE INDEX IF NOT EXISTS file_changes_modified_dir_idx ON filesystem.file_changes (scan_id, parent_dir)
+WHERE
+    change_type = 'modified';
+
+CREATE INDEX IF NOT EXISTS file_changes_deleted_dir_idx ON filesystem.file_changes (scan_id, parent_dir)
+WHERE
+    change_type = 'deleted';
+
+CREATE INDEX IF NOT EXISTS file_changes_added_size_idx ON filesystem.file_changes (scan_id, new_size_bytes DESC)
+WHERE
+    change_type = 'added';
+
+-- Shards of sharded scans
+CREATE TABLE IF NOT EXISTS filesystem.scan_shards (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    shard_index INT NOT NULL,
+    batch_paths TEXT [] NOT NULL,
+    shard_status TEXT NOT NULL DEFAULT 'pending',
+    worker TEXT NULL,
+    claimed_at TIMESTAMPTZ NULL,
+    heartbeat_at TIMESTAMPTZ NULL,
+    finished_at TIMESTAMPTZ NULL,
+    shard_metadata JSONB NULL,
+    error TEXT NULL,
+    PRIMARY KEY (scan_id, shard_index)
+);
+
+-- Checkpoints of chunked staging loads
+CREATE TABLE IF NOT EXISTS filesystem.load_checkpoints (
+    scan_id INT PRIMARY KEY REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    tsv_file TEXT NOT NULL,
+    tsv_size_bytes BIGINT NOT NULL,
+    byte_offset BIGINT NOT NULL,
+    rows_loaded BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Quick scans: per-directory file counts and sizes without per-file rows, for
+-- daily trend lines between full scans (`scan --quick`)
+CREATE TABLE IF NOT EXISTS filesystem.quick_scans (
+    quick_scan_id SERIAL PRIMARY KEY,
+    scan_root TEXT NOT NULL,
+    tenant TEXT NULL,
+    hostname TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    -- NULL while running, or if the quick scan failed
+    finished_at TIMESTAMPTZ NULL,
+    total_files BIGINT NULL,
+    total_size_bytes BIGINT NULL,
+    scan_metadata JSONB NULL
+);
+
+CREATE INDEX IF NOT EXISTS quick_scans_root_idx ON filesystem.quick_scans (scan_root, started_at);
+
+-- Files directly in each directory of a quick scan; directories without files are omitted
+CREATE TABLE IF NOT EXISTS filesystem.dir_summaries (
+    quick_scan_id INT NOT NULL REFERENCES filesystem.quick_scans(quick_scan_id) ON DELETE CASCADE,
+    dir_path TEXT NOT NULL,
+    file_count BIGINT NOT NULL,
+    total_size_bytes BIGINT NOT NULL,
+    PRIMARY KEY (quick_scan_id, dir_path)
+);
+
+-- Directories of scans recording them (`--record-dirs`), with the totals of
+-- the files below them
+CREATE TABLE IF NOT EXISTS filesystem.directories (
+    dir_path TEXT PRIMARY KEY,
+    -- entries directly in the directory, of any type
+    entry_count BIGINT NOT NULL,
+    -- recorded files below the directory, at any depth
+    file_count BIGINT NOT NULL,
+    total_size_bytes BIGINT NOT NULL,
+    dir_mtime TIMESTAMPTZ NOT NULL,
+    last_seen_scan INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON UPDATE CASCADE ON DELETE CASCADE,
+    last_updated TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS directories_last_seen_scan_idx ON filesystem.directories (last_seen_scan);
+
+CREATE TABLE IF NOT EXISTS filesystem.dir_changes (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    dir_path TEXT NOT NULL,
+    -- added, modified (any count, size or mtime changed) or deleted
+    change_type TEXT NOT NULL,
+    old_entry_count BIGINT NULL,
+    new_entry_count BIGINT NULL,
+    old_file_count BIGINT NULL,
+    new_file_count BIGINT NULL,
+    old_size_bytes BIGINT NULL,
+    new_size_bytes BIGINT NULL,
+    old_mtime TIMESTAMPTZ NULL,
+    new_mtime TIMESTAMPTZ NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (scan_id, dir_path)
+);
+
+CREATE INDEX IF NOT EXISTS dir_changes_change_type_idx ON filesystem.dir_changes (scan_id, change_type);
+
+CREATE UNLOGGED TABLE IF NOT EXISTS filesystem.staging_dirs (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    dir_path TEXT NOT NULL,
+    entry_count BIGINT NOT NULL,
+    file_count BIGINT NOT NULL,
+    total_size_bytes BIGINT NOT NULL,
+    dir_mtime TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (scan_id, dir_path)
+);
+
+-- Scans whose changes were pushed to a data catalog by `catalog_sync`
+CREATE TABLE IF NOT EXISTS filesystem.catalog_syncs (
+    -- the catalog's URL, or `irods:` and its collection
+    catalog TEXT NOT NULL,
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    added_count BIGINT NOT NULL,
+    modified_count BIGINT NOT NULL,
+    removed_count BIGINT NOT NULL,
+    synced_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (catalog, scan_id)
+);
+
+-- Downstream consumers of the changes of completed scans, each notified of the
+-- changes matching its filters on the channel named after it, see `subscriptions`
+CREATE TABLE IF NOT EXISTS filesystem.change_subscriptions (
+    -- also the NOTIFY channel, so a plain lowercase identifier
+    consumer TEXT PRIMARY KEY CHECK (consumer ~ '^[a-z_][a-z0-9_]{0,62}$'),
+    -- only changes of this path or of paths below it; NULL for all
+    path_prefix TEXT NULL,
+    -- only changes of these types; NULL for all
+    change_types TEXT[] NULL,
+    -- POST the changes here instead of sending them with NOTIFY
+    webhook_url TEXT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+ALTER TABLE
+    filesystem.change_subscriptions
+ADD
+    COLUMN IF NOT EXISTS webhook_url TEXT NULL;
+
+-- Changes queued for the subscriptions they match, in the transaction that
+-- recorded them, until `subscriptions deliver` (or the scan's end) delivers them
+CREATE TABLE IF NOT EXISTS filesystem.event_outbox (
+    event_id BIGSERIAL PRIMARY KEY,
+    consumer TEXT NOT NULL REFERENCES filesystem.change_subscriptions(consumer) ON DELETE CASCADE,
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    payload JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_error TEXT NULL,
+    delivered_at TIMESTAMPTZ NULL,
+    -- given up on after too many failed attempts, until requeued
+    dead_lettered_at TIMESTAMPTZ NULL
+);
+
+CREATE INDEX IF NOT EXISTS event_outbox_due_idx ON filesystem.event_outbox (event_id)
+WHERE
+    delivered_at IS NULL
+    AND dead_lettered_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS event_outbox_consumer_idx ON filesystem.event_outbox (consumer);
+
+CREATE INDEX IF NOT EXISTS event_outbox_scan_id_idx ON filesystem.event_outbox (scan_id);
+
+-- Queue the changes each statement records for the subscriptions they match
+CREATE
+OR REPLACE FUNCTION filesystem.queue_change_events() RETURNS trigger LANGUAGE plpgsql AS $$
+BEGIN
+    INSERT INTO filesystem.event_outbox (consumer, scan_id, payload)
+    SELECT s.consumer, c.scan_id,
+           jsonb_build_object(
+               'consumer', s.consumer,
+               'scan_id', c.scan_id,
+               'scan_root', r.scan_root,
+               'change_type', c.change_type,
+               'path', c.file_path,
+               'size_bytes', COALESCE(c.new_size_bytes, c.old_size_bytes),
+               'mtime', COALESCE(c.new_mtime, c.old_mtime),
+               'fingerprint', COALESCE(c.new_fingerprint, c.old_fingerprint)
+           )
+    FROM recorded_changes AS c
+    JOIN filesystem.scan_runs AS r ON r.scan_id = c.scan_id
+    JOIN filesystem.change_subscriptions AS s
+      ON (s.path_prefix IS NULL
+          OR s.path_prefix = '/'
+          OR c.file_path = s.path_prefix
+          OR starts_with(c.file_path, s.path_prefix || '/'))
+     AND (s.change_types IS NULL OR c.change_type = ANY (s.change_types))
+    ORDER BY s.consumer, c.file_path;
+    RETURN NULL;
+END $$;
+
+CREATE
+OR REPLACE TRIGGER file_changes_queue_events
+AFTER
+INSERT
+    ON filesystem.file_changes REFERENCING NEW TABLE AS recorded_changes FOR EACH STATEMENT EXECUTE FUNCTION filesystem.queue_change_events();
+
+-- Entries the walker of a scan failed to read, left out of its crawl with
+-- whatever is below them, e.g. unreadable directories or stale NFS handles
+CREATE TABLE IF NOT EXISTS filesystem.scan_errors (
+    scan_id INT NOT NULL REFERENCES filesystem.scan_runs(scan_id) ON DELETE CASCADE,
+    entry_path TEXT NOT NULL,
+    -- permission_denied, stale_handle, not_found, fd_exhausted, name_too_long, loop or io
+    error_kind TEXT NOT NULL,
+    message TEXT NOT NULL,
+    PRIMARY KEY (scan_id, entry_path)
+);
+
+CREATE INDEX IF NOT EXISTS scan_errors_kind_idx ON filesystem.scan_errors (scan_id, error_kind);
+
+COMMIT;
@@ -0,0 +1,93 @@
+use anyhow::Ok;
+use clap::Parser;
+
+use fs_delta_tracker::logging;
+
+static PROJECT_DIR: include_dir::Dir = include_dir::include_dir!("$CARGO_MANIFEST_DIR/assets");
+/// Datasource uid of the template, an import input Grafana prompts for
+const DATASOURCE_INPUT: &str = "${DS_FS_DELTA_TRACKER}";
+
+/// Command-line tool to export a ready-to-import Grafana dashboard wired to the reporting views.
+#[derive(clap::Parser, Debug)]
+#[command(author, version, about)]
+struct Opt {
+    /// Path to log file (default: logs/app.log).
+    #[arg(long, env = "LOG_FILE")]
+    log_file: Option<std::path::PathBuf>,
+
+    /// Output JSON file. If not provided, the dashboard is printed to stdout.
+    #[arg(long)]
+    out: Option<std::path::PathBuf>,
+
+    /// UID of an existing Grafana PostgreSQL datasource to wire the panels to.
+    /// If not provided, Grafana prompts for the datasource on import.
+    #[arg(long)]
+    datasource_uid: Option<String>,
+
+    /// Dashboard title.
+    #[arg(long, default_value = "fs-delta-tracker")]
+    title: String,
+}
+
+fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    let opt = Opt::parse();
+
+    let template = PROJECT_DIR
+        .get_file("templates/grafana/dashboard.json")
+        .expect("Dashboard template file not found")
+        .contents_utf8()
+        .expect("Failed to read dashboard template as UTF-8");
+
+    let mut dashboard: serde_json::Value = serde_json::from_str(template)?;
+    dashboard["title"] = serde_json::Value::String(opt.title.clone());
+    if let Some(uid) = &opt.datasource_uid {
+        set_datasource_uid(&mut dashboard, uid);
+        if let Some(obj) = dashboard.as_object_mut() {
+            // Import inputs only make sense when Grafana has to ask for the datasource
+            obj.remove("__inputs");
+        }
+    }
+
+    let json = serde_json::to_string_pretty(&dashboard)?;
+    match &opt.out {
+        Some(out) => {
+            let _guard = logging::setup_logging(opt.log_file.as_deref())?;
+            if let Some(p) = out.parent() {
+                std::fs::create_dir_all(p)?;
+            }
+            std::fs::write(out, format!("{}\n", json))?;
+            tracing::info!("📊 Grafana dashboard written to {}", out.display());
+        }
+        None => println!("{}", json),
+    }
+
+    Ok(())
+}
+
+/// Point every `datasource` object still referring to the import input at `uid`
+fn set_datasource_uid(value: &mut serde_json::Value, uid: &str) {
+    match value {
+        serde_json::Value::Object(obj) => {
+            for (key, child) in obj.iter_mut() {
+                if key == "datasource"
+                    && child["uid"] == DATASOURCE_INPUT
+                    && let Some(datasource) = child.as_object_mut()
+                {
+                    datasource.insert(
+                        "uid".to_string(),
+                        serde_json::Value::String(uid.to_string()),
+                    );
+                } else {
+                    set_datasource_uid(child, uid);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                set_datasource_uid(item, uid);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,89 @@
+use clap::Parser;
+
+use fs_delta_tracker::{lock, logging};
+
+/// Privileged daemon logging the paths changed under a root between scans, so that scans run
+/// with `--snapshot-diff fanotify` restate only those instead of crawling the whole root. Needs
+/// Linux 5.9 or later, CAP_SYS_ADMIN and CAP_DAC_READ_SEARCH.
+#[derive(clap::Parser, Debug)]
+#[command(author, version, about)]
+struct Opt {
+    /// Path to log file (default: logs/app.log).
+    #[arg(long, env = "LOG_FILE")]
+    log_file: Option<std::path::PathBuf>,
+
+    /// Root whose changes are logged. Its whole filesystem is watched.
+    #[arg(short, long, env = "DATA_ROOT")]
+    data_root: std::path::PathBuf,
+
+    /// Directory of the per-root lock files, where the change log is kept; must match the
+    /// scans' (default: `fs-delta-tracker` under the system temp directory).
+    #[arg(long, env = "LOCK_DIR")]
+    lock_dir: Option<std::path::PathBuf>,
+
+    /// Start a new change log once it grows past this size; the next scan then crawls the
+    /// root.
+    #[arg(long, env = "FANOTIFY_MAX_LOG_MB", default_value_t = 1024)]
+    max_log_mb: u64,
+
+    /// TOML file of settings reloaded on SIGHUP or when it changes, without losing the
+    /// change log: `log_level` and `max_log_mb`.
+    #[arg(long, env = "DAEMON_CONFIG")]
+    config: Option<std::path::PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    let opt = Opt::parse();
+    let _guard = logging::setup_logging(opt.log_file.as_deref())?;
+    watch(opt).await
+}
+
+#[cfg(target_os = "linux")]
+async fn watch(opt: Opt) -> anyhow::Result<()> {
+    use fs_delta_tracker::{fanotify, reload, systemd};
+
+    let (reloader, config) = match opt.config {
+        Some(path) => {
+            let (reloader, config) = reload::ConfigReloader::new(path)?;
+            (Some(reloader), config)
+        }
+        None => (None, reload::ReloadableConfig::default()),
+    };
+    let default_max_log_mb = opt.max_log_mb;
+    let max_log_mb = move |config: &reload::ReloadableConfig| {
+        config.max_log_mb.unwrap_or(default_max_log_mb) * 1024 * 1024
+    };
+    let max_log_bytes = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(max_log_mb(&config)));
+
+    let lock_dir = opt.lock_dir.unwrap_or_else(lock::default_lock_dir);
+    let watcher = fanotify::Watcher::start(&lock_dir, &opt.data_root, max_log_bytes.clone())?;
+    tracing::info!(
+        "👀 Watching {} (change log {})",
+        watcher.root().display(),
+        watcher.log_path().display()
+    );
+    systemd::notify_ready(&format!("watching {}", watcher.root().display()));
+    let _watchdog = systemd::spawn_watchdog();
+
+    if let Some(mut reloader) = reloader {
+        tokio::spawn(async move {
+            loop {
+                let config = reloader.changed().await;
+                max_log_bytes.store(max_log_mb(&config), std::sync::atomic::Ordering::Relaxed);
+            }
+        });
+    }
+    tokio::task::spawn_blocking(move || watcher.run()).await?
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn watch(opt: Opt) -> anyhow::Result<()> {
+    let _ = (
+        opt.data_root,
+        opt.lock_dir.unwrap_or_else(lock::default_lock_dir),
+        opt.config,
+    );
+    anyhow::bail!("fanotify_watch needs Linux")
+}
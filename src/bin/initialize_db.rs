@@ -1,7 +1,7 @@
 use anyhow::Ok;
 use clap::Parser;
 
-use fs_delta_tracker::{db, logging};
+use fs_delta_tracker::{confirm, data, db, logging, staging};
 
 static PROJECT_DIR: include_dir::Dir = include_dir::include_dir!("$CARGO_MANIFEST_DIR/assets");
 
@@ -16,6 +16,35 @@ struct Opt {
     /// Path to log file (default: logs/app.log).
     #[arg(long, env = "LOG_FILE")]
     log_file: Option<std::path::PathBuf>,
+
+    /// Also create a pg_trgm index on file paths, so `search` can match path fragments
+    /// without scanning every file. Needs the pg_trgm extension.
+    #[arg(long, env = "TRIGRAM_INDEX")]
+    trigram_index: bool,
+
+    /// Staging strategy scans will use: `logged` makes the shared staging table crash-safe
+    /// and replicated at the cost of WAL for every staged row, `unlogged` skips that WAL,
+    /// and `temporary` scans stage in per-scan tables (the shared one stays unlogged).
+    #[arg(
+        long,
+        env = "STAGING_STRATEGY",
+        default_value_t = staging::StagingStrategy::Unlogged
+    )]
+    staging_strategy: staging::StagingStrategy,
+
+    /// Create (or refresh the grants of) the `fs_delta_tracker_read` and
+    /// `fs_delta_tracker_admin` group roles, for read-only dashboards and for scanning hosts.
+    #[arg(long, env = "CREATE_ROLES")]
+    create_roles: bool,
+
+    /// Enable row-level security so members of `fs_delta_tracker_tenant_<tenant>` roles only
+    /// see their tenant's scans and files. Implies --create-roles.
+    #[arg(long, env = "TENANT_ISOLATION")]
+    tenant_isolation: bool,
+
+    /// Do not ask for confirmation before dropping existing data.
+    #[arg(short, long, env = "ASSUME_YES")]
+    yes: bool,
 }
 
 #[tokio::main]
@@ -40,14 +69,25 @@ async fn main() -> anyhow::Result<()> {
     );
     tracing::info!("{}", "=".repeat(50));
 
-    tracing::info!("⚠️ This will drop all existing tables and data in the database!");
-
     tracing::info!("🔗 Connecting to database...");
     let (client, connection) =
         tokio_postgres::connect(&opt.database_url, tokio_postgres::NoTls).await?;
     tokio::spawn(connection);
     tracing::info!("🔗 Connected to database");
 
+    if let Some((scans, files, changes)) = data::count_tracked_rows(&client).await? {
+        tracing::info!("⚠️ This will drop all existing tables and data in the database!");
+        confirm::confirm(
+            "drop all tracked data",
+            &[
+                (scans, "scans"),
+                (files, "current files"),
+                (changes, "recorded changes"),
+            ],
+            opt.yes,
+        )?;
+    }
+
     let processing_sql = PROJECT_DIR
         .get_file("templates/sql/init_db.sql")
         .expect("SQL template file not found")
@@ -60,6 +100,55 @@ async fn main() -> anyhow::Result<()> {
             anyhow::anyhow!("SQL execution failed: {}", e)
         })?;
 
+    tracing::info!("📥 Staging strategy: {}", opt.staging_strategy);
+    opt.staging_strategy.configure(&client).await?;
+
+    if opt.trigram_index {
+        tracing::info!("🔤 Creating trigram index on file paths...");
+        let trigram_sql = PROJECT_DIR
+            .get_file("templates/sql/create_trigram_index.sql")
+            .expect("SQL template file not found")
+            .contents_utf8()
+            .expect("Failed to read SQL template as UTF-8");
+        db::execute_sql_template_str(&client, trigram_sql, None).await?;
+    }
+
+    tracing::info!("📊 Creating reporting views...");
+    let views_sql = PROJECT_DIR
+        .get_file("templates/sql/create_views.sql")
+        .expect("SQL template file not found")
+        .contents_utf8()
+        .expect("Failed to read SQL template as UTF-8");
+    db::execute_sql_template_str(&client, views_sql, None).await?;
+
+    if opt.create_roles || opt.tenant_isolation {
+        tracing::info!("🔐 Creating access roles...");
+        let roles_sql = PROJECT_DIR
+            .get_file("templates/sql/create_roles.sql")
+            .expect("SQL template file not found")
+            .contents_utf8()
+            .expect("Failed to read SQL template as UTF-8");
+        db::execute_sql_template_str(&client, roles_sql, None).await?;
+    }
+
+    if opt.tenant_isolation {
+        tracing::info!("🏢 Enabling tenant isolation...");
+        let isolation_sql = PROJECT_DIR
+            .get_file("templates/sql/enable_tenant_isolation.sql")
+            .expect("SQL template file not found")
+            .contents_utf8()
+            .expect("Failed to read SQL template as UTF-8");
+        db::execute_sql_template_str(&client, isolation_sql, None).await?;
+    }
+
+    data::audit(
+        &client,
+        "database_initialized",
+        None,
+        serde_json::json!({ "staging_strategy": opt.staging_strategy.to_string() }),
+    )
+    .await?;
+
     tracing::info!("✅ Database initialized successfully!");
 
     Ok(())
@@ -0,0 +1,202 @@
+use clap::Parser;
+
+use fs_delta_tracker::{
+    content_hash, crawler, extension, fault, fs_type, hash_cache, local_state, lock, output,
+    path_cipher, pipeline, progress, security_label, thread_tuner,
+};
+
+/// Command-line tool to track a directory without a database: each run diffs a crawl
+/// against the snapshot kept by the previous run and writes the changes to a delta file.
+#[derive(clap::Parser, Debug)]
+#[command(author, version, about)]
+struct Opt {
+    /// The directory to scan
+    #[arg(short, long, env = "DATA_ROOT")]
+    data_root: std::path::PathBuf,
+
+    /// Directory keeping the root's snapshot between runs, and its delta files under
+    /// `deltas/`. One per root.
+    #[arg(long, env = "LOCAL_STATE_DIR")]
+    state_dir: std::path::PathBuf,
+
+    /// Path to log file (default: logs/app.log).
+    #[arg(long, env = "LOG_FILE")]
+    log_file: Option<std::path::PathBuf>,
+
+    /// Progress logging interval in seconds.
+    #[arg(long, env = "PROGRESS_INTERVAL", default_value_t = 30)]
+    progress_interval: u64,
+
+    /// Directory of the per-root lock files preventing overlapping crawls on this host.
+    #[arg(long, env = "LOCK_DIR")]
+    lock_dir: Option<std::path::PathBuf>,
+
+    /// Keep the original case of file extensions instead of lowercasing them.
+    #[arg(long, env = "KEEP_EXTENSION_CASE")]
+    keep_extension_case: bool,
+
+    /// Comma-separated multi-part extensions recorded as a single file type.
+    #[arg(
+        long,
+        env = "MULTI_PART_EXTENSIONS",
+        value_delimiter = ',',
+        default_values_t = extension::DEFAULT_MULTI_PART_EXTENSIONS.iter().map(|e| e.to_string())
+    )]
+    multi_part_extensions: Vec<String>,
+
+    /// File type recorded for files without an extension.
+    #[arg(long, env = "UNKNOWN_EXTENSION", default_value = "unknown")]
+    unknown_extension: String,
+
+    /// Record each file's security label, `selinux` (context) or `smack`, and report
+    /// label changes as `relabeled`.
+    #[arg(long, env = "SECURITY_LABELS")]
+    security_labels: Option<security_label::LabelSource>,
+
+    /// Read every file to record a checksum of its contents, `xxhash`, `blake3` or `sha256`,
+    /// so that same-size edits are detected and touch-only updates are not. Reads all data
+    /// below the root on each scan, less on network filesystems (see `--fs-tuning`).
+    #[arg(long, env = "CONTENT_HASH")]
+    content_hash: Option<content_hash::HashAlgorithm>,
+
+    /// Files --content-hash reads: `metadata_only`, `small_files` or `full`, for every root
+    /// (lowered further on filesystems the tuning table hashes less of) or, as `ROOT=POLICY`,
+    /// for the roots at or below ROOT; comma-separated or repeated.
+    #[arg(long = "hash-policy", env = "HASH_POLICY", value_delimiter = ',')]
+    hash_policies: Vec<content_hash::RootHashPolicy>,
+
+    /// Largest file, in bytes, hashed under the `small_files` policy.
+    #[arg(long, env = "HASH_SMALL_FILE_BYTES", default_value_t = content_hash::SMALL_FILE_BYTES)]
+    hash_small_file_bytes: u64,
+
+    /// SQLite file caching the fingerprints --content-hash computed, by device, inode, size and
+    /// mtime, so that files unchanged since an earlier scan are not read again; created if absent.
+    #[arg(long, env = "HASH_CACHE")]
+    hash_cache: Option<std::path::PathBuf>,
+
+    /// TOML file overriding the built-in walker threads, mount following and hashing policy
+    /// per filesystem type, e.g. `[nfs]` / `threads = 64`.
+    #[arg(long, env = "FS_TUNING")]
+    fs_tuning: Option<std::path::PathBuf>,
+
+    /// Tune the number of walker threads during the crawl: start with a few, measure the stat
+    /// throughput and add or remove threads to find the most the storage benefits from. The
+    /// next run starts from the number chosen.
+    #[arg(long, env = "ADAPTIVE_THREADS")]
+    adaptive_threads: bool,
+
+    /// Read all directories below the root with many threads before the walk, so it finds
+    /// them cached; speeds up crawls of cold spinning disks.
+    #[arg(long, env = "PREWARM")]
+    prewarm: bool,
+
+    /// Only record files matching this glob (gitignore syntax, relative to the root), e.g.
+    /// `*.dcm`; repeatable, or `{a,b}` alternatives. Tracked files left out are recorded as deleted.
+    #[arg(long = "include", env = "INCLUDE_GLOBS")]
+    include: Vec<String>,
+
+    /// Skip files and whole directories matching this glob, e.g. `node_modules`; repeatable,
+    /// and wins over --include.
+    #[arg(long = "exclude", env = "EXCLUDE_GLOBS")]
+    exclude: Vec<String>,
+
+    /// How to traverse directories: `parallel` (many threads, the default) or `dirfd` (one
+    /// thread opening each directory relative to its parent, for trees nested beyond PATH_MAX).
+    #[arg(long, env = "WALKER", default_value_t = crawler::WalkerBackend::Parallel)]
+    walker: crawler::WalkerBackend,
+
+    /// Hash this fraction of the files (e.g. `0.01`), drawn anew each scan, and check that
+    /// they start like their extension says; records bit-rot and damage estimates.
+    #[arg(long, env = "SAMPLE_FRACTION")]
+    sample_fraction: Option<f64>,
+
+    /// Descend into the targets of symlinks, recording what they lead to under the link's
+    /// path; loops are detected and skipped. Not with `--walker dirfd`.
+    #[arg(long, env = "FOLLOW_SYMLINKS", conflicts_with = "record_symlinks")]
+    follow_symlinks: bool,
+
+    /// Record symlinks as entries of their own, with their target, so that links created,
+    /// removed or retargeted show up as deltas.
+    #[arg(long, env = "RECORD_SYMLINKS")]
+    record_symlinks: bool,
+
+    /// File holding the site key to encrypt file names and paths below the root with.
+    #[arg(long, env = "PATH_ENCRYPTION_KEY_FILE")]
+    path_encryption_key_file: Option<std::path::PathBuf>,
+
+    /// Print `text` for people or `json` for scripts; with `json` the run's summary is
+    /// printed to stdout, and logging moves to stderr.
+    #[arg(long, env = "OUTPUT_FORMAT", default_value_t = output::OutputFormat::Text)]
+    output: output::OutputFormat,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    let opt = Opt::parse();
+    let _guard = opt.output.setup_logging(opt.log_file.as_deref())?;
+    fault::arm_from_env()?;
+
+    tracing::info!("📁 Scanning root: {}", opt.data_root.display());
+    let _lock = lock::RootLock::acquire(
+        &opt.lock_dir.unwrap_or_else(lock::default_lock_dir),
+        &opt.data_root,
+    )?;
+    let mut options = pipeline::ScanOptions::new(opt.data_root);
+    options.progress_interval = opt.progress_interval;
+    options.crawl = crawler::CrawlOptions {
+        extension_rules: extension::ExtensionRules {
+            lowercase: !opt.keep_extension_case,
+            multi_part: opt.multi_part_extensions,
+            unknown: opt.unknown_extension,
+        },
+        path_cipher: opt
+            .path_encryption_key_file
+            .as_deref()
+            .map(path_cipher::PathCipher::from_key_file)
+            .transpose()?,
+        security_labels: opt.security_labels,
+        content_hash: opt.content_hash,
+        hash_policies: opt.hash_policies.clone(),
+        small_file_bytes: opt.hash_small_file_bytes,
+        hash_cache: opt
+            .hash_cache
+            .as_deref()
+            .map(hash_cache::HashCache::open)
+            .transpose()?,
+        fs_tuning: opt
+            .fs_tuning
+            .as_deref()
+            .map(fs_type::TuningTable::from_file)
+            .transpose()?
+            .unwrap_or_default(),
+        adaptive_threads: opt.adaptive_threads.then_some(thread_tuner::START_THREADS),
+        prewarm: opt.prewarm,
+        include: opt.include,
+        exclude: opt.exclude,
+        walker: opt.walker,
+        sample_fraction: opt.sample_fraction,
+        follow_symlinks: opt.follow_symlinks,
+        record_symlinks: opt.record_symlinks,
+        ..crawler::CrawlOptions::default()
+    };
+
+    let summary = local_state::run_local_scan(
+        &options,
+        &opt.state_dir,
+        &progress::ProgressReporter::default(),
+    )
+    .await?;
+    tracing::info!(
+        "✅ Run {} of {}: {} added, {} modified, {} deleted",
+        summary.state.run,
+        summary.state.scan_root.display(),
+        summary.counts.added,
+        summary.counts.modified,
+        summary.counts.deleted
+    );
+    if opt.output.is_json() {
+        output::print_json(&summary)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use clap::Parser;
+
+use fs_delta_tracker::{logging, shard};
+
+/// Command-line tool to plan a sharded scan: estimates the size of each top-level directory of
+/// a root from its first levels and deals them into balanced shards, for `shard_scan start
+/// --plan`.
+#[derive(clap::Parser, Debug)]
+#[command(author, version, about)]
+struct Opt {
+    /// Path to log file (default: logs/app.log).
+    #[arg(long, env = "LOG_FILE")]
+    log_file: Option<std::path::PathBuf>,
+
+    /// The directory to plan the scan of
+    #[arg(short, long, env = "DATA_ROOT", visible_alias = "root")]
+    data_root: std::path::PathBuf,
+
+    /// Number of shards to plan.
+    #[arg(long, env = "SHARDS")]
+    shards: usize,
+
+    /// Directory levels read below each top-level directory to estimate its size; deeper
+    /// levels are extrapolated from the last one.
+    #[arg(
+        long,
+        env = "SHARD_SAMPLE_DEPTH",
+        default_value_t = 2,
+        value_parser = clap::value_parser!(u8).range(1..=2)
+    )]
+    sample_depth: u8,
+
+    /// Output JSON file. If not provided, the plan is printed to stdout.
+    #[arg(long)]
+    out: Option<std::path::PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    let opt = Opt::parse();
+
+    let plan = shard::ShardPlan::sample(&opt.data_root, opt.shards, opt.sample_depth as usize)?;
+    let json = serde_json::to_string_pretty(&plan)?;
+    match &opt.out {
+        Some(out) => {
+            let _guard = logging::setup_logging(opt.log_file.as_deref())?;
+            if let Some(p) = out.parent() {
+                std::fs::create_dir_all(p)?;
+            }
+            std::fs::write(out, format!("{}\n", json))?;
+            for (index, shard) in plan.shards.iter().enumerate() {
+                tracing::info!(
+                    "🧩 Shard {}: {} directories, ~{} entries",
+                    index,
+                    shard.paths.len(),
+                    shard.estimated_entries
+                );
+            }
+            tracing::info!("🧩 Shard plan written to {}", out.display());
+        }
+        None => println!("{}", json),
+    }
+
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use anyhow::Ok;
 use clap::Parser;
 
-use fs_delta_tracker::{logging, crawler};
+use fs_delta_tracker::{logging, crawler, pause, progress};
 
 /// A Tokio-based, multi-threaded filesystem crawler/scanner.
 #[derive(clap::Parser, Debug)]
@@ -53,7 +53,15 @@ async fn main() -> anyhow::Result<()> {
 
     // Walk the directory and process files
     tracing::info!("🔍 Starting directory walk...");
-    crawler::walk_directory(opt.data_root, opt.progress_interval, opt.scan_id, opt.output_tsv_file)
+    crawler::walk_directory(
+        vec![opt.data_root],
+        opt.progress_interval,
+        opt.scan_id,
+        opt.output_tsv_file,
+        progress::ProgressReporter::default(),
+        &crawler::CrawlOptions::default(),
+        pause::PauseSwitch::default(),
+    )
         .await
         .map_err(|e| {
             tracing::error!("Failed to walk directory: {}", e);
@@ -1,6 +1,6 @@
 use clap::Parser;
 use anyhow::Ok;
-use fs_delta_tracker::{logging, data, db};
+use fs_delta_tracker::{logging, data, db, integrity};
 
 #[derive(clap::Parser, Debug)]
 #[command(author, version, about)]
@@ -60,9 +60,19 @@ async fn main() -> anyhow::Result<()> {
     tokio::spawn(connection);
     tracing::info!("🔗 Connected to database");
 
+    // Refuse a TSV truncated or corrupted on its way from the crawl host
+    integrity::verify_checksum_sidecar(&opt.output_tsv_file)?;
+
     // Load the TSV file into the staging table
     tracing::info!("📥 Loading TSV file -> staging: {}", opt.output_tsv_file.display());
-    data::load_tsv_file(&client, opt.output_tsv_file).await?;
+    data::load_tsv_file(
+        &client,
+        opt.output_tsv_file,
+        "filesystem.staging_files",
+        &data::LoadPacing::default(),
+        &fs_delta_tracker::progress::ProgressReporter::default(),
+    )
+    .await?;
     tracing::info!("📥 TSV file loaded into staging table");
 
     // Execute the SQL template file
@@ -70,6 +80,10 @@ async fn main() -> anyhow::Result<()> {
     // Construct a HashMap for parameters
     let mut params = std::collections::HashMap::new();
     params.insert("scan_id".to_string(), opt.scan_id.to_string());
+    params.insert(
+        "staging_table".to_string(),
+        "filesystem.staging_files".to_string(),
+    );
 
     tracing::info!("📄 Executing SQL file: {}", opt.sql_file.display());
     db::execute_sql_template(&client, opt.sql_file, Some(params)).await?;
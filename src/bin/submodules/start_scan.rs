@@ -49,7 +49,7 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("🔗 Connected to database");
 
     let started_at = chrono::Utc::now();
-    let scan_id = data::start_scan(&client, &opt.data_root, started_at).await?;
+    let scan_id = data::start_scan(&client, &opt.data_root, &[], None, None, started_at).await?;
     tracing::info!("Starting scan with ID: {}", scan_id);
 
     Ok(())
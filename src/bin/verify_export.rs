@@ -0,0 +1,68 @@
+use clap::Parser;
+
+use fs_delta_tracker::signing::SignatureMethod;
+use fs_delta_tracker::{logging, signing};
+
+/// Command-line tool to authenticate an export against its detached signature.
+#[derive(clap::Parser, Debug)]
+#[command(author, version, about)]
+struct Opt {
+    /// Path to log file (default: logs/app.log).
+    #[arg(long, env = "LOG_FILE")]
+    log_file: Option<std::path::PathBuf>,
+
+    /// The exported file.
+    #[arg(long)]
+    file: std::path::PathBuf,
+
+    /// Detached signature (default: the export path plus `.asc` or `.minisig`,
+    /// whichever exists).
+    #[arg(long)]
+    signature: Option<std::path::PathBuf>,
+
+    /// Signature method (default: inferred from the signature's extension).
+    #[arg(long, value_parser = ["gpg", "minisign"])]
+    method: Option<String>,
+
+    /// minisign public key path (GPG uses the caller's keyring).
+    #[arg(long, env = "EXPORT_PUBLIC_KEY")]
+    public_key: Option<std::path::PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    let opt = Opt::parse();
+    let _guard = logging::setup_logging(opt.log_file.as_deref())?;
+
+    let method: Option<SignatureMethod> = opt.method.as_deref().map(str::parse).transpose()?;
+    let signature = match opt.signature {
+        Some(path) => path,
+        None => [SignatureMethod::Gpg, SignatureMethod::Minisign]
+            .into_iter()
+            .filter(|m| method.is_none_or(|method| method == *m))
+            .map(|m| signing::signature_path(&opt.file, m))
+            .find(|p| p.exists())
+            .ok_or_else(|| anyhow::anyhow!("No signature found next to {}", opt.file.display()))?,
+    };
+    let method = match method {
+        Some(method) => method,
+        None => SignatureMethod::from_signature_path(&signature).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Cannot infer the signature method of {}, pass --method",
+                signature.display()
+            )
+        })?,
+    };
+
+    tracing::info!(
+        "🔍 Verifying {} against {} ({})",
+        opt.file.display(),
+        signature.display(),
+        method
+    );
+    signing::verify(&opt.file, &signature, method, opt.public_key.as_deref()).await?;
+    tracing::info!("✅ Signature is valid");
+
+    Ok(())
+}
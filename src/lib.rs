@@ -1,10 +1,122 @@
 pub mod lib {
+    pub mod backup_check;
+    pub mod bench;
+    pub mod bundle;
+    pub mod cadence;
+    pub mod catalog;
+    pub mod cleanup;
+    pub mod confirm;
+    pub mod content_hash;
     pub mod crawler;
+    pub mod cursor;
     pub mod data;
     pub mod db;
+    pub mod dir_stats;
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub mod dirfd_walk;
+    pub mod embedded_db;
+    pub mod export;
+    pub mod extension;
+    pub mod fanotify;
+    pub mod fault;
+    pub mod fd_limit;
+    pub mod fs_type;
+    pub mod fsevents;
+    pub mod hash_cache;
+    pub mod integrity;
+    pub mod integrity_policy;
+    pub mod inventory;
+    pub mod journal;
+    pub mod local_state;
+    pub mod lock;
     pub mod logging;
+    pub mod outcome;
+    pub mod output;
+    pub mod path_cipher;
+    pub mod pause;
+    pub mod pipeline;
+    pub mod prewarm;
+    pub mod process;
+    pub mod profiler;
+    pub mod progress;
+    pub mod purge;
+    pub mod quick_scan;
+    pub mod reload;
+    pub mod remote;
+    pub mod resource_usage;
+    pub mod sample;
+    pub mod security_label;
+    pub mod shard;
+    pub mod signing;
+    pub mod snapshot_diff;
+    pub mod sqlite_store;
+    pub mod staging;
+    pub mod subscription;
+    pub mod systemd;
+    pub mod thread_tuner;
+    pub mod tiering;
+    pub mod treemap;
+    pub mod tsv;
+    pub mod usn_journal;
+    pub mod watch;
 }
+pub use lib::backup_check;
+pub use lib::bench;
+pub use lib::bundle;
+pub use lib::cadence;
+pub use lib::catalog;
+pub use lib::cleanup;
+pub use lib::confirm;
+pub use lib::content_hash;
 pub use lib::crawler;
+pub use lib::cursor;
 pub use lib::data;
 pub use lib::db;
+pub use lib::dir_stats;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub use lib::dirfd_walk;
+pub use lib::embedded_db;
+pub use lib::export;
+pub use lib::extension;
+pub use lib::fanotify;
+pub use lib::fault;
+pub use lib::fd_limit;
+pub use lib::fs_type;
+pub use lib::fsevents;
+pub use lib::hash_cache;
+pub use lib::integrity;
+pub use lib::integrity_policy;
+pub use lib::inventory;
+pub use lib::journal;
+pub use lib::local_state;
+pub use lib::lock;
 pub use lib::logging;
+pub use lib::outcome;
+pub use lib::output;
+pub use lib::path_cipher;
+pub use lib::pause;
+pub use lib::pipeline;
+pub use lib::prewarm;
+pub use lib::process;
+pub use lib::profiler;
+pub use lib::progress;
+pub use lib::purge;
+pub use lib::quick_scan;
+pub use lib::reload;
+pub use lib::remote;
+pub use lib::resource_usage;
+pub use lib::sample;
+pub use lib::security_label;
+pub use lib::shard;
+pub use lib::signing;
+pub use lib::snapshot_diff;
+pub use lib::sqlite_store;
+pub use lib::staging;
+pub use lib::subscription;
+pub use lib::systemd;
+pub use lib::thread_tuner;
+pub use lib::tiering;
+pub use lib::treemap;
+pub use lib::tsv;
+pub use lib::usn_journal;
+pub use lib::watch;
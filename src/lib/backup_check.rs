@@ -0,0 +1,239 @@
+use futures::StreamExt;
+use tokio::io::AsyncWriteExt;
+
+/// Backup tool whose snapshot listing is checked against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupTool {
+    /// `restic ls --json <snapshot>`
+    Restic,
+    /// `borg list --json-lines <repo>::<archive>`
+    Borg,
+}
+
+impl std::str::FromStr for BackupTool {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "restic" => Ok(BackupTool::Restic),
+            "borg" => Ok(BackupTool::Borg),
+            other => anyhow::bail!("Unknown backup tool: {}", other),
+        }
+    }
+}
+
+impl std::fmt::Display for BackupTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackupTool::Restic => write!(f, "restic"),
+            BackupTool::Borg => write!(f, "borg"),
+        }
+    }
+}
+
+/// The non-directory entries of a backup snapshot
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotListing {
+    /// Absolute path of each entry, with its size when listed
+    pub entries: std::collections::HashMap<String, Option<i64>>,
+    /// Snapshot ID, when the listing names it (restic)
+    pub id: Option<String>,
+    /// Time the snapshot was taken, when the listing names it (restic)
+    pub time: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl SnapshotListing {
+    /// Parse the JSON lines printed by `restic ls --json` or
+    /// `borg list --json-lines`. borg lists paths without their leading `/`,
+    /// which is put back.
+    pub fn parse(tool: BackupTool, reader: impl std::io::BufRead) -> anyhow::Result<Self> {
+        let mut listing = SnapshotListing::default();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let value: serde_json::Value = serde_json::from_str(&line).map_err(|e| {
+                anyhow::anyhow!("Line {} of the {} listing: {}", number + 1, tool, e)
+            })?;
+            let field = |name: &str| value.get(name).and_then(|v| v.as_str());
+
+            let path = match tool {
+                BackupTool::Restic => {
+                    // restic >= 0.17 names the record type `message_type`
+                    match field("struct_type").or_else(|| field("message_type")) {
+                        Some("snapshot") => {
+                            listing.id = field("id").map(str::to_string);
+                            listing.time = field("time")
+                                .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                                .map(|t| t.with_timezone(&chrono::Utc));
+                            continue;
+                        }
+                        Some("node") if field("type") != Some("dir") => field("path"),
+                        _ => continue,
+                    }
+                }
+                BackupTool::Borg => match field("type") {
+                    Some("d") | None => continue,
+                    Some(_) => field("path"),
+                },
+            };
+            let Some(path) = path else {
+                anyhow::bail!("Line {} of the {} listing has no path", number + 1, tool);
+            };
+            let path = if path.starts_with('/') {
+                path.to_string()
+            } else {
+                format!("/{}", path)
+            };
+            let size = value.get("size").and_then(|v| v.as_i64());
+            listing.entries.insert(path, size);
+        }
+        Ok(listing)
+    }
+}
+
+/// How [`check_coverage`] compares a scan with a snapshot
+#[derive(Debug, Clone, Default)]
+pub struct CoverageOptions {
+    /// Path in the snapshot holding the scanned root, when it was backed up
+    /// from elsewhere (a mount of a filesystem snapshot, say)
+    pub backup_root: Option<String>,
+    /// Time the snapshot was taken, overriding the listing's; files modified
+    /// after it are counted as new rather than missing
+    pub snapshot_time: Option<chrono::DateTime<chrono::Utc>>,
+    /// Key the scan's paths are encrypted with
+    pub path_cipher: Option<crate::path_cipher::PathCipher>,
+    /// Write every missing path here, one per line
+    pub out: Option<std::path::PathBuf>,
+    /// Terminate the paths written to `out` with NUL instead of a newline
+    pub null: bool,
+    /// Number of missing paths to keep in the report
+    pub sample: usize,
+}
+
+/// Result of [`check_coverage`]
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct CoverageReport {
+    pub scan_id: i32,
+    /// Entries of the snapshot under the scanned root
+    pub listed: u64,
+    /// Current files under the root
+    pub files: u64,
+    pub bytes: u64,
+    /// Files in the snapshot
+    pub covered: u64,
+    /// Files in the snapshot with a different size, i.e. changed since
+    pub size_differs: u64,
+    /// Files not in the snapshot, modified before it was taken
+    pub missing: u64,
+    pub missing_bytes: u64,
+    /// Files not in the snapshot, modified after it was taken
+    pub new_since_snapshot: u64,
+    /// The first missing paths, in path order
+    pub missing_sample: Vec<String>,
+}
+
+/// Compare the current files under the root of `scan_id` with the entries of
+/// a backup snapshot, reporting the files that are on disk but not backed up.
+///
+/// The snapshot's paths are mapped from `backup_root` (default: the scan's
+/// root) onto the scan's root; entries outside it are ignored.
+#[tracing::instrument(skip(client, listing, options))]
+pub async fn check_coverage(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    listing: &SnapshotListing,
+    options: &CoverageOptions,
+) -> anyhow::Result<CoverageReport> {
+    let (scan_root, path_key_id) = crate::data::get_scan_root(client, scan_id).await?;
+    let cipher = crate::path_cipher::cipher_for_scan(
+        scan_id,
+        path_key_id.as_deref(),
+        options.path_cipher.as_ref(),
+    )?;
+
+    let scan_root = scan_root.trim_end_matches('/').to_string();
+    let backup_root = options
+        .backup_root
+        .as_deref()
+        .unwrap_or(&scan_root)
+        .trim_end_matches('/')
+        .to_string();
+    let backup_prefix = format!("{}/", backup_root);
+    let entries: std::collections::HashMap<String, Option<i64>> = listing
+        .entries
+        .iter()
+        .filter_map(|(path, size)| {
+            let relative = path.strip_prefix(&backup_prefix)?;
+            Some((format!("{}/{}", scan_root, relative), *size))
+        })
+        .collect();
+    let snapshot_time = options.snapshot_time.or(listing.time);
+
+    let mut report = CoverageReport {
+        scan_id,
+        listed: entries.len() as u64,
+        ..Default::default()
+    };
+
+    let query = "
+        SELECT f.file_path, f.file_size_bytes, f.file_mtime
+        FROM filesystem.files AS f
+        JOIN filesystem.scan_runs AS r ON r.scan_id = $1
+        WHERE f.file_path LIKE r.scan_root || '/%'
+        ORDER BY f.file_path";
+    let rows = client.query_raw(query, [&scan_id]).await?;
+    futures::pin_mut!(rows);
+
+    let mut out = match &options.out {
+        Some(path) => Some(tokio::io::BufWriter::new(
+            tokio::fs::File::create(path).await?,
+        )),
+        None => None,
+    };
+    let terminator = if options.null { b'\0' } else { b'\n' };
+    while let Some(row) = rows.next().await {
+        let row = row?;
+        let path: String = row.get(0);
+        let size: i64 = row.get(1);
+        let mtime: chrono::DateTime<chrono::Utc> = row.get(2);
+        let path = match cipher {
+            Some(cipher) => cipher.decrypt_path(&path),
+            None => path,
+        };
+        report.files += 1;
+        report.bytes += size as u64;
+
+        match entries.get(&path) {
+            Some(listed_size) => {
+                report.covered += 1;
+                if listed_size.is_some_and(|listed| listed != size) {
+                    report.size_differs += 1;
+                }
+            }
+            None if snapshot_time.is_some_and(|time| mtime > time) => {
+                report.new_since_snapshot += 1;
+            }
+            None => {
+                report.missing += 1;
+                report.missing_bytes += size as u64;
+                // a newline-delimited list cannot hold such a path
+                if let Some(out) = out.as_mut()
+                    && (options.null || !path.contains('\n'))
+                {
+                    out.write_all(path.as_bytes()).await?;
+                    out.write_all(&[terminator]).await?;
+                }
+                if report.missing_sample.len() < options.sample {
+                    report.missing_sample.push(path);
+                }
+            }
+        }
+    }
+    if let Some(out) = out.as_mut() {
+        out.flush().await?;
+    }
+
+    Ok(report)
+}
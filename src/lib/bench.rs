@@ -0,0 +1,282 @@
+use std::io::Write;
+
+use crate::progress::ProgressReporter;
+use crate::staging::StagingStrategy;
+use crate::{data, pipeline};
+
+/// Root of the synthetic scans; never a real path, so processing only ever
+/// touches the synthetic rows
+const BENCH_ROOT: &str = "/__fs_delta_tracker_bench__";
+
+/// Reporting views timed by [`run_bench`]
+const REPORT_VIEWS: [&str; 4] = [
+    "filesystem.scan_summary",
+    "filesystem.daily_growth",
+    "filesystem.top_changed_dirs",
+    "filesystem.per_owner_usage",
+];
+
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// Synthetic files per scan
+    pub files: u64,
+    /// Files per synthetic directory
+    pub files_per_dir: u64,
+    /// Fraction of files changed between the two synthetic scans
+    pub change_ratio: f64,
+    /// Times each report query is run
+    pub report_iterations: usize,
+    /// Table the synthetic scans are staged in
+    pub staging: StagingStrategy,
+    /// Drop the staging table's secondary indexes for the COPY
+    pub defer_staging_indexes: bool,
+    pub copy_format: data::CopyFormat,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            files: 100_000,
+            files_per_dir: 1_000,
+            change_ratio: 0.01,
+            report_iterations: 5,
+            staging: StagingStrategy::default(),
+            defer_staging_indexes: false,
+            copy_format: data::CopyFormat::default(),
+        }
+    }
+}
+
+/// Latencies of one report query, in milliseconds
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct QueryLatency {
+    pub query: String,
+    pub rows: u64,
+    pub min_ms: f64,
+    pub median_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BenchReport {
+    pub files: u64,
+    pub staging: String,
+    pub copy_format: String,
+    pub copy_seconds: f64,
+    pub copy_rows_per_second: f64,
+    pub copy_mb_per_second: f64,
+    /// Processing of a first scan, where every file is new
+    pub initial_process_seconds: f64,
+    /// Processing of a rescan with `change_ratio` of the files changed
+    pub rescan_process_seconds: f64,
+    pub reports: Vec<QueryLatency>,
+}
+
+/// Measure COPY throughput, delta processing and report latencies.
+///
+/// Reports are timed against the existing data. COPY and processing run on
+/// synthetic scans of a fake root inside a transaction that is rolled back,
+/// so nothing is left behind apart from consumed `scan_id` sequence values.
+#[tracing::instrument(skip(client))]
+pub async fn run_bench(
+    client: &tokio_postgres::Client,
+    options: &BenchOptions,
+) -> anyhow::Result<BenchReport> {
+    let reports = time_reports(client, options.report_iterations).await?;
+
+    let tsv = std::env::temp_dir().join(format!("bench_{}.tsv", std::process::id()));
+    client.batch_execute("BEGIN").await?;
+    let result = bench_writes(client, options, &tsv).await;
+    let rollback = client.batch_execute("ROLLBACK").await;
+    std::fs::remove_file(&tsv).ok();
+    let (tsv_bytes, copy_seconds, initial_process_seconds, rescan_process_seconds) = result?;
+    rollback?;
+    tracing::info!("↩️ Rolled back synthetic scans");
+
+    Ok(BenchReport {
+        files: options.files,
+        staging: options.staging.to_string(),
+        copy_format: options.copy_format.to_string(),
+        copy_seconds,
+        copy_rows_per_second: options.files as f64 / copy_seconds,
+        copy_mb_per_second: tsv_bytes as f64 / 1024.0 / 1024.0 / copy_seconds,
+        initial_process_seconds,
+        rescan_process_seconds,
+        reports,
+    })
+}
+
+/// Time every existing reporting view, fetching all rows
+async fn time_reports(
+    client: &tokio_postgres::Client,
+    iterations: usize,
+) -> anyhow::Result<Vec<QueryLatency>> {
+    let mut latencies = Vec::new();
+    for view in REPORT_VIEWS {
+        let exists: bool = client
+            .query_one("SELECT to_regclass($1) IS NOT NULL", &[&view])
+            .await?
+            .get(0);
+        if !exists {
+            tracing::warn!("⚠️ {} does not exist, run create_views to time it", view);
+            continue;
+        }
+
+        let query = format!("SELECT * FROM {}", view);
+        let mut durations = Vec::with_capacity(iterations);
+        let mut rows = 0;
+        for _ in 0..iterations.max(1) {
+            let start = std::time::Instant::now();
+            rows = client.query(&query, &[]).await?.len() as u64;
+            durations.push(start.elapsed().as_secs_f64() * 1000.0);
+        }
+        durations.sort_by(f64::total_cmp);
+        let latency = QueryLatency {
+            query,
+            rows,
+            min_ms: durations[0],
+            median_ms: durations[durations.len() / 2],
+            max_ms: durations[durations.len() - 1],
+        };
+        tracing::info!(
+            "⏱️ {}: {} rows, median {:.1} ms",
+            view,
+            latency.rows,
+            latency.median_ms
+        );
+        latencies.push(latency);
+    }
+    Ok(latencies)
+}
+
+/// Write `options.files` synthetic crawler lines for `scan_id`, returning the
+/// file size
+fn write_synthetic_tsv(
+    path: &std::path::Path,
+    options: &BenchOptions,
+    scan_id: i32,
+) -> anyhow::Result<u64> {
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mtime = chrono::Utc::now().to_rfc3339();
+    for i in 0..options.files {
+        let dir = i / options.files_per_dir.max(1);
+        writeln!(
+            out,
+            "file_{i}.dat\tdat\t{BENCH_ROOT}/dir_{dir}/file_{i}.dat\t{}\t{mtime}\t\\N\t\\N\t\\N\t{scan_id}",
+            (i % 65_536) * 1024
+        )?;
+    }
+    out.flush()?;
+    Ok(std::fs::metadata(path)?.len())
+}
+
+/// Load and process a first synthetic scan, then process a rescan with some
+/// files changed. Returns the TSV size and the copy, initial processing and
+/// rescan processing durations in seconds. Must run inside a transaction.
+async fn bench_writes(
+    client: &tokio_postgres::Client,
+    options: &BenchOptions,
+    tsv: &std::path::Path,
+) -> anyhow::Result<(u64, f64, f64, f64)> {
+    options.staging.prepare(client).await?;
+    let first = start_bench_scan(client).await?;
+    let tsv_bytes = write_synthetic_tsv(tsv, options, first)?;
+
+    tracing::info!(
+        "📥 Copying {} synthetic rows into {} staging in {} format...",
+        options.files,
+        options.staging,
+        options.copy_format
+    );
+    let pacing = data::LoadPacing {
+        copy_format: options.copy_format,
+        ..Default::default()
+    };
+    let progress = ProgressReporter::default();
+    let start = std::time::Instant::now();
+    let load = data::load_tsv_file(
+        client,
+        tsv.to_path_buf(),
+        options.staging.table(),
+        &pacing,
+        &progress,
+    );
+    if options.defer_staging_indexes {
+        // The drop and rebuild are rolled back with the rest of the benchmark
+        data::with_deferred_indexes(client, options.staging.table(), load).await?;
+    } else {
+        load.await?;
+    }
+    let copy_seconds = start.elapsed().as_secs_f64();
+    tracing::info!(
+        "📥 COPY: {:.0} rows/s, {:.1} MB/s",
+        options.files as f64 / copy_seconds,
+        tsv_bytes as f64 / 1024.0 / 1024.0 / copy_seconds
+    );
+
+    let initial_process_seconds = time_processing(client, options.staging, first).await?;
+    tracing::info!(
+        "⚙️ Initial scan processed in {:.2}s",
+        initial_process_seconds
+    );
+
+    let second = start_bench_scan(client).await?;
+    // every n-th file (by path hash) changes size; a ratio of 0 changes none
+    let changed_every = (1.0 / options.change_ratio).clamp(1.0, i32::MAX as f64) as i32;
+    let query = format!(
+        "UPDATE {}
+         SET scan_id = $1,
+             file_size_bytes = file_size_bytes
+                 + CASE WHEN hashtext(file_path) % $3 = 0 THEN 1 ELSE 0 END
+         WHERE scan_id = $2",
+        options.staging.table()
+    );
+    client
+        .execute(&query, &[&second, &first, &changed_every])
+        .await?;
+    let rescan_process_seconds = time_processing(client, options.staging, second).await?;
+    tracing::info!("⚙️ Rescan processed in {:.2}s", rescan_process_seconds);
+
+    Ok((
+        tsv_bytes,
+        copy_seconds,
+        initial_process_seconds,
+        rescan_process_seconds,
+    ))
+}
+
+async fn start_bench_scan(client: &tokio_postgres::Client) -> anyhow::Result<i32> {
+    // inserted directly rather than via `data::start_scan`, which audits
+    let row = client
+        .query_one(
+            "INSERT INTO filesystem.scan_runs (scan_root) VALUES ($1) RETURNING scan_id",
+            &[&BENCH_ROOT],
+        )
+        .await?;
+    Ok(row.get(0))
+}
+
+/// Run the delta processing template for `scan_id`, returning its duration
+async fn time_processing(
+    client: &tokio_postgres::Client,
+    staging: StagingStrategy,
+    scan_id: i32,
+) -> anyhow::Result<f64> {
+    // The template commits its own transaction; strip that so it runs inside
+    // the benchmark's, which is rolled back
+    let sql = pipeline::sql_template("process_staging_v2.sql")
+        .replace("BEGIN;", "")
+        .replace("COMMIT;", "")
+        .replace(":scan_id", &scan_id.to_string())
+        .replace(":staging_table", staging.table())
+        .replace(":defer_deletes", "false");
+    // Up-to-date statistics, as autovacuum would have gathered for the
+    // previous scan of a real root; stale ones make the planner pick nested
+    // loops over the synthetic rows
+    client
+        .batch_execute(&format!("ANALYZE {}, filesystem.files", staging.table()))
+        .await?;
+    let start = std::time::Instant::now();
+    client.batch_execute(&sql).await?;
+    Ok(start.elapsed().as_secs_f64())
+}
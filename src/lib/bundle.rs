@@ -0,0 +1,385 @@
+use chrono::SubsecRound;
+use sha2::Digest;
+use std::io::{BufRead, Write};
+
+use crate::pipeline::ScanOptions;
+use crate::progress::ProgressReporter;
+use crate::signing::SignatureMethod;
+use crate::{crawler, data, pause, pipeline, signing};
+
+/// Crawl TSV inside a bundle
+const FILES_ENTRY: &str = "files.tsv";
+/// Manifest inside a bundle; the detached signature, if any, covers it
+const MANIFEST_ENTRY: &str = "manifest.json";
+/// 6: crawl TSV with mode, uid and gid fields; 5: with a symlink target
+/// field; 4: with a content fingerprint field; 3: with a security label
+/// field; 2: crawl TSV fields escaped for COPY's text format; 1: raw fields
+const FORMAT_VERSION: u32 = 6;
+/// scan_id written into the crawl TSV of a bundle, replaced on ingest
+const PLACEHOLDER_SCAN_ID: i32 = 0;
+
+/// Describes the crawl packed into a bundle
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BundleManifest {
+    pub format_version: u32,
+    pub scan_root: std::path::PathBuf,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: chrono::DateTime<chrono::Utc>,
+    pub hostname: String,
+    /// SHA-256 of the crawl TSV, pinning it to the (signed) manifest
+    pub files_sha256: String,
+    pub metadata: std::collections::HashMap<String, String>,
+    pub hot_dirs: Vec<crawler::HotDir>,
+    /// Entries the crawl failed to read
+    #[serde(default)]
+    pub errors: Vec<crawler::ScanError>,
+    /// Fingerprint of the key the crawl's paths are encrypted with, if any
+    #[serde(default)]
+    pub path_key_id: Option<String>,
+}
+
+/// Scratch directory removed when dropped
+struct WorkDir(std::path::PathBuf);
+
+impl WorkDir {
+    fn new(prefix: &str) -> anyhow::Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "{}_{}_{}",
+            prefix,
+            std::process::id(),
+            chrono::Utc::now().timestamp_micros()
+        ));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self(path))
+    }
+
+    fn join(&self, name: &str) -> std::path::PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.0) {
+            tracing::warn!("⚠️ Failed to remove {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+/// Crawl `options.data_root` without a database and pack the crawl, its
+/// manifest and an optional detached signature of the manifest into a
+/// `.tar.zst` bundle at `out`
+#[tracing::instrument(skip(options, progress))]
+pub async fn create_bundle(
+    options: &ScanOptions,
+    out: &std::path::Path,
+    sign: Option<SignatureMethod>,
+    signing_key: Option<&str>,
+    progress: &ProgressReporter,
+) -> anyhow::Result<BundleManifest> {
+    let work_dir = WorkDir::new("bundle")?;
+    let files_tsv = work_dir.join(FILES_ENTRY);
+
+    let started_at = chrono::Utc::now();
+    tracing::info!("🔍 Starting directory walk...");
+    let report = crawler::walk_directory(
+        vec![options.data_root.clone()],
+        options.progress_interval,
+        PLACEHOLDER_SCAN_ID,
+        files_tsv.clone(),
+        progress.clone(),
+        &options.crawl,
+        pause::PauseSwitch::default(),
+    )
+    .await?;
+    let finished_at = chrono::Utc::now();
+
+    let hostname = hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "unknown".to_string());
+    let mut metadata = report.metadata;
+    metadata.insert("hostname".to_string(), hostname.clone());
+
+    let manifest = BundleManifest {
+        format_version: FORMAT_VERSION,
+        scan_root: options.data_root.clone(),
+        started_at,
+        finished_at,
+        hostname,
+        files_sha256: crate::integrity::sha256_file(&files_tsv)?,
+        metadata,
+        hot_dirs: report.hot_dirs,
+        errors: report.errors,
+        path_key_id: options
+            .crawl
+            .path_cipher
+            .as_ref()
+            .map(|c| c.key_id().to_string()),
+    };
+    let manifest_path = work_dir.join(MANIFEST_ENTRY);
+    std::fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)?;
+
+    let mut entries = vec![manifest_path.clone(), files_tsv];
+    if let Some(method) = sign {
+        let signature = signing::sign(&manifest_path, method, signing_key).await?;
+        tracing::info!("🔏 Manifest signed with {}", method);
+        entries.push(signature);
+    }
+
+    let out = out.to_path_buf();
+    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+        let encoder = zstd::Encoder::new(std::fs::File::create(&out)?, 0)?;
+        let mut archive = tar::Builder::new(encoder);
+        for entry in &entries {
+            let name = entry.file_name().expect("bundle entries are files");
+            archive.append_path_with_name(entry, name)?;
+        }
+        archive.into_inner()?.finish()?.flush()?;
+        Ok(())
+    })
+    .await??;
+
+    Ok(manifest)
+}
+
+/// Upload a bundle written by [`create_bundle`] below `prefix`, along with
+/// its manifest (`<bundle>.manifest.json`) and checksum (`<bundle>.sha256`),
+/// both also written next to the bundle. The checksum goes last, so its
+/// presence marks a complete upload. Returns the bundle's uploaded location.
+#[tracing::instrument(skip(manifest, prefix), fields(prefix = %prefix))]
+pub async fn upload_bundle(
+    bundle: &std::path::Path,
+    manifest: &BundleManifest,
+    prefix: &crate::remote::Location,
+) -> anyhow::Result<crate::remote::Location> {
+    let name = bundle
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid bundle path {}", bundle.display()))?
+        .to_string_lossy()
+        .to_string();
+    let sidecar = |suffix: &str| {
+        let mut path = bundle.as_os_str().to_owned();
+        path.push(suffix);
+        std::path::PathBuf::from(path)
+    };
+
+    let manifest_path = sidecar(".manifest.json");
+    std::fs::write(&manifest_path, serde_json::to_vec_pretty(manifest)?)?;
+    let bundle_path = bundle.to_path_buf();
+    let sha256 =
+        tokio::task::spawn_blocking(move || crate::integrity::sha256_file(&bundle_path)).await??;
+    crate::integrity::write_checksum_sidecar(bundle, &sha256)?;
+
+    let target = prefix.join(&name);
+    target.upload(bundle).await?;
+    prefix
+        .join(&format!("{}.manifest.json", name))
+        .upload(&manifest_path)
+        .await?;
+    prefix
+        .join(&format!("{}.sha256", name))
+        .upload(&crate::integrity::checksum_sidecar(bundle))
+        .await?;
+    Ok(target)
+}
+
+/// Unpack a bundle, check its signature and checksum, and record its crawl
+/// as a new scan of its root, returning the scan_id.
+///
+/// Scan settings other than the crawl ones are taken from `options`; its
+/// `data_root` is replaced by the bundle's root.
+#[tracing::instrument(skip(client, bundle, options, progress), fields(bundle = %bundle))]
+pub async fn ingest_bundle(
+    client: &tokio_postgres::Client,
+    bundle: &crate::remote::Location,
+    options: &ScanOptions,
+    public_key: Option<&std::path::Path>,
+    require_signature: bool,
+    progress: &ProgressReporter,
+) -> anyhow::Result<i32> {
+    let work_dir = WorkDir::new("bundle_ingest")?;
+    let unpack_dir = work_dir.0.clone();
+    // remote bundles are unpacked as they are downloaded
+    let reader = tokio_util::io::SyncIoBridge::new(bundle.open().await?);
+    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+        let decoder = zstd::Decoder::new(reader)?;
+        tar::Archive::new(decoder).unpack(&unpack_dir)?;
+        Ok(())
+    })
+    .await??;
+
+    let manifest_path = work_dir.join(MANIFEST_ENTRY);
+    let manifest: BundleManifest = serde_json::from_slice(&std::fs::read(&manifest_path)?)
+        .map_err(|e| anyhow::anyhow!("Invalid bundle manifest: {}", e))?;
+    if !(1..=FORMAT_VERSION).contains(&manifest.format_version) {
+        anyhow::bail!(
+            "Unsupported bundle format version {}",
+            manifest.format_version
+        );
+    }
+
+    let signature = [SignatureMethod::Gpg, SignatureMethod::Minisign]
+        .into_iter()
+        .map(|m| (m, signing::signature_path(&manifest_path, m)))
+        .find(|(_, path)| path.exists());
+    let signed = signature.is_some();
+    match signature {
+        Some((method, path)) => {
+            signing::verify(&manifest_path, &path, method, public_key).await?;
+            tracing::info!("✅ Bundle signature is valid ({})", method);
+        }
+        None if require_signature => anyhow::bail!("Bundle {} is not signed", bundle),
+        None => tracing::warn!("⚠️ Bundle {} is not signed", bundle),
+    }
+
+    let files_tsv = work_dir.join(FILES_ENTRY);
+    let files_sha256 = crate::integrity::sha256_file(&files_tsv)?;
+    if files_sha256 != manifest.files_sha256 {
+        anyhow::bail!(
+            "Bundle checksum mismatch: manifest has {}, {} hashes to {}",
+            manifest.files_sha256,
+            FILES_ENTRY,
+            files_sha256
+        );
+    }
+
+    // Deltas are computed against the current state of the root, so an older
+    // crawl would undo changes recorded since. Also rejects re-ingesting a bundle
+    // (PostgreSQL keeps microseconds only).
+    if let Some(latest) = data::get_latest_completed_scan_start(client, &manifest.scan_root).await?
+        && latest >= manifest.started_at.trunc_subsecs(6)
+    {
+        anyhow::bail!(
+            "Bundle crawl of {} started at {}, but a scan started at {} is already recorded",
+            manifest.scan_root.display(),
+            manifest.started_at,
+            latest
+        );
+    }
+
+    tracing::info!(
+        "📦 Ingesting crawl of {} from {} ({} files)",
+        manifest.scan_root.display(),
+        manifest.hostname,
+        manifest
+            .metadata
+            .get("total_files_processed")
+            .map(String::as_str)
+            .unwrap_or("?")
+    );
+    crate::pipeline::check_root_tenant(client, &manifest.scan_root, options.tenant.as_deref())
+        .await?;
+    crate::pipeline::check_root_path_key(
+        client,
+        &manifest.scan_root,
+        manifest.path_key_id.as_deref(),
+    )
+    .await?;
+    let scan_id = data::start_scan(
+        client,
+        &manifest.scan_root,
+        &[],
+        options.tenant.as_deref(),
+        manifest.path_key_id.as_deref(),
+        manifest.started_at,
+    )
+    .await?;
+    tracing::info!("🔍 Scan ID: {}", scan_id);
+
+    let output_tsv_file = std::env::temp_dir().join(format!("scan_{}.tsv", scan_id));
+    rewrite_scan_id(
+        &files_tsv,
+        &output_tsv_file,
+        scan_id,
+        manifest.format_version,
+    )?;
+
+    if !manifest.hot_dirs.is_empty() {
+        data::record_hot_dirs(client, scan_id, &manifest.hot_dirs).await?;
+    }
+    data::record_scan_errors(client, scan_id, &manifest.errors).await?;
+    let mut metadata = manifest.metadata;
+    metadata.insert("bundle".to_string(), bundle.to_string());
+    metadata.insert("bundle_sha256".to_string(), files_sha256);
+    data::audit(
+        client,
+        "bundle_ingested",
+        Some(scan_id),
+        serde_json::json!({
+            "bundle": bundle.to_string(),
+            "crawl_hostname": manifest.hostname,
+            "signed": signed,
+        }),
+    )
+    .await?;
+
+    let mut options = options.clone();
+    options.data_root = manifest.scan_root;
+    pipeline::check_min_expected_files(
+        client,
+        &options,
+        scan_id,
+        Some(&output_tsv_file),
+        &metadata,
+    )
+    .await?;
+    pipeline::process_crawl(
+        client,
+        &options,
+        scan_id,
+        &output_tsv_file,
+        metadata,
+        progress,
+    )
+    .await?;
+    pipeline::remove_tsv_file(&output_tsv_file);
+
+    Ok(scan_id)
+}
+
+/// Copy a bundle's crawl TSV, replacing the placeholder scan_id of each line.
+/// The lines of an older bundle are brought to the crawler's current format:
+/// fields of format 1 are escaped (a raw field can only hold a backslash to
+/// escape), a NULL security label is added before format 3, a NULL
+/// fingerprint before format 4, a NULL symlink target before format 5 and
+/// NULL ownership before format 6. The copy gets its own checksum sidecar,
+/// as if the crawler had written it.
+fn rewrite_scan_id(
+    input: &std::path::Path,
+    output: &std::path::Path,
+    scan_id: i32,
+    format_version: u32,
+) -> anyhow::Result<()> {
+    let reader = std::io::BufReader::new(std::fs::File::open(input)?);
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(output)?);
+    let mut hasher = sha2::Sha256::new();
+    for line in reader.lines() {
+        let line = line?;
+        let (fields, _) = line
+            .rsplit_once('\t')
+            .ok_or_else(|| anyhow::anyhow!("Malformed line in {}: {}", FILES_ENTRY, line))?;
+        let fields = match format_version {
+            1 => std::borrow::Cow::Owned(fields.replace('\\', "\\\\")),
+            _ => std::borrow::Cow::Borrowed(fields),
+        };
+        // crawls before format 3 recorded no security labels, before format 4
+        // no fingerprints, before format 5 no symlink targets, before format 6
+        // no ownership
+        let missing = match format_version {
+            ..=2 => 6,
+            3 => 5,
+            4 => 4,
+            5 => 3,
+            _ => 0,
+        };
+        let line = format!("{}{}\t{}\n", fields, "\t\\N".repeat(missing), scan_id);
+        writer.write_all(line.as_bytes())?;
+        hasher.update(line.as_bytes());
+    }
+    writer.flush()?;
+    crate::integrity::write_checksum_sidecar(
+        output,
+        &crate::integrity::to_hex(&hasher.finalize()),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,149 @@
+/// What a run of `fs_delta_tracker` records, from the cheapest to the most
+/// complete
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ScanKind {
+    /// Per-directory counts and sizes, see [`crate::quick_scan`]
+    Quick,
+    /// Every file and its deltas
+    Full,
+}
+
+impl std::str::FromStr for ScanKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "quick" => Ok(ScanKind::Quick),
+            "full" => Ok(ScanKind::Full),
+            other => anyhow::bail!("Unknown scan kind: {}", other),
+        }
+    }
+}
+
+impl std::fmt::Display for ScanKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanKind::Quick => write!(f, "quick"),
+            ScanKind::Full => write!(f, "full"),
+        }
+    }
+}
+
+/// How often each kind of scan of a root is due, e.g. `quick=1d,full=7d`:
+/// every run picks the most complete kind that is due, so a daily timer
+/// interleaves quick scans with a weekly full scan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cadence {
+    /// Most complete kind first
+    intervals: Vec<(ScanKind, chrono::Duration)>,
+}
+
+/// Parse an interval such as `12h`, `1d` or `2w`
+fn parse_interval(s: &str) -> anyhow::Result<chrono::Duration> {
+    let s = s.trim();
+    let (count, unit) = s.split_at(s.len().saturating_sub(1));
+    let count: i64 = count
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid interval '{}': {}", s, e))?;
+    anyhow::ensure!(count > 0, "Interval '{}' must be positive", s);
+    match unit {
+        "h" => Ok(chrono::Duration::hours(count)),
+        "d" => Ok(chrono::Duration::days(count)),
+        "w" => Ok(chrono::Duration::weeks(count)),
+        _ => anyhow::bail!("Expected an interval in h, d or w, got '{}'", s),
+    }
+}
+
+impl std::str::FromStr for Cadence {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut intervals = Vec::new();
+        for entry in s.split(',') {
+            let (kind, interval) = entry.split_once('=').ok_or_else(|| {
+                anyhow::anyhow!("Expected KIND=INTERVAL in cadence, got {}", entry)
+            })?;
+            let kind: ScanKind = kind.trim().parse()?;
+            anyhow::ensure!(
+                intervals.iter().all(|&(k, _)| k != kind),
+                "Scan kind {} is listed twice in cadence {}",
+                kind,
+                s
+            );
+            intervals.push((kind, parse_interval(interval)?));
+        }
+        intervals.sort_by_key(|&(kind, _)| std::cmp::Reverse(kind));
+        Ok(Cadence { intervals })
+    }
+}
+
+impl std::fmt::Display for Cadence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let entries: Vec<String> = self
+            .intervals
+            .iter()
+            .map(|(kind, interval)| match interval.num_hours() {
+                hours if hours % (24 * 7) == 0 => format!("{}={}w", kind, hours / (24 * 7)),
+                hours if hours % 24 == 0 => format!("{}={}d", kind, hours / 24),
+                hours => format!("{}={}h", kind, hours),
+            })
+            .collect();
+        write!(f, "{}", entries.join(","))
+    }
+}
+
+/// Start of the last successful scan of `kind` of `data_root`
+async fn last_run(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+    kind: ScanKind,
+) -> anyhow::Result<Option<chrono::DateTime<chrono::Utc>>> {
+    let query = match kind {
+        ScanKind::Quick => {
+            "SELECT max(started_at) FROM filesystem.quick_scans \
+            WHERE scan_root = $1 AND finished_at IS NOT NULL"
+        }
+        ScanKind::Full => {
+            "SELECT max(started_at) FROM filesystem.scan_runs \
+            WHERE scan_root = $1 AND scan_status = 'completed'"
+        }
+    };
+    let row = client
+        .query_one(query, &[&data_root.to_string_lossy()])
+        .await?;
+    Ok(row.get(0))
+}
+
+impl Cadence {
+    /// The most complete kind of scan of `data_root` that is due at `now`, or
+    /// `None` if all ran recently enough. A kind is also satisfied by a more
+    /// complete scan (a full scan counts as a quick one), and may run up to an
+    /// hour early so that a timer firing at the same time every day does not
+    /// miss it by the previous run's startup delay.
+    pub async fn due(
+        &self,
+        client: &tokio_postgres::Client,
+        data_root: &std::path::Path,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<Option<ScanKind>> {
+        let mut last_complete: Option<chrono::DateTime<chrono::Utc>> = None;
+        for &(kind, interval) in &self.intervals {
+            let last = last_run(client, data_root, kind).await?;
+            // the most complete kinds come first, so this covers all of them
+            last_complete = last_complete.max(last);
+            let slack = chrono::Duration::hours(1).min(interval / 2);
+            match last_complete {
+                Some(last) if now - last < interval - slack => {
+                    tracing::debug!(
+                        "{} scan of {} not due, last one at {}",
+                        kind,
+                        data_root.display(),
+                        last
+                    )
+                }
+                _ => return Ok(Some(kind)),
+            }
+        }
+        Ok(None)
+    }
+}
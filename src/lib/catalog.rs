@@ -0,0 +1,311 @@
+use futures::StreamExt;
+
+/// A data catalog kept in sync with the files scans find
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Catalog {
+    /// A REST endpoint the changes are POSTed to as JSON, in batches, with
+    /// `CATALOG_TOKEN` from the environment as bearer token if set
+    Rest(String),
+    /// An iRODS collection the scan's root is registered below, through
+    /// the icommands of the zone the environment is logged in to
+    Irods { collection: String },
+}
+
+impl std::str::FromStr for Catalog {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(collection) = s.strip_prefix("irods:") {
+            anyhow::ensure!(
+                collection.starts_with('/'),
+                "Expected irods:/zone/collection, got {}",
+                s
+            );
+            return Ok(Catalog::Irods {
+                collection: collection.trim_end_matches('/').to_string(),
+            });
+        }
+        if s.starts_with("https://") || s.starts_with("http://") {
+            return Ok(Catalog::Rest(s.to_string()));
+        }
+        anyhow::bail!(
+            "Expected an https:// URL or irods:/zone/collection, got {}",
+            s
+        )
+    }
+}
+
+impl std::fmt::Display for Catalog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Catalog::Rest(url) => write!(f, "{}", url),
+            Catalog::Irods { collection } => write!(f, "irods:{}", collection),
+        }
+    }
+}
+
+/// How [`sync_scan`] pushes changes
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    /// Changes per request to a REST catalog
+    pub batch_size: usize,
+    /// Key the scan's paths are encrypted with
+    pub path_cipher: Option<crate::path_cipher::PathCipher>,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 1000,
+            path_cipher: None,
+        }
+    }
+}
+
+/// A change of a scan as a catalog sees it: the file's new state, or its
+/// last one for a removal
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CatalogChange {
+    /// `added`, `modified` or `deleted`
+    pub change_type: String,
+    pub path: String,
+    pub size_bytes: Option<i64>,
+    pub mtime: Option<chrono::DateTime<chrono::Utc>>,
+    /// `<algorithm>:<hex digest>`, when the scan hashed contents
+    pub fingerprint: Option<String>,
+}
+
+/// Changes of a scan pushed by [`sync_scan`]
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct SyncStats {
+    pub added: u64,
+    pub modified: u64,
+    pub removed: u64,
+    /// paths of extra roots, outside the scan's root an iRODS collection
+    /// mirrors
+    pub skipped: u64,
+}
+
+impl SyncStats {
+    fn count(&mut self, change: &CatalogChange) {
+        match change.change_type.as_str() {
+            "added" => self.added += 1,
+            "modified" => self.modified += 1,
+            _ => self.removed += 1,
+        }
+    }
+}
+
+/// Push the file changes of completed scan `scan_id` to `catalog` and record
+/// the sync, so that it is not pushed twice. Symlinks are left out.
+#[tracing::instrument(skip(client, options))]
+pub async fn sync_scan(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    catalog: &Catalog,
+    options: &SyncOptions,
+) -> anyhow::Result<SyncStats> {
+    let status = crate::data::get_scan_status(client, scan_id).await?;
+    anyhow::ensure!(
+        status == "completed",
+        "Scan {} is {}; only completed scans are synced",
+        scan_id,
+        status
+    );
+    let (scan_root, path_key_id) = crate::data::get_scan_root(client, scan_id).await?;
+    let cipher = crate::path_cipher::cipher_for_scan(
+        scan_id,
+        path_key_id.as_deref(),
+        options.path_cipher.as_ref(),
+    )?;
+
+    let query = "
+        SELECT change_type, file_path,
+               COALESCE(new_size_bytes, old_size_bytes),
+               COALESCE(new_mtime, old_mtime),
+               COALESCE(new_fingerprint, old_fingerprint)
+        FROM filesystem.file_changes
+        WHERE scan_id = $1
+          AND change_type IN ('added', 'modified', 'deleted')
+          AND COALESCE(new_symlink_target, old_symlink_target) IS NULL
+        ORDER BY file_path";
+    let rows = client.query_raw(query, [&scan_id]).await?;
+    futures::pin_mut!(rows);
+
+    let mut pusher = match catalog {
+        Catalog::Rest(url) => Pusher::Rest {
+            client: reqwest::Client::builder()
+                .connect_timeout(std::time::Duration::from_secs(30))
+                .build()?,
+            url,
+            token: std::env::var("CATALOG_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+        },
+        Catalog::Irods { collection } => Pusher::Irods {
+            collection,
+            collections: std::collections::HashSet::new(),
+        },
+    };
+    let mut stats = SyncStats::default();
+    let mut batch = Vec::with_capacity(options.batch_size);
+    while let Some(row) = rows.next().await {
+        let row = row?;
+        let path: String = row.get(1);
+        let change = CatalogChange {
+            change_type: row.get(0),
+            path: match cipher {
+                Some(cipher) => cipher.decrypt_path(&path),
+                None => path,
+            },
+            size_bytes: row.get(2),
+            mtime: row.get(3),
+            fingerprint: row.get(4),
+        };
+        batch.push(change);
+        if batch.len() >= options.batch_size.max(1) {
+            pusher.push(scan_id, &scan_root, &batch, &mut stats).await?;
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        pusher.push(scan_id, &scan_root, &batch, &mut stats).await?;
+    }
+
+    client
+        .execute(
+            "INSERT INTO filesystem.catalog_syncs
+                 (catalog, scan_id, added_count, modified_count, removed_count)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (catalog, scan_id) DO UPDATE
+             SET added_count = EXCLUDED.added_count,
+                 modified_count = EXCLUDED.modified_count,
+                 removed_count = EXCLUDED.removed_count,
+                 synced_at = now()",
+            &[
+                &catalog.to_string(),
+                &scan_id,
+                &(stats.added as i64),
+                &(stats.modified as i64),
+                &(stats.removed as i64),
+            ],
+        )
+        .await?;
+    Ok(stats)
+}
+
+/// Completed scans not yet synced to `catalog`, oldest first; only those of
+/// `scan_root` if given
+#[tracing::instrument(skip(client))]
+pub async fn unsynced_scans(
+    client: &tokio_postgres::Client,
+    catalog: &Catalog,
+    scan_root: Option<&str>,
+) -> anyhow::Result<Vec<i32>> {
+    let query = "
+        SELECT r.scan_id
+        FROM filesystem.scan_runs AS r
+        WHERE r.scan_status = 'completed'
+          AND ($2::text IS NULL OR r.scan_root = $2)
+          AND NOT EXISTS (
+              SELECT 1
+              FROM filesystem.catalog_syncs AS s
+              WHERE s.catalog = $1 AND s.scan_id = r.scan_id
+          )
+        ORDER BY r.scan_id";
+    let rows = client
+        .query(query, &[&catalog.to_string(), &scan_root])
+        .await?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+enum Pusher<'a> {
+    Rest {
+        client: reqwest::Client,
+        url: &'a str,
+        token: Option<String>,
+    },
+    Irods {
+        collection: &'a str,
+        /// collections created by this sync
+        collections: std::collections::HashSet<String>,
+    },
+}
+
+impl Pusher<'_> {
+    async fn push(
+        &mut self,
+        scan_id: i32,
+        scan_root: &str,
+        changes: &[CatalogChange],
+        stats: &mut SyncStats,
+    ) -> anyhow::Result<()> {
+        match self {
+            Pusher::Rest { client, url, token } => {
+                let body = serde_json::to_vec(&serde_json::json!({
+                    "scan_id": scan_id,
+                    "scan_root": scan_root,
+                    "host": crate::data::local_hostname(),
+                    "changes": changes,
+                }))?;
+                let mut request = client
+                    .post(*url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body);
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                let response = request.send().await?;
+                let status = response.status();
+                if !status.is_success() {
+                    let body = response.text().await.unwrap_or_default();
+                    anyhow::bail!(
+                        "Catalog {} refused changes of scan {} with {}: {}",
+                        url,
+                        scan_id,
+                        status,
+                        body.chars().take(300).collect::<String>()
+                    );
+                }
+                changes.iter().for_each(|change| stats.count(change));
+            }
+            Pusher::Irods {
+                collection,
+                collections,
+            } => {
+                let prefix = format!("{}/", scan_root.trim_end_matches('/'));
+                for change in changes {
+                    let Some(relative) = change.path.strip_prefix(&prefix) else {
+                        stats.skipped += 1;
+                        continue;
+                    };
+                    let object = format!("{}/{}", collection, relative);
+                    stats.count(change);
+                    if change.change_type != "added" {
+                        irods_command("iunreg", &[&object]).await?;
+                    }
+                    if change.change_type == "deleted" {
+                        continue;
+                    }
+                    if let Some((parent, _)) = object.rsplit_once('/')
+                        && collections.insert(parent.to_string())
+                    {
+                        irods_command("imkdir", &["-p", parent]).await?;
+                    }
+                    irods_command("ireg", &[&change.path, &object]).await?;
+                    if let Some(fingerprint) = &change.fingerprint {
+                        irods_command("imeta", &["set", "-d", &object, "fingerprint", fingerprint])
+                            .await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn irods_command(program: &str, args: &[&str]) -> anyhow::Result<()> {
+    let mut command = tokio::process::Command::new(program);
+    command.args(args);
+    crate::process::run_command(command).await
+}
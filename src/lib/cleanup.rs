@@ -0,0 +1,99 @@
+use crate::{data, lock};
+
+/// Leftovers of crashed runs found at startup
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct OrphanReport {
+    /// Scans of this host stuck in `running`, voided (or flagged, if their
+    /// deltas were already applied) when cleaning
+    pub stale_scans: Vec<i32>,
+    /// Scans whose staging rows were left behind, cleared when cleaning
+    pub staging_scans: Vec<i32>,
+}
+
+impl OrphanReport {
+    pub fn is_empty(&self) -> bool {
+        self.stale_scans.is_empty() && self.staging_scans.is_empty()
+    }
+}
+
+/// Find scans started on this host more than `stale_after` ago that are still
+/// `running`, and staging rows of scans that are no longer running. With
+/// `clean` they are marked and cleared, otherwise only reported.
+///
+/// Scans of roots currently locked on this host are left alone, except those
+/// of `own_root`, whose lock is held by the caller.
+#[tracing::instrument(skip(client))]
+pub async fn clean_orphans(
+    client: &tokio_postgres::Client,
+    own_root: &std::path::Path,
+    lock_dir: &std::path::Path,
+    stale_after: chrono::Duration,
+    clean: bool,
+) -> anyhow::Result<OrphanReport> {
+    let mut report = OrphanReport::default();
+    let hostname = data::local_hostname();
+    let started_before = chrono::Utc::now() - stale_after;
+
+    for (scan_id, scan_root, started_at) in
+        data::get_stale_running_scans(client, &hostname, started_before).await?
+    {
+        let scan_root = std::path::PathBuf::from(scan_root);
+        if scan_root != own_root && lock::is_locked(lock_dir, &scan_root)? {
+            tracing::info!(
+                "⏳ Scan {} of {} is still running on this host, leaving it",
+                scan_id,
+                scan_root.display()
+            );
+            continue;
+        }
+        report.stale_scans.push(scan_id);
+        if !clean {
+            tracing::warn!(
+                "🧟 Scan {} of {} has been running since {}; use --auto-clean to void it",
+                scan_id,
+                scan_root.display(),
+                started_at
+            );
+            continue;
+        }
+
+        data::clear_staging(client, scan_id).await?;
+        if data::has_file_changes(client, scan_id).await? {
+            // Its deltas are in filesystem.files already; leave the decision
+            // between finishing and rolling it back to an operator
+            let anomaly = serde_json::json!({
+                "orphaned": { "hostname": hostname, "started_at": started_at }
+            });
+            let metadata = data::get_scan_metadata(client, scan_id).await?;
+            data::flag_scan(client, scan_id, anomaly, metadata).await?;
+        } else {
+            tracing::info!("🧹 Voiding orphaned scan {}", scan_id);
+            data::void_scan(client, scan_id).await?;
+        }
+    }
+
+    for (scan_id, rows) in data::get_orphan_staging_rows(client).await? {
+        report.staging_scans.push(scan_id);
+        if clean {
+            tracing::info!("🧹 Clearing {} staging rows of scan {}", rows, scan_id);
+            data::clear_staging(client, scan_id).await?;
+        } else {
+            tracing::warn!(
+                "🧟 {} staging rows of finished scan {} are left behind; use --auto-clean to clear them",
+                rows,
+                scan_id
+            );
+        }
+    }
+
+    if clean && !report.is_empty() {
+        data::audit(
+            client,
+            "orphans_cleaned",
+            None,
+            serde_json::to_value(&report)?,
+        )
+        .await?;
+    }
+    Ok(report)
+}
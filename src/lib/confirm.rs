@@ -0,0 +1,29 @@
+use std::io::{BufRead, IsTerminal, Write};
+
+/// Ask on the terminal before a destructive `action`, e.g. "drop all tracked
+/// data", showing the rows it affects as `(count, what)` pairs; `yes` skips
+/// the question. Without a terminal on stdin the action is refused unless
+/// `yes` is set, so scripts have to opt in explicitly.
+pub fn confirm(action: &str, affected: &[(u64, &str)], yes: bool) -> anyhow::Result<()> {
+    if yes {
+        return Ok(());
+    }
+    anyhow::ensure!(
+        std::io::stdin().is_terminal(),
+        "Refusing to {} without confirmation; pass --yes to run non-interactively",
+        action
+    );
+
+    let mut stderr = std::io::stderr().lock();
+    writeln!(stderr, "This will {}:", action)?;
+    for (count, what) in affected {
+        writeln!(stderr, "  {:>12} {}", count, what)?;
+    }
+    write!(stderr, "Type 'yes' to continue: ")?;
+    stderr.flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    anyhow::ensure!(answer.trim() == "yes", "Aborted, nothing was changed");
+    Ok(())
+}
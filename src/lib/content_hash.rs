@@ -0,0 +1,194 @@
+use sha2::Digest;
+use std::io::Read;
+
+/// Checksum of file contents recorded as a file's fingerprint, written as
+/// `<algorithm>:<hex digest>` so fingerprints of different algorithms are
+/// never compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// XXH3, 64 bits: fast, not cryptographic
+    Xxh3,
+    Blake3,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    pub(crate) fn prefix(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Xxh3 => "xxh3",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    /// Fingerprint of the contents of the file at `path`
+    pub fn hash_file(&self, path: &std::path::Path) -> std::io::Result<String> {
+        self.hash(&mut std::fs::File::open(path)?)
+    }
+
+    /// Fingerprint of the contents of `file`, read from where it stands
+    pub fn hash(&self, file: &mut std::fs::File) -> std::io::Result<String> {
+        let mut buf = vec![0u8; 256 * 1024];
+        let digest = match self {
+            HashAlgorithm::Xxh3 => {
+                let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+                read_chunks(file, &mut buf, |chunk| hasher.update(chunk))?;
+                format!("{:016x}", hasher.digest())
+            }
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                read_chunks(file, &mut buf, |chunk| {
+                    hasher.update(chunk);
+                })?;
+                hasher.finalize().to_hex().to_string()
+            }
+            HashAlgorithm::Sha256 => {
+                let mut hasher = sha2::Sha256::new();
+                read_chunks(file, &mut buf, |chunk| hasher.update(chunk))?;
+                crate::integrity::to_hex(&hasher.finalize())
+            }
+        };
+        Ok(format!("{}:{}", self.prefix(), digest))
+    }
+}
+
+/// Files a crawl hashing contents reads: all of them, only small ones, or
+/// none, e.g. on a network filesystem where every read is a round trip.
+/// Ordered from the fewest reads to the most.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum HashPolicy {
+    /// Record metadata only; fingerprints stay NULL
+    MetadataOnly,
+    /// Hash small files only, of at most `--hash-small-file-bytes`
+    SmallFiles,
+    Full,
+}
+
+/// Largest file hashed under [`HashPolicy::SmallFiles`] by default
+pub const SMALL_FILE_BYTES: u64 = 1024 * 1024;
+
+impl HashPolicy {
+    /// Whether a file of `size` bytes is hashed, small files being those of at
+    /// most `small_file_bytes`
+    pub fn hashes(self, size: u64, small_file_bytes: u64) -> bool {
+        match self {
+            HashPolicy::MetadataOnly => false,
+            HashPolicy::SmallFiles => size <= small_file_bytes,
+            HashPolicy::Full => true,
+        }
+    }
+}
+
+/// A hashing policy given for the roots at or below `root` (`ROOT=POLICY`),
+/// or for every root (`POLICY`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootHashPolicy {
+    pub root: Option<std::path::PathBuf>,
+    pub policy: HashPolicy,
+}
+
+impl RootHashPolicy {
+    /// The policy `root`, on a filesystem tuned to `tuned`, is hashed with:
+    /// the one given for it or the closest root above it, otherwise the tuned
+    /// one lowered to the one given for every root
+    pub fn resolve(
+        policies: &[RootHashPolicy],
+        root: &std::path::Path,
+        tuned: HashPolicy,
+    ) -> HashPolicy {
+        let given = policies
+            .iter()
+            .filter_map(|given| Some((given.root.as_deref()?, given.policy)))
+            .filter(|(given, _)| root.starts_with(given))
+            .max_by_key(|(given, _)| given.components().count());
+        if let Some((_, policy)) = given {
+            return policy;
+        }
+        policies
+            .iter()
+            .filter(|given| given.root.is_none())
+            .map(|given| given.policy)
+            .fold(tuned, HashPolicy::min)
+    }
+}
+
+impl std::str::FromStr for RootHashPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // the policy follows the last `=`, as paths may hold one
+        Ok(match s.rsplit_once('=') {
+            Some((root, policy)) => RootHashPolicy {
+                root: Some(std::path::PathBuf::from(root)),
+                policy: policy.parse()?,
+            },
+            None => RootHashPolicy {
+                root: None,
+                policy: s.parse()?,
+            },
+        })
+    }
+}
+
+impl std::str::FromStr for HashPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('-', "_").as_str() {
+            "metadata_only" => Ok(HashPolicy::MetadataOnly),
+            "small_files" => Ok(HashPolicy::SmallFiles),
+            "full" => Ok(HashPolicy::Full),
+            other => anyhow::bail!(
+                "Unknown hash policy: {} (expected metadata_only, small_files or full)",
+                other
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for HashPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HashPolicy::MetadataOnly => "metadata_only",
+            HashPolicy::SmallFiles => "small_files",
+            HashPolicy::Full => "full",
+        })
+    }
+}
+
+fn read_chunks(
+    file: &mut std::fs::File,
+    buf: &mut [u8],
+    mut update: impl FnMut(&[u8]),
+) -> std::io::Result<()> {
+    loop {
+        match file.read(buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => update(&buf[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+impl std::str::FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "xxhash" | "xxh3" => Ok(HashAlgorithm::Xxh3),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            other => anyhow::bail!("Unknown hash algorithm: {}", other),
+        }
+    }
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.prefix())
+    }
+}
@@ -1,51 +1,937 @@
 use anyhow::Ok;
 use std::io::Write as _;
 
-/// Walk the directory in parallel, printing formatted TSV lines,
-#[tracing::instrument(skip(output_tsv_file, data_root, progress_log_interval))]
+/// Tuning knobs for [`walk_directory`]
+#[derive(Debug, Clone)]
+pub struct CrawlOptions {
+    /// Directories with more entries than this are logged and reported as
+    /// hot; the entries of every directory are counted while walking
+    pub hot_dir_threshold: Option<u64>,
+    /// Stop recording entries of a directory after this many; the directory
+    /// is reported as truncated, and delta processing keeps the tracked files
+    /// below it as they were rather than deleting those not recorded
+    pub max_entries_per_dir: Option<u64>,
+    /// How file extensions are normalized into `file_type`
+    pub extension_rules: crate::extension::ExtensionRules,
+    /// Only descend this many levels below the root (1: its direct entries)
+    pub max_depth: Option<usize>,
+    /// Record file names and the path components below the scan root encrypted
+    pub path_cipher: Option<crate::path_cipher::PathCipher>,
+    /// Root of the scan the walked directory is part of, if not the directory
+    /// itself (a batch); paths are encrypted below it
+    pub scan_root: Option<std::path::PathBuf>,
+    /// Buffer the lines and write them ordered by path, so that crawls of an
+    /// unchanged tree only differ in their scan_id (see `content_sha256`)
+    pub sort_output: bool,
+    /// Record each file's SELinux context or SMACK label; `\N` otherwise
+    pub security_labels: Option<crate::security_label::LabelSource>,
+    /// Record each file's permission bits, owner and group (Unix only); `\N`
+    /// otherwise
+    pub record_ownership: bool,
+    /// Read each file to record a checksum of its contents as its fingerprint;
+    /// `\N` otherwise, or if the file cannot be read
+    pub content_hash: Option<crate::content_hash::HashAlgorithm>,
+    /// Files `content_hash` reads per root, over those of the root's
+    /// filesystem type in `fs_tuning`
+    pub hash_policies: Vec<crate::content_hash::RootHashPolicy>,
+    /// Largest file hashed under `HashPolicy::SmallFiles`
+    pub small_file_bytes: u64,
+    /// Fingerprints hashed by earlier crawls, reused for files whose device,
+    /// inode, size and mtime are unchanged instead of reading them again
+    pub hash_cache: Option<crate::hash_cache::HashCache>,
+    /// Walker threads, mount following and hashing policy per type of the
+    /// walked filesystem
+    pub fs_tuning: crate::fs_type::TuningTable,
+    /// Tune the number of walker threads stat'ing at once during the walk,
+    /// starting from this many (see `thread_tuner`)
+    pub adaptive_threads: Option<usize>,
+    /// Read all directories with many threads before the walk, so that it
+    /// finds them cached (see `prewarm`)
+    pub prewarm: bool,
+    /// Only record files matching one of these globs (gitignore syntax,
+    /// relative to the scan root); every file if empty. Files left out are
+    /// treated as absent by delta processing.
+    pub include: Vec<String>,
+    /// Skip files and whole directories matching these globs, even if included
+    pub exclude: Vec<String>,
+    /// How directories are traversed
+    pub walker: WalkerBackend,
+    /// Hash this fraction of the files, drawn anew for each scan, and check
+    /// that they start like their extension says (see `sample`); every file
+    /// is hashed anyway with `content_hash`
+    pub sample_fraction: Option<f64>,
+    /// Also record each directory, with its entries and the files and bytes
+    /// of its subtree, into a TSV next to the crawl's (see `dir_stats`)
+    pub record_dirs: bool,
+    /// Descend into the targets of symlinks, recording what they lead to
+    /// under the link's path; links are otherwise neither followed nor recorded
+    pub follow_symlinks: bool,
+    /// Record symlinks themselves, with their `symlink_target`; their size
+    /// and mtime are the link's own
+    pub record_symlinks: bool,
+    /// Walker threads stat'ing at once, shared with the other crawls holding
+    /// the budget; unbounded if `None`
+    pub io_budget: Option<crate::thread_tuner::IoBudget>,
+}
+
+impl Default for CrawlOptions {
+    fn default() -> Self {
+        Self {
+            hot_dir_threshold: None,
+            max_entries_per_dir: None,
+            extension_rules: crate::extension::ExtensionRules::default(),
+            max_depth: None,
+            path_cipher: None,
+            scan_root: None,
+            sort_output: false,
+            security_labels: None,
+            record_ownership: false,
+            content_hash: None,
+            hash_policies: Vec::new(),
+            small_file_bytes: crate::content_hash::SMALL_FILE_BYTES,
+            hash_cache: None,
+            fs_tuning: crate::fs_type::TuningTable::default(),
+            adaptive_threads: None,
+            prewarm: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            walker: WalkerBackend::Parallel,
+            sample_fraction: None,
+            record_dirs: false,
+            follow_symlinks: false,
+            record_symlinks: false,
+            io_budget: None,
+        }
+    }
+}
+
+impl CrawlOptions {
+    /// Tuning of a root on filesystem type `fs_type`, if told
+    pub(crate) fn tuning(&self, fs_type: Option<&str>) -> crate::fs_type::FsTuning {
+        fs_type
+            .map(|fs_type| self.fs_tuning.get(fs_type))
+            .unwrap_or_default()
+    }
+
+    /// Files crawls of `root` on filesystem type `fs_type` hash with
+    /// `content_hash`
+    fn root_hash_policy(
+        &self,
+        root: &std::path::Path,
+        fs_type: Option<&str>,
+    ) -> crate::content_hash::HashPolicy {
+        let tuned = self
+            .tuning(fs_type)
+            .hash_policy
+            .unwrap_or(crate::content_hash::HashPolicy::Full);
+        crate::content_hash::RootHashPolicy::resolve(&self.hash_policies, root, tuned)
+    }
+
+    /// Files crawls of `root` hash with `content_hash`
+    pub(crate) fn hash_policy(&self, root: &std::path::Path) -> crate::content_hash::HashPolicy {
+        self.root_hash_policy(root, crate::fs_type::detect(root).as_deref())
+    }
+
+    /// The `include` and `exclude` globs as walker overrides, anchored at
+    /// `scan_root`
+    pub(crate) fn overrides(
+        &self,
+        scan_root: &std::path::Path,
+    ) -> anyhow::Result<ignore::overrides::Override> {
+        let mut builder = ignore::overrides::OverrideBuilder::new(scan_root);
+        for glob in &self.include {
+            builder
+                .add(glob)
+                .map_err(|e| anyhow::anyhow!("Invalid include glob {}: {}", glob, e))?;
+        }
+        // the last matching glob decides, so excludes win over includes
+        for glob in &self.exclude {
+            builder
+                .add(&format!("!{}", glob))
+                .map_err(|e| anyhow::anyhow!("Invalid exclude glob {}: {}", glob, e))?;
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Directory traversal of a crawl
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkerBackend {
+    /// Many threads stat'ing full paths (the `ignore` crate's walker); fails
+    /// on the subtrees of paths longer than `PATH_MAX`
+    Parallel,
+    /// One thread opening every directory relative to its parent's fd (see
+    /// `dirfd_walk`), for trees nested beyond `PATH_MAX`; Linux and macOS only
+    Dirfd,
+}
+
+impl std::str::FromStr for WalkerBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "parallel" => Ok(WalkerBackend::Parallel),
+            "dirfd" => Ok(WalkerBackend::Dirfd),
+            other => anyhow::bail!("Unknown walker: {}", other),
+        }
+    }
+}
+
+impl std::fmt::Display for WalkerBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WalkerBackend::Parallel => write!(f, "parallel"),
+            WalkerBackend::Dirfd => write!(f, "dirfd"),
+        }
+    }
+}
+
+/// Lines a streamed crawl buffers ahead of the COPY it feeds; walker
+/// threads wait for room beyond that, so the walk keeps the database's pace
+const STREAM_BACKLOG_LINES: usize = 65_536;
+
+/// Where [`walk_directory`] writes the crawl
+#[derive(Debug)]
+pub enum CrawlSink {
+    /// A TSV file, with a checksum sidecar next to it
+    File(std::path::PathBuf),
+    /// The write end of a pipe, e.g. into a COPY (see `data::load_tsv_stream`)
+    Pipe(tokio::io::DuplexStream),
+}
+
+impl From<std::path::PathBuf> for CrawlSink {
+    fn from(path: std::path::PathBuf) -> Self {
+        CrawlSink::File(path)
+    }
+}
+
+/// A directory with a pathological number of entries
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct HotDir {
+    pub path: std::path::PathBuf,
+    pub entry_count: u64,
+    /// Whether entries were dropped because of `max_entries_per_dir`
+    pub truncated: bool,
+}
+
+/// An entry the walker failed to read, left out of the crawl with whatever
+/// is below it
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ScanError {
+    pub path: std::path::PathBuf,
+    /// `permission_denied`, `stale_handle`, `not_found`, `fd_exhausted`,
+    /// `name_too_long`, `loop` or `io`
+    pub kind: String,
+    pub message: String,
+}
+
+/// Errors a crawl keeps at most; further ones are only counted, so that a
+/// tree the scanning user cannot read does not fill the memory
+const MAX_SCAN_ERRORS: usize = 100_000;
+
+/// Everything a crawl produced besides the TSV file itself
+#[derive(Debug, Clone, Default)]
+pub struct CrawlReport {
+    pub metadata: std::collections::HashMap<String, String>,
+    pub hot_dirs: Vec<HotDir>,
+    /// The first `MAX_SCAN_ERRORS` errors of the walk
+    pub errors: Vec<ScanError>,
+}
+
+/// Errors of the walk, gathered by the walker threads
+#[derive(Debug, Default)]
+struct ScanErrors {
+    kept: std::sync::Mutex<Vec<ScanError>>,
+    by_kind: dashmap::DashMap<&'static str, u64>,
+}
+
+impl ScanErrors {
+    /// Record the failure of the walker on `path`
+    fn record(&self, path: &std::path::Path, kind: &'static str, message: String) {
+        tracing::debug!("Failed to walk {}: {}", path.display(), message);
+        *self.by_kind.entry(kind).or_insert(0) += 1;
+        if let std::result::Result::Ok(mut kept) = self.kept.lock()
+            && kept.len() < MAX_SCAN_ERRORS
+        {
+            kept.push(ScanError {
+                path: path.to_path_buf(),
+                kind: kind.to_string(),
+                message,
+            });
+        }
+    }
+
+    fn record_io(&self, path: &std::path::Path, error: &std::io::Error) {
+        self.record(path, io_error_kind(error), error.to_string());
+    }
+
+    /// Record an error of the parallel walker, under the path it names or
+    /// else `root`
+    fn record_walk(&self, root: &std::path::Path, error: &ignore::Error) {
+        let mut inner = error;
+        let mut path = None;
+        loop {
+            match inner {
+                ignore::Error::WithPath { path: at, err } => {
+                    path.get_or_insert(at.as_path());
+                    inner = err;
+                }
+                ignore::Error::WithDepth { err, .. }
+                | ignore::Error::WithLineNumber { err, .. } => inner = err,
+                ignore::Error::Loop { child, .. } => {
+                    return self.record(path.unwrap_or(child), "loop", inner.to_string());
+                }
+                ignore::Error::Io(e) => return self.record_io(path.unwrap_or(root), e),
+                _ => return self.record(path.unwrap_or(root), "io", inner.to_string()),
+            }
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.by_kind.iter().map(|entry| *entry.value()).sum()
+    }
+
+    /// The errors kept, and their counts by kind into `metadata`
+    fn into_report(
+        self,
+        metadata: &mut std::collections::HashMap<String, String>,
+    ) -> Vec<ScanError> {
+        let total = self.total();
+        let kept = self.kept.into_inner().unwrap_or_default();
+        metadata.insert("scan_errors".to_string(), total.to_string());
+        if total == 0 {
+            return kept;
+        }
+        let by_kind: std::collections::BTreeMap<_, _> = self.by_kind.into_iter().collect();
+        tracing::warn!(
+            "⚠️ {} entries could not be read and are missing from the crawl: {:?}",
+            total,
+            by_kind
+        );
+        metadata.insert(
+            "scan_errors_by_kind".to_string(),
+            serde_json::json!(by_kind).to_string(),
+        );
+        if total > kept.len() as u64 {
+            metadata.insert(
+                "scan_errors_unrecorded".to_string(),
+                (total - kept.len() as u64).to_string(),
+            );
+        }
+        kept
+    }
+}
+
+/// What kind of [`ScanError`] an I/O error is
+fn io_error_kind(error: &std::io::Error) -> &'static str {
+    if crate::fd_limit::is_exhausted(error) {
+        return "fd_exhausted";
+    }
+    match error.raw_os_error() {
+        Some(ENAMETOOLONG) => return "name_too_long",
+        Some(ELOOP) => return "loop",
+        _ => {}
+    }
+    match error.kind() {
+        std::io::ErrorKind::PermissionDenied => "permission_denied",
+        std::io::ErrorKind::StaleNetworkFileHandle => "stale_handle",
+        std::io::ErrorKind::NotFound => "not_found",
+        _ => "io",
+    }
+}
+
+/// Shape of the walked tree, accumulated lock-free by the walker threads
+#[derive(Debug, Default)]
+struct TreeStats {
+    max_depth: std::sync::atomic::AtomicUsize,
+    directories: std::sync::atomic::AtomicU64,
+    symlinks: std::sync::atomic::AtomicU64,
+    /// entries below the root, i.e. the children of all walked directories
+    entries: std::sync::atomic::AtomicU64,
+    /// size of all recorded files
+    total_bytes: std::sync::atomic::AtomicU64,
+    /// newest mtime of a recorded file, in seconds like the TSV
+    max_mtime: std::sync::atomic::AtomicI64,
+}
+
+impl TreeStats {
+    fn record(&self, ent: &ignore::DirEntry) {
+        self.record_entry(
+            ent.depth(),
+            ent.path_is_symlink(),
+            ent.file_type().is_some_and(|ft| ft.is_dir()),
+        );
+    }
+
+    fn record_entry(&self, depth: usize, is_symlink: bool, is_dir: bool) {
+        use std::sync::atomic::Ordering::Relaxed;
+
+        self.max_depth.fetch_max(depth, Relaxed);
+        if depth > 0 {
+            self.entries.fetch_add(1, Relaxed);
+        }
+        if is_symlink {
+            self.symlinks.fetch_add(1, Relaxed);
+        } else if is_dir {
+            self.directories.fetch_add(1, Relaxed);
+        }
+    }
+
+    fn record_file(&self, size: u64, mtime: Option<std::time::SystemTime>) {
+        use std::sync::atomic::Ordering::Relaxed;
+
+        self.total_bytes.fetch_add(size, Relaxed);
+        if let Some(mtime) = mtime.and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok()) {
+            self.max_mtime.fetch_max(mtime.as_secs() as i64, Relaxed);
+        }
+    }
+
+    fn insert_into(&self, metadata: &mut std::collections::HashMap<String, String>) {
+        use std::sync::atomic::Ordering::Relaxed;
+
+        let directories = self.directories.load(Relaxed);
+        let entries = self.entries.load(Relaxed);
+        let mean_fan_out = if directories > 0 {
+            entries as f64 / directories as f64
+        } else {
+            0.0
+        };
+        metadata.insert(
+            "max_depth".to_string(),
+            self.max_depth.load(Relaxed).to_string(),
+        );
+        metadata.insert("directory_count".to_string(), directories.to_string());
+        metadata.insert(
+            "symlink_count".to_string(),
+            self.symlinks.load(Relaxed).to_string(),
+        );
+        metadata.insert("mean_fan_out".to_string(), mean_fan_out.to_string());
+        metadata.insert(
+            "total_bytes".to_string(),
+            self.total_bytes.load(Relaxed).to_string(),
+        );
+        metadata.insert(
+            "max_mtime".to_string(),
+            self.max_mtime.load(Relaxed).to_string(),
+        );
+    }
+}
+
+/// What the crawl TSV records of a regular file besides its path
+pub(crate) struct FileFacts {
+    pub size: u64,
+    pub mtime: Option<std::time::SystemTime>,
+    pub label: Option<String>,
+    pub fingerprint: Option<String>,
+    /// Where the entry leads if it is a recorded symlink
+    pub symlink_target: Option<String>,
+    pub ownership: Option<Ownership>,
+}
+
+/// Permission bits, owner and group of a file, as `record_ownership` records
+/// them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Ownership {
+    /// `st_mode` without the file type bits
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// The ownership of the file with `meta`, if `options` record it; `None` off
+/// Unix, where there are no such bits
+fn ownership(meta: &std::fs::Metadata, options: &CrawlOptions) -> Option<Ownership> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        options.record_ownership.then(|| Ownership {
+            mode: meta.mode() & 0o7777,
+            uid: meta.uid(),
+            gid: meta.gid(),
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (meta, options);
+        None
+    }
+}
+
+/// The crawl TSV line of the regular file at `path` with metadata `meta`,
+/// hashed if `hash_policy` says so, its path encrypted below `scan_root` if
+/// `options` say so
+pub(crate) fn tsv_line(
+    path: &std::path::Path,
+    meta: &std::fs::Metadata,
+    scan_id: i32,
+    scan_root: &std::path::Path,
+    options: &CrawlOptions,
+    hash_policy: crate::content_hash::HashPolicy,
+) -> String {
+    let facts = FileFacts {
+        size: meta.len(),
+        mtime: meta.modified().ok(),
+        label: options.security_labels.and_then(|source| source.read(path)),
+        fingerprint: options
+            .content_hash
+            .filter(|_| hash_policy.hashes(meta.len(), options.small_file_bytes))
+            .and_then(|algorithm| {
+                let cached = options.hash_cache.as_ref().zip(file_key(meta));
+                cached
+                    .and_then(|(cache, key)| cache.get(&key, algorithm))
+                    .or_else(|| {
+                        let fingerprint = log_hash_error(path, algorithm.hash_file(path))?;
+                        if let Some((cache, key)) = cached {
+                            cache.put(&key, algorithm, &fingerprint);
+                        }
+                        Some(fingerprint)
+                    })
+            }),
+        symlink_target: None,
+        ownership: ownership(meta, options),
+    };
+    format_tsv_line(path, facts, scan_id, scan_root, options)
+}
+
+/// What the hash cache knows the file with `meta` by; `None` off Unix, where
+/// there is no cache
+fn file_key(meta: &std::fs::Metadata) -> Option<crate::hash_cache::FileKey> {
+    #[cfg(unix)]
+    {
+        Some(crate::hash_cache::FileKey::from_metadata(meta))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        None
+    }
+}
+
+/// The fingerprint hashed, or `None` with the error logged
+fn log_hash_error(path: &std::path::Path, hashed: std::io::Result<String>) -> Option<String> {
+    match hashed {
+        std::result::Result::Ok(fingerprint) => Some(fingerprint),
+        Err(e) => {
+            tracing::debug!("Failed to hash {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// The target read of the symlink at `path`, or `None` with the error logged
+fn log_link_error(
+    path: &std::path::Path,
+    target: std::io::Result<std::path::PathBuf>,
+) -> Option<String> {
+    match target {
+        std::result::Result::Ok(target) => Some(target.to_string_lossy().into_owned()),
+        Err(e) => {
+            tracing::debug!("Failed to read the symlink {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Fingerprint of a file of `size` bytes the crawl hashes: every file
+/// `hash_policy` lets `content_hash` read, otherwise those in the scan's
+/// sample, with XXH3. Sampled files are also sniffed, into `samples`; the
+/// others are looked up in the hash cache by `key` first, counted into
+/// `cached`.
+fn crawl_fingerprint(
+    path: &std::path::Path,
+    size: u64,
+    key: Option<crate::hash_cache::FileKey>,
+    open: impl FnOnce() -> std::io::Result<std::fs::File>,
+    scan_id: i32,
+    (options, hash_policy): (&CrawlOptions, crate::content_hash::HashPolicy),
+    (samples, cached): (&crate::sample::SampleStats, &crate::hash_cache::CacheStats),
+) -> Option<String> {
+    let sampled = options
+        .sample_fraction
+        .is_some_and(|fraction| crate::sample::is_sampled(path, scan_id, fraction));
+    let algorithm = options
+        .content_hash
+        .filter(|_| hash_policy.hashes(size, options.small_file_bytes))
+        .or(sampled.then_some(crate::content_hash::HashAlgorithm::Xxh3))?;
+    // sampled files are read anyway, to be sniffed
+    let cache = options.hash_cache.as_ref().zip(key);
+    if let Some((cache, key)) = cache.filter(|_| !sampled) {
+        let fingerprint = cache.get(&key, algorithm);
+        cached.record(fingerprint.is_some());
+        if fingerprint.is_some() {
+            return fingerprint;
+        }
+    }
+    let hashed = open().and_then(|mut file| {
+        if sampled {
+            let ext = options.extension_rules.normalize(path);
+            let sniffed = crate::sample::sniff(&mut file, &ext.to_ascii_lowercase())?;
+            samples.record(path, &ext, sniffed);
+        }
+        algorithm.hash(&mut file)
+    });
+    let fingerprint = log_hash_error(path, hashed)?;
+    if let Some((cache, key)) = cache {
+        cache.put(&key, algorithm, &fingerprint);
+    }
+    Some(fingerprint)
+}
+
+/// An mtime as the crawl TSV records it: RFC 3339, to the second, the epoch
+/// if unknown
+pub(crate) fn format_mtime(mtime: Option<std::time::SystemTime>) -> String {
+    mtime
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| {
+            let dt = chrono::DateTime::<chrono::Utc>::from_timestamp(d.as_secs() as i64, 0)
+                .unwrap_or_default();
+            dt.to_rfc3339()
+        })
+        .unwrap_or_else(|| "1970-01-01T00:00:00Z".to_string())
+}
+
+/// The crawl TSV line of the regular file at `path` with `facts`, its path
+/// encrypted below `scan_root` if `options` say so
+pub(crate) fn format_tsv_line(
+    path: &std::path::Path,
+    facts: FileFacts,
+    scan_id: i32,
+    scan_root: &std::path::Path,
+    options: &CrawlOptions,
+) -> String {
+    let fname = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let (fname, fpath) = match &options.path_cipher {
+        Some(cipher) => (
+            cipher.encrypt_component(&fname),
+            cipher.encrypt_path(scan_root, path),
+        ),
+        None => (fname.to_string(), path.display().to_string()),
+    };
+    let ext = options.extension_rules.normalize(path);
+    let size = facts.size;
+    let mtime = format_mtime(facts.mtime);
+    // encrypted whole, as it may lead anywhere
+    let symlink_target = facts
+        .symlink_target
+        .map(|target| match &options.path_cipher {
+            Some(cipher) => cipher.encrypt_component(&target),
+            None => target,
+        });
+
+    crate::tsv::Row::new()
+        .field(&fname)
+        .field(&ext)
+        .field(&fpath)
+        .value(size)
+        .value(mtime)
+        .nullable(facts.label.as_deref())
+        .nullable(facts.fingerprint.as_deref())
+        .nullable(symlink_target.as_deref())
+        .nullable_value(facts.ownership.map(|o| o.mode))
+        .nullable_value(facts.ownership.map(|o| o.uid))
+        .nullable_value(facts.ownership.map(|o| o.gid))
+        .value(scan_id)
+        .finish()
+}
+
+/// The (escaped) path field of a crawl TSV line
+fn tsv_line_path(line: &str) -> &str {
+    line.split('\t').nth(2).unwrap_or_default()
+}
+
+/// `errno` of a path beyond `PATH_MAX`
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const ENAMETOOLONG: i32 = libc::ENAMETOOLONG;
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+const ENAMETOOLONG: i32 = -1;
+
+/// `errno` of too many levels of symlinks
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const ELOOP: i32 = libc::ELOOP;
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+const ELOOP: i32 = -2;
+
+/// Count an entry of directory `parent`, warning once it turns hot; whether
+/// the entry is beyond `max_entries_per_dir` and to be skipped
+fn count_entry(
+    dir_counts: &dashmap::DashMap<std::path::PathBuf, u64>,
+    parent: &std::path::Path,
+    hot_dir_threshold: Option<u64>,
+    max_entries_per_dir: Option<u64>,
+) -> bool {
+    let entries = match dir_counts.get_mut(parent) {
+        Some(mut count) => {
+            *count += 1;
+            *count
+        }
+        None => {
+            let mut count = dir_counts.entry(parent.to_path_buf()).or_insert(0);
+            *count += 1;
+            *count
+        }
+    };
+    if hot_dir_threshold.is_some_and(|t| entries == t + 1) {
+        tracing::warn!(
+            "🔥 Hot directory: {} has more than {} entries",
+            parent.display(),
+            entries - 1
+        );
+    }
+    max_entries_per_dir.is_some_and(|max| entries > max)
+}
+
+/// How one root of a crawl is walked, after the type of its filesystem
+struct RootPlan {
+    root: std::path::PathBuf,
+    fs_type: Option<String>,
+    threads: usize,
+    follow_mounts: bool,
+    hash_policy: crate::content_hash::HashPolicy,
+    overrides: ignore::overrides::Override,
+}
+
+impl RootPlan {
+    fn new(root: std::path::PathBuf, options: &CrawlOptions) -> anyhow::Result<Self> {
+        // globs are anchored at the scan's root, also when walking a batch of it
+        let overrides = options.overrides(options.scan_root.as_deref().unwrap_or(&root))?;
+        let fs_type = crate::fs_type::detect(&root);
+        let tuning = options.tuning(fs_type.as_deref());
+        // the walker's own default when left to it
+        let threads = tuning.threads.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map_or(1, |n| n.get())
+                .min(12)
+        });
+        let threads = if options.walker == WalkerBackend::Dirfd {
+            1
+        } else if options.adaptive_threads.is_some() {
+            // adaptive crawls cap the threads they spawn themselves
+            threads
+        } else {
+            crate::fd_limit::cap_threads(threads, "walker")
+        };
+        let follow_mounts = tuning.follow_mounts.unwrap_or(true);
+        let hash_policy = options.root_hash_policy(&root, fs_type.as_deref());
+        tracing::info!(
+            "💽 {} on {}: {} walker threads, {}following mounts{}",
+            root.display(),
+            fs_type.as_deref().unwrap_or("unknown"),
+            match options.adaptive_threads {
+                Some(start) => format!("adaptive ({} to start with)", start),
+                None => threads.to_string(),
+            },
+            if follow_mounts { "" } else { "not " },
+            match options.content_hash {
+                Some(_) => format!(", hashing {}", hash_policy),
+                None => String::new(),
+            }
+        );
+        Ok(Self {
+            root,
+            fs_type,
+            threads,
+            follow_mounts,
+            hash_policy,
+            overrides,
+        })
+    }
+
+    /// Whether the root itself is excluded, e.g. a batch of an excluded
+    /// directory; the walker only applies the globs below it
+    fn excluded(&self) -> bool {
+        self.overrides.matched(&self.root, true).is_ignore()
+    }
+}
+
+/// Walk the directories in parallel, one after the other, printing
+/// formatted TSV lines to `output`, idling while `pause` is set
+#[tracing::instrument(skip(output, data_roots, progress_log_interval, progress, options, pause))]
 pub async fn walk_directory(
-    data_root: std::path::PathBuf,
+    data_roots: Vec<std::path::PathBuf>,
     progress_log_interval: u64,
     scan_id: i32,
-    output_tsv_file: std::path::PathBuf,
-) -> anyhow::Result<std::collections::HashMap<String, String>> {
-    // 1) channel
-    let (tx, rx) = crossbeam_channel::unbounded::<String>();
+    output: impl Into<CrawlSink>,
+    progress: crate::progress::ProgressReporter,
+    options: &CrawlOptions,
+    pause: crate::pause::PauseSwitch,
+) -> anyhow::Result<CrawlReport> {
+    let output = output.into();
+    anyhow::ensure!(!data_roots.is_empty(), "No directory to walk");
+    anyhow::ensure!(
+        options.walker == WalkerBackend::Parallel
+            || cfg!(any(target_os = "linux", target_os = "macos")),
+        "The dirfd walker needs Linux or macOS"
+    );
+    anyhow::ensure!(
+        options
+            .sample_fraction
+            .is_none_or(|fraction| fraction > 0.0 && fraction <= 1.0),
+        "The sample fraction must be above 0 and at most 1"
+    );
+    anyhow::ensure!(
+        !(options.walker == WalkerBackend::Dirfd && options.adaptive_threads.is_some()),
+        "The dirfd walker is single-threaded and cannot tune its threads"
+    );
+    anyhow::ensure!(
+        !(options.walker == WalkerBackend::Dirfd && options.follow_symlinks),
+        "The dirfd walker does not follow symlinks"
+    );
+    anyhow::ensure!(
+        !(options.follow_symlinks && options.record_symlinks),
+        "Symlinks are either followed or recorded"
+    );
+    anyhow::ensure!(
+        !(options.record_dirs && matches!(output, CrawlSink::Pipe(_))),
+        "Directories can only be recorded by crawls written to a file"
+    );
+    let plans = std::sync::Arc::new(
+        data_roots
+            .into_iter()
+            .map(|root| RootPlan::new(root, options))
+            .collect::<anyhow::Result<Vec<_>>>()?,
+    );
+    // 0) read the directories ahead of the walk, if asked to
+    let prewarm = if options.prewarm {
+        let plans = plans.clone();
+        let max_depth = options.max_depth;
+        let pause = pause.clone();
+        let started = std::time::Instant::now();
+        let directories = tokio::task::spawn_blocking(move || {
+            plans
+                .iter()
+                .map(|plan| {
+                    tracing::info!(
+                        "🌡️ Warming the directory cache of {}...",
+                        plan.root.display()
+                    );
+                    crate::prewarm::warm_directories(
+                        &plan.root,
+                        max_depth,
+                        plan.follow_mounts,
+                        &plan.overrides,
+                        &pause,
+                    )
+                })
+                .sum::<u64>()
+        })
+        .await?;
+        let elapsed = started.elapsed().as_secs_f64();
+        tracing::info!("🌡️ Read {} directories in {:.1}s", directories, elapsed);
+        Some((directories, elapsed))
+    } else {
+        None
+    };
+
+    // 1) channel, bounded when streaming so that a slow database holds the
+    // walk back instead of the lines piling up in memory
+    let (tx, rx) = match output {
+        CrawlSink::File(_) => crossbeam_channel::unbounded::<String>(),
+        CrawlSink::Pipe(_) => crossbeam_channel::bounded::<String>(STREAM_BACKLOG_LINES),
+    };
     let (stop_tx, stop_rx) = crossbeam_channel::bounded::<()>(0);
 
     // 2) progress / done flags
     let counter = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
     let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    // directory most recently entered by any worker; workers only `try_lock`
+    // it so sampling never slows the walk down
+    let current_dir = std::sync::Arc::new(std::sync::Mutex::new(None::<std::path::PathBuf>));
+    // tree-shape statistics
+    let tree_stats = std::sync::Arc::new(TreeStats::default());
+    // entries seen per directory, only tracked when hot dirs are detected or capped
+    let dir_counts = std::sync::Arc::new(dashmap::DashMap::<std::path::PathBuf, u64>::new());
+    let count_entries =
+        options.hot_dir_threshold.is_some() || options.max_entries_per_dir.is_some();
+    // entries the walker failed on for want of file descriptors, each
+    // possibly a subtree left out
+    let fd_errors = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+    // entries the walker failed on, whatever the error
+    let scan_errors = std::sync::Arc::new(ScanErrors::default());
+    // directories, only tracked when recorded
+    let dir_stats = options
+        .record_dirs
+        .then(|| std::sync::Arc::new(crate::dir_stats::DirStats::default()));
 
-    // 3) writer thread
+    // 3) writer thread, hashing the lines as it writes them
+    let sort_output = options.sort_output;
+    let output_tsv_file = match &output {
+        CrawlSink::File(path) => Some(path.clone()),
+        CrawlSink::Pipe(_) => None,
+    };
     let writer_handle = {
         let rx = rx;
-        std::thread::spawn(move || {
-            // open file or stdout …
-            let mut out: Box<dyn std::io::Write> = {
-                if let Some(p) = output_tsv_file.parent() {
-                    std::fs::create_dir_all(p).unwrap();
+        // open the file, or bridge the pipe, from here: the bridge needs the runtime
+        let mut out: Box<dyn std::io::Write + Send> = match output {
+            CrawlSink::File(path) => {
+                if let Some(p) = path.parent() {
+                    std::fs::create_dir_all(p)?;
                 }
-                let f = std::fs::File::create(output_tsv_file).unwrap();
+                let f = std::fs::File::create(&path)
+                    .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))?;
                 Box::new(std::io::BufWriter::new(f))
+            }
+            CrawlSink::Pipe(pipe) => Box::new(std::io::BufWriter::with_capacity(
+                64 * 1024,
+                tokio_util::io::SyncIoBridge::new(pipe),
+            )),
+        };
+        std::thread::spawn(move || {
+            use sha2::Digest;
+
+            let lines: Box<dyn Iterator<Item = String>> = if sort_output {
+                let mut lines: Vec<String> = rx.into_iter().collect();
+                tracing::debug!("🔤 Sorting {} lines by path...", lines.len());
+                lines.sort_unstable_by(|a, b| tsv_line_path(a).cmp(tsv_line_path(b)));
+                Box::new(lines.into_iter())
+            } else {
+                Box::new(rx.into_iter())
             };
 
-            for line in rx {
-                let _ = out.write_all(line.as_bytes());
+            let fail_after = crate::fault::FaultPoint::WriterFail.armed();
+            let mut hasher = sha2::Sha256::new();
+            // the lines without their scan_id, which differs between crawls
+            // of the same tree
+            let mut content_hasher = sort_output.then(sha2::Sha256::new);
+            for (written, line) in lines.enumerate() {
+                if fail_after == Some(written as u64) {
+                    let _ = out.flush();
+                    return Err(crate::fault::FaultPoint::WriterFail.error());
+                }
+                out.write_all(line.as_bytes())
+                    .map_err(|e| anyhow::anyhow!("Failed to write the crawl: {}", e))?;
+                hasher.update(line.as_bytes());
+                if let Some(content_hasher) = &mut content_hasher {
+                    let fields = line.rsplit_once('\t').map_or(line.as_str(), |(f, _)| f);
+                    content_hasher.update(fields.as_bytes());
+                    content_hasher.update(b"\n");
+                }
             }
-            let _ = out.flush();
+            out.flush()
+                .map_err(|e| anyhow::anyhow!("Failed to write the crawl: {}", e))?;
+            Ok((
+                crate::integrity::to_hex(&hasher.finalize()),
+                content_hasher.map(|h| crate::integrity::to_hex(&h.finalize())),
+            ))
         })
     };
 
     // 4) progress thread
     let progress_handle = {
         let counter = counter.clone();
+        let current_dir = current_dir.clone();
+        let pause = pause.clone();
         // tick channel emits a `()` every `progress_log_interval` seconds
         let ticker = crossbeam_channel::tick(std::time::Duration::from_secs(progress_log_interval));
         std::thread::spawn(move || {
             let start = std::time::Instant::now();
             let mut last_cnt = 0;
             let mut last_t = start;
+            let mut last_dir: Option<std::path::PathBuf> = None;
+            let mut last_dir_since = start;
 
             loop {
                 // select! will unblock either on a tick **or** on stop_rx
@@ -58,6 +944,14 @@ pub async fn walk_directory(
                     recv(ticker) -> _ => {
                         let now = std::time::Instant::now();
                         let total = counter.load(std::sync::atomic::Ordering::Relaxed);
+                        if pause.is_paused() {
+                            tracing::info!("⏸️ Crawl paused at {} files", total);
+                            // neither a stall nor a slowdown once resumed
+                            last_cnt = total;
+                            last_t = now;
+                            last_dir_since = now;
+                            continue;
+                        }
                         let interval_secs = now.duration_since(last_t).as_secs_f64().max(1e-9);
                         let interval_cnt  = total - last_cnt;
                         let rate_now = interval_cnt as f64 / interval_secs;
@@ -73,6 +967,26 @@ pub async fn walk_directory(
                             rate_now, progress_log_interval, rate_all
                         );
 
+                        let dir = current_dir.lock().map(|d| d.clone()).unwrap_or(None);
+                        if dir != last_dir {
+                            last_dir = dir.clone();
+                            last_dir_since = now;
+                        }
+                        if let Some(d) = &dir {
+                            let in_dir_secs = now.duration_since(last_dir_since).as_secs();
+                            if in_dir_secs >= progress_log_interval {
+                                tracing::warn!("📂 Still in {} after {}s", d.display(), in_dir_secs);
+                            } else {
+                                tracing::info!("📂 Currently in: {}", d.display());
+                            }
+                        }
+                        progress.emit(crate::progress::ProgressEvent::CrawlTick {
+                            files: total,
+                            elapsed: now.duration_since(start),
+                            files_per_second: rate_now,
+                            current_dir: dir,
+                        });
+
                         last_cnt = total;
                         last_t = now;
                     }
@@ -84,67 +998,303 @@ pub async fn walk_directory(
     // 5) do the blocking parallel walk
     let tx2 = tx.clone();
     let counter2 = counter.clone();
+    let tree_stats2 = tree_stats.clone();
+    let current_dir2 = current_dir.clone();
+    let dir_counts2 = dir_counts.clone();
+    let fd_errors2 = fd_errors.clone();
+    let scan_errors2 = scan_errors.clone();
+    let dir_stats2 = dir_stats.clone();
+    let samples = std::sync::Arc::new(crate::sample::SampleStats::default());
+    let samples2 = samples.clone();
+    let cache_stats = std::sync::Arc::new(crate::hash_cache::CacheStats::default());
+    let cache_stats2 = cache_stats.clone();
+    // whether the parallel walker was handed a path beyond PATH_MAX
+    let long_paths = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let hot_dir_threshold = options.hot_dir_threshold;
+    let max_entries_per_dir = options.max_entries_per_dir;
+    let max_depth = options.max_depth;
+    // adaptive: all threads are spawned, the gate lets the tuned number of
+    // them stat at once
+    let adaptive_max = options
+        .adaptive_threads
+        .map(|_| crate::fd_limit::cap_threads(crate::thread_tuner::MAX_THREADS, "walker"));
+    let gate = options
+        .adaptive_threads
+        .zip(adaptive_max)
+        .map(|(start, max)| {
+            std::sync::Arc::new(crate::thread_tuner::ConcurrencyGate::new(start.min(max)))
+        });
+    let (tuner_stop_tx, tuner_stop_rx) = crossbeam_channel::bounded::<()>(0);
+    let tuner_handle = gate.clone().zip(adaptive_max).map(|(gate, max)| {
+        let counter = counter.clone();
+        let pause = pause.clone();
+        std::thread::spawn(move || {
+            crate::thread_tuner::run(&gate, max, &counter, &pause, tuner_stop_rx)
+        })
+    });
+    let line_options = std::sync::Arc::new(options.clone());
+    let gate2 = gate.clone();
     let done2 = done.clone();
-    let root = data_root.clone();
+    let plans2 = plans.clone();
 
     let start = std::time::Instant::now();
     tracing::debug!("🔍 Starting directory walk in parallel...");
 
-    tokio::task::spawn_blocking(move || {
-        let mut builder = ignore::WalkBuilder::new(root);
-        builder.ignore(false).hidden(false).git_ignore(false);
-
-        builder.build_parallel().run(|| {
-            let tx = tx2.clone();
-            let cnt = counter2.clone();
-            Box::new(move |res| {
-                if let std::result::Result::Ok(ent) = res {
-                    if let Some(ft) = ent.file_type() {
-                        if ft.is_file() {
-                            if let std::result::Result::Ok(meta) = ent.metadata() {
-                                let fname = ent.file_name().to_string_lossy();
-                                let ext = ent
-                                    .path()
-                                    .extension()
-                                    .and_then(|s| s.to_str())
-                                    .unwrap_or("unknown");
-                                let size = meta.len();
-                                let mtime = meta
-                                    .modified()
-                                    .ok()
-                                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                                    .map(|d| {
-                                        let dt = chrono::DateTime::<chrono::Utc>::from_timestamp(
-                                            d.as_secs() as i64,
-                                            0,
+    let root_files = tokio::task::spawn_blocking(move || {
+        let mut root_files = Vec::with_capacity(plans2.len());
+        for plan in plans2.iter() {
+            // paths are encrypted below the root, or the scan's if this is a batch of it
+            let scan_root = line_options
+                .scan_root
+                .clone()
+                .unwrap_or_else(|| plan.root.clone());
+            let before = counter2.load(std::sync::atomic::Ordering::Relaxed);
+            if plan.excluded() {
+                tracing::info!("🚫 Skipping excluded {}", plan.root.display());
+                root_files.push(0);
+                continue;
+            }
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            if line_options.walker == WalkerBackend::Dirfd {
+                crate::dirfd_walk::walk(
+                    &plan.root,
+                    max_depth,
+                    plan.follow_mounts,
+                    &plan.overrides,
+                    |res| {
+                        pause.wait();
+                        let _budget = line_options.io_budget.as_ref().map(|b| b.enter());
+                        let ent = match res {
+                            std::result::Result::Ok(ent) => ent,
+                            Err(err) => {
+                                if crate::fd_limit::is_exhausted(&err.error)
+                                    && fd_errors2.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                                        == 0
+                                {
+                                    tracing::warn!("⚠️ Out of file descriptors, skipping: {}", err);
+                                }
+                                scan_errors2.record_io(&err.path, &err.error);
+                                return ignore::WalkState::Continue;
+                            }
+                        };
+                        let is_dir = ent.kind == crate::dirfd_walk::EntryKind::Dir;
+                        if is_dir && let std::result::Result::Ok(mut slot) = current_dir2.try_lock() {
+                            *slot = Some(ent.path.to_path_buf());
+                        }
+                        tree_stats2.record_entry(
+                            ent.depth,
+                            ent.kind == crate::dirfd_walk::EntryKind::Symlink,
+                            is_dir,
+                        );
+                        if let Some(dir_stats) = &dir_stats2 {
+                            if is_dir {
+                                dir_stats.record_dir(ent.path, ent.mtime);
+                            }
+                            if ent.depth > 0 {
+                                dir_stats.record_entry(ent.path);
+                            }
+                        }
+                        if count_entries
+                            && ent.depth > 0
+                            && let Some(parent) = ent.path.parent()
+                            && count_entry(
+                                &dir_counts2,
+                                parent,
+                                hot_dir_threshold,
+                                max_entries_per_dir,
+                            )
+                        {
+                            return ignore::WalkState::Skip;
+                        }
+                        let symlink_target = (line_options.record_symlinks
+                            && ent.kind == crate::dirfd_walk::EntryKind::Symlink)
+                            .then(|| log_link_error(ent.path, ent.read_link()))
+                            .flatten();
+                        if ent.kind == crate::dirfd_walk::EntryKind::File || symlink_target.is_some()
+                        {
+                            let is_file = ent.kind == crate::dirfd_walk::EntryKind::File;
+                            let facts = FileFacts {
+                                size: ent.size,
+                                mtime: ent.mtime,
+                                label: line_options
+                                    .security_labels
+                                    .filter(|_| is_file)
+                                    .and_then(|source| ent.label(source)),
+                                fingerprint: is_file
+                                    .then(|| {
+                                        crawl_fingerprint(
+                                            ent.path,
+                                            ent.size,
+                                            Some(ent.key()),
+                                            || ent.open(),
+                                            scan_id,
+                                            (&line_options, plan.hash_policy),
+                                            (&samples2, &cache_stats2),
                                         )
-                                        .unwrap_or_default();
-                                        dt.to_rfc3339()
                                     })
-                                    .unwrap_or_else(|| "1970-01-01T00:00:00Z".to_string());
-
-                                let line = format!(
-                                    "{}\t{}\t{}\t{}\t{}\t{}\n",
-                                    fname,
-                                    ext,
-                                    ent.path().display(),
-                                    size,
-                                    mtime,
-                                    scan_id
-                                );
-                                cnt.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                                let _ = tx.send(line);
+                                    .flatten(),
+                                symlink_target,
+                                ownership: line_options
+                                    .record_ownership
+                                    .then(|| ent.ownership())
+                                    .filter(|_| is_file),
+                            };
+                            let line =
+                                format_tsv_line(ent.path, facts, scan_id, &scan_root, &line_options);
+                            counter2.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                            tree_stats2.record_file(ent.size, ent.mtime);
+                            if let Some(dir_stats) = &dir_stats2 {
+                                dir_stats.record_file(ent.path, ent.size);
                             }
+                            let _ = tx2.send(line);
                         }
+                        ignore::WalkState::Continue
+                    },
+                );
+                root_files.push(counter2.load(std::sync::atomic::Ordering::Relaxed) - before);
+                continue;
+            }
+            let mut builder = ignore::WalkBuilder::new(&plan.root);
+            builder
+                .ignore(false)
+                .hidden(false)
+                .git_ignore(false)
+                .max_depth(max_depth)
+                .threads(adaptive_max.unwrap_or(plan.threads))
+                .same_file_system(!plan.follow_mounts)
+                .follow_links(line_options.follow_symlinks)
+                .overrides(plan.overrides.clone());
+
+            builder.build_parallel().run(|| {
+                let tx = tx2.clone();
+                let cnt = counter2.clone();
+                let tree_stats = tree_stats2.clone();
+                let line_options = line_options.clone();
+                let scan_root = &scan_root;
+                let current_dir = current_dir2.clone();
+                let dir_counts = dir_counts2.clone();
+                let fd_errors = fd_errors2.clone();
+                let scan_errors = scan_errors2.clone();
+                let root = &plan.root;
+                let hash_policy = plan.hash_policy;
+                let dir_stats = dir_stats2.clone();
+                let long_paths = long_paths.clone();
+                let samples = samples2.clone();
+                let cache_stats = cache_stats2.clone();
+                let pause = pause.clone();
+                let gate = gate2.clone();
+                Box::new(move |res| {
+                    pause.wait();
+                    let _pass = gate.as_ref().map(|gate| gate.enter());
+                    let _budget = line_options.io_budget.as_ref().map(|b| b.enter());
+                    if let std::result::Result::Ok(ent) = &res
+                        && ent.file_type().is_some_and(|ft| ft.is_dir())
+                        && let std::result::Result::Ok(mut slot) = current_dir.try_lock()
+                    {
+                        *slot = Some(ent.path().to_path_buf());
                     }
-                }
-                ignore::WalkState::Continue
-            })
-        });
+                    if let std::result::Result::Ok(ent) = &res {
+                        tree_stats.record(ent);
+                    }
+                    if let Some(dir_stats) = &dir_stats
+                        && let std::result::Result::Ok(ent) = &res
+                    {
+                        if ent.file_type().is_some_and(|ft| ft.is_dir()) {
+                            let mtime = ent.metadata().ok().and_then(|meta| meta.modified().ok());
+                            dir_stats.record_dir(ent.path(), mtime);
+                        }
+                        if ent.depth() > 0 {
+                            dir_stats.record_entry(ent.path());
+                        }
+                    }
+                    if let Err(err) = &res {
+                        scan_errors.record_walk(root, err);
+                    }
+                    if let Err(err) = &res
+                        && err.io_error().is_some_and(crate::fd_limit::is_exhausted)
+                        && fd_errors.fetch_add(1, std::sync::atomic::Ordering::Relaxed) == 0
+                    {
+                        tracing::warn!("⚠️ Out of file descriptors, skipping: {}", err);
+                    }
+                    if let Err(err) = &res
+                        && err.io_error().and_then(|e| e.raw_os_error()) == Some(ENAMETOOLONG)
+                        && !long_paths.swap(true, std::sync::atomic::Ordering::Relaxed)
+                    {
+                        tracing::warn!(
+                            "⚠️ Path too long to walk, skipping: {}; --walker dirfd walks such trees",
+                            err
+                        );
+                    }
+                    if count_entries
+                        && let std::result::Result::Ok(ent) = &res
+                        && ent.depth() > 0
+                        && let Some(parent) = ent.path().parent()
+                        && count_entry(&dir_counts, parent, hot_dir_threshold, max_entries_per_dir)
+                    {
+                        return ignore::WalkState::Skip;
+                    }
+                    // a symlink's own metadata, as links are not followed when recorded
+                    if let std::result::Result::Ok(ent) = res
+                        && let Some(ft) = ent.file_type()
+                        && (ft.is_file() || (line_options.record_symlinks && ft.is_symlink()))
+                    {
+                        let meta = match ent.metadata() {
+                            std::result::Result::Ok(meta) => meta,
+                            Err(err) => {
+                                scan_errors.record_walk(ent.path(), &err);
+                                return ignore::WalkState::Continue;
+                            }
+                        };
+                        let symlink_target = ft
+                            .is_symlink()
+                            .then(|| log_link_error(ent.path(), std::fs::read_link(ent.path())));
+                        if symlink_target == Some(None) {
+                            return ignore::WalkState::Continue;
+                        }
+                        let facts = FileFacts {
+                            size: meta.len(),
+                            mtime: meta.modified().ok(),
+                            label: line_options
+                                .security_labels
+                                .filter(|_| ft.is_file())
+                                .and_then(|source| source.read(ent.path())),
+                            fingerprint: ft
+                                .is_file()
+                                .then(|| {
+                                    crawl_fingerprint(
+                                        ent.path(),
+                                        meta.len(),
+                                        file_key(&meta),
+                                        || std::fs::File::open(ent.path()),
+                                        scan_id,
+                                        (&line_options, hash_policy),
+                                        (&samples, &cache_stats),
+                                    )
+                                })
+                                .flatten(),
+                            symlink_target: symlink_target.flatten(),
+                            ownership: ownership(&meta, &line_options)
+                                .filter(|_| ft.is_file()),
+                        };
+                        let line =
+                            format_tsv_line(ent.path(), facts, scan_id, scan_root, &line_options);
+                        cnt.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        tree_stats.record_file(meta.len(), meta.modified().ok());
+                        if let Some(dir_stats) = &dir_stats {
+                            dir_stats.record_file(ent.path(), meta.len());
+                        }
+                        let _ = tx.send(line);
+                    }
+                    ignore::WalkState::Continue
+                })
+            });
+            root_files.push(counter2.load(std::sync::atomic::Ordering::Relaxed) - before);
+        }
 
         // done walking: drop all clones in this thread …
         drop(tx2);
         done2.store(true, std::sync::atomic::Ordering::Relaxed);
+        root_files
     })
     .await?; // wait until the walk really finishes
 
@@ -156,11 +1306,46 @@ pub async fn walk_directory(
     tracing::debug!("🔚 Signaling progress thread to stop...");
     done.store(true, std::sync::atomic::Ordering::Relaxed);
     let _ = stop_tx.send(());
+    drop(tuner_stop_tx);
 
     // 6) wait for both threads to finish
     tracing::debug!("⏳ Waiting for progress and writer threads to finish...");
     let _ = progress_handle.join();
-    let _ = writer_handle.join();
+    let threads = match tuner_handle {
+        Some(handle) => {
+            let chosen = handle
+                .join()
+                .map_err(|_| anyhow::anyhow!("Thread tuner panicked"))?;
+            tracing::info!("🎛️ Settled on {} walker threads", chosen);
+            chosen
+        }
+        None => plans[0].threads,
+    };
+    // joined off the runtime: a streamed crawl's writer waits for the COPY,
+    // which may be driven by this very task
+    let (tsv_sha256, content_sha256) = tokio::task::spawn_blocking(|| writer_handle.join())
+        .await?
+        .map_err(|_| anyhow::anyhow!("TSV writer thread panicked"))??;
+    if let Some(output_tsv_file) = &output_tsv_file {
+        crate::integrity::write_checksum_sidecar(output_tsv_file, &tsv_sha256)?;
+    }
+    let directories_recorded = match (dir_stats, &output_tsv_file) {
+        (Some(dir_stats), Some(output_tsv_file)) => {
+            let dir_stats = std::sync::Arc::into_inner(dir_stats)
+                .ok_or_else(|| anyhow::anyhow!("Directory stats still shared after the walk"))?;
+            let roots: Vec<std::path::PathBuf> =
+                plans.iter().map(|plan| plan.root.clone()).collect();
+            let dirs_tsv_file = crate::dir_stats::dirs_tsv_path(output_tsv_file);
+            let recorded = dir_stats.write_tsv(&dirs_tsv_file, scan_id, &roots, options)?;
+            tracing::info!(
+                "📁 Recorded {} directories to {}",
+                recorded,
+                dirs_tsv_file.display()
+            );
+            Some(recorded)
+        }
+        _ => None,
+    };
 
     // 7) final stats
     let total = counter.load(std::sync::atomic::Ordering::Relaxed) as f64;
@@ -175,10 +1360,66 @@ pub async fn walk_directory(
     );
 
     let mut metadata = std::collections::HashMap::new();
+    // the first root's, like the scan_root the scan is recorded under
+    let first = &plans[0];
     metadata.insert(
         "data_root".to_string(),
-        data_root.to_string_lossy().to_string(),
+        first.root.to_string_lossy().to_string(),
     );
+    if let Some(fs_type) = &first.fs_type {
+        metadata.insert("fs_type".to_string(), fs_type.clone());
+    }
+    metadata.insert("walker_threads".to_string(), threads.to_string());
+    metadata.insert("walker".to_string(), options.walker.to_string());
+    if options.adaptive_threads.is_some() {
+        metadata.insert("walker_threads_adaptive".to_string(), "true".to_string());
+    }
+    metadata.insert("follow_mounts".to_string(), first.follow_mounts.to_string());
+    if options.content_hash.is_some() {
+        metadata.insert("hash_policy".to_string(), first.hash_policy.to_string());
+    }
+    if plans.len() > 1 {
+        let roots: Vec<serde_json::Value> = plans
+            .iter()
+            .zip(&root_files)
+            .map(|(plan, files)| {
+                serde_json::json!({
+                    "root": plan.root,
+                    "fs_type": plan.fs_type,
+                    "follow_mounts": plan.follow_mounts,
+                    "hash_policy": options.content_hash.map(|_| plan.hash_policy.to_string()),
+                    "files": files,
+                })
+            })
+            .collect();
+        metadata.insert(
+            "scan_roots".to_string(),
+            serde_json::Value::from(roots).to_string(),
+        );
+    }
+    for (key, globs) in [
+        ("include_globs", &options.include),
+        ("exclude_globs", &options.exclude),
+    ] {
+        if !globs.is_empty() {
+            metadata.insert(key.to_string(), serde_json::json!(globs).to_string());
+        }
+    }
+    if let Some((directories, elapsed)) = prewarm {
+        metadata.insert("prewarm_directories".to_string(), directories.to_string());
+        metadata.insert("prewarm_duration_s".to_string(), elapsed.to_string());
+    }
+    if let Some(limit) = crate::fd_limit::raise() {
+        metadata.insert("open_files_limit".to_string(), limit.to_string());
+    }
+    let fd_errors = fd_errors.load(std::sync::atomic::Ordering::Relaxed);
+    if fd_errors > 0 {
+        tracing::warn!(
+            "⚠️ {} entries were skipped for want of file descriptors; their files are missing from the crawl",
+            fd_errors
+        );
+        metadata.insert("fd_exhausted_errors".to_string(), fd_errors.to_string());
+    }
     metadata.insert("crawl_timer_duration_s".to_string(), elapsed.to_string());
     metadata.insert("total_files_processed".to_string(), total.to_string());
     metadata.insert(
@@ -186,5 +1427,81 @@ pub async fn walk_directory(
         (total / elapsed).to_string(),
     );
 
-    Ok(metadata)
+    metadata.insert("tsv_sha256".to_string(), tsv_sha256);
+    if let Some(content_sha256) = content_sha256 {
+        metadata.insert("content_sha256".to_string(), content_sha256);
+    }
+
+    tree_stats.insert_into(&mut metadata);
+    if let Some(recorded) = directories_recorded {
+        metadata.insert("directories_recorded".to_string(), recorded.to_string());
+    }
+    if let Some(fraction) = options.sample_fraction {
+        samples.insert_into(fraction, &mut metadata);
+    }
+    if let Some(cache) = options
+        .hash_cache
+        .as_ref()
+        .filter(|_| options.content_hash.is_some())
+    {
+        if let Err(e) = cache.flush() {
+            tracing::warn!(
+                "⚠️ Failed to write the hash cache {}: {:#}",
+                cache.path().display(),
+                e
+            );
+        }
+        cache_stats.insert_into(&mut metadata);
+    }
+
+    // the root paths are encrypted below
+    let stored_root = |path: &std::path::Path| match &options.scan_root {
+        Some(scan_root) => scan_root,
+        None => plans
+            .iter()
+            .map(|plan| &plan.root)
+            .find(|root| path.starts_with(root))
+            .unwrap_or(&first.root),
+    };
+    let mut hot_dirs: Vec<HotDir> = dir_counts
+        .iter()
+        .filter(|e| {
+            let count = *e.value();
+            options.hot_dir_threshold.is_some_and(|t| count > t)
+                || options.max_entries_per_dir.is_some_and(|max| count > max)
+        })
+        .map(|e| HotDir {
+            path: match &options.path_cipher {
+                Some(cipher) => cipher.encrypt_path(stored_root(e.key()), e.key()).into(),
+                None => e.key().clone(),
+            },
+            entry_count: *e.value(),
+            truncated: options
+                .max_entries_per_dir
+                .is_some_and(|max| *e.value() > max),
+        })
+        .collect();
+    hot_dirs.sort_by_key(|d| std::cmp::Reverse(d.entry_count));
+    metadata.insert("hot_dirs_count".to_string(), hot_dirs.len().to_string());
+    metadata.insert(
+        "truncated_dirs_count".to_string(),
+        hot_dirs.iter().filter(|d| d.truncated).count().to_string(),
+    );
+
+    let scan_errors = std::sync::Arc::into_inner(scan_errors)
+        .ok_or_else(|| anyhow::anyhow!("Scan errors still shared after the walk"))?;
+    let mut errors = scan_errors.into_report(&mut metadata);
+    if let Some(cipher) = &options.path_cipher {
+        for error in &mut errors {
+            error.path = cipher
+                .encrypt_path(stored_root(&error.path), &error.path)
+                .into();
+        }
+    }
+
+    Ok(CrawlReport {
+        metadata,
+        hot_dirs,
+        errors,
+    })
 }
@@ -0,0 +1,68 @@
+use base64::Engine;
+
+/// What a cursor token holds: the listing it belongs to and the sort key of
+/// the last row already returned
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Token<K> {
+    listing: String,
+    after: K,
+}
+
+/// Encode the position after `after` in `listing` as an opaque, URL-safe
+/// token that callers hand back unchanged to get the next page
+pub fn encode<K: serde::Serialize>(listing: &str, after: &K) -> String {
+    let token = Token {
+        listing: listing.to_string(),
+        after,
+    };
+    let json = serde_json::to_vec(&token).expect("cursor keys serialize to JSON");
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json)
+}
+
+/// Decode a token made by [`encode`] for the same `listing`
+pub fn decode<K: serde::de::DeserializeOwned>(listing: &str, token: &str) -> anyhow::Result<K> {
+    let invalid = || anyhow::anyhow!("Invalid cursor: {}", token);
+    let json = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(token)
+        .map_err(|_| invalid())?;
+    let parsed: Token<serde_json::Value> = serde_json::from_slice(&json).map_err(|_| invalid())?;
+    anyhow::ensure!(
+        parsed.listing == listing,
+        "Cursor is for {}, not {}",
+        parsed.listing,
+        listing
+    );
+    serde_json::from_value(parsed.after).map_err(|_| invalid())
+}
+
+/// One page of a keyset-paginated listing
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Token for the next page, `None` on the last one
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Build a page from up to `limit + 1` rows fetched in sort order: the
+    /// extra row only tells that there is a next page, keyed on the last row
+    /// kept by `key`
+    pub fn from_rows<K: serde::Serialize>(
+        mut rows: Vec<T>,
+        limit: i64,
+        listing: &str,
+        key: impl Fn(&T) -> K,
+    ) -> Self {
+        let limit = limit.max(0) as usize;
+        let next_cursor = if rows.len() > limit {
+            rows.truncate(limit);
+            rows.last().map(|last| encode(listing, &key(last)))
+        } else {
+            None
+        };
+        Page {
+            items: rows,
+            next_cursor,
+        }
+    }
+}
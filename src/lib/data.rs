@@ -1,110 +1,2548 @@
 use futures::SinkExt;
-use tokio::io::AsyncBufReadExt;
+use tokio::io::{AsyncBufReadExt, AsyncSeekExt};
 
 #[tracing::instrument]
 pub async fn clear_staging(client: &tokio_postgres::Client, scan_id: i32) -> anyhow::Result<()> {
     let query = "DELETE FROM filesystem.staging_files WHERE scan_id = $1";
     client.execute(query, &[&scan_id]).await?;
+    let query = "DELETE FROM filesystem.load_checkpoints WHERE scan_id = $1";
+    client.execute(query, &[&scan_id]).await?;
+    Ok(())
+}
+
+/// Name of this host as recorded on scans and audit entries
+pub fn local_hostname() -> String {
+    hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Append an entry to the immutable filesystem.audit_log, recording who did
+/// what from which host
+#[tracing::instrument(skip(client, details))]
+pub async fn audit(
+    client: &tokio_postgres::Client,
+    action: &str,
+    scan_id: Option<i32>,
+    details: serde_json::Value,
+) -> anyhow::Result<()> {
+    let os_user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    let hostname = local_hostname();
+
+    let query = "
+        INSERT INTO filesystem.audit_log (action, scan_id, os_user, hostname, details)
+        VALUES ($1, $2, $3, $4, $5)";
+    client
+        .execute(query, &[&action, &scan_id, &os_user, &hostname, &details])
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument]
+pub async fn get_files_count_by_change_type(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    change_type: &str,
+) -> anyhow::Result<i64> {
+    let query = "
+        SELECT COUNT(*)
+        FROM filesystem.file_changes
+        WHERE scan_id = $1 AND change_type = $2";
+
+    let row = client.query_one(query, &[&scan_id, &change_type]).await?;
+    let count: i64 = row.get(0);
+    Ok(count)
+}
+
+#[tracing::instrument]
+pub async fn get_file_size_by_change_type(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    change_type: &str,
+) -> anyhow::Result<i64> {
+    let query = "
+        SELECT COALESCE(SUM(ABS(COALESCE(new_size_bytes, 0) - COALESCE(old_size_bytes, 0))), 0)::bigint
+        FROM filesystem.file_changes
+        WHERE scan_id = $1 AND change_type = $2";
+
+    let row = client.query_one(query, &[&scan_id, &change_type]).await?;
+    let size: i64 = row.get(0);
+    Ok(size)
+}
+
+/// Insert a new row into filesystem.scan_runs and return the scan_id. A scan
+/// of several roots is recorded under `data_root`, with all of them in
+/// `scan_roots`.
+#[tracing::instrument(skip(client, started_at))]
+pub async fn start_scan(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::PathBuf,
+    extra_roots: &[std::path::PathBuf],
+    tenant: Option<&str>,
+    path_key_id: Option<&str>,
+    started_at: chrono::DateTime<chrono::Utc>,
+) -> anyhow::Result<i32> {
+    tracing::info!(
+        "Starting scan for root: {} at {}",
+        data_root.display(),
+        started_at
+    );
+    let scan_roots: Option<Vec<String>> = (!extra_roots.is_empty()).then(|| {
+        std::iter::once(data_root)
+            .chain(extra_roots)
+            .map(|root| root.to_string_lossy().into_owned())
+            .collect()
+    });
+    // Construct a insert statement, returning the scan_id
+    let stmt = client
+        .prepare(
+            "INSERT INTO filesystem.scan_runs \
+            (scan_root, scan_roots, tenant, path_key_id, hostname, started_at) \
+            VALUES ($1, $2, $3, $4, $5, $6) RETURNING scan_id",
+        )
+        .await?;
+    let row = client
+        .query_one(
+            &stmt,
+            &[
+                &data_root.to_string_lossy(),
+                &scan_roots,
+                &tenant,
+                &path_key_id,
+                &local_hostname(),
+                &started_at,
+            ],
+        )
+        .await?;
+
+    let scan_id: i32 = row.get(0);
+    tracing::info!("Scan started with ID: {}", scan_id);
+    audit(
+        client,
+        "scan_started",
+        Some(scan_id),
+        match &scan_roots {
+            Some(scan_roots) => serde_json::json!({ "scan_roots": scan_roots }),
+            None => serde_json::json!({ "scan_root": data_root.to_string_lossy() }),
+        },
+    )
+    .await?;
+    Ok(scan_id)
+}
+
+/// Return the total file count of the most recent completed scan of `data_root`
+/// that precedes `scan_id`, if any.
+#[tracing::instrument(skip(client))]
+pub async fn get_previous_files_count(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+    scan_id: i32,
+) -> anyhow::Result<Option<i64>> {
+    let query = "
+        SELECT total_paths_count
+        FROM filesystem.scan_runs
+        WHERE scan_root = $1
+          AND scan_id < $2
+          AND scan_status = 'completed'
+          AND total_paths_count IS NOT NULL
+        ORDER BY scan_id DESC
+        LIMIT 1";
+
+    let row = client
+        .query_opt(query, &[&data_root.to_string_lossy(), &scan_id])
+        .await?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+/// Mark a scan as flagged without processing its deltas, recording the
+/// anomaly that tripped the sanity guard alongside the crawl metadata.
+#[tracing::instrument(skip(client, anomaly, metadata))]
+pub async fn flag_scan(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    anomaly: serde_json::Value,
+    metadata: std::collections::HashMap<String, String>,
+) -> anyhow::Result<()> {
+    let query = "
+        UPDATE filesystem.scan_runs
+        SET finished_at = $1,
+            total_paths_count = $2,
+            scan_status = 'flagged',
+            anomaly_flags = COALESCE(anomaly_flags, '{}'::jsonb) || $3,
+            scan_metadata = $4
+        WHERE scan_id = $5";
+
+    let metadata_json = serde_json::to_value(&metadata)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize metadata: {}", e))?;
+
+    client
+        .execute(
+            query,
+            &[
+                &chrono::Utc::now(),
+                &metadata
+                    .get("total_files_processed")
+                    .unwrap_or(&"0".to_string())
+                    .parse::<i64>()
+                    .unwrap_or(0),
+                &anomaly,
+                &metadata_json,
+                &scan_id,
+            ],
+        )
+        .await?;
+
+    tracing::warn!("🚩 Scan {} flagged: {}", scan_id, anomaly);
+    audit(client, "scan_flagged", Some(scan_id), anomaly).await?;
+    Ok(())
+}
+
+/// Return the status of a scan (`running`, `pending_review`, `completed`, ...)
+#[tracing::instrument(skip(client))]
+pub async fn get_scan_status(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<String> {
+    let query = "SELECT scan_status FROM filesystem.scan_runs WHERE scan_id = $1";
+    let row = client
+        .query_opt(query, &[&scan_id])
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Scan {} not found", scan_id))?;
+    Ok(row.get(0))
+}
+
+/// Pause the crawl of a running scan, or resume it: its crawl workers idle
+/// within seconds, keeping everything crawled so far
+#[tracing::instrument(skip(client))]
+pub async fn set_scan_paused(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    paused: bool,
+) -> anyhow::Result<()> {
+    let query = "
+        UPDATE filesystem.scan_runs
+        SET paused_at = CASE WHEN $2 THEN COALESCE(paused_at, now()) END
+        WHERE scan_id = $1 AND scan_status = 'running'";
+
+    let updated = client.execute(query, &[&scan_id, &paused]).await?;
+    if updated == 0 {
+        let status = get_scan_status(client, scan_id).await?;
+        anyhow::bail!(
+            "Scan {} is {}, only running scans can be paused or resumed",
+            scan_id,
+            status
+        );
+    }
+    audit(
+        client,
+        if paused {
+            "scan_paused"
+        } else {
+            "scan_resumed"
+        },
+        Some(scan_id),
+        serde_json::json!({}),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Whether a scan was paused with [`set_scan_paused`]
+#[tracing::instrument(skip(client))]
+pub async fn is_scan_paused(client: &tokio_postgres::Client, scan_id: i32) -> anyhow::Result<bool> {
+    let query = "SELECT paused_at IS NOT NULL FROM filesystem.scan_runs WHERE scan_id = $1";
+    let row = client
+        .query_opt(query, &[&scan_id])
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Scan {} not found", scan_id))?;
+    Ok(row.get(0))
+}
+
+/// Return the root of a scan and the key its paths are encrypted with, if any
+#[tracing::instrument(skip(client))]
+pub async fn get_scan_root(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<(String, Option<String>)> {
+    let query = "SELECT scan_root, path_key_id FROM filesystem.scan_runs WHERE scan_id = $1";
+    let row = client
+        .query_opt(query, &[&scan_id])
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Scan {} not found", scan_id))?;
+    Ok((row.get(0), row.get(1)))
+}
+
+/// Return the metadata recorded for a scan, or an empty map if none was stored
+#[tracing::instrument(skip(client))]
+pub async fn get_scan_metadata(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<std::collections::HashMap<String, String>> {
+    let query = "SELECT scan_metadata FROM filesystem.scan_runs WHERE scan_id = $1";
+    let row = client
+        .query_opt(query, &[&scan_id])
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Scan {} not found", scan_id))?;
+    let metadata: Option<serde_json::Value> = row.get(0);
+    match metadata {
+        Some(value) => serde_json::from_value(value)
+            .map_err(|e| anyhow::anyhow!("Failed to deserialize metadata: {}", e)),
+        None => Ok(std::collections::HashMap::new()),
+    }
+}
+
+/// Summarize the pending (review mode) deltas of a scan as
+/// `(change_type, file_count, size_mb)` tuples
+#[tracing::instrument(skip(client))]
+pub async fn get_pending_changes_summary(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<Vec<(String, i64, f64)>> {
+    let query = "
+        SELECT change_type,
+               COUNT(*),
+               COALESCE(SUM(ABS(COALESCE(new_size_bytes, 0) - COALESCE(old_size_bytes, 0))), 0)::float8
+                   / 1024.0 / 1024.0
+        FROM filesystem.pending_file_changes
+        WHERE scan_id = $1
+        GROUP BY change_type
+        ORDER BY change_type";
+
+    let rows = client.query(query, &[&scan_id]).await?;
+    Ok(rows
+        .iter()
+        .map(|r| (r.get(0), r.get(1), r.get(2)))
+        .collect())
+}
+
+/// Record the crawl metadata of a scan whose deltas await review,
+/// leaving its staging rows in place for `apply_scan`
+#[tracing::instrument(skip(client, metadata))]
+pub async fn mark_scan_pending_review(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    metadata: std::collections::HashMap<String, String>,
+) -> anyhow::Result<()> {
+    let query = "
+        UPDATE filesystem.scan_runs
+        SET scan_status = 'pending_review',
+            scan_metadata = $1
+        WHERE scan_id = $2";
+
+    let metadata_json = serde_json::to_value(&metadata)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize metadata: {}", e))?;
+    client.execute(query, &[&metadata_json, &scan_id]).await?;
+    audit(
+        client,
+        "scan_pending_review",
+        Some(scan_id),
+        serde_json::json!({}),
+    )
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument]
+pub async fn clear_pending_changes(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<()> {
+    let query = "DELETE FROM filesystem.pending_file_changes WHERE scan_id = $1";
+    client.execute(query, &[&scan_id]).await?;
+    Ok(())
+}
+
+/// Return the IDs of completed scans of the same root newer than `scan_id`
+#[tracing::instrument(skip(client))]
+pub async fn get_newer_completed_scans(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<Vec<i32>> {
+    let query = "
+        SELECT scan_id
+        FROM filesystem.scan_runs
+        WHERE scan_id > $1
+          AND scan_status = 'completed'
+          AND scan_root = (SELECT scan_root FROM filesystem.scan_runs WHERE scan_id = $1)
+        ORDER BY scan_id";
+
+    let rows = client.query(query, &[&scan_id]).await?;
+    Ok(rows.iter().map(|r| r.get(0)).collect())
+}
+
+/// Return the ID of the most recent completed scan of the same root preceding `scan_id`
+#[tracing::instrument(skip(client))]
+pub async fn get_previous_completed_scan(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<Option<i32>> {
+    let query = "
+        SELECT scan_id
+        FROM filesystem.scan_runs
+        WHERE scan_id < $1
+          AND scan_status = 'completed'
+          AND scan_root = (SELECT scan_root FROM filesystem.scan_runs WHERE scan_id = $1)
+        ORDER BY scan_id DESC
+        LIMIT 1";
+
+    let row = client.query_opt(query, &[&scan_id]).await?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+/// The scan_runs row of a scan as JSON, for machine-readable run summaries
+#[tracing::instrument(skip(client))]
+pub async fn get_scan_summary(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<serde_json::Value> {
+    let query = "SELECT to_jsonb(r) FROM filesystem.scan_runs AS r WHERE scan_id = $1";
+    let row = client
+        .query_opt(query, &[&scan_id])
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Scan {} not found", scan_id))?;
+    Ok(row.get(0))
+}
+
+/// Store the crawl metadata of a scan that is still running
+#[tracing::instrument(skip(client, metadata))]
+pub async fn set_scan_metadata(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    metadata: &std::collections::HashMap<String, String>,
+) -> anyhow::Result<()> {
+    let metadata_json = serde_json::to_value(metadata)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize metadata: {}", e))?;
+    let query = "UPDATE filesystem.scan_runs SET scan_metadata = $1 WHERE scan_id = $2";
+    client.execute(query, &[&metadata_json, &scan_id]).await?;
+    Ok(())
+}
+
+/// Add `metadata` to the scan's, overwriting keys it already has
+#[tracing::instrument(skip(client))]
+pub async fn merge_scan_metadata(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    metadata: &std::collections::HashMap<String, String>,
+) -> anyhow::Result<()> {
+    let metadata_json = serde_json::to_value(metadata)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize metadata: {}", e))?;
+    let query = "
+        UPDATE filesystem.scan_runs
+        SET scan_metadata = COALESCE(scan_metadata, '{}'::jsonb) || $1
+        WHERE scan_id = $2";
+    client.execute(query, &[&metadata_json, &scan_id]).await?;
     Ok(())
 }
 
-#[tracing::instrument]
-pub async fn get_files_count_by_change_type(
+/// Last pipeline phase a scan completed, persisted in scan_runs so that an
+/// interrupted scan can be continued after it with `resume_scan`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ScanPhase {
+    /// The crawl TSV file is complete
+    Crawled,
+    /// The crawl is loaded into the shared staging table
+    Loaded,
+    /// The deltas are applied to filesystem.files
+    Processed,
+    /// The scan is completed
+    Finalized,
+}
+
+impl std::str::FromStr for ScanPhase {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "crawled" => Ok(ScanPhase::Crawled),
+            "loaded" => Ok(ScanPhase::Loaded),
+            "processed" => Ok(ScanPhase::Processed),
+            "finalized" => Ok(ScanPhase::Finalized),
+            other => anyhow::bail!("Unknown scan phase: {}", other),
+        }
+    }
+}
+
+impl std::fmt::Display for ScanPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanPhase::Crawled => write!(f, "crawled"),
+            ScanPhase::Loaded => write!(f, "loaded"),
+            ScanPhase::Processed => write!(f, "processed"),
+            ScanPhase::Finalized => write!(f, "finalized"),
+        }
+    }
+}
+
+/// Record that a scan completed `phase`, along with the metadata gathered so
+/// far, which resuming it starts from
+#[tracing::instrument(skip(client, metadata))]
+pub async fn set_scan_phase(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    phase: ScanPhase,
+    metadata: &std::collections::HashMap<String, String>,
+) -> anyhow::Result<()> {
+    let metadata_json = serde_json::to_value(metadata)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize metadata: {}", e))?;
+    let query = "
+        UPDATE filesystem.scan_runs
+        SET scan_phase = $1, scan_metadata = $2
+        WHERE scan_id = $3";
+    client
+        .execute(query, &[&phase.to_string(), &metadata_json, &scan_id])
+        .await?;
+    Ok(())
+}
+
+/// Last phase a scan completed, `None` while it is crawling
+#[tracing::instrument(skip(client))]
+pub async fn get_scan_phase(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<Option<ScanPhase>> {
+    let query = "SELECT scan_phase FROM filesystem.scan_runs WHERE scan_id = $1";
+    let row = client
+        .query_opt(query, &[&scan_id])
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Scan {} not found", scan_id))?;
+    row.get::<_, Option<String>>(0)
+        .map(|phase| phase.parse())
+        .transpose()
+}
+
+/// Whether any deltas were applied for a scan
+#[tracing::instrument(skip(client))]
+pub async fn has_file_changes(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<bool> {
+    let query = "SELECT EXISTS (SELECT 1 FROM filesystem.file_changes WHERE scan_id = $1)";
+    let row = client.query_one(query, &[&scan_id]).await?;
+    Ok(row.get(0))
+}
+
+/// Count the recorded and the pending changes of a scan, as
+/// `(file_changes, pending_file_changes)`
+#[tracing::instrument(skip(client))]
+pub async fn count_scan_changes(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<(u64, u64)> {
+    let query = "
+        SELECT
+            (SELECT count(*) FROM filesystem.file_changes WHERE scan_id = $1),
+            (SELECT count(*) FROM filesystem.pending_file_changes WHERE scan_id = $1)";
+    let row = client.query_one(query, &[&scan_id]).await?;
+    Ok((row.get::<_, i64>(0) as u64, row.get::<_, i64>(1) as u64))
+}
+
+/// Count the scans, current files and recorded changes in the database, as
+/// `(scan_runs, files, file_changes)`; `None` when it was never initialized
+#[tracing::instrument(skip(client))]
+pub async fn count_tracked_rows(
+    client: &tokio_postgres::Client,
+) -> anyhow::Result<Option<(u64, u64, u64)>> {
+    let query = "SELECT to_regclass('filesystem.scan_runs') IS NOT NULL";
+    let initialized: bool = client.query_one(query, &[]).await?.get(0);
+    if !initialized {
+        return Ok(None);
+    }
+    let query = "
+        SELECT
+            (SELECT count(*) FROM filesystem.scan_runs),
+            (SELECT count(*) FROM filesystem.files),
+            (SELECT count(*) FROM filesystem.file_changes)";
+    let row = client.query_one(query, &[]).await?;
+    Ok(Some((
+        row.get::<_, i64>(0) as u64,
+        row.get::<_, i64>(1) as u64,
+        row.get::<_, i64>(2) as u64,
+    )))
+}
+
+/// Store the Merkle root of a scan's change set
+#[tracing::instrument(skip(client))]
+pub async fn set_merkle_root(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    merkle_root: &str,
+) -> anyhow::Result<()> {
+    let query = "UPDATE filesystem.scan_runs SET merkle_root = $1 WHERE scan_id = $2";
+    client.execute(query, &[&merkle_root, &scan_id]).await?;
+    Ok(())
+}
+
+/// Completed scans with a stored Merkle root as `(scan_id, merkle_root)`,
+/// optionally restricted to one scan
+#[tracing::instrument(skip(client))]
+pub async fn get_merkle_roots(
+    client: &tokio_postgres::Client,
+    scan_id: Option<i32>,
+) -> anyhow::Result<Vec<(i32, String)>> {
+    let query = "
+        SELECT scan_id, merkle_root
+        FROM filesystem.scan_runs
+        WHERE merkle_root IS NOT NULL
+          AND scan_status = 'completed'
+          AND ($1::int IS NULL OR scan_id = $1)
+        ORDER BY scan_id";
+    let rows = client.query(query, &[&scan_id]).await?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
+/// ID and path key of the most recent completed scan of a root
+#[tracing::instrument(skip(client))]
+pub async fn get_latest_completed_scan(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+) -> anyhow::Result<Option<(i32, Option<String>)>> {
+    let query = "
+        SELECT scan_id, path_key_id
+        FROM filesystem.scan_runs
+        WHERE scan_root = $1 AND scan_status = 'completed'
+        ORDER BY scan_id DESC
+        LIMIT 1";
+    let row = client
+        .query_opt(query, &[&data_root.to_string_lossy()])
+        .await?;
+    Ok(row.map(|r| (r.get(0), r.get(1))))
+}
+
+/// Walker threads the most recent completed adaptive crawl of a root
+/// settled on, to start the next one from
+#[tracing::instrument(skip(client))]
+pub async fn get_adaptive_walker_threads(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+) -> anyhow::Result<Option<usize>> {
+    let query = "
+        SELECT scan_metadata->>'walker_threads'
+        FROM filesystem.scan_runs
+        WHERE scan_root = $1 AND scan_status = 'completed'
+          AND scan_metadata->>'walker_threads_adaptive' = 'true'
+        ORDER BY scan_id DESC
+        LIMIT 1";
+    let row = client
+        .query_opt(query, &[&data_root.to_string_lossy()])
+        .await?;
+    Ok(row
+        .and_then(|r| r.get::<_, Option<String>>(0))
+        .and_then(|threads| threads.parse().ok()))
+}
+
+/// Start time of the most recent completed scan of a root
+#[tracing::instrument(skip(client))]
+pub async fn get_latest_completed_scan_start(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+) -> anyhow::Result<Option<chrono::DateTime<chrono::Utc>>> {
+    let query = "
+        SELECT MAX(started_at)
+        FROM filesystem.scan_runs
+        WHERE scan_root = $1 AND scan_status = 'completed'";
+    let row = client
+        .query_one(query, &[&data_root.to_string_lossy()])
+        .await?;
+    Ok(row.get(0))
+}
+
+/// Scans started on `hostname` before `started_before` that are still
+/// running, as `(scan_id, scan_root, started_at)`
+#[tracing::instrument(skip(client))]
+pub async fn get_stale_running_scans(
+    client: &tokio_postgres::Client,
+    hostname: &str,
+    started_before: chrono::DateTime<chrono::Utc>,
+) -> anyhow::Result<Vec<(i32, String, chrono::DateTime<chrono::Utc>)>> {
+    let query = "
+        SELECT scan_id, scan_root, started_at
+        FROM filesystem.scan_runs
+        WHERE scan_status = 'running'
+          AND hostname = $1
+          AND started_at < $2
+        ORDER BY scan_id";
+    let rows = client.query(query, &[&hostname, &started_before]).await?;
+    Ok(rows
+        .iter()
+        .map(|r| (r.get(0), r.get(1), r.get(2)))
+        .collect())
+}
+
+/// Staging rows left behind by scans that are no longer running, as
+/// `(scan_id, row_count)`
+#[tracing::instrument(skip(client))]
+pub async fn get_orphan_staging_rows(
+    client: &tokio_postgres::Client,
+) -> anyhow::Result<Vec<(i32, i64)>> {
+    let query = "
+        SELECT s.scan_id, COUNT(*)
+        FROM filesystem.staging_files AS s
+        JOIN filesystem.scan_runs AS r USING (scan_id)
+        WHERE r.scan_status <> 'running'
+        GROUP BY s.scan_id
+        ORDER BY s.scan_id";
+    let rows = client.query(query, &[]).await?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
+/// Mark a scan as voided, e.g. a flagged scan or one discarded during review
+#[tracing::instrument]
+pub async fn void_scan(client: &tokio_postgres::Client, scan_id: i32) -> anyhow::Result<()> {
+    let query = "UPDATE filesystem.scan_runs SET scan_status = 'voided' WHERE scan_id = $1";
+    client.execute(query, &[&scan_id]).await?;
+    audit(client, "scan_voided", Some(scan_id), serde_json::json!({})).await?;
+    Ok(())
+}
+
+/// Mark a scan that was cut short without applying anything as `aborted`,
+/// recording `reason` (e.g. `{"timed_out": ...}`) in its anomaly flags
+#[tracing::instrument(skip(client))]
+pub async fn abort_scan(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    reason: serde_json::Value,
+) -> anyhow::Result<()> {
+    let query = "
+        UPDATE filesystem.scan_runs
+        SET finished_at = now(),
+            scan_status = 'aborted',
+            anomaly_flags = COALESCE(anomaly_flags, '{}'::jsonb) || $1
+        WHERE scan_id = $2";
+    client.execute(query, &[&reason, &scan_id]).await?;
+    audit(client, "scan_aborted", Some(scan_id), reason).await?;
+    Ok(())
+}
+
+/// Return the `limit` largest files added by a scan as `(file_path, size_bytes)`,
+/// from its pending (review mode) deltas if `pending` is set
+#[tracing::instrument(skip(client))]
+pub async fn get_largest_added_files(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    limit: i64,
+    pending: bool,
+) -> anyhow::Result<Vec<(String, i64)>> {
+    let table = if pending {
+        "filesystem.pending_file_changes"
+    } else {
+        "filesystem.file_changes"
+    };
+    let query = format!(
+        "
+        SELECT file_path, new_size_bytes
+        FROM {}
+        WHERE scan_id = $1 AND change_type = 'added'
+        ORDER BY new_size_bytes DESC, file_path
+        LIMIT $2",
+        table
+    );
+
+    let rows = client.query(&query, &[&scan_id, &limit]).await?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
+/// A delta budget exceeded by the scans of a root within its window
+#[derive(Debug, Clone)]
+pub struct BudgetViolation {
+    pub metric: String,
+    pub max_value: f64,
+    pub window_days: i32,
+    pub actual: f64,
+}
+
+/// Compare the deltas accumulated by the completed scans of the scan's root
+/// against its budgets, recording any violation in the scan's anomaly flags
+#[tracing::instrument(skip(client))]
+pub async fn check_delta_budgets(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<Vec<BudgetViolation>> {
+    let query = "
+        WITH totals AS (
+            SELECT b.metric, b.max_value, b.window_days,
+                   COALESCE(SUM(
+                       CASE b.metric
+                           WHEN 'new_data_mb' THEN r.new_data_mb
+                           WHEN 'modified_data_mb' THEN r.modified_data_mb
+                           WHEN 'deleted_data_mb' THEN r.deleted_data_mb
+                           WHEN 'added_files_count' THEN r.added_files_count::float8
+                           WHEN 'modified_files_count' THEN r.modified_files_count::float8
+                           WHEN 'removed_files_count' THEN r.removed_files_count::float8
+                       END
+                   ), 0)::float8 AS actual
+            FROM filesystem.scan_runs AS cur
+            JOIN filesystem.delta_budgets AS b ON b.scan_root = cur.scan_root
+            JOIN filesystem.scan_runs AS r
+              ON r.scan_root = cur.scan_root
+             AND r.scan_status = 'completed'
+             AND r.started_at > cur.started_at - make_interval(days => b.window_days)
+             AND r.started_at <= cur.started_at
+            WHERE cur.scan_id = $1
+            GROUP BY b.metric, b.max_value, b.window_days
+        )
+        SELECT metric, max_value, window_days, actual
+        FROM totals
+        WHERE actual > max_value
+        ORDER BY metric";
+
+    let rows = client.query(query, &[&scan_id]).await?;
+    let violations: Vec<BudgetViolation> = rows
+        .iter()
+        .map(|r| BudgetViolation {
+            metric: r.get(0),
+            max_value: r.get(1),
+            window_days: r.get(2),
+            actual: r.get(3),
+        })
+        .collect();
+    if violations.is_empty() {
+        return Ok(violations);
+    }
+
+    for v in &violations {
+        tracing::error!(
+            "🚨 Budget exceeded: {} = {:.2} over the last {} days (limit {:.2})",
+            v.metric,
+            v.actual,
+            v.window_days,
+            v.max_value
+        );
+    }
+    let flags: Vec<serde_json::Value> = violations
+        .iter()
+        .map(|v| {
+            serde_json::json!({
+                "metric": v.metric,
+                "max_value": v.max_value,
+                "window_days": v.window_days,
+                "actual": v.actual,
+            })
+        })
+        .collect();
+    let anomaly = serde_json::json!({ "budget_exceeded": flags });
+    client
+        .execute(
+            "UPDATE filesystem.scan_runs \
+            SET anomaly_flags = COALESCE(anomaly_flags, '{}'::jsonb) || $1 \
+            WHERE scan_id = $2",
+            &[&anomaly, &scan_id],
+        )
+        .await?;
+    Ok(violations)
+}
+
+/// A file whose contents changed while its size and mtime stayed the same,
+/// the signature of silent corruption
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BitrotFile {
+    pub file_path: String,
+    pub size_bytes: i64,
+    pub mtime: chrono::DateTime<chrono::Utc>,
+    pub old_fingerprint: String,
+    pub new_fingerprint: String,
+}
+
+/// The change table of a scan: its pending (review mode) deltas if
+/// `pending` is set
+fn changes_table(pending: bool) -> &'static str {
+    if pending {
+        "filesystem.pending_file_changes"
+    } else {
+        "filesystem.file_changes"
+    }
+}
+
+/// Condition on the modifications of a change table (`c`) that are bit rot:
+/// another fingerprint of the same algorithm, size and mtime kept
+const BITROT_CONDITION: &str = "c.change_type = 'modified'
+    AND c.old_size_bytes = c.new_size_bytes
+    AND c.old_mtime = c.new_mtime
+    AND split_part(c.old_fingerprint, ':', 1) = split_part(c.new_fingerprint, ':', 1)
+    AND c.old_fingerprint <> c.new_fingerprint";
+
+/// The files a scan found bit-rotted, by path; from its pending (review
+/// mode) deltas if `pending` is set
+#[tracing::instrument(skip(client))]
+pub async fn get_bitrot_files(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    pending: bool,
+) -> anyhow::Result<Vec<BitrotFile>> {
+    let table = changes_table(pending);
+    let query = format!(
+        "SELECT c.file_path, c.new_size_bytes, c.new_mtime, c.old_fingerprint, c.new_fingerprint
+         FROM {} AS c
+         WHERE c.scan_id = $1 AND {}
+         ORDER BY c.file_path",
+        table, BITROT_CONDITION
+    );
+    let rows = client.query(&query, &[&scan_id]).await?;
+    Ok(rows
+        .iter()
+        .map(|r| BitrotFile {
+            file_path: r.get(0),
+            size_bytes: r.get(1),
+            mtime: r.get(2),
+            old_fingerprint: r.get(3),
+            new_fingerprint: r.get(4),
+        })
+        .collect())
+}
+
+/// Count the files a scan found bit-rotted (see [`get_bitrot_files`]),
+/// recording the count in its anomaly flags if there are any
+#[tracing::instrument(skip(client))]
+pub async fn flag_bitrot(client: &tokio_postgres::Client, scan_id: i32) -> anyhow::Result<i64> {
+    let query = format!(
+        "SELECT COUNT(*) FROM filesystem.file_changes AS c WHERE c.scan_id = $1 AND {}",
+        BITROT_CONDITION
+    );
+    let count: i64 = client.query_one(&query, &[&scan_id]).await?.get(0);
+    if count == 0 {
+        return Ok(0);
+    }
+    tracing::error!(
+        "🧪 {} files changed contents with size and mtime intact, see `report bitrot --scan-id {}`",
+        count,
+        scan_id
+    );
+    let anomaly = serde_json::json!({ "bitrot_files": count });
+    client
+        .execute(
+            "UPDATE filesystem.scan_runs \
+            SET anomaly_flags = COALESCE(anomaly_flags, '{}'::jsonb) || $1 \
+            WHERE scan_id = $2",
+            &[&anomaly, &scan_id],
+        )
+        .await?;
+    Ok(count)
+}
+
+/// An added file whose contents the tracked files already held elsewhere
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DuplicateFile {
+    pub file_path: String,
+    pub size_bytes: i64,
+    pub fingerprint: String,
+    /// tracked files with the same contents, not added by the scan
+    pub copies: i64,
+    /// the first of them by path
+    pub existing_path: String,
+}
+
+/// How much of what a scan added was copies of content tracked before
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct DuplicateSummary {
+    pub added_files: i64,
+    pub added_bytes: i64,
+    /// added files with a fingerprint, the only ones that can be matched
+    pub hashed_files: i64,
+    pub duplicate_files: i64,
+    pub duplicate_bytes: i64,
+}
+
+/// Added files of a change table (`c`) with the tracked files (`f`) holding
+/// the same contents under another path, other than those added alongside
+fn duplicates_query(table: &str, select: &str) -> String {
+    format!(
+        "SELECT {select}
+         FROM {table} AS c
+         CROSS JOIN LATERAL (
+             SELECT COUNT(*) AS copies, MIN(f.file_path) AS existing_path
+             FROM filesystem.files AS f
+             WHERE f.file_fingerprint = c.new_fingerprint
+               AND f.file_size_bytes = c.new_size_bytes
+               AND f.file_path <> c.file_path
+               AND NOT EXISTS (
+                   SELECT 1 FROM {table} AS a
+                   WHERE a.scan_id = c.scan_id
+                     AND a.change_type = 'added'
+                     AND a.file_path = f.file_path
+               )
+         ) AS d
+         WHERE c.scan_id = $1
+           AND c.change_type = 'added'
+           AND c.new_fingerprint IS NOT NULL
+           AND d.copies > 0"
+    )
+}
+
+/// The `limit` largest files a scan added that duplicate content tracked
+/// elsewhere, matched by fingerprint and size
+#[tracing::instrument(skip(client))]
+pub async fn get_duplicate_files(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    pending: bool,
+    limit: i64,
+) -> anyhow::Result<Vec<DuplicateFile>> {
+    let query = format!(
+        "{} ORDER BY c.new_size_bytes DESC, c.file_path LIMIT $2",
+        duplicates_query(
+            changes_table(pending),
+            "c.file_path, c.new_size_bytes, c.new_fingerprint, d.copies, d.existing_path"
+        )
+    );
+    let rows = client.query(&query, &[&scan_id, &limit]).await?;
+    Ok(rows
+        .iter()
+        .map(|r| DuplicateFile {
+            file_path: r.get(0),
+            size_bytes: r.get(1),
+            fingerprint: r.get(2),
+            copies: r.get(3),
+            existing_path: r.get(4),
+        })
+        .collect())
+}
+
+/// Totals of [`get_duplicate_files`] against everything the scan added
+#[tracing::instrument(skip(client))]
+pub async fn get_duplicate_summary(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    pending: bool,
+) -> anyhow::Result<DuplicateSummary> {
+    let table = changes_table(pending);
+    let added = client
+        .query_one(
+            &format!(
+                "SELECT COUNT(*), COALESCE(SUM(new_size_bytes), 0)::BIGINT,
+                    COUNT(new_fingerprint)
+                 FROM {} WHERE scan_id = $1 AND change_type = 'added'",
+                table
+            ),
+            &[&scan_id],
+        )
+        .await?;
+    let duplicates = client
+        .query_one(
+            &duplicates_query(
+                table,
+                "COUNT(*), COALESCE(SUM(c.new_size_bytes), 0)::BIGINT",
+            ),
+            &[&scan_id],
+        )
+        .await?;
+    Ok(DuplicateSummary {
+        added_files: added.get(0),
+        added_bytes: added.get(1),
+        hashed_files: added.get(2),
+        duplicate_files: duplicates.get(0),
+        duplicate_bytes: duplicates.get(1),
+    })
+}
+
+/// COPY the directories TSV of a crawl (see `dir_stats`) into
+/// `filesystem.staging_dirs`, in one go; the number of directories
+#[tracing::instrument(skip(client))]
+pub async fn load_dirs_tsv_file(
+    client: &tokio_postgres::Client,
+    dirs_tsv_file: &std::path::Path,
+) -> anyhow::Result<u64> {
+    let contents = tokio::fs::read(dirs_tsv_file)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", dirs_tsv_file.display(), e))?;
+    let sink = client
+        .copy_in(&format!(
+            "COPY filesystem.staging_dirs(
+                dir_path, entry_count, file_count, total_size_bytes, dir_mtime, scan_id
+            )
+            FROM STDIN
+            WITH ({})",
+            crate::tsv::COPY_OPTIONS
+        ))
+        .await?;
+    let mut sink: CopySink = Box::pin(sink);
+    sink.send(std::io::Cursor::new(contents)).await?;
+    Ok(sink.as_mut().finish().await?)
+}
+
+/// How a directory changed in a scan (`--record-dirs`)
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DirGrowth {
+    pub dir_path: String,
+    pub change_type: String,
+    pub old_size_bytes: Option<i64>,
+    pub new_size_bytes: Option<i64>,
+    /// `new_size_bytes - old_size_bytes`, absent sizes counting as 0
+    pub size_delta_bytes: i64,
+    pub old_file_count: Option<i64>,
+    pub new_file_count: Option<i64>,
+    pub old_entry_count: Option<i64>,
+    pub new_entry_count: Option<i64>,
+}
+
+/// The `limit` directories that grew the most in a scan, by bytes
+#[tracing::instrument(skip(client))]
+pub async fn get_dir_growth(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    limit: i64,
+) -> anyhow::Result<Vec<DirGrowth>> {
+    let rows = client
+        .query(
+            "SELECT dir_path, change_type, old_size_bytes, new_size_bytes,
+                COALESCE(new_size_bytes, 0) - COALESCE(old_size_bytes, 0) AS size_delta_bytes,
+                old_file_count, new_file_count, old_entry_count, new_entry_count
+             FROM filesystem.dir_changes
+             WHERE scan_id = $1
+             ORDER BY size_delta_bytes DESC, dir_path
+             LIMIT $2",
+            &[&scan_id, &limit],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|r| DirGrowth {
+            dir_path: r.get(0),
+            change_type: r.get(1),
+            old_size_bytes: r.get(2),
+            new_size_bytes: r.get(3),
+            size_delta_bytes: r.get(4),
+            old_file_count: r.get(5),
+            new_file_count: r.get(6),
+            old_entry_count: r.get(7),
+            new_entry_count: r.get(8),
+        })
+        .collect())
+}
+
+/// Create or replace the budget of `metric` for a root
+#[tracing::instrument(skip(client))]
+pub async fn set_delta_budget(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+    metric: &str,
+    max_value: f64,
+    window_days: i32,
+) -> anyhow::Result<()> {
+    let query = "
+        INSERT INTO filesystem.delta_budgets (scan_root, metric, max_value, window_days)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (scan_root, metric) DO UPDATE
+        SET max_value = EXCLUDED.max_value, window_days = EXCLUDED.window_days";
+    client
+        .execute(
+            query,
+            &[
+                &data_root.to_string_lossy(),
+                &metric,
+                &max_value,
+                &window_days,
+            ],
+        )
+        .await?;
+    audit(
+        client,
+        "budget_set",
+        None,
+        serde_json::json!({
+            "scan_root": data_root.to_string_lossy(),
+            "metric": metric,
+            "max_value": max_value,
+            "window_days": window_days,
+        }),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Remove the budget of `metric` for a root
+#[tracing::instrument(skip(client))]
+pub async fn remove_delta_budget(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+    metric: &str,
+) -> anyhow::Result<()> {
+    let query = "DELETE FROM filesystem.delta_budgets WHERE scan_root = $1 AND metric = $2";
+    client
+        .execute(query, &[&data_root.to_string_lossy(), &metric])
+        .await?;
+    audit(
+        client,
+        "budget_removed",
+        None,
+        serde_json::json!({ "scan_root": data_root.to_string_lossy(), "metric": metric }),
+    )
+    .await?;
+    Ok(())
+}
+
+/// List the budgets of a root as `(metric, max_value, window_days)`
+#[tracing::instrument(skip(client))]
+pub async fn list_delta_budgets(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+) -> anyhow::Result<Vec<(String, f64, i32)>> {
+    let query = "
+        SELECT metric, max_value, window_days
+        FROM filesystem.delta_budgets
+        WHERE scan_root = $1
+        ORDER BY metric";
+    let rows = client.query(query, &[&data_root.to_string_lossy()]).await?;
+    Ok(rows
+        .iter()
+        .map(|r| (r.get(0), r.get(1), r.get(2)))
+        .collect())
+}
+
+/// Replace the directory -> owner mapping used for quota attribution
+#[tracing::instrument(skip(client, owners))]
+pub async fn replace_directory_owners(
+    client: &tokio_postgres::Client,
+    owners: &std::collections::BTreeMap<String, String>,
+) -> anyhow::Result<u64> {
+    let prefixes: Vec<&String> = owners.keys().collect();
+    let names: Vec<&String> = owners.values().collect();
+    // Single statement, so readers never observe a half-loaded mapping
+    let query = "
+        WITH upserted AS (
+            INSERT INTO filesystem.directory_owners (dir_prefix, owner)
+            SELECT * FROM unnest($1::text[], $2::text[])
+            ON CONFLICT (dir_prefix) DO UPDATE SET owner = EXCLUDED.owner
+        )
+        DELETE FROM filesystem.directory_owners
+        WHERE dir_prefix <> ALL($1::text[])";
+    client.execute(query, &[&prefixes, &names]).await?;
+    audit(client, "owners_loaded", None, serde_json::to_value(owners)?).await?;
+    Ok(owners.len() as u64)
+}
+
+/// Growth attributed to one owner by a scan
+#[derive(Debug, Clone)]
+pub struct OwnerGrowth {
+    pub owner: String,
+    pub added_files_count: i64,
+    pub modified_files_count: i64,
+    pub removed_files_count: i64,
+    pub net_change_bytes: i64,
+}
+
+/// Attribute the changes of a scan to owners, largest net growth first
+#[tracing::instrument(skip(client))]
+pub async fn get_owner_growth(
     client: &tokio_postgres::Client,
     scan_id: i32,
-    change_type: &str,
-) -> anyhow::Result<i64> {
+) -> anyhow::Result<Vec<OwnerGrowth>> {
     let query = "
-        SELECT COUNT(*)
-        FROM filesystem.file_changes
-        WHERE scan_id = $1 AND change_type = $2";
+        SELECT filesystem.path_owner(c.file_path, r.scan_root) AS owner,
+               COUNT(*) FILTER (WHERE c.change_type = 'added'),
+               COUNT(*) FILTER (WHERE c.change_type = 'modified'),
+               COUNT(*) FILTER (WHERE c.change_type = 'deleted'),
+               COALESCE(SUM(COALESCE(c.new_size_bytes, 0) - COALESCE(c.old_size_bytes, 0)), 0)::bigint
+        FROM filesystem.file_changes AS c
+        JOIN filesystem.scan_runs AS r ON r.scan_id = c.scan_id
+        WHERE c.scan_id = $1
+        GROUP BY 1
+        ORDER BY 5 DESC, 1";
 
-    let row = client.query_one(query, &[&scan_id, &change_type]).await?;
-    let count: i64 = row.get(0);
-    Ok(count)
+    let rows = client.query(query, &[&scan_id]).await?;
+    Ok(rows
+        .iter()
+        .map(|r| OwnerGrowth {
+            owner: r.get(0),
+            added_files_count: r.get(1),
+            modified_files_count: r.get(2),
+            removed_files_count: r.get(3),
+            net_change_bytes: r.get(4),
+        })
+        .collect())
 }
 
-#[tracing::instrument]
-pub async fn get_file_size_by_change_type(
+/// Append an operator note to a scan
+#[tracing::instrument(skip(client))]
+pub async fn annotate_scan(
     client: &tokio_postgres::Client,
     scan_id: i32,
-    change_type: &str,
-) -> anyhow::Result<i64> {
+    note: &str,
+) -> anyhow::Result<()> {
     let query = "
-        SELECT COALESCE(SUM(ABS(COALESCE(new_size_bytes, 0) - COALESCE(old_size_bytes, 0))), 0)::bigint
-        FROM filesystem.file_changes
-        WHERE scan_id = $1 AND change_type = $2";
+        UPDATE filesystem.scan_runs
+        SET notes = array_append(notes, $1)
+        WHERE scan_id = $2";
 
-    let row = client.query_one(query, &[&scan_id, &change_type]).await?;
-    let size: i64 = row.get(0);
-    Ok(size)
+    let updated = client.execute(query, &[&note, &scan_id]).await?;
+    if updated == 0 {
+        anyhow::bail!("Scan {} not found", scan_id);
+    }
+    audit(
+        client,
+        "scan_annotated",
+        Some(scan_id),
+        serde_json::json!({ "note": note }),
+    )
+    .await?;
+    Ok(())
 }
 
-/// Insert a new row into filesystem.scan_runs and return the scan_id
-#[tracing::instrument(skip(client, started_at))]
-pub async fn start_scan(
+/// Roots overlapping `data_root` (the root itself, an ancestor or a
+/// descendant) and the tenant they belong to, from their most recent
+/// tenanted scan that was not voided, as `(scan_root, tenant)`
+#[tracing::instrument(skip(client))]
+pub async fn get_overlapping_root_tenants(
     client: &tokio_postgres::Client,
-    data_root: &std::path::PathBuf,
-    started_at: chrono::DateTime<chrono::Utc>,
-) -> anyhow::Result<i32> {
-    tracing::info!(
-        "Starting scan for root: {} at {}",
-        data_root.display(),
-        started_at
+    data_root: &std::path::Path,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let query = "
+        SELECT DISTINCT ON (scan_root) scan_root, tenant
+        FROM filesystem.scan_runs
+        WHERE (scan_root = $1 OR $1 LIKE scan_root || '/%' OR scan_root LIKE $1 || '/%')
+          AND tenant IS NOT NULL
+          AND scan_status <> 'voided'
+        ORDER BY scan_root, scan_id DESC";
+    let rows = client.query(query, &[&data_root.to_string_lossy()]).await?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
+/// Roots overlapping `data_root` and the key their paths are encrypted with
+/// (`None` if plain), from their most recent scan that was not voided, as
+/// `(scan_root, path_key_id)`
+#[tracing::instrument(skip(client))]
+pub async fn get_overlapping_root_path_keys(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+) -> anyhow::Result<Vec<(String, Option<String>)>> {
+    let query = "
+        SELECT DISTINCT ON (scan_root) scan_root, path_key_id
+        FROM filesystem.scan_runs
+        WHERE (scan_root = $1 OR $1 LIKE scan_root || '/%' OR scan_root LIKE $1 || '/%')
+          AND scan_status <> 'voided'
+        ORDER BY scan_root, scan_id DESC";
+    let rows = client.query(query, &[&data_root.to_string_lossy()]).await?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
+/// A row of filesystem.scan_runs, as shown by `list_scans`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ScanRun {
+    pub scan_id: i32,
+    pub scan_root: String,
+    pub tenant: Option<String>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub scan_status: String,
+    pub total_paths_count: Option<i64>,
+    pub added_files_count: Option<i64>,
+    pub modified_files_count: Option<i64>,
+    pub removed_files_count: Option<i64>,
+    pub notes: Vec<String>,
+}
+
+/// List scans newest first, optionally restricted to one root or tenant, a
+/// page at a time; pass the previous page's `next_cursor` as `cursor` for the
+/// next one
+#[tracing::instrument(skip(client))]
+pub async fn list_scans(
+    client: &tokio_postgres::Client,
+    data_root: Option<&std::path::Path>,
+    tenant: Option<&str>,
+    cursor: Option<&str>,
+    limit: i64,
+) -> anyhow::Result<crate::cursor::Page<ScanRun>> {
+    let query = "
+        SELECT scan_id, scan_root, tenant, started_at, finished_at, scan_status,
+               total_paths_count, added_files_count, modified_files_count,
+               removed_files_count, notes
+        FROM filesystem.scan_runs
+        WHERE ($1::text IS NULL OR scan_root = $1)
+          AND ($2::text IS NULL OR tenant = $2)
+          AND ($3::int IS NULL OR scan_id < $3)
+        ORDER BY scan_id DESC
+        LIMIT $4";
+
+    let data_root = data_root.map(|p| p.to_string_lossy().to_string());
+    let before: Option<i32> = cursor
+        .map(|c| crate::cursor::decode("scans", c))
+        .transpose()?;
+    let rows = client
+        .query(query, &[&data_root, &tenant, &before, &(limit + 1)])
+        .await?;
+    let scans = rows
+        .iter()
+        .map(|r| ScanRun {
+            scan_id: r.get(0),
+            scan_root: r.get(1),
+            tenant: r.get(2),
+            started_at: r.get(3),
+            finished_at: r.get(4),
+            scan_status: r.get(5),
+            total_paths_count: r.get(6),
+            added_files_count: r.get(7),
+            modified_files_count: r.get(8),
+            removed_files_count: r.get(9),
+            notes: r.get(10),
+        })
+        .collect();
+    Ok(crate::cursor::Page::from_rows(scans, limit, "scans", |s| {
+        s.scan_id
+    }))
+}
+
+/// A row of filesystem.files, as shown by `search`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FileMatch {
+    pub file_path: String,
+    pub file_type: String,
+    pub file_size_bytes: i64,
+    pub file_mtime: chrono::DateTime<chrono::Utc>,
+}
+
+/// Restrictions on the files returned by [`search_paths`]; unset fields match
+/// everything
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilters {
+    /// Match the pattern case-insensitively (ILIKE)
+    pub ignore_case: bool,
+    /// Only files under this root
+    pub data_root: Option<std::path::PathBuf>,
+    /// Only files last seen by a scan of this tenant
+    pub tenant: Option<String>,
+    /// Only files of these types (extensions, as recorded by the crawler)
+    pub extensions: Vec<String>,
+    pub min_size_bytes: Option<i64>,
+    pub max_size_bytes: Option<i64>,
+    /// Only files modified at or after this time
+    pub modified_after: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only files modified before this time
+    pub modified_before: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Whether `text` matches the SQL LIKE `pattern` (`%`, `_`, `\` escapes)
+fn like_match(pattern: &str, text: &str, ignore_case: bool) -> bool {
+    let fold = |s: &str| -> Vec<char> {
+        if ignore_case {
+            s.to_lowercase().chars().collect()
+        } else {
+            s.chars().collect()
+        }
+    };
+    let (pattern, text) = (fold(pattern), fold(text));
+    // position in the pattern after the last `%` and in the text it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('%') => {
+                p += 1;
+                backtrack = Some((p, t));
+                continue;
+            }
+            Some('_') => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            Some('\\') if pattern.get(p + 1) == Some(&text[t]) => {
+                p += 2;
+                t += 1;
+                continue;
+            }
+            Some(c) if *c != '\\' && *c == text[t] => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match backtrack {
+            Some((bp, bt)) => {
+                p = bp;
+                t = bt + 1;
+                backtrack = Some((bp, bt + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '%')
+}
+
+/// Find current files whose path matches the LIKE `pattern` and `filters`,
+/// ordered by path, a page at a time. Pages are keyed on the path, so paging
+/// stays stable while files change.
+///
+/// With a `cipher`, encrypted paths are decrypted, and the pattern and the
+/// root filter are matched against the decrypted paths here rather than in
+/// the database, which reads every file passing the other filters. Pages are
+/// then ordered by the recorded (encrypted) path.
+#[tracing::instrument(skip(client, cipher))]
+pub async fn search_paths(
+    client: &tokio_postgres::Client,
+    pattern: &str,
+    filters: &SearchFilters,
+    cipher: Option<&crate::path_cipher::PathCipher>,
+    cursor: Option<&str>,
+    limit: i64,
+) -> anyhow::Result<crate::cursor::Page<FileMatch>> {
+    if let Some(cipher) = cipher {
+        return search_encrypted_paths(client, pattern, filters, cipher, cursor, limit).await;
+    }
+    let query = format!(
+        "
+        SELECT file_path, file_type, file_size_bytes, file_mtime
+        FROM filesystem.files
+        WHERE file_path {} $1
+          AND ($2::text IS NULL OR file_path LIKE $2 || '/%')
+          AND (cardinality($3::text[]) = 0 OR file_type = ANY($3))
+          AND ($4::bigint IS NULL OR file_size_bytes >= $4)
+          AND ($5::bigint IS NULL OR file_size_bytes <= $5)
+          AND ($6::timestamptz IS NULL OR file_mtime >= $6)
+          AND ($7::timestamptz IS NULL OR file_mtime < $7)
+          AND ($8::text IS NULL OR file_path > $8)
+          AND ($9::text IS NULL OR last_seen_scan IN (
+              SELECT scan_id FROM filesystem.scan_runs WHERE tenant = $9
+          ))
+        ORDER BY file_path
+        LIMIT $10",
+        if filters.ignore_case { "ILIKE" } else { "LIKE" }
     );
-    // Construct a insert statement, returning the scan_id
+
+    let data_root = filters
+        .data_root
+        .as_ref()
+        .map(|p| p.to_string_lossy().to_string());
+    let after: Option<String> = cursor
+        .map(|c| crate::cursor::decode("files", c))
+        .transpose()?;
+    let rows = client
+        .query(
+            &query,
+            &[
+                &pattern,
+                &data_root,
+                &filters.extensions,
+                &filters.min_size_bytes,
+                &filters.max_size_bytes,
+                &filters.modified_after,
+                &filters.modified_before,
+                &after,
+                &filters.tenant,
+                &(limit + 1),
+            ],
+        )
+        .await?;
+    let files = rows
+        .iter()
+        .map(|r| FileMatch {
+            file_path: r.get(0),
+            file_type: r.get(1),
+            file_size_bytes: r.get(2),
+            file_mtime: r.get(3),
+        })
+        .collect();
+    Ok(crate::cursor::Page::from_rows(files, limit, "files", |f| {
+        f.file_path.clone()
+    }))
+}
+
+/// [`search_paths`] over encrypted paths: fetch the files passing the other
+/// filters in chunks and match their decrypted paths until the page is full
+async fn search_encrypted_paths(
+    client: &tokio_postgres::Client,
+    pattern: &str,
+    filters: &SearchFilters,
+    cipher: &crate::path_cipher::PathCipher,
+    cursor: Option<&str>,
+    limit: i64,
+) -> anyhow::Result<crate::cursor::Page<FileMatch>> {
+    const CHUNK: i64 = 10_000;
+    let root_pattern = filters.data_root.as_ref().map(|root| {
+        let root = root.to_string_lossy();
+        format!(
+            "{}/%",
+            root.trim_end_matches('/')
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        )
+    });
+    let unfiltered = SearchFilters {
+        ignore_case: false,
+        data_root: None,
+        ..filters.clone()
+    };
+
+    // (recorded path, file with its path decrypted)
+    let mut matches: Vec<(String, FileMatch)> = Vec::new();
+    let mut after: Option<String> = cursor
+        .map(|c| crate::cursor::decode("files", c))
+        .transpose()?;
+    while matches.len() as i64 <= limit {
+        let chunk = Box::pin(search_paths(
+            client,
+            "%",
+            &unfiltered,
+            None,
+            after
+                .as_ref()
+                .map(|a| crate::cursor::encode("files", a))
+                .as_deref(),
+            CHUNK,
+        ))
+        .await?;
+        let Some(last) = chunk.items.last() else {
+            break;
+        };
+        after = Some(last.file_path.clone());
+        let exhausted = chunk.next_cursor.is_none();
+        for file in chunk.items {
+            let decrypted = cipher.decrypt_path(&file.file_path);
+            if like_match(pattern, &decrypted, filters.ignore_case)
+                && root_pattern
+                    .as_ref()
+                    .is_none_or(|root| like_match(root, &decrypted, false))
+            {
+                let stored = file.file_path.clone();
+                matches.push((
+                    stored,
+                    FileMatch {
+                        file_path: decrypted,
+                        ..file
+                    },
+                ));
+            }
+        }
+        if exhausted {
+            break;
+        }
+    }
+
+    let page =
+        crate::cursor::Page::from_rows(matches, limit, "files", |(stored, _)| stored.clone());
+    Ok(crate::cursor::Page {
+        items: page.items.into_iter().map(|(_, file)| file).collect(),
+        next_cursor: page.next_cursor,
+    })
+}
+
+/// Whether filesystem.files has a trigram index on file_path, without which
+/// [`search_paths`] scans the whole table
+#[tracing::instrument(skip(client))]
+pub async fn has_trigram_index(client: &tokio_postgres::Client) -> anyhow::Result<bool> {
+    let query = "
+        SELECT EXISTS (
+            SELECT 1 FROM pg_indexes
+            WHERE schemaname = 'filesystem' AND tablename = 'files'
+              AND indexdef LIKE '%gin_trgm_ops%'
+        )";
+    Ok(client.query_one(query, &[]).await?.get(0))
+}
+
+/// Record the entries the crawler of a scan failed to read
+#[tracing::instrument(skip(client, errors))]
+pub async fn record_scan_errors(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    errors: &[crate::crawler::ScanError],
+) -> anyhow::Result<()> {
     let stmt = client
         .prepare(
-            "INSERT INTO filesystem.scan_runs (scan_root, started_at) \
-            VALUES ($1, $2) RETURNING scan_id",
+            "INSERT INTO filesystem.scan_errors (scan_id, entry_path, error_kind, message) \
+            SELECT $1, * FROM unnest($2::text[], $3::text[], $4::text[]) \
+            ON CONFLICT (scan_id, entry_path) DO UPDATE \
+            SET error_kind = EXCLUDED.error_kind, message = EXCLUDED.message",
         )
         .await?;
-    let row = client
-        .query_one(&stmt, &[&data_root.to_string_lossy(), &started_at])
+    for chunk in errors.chunks(10_000) {
+        // a path may fail twice, e.g. once listed and once stat'ed
+        let chunk: std::collections::BTreeMap<_, _> = chunk
+            .iter()
+            .map(|error| (error.path.to_string_lossy(), error))
+            .collect();
+        let paths: Vec<&str> = chunk.keys().map(|path| path.as_ref()).collect();
+        let kinds: Vec<&str> = chunk.values().map(|error| error.kind.as_str()).collect();
+        let messages: Vec<&str> = chunk.values().map(|error| error.message.as_str()).collect();
+        client
+            .execute(&stmt, &[&scan_id, &paths, &kinds, &messages])
+            .await?;
+    }
+    Ok(())
+}
+
+/// Count the recorded errors of a scan into its `scan_errors` and
+/// `scan_errors_by_kind` metadata, with those the crawl did not record
+async fn count_scan_errors(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    metadata: &mut std::collections::HashMap<String, String>,
+) -> anyhow::Result<()> {
+    let rows = client
+        .query(
+            "SELECT error_kind, count(*) FROM filesystem.scan_errors \
+            WHERE scan_id = $1 GROUP BY error_kind ORDER BY error_kind",
+            &[&scan_id],
+        )
+        .await?;
+    let by_kind: serde_json::Map<String, serde_json::Value> = rows
+        .iter()
+        .map(|row| (row.get(0), row.get::<_, i64>(1).into()))
+        .collect();
+    let unrecorded: i64 = metadata
+        .get("scan_errors_unrecorded")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let total = rows.iter().map(|row| row.get::<_, i64>(1)).sum::<i64>() + unrecorded;
+    metadata.insert("scan_errors".to_string(), total.to_string());
+    if by_kind.is_empty() {
+        metadata.remove("scan_errors_by_kind");
+    } else {
+        metadata.insert(
+            "scan_errors_by_kind".to_string(),
+            serde_json::Value::from(by_kind).to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Record the hot directories found by the crawler for a scan
+#[tracing::instrument(skip(client, hot_dirs))]
+pub async fn record_hot_dirs(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    hot_dirs: &[crate::crawler::HotDir],
+) -> anyhow::Result<()> {
+    let stmt = client
+        .prepare(
+            "INSERT INTO filesystem.hot_dirs (scan_id, dir_path, entry_count, truncated) \
+            VALUES ($1, $2, $3, $4) \
+            ON CONFLICT (scan_id, dir_path) DO UPDATE \
+            SET entry_count = EXCLUDED.entry_count, truncated = EXCLUDED.truncated",
+        )
         .await?;
+    for hot_dir in hot_dirs {
+        client
+            .execute(
+                &stmt,
+                &[
+                    &scan_id,
+                    &hot_dir.path.to_string_lossy(),
+                    &(hot_dir.entry_count as i64),
+                    &hot_dir.truncated,
+                ],
+            )
+            .await?;
+    }
+    Ok(())
+}
 
-    let scan_id: i32 = row.get(0);
-    tracing::info!("Scan started with ID: {}", scan_id);
-    Ok(scan_id)
+/// How long a single COPY write may wait on the database before the load is
+/// reported as stalled
+const COPY_STALL_WARNING: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How [`load_tsv_file`] sends the crawl to the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CopyFormat {
+    /// The TSV lines as they are, parsed by the server
+    #[default]
+    Text,
+    /// Typed rows in COPY's binary format, parsed from the TSV lines by the
+    /// client: less work for the server on big loads
+    Binary,
+}
+
+impl std::str::FromStr for CopyFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(CopyFormat::Text),
+            "binary" => Ok(CopyFormat::Binary),
+            other => anyhow::bail!("Unknown COPY format: {}", other),
+        }
+    }
+}
+
+impl std::fmt::Display for CopyFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CopyFormat::Text => write!(f, "text"),
+            CopyFormat::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+/// Progress reporting, throttling, checkpointing and wire format of
+/// [`load_tsv_file`]
+#[derive(Debug, Clone, Default)]
+pub struct LoadPacing {
+    /// Log and report the load rate this often
+    pub progress_interval: Option<std::time::Duration>,
+    /// Send at most this many rows per second, sparing a struggling database
+    pub max_rows_per_second: Option<u64>,
+    /// Commit the load in chunks, so that an interrupted load resumes
+    /// after its last committed chunk instead of starting over
+    pub checkpoints: Option<LoadCheckpoints>,
+    pub copy_format: CopyFormat,
+}
+
+/// Chunking of a resumable [`load_tsv_file`]
+#[derive(Debug, Clone, Copy)]
+pub struct LoadCheckpoints {
+    /// Scan the rows are staged for, keying its checkpoint
+    pub scan_id: i32,
+    /// Rows per committed chunk
+    pub every_rows: u64,
+}
+
+/// Outcome of [`load_tsv_file`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadStats {
+    pub rows: u64,
+    pub elapsed: std::time::Duration,
+    /// Time spent waiting for the database to accept data (backpressure)
+    pub blocked: std::time::Duration,
+    /// Time spent sleeping to honour `max_rows_per_second`
+    pub throttled: std::time::Duration,
+    /// Time spent rebuilding indexes deferred by [`with_deferred_indexes`]
+    pub index_rebuild: std::time::Duration,
+}
+
+impl std::ops::AddAssign for LoadStats {
+    fn add_assign(&mut self, other: Self) {
+        self.rows += other.rows;
+        self.elapsed += other.elapsed;
+        self.blocked += other.blocked;
+        self.throttled += other.throttled;
+        self.index_rebuild += other.index_rebuild;
+    }
 }
 
-#[tracing::instrument(skip(client, input_tsv_file))]
+impl LoadStats {
+    /// Effective load rate, including waits
+    pub fn rows_per_second(&self) -> f64 {
+        self.rows as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Record the load rate in scan metadata
+    pub fn record(&self, metadata: &mut std::collections::HashMap<String, String>) {
+        metadata.insert(
+            "load_time_s".to_string(),
+            self.elapsed.as_secs_f64().to_string(),
+        );
+        metadata.insert(
+            "load_rows_per_second".to_string(),
+            self.rows_per_second().to_string(),
+        );
+        metadata.insert(
+            "load_blocked_time_s".to_string(),
+            self.blocked.as_secs_f64().to_string(),
+        );
+        metadata.insert(
+            "load_throttled_time_s".to_string(),
+            self.throttled.as_secs_f64().to_string(),
+        );
+        metadata.insert(
+            "load_index_rebuild_time_s".to_string(),
+            self.index_rebuild.as_secs_f64().to_string(),
+        );
+    }
+}
+
+/// COPY a crawl TSV file into `staging_table`.
+///
+/// Time spent waiting for the database to take more data is measured, and a
+/// write blocked for longer than [`COPY_STALL_WARNING`] is logged while it
+/// waits, so a database that falls behind shows up as such rather than as a
+/// hung load.
+///
+/// With [`LoadPacing::checkpoints`], every chunk is committed together with
+/// the byte offset it ends at, in `filesystem.load_checkpoints`. A later
+/// load of the same file for the scan then resumes from that offset if the
+/// staged rows still match it (an unlogged staging table is emptied by a
+/// database crash), and discards them otherwise. Without, the file is loaded
+/// in a single COPY, e.g. within a caller's transaction.
+#[tracing::instrument(skip(client, input_tsv_file, progress))]
 pub async fn load_tsv_file(
     client: &tokio_postgres::Client,
     input_tsv_file: std::path::PathBuf,
-) -> anyhow::Result<i32> {
-    // Returns the number of rows inserted into the staging table
-    let query_header = "
-        COPY filesystem.staging_files(
-            file_name, file_type, file_path, file_size_bytes, file_mtime, scan_id
-        )
-        FROM STDIN
-        WITH (
-            FORMAT csv,
-            DELIMITER E'\t',
-            NULL '',
-            HEADER FALSE
-        )";
+    staging_table: &str,
+    pacing: &LoadPacing,
+    progress: &crate::progress::ProgressReporter,
+) -> anyhow::Result<LoadStats> {
+    let mut file = tokio::fs::File::open(&input_tsv_file).await?;
+    let tsv_size = file.metadata().await?.len();
+    let mut offset = 0;
+    let mut resumed_rows = 0;
+    if let Some(checkpoints) = &pacing.checkpoints {
+        (offset, resumed_rows) = resume_load(
+            client,
+            checkpoints.scan_id,
+            &input_tsv_file,
+            tsv_size,
+            staging_table,
+        )
+        .await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+    }
+
+    let result = copy_tsv_chunks(
+        client,
+        tokio::io::BufReader::new(file),
+        staging_table,
+        pacing,
+        (&input_tsv_file, tsv_size, offset, resumed_rows),
+        progress,
+    )
+    .await;
+    if result.is_err() && pacing.checkpoints.is_some() {
+        // roll back the open chunk, so the client can be used again
+        client.batch_execute("ROLLBACK").await.ok();
+    }
+    result
+}
+
+/// COPY a crawl streamed through `reader`, e.g. the read end of the pipe a
+/// crawl writes to (see `crawler::CrawlSink::Pipe`), into `staging_table` as
+/// it arrives, in a single COPY. The database's pace propagates back to the
+/// writer through the pipe. Streams cannot be checkpointed: there is no file
+/// to resume from.
+#[tracing::instrument(skip(client, reader, progress))]
+pub async fn load_tsv_stream(
+    client: &tokio_postgres::Client,
+    reader: impl tokio::io::AsyncBufRead + Unpin,
+    staging_table: &str,
+    pacing: &LoadPacing,
+    progress: &crate::progress::ProgressReporter,
+) -> anyhow::Result<LoadStats> {
+    anyhow::ensure!(
+        pacing.checkpoints.is_none(),
+        "A streamed load cannot be checkpointed"
+    );
+    // the file position is only recorded by checkpoints
+    copy_tsv_chunks(
+        client,
+        reader,
+        staging_table,
+        pacing,
+        (std::path::Path::new("-"), 0, 0, 0),
+        progress,
+    )
+    .await
+}
+
+/// The offset and row count to resume loading `input_tsv_file` for a scan
+/// from, discarding rows staged by a load that cannot be resumed
+async fn resume_load(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    input_tsv_file: &std::path::Path,
+    tsv_size: u64,
+    staging_table: &str,
+) -> anyhow::Result<(u64, u64)> {
+    let checkpoint = client
+        .query_opt(
+            "SELECT tsv_file, tsv_size_bytes, byte_offset, rows_loaded
+             FROM filesystem.load_checkpoints
+             WHERE scan_id = $1",
+            &[&scan_id],
+        )
+        .await?;
+    let staged: i64 = client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {} WHERE scan_id = $1", staging_table),
+            &[&scan_id],
+        )
+        .await?
+        .get(0);
+    if let Some(row) = &checkpoint
+        && row.get::<_, String>(0) == input_tsv_file.to_string_lossy()
+        && row.get::<_, i64>(1) == tsv_size as i64
+        && row.get::<_, i64>(3) == staged
+    {
+        let offset = row.get::<_, i64>(2) as u64;
+        tracing::info!(
+            "📥 Resuming load of {} at byte {} of {} ({} rows already staged)",
+            input_tsv_file.display(),
+            offset,
+            tsv_size,
+            staged
+        );
+        return Ok((offset, staged as u64));
+    }
+
+    if staged > 0 {
+        tracing::warn!(
+            "📥 Discarding {} rows staged for scan {} by a load that cannot be resumed",
+            staged,
+            scan_id
+        );
+        client
+            .execute(
+                &format!("DELETE FROM {} WHERE scan_id = $1", staging_table),
+                &[&scan_id],
+            )
+            .await?;
+    }
+    if checkpoint.is_some() {
+        client
+            .execute(
+                "DELETE FROM filesystem.load_checkpoints WHERE scan_id = $1",
+                &[&scan_id],
+            )
+            .await?;
+    }
+    Ok((0, 0))
+}
+
+type CopySink = std::pin::Pin<Box<tokio_postgres::CopyInSink<std::io::Cursor<Vec<u8>>>>>;
+
+/// Column types of the crawl TSV, in order, for a binary COPY
+const CRAWL_COLUMN_TYPES: [tokio_postgres::types::Type; 12] = [
+    tokio_postgres::types::Type::TEXT,
+    tokio_postgres::types::Type::TEXT,
+    tokio_postgres::types::Type::TEXT,
+    tokio_postgres::types::Type::INT8,
+    tokio_postgres::types::Type::TIMESTAMPTZ,
+    tokio_postgres::types::Type::TEXT,
+    tokio_postgres::types::Type::TEXT,
+    tokio_postgres::types::Type::TEXT,
+    tokio_postgres::types::Type::INT4,
+    tokio_postgres::types::Type::INT8,
+    tokio_postgres::types::Type::INT8,
+    tokio_postgres::types::Type::INT4,
+];
 
-    let file = tokio::fs::File::open(&input_tsv_file).await?;
-    let reader = tokio::io::BufReader::new(file);
-    let mut lines = reader.lines();
+/// A crawl TSV line parsed into the typed values of a binary COPY row
+struct CrawlRow {
+    name: String,
+    file_type: String,
+    path: String,
+    size: i64,
+    mtime: chrono::DateTime<chrono::Utc>,
+    label: Option<String>,
+    fingerprint: Option<String>,
+    symlink_target: Option<String>,
+    mode: Option<i32>,
+    uid: Option<i64>,
+    gid: Option<i64>,
+    scan_id: i32,
+}
+
+impl CrawlRow {
+    fn parse(line: &[u8]) -> anyhow::Result<Self> {
+        let line = std::str::from_utf8(line)
+            .map_err(|e| anyhow::anyhow!("Crawl line is not UTF-8: {}", e))?;
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [
+            name,
+            file_type,
+            path,
+            size,
+            mtime,
+            label,
+            fingerprint,
+            symlink_target,
+            mode,
+            uid,
+            gid,
+            scan_id,
+        ] = fields.as_slice()
+        else {
+            anyhow::bail!("Malformed crawl line: {:?}", line);
+        };
+        let required = |field: &str| {
+            crate::tsv::field(field)
+                .ok_or_else(|| anyhow::anyhow!("Null field in crawl line: {:?}", line))
+        };
+        Ok(CrawlRow {
+            name: required(name)?,
+            file_type: required(file_type)?,
+            path: required(path)?,
+            size: size
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid size {:?}: {}", size, e))?,
+            mtime: chrono::DateTime::parse_from_rfc3339(mtime)
+                .map_err(|e| anyhow::anyhow!("Invalid mtime {:?}: {}", mtime, e))?
+                .to_utc(),
+            label: crate::tsv::field(label),
+            fingerprint: crate::tsv::field(fingerprint),
+            symlink_target: crate::tsv::field(symlink_target),
+            mode: nullable_number(mode)?,
+            uid: nullable_number(uid)?,
+            gid: nullable_number(gid)?,
+            scan_id: scan_id
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid scan id {:?}: {}", scan_id, e))?,
+        })
+    }
+}
+
+/// A numeric crawl TSV field, `None` if `\N`
+fn nullable_number<T: std::str::FromStr>(field: &str) -> anyhow::Result<Option<T>>
+where
+    T::Err: std::fmt::Display,
+{
+    crate::tsv::field(field)
+        .map(|value| value.parse())
+        .transpose()
+        .map_err(|e| anyhow::anyhow!("Invalid number {:?}: {}", field, e))
+}
+
+/// The open COPY of a chunk of [`copy_tsv_chunks`]
+enum CopyWriter {
+    Text(CopySink),
+    Binary(std::pin::Pin<Box<tokio_postgres::binary_copy::BinaryCopyInWriter>>),
+}
+
+impl CopyWriter {
+    async fn open(
+        client: &tokio_postgres::Client,
+        staging_table: &str,
+        format: CopyFormat,
+    ) -> anyhow::Result<Self> {
+        let query = format!(
+            "
+            COPY {}(
+                file_name, file_type, file_path, file_size_bytes, file_mtime,
+                security_label, file_fingerprint, symlink_target,
+                file_mode, file_uid, file_gid, scan_id
+            )
+            FROM STDIN
+            WITH ({})",
+            staging_table,
+            match format {
+                CopyFormat::Text => crate::tsv::COPY_OPTIONS,
+                CopyFormat::Binary => "FORMAT binary",
+            }
+        );
+        Ok(match format {
+            CopyFormat::Text => CopyWriter::Text(Box::pin(client.copy_in(&query).await?)),
+            CopyFormat::Binary => CopyWriter::Binary(Box::pin(
+                tokio_postgres::binary_copy::BinaryCopyInWriter::new(
+                    client.copy_in(&query).await?,
+                    &CRAWL_COLUMN_TYPES,
+                ),
+            )),
+        })
+    }
+
+    /// Send the crawl line `line`, the `rows`th, returning how long the
+    /// database kept it waiting
+    async fn send(&mut self, line: Vec<u8>, rows: u64) -> anyhow::Result<std::time::Duration> {
+        match self {
+            CopyWriter::Text(sink) => {
+                wait_for_copy(sink.send(std::io::Cursor::new(line)), rows).await
+            }
+            CopyWriter::Binary(writer) => {
+                let row = CrawlRow::parse(&line)?;
+                wait_for_copy(
+                    writer.as_mut().write(&[
+                        &row.name,
+                        &row.file_type,
+                        &row.path,
+                        &row.size,
+                        &row.mtime,
+                        &row.label,
+                        &row.fingerprint,
+                        &row.symlink_target,
+                        &row.mode,
+                        &row.uid,
+                        &row.gid,
+                        &row.scan_id,
+                    ]),
+                    rows,
+                )
+                .await
+            }
+        }
+    }
+
+    /// End the COPY once the database has taken all of its rows
+    async fn close(self, rows: u64) -> anyhow::Result<std::time::Duration> {
+        match self {
+            CopyWriter::Text(mut sink) => wait_for_copy(sink.close(), rows).await,
+            CopyWriter::Binary(mut writer) => {
+                wait_for_copy(async { writer.as_mut().finish().await.map(|_| ()) }, rows).await
+            }
+        }
+    }
+}
+
+/// The COPY loop of [`load_tsv_file`] and [`load_tsv_stream`], from `reader`
+/// positioned at `offset` of the `(path, size, offset, resumed rows)` TSV file
+async fn copy_tsv_chunks(
+    client: &tokio_postgres::Client,
+    mut reader: impl tokio::io::AsyncBufRead + Unpin,
+    staging_table: &str,
+    pacing: &LoadPacing,
+    (input_tsv_file, tsv_size, mut offset, resumed_rows): (&std::path::Path, u64, u64, u64),
+    progress: &crate::progress::ProgressReporter,
+) -> anyhow::Result<LoadStats> {
+    let start = std::time::Instant::now();
+    let mut stats = LoadStats::default();
+    let mut last_report = start;
+    let mut writer: Option<CopyWriter> = None;
+    let mut chunk_rows = 0;
+    let disconnect_after = crate::fault::FaultPoint::CopyDisconnect.armed();
+    loop {
+        if disconnect_after == Some(stats.rows) {
+            return Err(crate::fault::FaultPoint::CopyDisconnect.error());
+        }
+        let mut line = Vec::new();
+        let read = reader.read_until(b'\n', &mut line).await?;
+        if read == 0 {
+            break;
+        }
+        if !line.ends_with(b"\n") {
+            line.push(b'\n');
+        }
+        offset += read as u64;
+
+        let sink = match &mut writer {
+            Some(sink) => sink,
+            None => {
+                if pacing.checkpoints.is_some() {
+                    client.batch_execute("BEGIN").await?;
+                }
+                writer.insert(CopyWriter::open(client, staging_table, pacing.copy_format).await?)
+            }
+        };
+        stats.rows += 1;
+        chunk_rows += 1;
+        stats.blocked += sink.send(line, stats.rows).await?;
+
+        if let Some(checkpoints) = &pacing.checkpoints
+            && chunk_rows >= checkpoints.every_rows
+            && let Some(sink) = writer.take()
+        {
+            stats.blocked += sink.close(stats.rows).await?;
+            commit_checkpoint(
+                client,
+                checkpoints.scan_id,
+                (input_tsv_file, tsv_size, offset),
+                resumed_rows + stats.rows,
+            )
+            .await?;
+            chunk_rows = 0;
+        }
+
+        // Checked every 1000 rows to keep the clock out of the hot loop
+        if !stats.rows.is_multiple_of(1000) {
+            continue;
+        }
+        if let Some(max) = pacing.max_rows_per_second {
+            let due = std::time::Duration::from_secs_f64(stats.rows as f64 / max.max(1) as f64);
+            let elapsed = start.elapsed();
+            if due > elapsed {
+                tokio::time::sleep(due - elapsed).await;
+                stats.throttled += due - elapsed;
+            }
+        }
+        if let Some(interval) = pacing.progress_interval
+            && last_report.elapsed() >= interval
+        {
+            last_report = std::time::Instant::now();
+            stats.elapsed = start.elapsed();
+            tracing::info!(
+                "📥 Loaded {} rows ({:.0} rows/s, {:.0?} waiting on the database)",
+                stats.rows,
+                stats.rows_per_second(),
+                stats.blocked
+            );
+            progress.emit(crate::progress::ProgressEvent::LoadTick {
+                rows: stats.rows,
+                elapsed: stats.elapsed,
+                rows_per_second: stats.rows_per_second(),
+                blocked: stats.blocked,
+            });
+        }
+    }
+
+    // The server only confirms the rows once it has taken all of them
+    if let Some(sink) = writer.take() {
+        stats.blocked += sink.close(stats.rows).await?;
+        if let Some(checkpoints) = &pacing.checkpoints {
+            commit_checkpoint(
+                client,
+                checkpoints.scan_id,
+                (input_tsv_file, tsv_size, offset),
+                resumed_rows + stats.rows,
+            )
+            .await?;
+        }
+    }
+    stats.elapsed = start.elapsed();
+
+    Ok(stats)
+}
+
+/// Record that the staged rows of a scan end at `offset` of the TSV file,
+/// committing the chunk copied since the last checkpoint along with it
+async fn commit_checkpoint(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    (input_tsv_file, tsv_size, offset): (&std::path::Path, u64, u64),
+    rows: u64,
+) -> anyhow::Result<()> {
+    client
+        .execute(
+            "INSERT INTO filesystem.load_checkpoints
+                 (scan_id, tsv_file, tsv_size_bytes, byte_offset, rows_loaded)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (scan_id) DO UPDATE
+             SET tsv_file = EXCLUDED.tsv_file,
+                 tsv_size_bytes = EXCLUDED.tsv_size_bytes,
+                 byte_offset = EXCLUDED.byte_offset,
+                 rows_loaded = EXCLUDED.rows_loaded,
+                 updated_at = now()",
+            &[
+                &scan_id,
+                &input_tsv_file.to_string_lossy(),
+                &(tsv_size as i64),
+                &(offset as i64),
+                &(rows as i64),
+            ],
+        )
+        .await?;
+    client.batch_execute("COMMIT").await?;
+    Ok(())
+}
 
-    let writer = client.copy_in(query_header).await?;
-    let mut writer = Box::pin(writer);
+/// Run `load` with the secondary indexes of `table` dropped, recreating them
+/// afterwards (also when the load fails). Returns the load's result and how
+/// long the rebuild took.
+///
+/// Indexes backing constraints, such as the primary key, are kept. Dropping
+/// an index locks the table, so on the shared staging table this blocks other
+/// hosts' scans until the rebuild is done.
+#[tracing::instrument(skip(client, load))]
+pub async fn with_deferred_indexes<T>(
+    client: &tokio_postgres::Client,
+    table: &str,
+    load: impl std::future::Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<(T, std::time::Duration)> {
+    let query = "
+        SELECT i.indexrelid::regclass::text, pg_get_indexdef(i.indexrelid)
+        FROM pg_index AS i
+        WHERE i.indrelid = $1::text::regclass
+          AND NOT EXISTS (
+              SELECT 1 FROM pg_constraint AS c WHERE c.conindid = i.indexrelid
+          )";
+    let indexes: Vec<(String, String)> = client
+        .query(query, &[&table])
+        .await?
+        .iter()
+        .map(|r| (r.get(0), r.get(1)))
+        .collect();
 
-    let mut line_count = 0;
-    while let Some(line) = lines.next_line().await? {
-        let line_with_newline = format!("{}\n", line);
-        line_count += 1;
-        writer
-            .send(std::io::Cursor::new(line_with_newline.into_bytes()))
+    for (name, _) in &indexes {
+        tracing::info!("📉 Dropping index {} for the load", name);
+        // IF EXISTS: a concurrent load may have deferred it already
+        client
+            .batch_execute(&format!("DROP INDEX IF EXISTS {}", name))
             .await?;
     }
 
-    writer.close().await?;
+    let result = load.await;
+
+    let start = std::time::Instant::now();
+    for (name, definition) in &indexes {
+        tracing::info!("📈 Rebuilding index {}", name);
+        let create = definition
+            .replacen("CREATE INDEX ", "CREATE INDEX IF NOT EXISTS ", 1)
+            .replacen(
+                "CREATE UNIQUE INDEX ",
+                "CREATE UNIQUE INDEX IF NOT EXISTS ",
+                1,
+            );
+        if let Err(e) = client.batch_execute(&create).await {
+            tracing::error!("Failed to rebuild index {}: {}", name, e);
+            // the load's own error is the more useful one to report
+            result?;
+            return Err(e.into());
+        }
+    }
+    let rebuild = start.elapsed();
+    if !indexes.is_empty() {
+        tracing::info!("📈 Rebuilt {} indexes in {:.2?}", indexes.len(), rebuild);
+    }
+
+    Ok((result?, rebuild))
+}
+
+/// Await a COPY write, warning every [`COPY_STALL_WARNING`] it stays blocked;
+/// returns how long it took
+async fn wait_for_copy(
+    write: impl std::future::Future<Output = Result<(), tokio_postgres::Error>>,
+    rows: u64,
+) -> anyhow::Result<std::time::Duration> {
+    let start = std::time::Instant::now();
+    tokio::pin!(write);
+    loop {
+        match tokio::time::timeout(COPY_STALL_WARNING, &mut write).await {
+            Ok(result) => {
+                result?;
+                return Ok(start.elapsed());
+            }
+            Err(_) => tracing::warn!(
+                "⏳ Database has not accepted COPY data for {:.0?} ({} rows sent); it is falling behind",
+                start.elapsed(),
+                rows
+            ),
+        }
+    }
+}
+
+/// Delete the tracked files at `paths`, or below `dirs`, that are not staged
+/// for `scan_id`, recording them as deleted by the scan; returns how many.
+/// Used by snapshot diff scans, whose staging only holds the changed files.
+#[tracing::instrument(skip(client, paths, dirs))]
+pub async fn delete_removed_paths(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    staging_table: &str,
+    paths: &[String],
+    dirs: &[String],
+) -> anyhow::Result<u64> {
+    let dir_patterns: Vec<String> = dirs.iter().map(|dir| like_below(dir)).collect();
+    let query = format!(
+        "WITH deleted AS (
+            DELETE FROM filesystem.files AS f
+            WHERE (f.file_path = ANY($2) OR f.file_path LIKE ANY($3))
+              AND NOT EXISTS (
+                  SELECT 1 FROM {} AS s
+                  WHERE s.scan_id = $1 AND s.file_path = f.file_path
+              )
+              AND NOT EXISTS (
+                  SELECT 1 FROM filesystem.hot_dirs AS h
+                  WHERE h.scan_id = $1 AND h.truncated
+                    AND left(f.file_path, length(h.dir_path) + 1) = h.dir_path || '/'
+              )
+            RETURNING f.file_path, f.file_type, f.file_size_bytes, f.file_mtime,
+                      f.security_label, f.file_fingerprint, f.symlink_target,
+                      f.file_mode, f.file_uid, f.file_gid
+        )
+        INSERT INTO filesystem.file_changes (
+            scan_id, file_path, change_type, old_size_bytes, old_mtime, old_file_type,
+            old_security_label, old_fingerprint, old_symlink_target,
+            old_mode, old_uid, old_gid
+        )
+        SELECT $1, file_path, 'deleted', file_size_bytes, file_mtime, file_type,
+               security_label, file_fingerprint, symlink_target,
+               file_mode, file_uid, file_gid
+        FROM deleted",
+        staging_table
+    );
+    let deleted = client
+        .execute(&query, &[&scan_id, &paths, &dir_patterns])
+        .await?;
+    Ok(deleted)
+}
+
+/// Files staged for `scan_id` with a fingerprint whose tracked size, mtime
+/// and hash algorithm are the same: how many, and how many of them hashed
+/// to another fingerprint (see `sample::record_bitrot_estimate`)
+#[tracing::instrument(skip(client))]
+pub async fn count_fingerprint_mismatches(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    staging_table: &str,
+) -> anyhow::Result<(i64, i64)> {
+    let query = format!(
+        "SELECT COUNT(*), COUNT(*) FILTER (WHERE s.file_fingerprint <> f.file_fingerprint)
+         FROM {} AS s
+         JOIN filesystem.files AS f ON f.file_path = s.file_path
+         WHERE s.scan_id = $1
+           AND split_part(s.file_fingerprint, ':', 1) = split_part(f.file_fingerprint, ':', 1)
+           AND s.file_size_bytes = f.file_size_bytes
+           AND s.file_mtime = f.file_mtime",
+        staging_table
+    );
+    let row = client.query_one(&query, &[&scan_id]).await?;
+    Ok((row.get(0), row.get(1)))
+}
+
+/// LIKE pattern matching the paths below `dir`
+fn like_below(dir: &str) -> String {
+    format!(
+        "{}/%",
+        dir.trim_end_matches('/')
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    )
+}
+
+/// Recorded paths of the files directly in `dir`, and of its subdirectories
+/// holding recorded files
+#[tracing::instrument(skip(client))]
+pub async fn get_recorded_children(
+    client: &tokio_postgres::Client,
+    dir: &str,
+) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+    let dir = dir.trim_end_matches('/');
+    let files = client
+        .query(
+            "SELECT file_path FROM filesystem.files WHERE parent_dir = $1",
+            &[&dir],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    let subdirs = client
+        .query(
+            "SELECT DISTINCT $1::text || '/' || split_part(substr(parent_dir, length($1::text) + 2), '/', 1)
+            FROM filesystem.files
+            WHERE parent_dir LIKE $2",
+            &[&dir, &like_below(dir)],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    Ok((files, subdirs))
+}
+
+/// Record the per-extension totals of `from_scan_id` for `scan_id`, which
+/// found the same files without processing them
+#[tracing::instrument(skip(client))]
+pub async fn copy_extension_stats(
+    client: &tokio_postgres::Client,
+    from_scan_id: i32,
+    scan_id: i32,
+) -> anyhow::Result<()> {
+    client
+        .execute(
+            "INSERT INTO filesystem.extension_stats (scan_id, file_type, file_count, total_size_bytes)
+             SELECT $2, file_type, file_count, total_size_bytes
+             FROM filesystem.extension_stats
+             WHERE scan_id = $1",
+            &[&from_scan_id, &scan_id],
+        )
+        .await?;
+    Ok(())
+}
 
-    Ok(line_count)
+/// Replace the per-extension totals of a scan with those of all files now
+/// tracked under its root, returning the number of files. Used by snapshot
+/// diff scans, which only stage the changed files.
+#[tracing::instrument(skip(client))]
+pub async fn record_extension_stats_from_files(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<u64> {
+    client
+        .execute(
+            "DELETE FROM filesystem.extension_stats WHERE scan_id = $1",
+            &[&scan_id],
+        )
+        .await?;
+    let query = "
+        INSERT INTO filesystem.extension_stats (scan_id, file_type, file_count, total_size_bytes)
+        SELECT $1, f.file_type, COUNT(*), SUM(f.file_size_bytes)
+        FROM filesystem.files AS f,
+             (SELECT replace(btrim(scan_root, '/'), '/', '.')::ltree AS root_ltree
+              FROM filesystem.scan_runs
+              WHERE scan_id = $1) AS scan_info
+        WHERE f.path_ltree <@ scan_info.root_ltree
+        GROUP BY f.file_type
+        RETURNING file_count";
+    let rows = client.query(query, &[&scan_id]).await?;
+    Ok(rows.iter().map(|r| r.get::<_, i64>(0) as u64).sum())
 }
 
 #[tracing::instrument(skip(client, scan_id, metadata))]
@@ -129,6 +2567,25 @@ pub async fn finalize_scan(
         // Convert size from bytes to megabytes
         file_sizes_mb.insert(change_type.to_string(), size as f64 / 1024.0 / 1024.0);
     }
+    // only scans recording security labels (`--security-labels`) relabel files
+    let relabeled = get_files_count_by_change_type(client, scan_id, "relabeled").await?;
+    if relabeled > 0 {
+        metadata.insert("relabeled_files_count".to_string(), relabeled.to_string());
+    }
+    // nor do scans not recording ownership (`--record-ownership`) find these
+    let permissions_changed =
+        get_files_count_by_change_type(client, scan_id, "permissions_changed").await?;
+    if permissions_changed > 0 {
+        metadata.insert(
+            "permissions_changed_files_count".to_string(),
+            permissions_changed.to_string(),
+        );
+    }
+    // only scans hashing the contents (`--content-hash`, `--sample-fraction`) find any
+    let bitrot = flag_bitrot(client, scan_id).await?;
+    metadata.insert("bitrot_files".to_string(), bitrot.to_string());
+    // the crawl's own counts cover one batch of a batched scan only
+    count_scan_errors(client, scan_id, &mut metadata).await?;
 
     // Update the scan_runs table with all the scan results
     let query = "
@@ -141,7 +2598,9 @@ pub async fn finalize_scan(
             new_data_mb = $6,
             modified_data_mb = $7,
             deleted_data_mb = $8,
-            scan_metadata = $9
+            scan_metadata = $9,
+            scan_status = 'completed',
+            scan_phase = 'finalized'
         WHERE scan_id = $10";
 
     let metadata_json = serde_json::to_value(&metadata)
@@ -169,6 +2628,29 @@ pub async fn finalize_scan(
         )
         .await?;
 
+    // the scan's changes were queued for subscribers with the changes
+    // themselves; a failed delivery is left to `subscriptions deliver`
+    let delivery = crate::subscription::DeliveryOptions::default();
+    match crate::subscription::deliver_events(client, &delivery).await {
+        Ok(stats) if stats.failed + stats.dead_lettered > 0 => tracing::warn!(
+            "⚠️ Delivered {} events to subscribers, {} failed and {} dead-lettered",
+            stats.delivered,
+            stats.failed,
+            stats.dead_lettered
+        ),
+        Ok(stats) if stats.delivered > 0 => {
+            tracing::info!("📣 Delivered {} events to subscribers", stats.delivered)
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!("⚠️ Failed to deliver events to subscribers: {:#}", e),
+    }
+
+    let violations = check_delta_budgets(client, scan_id).await?;
+    metadata.insert(
+        "budget_violations".to_string(),
+        violations.len().to_string(),
+    );
+
     metadata.insert("scan_id".to_string(), scan_id.to_string());
     metadata.insert("completed_at".to_string(), completed_at.to_rfc3339());
     metadata.insert(
@@ -196,6 +2678,20 @@ pub async fn finalize_scan(
         file_sizes_mb.get("deleted").unwrap_or(&0.0).to_string(),
     );
 
+    audit(
+        client,
+        "scan_finished",
+        Some(scan_id),
+        serde_json::json!({
+            "added_files_count": file_counts.get("added").unwrap_or(&0),
+            "modified_files_count": file_counts.get("modified").unwrap_or(&0),
+            "removed_files_count": file_counts.get("deleted").unwrap_or(&0),
+            "budget_violations": violations.len(),
+            "bitrot_files": bitrot,
+        }),
+    )
+    .await?;
+
     tracing::info!("📊 Scan metadata:\n{:#?}", metadata);
 
     Ok(())
@@ -55,3 +55,238 @@ pub async fn execute_sql_template_str(
 
     Ok(())
 }
+
+/// Bring a database created by an older `init_db.sql` up to the current
+/// schema and views, keeping its data (see `upgrade_schema.sql`)
+pub async fn upgrade_schema(client: &tokio_postgres::Client) -> anyhow::Result<()> {
+    execute_sql_template_str(
+        client,
+        crate::pipeline::sql_template("upgrade_schema.sql"),
+        None,
+    )
+    .await?;
+    // the views may use the new columns
+    execute_sql_template_str(
+        client,
+        crate::pipeline::sql_template("create_views.sql"),
+        None,
+    )
+    .await
+}
+
+/// Database a scan's deltas are recorded in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// The `filesystem` schema of a PostgreSQL database
+    #[default]
+    Postgres,
+    /// A single SQLite file (see `sqlite_store`), for laptops and air-gapped
+    /// machines without a database server; plain crawls only
+    Sqlite,
+}
+
+impl std::str::FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "postgres" | "postgresql" => Ok(Backend::Postgres),
+            "sqlite" => Ok(Backend::Sqlite),
+            other => anyhow::bail!("Unknown backend: {}", other),
+        }
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Postgres => write!(f, "postgres"),
+            Backend::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+/// Where the lifecycle of a plain scan is recorded: its start, its staged
+/// crawl, the deltas that makes against the tracked files, and its outcome.
+/// See `pipeline::run_store_scan`, which drives a scan through any of them.
+#[allow(async_fn_in_trait)]
+pub trait DeltaStore {
+    /// The kind of database the store is, recorded in scan metadata
+    fn backend(&self) -> Backend;
+
+    /// Record the start of a scan of `scan_root`, returning its scan_id
+    async fn start_scan(
+        &self,
+        scan_root: &std::path::Path,
+        started_at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<i32>;
+
+    /// Stage the crawl TSV file of a scan, returning the rows staged
+    async fn load_crawl(
+        &self,
+        scan_id: i32,
+        tsv_file: &std::path::Path,
+        progress: &crate::progress::ProgressReporter,
+    ) -> anyhow::Result<u64>;
+
+    /// Record the deltas between the staged crawl and the tracked files,
+    /// bring the latter in line and empty staging, all or nothing
+    async fn apply_deltas(&self, scan_id: i32) -> anyhow::Result<()>;
+
+    /// Count the recorded deltas and mark the scan completed with `metadata`
+    async fn finalize_scan(
+        &self,
+        scan_id: i32,
+        metadata: std::collections::HashMap<String, String>,
+    ) -> anyhow::Result<()>;
+
+    /// Mark a failed scan aborted, recording `reason` in its anomaly flags
+    async fn abort_scan(&self, scan_id: i32, reason: serde_json::Value) -> anyhow::Result<()>;
+
+    /// The scan_runs row of a scan as JSON
+    async fn scan_summary(&self, scan_id: i32) -> anyhow::Result<serde_json::Value>;
+}
+
+impl DeltaStore for tokio_postgres::Client {
+    fn backend(&self) -> Backend {
+        Backend::Postgres
+    }
+
+    async fn start_scan(
+        &self,
+        scan_root: &std::path::Path,
+        started_at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<i32> {
+        crate::data::start_scan(self, &scan_root.to_path_buf(), &[], None, None, started_at).await
+    }
+
+    async fn load_crawl(
+        &self,
+        _scan_id: i32,
+        tsv_file: &std::path::Path,
+        progress: &crate::progress::ProgressReporter,
+    ) -> anyhow::Result<u64> {
+        let stats = crate::data::load_tsv_file(
+            self,
+            tsv_file.to_path_buf(),
+            crate::staging::StagingStrategy::default().table(),
+            &crate::data::LoadPacing::default(),
+            progress,
+        )
+        .await?;
+        Ok(stats.rows)
+    }
+
+    async fn apply_deltas(&self, scan_id: i32) -> anyhow::Result<()> {
+        let mut params = std::collections::HashMap::new();
+        params.insert("scan_id".to_string(), scan_id.to_string());
+        params.insert("defer_deletes".to_string(), false.to_string());
+        params.insert(
+            "staging_table".to_string(),
+            crate::staging::StagingStrategy::default()
+                .table()
+                .to_string(),
+        );
+        let sql = crate::pipeline::sql_template("process_staging_v2.sql");
+        execute_sql_template_str(self, sql, Some(params)).await?;
+        crate::data::clear_staging(self, scan_id).await
+    }
+
+    async fn finalize_scan(
+        &self,
+        scan_id: i32,
+        metadata: std::collections::HashMap<String, String>,
+    ) -> anyhow::Result<()> {
+        crate::data::finalize_scan(self, scan_id, metadata).await
+    }
+
+    async fn abort_scan(&self, scan_id: i32, reason: serde_json::Value) -> anyhow::Result<()> {
+        crate::data::abort_scan(self, scan_id, reason).await
+    }
+
+    async fn scan_summary(&self, scan_id: i32) -> anyhow::Result<serde_json::Value> {
+        crate::data::get_scan_summary(self, scan_id).await
+    }
+}
+
+/// Connections to PostgreSQL shared by the concurrent scans of a process:
+/// each is held by one scan at a time, then reused by the next, and at most
+/// `size` are open
+pub struct ClientPool {
+    database_url: String,
+    size: usize,
+    idle: std::sync::Mutex<Vec<tokio_postgres::Client>>,
+    permits: tokio::sync::Semaphore,
+}
+
+/// A connection of a [`ClientPool`], back in the pool when dropped
+pub struct PooledClient<'a> {
+    pool: &'a ClientPool,
+    client: Option<tokio_postgres::Client>,
+    _permit: tokio::sync::SemaphorePermit<'a>,
+}
+
+impl ClientPool {
+    /// A pool connecting to `database_url` on demand
+    pub fn new(database_url: &str, size: usize) -> Self {
+        Self {
+            database_url: database_url.to_string(),
+            size: size.max(1),
+            idle: Default::default(),
+            permits: tokio::sync::Semaphore::new(size.max(1)),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Wait for a connection, reusing an idle one or else opening one
+    pub async fn get(&self) -> anyhow::Result<PooledClient<'_>> {
+        let permit = self.permits.acquire().await?;
+        let idle = loop {
+            let client = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+            match client {
+                Some(client) if client.is_closed() => continue,
+                client => break client,
+            }
+        };
+        let client = match idle {
+            Some(client) => client,
+            None => {
+                let (client, connection) =
+                    tokio_postgres::connect(&self.database_url, tokio_postgres::NoTls).await?;
+                tokio::spawn(connection);
+                tracing::debug!("🔗 Opened a pooled database connection");
+                client
+            }
+        };
+        Ok(PooledClient {
+            pool: self,
+            client: Some(client),
+            _permit: permit,
+        })
+    }
+}
+
+impl std::ops::Deref for PooledClient<'_> {
+    type Target = tokio_postgres::Client;
+
+    fn deref(&self) -> &Self::Target {
+        self.client.as_ref().expect("held until dropped")
+    }
+}
+
+impl Drop for PooledClient<'_> {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take()
+            && !client.is_closed()
+        {
+            self.pool
+                .idle
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(client);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::io::Write as _;
+
+/// What a crawl records of a directory (`--record-dirs`)
+#[derive(Debug, Default, Clone)]
+struct DirFacts {
+    /// entries directly in the directory
+    entries: u64,
+    /// regular files recorded in its subtree
+    files: u64,
+    /// size of those files
+    bytes: u64,
+    mtime: Option<std::time::SystemTime>,
+}
+
+/// Directories of a crawl, accumulated by the walker threads; file counts and
+/// sizes are rolled up into their ancestors once the walk is done
+#[derive(Debug, Default)]
+pub(crate) struct DirStats {
+    dirs: dashmap::DashMap<std::path::PathBuf, DirFacts>,
+}
+
+impl DirStats {
+    /// A directory the walk entered, with its mtime
+    pub(crate) fn record_dir(&self, path: &std::path::Path, mtime: Option<std::time::SystemTime>) {
+        self.dirs.entry(path.to_path_buf()).or_default().mtime = mtime;
+    }
+
+    /// An entry of any kind below the root, counted in its parent
+    pub(crate) fn record_entry(&self, path: &std::path::Path) {
+        if let Some(parent) = path.parent() {
+            self.dirs.entry(parent.to_path_buf()).or_default().entries += 1;
+        }
+    }
+
+    /// A regular file the crawl recorded, counted in its parent
+    pub(crate) fn record_file(&self, path: &std::path::Path, size: u64) {
+        if let Some(parent) = path.parent() {
+            let mut facts = self.dirs.entry(parent.to_path_buf()).or_default();
+            facts.files += 1;
+            facts.bytes += size;
+        }
+    }
+
+    /// Roll the files up into their ancestors and write one line per
+    /// directory to `tsv_file`, ordered by path: path, entry_count,
+    /// file_count, total_size_bytes, mtime, scan_id. Paths are encrypted like
+    /// the crawl's below `scan_root`, or the walked root holding them.
+    pub(crate) fn write_tsv(
+        self,
+        tsv_file: &std::path::Path,
+        scan_id: i32,
+        roots: &[std::path::PathBuf],
+        options: &crate::crawler::CrawlOptions,
+    ) -> anyhow::Result<u64> {
+        let mut dirs: Vec<(std::path::PathBuf, DirFacts)> = self.dirs.into_iter().collect();
+        // deepest first, so that each directory is complete before its parent
+        dirs.sort_unstable_by_key(|(path, _)| std::cmp::Reverse(path.components().count()));
+        let mut totals: std::collections::HashMap<std::path::PathBuf, DirFacts> =
+            std::collections::HashMap::with_capacity(dirs.len());
+        for (path, facts) in dirs {
+            let facts = match totals.remove(&path) {
+                Some(below) => DirFacts {
+                    files: facts.files + below.files,
+                    bytes: facts.bytes + below.bytes,
+                    ..facts
+                },
+                None => facts,
+            };
+            // ancestors above the walked roots are not directories of the crawl
+            if !roots.iter().any(|root| &path == root)
+                && let Some(parent) = path.parent()
+            {
+                let parent = totals.entry(parent.to_path_buf()).or_default();
+                parent.files += facts.files;
+                parent.bytes += facts.bytes;
+            }
+            totals.insert(path, facts);
+        }
+
+        let mut lines: Vec<(String, String)> = totals
+            .into_iter()
+            .filter(|(path, _)| roots.iter().any(|root| path.starts_with(root)))
+            .map(|(path, facts)| {
+                let path = match &options.path_cipher {
+                    Some(cipher) => {
+                        let scan_root = options
+                            .scan_root
+                            .as_ref()
+                            .or_else(|| roots.iter().find(|root| path.starts_with(root)))
+                            .unwrap_or(&path);
+                        cipher.encrypt_path(scan_root, &path)
+                    }
+                    None => path.display().to_string(),
+                };
+                let line = crate::tsv::Row::new()
+                    .field(&path)
+                    .value(facts.entries)
+                    .value(facts.files)
+                    .value(facts.bytes)
+                    .value(crate::crawler::format_mtime(facts.mtime))
+                    .value(scan_id)
+                    .finish();
+                (path, line)
+            })
+            .collect();
+        lines.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+        let file = std::fs::File::create(tsv_file)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", tsv_file.display(), e))?;
+        let mut out = std::io::BufWriter::new(file);
+        for (_, line) in &lines {
+            out.write_all(line.as_bytes())?;
+        }
+        out.flush()?;
+        Ok(lines.len() as u64)
+    }
+}
+
+/// The directories TSV written next to crawl TSV `tsv_file`
+pub fn dirs_tsv_path(tsv_file: &std::path::Path) -> std::path::PathBuf {
+    tsv_file.with_extension("dirs.tsv")
+}
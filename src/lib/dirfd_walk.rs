@@ -0,0 +1,461 @@
+use std::os::fd::{AsRawFd as _, FromRawFd as _};
+use std::os::unix::ffi::{OsStrExt as _, OsStringExt as _};
+
+/// Directories on the stack kept open; deeper ones close the fd of the
+/// oldest, which is reopened from its child through `..` when the walk
+/// climbs back to it. Bounds the descriptors a 10k-deep tree holds.
+const OPEN_DIRS: usize = 32;
+
+/// What an entry is, not following symlinks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EntryKind {
+    Dir,
+    File,
+    Symlink,
+    Other,
+}
+
+/// An entry found by [`walk`], valid for the duration of the visit
+pub(crate) struct Entry<'a> {
+    pub path: &'a std::path::Path,
+    /// 0 for the root, 1 for its direct entries
+    pub depth: usize,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub mtime: Option<std::time::SystemTime>,
+    dev: libc::dev_t,
+    ino: libc::ino_t,
+    mode: libc::mode_t,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+    /// Directory the entry is in, `None` for the root
+    dir: Option<std::os::fd::BorrowedFd<'a>>,
+    name: &'a std::ffi::CStr,
+}
+
+impl Entry<'_> {
+    /// Open the file for reading, relative to its directory
+    pub fn open(&self) -> std::io::Result<std::fs::File> {
+        let fd = match self.dir {
+            // SAFETY: the name is NUL-terminated and the directory fd open
+            Some(dir) => unsafe {
+                libc::openat(
+                    dir.as_raw_fd(),
+                    self.name.as_ptr(),
+                    libc::O_RDONLY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+                )
+            },
+            None => return std::fs::File::open(self.path),
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: openat returned a descriptor nobody else owns
+        Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+    }
+
+    /// What the hash cache knows the file by
+    // dev_t is signed on macOS
+    #[allow(clippy::unnecessary_cast)]
+    pub fn key(&self) -> crate::hash_cache::FileKey {
+        crate::hash_cache::FileKey {
+            dev: self.dev as u64,
+            ino: self.ino as u64,
+            size: self.size,
+            mtime: self.mtime,
+        }
+    }
+
+    /// Permission bits, owner and group
+    // mode_t is 16 bits on macOS
+    #[allow(clippy::unnecessary_cast)]
+    pub fn ownership(&self) -> crate::crawler::Ownership {
+        crate::crawler::Ownership {
+            mode: self.mode as u32 & 0o7777,
+            uid: self.uid,
+            gid: self.gid,
+        }
+    }
+
+    /// Target of the symlink, read relative to its directory
+    pub fn read_link(&self) -> std::io::Result<std::path::PathBuf> {
+        let Some(dir) = self.dir else {
+            return std::fs::read_link(self.path);
+        };
+        let mut buf = vec![0u8; libc::PATH_MAX as usize];
+        // SAFETY: the name is NUL-terminated, the directory fd open and the
+        // buffer as long as passed
+        let len = unsafe {
+            libc::readlinkat(
+                dir.as_raw_fd(),
+                self.name.as_ptr(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+            )
+        };
+        if len < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        buf.truncate(len as usize);
+        Ok(std::ffi::OsString::from_vec(buf).into())
+    }
+
+    /// Security label of the entry, read through the directory's fd so the
+    /// path length does not matter
+    pub fn label(&self, source: crate::security_label::LabelSource) -> Option<String> {
+        match self.dir {
+            Some(dir) if cfg!(target_os = "linux") => {
+                let mut path =
+                    std::ffi::OsString::from(format!("/proc/self/fd/{}/", dir.as_raw_fd()));
+                path.push(std::ffi::OsStr::from_bytes(self.name.to_bytes()));
+                source.read(std::path::Path::new(&path))
+            }
+            _ => source.read(self.path),
+        }
+    }
+}
+
+/// An entry the walk could not stat, list or descend into
+#[derive(Debug)]
+pub(crate) struct WalkError {
+    pub path: std::path::PathBuf,
+    pub error: std::io::Error,
+}
+
+impl std::fmt::Display for WalkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
+    }
+}
+
+/// A directory on the walk's stack
+struct Frame {
+    /// `None` once closed for deeper directories, see [`OPEN_DIRS`]
+    fd: Option<std::os::fd::OwnedFd>,
+    dev: libc::dev_t,
+    ino: libc::ino_t,
+    path: std::path::PathBuf,
+    depth: usize,
+    /// Subdirectories still to descend into
+    subdirs: std::vec::IntoIter<std::ffi::CString>,
+}
+
+/// Walk `root` depth-first, calling `visit` with every entry below it and
+/// the root itself, except those `overrides` ignore. Every directory is
+/// opened, listed and stat'ed relative to its parent's descriptor (`openat`,
+/// `fstatat`), so the kernel never sees a path longer than one name and
+/// trees nested beyond `PATH_MAX` are walked like any other; the stack is
+/// explicit, so depth costs no recursion. Single-threaded. Directories are not
+/// descended into beyond `max_depth`, onto other filesystems unless
+/// `follow_mounts`, or when `visit` returns `Skip` for them; `Quit` ends
+/// the walk.
+pub(crate) fn walk(
+    root: &std::path::Path,
+    max_depth: Option<usize>,
+    follow_mounts: bool,
+    overrides: &ignore::overrides::Override,
+    mut visit: impl FnMut(Result<Entry<'_>, WalkError>) -> ignore::WalkState,
+) {
+    let error = |path: &std::path::Path, error| WalkError {
+        path: path.to_path_buf(),
+        error,
+    };
+    let root_name = match std::ffi::CString::new(root.as_os_str().as_bytes()) {
+        Ok(name) => name,
+        Err(e) => {
+            visit(Err(error(root, e.into())));
+            return;
+        }
+    };
+    let (fd, stat) = match open_dir(libc::AT_FDCWD, &root_name) {
+        Ok(opened) => opened,
+        Err(e) => {
+            visit(Err(error(root, e)));
+            return;
+        }
+    };
+    let root_dev = stat.st_dev;
+    let state = visit(Ok(Entry {
+        path: root,
+        depth: 0,
+        kind: EntryKind::Dir,
+        size: stat.st_size as u64,
+        mtime: mtime(&stat),
+        dev: stat.st_dev,
+        ino: stat.st_ino,
+        mode: stat.st_mode,
+        uid: stat.st_uid,
+        gid: stat.st_gid,
+        dir: None,
+        name: &root_name,
+    }));
+    if !matches!(state, ignore::WalkState::Continue) {
+        return;
+    }
+    let mut stack = Vec::new();
+    match list(
+        &fd,
+        root,
+        0,
+        root_dev,
+        max_depth,
+        follow_mounts,
+        overrides,
+        &mut visit,
+    ) {
+        Some(subdirs) => stack.push(Frame {
+            fd: Some(fd),
+            dev: stat.st_dev,
+            ino: stat.st_ino,
+            path: root.to_path_buf(),
+            depth: 0,
+            subdirs: subdirs.into_iter(),
+        }),
+        None => return,
+    }
+
+    while let Some(top) = stack.last_mut() {
+        let Some(name) = top.subdirs.next() else {
+            let done = stack.pop().expect("the stack has a top");
+            if let Some(parent) = stack.last_mut()
+                && parent.fd.is_none()
+            {
+                let fd = done.fd.as_ref().expect("the top of the stack is open");
+                match reopen_parent(fd, parent) {
+                    Ok(fd) => parent.fd = Some(fd),
+                    Err(e) => {
+                        // moved while the walk was below it: its remaining
+                        // subdirectories cannot be reached from here
+                        visit(Err(error(&parent.path, e)));
+                        parent.subdirs = Vec::new().into_iter();
+                    }
+                }
+            }
+            continue;
+        };
+        let path = top.path.join(std::ffi::OsStr::from_bytes(name.to_bytes()));
+        let depth = top.depth + 1;
+        let parent = top.fd.as_ref().expect("the top of the stack is open");
+        let (fd, stat) = match open_dir(parent.as_raw_fd(), &name) {
+            Ok(opened) => opened,
+            Err(e) => {
+                if matches!(visit(Err(error(&path, e))), ignore::WalkState::Quit) {
+                    return;
+                }
+                continue;
+            }
+        };
+        let Some(subdirs) = list(
+            &fd,
+            &path,
+            depth,
+            root_dev,
+            max_depth,
+            follow_mounts,
+            overrides,
+            &mut visit,
+        ) else {
+            return;
+        };
+        stack.push(Frame {
+            fd: Some(fd),
+            dev: stat.st_dev,
+            ino: stat.st_ino,
+            path,
+            depth,
+            subdirs: subdirs.into_iter(),
+        });
+        if stack.len() > OPEN_DIRS {
+            let oldest_open = stack.len() - OPEN_DIRS - 1;
+            stack[oldest_open].fd = None;
+        }
+    }
+}
+
+/// Visit the entries of the directory open as `fd`, returning the names of
+/// the subdirectories to descend into; `None` if `visit` quit the walk
+#[allow(clippy::too_many_arguments)]
+fn list(
+    fd: &std::os::fd::OwnedFd,
+    path: &std::path::Path,
+    depth: usize,
+    root_dev: libc::dev_t,
+    max_depth: Option<usize>,
+    follow_mounts: bool,
+    overrides: &ignore::overrides::Override,
+    visit: &mut impl FnMut(Result<Entry<'_>, WalkError>) -> ignore::WalkState,
+) -> Option<Vec<std::ffi::CString>> {
+    let names = match read_names(fd) {
+        Ok(names) => names,
+        Err(e) => {
+            let state = visit(Err(WalkError {
+                path: path.to_path_buf(),
+                error: e,
+            }));
+            return (!matches!(state, ignore::WalkState::Quit)).then(Vec::new);
+        }
+    };
+    let descend = max_depth.is_none_or(|max| depth + 1 < max);
+    let mut subdirs = Vec::new();
+    for name in names {
+        let child = path.join(std::ffi::OsStr::from_bytes(name.to_bytes()));
+        let stat = match stat_at(fd.as_raw_fd(), &name) {
+            Ok(stat) => stat,
+            Err(e) => {
+                let state = visit(Err(WalkError {
+                    path: child,
+                    error: e,
+                }));
+                if matches!(state, ignore::WalkState::Quit) {
+                    return None;
+                }
+                continue;
+            }
+        };
+        let kind = match stat.st_mode & libc::S_IFMT {
+            libc::S_IFDIR => EntryKind::Dir,
+            libc::S_IFREG => EntryKind::File,
+            libc::S_IFLNK => EntryKind::Symlink,
+            _ => EntryKind::Other,
+        };
+        if overrides
+            .matched(&child, kind == EntryKind::Dir)
+            .is_ignore()
+        {
+            continue;
+        }
+        if kind == EntryKind::Dir && !follow_mounts && stat.st_dev != root_dev {
+            continue;
+        }
+        let state = visit(Ok(Entry {
+            path: &child,
+            depth: depth + 1,
+            kind,
+            size: stat.st_size as u64,
+            mtime: mtime(&stat),
+            dev: stat.st_dev,
+            ino: stat.st_ino,
+            mode: stat.st_mode,
+            uid: stat.st_uid,
+            gid: stat.st_gid,
+            dir: Some(std::os::fd::AsFd::as_fd(fd)),
+            name: &name,
+        }));
+        match state {
+            ignore::WalkState::Quit => return None,
+            ignore::WalkState::Continue if kind == EntryKind::Dir && descend => subdirs.push(name),
+            _ => {}
+        }
+    }
+    Some(subdirs)
+}
+
+/// Open the directory `name` relative to `dir`, without following a symlink
+fn open_dir(
+    dir: std::os::fd::RawFd,
+    name: &std::ffi::CStr,
+) -> std::io::Result<(std::os::fd::OwnedFd, libc::stat)> {
+    // SAFETY: the name is NUL-terminated
+    let fd = unsafe {
+        libc::openat(
+            dir,
+            name.as_ptr(),
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: openat returned a descriptor nobody else owns
+    let fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) };
+    let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+    // SAFETY: fstat fills the struct it is given
+    if unsafe { libc::fstat(fd.as_raw_fd(), stat.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: fstat succeeded
+    Ok((fd, unsafe { stat.assume_init() }))
+}
+
+/// Reopen `parent` through the `..` of its child open as `child`, checking
+/// that it is still the directory the walk came from
+fn reopen_parent(
+    child: &std::os::fd::OwnedFd,
+    parent: &Frame,
+) -> std::io::Result<std::os::fd::OwnedFd> {
+    let (fd, stat) = open_dir(child.as_raw_fd(), c"..")?;
+    if stat.st_dev != parent.dev || stat.st_ino != parent.ino {
+        return Err(std::io::Error::other("directory moved during the walk"));
+    }
+    Ok(fd)
+}
+
+/// Stat `name` relative to `dir`, not following a symlink
+fn stat_at(dir: std::os::fd::RawFd, name: &std::ffi::CStr) -> std::io::Result<libc::stat> {
+    let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+    // SAFETY: the name is NUL-terminated and fstatat fills the struct it is given
+    if unsafe {
+        libc::fstatat(
+            dir,
+            name.as_ptr(),
+            stat.as_mut_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    } != 0
+    {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: fstatat succeeded
+    Ok(unsafe { stat.assume_init() })
+}
+
+/// Names of the entries of the directory open as `fd`, without `.` and `..`
+fn read_names(fd: &std::os::fd::OwnedFd) -> std::io::Result<Vec<std::ffi::CString>> {
+    // closedir closes the descriptor it was opened on, so it gets a copy
+    let dup = fd.try_clone()?;
+    // SAFETY: fdopendir takes over the descriptor
+    let dir = unsafe { libc::fdopendir(std::os::fd::IntoRawFd::into_raw_fd(dup)) };
+    if dir.is_null() {
+        return Err(std::io::Error::last_os_error());
+    }
+    let mut names = Vec::new();
+    let result = loop {
+        clear_errno();
+        // SAFETY: `dir` is an open directory stream
+        let ent = unsafe { libc::readdir(dir) };
+        if ent.is_null() {
+            let error = std::io::Error::last_os_error();
+            break match error.raw_os_error() {
+                Some(0) | None => Ok(()),
+                Some(_) => Err(error),
+            };
+        }
+        // SAFETY: readdir returned an entry with a NUL-terminated name
+        let name = unsafe { std::ffi::CStr::from_ptr((*ent).d_name.as_ptr()) };
+        if name != c"." && name != c".." {
+            names.push(name.to_owned());
+        }
+    };
+    // SAFETY: `dir` is open and not used afterwards
+    unsafe { libc::closedir(dir) };
+    result.map(|()| names)
+}
+
+fn clear_errno() {
+    // SAFETY: the thread's errno is always writable
+    unsafe {
+        #[cfg(target_os = "linux")]
+        {
+            *libc::__errno_location() = 0;
+        }
+        #[cfg(target_os = "macos")]
+        {
+            *libc::__error() = 0;
+        }
+    }
+}
+
+fn mtime(stat: &libc::stat) -> Option<std::time::SystemTime> {
+    let secs = u64::try_from(stat.st_mtime).ok()?;
+    Some(std::time::UNIX_EPOCH + std::time::Duration::new(secs, stat.st_mtime_nsec as u32))
+}
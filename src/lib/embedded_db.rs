@@ -0,0 +1,178 @@
+use crate::process::run_command;
+use crate::{data, db, pipeline};
+
+/// Role and database of the embedded cluster
+const EMBEDDED_USER: &str = "fs_delta_tracker";
+const EMBEDDED_DATABASE: &str = "fs_delta_tracker";
+
+/// A PostgreSQL cluster kept in a local data directory and run for the
+/// duration of the process, so small deployments need no database server.
+///
+/// The cluster only listens on a Unix socket inside its data directory and is
+/// stopped again when dropped, unless it was already running when started.
+#[derive(Debug)]
+pub struct EmbeddedPostgres {
+    data_dir: std::path::PathBuf,
+    port: u16,
+    bin_dir: Option<std::path::PathBuf>,
+    /// Whether this process started the server and must stop it
+    owned: bool,
+}
+
+impl EmbeddedPostgres {
+    /// Initialize `data_dir` on first use, start the server and make sure the
+    /// tracker's database and schema exist.
+    ///
+    /// `bin_dir` is the directory of `initdb` / `pg_ctl` (default: `PATH`).
+    #[tracing::instrument]
+    pub async fn start(
+        data_dir: &std::path::Path,
+        port: u16,
+        bin_dir: Option<&std::path::Path>,
+    ) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(data_dir)?;
+        let mut embedded = Self {
+            data_dir: data_dir.canonicalize()?,
+            port,
+            bin_dir: bin_dir.map(|p| p.to_path_buf()),
+            owned: false,
+        };
+
+        if !embedded.data_dir.join("PG_VERSION").exists() {
+            tracing::info!(
+                "🐘 Initializing embedded database in {}",
+                embedded.data_dir.display()
+            );
+            let mut initdb = embedded.command("initdb");
+            initdb.arg("-D").arg(&embedded.data_dir).args([
+                "-U",
+                EMBEDDED_USER,
+                "--auth=trust",
+                "-E",
+                "UTF8",
+            ]);
+            run_command(initdb).await?;
+        }
+
+        let mut status = embedded.command("pg_ctl");
+        status.arg("status").arg("-D").arg(&embedded.data_dir);
+        if run_command(status).await.is_ok() {
+            tracing::info!("🐘 Embedded database already running, reusing it");
+        } else {
+            tracing::info!("🐘 Starting embedded database on port {}", port);
+            let mut pg_ctl = embedded.command("pg_ctl");
+            pg_ctl
+                .args(["start", "-w", "-D"])
+                .arg(&embedded.data_dir)
+                .arg("-l")
+                .arg(embedded.data_dir.join("postgres.log"))
+                .arg("-o")
+                .arg(format!(
+                    "-p {} -k {} -c listen_addresses=''",
+                    port,
+                    embedded.data_dir.display()
+                ));
+            run_command(pg_ctl).await?;
+            embedded.owned = true;
+        }
+
+        embedded.ensure_database().await?;
+        Ok(embedded)
+    }
+
+    /// Connection string for the tracker's database
+    pub fn database_url(&self) -> String {
+        self.connection_string(EMBEDDED_DATABASE)
+    }
+
+    fn connection_string(&self, dbname: &str) -> String {
+        format!(
+            "host={} port={} user={} dbname={}",
+            self.data_dir.display(),
+            self.port,
+            EMBEDDED_USER,
+            dbname
+        )
+    }
+
+    fn command(&self, program: &str) -> tokio::process::Command {
+        match &self.bin_dir {
+            Some(bin_dir) => tokio::process::Command::new(bin_dir.join(program)),
+            None => tokio::process::Command::new(program),
+        }
+    }
+
+    /// Create the database and its schema on first use (or after an
+    /// interrupted first use)
+    async fn ensure_database(&self) -> anyhow::Result<()> {
+        let (client, connection) =
+            tokio_postgres::connect(&self.connection_string("postgres"), tokio_postgres::NoTls)
+                .await?;
+        tokio::spawn(connection);
+        let exists = client
+            .query_opt(
+                "SELECT 1 FROM pg_database WHERE datname = $1",
+                &[&EMBEDDED_DATABASE],
+            )
+            .await?
+            .is_some();
+        if !exists {
+            tracing::info!("🐘 Creating database {}", EMBEDDED_DATABASE);
+            client
+                .batch_execute(&format!("CREATE DATABASE {}", EMBEDDED_DATABASE))
+                .await?;
+        }
+
+        let (client, connection) =
+            tokio_postgres::connect(&self.database_url(), tokio_postgres::NoTls).await?;
+        tokio::spawn(connection);
+        let initialized: bool = client
+            .query_one(
+                "SELECT to_regclass('filesystem.scan_runs') IS NOT NULL",
+                &[],
+            )
+            .await?
+            .get(0);
+        if initialized {
+            return Ok(());
+        }
+
+        tracing::info!("🐘 Initializing schema");
+        db::execute_sql_template_str(&client, pipeline::sql_template("init_db.sql"), None).await?;
+        db::execute_sql_template_str(&client, pipeline::sql_template("create_views.sql"), None)
+            .await?;
+        data::audit(
+            &client,
+            "database_initialized",
+            None,
+            serde_json::json!({ "embedded": self.data_dir.to_string_lossy() }),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+impl Drop for EmbeddedPostgres {
+    fn drop(&mut self) {
+        if !self.owned {
+            return;
+        }
+        tracing::info!("🐘 Stopping embedded database");
+        let program = match &self.bin_dir {
+            Some(bin_dir) => bin_dir.join("pg_ctl"),
+            None => std::path::PathBuf::from("pg_ctl"),
+        };
+        let result = std::process::Command::new(program)
+            .args(["stop", "-w", "-m", "fast", "-D"])
+            .arg(&self.data_dir)
+            .output();
+        match result {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => tracing::warn!(
+                "⚠️ Failed to stop embedded database: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            Err(e) => tracing::warn!("⚠️ Failed to stop embedded database: {}", e),
+        }
+    }
+}
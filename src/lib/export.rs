@@ -0,0 +1,241 @@
+use futures::StreamExt;
+use tokio::io::AsyncWriteExt;
+
+/// What an export contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportKind {
+    /// The file_changes rows of one scan
+    Changes,
+    /// The current filesystem.files rows under the scan's root
+    Snapshot,
+}
+
+impl std::str::FromStr for ExportKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "changes" => Ok(ExportKind::Changes),
+            "snapshot" => Ok(ExportKind::Snapshot),
+            other => anyhow::bail!("Unknown export kind: {}", other),
+        }
+    }
+}
+
+impl std::fmt::Display for ExportKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportKind::Changes => write!(f, "changes"),
+            ExportKind::Snapshot => write!(f, "snapshot"),
+        }
+    }
+}
+
+/// Columns of an export, in order
+fn export_columns(kind: ExportKind) -> &'static [&'static str] {
+    match kind {
+        ExportKind::Changes => &[
+            "file_path",
+            "change_type",
+            "old_size_bytes",
+            "new_size_bytes",
+            "old_mtime",
+            "new_mtime",
+            "old_file_type",
+        ],
+        ExportKind::Snapshot => &[
+            "file_name",
+            "file_type",
+            "file_path",
+            "file_size_bytes",
+            "file_mtime",
+        ],
+    }
+}
+
+fn export_select(kind: ExportKind, scan_id: i32) -> String {
+    match kind {
+        ExportKind::Changes => format!(
+            "SELECT file_path, change_type, old_size_bytes, new_size_bytes,
+                    old_mtime, new_mtime, old_file_type
+             FROM filesystem.file_changes
+             WHERE scan_id = {}
+             ORDER BY file_path",
+            scan_id
+        ),
+        ExportKind::Snapshot => format!(
+            "SELECT f.file_name, f.file_type, f.file_path, f.file_size_bytes, f.file_mtime
+             FROM filesystem.files AS f
+             JOIN filesystem.scan_runs AS r ON r.scan_id = {}
+             WHERE f.file_path LIKE r.scan_root || '/%'
+             ORDER BY f.file_path",
+            scan_id
+        ),
+    }
+}
+
+fn export_query(kind: ExportKind, scan_id: i32) -> String {
+    format!(
+        "COPY ({}) TO STDOUT WITH (FORMAT csv, DELIMITER E'\\t', NULL '', HEADER TRUE)",
+        export_select(kind, scan_id)
+    )
+}
+
+/// Write an export of `scan_id` to `out` as a tab-separated file with a
+/// header row, returning the number of bytes written.
+///
+/// With `null`, every field (header included) is instead terminated by a NUL,
+/// a record being as many fields as the export has columns, so that any path
+/// survives `xargs -0 -n <columns>`. NULLs are written as empty fields.
+#[tracing::instrument(skip(client))]
+pub async fn export_scan(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    kind: ExportKind,
+    out: &std::path::Path,
+    null: bool,
+) -> anyhow::Result<u64> {
+    // fails early for unknown scans instead of writing an empty export
+    crate::data::get_scan_status(client, scan_id).await?;
+    if null {
+        return export_null_delimited(client, scan_id, kind, out).await;
+    }
+
+    let stream = client.copy_out(&export_query(kind, scan_id)).await?;
+    futures::pin_mut!(stream);
+
+    let mut file = tokio::io::BufWriter::new(tokio::fs::File::create(out).await?);
+    let mut written = 0u64;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        written += chunk.len() as u64;
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+
+    Ok(written)
+}
+
+async fn export_null_delimited(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    kind: ExportKind,
+    out: &std::path::Path,
+) -> anyhow::Result<u64> {
+    let columns = export_columns(kind);
+    let query = format!(
+        "SELECT {} FROM ({}) AS export",
+        columns
+            .iter()
+            .map(|c| format!("COALESCE({}::text, '')", c))
+            .collect::<Vec<_>>()
+            .join(", "),
+        export_select(kind, scan_id)
+    );
+    let rows = client
+        .query_raw(
+            &query,
+            std::iter::empty::<&(dyn tokio_postgres::types::ToSql + Sync)>(),
+        )
+        .await?;
+    futures::pin_mut!(rows);
+
+    let mut file = tokio::io::BufWriter::new(tokio::fs::File::create(out).await?);
+    let mut written = 0u64;
+    for column in columns {
+        file.write_all(column.as_bytes()).await?;
+        file.write_all(b"\0").await?;
+        written += column.len() as u64 + 1;
+    }
+    while let Some(row) = rows.next().await {
+        let row = row?;
+        for i in 0..columns.len() {
+            let field: &str = row.get(i);
+            file.write_all(field.as_bytes()).await?;
+            file.write_all(b"\0").await?;
+            written += field.len() as u64 + 1;
+        }
+    }
+    file.flush().await?;
+
+    Ok(written)
+}
+
+/// How [`write_manifest`] lists paths
+#[derive(Debug, Clone, Default)]
+pub struct ManifestOptions {
+    /// Terminate paths with NUL instead of a newline (`tar --null`, `rsync --from0`)
+    pub null: bool,
+    /// List paths relative to the scan's root, for `rsync --files-from` from it
+    pub relative: bool,
+    /// Key the scan's paths are encrypted with
+    pub path_cipher: Option<crate::path_cipher::PathCipher>,
+}
+
+/// Paths listed and left out by [`write_manifest`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ManifestStats {
+    pub paths: u64,
+    /// paths containing a newline, which a newline-delimited list cannot hold
+    pub skipped: u64,
+}
+
+/// Write the paths of a scan's changes of the given types to `out`, one per
+/// line (or NUL-terminated), e.g. as the file list of an incremental backup
+#[tracing::instrument(skip(client, options))]
+pub async fn write_manifest(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    change_types: &[String],
+    options: &ManifestOptions,
+    out: &std::path::Path,
+) -> anyhow::Result<ManifestStats> {
+    let (scan_root, path_key_id) = crate::data::get_scan_root(client, scan_id).await?;
+    let cipher = crate::path_cipher::cipher_for_scan(
+        scan_id,
+        path_key_id.as_deref(),
+        options.path_cipher.as_ref(),
+    )?;
+
+    let query = "
+        SELECT file_path
+        FROM filesystem.file_changes
+        WHERE scan_id = $1 AND change_type = ANY($2)
+        ORDER BY file_path";
+    let rows = client
+        .query_raw(
+            query,
+            [
+                &scan_id as &(dyn tokio_postgres::types::ToSql + Sync),
+                &change_types,
+            ],
+        )
+        .await?;
+    futures::pin_mut!(rows);
+
+    let prefix = format!("{}/", scan_root.trim_end_matches('/'));
+    let terminator = if options.null { b'\0' } else { b'\n' };
+    let mut file = tokio::io::BufWriter::new(tokio::fs::File::create(out).await?);
+    let mut stats = ManifestStats::default();
+    while let Some(row) = rows.next().await {
+        let path: String = row?.get(0);
+        let path = match cipher {
+            Some(cipher) => cipher.decrypt_path(&path),
+            None => path,
+        };
+        let path = match path.strip_prefix(&prefix) {
+            Some(relative) if options.relative => relative,
+            _ => path.as_str(),
+        };
+        if !options.null && path.contains('\n') {
+            stats.skipped += 1;
+            continue;
+        }
+        file.write_all(path.as_bytes()).await?;
+        file.write_all(&[terminator]).await?;
+        stats.paths += 1;
+    }
+    file.flush().await?;
+
+    Ok(stats)
+}
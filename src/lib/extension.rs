@@ -0,0 +1,59 @@
+/// Multi-part extensions recognized by default
+pub const DEFAULT_MULTI_PART_EXTENSIONS: &[&str] = &[
+    "tar.gz", "tar.bz2", "tar.xz", "tar.zst", "nii.gz", "fastq.gz", "fq.gz", "vcf.gz",
+];
+
+/// Rules turning a file name into the `file_type` recorded for it, so that
+/// statistics are not split across "TXT" / "txt" or "gz" / "tar.gz"
+#[derive(Debug, Clone)]
+pub struct ExtensionRules {
+    /// Lowercase every extension
+    pub lowercase: bool,
+    /// Extensions spanning several dots, e.g. "tar.gz"; matched case-insensitively
+    pub multi_part: Vec<String>,
+    /// Recorded for files without a (UTF-8) extension
+    pub unknown: String,
+}
+
+impl Default for ExtensionRules {
+    fn default() -> Self {
+        Self {
+            lowercase: true,
+            multi_part: DEFAULT_MULTI_PART_EXTENSIONS
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+            unknown: "unknown".to_string(),
+        }
+    }
+}
+
+impl ExtensionRules {
+    /// Return the normalized extension of the file at `path`
+    pub fn normalize(&self, path: &std::path::Path) -> String {
+        let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+            return self.unknown.clone();
+        };
+        let lower_name = file_name.to_lowercase();
+
+        // Longest multi-part extension first, so "tar.gz" wins over a shorter "gz" rule
+        let mut multi_part: Vec<&String> = self.multi_part.iter().collect();
+        multi_part.sort_by_key(|e| std::cmp::Reverse(e.len()));
+        for ext in multi_part {
+            let suffix = format!(".{}", ext.to_lowercase());
+            if lower_name.len() > suffix.len() && lower_name.ends_with(&suffix) {
+                let start = file_name.len() - ext.len();
+                return match file_name.get(start..) {
+                    Some(original) if !self.lowercase => original.to_string(),
+                    _ => ext.to_lowercase(),
+                };
+            }
+        }
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if self.lowercase => ext.to_lowercase(),
+            Some(ext) => ext.to_string(),
+            None => self.unknown.clone(),
+        }
+    }
+}
@@ -0,0 +1,475 @@
+use std::io::{BufRead, Read, Seek, Write};
+
+use crate::snapshot_diff::DiffEntries;
+
+/// First bytes of a change log, followed by the watcher's session ID
+const HEADER_PREFIX: &str = "fs-delta-tracker fanotify ";
+
+/// Record kinds of the change log: an entry created, modified, removed or
+/// renamed, a directory created or renamed into place, a directory removed or
+/// renamed away
+const ENTRY: u8 = b'f';
+const NEW_DIR: u8 = b'd';
+const OLD_DIR: u8 = b'x';
+
+/// Canonical root, change log and marker file of `data_root`, kept next to its
+/// lock file
+fn log_paths(
+    lock_dir: &std::path::Path,
+    data_root: &std::path::Path,
+) -> (std::path::PathBuf, std::path::PathBuf, std::path::PathBuf) {
+    let (root, lock_path) = crate::lock::lock_path(lock_dir, data_root);
+    (
+        root,
+        lock_path.with_extension("fanotify.log"),
+        lock_path.with_extension("fanotify.mark"),
+    )
+}
+
+/// Session ID and length of the header line of a change log
+fn read_header(file: &mut std::fs::File) -> anyhow::Result<(String, u64)> {
+    file.rewind()?;
+    let mut line = Vec::new();
+    std::io::BufReader::new(&mut *file)
+        .take(256)
+        .read_until(b'\n', &mut line)?;
+    let session = std::str::from_utf8(&line)
+        .ok()
+        .and_then(|line| line.strip_prefix(HEADER_PREFIX))
+        .and_then(|rest| rest.strip_suffix('\n'))
+        .ok_or_else(|| anyhow::anyhow!("Not a fanotify change log"))?;
+    Ok((session.to_string(), line.len() as u64))
+}
+
+fn parse_marker(marker: &str) -> anyhow::Result<(&str, u64)> {
+    marker
+        .rsplit_once(':')
+        .and_then(|(session, offset)| Some((session, offset.parse().ok()?)))
+        .ok_or_else(|| anyhow::anyhow!("Invalid fanotify marker: {}", marker))
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> std::path::PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    std::path::PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> std::path::PathBuf {
+    std::path::PathBuf::from(String::from_utf8_lossy(bytes).to_string())
+}
+
+/// The session of the `fanotify_watch` of `data_root` and the end of the last
+/// complete record of its change log, as `<session>:<offset>`. Fails if no
+/// watcher is running for the root.
+///
+/// The marker is also written to the root's marker file, telling the watcher
+/// that the changes it logs from now on belong to the next scan.
+pub fn mark(lock_dir: &std::path::Path, data_root: &std::path::Path) -> anyhow::Result<String> {
+    let (root, log_path, mark_path) = log_paths(lock_dir, data_root);
+    let mut file = std::fs::File::open(&log_path).map_err(|e| {
+        anyhow::anyhow!(
+            "No fanotify change log for {} ({}): {}",
+            root.display(),
+            log_path.display(),
+            e
+        )
+    })?;
+    match file.try_lock_shared() {
+        Ok(()) => anyhow::bail!("fanotify_watch is not running for {}", root.display()),
+        Err(std::fs::TryLockError::WouldBlock) => {}
+        Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+    }
+    let (session, header_len) = read_header(&mut file)?;
+
+    // a record still being appended is left to the next scan
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(8192).max(header_len);
+    let mut tail = Vec::new();
+    file.seek(std::io::SeekFrom::Start(start))?;
+    (&mut file).take(len - start).read_to_end(&mut tail)?;
+    let offset = tail
+        .iter()
+        .rposition(|b| *b == 0)
+        .map(|end| start + end as u64 + 1)
+        .unwrap_or(start);
+
+    let marker = format!("{}:{}", session, offset);
+    std::fs::write(
+        &mark_path,
+        format!("{} {}\n", marker, chrono::Utc::now().to_rfc3339()),
+    )?;
+    Ok(marker)
+}
+
+/// The entries of `data_root` named by the change log between the `previous`
+/// and `current` markers. Fails if the watcher was restarted in between (or
+/// started a new log after a queue overflow), as changes may have been missed.
+pub fn diff(
+    lock_dir: &std::path::Path,
+    data_root: &std::path::Path,
+    previous: &str,
+    current: &str,
+) -> anyhow::Result<DiffEntries> {
+    let (previous_session, from) = parse_marker(previous)?;
+    let (session, to) = parse_marker(current)?;
+    anyhow::ensure!(
+        previous_session == session && from <= to,
+        "fanotify_watch started a new change log since the previous scan ({} -> {})",
+        previous_session,
+        session
+    );
+
+    let (root, log_path, _) = log_paths(lock_dir, data_root);
+    let mut file = std::fs::File::open(&log_path)?;
+    anyhow::ensure!(
+        read_header(&mut file)?.0 == session,
+        "fanotify_watch started a new change log since this scan began"
+    );
+    let mut records = Vec::new();
+    file.seek(std::io::SeekFrom::Start(from))?;
+    (&mut file).take(to - from).read_to_end(&mut records)?;
+    // the log may have been restarted while it was read
+    anyhow::ensure!(
+        records.len() as u64 == to - from && read_header(&mut file)?.0 == session,
+        "fanotify_watch started a new change log while it was read"
+    );
+
+    let mut entries = DiffEntries::default();
+    for record in records.split(|b| *b == 0).filter(|r| !r.is_empty()) {
+        entries.lines += 1;
+        let (kind, path) = record.split_first().expect("records are not empty");
+        let path = path_from_bytes(path);
+        // logged below the canonical root, restated below the root as given
+        let relative = match path.strip_prefix(&root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative,
+            _ => continue,
+        };
+        let path = data_root.join(relative);
+        match *kind {
+            ENTRY => entries.paths.insert(path),
+            NEW_DIR => entries.new_dirs.insert(path),
+            OLD_DIR => entries.old_dirs.insert(path),
+            _ => anyhow::bail!("Unexpected record in {}", log_path.display()),
+        };
+    }
+    Ok(entries)
+}
+
+#[cfg(target_os = "linux")]
+pub use watcher::Watcher;
+
+#[cfg(target_os = "linux")]
+mod watcher {
+    use super::*;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::ffi::OsStrExt;
+
+    /// Entries logged since the last marker are not logged again until the
+    /// next one; past this many, the oldest are forgotten
+    const MAX_SEEN: usize = 1_000_000;
+
+    const EVENT_METADATA_LEN: usize = std::mem::size_of::<libc::fanotify_event_metadata>();
+
+    /// Events reported for the entries of the watched filesystem
+    const EVENTS: u64 = libc::FAN_CREATE
+        | libc::FAN_DELETE
+        | libc::FAN_MOVED_FROM
+        | libc::FAN_MOVED_TO
+        | libc::FAN_MODIFY
+        | libc::FAN_ATTRIB
+        | libc::FAN_ONDIR;
+
+    /// Privileged watcher logging the entries of a root changed on its
+    /// filesystem, for scans to restate with `--snapshot-diff fanotify`.
+    ///
+    /// Uses a fanotify filesystem mark reporting the changed entries by
+    /// directory file handle and name (Linux 5.9 or later), which needs
+    /// `CAP_SYS_ADMIN`, and resolves the handles with `open_by_handle_at`,
+    /// which needs `CAP_DAC_READ_SEARCH`.
+    #[derive(Debug)]
+    pub struct Watcher {
+        root: std::path::PathBuf,
+        log_path: std::path::PathBuf,
+        mark_path: std::path::PathBuf,
+        max_log_bytes: std::sync::Arc<std::sync::atomic::AtomicU64>,
+        fanotify: std::os::fd::OwnedFd,
+        mount: std::fs::File,
+        log: std::fs::File,
+        session: String,
+        last_mark: Option<Vec<u8>>,
+        seen: std::collections::HashSet<Vec<u8>>,
+    }
+
+    impl Watcher {
+        /// Mark the filesystem of `data_root` and start a new change log,
+        /// failing if another watcher holds it. `max_log_bytes` may be
+        /// changed while the watcher runs.
+        pub fn start(
+            lock_dir: &std::path::Path,
+            data_root: &std::path::Path,
+            max_log_bytes: std::sync::Arc<std::sync::atomic::AtomicU64>,
+        ) -> anyhow::Result<Self> {
+            std::fs::create_dir_all(lock_dir)?;
+            let (root, log_path, mark_path) = log_paths(lock_dir, data_root);
+
+            // SAFETY: plain syscall; the descriptor is owned from here on
+            let fd = unsafe {
+                libc::fanotify_init(
+                    libc::FAN_CLASS_NOTIF
+                        | libc::FAN_CLOEXEC
+                        | libc::FAN_UNLIMITED_QUEUE
+                        | libc::FAN_REPORT_DFID_NAME,
+                    (libc::O_RDONLY | libc::O_LARGEFILE) as u32,
+                )
+            };
+            if fd < 0 {
+                anyhow::bail!(
+                    "fanotify_init failed: {} (needs Linux 5.9 or later and CAP_SYS_ADMIN)",
+                    std::io::Error::last_os_error()
+                );
+            }
+            // SAFETY: `fd` was just opened and is not owned elsewhere
+            let fanotify = unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) };
+
+            let root_c = std::ffi::CString::new(root.as_os_str().as_bytes())?;
+            // SAFETY: `root_c` is a valid NUL-terminated path
+            let marked = unsafe {
+                libc::fanotify_mark(
+                    fanotify.as_raw_fd(),
+                    libc::FAN_MARK_ADD | libc::FAN_MARK_FILESYSTEM,
+                    EVENTS,
+                    libc::AT_FDCWD,
+                    root_c.as_ptr(),
+                )
+            };
+            if marked < 0 {
+                anyhow::bail!(
+                    "fanotify_mark of the filesystem of {} failed: {}",
+                    root.display(),
+                    std::io::Error::last_os_error()
+                );
+            }
+
+            let log = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&log_path)?;
+            match log.try_lock() {
+                Ok(()) => {}
+                Err(std::fs::TryLockError::WouldBlock) => anyhow::bail!(
+                    "Another fanotify_watch is running for {} ({})",
+                    root.display(),
+                    log_path.display()
+                ),
+                Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+            }
+
+            let mut watcher = Self {
+                mount: std::fs::File::open(&root)?,
+                root,
+                log_path,
+                mark_path,
+                max_log_bytes,
+                fanotify,
+                log,
+                session: String::new(),
+                last_mark: None,
+                seen: Default::default(),
+            };
+            // after the mark, so nothing is missed from the start of the log
+            watcher.new_session()?;
+            Ok(watcher)
+        }
+
+        /// Path of the change log
+        pub fn log_path(&self) -> &std::path::Path {
+            &self.log_path
+        }
+
+        /// Canonical root being watched
+        pub fn root(&self) -> &std::path::Path {
+            &self.root
+        }
+
+        /// Start a new change log, which the next scan cannot diff against
+        fn new_session(&mut self) -> anyhow::Result<()> {
+            self.session = format!(
+                "{}-{}",
+                std::process::id(),
+                chrono::Utc::now().timestamp_micros()
+            );
+            self.log.set_len(0)?;
+            self.log.rewind()?;
+            writeln!(self.log, "{}{}", HEADER_PREFIX, self.session)?;
+            self.seen.clear();
+            tracing::info!(
+                "📒 Logging changes under {} to {} (session {})",
+                self.root.display(),
+                self.log_path.display(),
+                self.session
+            );
+            Ok(())
+        }
+
+        /// Path of the directory with file handle `handle`, or `None` if it
+        /// is gone
+        fn resolve(&self, handle: &[u8]) -> Option<std::path::PathBuf> {
+            // SAFETY: `handle` is a `struct file_handle` as reported by the
+            // kernel, which only reads it
+            let fd = unsafe {
+                libc::syscall(
+                    libc::SYS_open_by_handle_at,
+                    self.mount.as_raw_fd(),
+                    handle.as_ptr(),
+                    libc::O_PATH | libc::O_CLOEXEC,
+                )
+            };
+            if fd < 0 {
+                return None;
+            }
+            // SAFETY: `fd` was just opened and is not owned elsewhere
+            let fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(fd as i32) };
+            let path = std::fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd())).ok()?;
+            (!path.as_os_str().as_bytes().ends_with(b" (deleted)")).then_some(path)
+        }
+
+        /// Log records for the event at the start of `event`, reporting
+        /// whether the queue overflowed
+        fn add_event(&mut self, event: &[u8], out: &mut Vec<u8>) -> bool {
+            let mask = u64::from_ne_bytes(event[8..16].try_into().expect("8 bytes"));
+            if mask & libc::FAN_Q_OVERFLOW != 0 {
+                return true;
+            }
+            let metadata_len = u16::from_ne_bytes([event[6], event[7]]) as usize;
+
+            // info records: type, padding, length, fsid, file handle, name
+            let mut info = &event[metadata_len.min(event.len())..];
+            while info.len() >= 4 {
+                let info_len = (u16::from_ne_bytes([info[2], info[3]]) as usize).min(info.len());
+                let (record, rest) = info.split_at(info_len);
+                info = rest;
+                if record[0] != libc::FAN_EVENT_INFO_TYPE_DFID_NAME || record.len() < 20 {
+                    if info_len == 0 {
+                        break;
+                    }
+                    continue;
+                }
+                let handle = &record[12..];
+                let handle_bytes = u32::from_ne_bytes(handle[..4].try_into().expect("4 bytes"));
+                let handle_len = (8 + handle_bytes as usize).min(handle.len());
+                let name = &handle[handle_len..];
+                let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+                if name.is_empty() || name == b"." {
+                    continue;
+                }
+                let Some(dir) = self.resolve(&handle[..handle_len]) else {
+                    // its entries are logged with its own removal
+                    continue;
+                };
+                let path = dir.join(std::ffi::OsStr::from_bytes(name));
+                if !path.starts_with(&self.root) || path == self.root {
+                    continue;
+                }
+
+                let mut kinds = Vec::new();
+                if mask & libc::FAN_ONDIR == 0 {
+                    kinds.push(ENTRY);
+                } else {
+                    if mask & (libc::FAN_DELETE | libc::FAN_MOVED_FROM) != 0 {
+                        kinds.push(OLD_DIR);
+                    }
+                    if mask & (libc::FAN_CREATE | libc::FAN_MOVED_TO) != 0 {
+                        kinds.push(NEW_DIR);
+                    }
+                }
+                for kind in kinds {
+                    let mut record = vec![kind];
+                    record.extend_from_slice(path.as_os_str().as_bytes());
+                    record.push(0);
+                    if self.seen.len() >= MAX_SEEN {
+                        self.seen.clear();
+                    }
+                    if self.seen.insert(record.clone()) {
+                        out.extend_from_slice(&record);
+                    }
+                }
+            }
+            false
+        }
+
+        /// A scan took a marker since the last check: entries seen before
+        /// must be logged again, for the next scan
+        fn check_mark(&mut self) {
+            let mark = std::fs::read(&self.mark_path).ok();
+            if mark != self.last_mark {
+                self.seen.clear();
+                self.last_mark = mark;
+            }
+        }
+
+        /// Log changes until an error occurs
+        pub fn run(mut self) -> anyhow::Result<()> {
+            let mut buffer = vec![0u8; 256 * 1024];
+            let mut out = Vec::new();
+            loop {
+                // SAFETY: `buffer` is valid for writes of its length
+                let read = unsafe {
+                    libc::read(
+                        self.fanotify.as_raw_fd(),
+                        buffer.as_mut_ptr().cast(),
+                        buffer.len(),
+                    )
+                };
+                if read < 0 {
+                    let e = std::io::Error::last_os_error();
+                    if e.kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(anyhow::anyhow!("Failed to read fanotify events: {}", e));
+                }
+                // after the read: events of changes made after a marker
+                // are never mistaken for ones logged before it
+                self.check_mark();
+
+                let mut events = &buffer[..read as usize];
+                let mut overflowed = false;
+                while events.len() >= EVENT_METADATA_LEN {
+                    let event_len = (u32::from_ne_bytes(events[..4].try_into().expect("4 bytes"))
+                        as usize)
+                        .clamp(EVENT_METADATA_LEN, events.len());
+                    let (event, rest) = events.split_at(event_len);
+                    events = rest;
+                    overflowed |= self.add_event(event, &mut out);
+                }
+
+                if overflowed {
+                    tracing::warn!(
+                        "⚠️ fanotify queue overflowed, starting a new change log: the next scan of {} crawls it",
+                        self.root.display()
+                    );
+                    out.clear();
+                    self.new_session()?;
+                    continue;
+                }
+                if !out.is_empty() {
+                    self.log.write_all(&out)?;
+                    out.clear();
+                }
+                let max_log_bytes = self
+                    .max_log_bytes
+                    .load(std::sync::atomic::Ordering::Relaxed);
+                if self.log.metadata()?.len() > max_log_bytes {
+                    tracing::warn!(
+                        "⚠️ Change log over {} bytes, starting a new one: the next scan of {} crawls it",
+                        max_log_bytes,
+                        self.root.display()
+                    );
+                    self.new_session()?;
+                }
+            }
+        }
+    }
+}
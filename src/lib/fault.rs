@@ -0,0 +1,187 @@
+/// Points where a build with the `testing` feature can be made to fail on
+/// purpose, so crash recovery and resume can be tested deterministically.
+/// Binaries arm them from `INJECT_FAULTS` (see [`arm_from_env`]), tests with
+/// [`arm`]; without the feature they are never armed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultPoint {
+    /// `writer_fail=N`: the crawl's TSV writer fails after writing N lines
+    WriterFail,
+    /// `copy_disconnect=N`: the load fails as if the database connection was
+    /// lost after N rows were sent, leaving its open chunk uncommitted
+    CopyDisconnect,
+    /// `process_error`: processing fails right before its transaction commits
+    ProcessError,
+    /// `inotify_stall`: the watcher's event handler stops taking events while
+    /// armed, so that the kernel's queue overflows
+    InotifyStall,
+}
+
+/// The points armed, with their counts
+#[cfg(feature = "testing")]
+static ARMED: std::sync::Mutex<Vec<(FaultPoint, u64)>> = std::sync::Mutex::new(Vec::new());
+
+impl FaultPoint {
+    pub const ALL: [FaultPoint; 4] = [
+        FaultPoint::WriterFail,
+        FaultPoint::CopyDisconnect,
+        FaultPoint::ProcessError,
+        FaultPoint::InotifyStall,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FaultPoint::WriterFail => "writer_fail",
+            FaultPoint::CopyDisconnect => "copy_disconnect",
+            FaultPoint::ProcessError => "process_error",
+            FaultPoint::InotifyStall => "inotify_stall",
+        }
+    }
+
+    /// The count this point is armed with (0 when given without one), or
+    /// `None` when it is not armed
+    pub fn armed(self) -> Option<u64> {
+        #[cfg(feature = "testing")]
+        {
+            ARMED
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .iter()
+                .rev()
+                .find(|(point, _)| *point == self)
+                .map(|(_, count)| *count)
+        }
+        #[cfg(not(feature = "testing"))]
+        {
+            None
+        }
+    }
+
+    /// The error this point fails with once triggered
+    pub fn error(self) -> anyhow::Error {
+        tracing::warn!("💥 Injected fault {}", self.name());
+        match self {
+            FaultPoint::CopyDisconnect => std::io::Error::new(
+                std::io::ErrorKind::ConnectionReset,
+                "injected fault: connection lost during COPY",
+            )
+            .into(),
+            _ => anyhow::anyhow!("injected fault: {}", self.name()),
+        }
+    }
+}
+
+impl std::str::FromStr for FaultPoint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FaultPoint::ALL
+            .into_iter()
+            .find(|point| point.name() == s)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown fault point {:?}, expected one of {}",
+                    s,
+                    FaultPoint::ALL.map(FaultPoint::name).join(", ")
+                )
+            })
+    }
+}
+
+/// The faults of an `INJECT_FAULTS` list: comma-separated `point` or
+/// `point=N`, e.g. `copy_disconnect=5000,process_error`
+pub fn parse(faults: &str) -> anyhow::Result<Vec<(FaultPoint, u64)>> {
+    faults
+        .split(',')
+        .map(str::trim)
+        .filter(|fault| !fault.is_empty())
+        .map(|fault| match fault.split_once('=') {
+            Some((name, count)) => Ok((
+                name.trim().parse()?,
+                count
+                    .trim()
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("Invalid fault count in {:?}: {}", fault, e))?,
+            )),
+            None => Ok((fault.parse()?, 0)),
+        })
+        .collect()
+}
+
+/// Arm the faults listed in `INJECT_FAULTS`, for the rest of the process;
+/// called once at startup, so that a malformed list fails the run before it
+/// starts. Without the `testing` feature the list is ignored.
+pub fn arm_from_env() -> anyhow::Result<()> {
+    let Ok(faults) = std::env::var("INJECT_FAULTS") else {
+        return Ok(());
+    };
+    #[cfg(feature = "testing")]
+    {
+        let faults =
+            parse(&faults).map_err(|e| anyhow::anyhow!("Invalid INJECT_FAULTS: {:#}", e))?;
+        for (point, count) in faults {
+            tracing::warn!("💥 Fault {} armed ({})", point.name(), count);
+            // armed for good: the guards are never dropped
+            std::mem::forget(arm(point, count));
+        }
+    }
+    #[cfg(not(feature = "testing"))]
+    if !faults.trim().is_empty() {
+        tracing::warn!("⚠️ INJECT_FAULTS is ignored by builds without the testing feature");
+    }
+    Ok(())
+}
+
+/// Arm `point` with `count` until the returned guard is dropped. Armed points
+/// are process-wide, so a test arming one should not share its process with
+/// tests that must not hit it.
+#[cfg(feature = "testing")]
+pub fn arm(point: FaultPoint, count: u64) -> FaultGuard {
+    ARMED
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .push((point, count));
+    FaultGuard { point, count }
+}
+
+/// Disarms the point [`arm`]ed when dropped
+#[cfg(feature = "testing")]
+#[must_use = "the point is disarmed when the guard is dropped"]
+#[derive(Debug)]
+pub struct FaultGuard {
+    point: FaultPoint,
+    count: u64,
+}
+
+#[cfg(feature = "testing")]
+impl Drop for FaultGuard {
+    fn drop(&mut self) {
+        let mut armed = ARMED
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(i) = armed
+            .iter()
+            .rposition(|armed| *armed == (self.point, self.count))
+        {
+            armed.remove(i);
+        }
+    }
+}
+
+/// `sql` with a statement raising an error inserted before its final
+/// `COMMIT`, when `process_error` is armed
+pub(crate) fn inject_process_error(sql: &str) -> std::borrow::Cow<'_, str> {
+    match FaultPoint::ProcessError
+        .armed()
+        .and(sql.rsplit_once("COMMIT;"))
+    {
+        Some((body, rest)) => {
+            tracing::warn!("💥 Injected fault {}", FaultPoint::ProcessError.name());
+            format!(
+                "{}DO $$ BEGIN RAISE EXCEPTION 'injected fault: process_error'; END $$;\nCOMMIT;{}",
+                body, rest
+            )
+            .into()
+        }
+        None => sql.into(),
+    }
+}
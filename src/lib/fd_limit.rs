@@ -0,0 +1,94 @@
+/// File descriptors left to everything but the walker threads: database
+/// connections, logs, the crawl output, the runtime
+const RESERVED_FDS: u64 = 64;
+/// Descriptors a walker thread holds at once: the directory it lists and
+/// the file it hashes or reads the label of
+const FDS_PER_THREAD: u64 = 2;
+/// Ceiling macOS puts on the soft limit, whatever the hard limit says
+#[cfg(target_os = "macos")]
+const MACOS_OPEN_MAX: u64 = 10_240;
+
+static LIMIT: std::sync::OnceLock<Option<u64>> = std::sync::OnceLock::new();
+
+/// The soft `RLIMIT_NOFILE` of the process, raised to the hard limit on the
+/// first call where that is permitted; `None` where there is no such limit.
+/// Later calls return the limit the first one left.
+pub fn raise() -> Option<u64> {
+    *LIMIT.get_or_init(raise_once)
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn raise_once() -> Option<u64> {
+    let mut limit = std::mem::MaybeUninit::<libc::rlimit>::uninit();
+    // SAFETY: getrlimit fills the struct it is given
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, limit.as_mut_ptr()) } != 0 {
+        tracing::warn!(
+            "⚠️ Failed to read the open file limit: {}",
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+    let mut limit = unsafe { limit.assume_init() };
+    let soft = limit.rlim_cur;
+    #[allow(unused_mut)]
+    let mut hard = limit.rlim_max;
+    #[cfg(target_os = "macos")]
+    {
+        hard = hard.min(MACOS_OPEN_MAX);
+    }
+    if soft >= hard {
+        tracing::debug!("📂 Open file limit: {}", soft);
+        return Some(soft);
+    }
+    limit.rlim_cur = hard;
+    // SAFETY: setrlimit only reads the struct it is given
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+        tracing::warn!(
+            "⚠️ Failed to raise the open file limit from {} to {}: {}",
+            soft,
+            hard,
+            std::io::Error::last_os_error()
+        );
+        return Some(soft);
+    }
+    tracing::info!("📂 Raised the open file limit from {} to {}", soft, hard);
+    Some(hard)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn raise_once() -> Option<u64> {
+    None
+}
+
+/// `wanted` threads for `what`, or as many as the open file limit leaves
+/// room for, with a warning, if that is fewer
+pub fn cap_threads(wanted: usize, what: &str) -> usize {
+    let Some(limit) = raise() else {
+        return wanted;
+    };
+    let room = (limit.saturating_sub(RESERVED_FDS) / FDS_PER_THREAD).max(1) as usize;
+    if wanted <= room {
+        return wanted;
+    }
+    tracing::warn!(
+        "⚠️ Open file limit {} leaves room for {} {} threads instead of {}; raise `ulimit -n` to use them all",
+        limit,
+        room,
+        what,
+        wanted
+    );
+    room
+}
+
+/// Whether `err` is the process or system running out of file descriptors
+pub fn is_exhausted(err: &std::io::Error) -> bool {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        matches!(err.raw_os_error(), Some(libc::EMFILE) | Some(libc::ENFILE))
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        let _ = err;
+        false
+    }
+}
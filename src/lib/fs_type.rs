@@ -0,0 +1,154 @@
+use crate::content_hash::HashPolicy;
+
+/// Crawl settings that suit a filesystem type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FsTuning {
+    /// Walker threads; the walker's default (CPUs, at most 12) if unset
+    pub threads: Option<usize>,
+    /// Descend into filesystems mounted below the root; true if unset
+    pub follow_mounts: Option<bool>,
+    /// Files crawls with `--content-hash` read to hash; all if unset
+    pub hash_policy: Option<crate::content_hash::HashPolicy>,
+}
+
+impl FsTuning {
+    /// `self`, with the settings it leaves unset taken from `fallback`
+    fn or(self, fallback: FsTuning) -> FsTuning {
+        FsTuning {
+            threads: self.threads.or(fallback.threads),
+            follow_mounts: self.follow_mounts.or(fallback.follow_mounts),
+            hash_policy: self.hash_policy.or(fallback.hash_policy),
+        }
+    }
+}
+
+/// Built-in tuning: network and parallel filesystems answer each stat with
+/// a round trip, so more of them in flight keep the walk busy. Reading
+/// contents costs them as much per file, so only small files are hashed, and
+/// none over SMB and FUSE. Local filesystems keep the walker's defaults.
+const BUILTIN_TUNING: &[(&str, FsTuning)] = &[
+    ("nfs", network_tuning(32, HashPolicy::SmallFiles)),
+    ("cifs", network_tuning(16, HashPolicy::MetadataOnly)),
+    ("smb2", network_tuning(16, HashPolicy::MetadataOnly)),
+    ("ceph", network_tuning(32, HashPolicy::SmallFiles)),
+    ("beegfs", network_tuning(32, HashPolicy::SmallFiles)),
+    ("lustre", network_tuning(48, HashPolicy::SmallFiles)),
+    ("gpfs", network_tuning(48, HashPolicy::SmallFiles)),
+    ("fuse", network_tuning(16, HashPolicy::MetadataOnly)),
+];
+
+const fn network_tuning(threads: usize, hash_policy: HashPolicy) -> FsTuning {
+    FsTuning {
+        threads: Some(threads),
+        follow_mounts: None,
+        hash_policy: Some(hash_policy),
+    }
+}
+
+/// Tuning per filesystem type: the built-in table, overridden per type and
+/// setting by a TOML file of tables named after filesystem types:
+///
+/// ```toml
+/// [nfs]
+/// threads = 64
+/// follow_mounts = false
+/// hash_policy = "full"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TuningTable(std::collections::BTreeMap<String, FsTuning>);
+
+impl Default for TuningTable {
+    fn default() -> Self {
+        TuningTable(
+            BUILTIN_TUNING
+                .iter()
+                .map(|(fs_type, tuning)| (fs_type.to_string(), *tuning))
+                .collect(),
+        )
+    }
+}
+
+impl TuningTable {
+    /// The built-in table with the overrides of the TOML file at `path`
+    pub fn from_file(path: &std::path::Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let overrides: std::collections::BTreeMap<String, FsTuning> = toml::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Invalid tuning table {}: {}", path.display(), e))?;
+        let mut table = TuningTable::default();
+        for (fs_type, tuning) in overrides {
+            let builtin = table.get(&fs_type);
+            table.0.insert(fs_type, tuning.or(builtin));
+        }
+        Ok(table)
+    }
+
+    /// Tuning of filesystem type `fs_type`, empty if the table has none but
+    /// for its hashing policy, which defaults to hashing every file
+    pub fn get(&self, fs_type: &str) -> FsTuning {
+        let mut tuning = self.0.get(fs_type).copied().unwrap_or_default();
+        tuning.hash_policy.get_or_insert(HashPolicy::Full);
+        tuning
+    }
+}
+
+/// Type of the filesystem holding `path`, as named by `statfs` (`nfs`,
+/// `xfs`, ...; the hex magic number of types not known here), or `None`
+/// where it cannot be told
+#[cfg(target_os = "linux")]
+pub fn detect(path: &std::path::Path) -> Option<String> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is NUL-terminated and `stat` is a valid out pointer
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let magic = stat.f_type as u64 & 0xffff_ffff;
+    let name = match magic {
+        0xEF53 => "ext4",
+        0x5846_5342 => "xfs",
+        0x9123_683E => "btrfs",
+        0x2FC1_2FC1 => "zfs",
+        0xF2F5_2010 => "f2fs",
+        0x0102_1994 => "tmpfs",
+        0x794C_7630 => "overlay",
+        0x5346_544E => "ntfs",
+        0x6969 => "nfs",
+        0xFF53_4D42 => "cifs",
+        0xFE53_4D42 => "smb2",
+        0x00C3_6400 => "ceph",
+        0x1983_0326 => "beegfs",
+        0x0BD0_0BD0 => "lustre",
+        0x4750_4653 => "gpfs",
+        0x6573_5546 => "fuse",
+        other => return Some(format!("{:#x}", other)),
+    };
+    Some(name.to_string())
+}
+
+#[cfg(target_os = "macos")]
+pub fn detect(path: &std::path::Path) -> Option<String> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is NUL-terminated and `stat` is a valid out pointer
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    // SAFETY: the kernel NUL-terminates the type name within the array
+    let name = unsafe { std::ffi::CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+    Some(match name.to_string_lossy().as_ref() {
+        "smbfs" => "cifs".to_string(),
+        "macfuse" | "osxfuse" => "fuse".to_string(),
+        other => other.to_string(),
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn detect(_path: &std::path::Path) -> Option<String> {
+    None
+}
@@ -0,0 +1,303 @@
+use crate::snapshot_diff::DiffEntries;
+
+/// The volume UUID of the FSEvents log of `data_root` and the current event
+/// ID, as `<uuid>:<event id>`. Fails if the volume keeps no FSEvents log
+/// (e.g. read-only and network volumes).
+pub fn mark(data_root: &std::path::Path) -> anyhow::Result<String> {
+    imp::mark(data_root)
+}
+
+fn parse_marker(marker: &str) -> anyhow::Result<(&str, u64)> {
+    marker
+        .rsplit_once(':')
+        .and_then(|(uuid, event_id)| Some((uuid, event_id.parse().ok()?)))
+        .ok_or_else(|| anyhow::anyhow!("Invalid FSEvents marker: {}", marker))
+}
+
+/// The directories of `data_root` FSEvents reports changes in since the
+/// `previous` marker, replayed from the volume's FSEvents log. Fails if the
+/// log was reset since, or events were dropped at the root.
+///
+/// FSEvents coalesces changes per directory without naming the entries, so
+/// the directories are rescanned, see
+/// [`crate::snapshot_diff::expand_rescan_dirs`]; where events were dropped,
+/// the whole subtree is.
+pub fn diff(
+    data_root: &std::path::Path,
+    previous: &str,
+    current: &str,
+) -> anyhow::Result<DiffEntries> {
+    let (previous_uuid, since) = parse_marker(previous)?;
+    let (current_uuid, _) = parse_marker(current)?;
+    anyhow::ensure!(
+        previous_uuid == current_uuid,
+        "The FSEvents log of the volume was reset since the previous scan ({} -> {})",
+        previous_uuid,
+        current_uuid
+    );
+
+    // events name real paths, e.g. /private/var for /var
+    let root = data_root.canonicalize()?;
+    let mut entries = DiffEntries::default();
+    for (path, flags) in imp::replay(&root, since)? {
+        entries.lines += 1;
+        anyhow::ensure!(
+            flags & (EVENT_IDS_WRAPPED | ROOT_CHANGED | MOUNT | UNMOUNT) == 0,
+            "FSEvents reported {:#x} for {}",
+            flags,
+            path.display()
+        );
+        let Ok(relative) = path.strip_prefix(&root) else {
+            continue;
+        };
+        let path = data_root.join(relative);
+        if flags & (MUST_SCAN_SUBDIRS | USER_DROPPED | KERNEL_DROPPED) != 0 {
+            anyhow::ensure!(
+                !relative.as_os_str().is_empty(),
+                "FSEvents dropped events for the root"
+            );
+            // a directory gone and back is restated by its walk
+            entries.old_dirs.insert(path.clone());
+            entries.new_dirs.insert(path);
+        } else {
+            entries.rescan_dirs.insert(path);
+        }
+    }
+    Ok(entries)
+}
+
+const MUST_SCAN_SUBDIRS: u32 = 0x01;
+const USER_DROPPED: u32 = 0x02;
+const KERNEL_DROPPED: u32 = 0x04;
+const EVENT_IDS_WRAPPED: u32 = 0x08;
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+const HISTORY_DONE: u32 = 0x10;
+const ROOT_CHANGED: u32 = 0x20;
+const MOUNT: u32 = 0x40;
+const UNMOUNT: u32 = 0x80;
+
+#[cfg(not(target_os = "macos"))]
+mod imp {
+    pub fn mark(_data_root: &std::path::Path) -> anyhow::Result<String> {
+        anyhow::bail!("FSEvents needs macOS")
+    }
+
+    pub fn replay(
+        _root: &std::path::Path,
+        _since: u64,
+    ) -> anyhow::Result<Vec<(std::path::PathBuf, u32)>> {
+        anyhow::bail!("FSEvents needs macOS")
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::ffi::{CStr, c_char, c_void};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+
+    type CFRef = *const c_void;
+
+    #[repr(C)]
+    struct FSEventStreamContext {
+        version: isize,
+        info: *mut c_void,
+        retain: *const c_void,
+        release: *const c_void,
+        copy_description: *const c_void,
+    }
+
+    type FSEventStreamCallback = extern "C" fn(
+        stream: CFRef,
+        info: *mut c_void,
+        num_events: usize,
+        event_paths: *mut c_void,
+        event_flags: *const u32,
+        event_ids: *const u64,
+    );
+
+    const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    const FS_EVENT_STREAM_CREATE_FLAG_NONE: u32 = 0;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        static kCFRunLoopDefaultMode: CFRef;
+        static kCFTypeArrayCallBacks: c_void;
+        fn CFRelease(cf: CFRef);
+        fn CFRunLoopGetCurrent() -> CFRef;
+        fn CFRunLoopRunInMode(mode: CFRef, seconds: f64, return_after_source_handled: u8) -> i32;
+        fn CFRunLoopStop(run_loop: CFRef);
+        fn CFStringCreateWithBytes(
+            allocator: CFRef,
+            bytes: *const u8,
+            num_bytes: isize,
+            encoding: u32,
+            is_external_representation: u8,
+        ) -> CFRef;
+        fn CFStringGetCString(string: CFRef, buffer: *mut c_char, size: isize, encoding: u32)
+        -> u8;
+        fn CFArrayCreate(
+            allocator: CFRef,
+            values: *const CFRef,
+            num_values: isize,
+            callbacks: *const c_void,
+        ) -> CFRef;
+        fn CFUUIDCreateString(allocator: CFRef, uuid: CFRef) -> CFRef;
+    }
+
+    #[link(name = "CoreServices", kind = "framework")]
+    unsafe extern "C" {
+        fn FSEventsGetCurrentEventId() -> u64;
+        fn FSEventsCopyUUIDForDevice(dev: libc::dev_t) -> CFRef;
+        fn FSEventStreamCreate(
+            allocator: CFRef,
+            callback: FSEventStreamCallback,
+            context: *const FSEventStreamContext,
+            paths_to_watch: CFRef,
+            since_when: u64,
+            latency: f64,
+            flags: u32,
+        ) -> CFRef;
+        fn FSEventStreamScheduleWithRunLoop(stream: CFRef, run_loop: CFRef, mode: CFRef);
+        fn FSEventStreamStart(stream: CFRef) -> u8;
+        fn FSEventStreamStop(stream: CFRef);
+        fn FSEventStreamInvalidate(stream: CFRef);
+        fn FSEventStreamRelease(stream: CFRef);
+    }
+
+    pub fn mark(data_root: &std::path::Path) -> anyhow::Result<String> {
+        let dev = std::fs::metadata(data_root)?.dev() as libc::dev_t;
+        // SAFETY: the returned UUID and string are released below
+        unsafe {
+            let uuid = FSEventsCopyUUIDForDevice(dev);
+            anyhow::ensure!(
+                !uuid.is_null(),
+                "The volume of {} keeps no FSEvents log",
+                data_root.display()
+            );
+            let string = CFUUIDCreateString(std::ptr::null(), uuid);
+            CFRelease(uuid);
+            let mut buffer = [0 as c_char; 64];
+            let ok = CFStringGetCString(
+                string,
+                buffer.as_mut_ptr(),
+                buffer.len() as isize,
+                CF_STRING_ENCODING_UTF8,
+            );
+            CFRelease(string);
+            anyhow::ensure!(ok != 0, "Failed to read the FSEvents volume UUID");
+            let uuid = CStr::from_ptr(buffer.as_ptr())
+                .to_string_lossy()
+                .to_string();
+            Ok(format!("{}:{}", uuid, FSEventsGetCurrentEventId()))
+        }
+    }
+
+    struct Replay {
+        run_loop: CFRef,
+        events: Vec<(std::path::PathBuf, u32)>,
+        done: bool,
+    }
+
+    extern "C" fn callback(
+        _stream: CFRef,
+        info: *mut c_void,
+        num_events: usize,
+        event_paths: *mut c_void,
+        event_flags: *const u32,
+        _event_ids: *const u64,
+    ) {
+        // SAFETY: `info` is the `Replay` given to the stream, and the
+        // arrays hold `num_events` entries, the paths as C strings
+        unsafe {
+            let replay = &mut *(info as *mut Replay);
+            let paths = event_paths as *const *const c_char;
+            for i in 0..num_events {
+                let flags = *event_flags.add(i);
+                if flags & super::HISTORY_DONE != 0 {
+                    replay.done = true;
+                    CFRunLoopStop(replay.run_loop);
+                    continue;
+                }
+                let path = CStr::from_ptr(*paths.add(i)).to_bytes();
+                let path = std::ffi::OsStr::from_bytes(path.strip_suffix(b"/").unwrap_or(path));
+                replay.events.push((std::path::PathBuf::from(path), flags));
+            }
+        }
+    }
+
+    /// Events of the FSEvents log under `root` since event `since`, up to
+    /// now, with their flags
+    pub fn replay(
+        root: &std::path::Path,
+        since: u64,
+    ) -> anyhow::Result<Vec<(std::path::PathBuf, u32)>> {
+        let mut replay = Replay {
+            // SAFETY: plain getter
+            run_loop: unsafe { CFRunLoopGetCurrent() },
+            events: Vec::new(),
+            done: false,
+        };
+        let context = FSEventStreamContext {
+            version: 0,
+            info: &mut replay as *mut Replay as *mut c_void,
+            retain: std::ptr::null(),
+            release: std::ptr::null(),
+            copy_description: std::ptr::null(),
+        };
+        let root_bytes = root.as_os_str().as_bytes();
+
+        // SAFETY: every object created is released before returning, and
+        // `replay` outlives the stream
+        unsafe {
+            let path = CFStringCreateWithBytes(
+                std::ptr::null(),
+                root_bytes.as_ptr(),
+                root_bytes.len() as isize,
+                CF_STRING_ENCODING_UTF8,
+                0,
+            );
+            anyhow::ensure!(!path.is_null(), "{} is not valid UTF-8", root.display());
+            let paths = CFArrayCreate(
+                std::ptr::null(),
+                &path,
+                1,
+                &kCFTypeArrayCallBacks as *const c_void,
+            );
+            CFRelease(path);
+            // without per-file events, changes are reported per directory
+            let stream = FSEventStreamCreate(
+                std::ptr::null(),
+                callback,
+                &context,
+                paths,
+                since,
+                0.0,
+                FS_EVENT_STREAM_CREATE_FLAG_NONE,
+            );
+            CFRelease(paths);
+            anyhow::ensure!(!stream.is_null(), "FSEventStreamCreate failed");
+
+            FSEventStreamScheduleWithRunLoop(stream, replay.run_loop, kCFRunLoopDefaultMode);
+            let started = FSEventStreamStart(stream) != 0;
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs(600);
+            while started && !replay.done && std::time::Instant::now() < deadline {
+                // returns early once the callback stops the run loop
+                CFRunLoopRunInMode(kCFRunLoopDefaultMode, 1.0, 0);
+            }
+            if started {
+                FSEventStreamStop(stream);
+            }
+            FSEventStreamInvalidate(stream);
+            FSEventStreamRelease(stream);
+
+            anyhow::ensure!(started, "FSEventStreamStart failed for {}", root.display());
+            anyhow::ensure!(
+                replay.done,
+                "FSEvents did not finish replaying its log for {}",
+                root.display()
+            );
+        }
+        Ok(replay.events)
+    }
+}
@@ -0,0 +1,240 @@
+use rusqlite::OptionalExtension as _;
+
+/// Digests kept buffered before they are written in one transaction
+const FLUSH_EVERY: usize = 1_000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS digests (
+    dev INTEGER NOT NULL,
+    ino INTEGER NOT NULL,
+    algorithm TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    mtime_ns INTEGER NOT NULL,
+    fingerprint TEXT NOT NULL,
+    PRIMARY KEY (dev, ino, algorithm)
+);
+";
+
+/// What identifies the contents of a file to the cache: a file whose device,
+/// inode, size and mtime are those it was hashed with is taken as unchanged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileKey {
+    pub dev: u64,
+    pub ino: u64,
+    pub size: u64,
+    pub mtime: Option<std::time::SystemTime>,
+}
+
+impl FileKey {
+    #[cfg(unix)]
+    pub fn from_metadata(meta: &std::fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        FileKey {
+            dev: meta.dev(),
+            ino: meta.ino(),
+            size: meta.len(),
+            mtime: meta.modified().ok(),
+        }
+    }
+
+    /// The mtime in nanoseconds since the epoch; `None` if unknown, as
+    /// such a file cannot be told unchanged
+    fn mtime_ns(&self) -> Option<i64> {
+        let since_epoch = self.mtime?.duration_since(std::time::UNIX_EPOCH).ok()?;
+        i64::try_from(since_epoch.as_nanos()).ok()
+    }
+}
+
+/// A SQLite file of the fingerprints crawls hashed, by [`FileKey`], so that
+/// crawls hashing contents only read the files that changed since. One entry
+/// per file and algorithm, replaced when the file is hashed anew. Unix only,
+/// as files are only told apart by their device and inode numbers there.
+#[derive(Debug, Clone)]
+pub struct HashCache {
+    path: std::path::PathBuf,
+    inner: std::sync::Arc<std::sync::Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    connection: rusqlite::Connection,
+    /// Digests hashed and not written yet: (key, algorithm, fingerprint)
+    pending: Vec<(FileKey, &'static str, String)>,
+}
+
+impl HashCache {
+    /// Open the cache file at `path`, creating it as needed
+    pub fn open(path: &std::path::Path) -> anyhow::Result<Self> {
+        if cfg!(not(unix)) {
+            anyhow::bail!("A hash cache needs the device and inode numbers of Unix files");
+        }
+        let connection = rusqlite::Connection::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+        // crawls of other roots sharing the file wait instead of failing
+        connection.busy_timeout(std::time::Duration::from_secs(60))?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.execute_batch(SCHEMA)?;
+        tracing::info!("🗄️ Opened hash cache {}", path.display());
+        Ok(HashCache {
+            path: path.to_path_buf(),
+            inner: std::sync::Arc::new(std::sync::Mutex::new(Inner {
+                connection,
+                pending: Vec::new(),
+            })),
+        })
+    }
+
+    /// The cache file, for logs
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// The fingerprint `algorithm` hashed of the file with `key`, if it is
+    /// unchanged since
+    pub fn get(
+        &self,
+        key: &FileKey,
+        algorithm: crate::content_hash::HashAlgorithm,
+    ) -> Option<String> {
+        let mtime_ns = key.mtime_ns()?;
+        let inner = self.inner.lock().ok()?;
+        let found = inner
+            .connection
+            .prepare_cached(
+                "SELECT fingerprint FROM digests
+                 WHERE dev = ?1 AND ino = ?2 AND algorithm = ?3 AND size = ?4 AND mtime_ns = ?5",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_row(
+                    rusqlite::params![
+                        key.dev as i64,
+                        key.ino as i64,
+                        algorithm.prefix(),
+                        key.size as i64,
+                        mtime_ns
+                    ],
+                    |row| row.get(0),
+                )
+                .optional()
+            });
+        match found {
+            Ok(found) => found.or_else(|| {
+                inner
+                    .pending
+                    .iter()
+                    .rev()
+                    .find(|(pending, alg, _)| pending == key && *alg == algorithm.prefix())
+                    .map(|(_, _, fingerprint)| fingerprint.clone())
+            }),
+            Err(e) => {
+                tracing::debug!(
+                    "Failed to look up inode {} in the hash cache: {}",
+                    key.ino,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Remember the fingerprint `algorithm` hashed of the file with `key`;
+    /// written with the next [`FLUSH_EVERY`] ones or on [`flush`](Self::flush)
+    pub fn put(
+        &self,
+        key: &FileKey,
+        algorithm: crate::content_hash::HashAlgorithm,
+        fingerprint: &str,
+    ) {
+        if key.mtime_ns().is_none() {
+            return;
+        }
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        inner
+            .pending
+            .push((*key, algorithm.prefix(), fingerprint.to_string()));
+        if inner.pending.len() >= FLUSH_EVERY
+            && let Err(e) = inner.flush()
+        {
+            tracing::warn!(
+                "⚠️ Failed to write the hash cache {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+
+    /// Write the fingerprints remembered so far
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.inner
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Hash cache poisoned by a panic"))?
+            .flush()
+    }
+}
+
+impl Inner {
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let tx = self.connection.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO digests (dev, ino, algorithm, size, mtime_ns, fingerprint)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for (key, algorithm, fingerprint) in &self.pending {
+                stmt.execute(rusqlite::params![
+                    key.dev as i64,
+                    key.ino as i64,
+                    algorithm,
+                    key.size as i64,
+                    key.mtime_ns(),
+                    fingerprint
+                ])?;
+            }
+        }
+        tx.commit()?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            tracing::warn!("⚠️ Failed to write the hash cache: {}", e);
+        }
+    }
+}
+
+/// What a crawl found in its hash cache
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    hits: std::sync::atomic::AtomicU64,
+    misses: std::sync::atomic::AtomicU64,
+}
+
+impl CacheStats {
+    /// Count a file looked up, found or hashed anew
+    pub fn record(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn insert_into(&self, metadata: &mut std::collections::HashMap<String, String>) {
+        use std::sync::atomic::Ordering::Relaxed;
+
+        metadata.insert(
+            "hash_cache_hits".to_string(),
+            self.hits.load(Relaxed).to_string(),
+        );
+        metadata.insert(
+            "hash_cache_misses".to_string(),
+            self.misses.load(Relaxed).to_string(),
+        );
+    }
+}
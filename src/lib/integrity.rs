@@ -0,0 +1,212 @@
+use futures::StreamExt;
+use sha2::{Digest, Sha256};
+
+/// Domain separation prefixes, so a leaf can never be passed off as an inner node
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Incremental Merkle tree over an ordered sequence of leaves.
+///
+/// Keeps only one pending hash per tree level, so arbitrarily large change
+/// sets can be hashed while streaming. An odd node at the end of a level is
+/// promoted unchanged instead of being paired with itself.
+#[derive(Debug, Default)]
+pub struct MerkleBuilder {
+    /// (level, hash) pairs with strictly decreasing levels
+    peaks: Vec<(u32, [u8; 32])>,
+    leaves: u64,
+}
+
+impl MerkleBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, leaf: &[u8]) {
+        let mut hash: [u8; 32] = Sha256::new()
+            .chain_update([LEAF_PREFIX])
+            .chain_update(leaf)
+            .finalize()
+            .into();
+        let mut level = 0;
+        while let Some(&(top_level, top_hash)) = self.peaks.last()
+            && top_level == level
+        {
+            self.peaks.pop();
+            hash = node_hash(&top_hash, &hash);
+            level += 1;
+        }
+        self.peaks.push((level, hash));
+        self.leaves += 1;
+    }
+
+    pub fn len(&self) -> u64 {
+        self.leaves
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves == 0
+    }
+
+    /// Hex-encoded root; the hash of no input for an empty sequence
+    pub fn finish(mut self) -> String {
+        let root = match self.peaks.pop() {
+            None => Sha256::digest([]).into(),
+            Some((_, mut hash)) => {
+                while let Some((_, left)) = self.peaks.pop() {
+                    hash = node_hash(&left, &hash);
+                }
+                hash
+            }
+        };
+        to_hex(&root)
+    }
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// SHA-256 of a file's contents, as hex
+pub fn sha256_file(path: &std::path::Path) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// The `.sha256` sidecar written next to a crawl TSV or bundle
+pub fn checksum_sidecar(tsv_file: &std::path::Path) -> std::path::PathBuf {
+    let mut name = tsv_file.as_os_str().to_owned();
+    name.push(".sha256");
+    name.into()
+}
+
+/// Write the checksum of a crawl TSV or bundle to its sidecar, in `sha256sum` format so
+/// it can also be checked with `sha256sum -c` after copying both files
+pub fn write_checksum_sidecar(tsv_file: &std::path::Path, sha256: &str) -> anyhow::Result<()> {
+    let file_name = tsv_file
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    std::fs::write(
+        checksum_sidecar(tsv_file),
+        format!("{}  {}\n", sha256, file_name),
+    )?;
+    Ok(())
+}
+
+/// Check a crawl TSV against its `.sha256` sidecar before it is loaded.
+///
+/// Fails on a mismatch, e.g. a file truncated or corrupted while copied from
+/// the crawl host. A TSV without sidecar is loaded unverified with a warning.
+/// Returns the verified checksum.
+pub fn verify_checksum_sidecar(tsv_file: &std::path::Path) -> anyhow::Result<Option<String>> {
+    let sidecar = checksum_sidecar(tsv_file);
+    let contents = match std::fs::read_to_string(&sidecar) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            tracing::warn!(
+                "⚠️ No checksum sidecar for {}, loading it unverified",
+                tsv_file.display()
+            );
+            return Ok(None);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let expected = contents
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Empty checksum sidecar {}", sidecar.display()))?
+        .to_ascii_lowercase();
+    let actual = sha256_file(tsv_file)?;
+    if actual != expected {
+        anyhow::bail!(
+            "Checksum mismatch for {}: expected {} from {}, got {}; the file was truncated or corrupted since the crawl",
+            tsv_file.display(),
+            expected,
+            sidecar.display(),
+            actual
+        );
+    }
+    tracing::info!("🔏 Verified checksum of {}: {}", tsv_file.display(), actual);
+    Ok(Some(actual))
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update([NODE_PREFIX])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+/// Canonical encoding of one file_changes row: unit-separated fields, with
+/// NULL distinguished from an empty value
+fn encode_change_row(row: &tokio_postgres::Row) -> Vec<u8> {
+    fn field(value: Option<String>) -> String {
+        match value {
+            Some(v) => format!("={}", v),
+            None => "\0".to_string(),
+        }
+    }
+
+    let fields = [
+        field(row.get::<_, Option<String>>(0)),
+        field(row.get::<_, Option<String>>(1)),
+        field(row.get::<_, Option<i64>>(2).map(|v| v.to_string())),
+        field(row.get::<_, Option<i64>>(3).map(|v| v.to_string())),
+        field(
+            row.get::<_, Option<chrono::DateTime<chrono::Utc>>>(4)
+                .map(|v| v.to_rfc3339()),
+        ),
+        field(
+            row.get::<_, Option<chrono::DateTime<chrono::Utc>>>(5)
+                .map(|v| v.to_rfc3339()),
+        ),
+        field(row.get::<_, Option<String>>(6)),
+    ];
+    fields.join("\x1f").into_bytes()
+}
+
+/// Compute the Merkle root over the change set of a scan, ordered by file path.
+/// Returns the hex root and the number of rows hashed.
+#[tracing::instrument(skip(client))]
+pub async fn compute_change_set_root(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<(String, u64)> {
+    let query = "
+        SELECT file_path, change_type, old_size_bytes, new_size_bytes,
+               old_mtime, new_mtime, old_file_type
+        FROM filesystem.file_changes
+        WHERE scan_id = $1
+        ORDER BY file_path COLLATE \"C\"";
+
+    let rows = client.query_raw(query, &[&scan_id]).await?;
+    futures::pin_mut!(rows);
+
+    let mut builder = MerkleBuilder::new();
+    while let Some(row) = rows.next().await {
+        builder.push(&encode_change_row(&row?));
+    }
+    let count = builder.len();
+    Ok((builder.finish(), count))
+}
+
+/// Compute and store the Merkle root of a scan's change set, returning it
+#[tracing::instrument(skip(client))]
+pub async fn record_merkle_root(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<String> {
+    let (root, count) = compute_change_set_root(client, scan_id).await?;
+    crate::data::set_merkle_root(client, scan_id, &root).await?;
+    tracing::info!(
+        "🔏 Merkle root over {} changes of scan {}: {}",
+        count,
+        scan_id,
+        root
+    );
+    Ok(root)
+}
@@ -0,0 +1,227 @@
+use futures::StreamExt;
+
+/// How serious a violation of a rule is
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+/// A property of a file whose change a rule reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Attribute {
+    /// The file appearing or disappearing
+    Existence,
+    Size,
+    Mtime,
+    /// The SELinux context or SMACK label, if scans record them
+    Label,
+    /// The content fingerprint, if scans hash contents
+    Hash,
+    /// The permission bits, if scans record ownership
+    Perms,
+    /// The owner or group, if scans record ownership
+    Owner,
+}
+
+impl std::str::FromStr for Attribute {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "existence" => Ok(Attribute::Existence),
+            "size" => Ok(Attribute::Size),
+            "mtime" => Ok(Attribute::Mtime),
+            "label" => Ok(Attribute::Label),
+            "hash" => Ok(Attribute::Hash),
+            "perms" => Ok(Attribute::Perms),
+            "owner" => Ok(Attribute::Owner),
+            other => anyhow::bail!("Unknown monitored attribute: {}", other),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Attribute {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// One `[[rules]]` entry of a policy file
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    /// Glob over recorded paths: `*` stays within a component, `**` spans several
+    pattern: String,
+    attributes: Vec<Attribute>,
+    severity: Severity,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyConfig {
+    rules: Vec<RuleConfig>,
+}
+
+/// Path patterns mapped to the attributes monitored below them and the
+/// severity of their changes, read from a TOML file:
+///
+/// ```toml
+/// [[rules]]
+/// pattern = "/etc/ssh/**"
+/// attributes = ["existence", "size", "mtime"]
+/// severity = "critical"
+/// ```
+///
+/// A change is checked against the first rule whose pattern matches its path.
+#[derive(Debug, Clone)]
+pub struct IntegrityPolicy {
+    rules: Vec<RuleConfig>,
+    patterns: globset::GlobSet,
+}
+
+/// A change a policy rule monitors
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Violation {
+    pub file_path: String,
+    pub change_type: String,
+    /// The monitored attributes that changed
+    pub changed: Vec<Attribute>,
+    pub severity: Severity,
+    /// Pattern of the rule violated
+    pub rule: String,
+}
+
+/// Which recorded attributes a change altered
+struct Changes {
+    size: bool,
+    mtime: bool,
+    label: bool,
+    hash: bool,
+    perms: bool,
+    owner: bool,
+}
+
+/// Violations of a policy by the changes of one scan
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct IntegrityReport {
+    pub scan_id: i32,
+    pub generated_at: chrono::DateTime<chrono::Utc>,
+    /// Number of changes checked
+    pub changes_checked: u64,
+    pub highest_severity: Option<Severity>,
+    pub violations: Vec<Violation>,
+}
+
+impl IntegrityPolicy {
+    pub fn from_file(path: &std::path::Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let config: PolicyConfig = toml::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Invalid policy {}: {}", path.display(), e))?;
+        let mut patterns = globset::GlobSetBuilder::new();
+        for rule in &config.rules {
+            patterns.add(
+                globset::GlobBuilder::new(&rule.pattern)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| anyhow::anyhow!("Invalid pattern {}: {}", rule.pattern, e))?,
+            );
+        }
+        Ok(IntegrityPolicy {
+            rules: config.rules,
+            patterns: patterns.build()?,
+        })
+    }
+
+    /// The violation of the change of `file_path`, if a rule monitors any of
+    /// the attributes it changed
+    fn check(&self, file_path: &str, change_type: &str, changes: Changes) -> Option<Violation> {
+        let rule = &self.rules[*self.patterns.matches(file_path).first()?];
+        let changed: Vec<Attribute> = rule
+            .attributes
+            .iter()
+            .copied()
+            .filter(|attribute| match attribute {
+                Attribute::Existence => matches!(change_type, "added" | "deleted"),
+                Attribute::Size => change_type == "modified" && changes.size,
+                Attribute::Mtime => change_type == "modified" && changes.mtime,
+                Attribute::Label => changes.label,
+                Attribute::Hash => change_type == "modified" && changes.hash,
+                Attribute::Perms => changes.perms,
+                Attribute::Owner => changes.owner,
+            })
+            .collect();
+        (!changed.is_empty()).then(|| Violation {
+            file_path: file_path.to_string(),
+            change_type: change_type.to_string(),
+            changed,
+            severity: rule.severity,
+            rule: rule.pattern.clone(),
+        })
+    }
+
+    /// Check the changes of `scan_id` against the policy: those applied, or
+    /// those pending review if the scan awaits it
+    #[tracing::instrument(skip(self, client))]
+    pub async fn evaluate(
+        &self,
+        client: &tokio_postgres::Client,
+        scan_id: i32,
+    ) -> anyhow::Result<IntegrityReport> {
+        let table = match crate::data::get_scan_status(client, scan_id)
+            .await?
+            .as_str()
+        {
+            "pending_review" => "filesystem.pending_file_changes",
+            _ => "filesystem.file_changes",
+        };
+        let query = format!(
+            "SELECT file_path, change_type,
+                    old_size_bytes IS DISTINCT FROM new_size_bytes,
+                    old_mtime IS DISTINCT FROM new_mtime,
+                    COALESCE(old_security_label <> new_security_label, false),
+                    COALESCE(old_fingerprint <> new_fingerprint, false),
+                    COALESCE(old_mode <> new_mode, false),
+                    COALESCE(old_uid <> new_uid OR old_gid <> new_gid, false)
+             FROM {}
+             WHERE scan_id = $1
+             ORDER BY file_path",
+            table
+        );
+        let rows = client.query_raw(query.as_str(), [&scan_id]).await?;
+        futures::pin_mut!(rows);
+
+        let mut report = IntegrityReport {
+            scan_id,
+            generated_at: chrono::Utc::now(),
+            changes_checked: 0,
+            highest_severity: None,
+            violations: Vec::new(),
+        };
+        while let Some(row) = rows.next().await {
+            let row = row?;
+            report.changes_checked += 1;
+            let changes = Changes {
+                size: row.get(2),
+                mtime: row.get(3),
+                label: row.get(4),
+                hash: row.get(5),
+                perms: row.get(6),
+                owner: row.get(7),
+            };
+            if let Some(violation) = self.check(row.get(0), row.get(1), changes) {
+                report.highest_severity = report.highest_severity.max(Some(violation.severity));
+                report.violations.push(violation);
+            }
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,405 @@
+use sha2::Digest;
+use std::io::{BufRead, Write};
+
+use crate::pipeline::{self, ScanOptions};
+use crate::progress::ProgressReporter;
+use crate::{crawler, data};
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PrefixRewrite {
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MappingConfig {
+    #[serde(default = "default_delimiter")]
+    delimiter: char,
+    #[serde(default = "default_true")]
+    has_header: bool,
+    path: String,
+    size: String,
+    mtime: String,
+    /// `rfc3339`, `epoch` (seconds) or a strftime format
+    #[serde(default = "default_mtime_format")]
+    mtime_format: String,
+    fingerprint: Option<String>,
+    fingerprint_algorithm: Option<String>,
+    #[serde(rename = "type")]
+    type_column: Option<String>,
+    #[serde(default)]
+    file_types: Vec<String>,
+    rewrite_prefix: Option<PrefixRewrite>,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_mtime_format() -> String {
+    "rfc3339".to_string()
+}
+
+/// Which columns of a CSV inventory hold what a crawl records, read from a
+/// TOML file:
+///
+/// ```toml
+/// path = "Path"
+/// size = "Size"
+/// mtime = "Modified"
+/// mtime_format = "%Y-%m-%d %H:%M:%S"
+/// fingerprint = "MD5"
+/// fingerprint_algorithm = "md5"
+/// type = "Type"
+/// file_types = ["file"]
+/// rewrite_prefix = { from = "/vol/projects", to = "/data/projects" }
+/// ```
+///
+/// Columns are named by their header, or by 1-based position with
+/// `has_header = false`.
+#[derive(Debug, Clone)]
+pub struct InventoryMapping {
+    config: MappingConfig,
+}
+
+impl InventoryMapping {
+    pub fn from_file(path: &std::path::Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let config: MappingConfig = toml::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Invalid mapping {}: {}", path.display(), e))?;
+        anyhow::ensure!(
+            config.delimiter != '"' && config.delimiter.is_ascii(),
+            "Invalid mapping {}: the delimiter must be an ASCII character other than '\"'",
+            path.display()
+        );
+        anyhow::ensure!(
+            config.fingerprint.is_some() == config.fingerprint_algorithm.is_some(),
+            "Invalid mapping {}: fingerprint and fingerprint_algorithm go together",
+            path.display()
+        );
+        anyhow::ensure!(
+            config.type_column.is_some() || config.file_types.is_empty(),
+            "Invalid mapping {}: file_types needs the type column",
+            path.display()
+        );
+        Ok(InventoryMapping { config })
+    }
+}
+
+/// Rows of an inventory read by [`import_inventory`]
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct ImportStats {
+    pub files: u64,
+    /// rows whose type is not one of the mapping's `file_types`
+    pub other_types: u64,
+    /// rows of paths outside the scanned root
+    pub outside_root: u64,
+}
+
+/// Read the next CSV record of `reader` into `record`, quoted fields possibly
+/// spanning lines. Returns false at the end of the input.
+fn read_record(
+    reader: &mut impl BufRead,
+    delimiter: char,
+    record: &mut Vec<String>,
+) -> std::io::Result<bool> {
+    record.clear();
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(false);
+    }
+    let mut field = String::new();
+    let mut quoted = false;
+    loop {
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' if quoted => quoted = false,
+                '"' if field.is_empty() => quoted = true,
+                c if c == delimiter && !quoted => record.push(std::mem::take(&mut field)),
+                '\r' | '\n' if !quoted => {}
+                c => field.push(c),
+            }
+        }
+        if !quoted {
+            break;
+        }
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "unterminated quoted field",
+            ));
+        }
+    }
+    record.push(field);
+    Ok(true)
+}
+
+fn parse_mtime(value: &str, format: &str) -> anyhow::Result<chrono::DateTime<chrono::Utc>> {
+    let mtime = match format {
+        "rfc3339" => chrono::DateTime::parse_from_rfc3339(value)?.to_utc(),
+        "epoch" => {
+            let seconds: f64 = value.parse()?;
+            chrono::DateTime::from_timestamp(seconds.floor() as i64, 0)
+                .ok_or_else(|| anyhow::anyhow!("out of range"))?
+        }
+        format => match chrono::DateTime::parse_from_str(value, format) {
+            Ok(mtime) => mtime.to_utc(),
+            // without a zone in the format, times are taken as UTC
+            Err(_) => chrono::NaiveDateTime::parse_from_str(value, format)?.and_utc(),
+        },
+    };
+    Ok(mtime)
+}
+
+/// Columns of the mapping resolved to positions in the inventory's records
+struct Columns {
+    path: usize,
+    size: usize,
+    mtime: usize,
+    fingerprint: Option<usize>,
+    file_type: Option<usize>,
+}
+
+impl Columns {
+    fn resolve(config: &MappingConfig, header: Option<&[String]>) -> anyhow::Result<Self> {
+        let position = |column: &str| -> anyhow::Result<usize> {
+            match header {
+                Some(header) => header
+                    .iter()
+                    .position(|name| name.trim_start_matches('\u{feff}').trim() == column)
+                    .ok_or_else(|| anyhow::anyhow!("The inventory has no column {}", column)),
+                None => match column.parse::<usize>() {
+                    Ok(position) if position > 0 => Ok(position - 1),
+                    _ => anyhow::bail!(
+                        "Columns of an inventory without header are numbered from 1, got {}",
+                        column
+                    ),
+                },
+            }
+        };
+        Ok(Columns {
+            path: position(&config.path)?,
+            size: position(&config.size)?,
+            mtime: position(&config.mtime)?,
+            fingerprint: config.fingerprint.as_deref().map(position).transpose()?,
+            file_type: config.type_column.as_deref().map(position).transpose()?,
+        })
+    }
+}
+
+/// Convert a CSV inventory of `options.data_root` into a crawl TSV for
+/// `scan_id`, with its checksum sidecar, returning the TSV's checksum
+fn write_crawl_tsv(
+    inventory: &std::path::Path,
+    mapping: &InventoryMapping,
+    options: &ScanOptions,
+    scan_id: i32,
+    output_tsv_file: &std::path::Path,
+    stats: &mut ImportStats,
+) -> anyhow::Result<String> {
+    let config = &mapping.config;
+    let mut reader = std::io::BufReader::new(
+        std::fs::File::open(inventory)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", inventory.display(), e))?,
+    );
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(output_tsv_file)?);
+    let mut hasher = sha2::Sha256::new();
+    let mut record = Vec::new();
+
+    let mut line = 0;
+    let columns = if config.has_header {
+        line += 1;
+        if !read_record(&mut reader, config.delimiter, &mut record)? {
+            anyhow::bail!("Inventory {} is empty", inventory.display());
+        }
+        Columns::resolve(config, Some(&record))?
+    } else {
+        Columns::resolve(config, None)?
+    };
+    let root = &options.data_root;
+    while read_record(&mut reader, config.delimiter, &mut record)
+        .map_err(|e| anyhow::anyhow!("{}:{}: {}", inventory.display(), line + 1, e))?
+    {
+        line += 1;
+        if record.len() == 1 && record[0].is_empty() {
+            continue;
+        }
+        let field = |column: usize| -> anyhow::Result<&str> {
+            record.get(column).map(|f| f.trim()).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{}:{}: expected at least {} fields, got {}",
+                    inventory.display(),
+                    line,
+                    column + 1,
+                    record.len()
+                )
+            })
+        };
+        if let Some(column) = columns.file_type
+            && !config
+                .file_types
+                .iter()
+                .any(|t| t == field(column).unwrap_or(""))
+        {
+            stats.other_types += 1;
+            continue;
+        }
+
+        let mut path = field(columns.path)?.to_string();
+        if let Some(rewrite) = &config.rewrite_prefix
+            && let Some(rest) = path.strip_prefix(&rewrite.from)
+        {
+            path = format!("{}{}", rewrite.to, rest);
+        }
+        let path = std::path::PathBuf::from(path);
+        if !path.starts_with(root) || path == *root {
+            stats.outside_root += 1;
+            continue;
+        }
+        let size: u64 = field(columns.size)?.parse().map_err(|e| {
+            anyhow::anyhow!("{}:{}: invalid size: {}", inventory.display(), line, e)
+        })?;
+        let mtime = parse_mtime(field(columns.mtime)?, &config.mtime_format).map_err(|e| {
+            anyhow::anyhow!("{}:{}: invalid mtime: {}", inventory.display(), line, e)
+        })?;
+        let fingerprint = match (columns.fingerprint, &config.fingerprint_algorithm) {
+            (Some(column), Some(algorithm)) => Some(field(column)?)
+                .filter(|digest| !digest.is_empty())
+                .map(|digest| {
+                    format!(
+                        "{}:{}",
+                        algorithm.to_ascii_lowercase(),
+                        digest.to_ascii_lowercase()
+                    )
+                }),
+            _ => None,
+        };
+
+        let facts = crawler::FileFacts {
+            size,
+            mtime: Some(mtime.into()),
+            label: None,
+            fingerprint,
+            symlink_target: None,
+            ownership: None,
+        };
+        let tsv_line = crawler::format_tsv_line(&path, facts, scan_id, root, &options.crawl);
+        writer.write_all(tsv_line.as_bytes())?;
+        hasher.update(tsv_line.as_bytes());
+        stats.files += 1;
+    }
+    writer.flush()?;
+
+    let tsv_sha256 = crate::integrity::to_hex(&hasher.finalize());
+    crate::integrity::write_checksum_sidecar(output_tsv_file, &tsv_sha256)?;
+    Ok(tsv_sha256)
+}
+
+/// Record a third-party inventory of `options.data_root` as a scan of it, whose
+/// deltas against the tracked state are staged for review but never applied:
+/// they are the inventory's discrepancies with the latest scans. Returns the
+/// scan_id.
+#[tracing::instrument(skip(client, mapping, options, progress))]
+pub async fn import_inventory(
+    client: &tokio_postgres::Client,
+    inventory: &std::path::Path,
+    mapping: &InventoryMapping,
+    options: &ScanOptions,
+    progress: &ProgressReporter,
+) -> anyhow::Result<(i32, ImportStats)> {
+    anyhow::ensure!(
+        options.extra_roots.is_empty(),
+        "An inventory is imported as a scan of a single root"
+    );
+    let mut options = options.clone();
+    options.review = true;
+    options.validate()?;
+
+    let path_key_id = options.crawl.path_cipher.as_ref().map(|c| c.key_id());
+    pipeline::check_root_tenant(client, &options.data_root, options.tenant.as_deref()).await?;
+    pipeline::check_root_path_key(client, &options.data_root, path_key_id).await?;
+    let scan_id = data::start_scan(
+        client,
+        &options.data_root,
+        &[],
+        options.tenant.as_deref(),
+        path_key_id,
+        chrono::Utc::now(),
+    )
+    .await?;
+    tracing::info!("🔍 Scan ID: {}", scan_id);
+
+    let output_tsv_file = std::env::temp_dir().join(format!("inventory_{}.tsv", scan_id));
+    let mut stats = ImportStats::default();
+    let tsv_sha256 = match write_crawl_tsv(
+        inventory,
+        mapping,
+        &options,
+        scan_id,
+        &output_tsv_file,
+        &mut stats,
+    ) {
+        Ok(tsv_sha256) => tsv_sha256,
+        Err(e) => {
+            pipeline::remove_tsv_file(&output_tsv_file);
+            data::abort_scan(
+                client,
+                scan_id,
+                serde_json::json!({ "inventory_error": e.to_string() }),
+            )
+            .await?;
+            return Err(e);
+        }
+    };
+    tracing::info!(
+        "📋 Read {} files from {} ({} of other types, {} outside the root)",
+        stats.files,
+        inventory.display(),
+        stats.other_types,
+        stats.outside_root
+    );
+
+    let mut metadata = std::collections::HashMap::new();
+    metadata.insert("inventory".to_string(), inventory.display().to_string());
+    metadata.insert("inventory_format".to_string(), "csv".to_string());
+    metadata.insert("total_files_processed".to_string(), stats.files.to_string());
+    metadata.insert("tsv_sha256".to_string(), tsv_sha256);
+    data::audit(
+        client,
+        "inventory_imported",
+        Some(scan_id),
+        serde_json::json!({
+            "inventory": inventory.display().to_string(),
+            "files": stats.files,
+        }),
+    )
+    .await?;
+
+    pipeline::process_crawl(
+        client,
+        &options,
+        scan_id,
+        &output_tsv_file,
+        metadata,
+        progress,
+    )
+    .await?;
+    pipeline::remove_tsv_file(&output_tsv_file);
+    // the deltas are kept for review, the staged rows are never promoted
+    data::clear_staging(client, scan_id).await?;
+
+    Ok((scan_id, stats))
+}
@@ -0,0 +1,140 @@
+use crate::progress::{Phase, ProgressEvent, ProgressReporter};
+
+/// State of an in-progress scan, persisted on local disk so the next run on
+/// this host can tell that a previous attempt died and clean up after it
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct JournalEntry {
+    pub data_root: std::path::PathBuf,
+    pub pid: u32,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    /// Set once the scan_runs row exists
+    pub scan_id: Option<i32>,
+    pub output_tsv_file: Option<std::path::PathBuf>,
+    /// Last phase started
+    pub phase: Option<Phase>,
+    /// Whether the crawl finished, i.e. the TSV file is complete
+    pub crawl_completed: bool,
+    /// Whether the scan is batched; `output_tsv_file` is then the current batch's
+    #[serde(default)]
+    pub batched: bool,
+}
+
+/// Local journal of the scan in progress for one root; only used while the
+/// root's [`crate::lock::RootLock`] is held, so there is a single writer
+#[derive(Debug)]
+pub struct ScanJournal {
+    path: std::path::PathBuf,
+    entry: std::sync::Mutex<Option<JournalEntry>>,
+}
+
+impl ScanJournal {
+    pub(crate) fn new(path: std::path::PathBuf) -> Self {
+        Self {
+            path,
+            entry: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Entry left behind by a previous run that did not finish, if any
+    pub fn previous(&self) -> anyhow::Result<Option<JournalEntry>> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes).map_err(|e| {
+                anyhow::anyhow!("Corrupt scan journal {}: {}", self.path.display(), e)
+            })?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Start journaling a new scan of `data_root`
+    pub fn begin(&self, data_root: &std::path::Path) -> anyhow::Result<()> {
+        let now = chrono::Utc::now();
+        self.update(|entry| {
+            *entry = Some(JournalEntry {
+                data_root: data_root.to_path_buf(),
+                pid: std::process::id(),
+                started_at: now,
+                updated_at: now,
+                scan_id: None,
+                output_tsv_file: None,
+                phase: None,
+                crawl_completed: false,
+                batched: false,
+            })
+        })
+    }
+
+    /// Forget the journaled scan; called once it finished or was cleaned up
+    pub fn clear(&self) -> anyhow::Result<()> {
+        *self.entry.lock().expect("journal mutex poisoned") = None;
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Wrap `inner` so scan progress is journaled before being forwarded
+    pub fn reporter(self: &std::sync::Arc<Self>, inner: ProgressReporter) -> ProgressReporter {
+        let journal = self.clone();
+        ProgressReporter::from_callback(move |event| {
+            let result = match event {
+                ProgressEvent::ScanStarted {
+                    scan_id,
+                    output_tsv_file,
+                } => journal.update(|entry| {
+                    if let Some(entry) = entry {
+                        entry.scan_id = Some(*scan_id);
+                        entry.output_tsv_file = Some(output_tsv_file.clone());
+                    }
+                }),
+                ProgressEvent::BatchStarted {
+                    output_tsv_file, ..
+                } => journal.update(|entry| {
+                    if let Some(entry) = entry {
+                        entry.batched = true;
+                        entry.output_tsv_file = Some(output_tsv_file.clone());
+                        entry.crawl_completed = false;
+                    }
+                }),
+                ProgressEvent::PhaseStarted { phase } => journal.update(|entry| {
+                    if let Some(entry) = entry {
+                        entry.phase = Some(*phase);
+                    }
+                }),
+                ProgressEvent::PhaseCompleted {
+                    phase: Phase::Crawl,
+                    ..
+                } => journal.update(|entry| {
+                    if let Some(entry) = entry {
+                        entry.crawl_completed = true;
+                    }
+                }),
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                tracing::warn!("⚠️ Failed to update scan journal: {}", e);
+            }
+            inner.emit(event.clone());
+        })
+    }
+
+    /// Apply `change` and persist the result atomically (write, fsync, rename)
+    fn update(&self, change: impl FnOnce(&mut Option<JournalEntry>)) -> anyhow::Result<()> {
+        let mut entry = self.entry.lock().expect("journal mutex poisoned");
+        change(&mut entry);
+        let Some(entry) = entry.as_mut() else {
+            return Ok(());
+        };
+        entry.updated_at = chrono::Utc::now();
+
+        let tmp = self.path.with_extension("tmp");
+        {
+            let mut file = std::fs::File::create(&tmp)?;
+            std::io::Write::write_all(&mut file, &serde_json::to_vec_pretty(entry)?)?;
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
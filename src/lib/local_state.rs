@@ -0,0 +1,320 @@
+use std::io::{BufRead, Write};
+
+use crate::pipeline::ScanOptions;
+use crate::progress::ProgressReporter;
+use crate::{crawler, pause, pipeline};
+
+/// Crawl of the previous run, sorted by path and zstd-compressed
+const SNAPSHOT_FILE: &str = "snapshot.tsv.zst";
+/// Describes the snapshot
+const STATE_FILE: &str = "state.json";
+/// Crawl of the running run, replacing the snapshot once diffed
+const CURRENT_FILE: &str = "current.tsv";
+/// Delta files, one per run
+const DELTAS_DIR: &str = "deltas";
+/// Columns of a delta file, those of `export_scan --kind changes`
+const DELTA_HEADER: &str =
+    "file_path\tchange_type\told_size_bytes\tnew_size_bytes\told_mtime\tnew_mtime\told_file_type\n";
+/// NULL in COPY's text format, which delta files share with the crawl TSV
+const NULL_FIELD: &str = "\\N";
+
+/// What a state directory holds besides the snapshot itself
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LocalState {
+    pub scan_root: std::path::PathBuf,
+    /// Number of the run the snapshot is from, written as the scan_id of its lines
+    pub run: i32,
+    pub crawled_at: chrono::DateTime<chrono::Utc>,
+    pub total_files: u64,
+    /// Walker threads the run's adaptive crawl settled on, for the next to start from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub walker_threads: Option<usize>,
+}
+
+/// Changes between two crawls
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct DeltaCounts {
+    pub added: u64,
+    pub modified: u64,
+    pub deleted: u64,
+    pub relabeled: u64,
+}
+
+/// Outcome of [`run_local_scan`]
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LocalScanSummary {
+    #[serde(flatten)]
+    pub state: LocalState,
+    #[serde(flatten)]
+    pub counts: DeltaCounts,
+    pub delta_file: std::path::PathBuf,
+}
+
+/// The fields of a crawl TSV line that deltas are made of, still escaped
+struct CrawlRecord<'a> {
+    file_type: &'a str,
+    path: &'a str,
+    size: &'a str,
+    mtime: &'a str,
+    /// `\N` if not recorded
+    security_label: &'a str,
+    /// `<algorithm>:<hex digest>`, `\N` if not recorded
+    fingerprint: &'a str,
+    /// `\N` unless a recorded symlink
+    symlink_target: &'a str,
+}
+
+impl<'a> CrawlRecord<'a> {
+    fn parse(line: &'a str) -> anyhow::Result<Self> {
+        let fields: Vec<&str> = line.trim_end_matches('\n').split('\t').collect();
+        match fields.as_slice() {
+            [_, file_type, path, size, mtime, rest @ ..] if !rest.is_empty() => Ok(CrawlRecord {
+                file_type,
+                path,
+                size,
+                mtime,
+                // snapshots of older runs end with the scan_id right away
+                security_label: if rest.len() > 1 { rest[0] } else { NULL_FIELD },
+                fingerprint: if rest.len() > 2 { rest[1] } else { NULL_FIELD },
+                symlink_target: if rest.len() > 3 { rest[2] } else { NULL_FIELD },
+            }),
+            _ => anyhow::bail!("Truncated crawl line: {:?}", line),
+        }
+    }
+
+    /// Like `filesystem.is_modified`: size or fingerprint changed when both
+    /// were hashed with the same algorithm, size or mtime otherwise; or
+    /// another symlink target
+    fn modified(&self, other: &CrawlRecord) -> bool {
+        let algorithm = |fingerprint| str::split_once(fingerprint, ':').map(|(a, _)| a);
+        if self.symlink_target != other.symlink_target {
+            return true;
+        }
+        match (algorithm(self.fingerprint), algorithm(other.fingerprint)) {
+            (Some(a), Some(b)) if a == b => {
+                self.size != other.size || self.fingerprint != other.fingerprint
+            }
+            _ => self.size != other.size || self.mtime != other.mtime,
+        }
+    }
+
+    fn relabeled(&self, other: &CrawlRecord) -> bool {
+        self.security_label != NULL_FIELD
+            && other.security_label != NULL_FIELD
+            && self.security_label != other.security_label
+    }
+}
+
+/// Next line of `reader` into `line`, or `false` at its end
+fn read_record(reader: &mut impl BufRead, line: &mut String) -> anyhow::Result<bool> {
+    line.clear();
+    Ok(reader.read_line(line)? > 0)
+}
+
+fn write_delta(
+    out: &mut impl Write,
+    change_type: &str,
+    old: Option<&CrawlRecord>,
+    new: Option<&CrawlRecord>,
+) -> std::io::Result<()> {
+    let path = new.or(old).map(|r| r.path).unwrap_or_default();
+    writeln!(
+        out,
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        path,
+        change_type,
+        old.map_or(NULL_FIELD, |r| r.size),
+        new.map_or(NULL_FIELD, |r| r.size),
+        old.map_or(NULL_FIELD, |r| r.mtime),
+        new.map_or(NULL_FIELD, |r| r.mtime),
+        old.map_or(NULL_FIELD, |r| r.file_type),
+    )
+}
+
+/// Write the changes taking crawl `previous` to crawl `current` to `out`,
+/// one line per changed file in the columns of [`DELTA_HEADER`]. Both crawls
+/// must be sorted by path (`CrawlOptions::sort_output`), so they are merged
+/// in one pass without holding either in memory. Like delta processing, a
+/// file counts as modified when its size or mtime changed (its size or
+/// fingerprint, when both crawls hashed it alike), and as relabeled when only
+/// its security label did.
+pub fn diff_crawls(
+    mut previous: impl BufRead,
+    mut current: impl BufRead,
+    out: &mut impl Write,
+) -> anyhow::Result<DeltaCounts> {
+    let mut counts = DeltaCounts::default();
+    let (mut old_line, mut new_line) = (String::new(), String::new());
+    let mut has_old = read_record(&mut previous, &mut old_line)?;
+    let mut has_new = read_record(&mut current, &mut new_line)?;
+    while has_old || has_new {
+        let old = has_old.then(|| CrawlRecord::parse(&old_line)).transpose()?;
+        let new = has_new.then(|| CrawlRecord::parse(&new_line)).transpose()?;
+        match (&old, &new) {
+            (Some(o), Some(n)) if o.path == n.path => {
+                if o.modified(n) {
+                    write_delta(out, "modified", old.as_ref(), new.as_ref())?;
+                    counts.modified += 1;
+                } else if o.relabeled(n) {
+                    write_delta(out, "relabeled", old.as_ref(), new.as_ref())?;
+                    counts.relabeled += 1;
+                }
+                has_old = read_record(&mut previous, &mut old_line)?;
+                has_new = read_record(&mut current, &mut new_line)?;
+            }
+            (Some(o), Some(n)) if o.path > n.path => {
+                write_delta(out, "added", None, new.as_ref())?;
+                counts.added += 1;
+                has_new = read_record(&mut current, &mut new_line)?;
+            }
+            (None, Some(_)) => {
+                write_delta(out, "added", None, new.as_ref())?;
+                counts.added += 1;
+                has_new = read_record(&mut current, &mut new_line)?;
+            }
+            (Some(_), _) => {
+                write_delta(out, "deleted", old.as_ref(), None)?;
+                counts.deleted += 1;
+                has_old = read_record(&mut previous, &mut old_line)?;
+            }
+            (None, None) => unreachable!("one of the crawls has a line left"),
+        }
+    }
+    Ok(counts)
+}
+
+/// Read the state of `state_dir`, if a run already left one there
+pub fn read_state(state_dir: &std::path::Path) -> anyhow::Result<Option<LocalState>> {
+    let path = state_dir.join(STATE_FILE);
+    match std::fs::read(&path) {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes).map_err(|e| {
+            anyhow::anyhow!("Failed to parse {}: {}", path.display(), e)
+        })?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replace `path` with what `write` writes, through a temporary
+/// file so that an interrupted run leaves the previous version in place
+fn replace_file(
+    path: &std::path::Path,
+    write: impl FnOnce(&mut std::fs::File) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = std::fs::File::create(&tmp)?;
+    write(&mut file)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Crawl `options.data_root` without a database, diff the crawl against the
+/// snapshot the previous run left in `state_dir` into a new file under its
+/// `deltas/`, and keep the crawl as the next run's snapshot. The first run
+/// records every file as added.
+#[tracing::instrument(skip(options, progress))]
+pub async fn run_local_scan(
+    options: &ScanOptions,
+    state_dir: &std::path::Path,
+    progress: &ProgressReporter,
+) -> anyhow::Result<LocalScanSummary> {
+    let previous = read_state(state_dir)?;
+    if let Some(previous) = &previous {
+        let canonical = |path: &std::path::Path| path.canonicalize().unwrap_or(path.to_path_buf());
+        anyhow::ensure!(
+            canonical(&previous.scan_root) == canonical(&options.data_root),
+            "{} holds the state of {}, not {}",
+            state_dir.display(),
+            previous.scan_root.display(),
+            options.data_root.display()
+        );
+    }
+    let run = previous.as_ref().map_or(1, |state| state.run + 1);
+    std::fs::create_dir_all(state_dir.join(DELTAS_DIR))?;
+
+    let current_tsv = state_dir.join(CURRENT_FILE);
+    let crawled_at = chrono::Utc::now();
+    tracing::info!("🔍 Starting directory walk (run {})...", run);
+    let report = crawler::walk_directory(
+        vec![options.data_root.clone()],
+        options.progress_interval,
+        run,
+        current_tsv.clone(),
+        progress.clone(),
+        &crawler::CrawlOptions {
+            sort_output: true,
+            adaptive_threads: options.crawl.adaptive_threads.map(|start| {
+                previous
+                    .as_ref()
+                    .and_then(|state| state.walker_threads)
+                    .unwrap_or(start)
+            }),
+            ..options.crawl.clone()
+        },
+        pause::PauseSwitch::default(),
+    )
+    .await?;
+
+    let snapshot = state_dir.join(SNAPSHOT_FILE);
+    let previous_crawl: Box<dyn BufRead> = match &previous {
+        Some(_) => Box::new(std::io::BufReader::new(zstd::Decoder::new(
+            std::fs::File::open(&snapshot)?,
+        )?)),
+        None => Box::new(std::io::empty()),
+    };
+    let delta_file = state_dir.join(DELTAS_DIR).join(format!(
+        "{:06}_{}.tsv",
+        run,
+        crawled_at.format("%Y%m%dT%H%M%SZ")
+    ));
+    let mut counts = DeltaCounts::default();
+    replace_file(&delta_file, |file| {
+        let mut out = std::io::BufWriter::new(file);
+        out.write_all(DELTA_HEADER.as_bytes())?;
+        let current_crawl = std::io::BufReader::new(std::fs::File::open(&current_tsv)?);
+        counts = diff_crawls(previous_crawl, current_crawl, &mut out)?;
+        out.flush()?;
+        Ok(())
+    })?;
+    tracing::info!(
+        "📝 {} added, {} modified, {} deleted, written to {}",
+        counts.added,
+        counts.modified,
+        counts.deleted,
+        delta_file.display()
+    );
+
+    replace_file(&snapshot, |file| {
+        let mut encoder = zstd::Encoder::new(file, 0)?;
+        std::io::copy(&mut std::fs::File::open(&current_tsv)?, &mut encoder)?;
+        encoder.finish()?;
+        Ok(())
+    })?;
+    let state = LocalState {
+        scan_root: options.data_root.clone(),
+        run,
+        crawled_at,
+        total_files: report
+            .metadata
+            .get("total_files_processed")
+            .and_then(|total| total.parse::<f64>().ok())
+            .unwrap_or_default() as u64,
+        walker_threads: options
+            .crawl
+            .adaptive_threads
+            .and(report.metadata.get("walker_threads"))
+            .and_then(|threads| threads.parse().ok()),
+    };
+    replace_file(&state_dir.join(STATE_FILE), |file| {
+        serde_json::to_writer_pretty(file, &state)?;
+        Ok(())
+    })?;
+    pipeline::remove_tsv_file(&current_tsv);
+
+    Ok(LocalScanSummary {
+        state,
+        counts,
+        delta_file,
+    })
+}
@@ -0,0 +1,115 @@
+use sha2::Digest;
+use std::io::{Read, Seek, Write};
+
+/// Default directory for per-root lock files
+pub fn default_lock_dir() -> std::path::PathBuf {
+    std::env::temp_dir().join("fs-delta-tracker")
+}
+
+/// Canonical root and lock file path of `data_root`
+pub(crate) fn lock_path(
+    lock_dir: &std::path::Path,
+    data_root: &std::path::Path,
+) -> (std::path::PathBuf, std::path::PathBuf) {
+    // canonicalize so `/data/x` and `/data/./x/` share a lock
+    let root = data_root
+        .canonicalize()
+        .unwrap_or_else(|_| data_root.to_path_buf());
+    let digest = sha2::Sha256::digest(root.as_os_str().as_encoded_bytes());
+    let path = lock_dir.join(format!(
+        "root_{}.lock",
+        &crate::integrity::to_hex(&digest)[..16]
+    ));
+    (root, path)
+}
+
+/// Whether a scan of `data_root` currently holds its lock on this host,
+/// including one held by this process
+pub fn is_locked(lock_dir: &std::path::Path, data_root: &std::path::Path) -> anyhow::Result<bool> {
+    let (_, path) = lock_path(lock_dir, data_root);
+    let file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    match file.try_lock_shared() {
+        Ok(()) => Ok(false),
+        Err(std::fs::TryLockError::WouldBlock) => Ok(true),
+        Err(std::fs::TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// Exclusive lock on a scan root for this host, held until dropped.
+///
+/// Backed by `flock` on a file named after the root, so it is released by the
+/// kernel even if the process crashes; the file itself is left behind.
+#[derive(Debug)]
+pub struct RootLock {
+    path: std::path::PathBuf,
+    journal: std::sync::Arc<crate::journal::ScanJournal>,
+    _file: std::fs::File,
+}
+
+impl RootLock {
+    /// Lock `data_root`, failing immediately if another process on this host
+    /// holds it
+    #[tracing::instrument]
+    pub fn acquire(
+        lock_dir: &std::path::Path,
+        data_root: &std::path::Path,
+    ) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(lock_dir)?;
+        let (root, path) = lock_path(lock_dir, data_root);
+
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(std::fs::TryLockError::WouldBlock) => {
+                let mut holder = String::new();
+                file.read_to_string(&mut holder).ok();
+                anyhow::bail!(
+                    "Another scan of {} is running on this host ({}; lock file {})",
+                    root.display(),
+                    holder.trim(),
+                    path.display()
+                );
+            }
+            Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(
+            file,
+            "pid {} since {} for {}",
+            std::process::id(),
+            chrono::Utc::now().to_rfc3339(),
+            root.display()
+        )?;
+        tracing::info!("🔒 Locked {} ({})", root.display(), path.display());
+
+        let journal = std::sync::Arc::new(crate::journal::ScanJournal::new(
+            path.with_extension("journal.json"),
+        ));
+        Ok(Self {
+            path,
+            journal,
+            _file: file,
+        })
+    }
+
+    /// Path of the lock file
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Crash journal of the root, only accessible while it is locked
+    pub fn journal(&self) -> std::sync::Arc<crate::journal::ScanJournal> {
+        self.journal.clone()
+    }
+}
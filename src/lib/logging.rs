@@ -1,7 +1,84 @@
-use tracing_subscriber::fmt::writer::MakeWriterExt;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::FormatTime;
+use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriterExt};
+
+/// Handle to swap the log filter of the installed subscriber, see [`set_log_filter`]
+static FILTER: std::sync::OnceLock<
+    tracing_subscriber::reload::Handle<tracing_subscriber::EnvFilter, tracing_subscriber::Registry>,
+> = std::sync::OnceLock::new();
+
+/// Log timestamps in the zone named by `TZ` when it is set, in UTC otherwise
+struct Timestamp {
+    local: bool,
+}
+
+impl FormatTime for Timestamp {
+    fn format_time(&self, w: &mut Writer<'_>) -> std::fmt::Result {
+        if self.local {
+            let now = chrono::Local::now();
+            write!(
+                w,
+                "{}",
+                now.to_rfc3339_opts(chrono::SecondsFormat::Micros, false)
+            )
+        } else {
+            let now = chrono::Utc::now();
+            write!(
+                w,
+                "{}",
+                now.to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+            )
+        }
+    }
+}
 
 pub fn setup_logging(
     log_file: Option<&std::path::Path>,
+) -> anyhow::Result<tracing_appender::non_blocking::WorkerGuard> {
+    setup_logging_with_journald(log_file, false)
+}
+
+/// Like [`setup_logging`], but with `journald` send events to the systemd
+/// journal as structured entries instead of printing them to stdout
+pub fn setup_logging_with_journald(
+    log_file: Option<&std::path::Path>,
+    journald: bool,
+) -> anyhow::Result<tracing_appender::non_blocking::WorkerGuard> {
+    setup(log_file, journald, false)
+}
+
+/// Like [`setup_logging`], but print events to stderr, for tools whose stdout
+/// is data, e.g. a NUL-delimited path list piped into `xargs -0`
+pub fn setup_logging_to_stderr(
+    log_file: Option<&std::path::Path>,
+) -> anyhow::Result<tracing_appender::non_blocking::WorkerGuard> {
+    setup(log_file, false, true)
+}
+
+/// Filter of the installed subscriber: `RUST_LOG`, at least at INFO
+fn default_filter() -> tracing_subscriber::EnvFilter {
+    tracing_subscriber::EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into())
+}
+
+/// Replace the log filter of a running process with `directives` in
+/// `RUST_LOG` syntax (e.g. `info,fs_delta_tracker=debug`), or restore the
+/// default one with `None`
+pub fn set_log_filter(directives: Option<&str>) -> anyhow::Result<()> {
+    let filter = match directives {
+        Some(directives) => tracing_subscriber::EnvFilter::try_new(directives)
+            .map_err(|e| anyhow::anyhow!("Invalid log filter '{}': {}", directives, e))?,
+        None => default_filter(),
+    };
+    if let Some(handle) = FILTER.get() {
+        handle.reload(filter)?;
+    }
+    Ok(())
+}
+
+fn setup(
+    log_file: Option<&std::path::Path>,
+    journald: bool,
+    stderr: bool,
 ) -> anyhow::Result<tracing_appender::non_blocking::WorkerGuard> {
     let log_path = log_file.unwrap_or(std::path::Path::new("logs/app.log"));
     let log_dir = log_path.parent().unwrap_or(std::path::Path::new("."));
@@ -12,17 +89,49 @@ pub fn setup_logging(
     let file_appender = tracing_appender::rolling::daily(log_dir, log_filename);
     let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
 
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive(tracing::Level::INFO.into()),
+    let (filter, handle) = tracing_subscriber::reload::Layer::new(default_filter());
+    let _ = FILTER.set(handle);
+    let timer = Timestamp {
+        local: std::env::var_os("TZ").is_some(),
+    };
+
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    if journald {
+        let file_layer = tracing_subscriber::fmt::layer()
+            .with_timer(timer)
+            .with_target(true)
+            .with_ansi(false)
+            .with_writer(non_blocking);
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(
+                tracing_journald::layer().map_err(|e| {
+                    anyhow::anyhow!("Failed to connect to the systemd journal: {}", e)
+                })?,
+            )
+            .with(file_layer)
+            .init();
+        return Ok(guard);
+    }
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_timer(timer)
+                .with_target(true)
+                .with_thread_ids(false)
+                .with_file(false)
+                .with_line_number(false)
+                .with_ansi(false)
+                .with_writer(if stderr {
+                    BoxMakeWriter::new(std::io::stderr.and(non_blocking))
+                } else {
+                    BoxMakeWriter::new(std::io::stdout.and(non_blocking))
+                }),
         )
-        .with_target(true)
-        .with_thread_ids(false)
-        .with_file(false)
-        .with_line_number(false)
-        .with_ansi(false)
-        .with_writer(std::io::stdout.and(non_blocking))
         .init();
 
     Ok(guard)
@@ -0,0 +1,128 @@
+use crate::progress::Phase;
+
+/// Exit code of a run that failed in a way a retry cannot fix, e.g. bad
+/// configuration or a scan flagged by a sanity guard
+pub const EXIT_PERMANENT: u8 = 1;
+/// Exit code of a run that failed transiently, e.g. the database was
+/// unreachable; matches `EX_TEMPFAIL` from sysexits.h
+pub const EXIT_RETRYABLE: u8 = 75;
+/// Exit code of a run cancelled by its `--timeout-minutes` deadline or one
+/// of a phase; matches timeout(1)
+pub const EXIT_TIMED_OUT: u8 = 124;
+/// Exit code of a scan that completed but whose changes violate its
+/// `--integrity-policy`
+pub const EXIT_INTEGRITY_VIOLATION: u8 = 3;
+
+/// Error of a run, or of one `phase` of its scan, that overran its deadline
+#[derive(Debug)]
+pub struct TimedOut {
+    pub scan_id: Option<i32>,
+    /// `None` when the deadline was the whole run's
+    pub phase: Option<Phase>,
+    pub timeout_minutes: u64,
+}
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.phase, self.scan_id) {
+            (Some(phase), Some(scan_id)) => write!(
+                f,
+                "The {} phase of scan {} did not finish within {} minutes",
+                phase, scan_id, self.timeout_minutes
+            ),
+            (None, Some(scan_id)) => write!(
+                f,
+                "Scan {} did not finish within {} minutes",
+                scan_id, self.timeout_minutes
+            ),
+            _ => write!(
+                f,
+                "Run did not finish within {} minutes",
+                self.timeout_minutes
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Kubernetes only keeps the first 4096 bytes of a termination message
+const TERMINATION_MESSAGE_LIMIT: usize = 4096;
+
+/// Whether `error` is worth retrying: lost or refused database connections,
+/// server shutdowns, serialization failures and transient I/O errors
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<tokio_postgres::Error>() {
+            if e.is_closed() {
+                return true;
+            }
+            if let Some(code) = e.code() {
+                let code = code.code();
+                // connection exceptions, insufficient resources, operator intervention
+                return code.starts_with("08")
+                    || code.starts_with("53")
+                    || code.starts_with("57P")
+                    || code == "40001"
+                    || code == "40P01";
+            }
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            return matches!(
+                e.kind(),
+                std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::NotConnected
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::WouldBlock
+            );
+        }
+        false
+    })
+}
+
+/// Exit code for a failed run
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    if error.downcast_ref::<TimedOut>().is_some() {
+        EXIT_TIMED_OUT
+    } else if is_retryable(error) {
+        EXIT_RETRYABLE
+    } else {
+        EXIT_PERMANENT
+    }
+}
+
+/// Write a Kubernetes-style termination message, truncated to what the
+/// kubelet keeps. Failures are logged, not returned, since this runs on exit.
+pub fn write_termination_message(path: &std::path::Path, message: &str) {
+    let mut end = message.len().min(TERMINATION_MESSAGE_LIMIT);
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    if let Err(e) = std::fs::write(path, &message[..end]) {
+        tracing::warn!(
+            "⚠️ Failed to write termination message to {}: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Write the run summary as pretty-printed JSON, creating parent directories
+pub fn write_summary(path: &std::path::Path, summary: &serde_json::Value) {
+    let result = (|| -> anyhow::Result<()> {
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(summary)?)?;
+        Ok(())
+    })();
+    match result {
+        Ok(()) => tracing::info!("📄 Summary written to {}", path.display()),
+        Err(e) => tracing::warn!("⚠️ Failed to write summary to {}: {}", path.display(), e),
+    }
+}
@@ -0,0 +1,58 @@
+/// How a command prints its result: a table or log lines for people, or one
+/// JSON document on stdout for scripts, with logging moved to stderr
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => anyhow::bail!("Unknown output format {}, expected text or json", s),
+        }
+    }
+}
+
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+        })
+    }
+}
+
+impl OutputFormat {
+    pub fn is_json(self) -> bool {
+        self == OutputFormat::Json
+    }
+
+    /// Set up logging for a command printing in this format: to stderr for
+    /// JSON, so stdout holds nothing but the document
+    pub fn setup_logging(
+        self,
+        log_file: Option<&std::path::Path>,
+    ) -> anyhow::Result<tracing_appender::non_blocking::WorkerGuard> {
+        match self {
+            OutputFormat::Text => crate::logging::setup_logging(log_file),
+            OutputFormat::Json => crate::logging::setup_logging_to_stderr(log_file),
+        }
+    }
+}
+
+/// Print `value` to stdout as one pretty-printed JSON document
+pub fn print_json(value: &impl serde::Serialize) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, value)?;
+    writeln!(stdout)?;
+    stdout.flush()?;
+    Ok(())
+}
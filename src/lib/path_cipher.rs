@@ -0,0 +1,161 @@
+use base64::Engine;
+use hmac::Mac;
+
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
+
+/// Length of the synthetic IV prefixed to each encrypted component
+const IV_LEN: usize = 16;
+/// Shortest accepted site key, in bytes
+const MIN_KEY_LEN: usize = 32;
+
+/// Deterministic encryption of path components with a site key.
+///
+/// Each component below the scan root is encrypted on its own, so equal
+/// paths encrypt equally and the tree structure is kept: deltas, directory
+/// rollups and depth filters work on encrypted paths unchanged. The scheme
+/// is SIV-like: the IV is an HMAC of the plaintext, which also authenticates
+/// it, and the keystream is HMAC-SHA256 of the IV and a block counter.
+/// Equal component names are visible as such, as are name lengths.
+#[derive(Clone)]
+pub struct PathCipher {
+    siv_key: [u8; 32],
+    stream_key: [u8; 32],
+    key_id: String,
+}
+
+impl std::fmt::Debug for PathCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PathCipher")
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+impl PathCipher {
+    /// Derive the cipher from a site key of at least 32 bytes
+    pub fn new(key: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            key.len() >= MIN_KEY_LEN,
+            "Path encryption key must be at least {} bytes, got {}",
+            MIN_KEY_LEN,
+            key.len()
+        );
+        let key_id = hmac(key, &[b"fs-delta-tracker path key id"]);
+        Ok(Self {
+            siv_key: hmac(key, &[b"fs-delta-tracker path siv"]),
+            stream_key: hmac(key, &[b"fs-delta-tracker path stream"]),
+            key_id: key_id[..8].iter().map(|b| format!("{:02x}", b)).collect(),
+        })
+    }
+
+    /// Read the site key from a file, ignoring surrounding whitespace
+    pub fn from_key_file(path: &std::path::Path) -> anyhow::Result<Self> {
+        let key = std::fs::read(path).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to read path encryption key {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+        Self::new(key.trim_ascii())
+    }
+
+    /// Fingerprint of the key, recorded on scans so that a root is never
+    /// scanned with two different keys (or with and without one)
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    fn apply_keystream(&self, iv: &[u8], data: &mut [u8]) {
+        for (counter, chunk) in data.chunks_mut(32).enumerate() {
+            let block = hmac(&self.stream_key, &[iv, &(counter as u32).to_be_bytes()]);
+            for (byte, key) in chunk.iter_mut().zip(block) {
+                *byte ^= key;
+            }
+        }
+    }
+
+    /// Encrypt one path component into URL-safe base64 (no `/`)
+    pub fn encrypt_component(&self, name: &str) -> String {
+        let iv = hmac(&self.siv_key, &[name.as_bytes()]);
+        let mut out = iv[..IV_LEN].to_vec();
+        out.extend_from_slice(name.as_bytes());
+        let (iv, data) = out.split_at_mut(IV_LEN);
+        self.apply_keystream(iv, data);
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(out)
+    }
+
+    /// Decrypt a component made by [`Self::encrypt_component`] with the same
+    /// key; `None` if it is not one
+    pub fn decrypt_component(&self, component: &str) -> Option<String> {
+        let mut data = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(component)
+            .ok()?;
+        if data.len() < IV_LEN {
+            return None;
+        }
+        let (iv, name) = data.split_at_mut(IV_LEN);
+        self.apply_keystream(iv, name);
+        let name = String::from_utf8(name.to_vec()).ok()?;
+        (hmac(&self.siv_key, &[name.as_bytes()])[..IV_LEN] == *iv).then_some(name)
+    }
+
+    /// Path to record for `path` found under `root`: the root as is,
+    /// followed by the encrypted components below it
+    pub fn encrypt_path(&self, root: &std::path::Path, path: &std::path::Path) -> String {
+        let Ok(relative) = path.strip_prefix(root) else {
+            return path.to_string_lossy().to_string();
+        };
+        let mut out = root.to_string_lossy().trim_end_matches('/').to_string();
+        for component in relative.components() {
+            out.push('/');
+            out.push_str(&self.encrypt_component(&component.as_os_str().to_string_lossy()));
+        }
+        out
+    }
+
+    /// Decrypt the encrypted components of a recorded path, leaving the
+    /// others (the root's) as they are
+    pub fn decrypt_path(&self, path: &str) -> String {
+        path.split('/')
+            .map(|component| {
+                self.decrypt_component(component)
+                    .unwrap_or_else(|| component.to_string())
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// The cipher to read the paths of `scan_id` with, given the key id recorded
+/// on the scan: `None` for unencrypted scans, an error if the scan's key is
+/// not the one given
+pub fn cipher_for_scan<'a>(
+    scan_id: i32,
+    path_key_id: Option<&str>,
+    cipher: Option<&'a PathCipher>,
+) -> anyhow::Result<Option<&'a PathCipher>> {
+    match (path_key_id, cipher) {
+        (None, _) => Ok(None),
+        (Some(key_id), None) => anyhow::bail!(
+            "Scan {} recorded paths encrypted with key {}; pass its key file to read them",
+            scan_id,
+            key_id
+        ),
+        (Some(key_id), Some(cipher)) if key_id != cipher.key_id() => anyhow::bail!(
+            "Scan {} recorded paths encrypted with key {}, not {}",
+            scan_id,
+            key_id,
+            cipher.key_id()
+        ),
+        (Some(_), Some(cipher)) => Ok(Some(cipher)),
+    }
+}
@@ -0,0 +1,139 @@
+use crate::data;
+use crate::progress::{ProgressEvent, ProgressReporter};
+
+/// How often a crawling scan checks whether it was paused or resumed
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+/// How often idle crawl workers check whether they may go on
+const IDLE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Daily window of local time (`TZ`) crawls may run in, e.g. `22:00-06:00`;
+/// a window ending before it starts spans midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllowedHours {
+    pub start: chrono::NaiveTime,
+    pub end: chrono::NaiveTime,
+}
+
+impl std::str::FromStr for AllowedHours {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |time: &str| {
+            chrono::NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|e| anyhow::anyhow!("Invalid time '{}' in allowed hours: {}", time, e))
+        };
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow::anyhow!("Expected allowed hours as HH:MM-HH:MM, got {}", s))?;
+        let hours = AllowedHours {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+        anyhow::ensure!(
+            hours.start != hours.end,
+            "Allowed hours {} are an empty window",
+            s
+        );
+        Ok(hours)
+    }
+}
+
+impl std::fmt::Display for AllowedHours {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+impl AllowedHours {
+    pub fn contains(&self, time: chrono::NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    pub fn contains_now(&self) -> bool {
+        self.contains(chrono::Local::now().time())
+    }
+}
+
+/// Whether the workers of a crawl should idle; cheap to clone into them.
+/// The default switch is never flipped.
+#[derive(Debug, Clone, Default)]
+pub struct PauseSwitch(std::sync::Arc<std::sync::atomic::AtomicBool>);
+
+impl PauseSwitch {
+    pub fn set(&self, paused: bool) {
+        self.0.store(paused, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Block the calling worker for as long as the crawl is paused
+    pub fn wait(&self) {
+        while self.is_paused() {
+            std::thread::sleep(IDLE_INTERVAL);
+        }
+    }
+}
+
+/// Drive `crawl` of `scan_id` to completion while mirroring the scan's pause
+/// flag, set by `pause_scan`, into `switch`, and pausing it outside of
+/// `allowed_hours`
+pub(crate) async fn follow_scan<T>(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    allowed_hours: Option<AllowedHours>,
+    switch: &PauseSwitch,
+    progress: &ProgressReporter,
+    crawl: impl std::future::Future<Output = T>,
+) -> T {
+    tokio::pin!(crawl);
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut requested = false;
+    loop {
+        tokio::select! {
+            result = &mut crawl => {
+                switch.set(false);
+                return result;
+            }
+            _ = interval.tick() => {
+                match data::is_scan_paused(client, scan_id).await {
+                    Ok(paused) => requested = paused,
+                    Err(e) => {
+                        tracing::warn!("⚠️ Failed to check whether scan {} is paused: {}", scan_id, e);
+                    }
+                }
+                let outside = allowed_hours.filter(|hours| !hours.contains_now());
+                let paused = requested || outside.is_some();
+                if paused == switch.is_paused() {
+                    continue;
+                }
+                switch.set(paused);
+                if let Some(hours) = outside.filter(|_| !requested) {
+                    tracing::warn!(
+                        "⏸️ Scan {} paused outside the allowed hours {}, crawl workers idle until {}",
+                        scan_id,
+                        hours,
+                        hours.start.format("%H:%M")
+                    );
+                    progress.emit(ProgressEvent::CrawlPaused);
+                } else if paused {
+                    tracing::warn!("⏸️ Scan {} paused, crawl workers idle until it is resumed", scan_id);
+                    progress.emit(ProgressEvent::CrawlPaused);
+                } else {
+                    tracing::info!("▶️ Scan {} resumed", scan_id);
+                    progress.emit(ProgressEvent::CrawlResumed);
+                }
+            }
+        }
+    }
+}
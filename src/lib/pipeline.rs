@@ -0,0 +1,1944 @@
+use crate::progress::{Phase, ProgressEvent, ProgressReporter};
+use crate::snapshot_diff::SnapshotDiff;
+use crate::staging::StagingStrategy;
+use crate::{crawler, data, db, integrity, outcome, pause, snapshot_diff};
+
+/// Bytes in flight between a streamed crawl and its COPY
+const STREAM_PIPE_BYTES: usize = 1024 * 1024;
+
+static PROJECT_DIR: include_dir::Dir = include_dir::include_dir!("$CARGO_MANIFEST_DIR/assets");
+
+/// Options for a full crawl -> load -> process -> finalize scan run
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// The directory to scan
+    pub data_root: std::path::PathBuf,
+    /// Further directories crawled into the same scan and change set, e.g.
+    /// mount points of one system; the scan is recorded under `data_root`
+    pub extra_roots: Vec<std::path::PathBuf>,
+    /// Progress logging interval in seconds
+    pub progress_interval: u64,
+    /// Flag the scan instead of processing it if fewer files are found
+    pub min_expected_files: Option<u64>,
+    /// Flag the scan if fewer than this fraction of the previous scan's files are found
+    pub min_files_ratio: Option<f64>,
+    /// Write deltas to the pending table instead of applying them
+    pub review: bool,
+    /// Crawler tuning
+    pub crawl: crawler::CrawlOptions,
+    /// Number of largest added files to call out in the scan summary (0 disables)
+    pub largest_new_files: usize,
+    /// Added files at least this large (in MB) are reported as alerts
+    pub large_file_alert_mb: Option<f64>,
+    /// Store a Merkle root over the change set for tamper evidence
+    pub merkle_root: bool,
+    /// Throttle loading the crawl into staging to this many rows per second
+    pub load_max_rows_per_second: Option<u64>,
+    /// Commit the load in chunks of this many rows, so that an interrupted
+    /// scan resumes its load after the last chunk (0 loads in one go)
+    pub load_checkpoint_rows: u64,
+    /// Send the crawl to staging in COPY's text or binary format
+    pub copy_format: data::CopyFormat,
+    /// COPY the crawl into staging as it is walked instead of through a TSV
+    /// file; the load is neither checkpointed nor resumable
+    pub stream_load: bool,
+    /// Crawl, load and process the root one top-level directory at a time
+    pub batch_by_top_level_dir: bool,
+    /// Table the crawl is staged in
+    pub staging: StagingStrategy,
+    /// Drop the staging table's secondary indexes for the load and rebuild them after
+    pub defer_staging_indexes: bool,
+    /// Department the root belongs to, recorded on the scan for tenant isolation
+    pub tenant: Option<String>,
+    /// Build the change set from the filesystem's snapshot diff since the
+    /// previous scan instead of crawling, when one is available
+    pub snapshot_diff: Option<SnapshotDiff>,
+    /// Directory of the per-root lock files, where `fanotify_watch` keeps
+    /// its change logs
+    pub lock_dir: std::path::PathBuf,
+    /// Pause the crawl outside of this daily window, resuming in the next one
+    pub allowed_hours: Option<pause::AllowedHours>,
+    /// Fail the scan if crawling, loading or processing it takes longer
+    pub crawl_timeout_minutes: Option<u64>,
+    pub load_timeout_minutes: Option<u64>,
+    pub process_timeout_minutes: Option<u64>,
+    /// Record a scan without changes, neither loading nor processing its
+    /// crawl, if the crawl matches the previous completed scan of the root
+    pub skip_unchanged: bool,
+}
+
+impl ScanOptions {
+    pub fn new(data_root: std::path::PathBuf) -> Self {
+        Self {
+            data_root,
+            extra_roots: Vec::new(),
+            progress_interval: 30,
+            min_expected_files: None,
+            min_files_ratio: None,
+            review: false,
+            crawl: crawler::CrawlOptions::default(),
+            largest_new_files: 10,
+            large_file_alert_mb: None,
+            merkle_root: false,
+            load_max_rows_per_second: None,
+            load_checkpoint_rows: 1_000_000,
+            copy_format: data::CopyFormat::default(),
+            stream_load: false,
+            batch_by_top_level_dir: false,
+            staging: StagingStrategy::default(),
+            defer_staging_indexes: false,
+            tenant: None,
+            snapshot_diff: None,
+            lock_dir: crate::lock::default_lock_dir(),
+            allowed_hours: None,
+            crawl_timeout_minutes: None,
+            load_timeout_minutes: None,
+            process_timeout_minutes: None,
+            skip_unchanged: false,
+        }
+    }
+
+    /// Reject combinations of options that cannot work together
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !(self.batch_by_top_level_dir && self.review),
+            "Batched scans apply each batch as it goes and cannot be reviewed"
+        );
+        anyhow::ensure!(
+            !(self.staging == StagingStrategy::Temporary && self.review),
+            "Reviewed scans keep their staging rows until `apply_scan`, which temporary staging cannot"
+        );
+        anyhow::ensure!(
+            self.snapshot_diff.is_none() || !(self.batch_by_top_level_dir || self.review),
+            "Snapshot diff scans apply their deltas directly and cannot be batched or reviewed"
+        );
+        anyhow::ensure!(
+            self.extra_roots.is_empty()
+                || !(self.batch_by_top_level_dir || self.snapshot_diff.is_some()),
+            "Scans of several roots cannot be batched or use snapshot diffs"
+        );
+        anyhow::ensure!(
+            !self.stream_load || !(self.batch_by_top_level_dir || self.skip_unchanged),
+            "Streamed scans load as they crawl and cannot be batched or skip unchanged crawls"
+        );
+        anyhow::ensure!(
+            !(self.stream_load && self.allowed_hours.is_some()),
+            "Streamed scans hold their COPY open for the whole crawl and cannot pause outside allowed hours"
+        );
+        anyhow::ensure!(
+            self.snapshot_diff.is_none()
+                || !(self.crawl.follow_symlinks || self.crawl.record_symlinks),
+            "Snapshot diff scans only recrawl the regular files that changed and cannot follow or record symlinks"
+        );
+        anyhow::ensure!(
+            !self.crawl.record_dirs
+                || !(self.review
+                    || self.batch_by_top_level_dir
+                    || self.stream_load
+                    || self.snapshot_diff.is_some()),
+            "Scans recording directories apply them with the files' deltas and cannot be reviewed, batched, streamed or use snapshot diffs"
+        );
+        self.crawl.overrides(&self.data_root)?;
+        anyhow::ensure!(
+            self.crawl
+                .sample_fraction
+                .is_none_or(|fraction| fraction > 0.0 && fraction <= 1.0),
+            "The sample fraction must be above 0 and at most 1"
+        );
+        let roots = self.roots();
+        for (i, root) in roots.iter().enumerate() {
+            if let Some(other) = roots[..i]
+                .iter()
+                .find(|other| root.starts_with(other) || other.starts_with(root))
+            {
+                anyhow::bail!(
+                    "Roots {} and {} overlap; each file must belong to one root",
+                    other.display(),
+                    root.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// `data_root` and the `extra_roots`
+    pub fn roots(&self) -> Vec<std::path::PathBuf> {
+        std::iter::once(&self.data_root)
+            .chain(&self.extra_roots)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Run `fut` as `phase`, reporting its start, completion or failure
+pub(crate) async fn run_phase<T>(
+    progress: &ProgressReporter,
+    phase: Phase,
+    fut: impl std::future::Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    progress.emit(ProgressEvent::PhaseStarted { phase });
+    let start = std::time::Instant::now();
+    match fut.await {
+        Ok(value) => {
+            progress.emit(ProgressEvent::PhaseCompleted {
+                phase,
+                duration: start.elapsed(),
+            });
+            Ok(value)
+        }
+        Err(e) => {
+            progress.emit(ProgressEvent::Error {
+                phase,
+                message: e.to_string(),
+            });
+            Err(e)
+        }
+    }
+}
+
+/// Run `fut`, the `phase` of `scan_id`, for at most `timeout_minutes`. When
+/// it overruns, the statement in flight is cancelled and its transaction
+/// rolled back, the timeout is recorded in the scan's metadata and an
+/// [`outcome::TimedOut`] error returned; crawl threads stuck on a dead mount
+/// are abandoned.
+pub(crate) async fn with_phase_timeout<T>(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    phase: Phase,
+    timeout_minutes: Option<u64>,
+    fut: impl std::future::Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    let Some(timeout_minutes) = timeout_minutes else {
+        return fut.await;
+    };
+    let timeout = std::time::Duration::from_secs(timeout_minutes * 60);
+    let Ok(result) = tokio::time::timeout(timeout, fut).await else {
+        tracing::error!(
+            "⏰ The {} phase of scan {} exceeded its {} minute timeout",
+            phase,
+            scan_id,
+            timeout_minutes
+        );
+        if let Err(e) = client
+            .cancel_token()
+            .cancel_query(tokio_postgres::NoTls)
+            .await
+        {
+            tracing::warn!("⚠️ Failed to cancel the running statement: {}", e);
+        }
+        // A no-op, with a warning, when no transaction was open
+        client.batch_execute("ROLLBACK").await?;
+        let mut metadata = std::collections::HashMap::new();
+        metadata.insert("timed_out_phase".to_string(), phase.to_string());
+        metadata.insert(
+            format!("{}_timeout_minutes", phase),
+            timeout_minutes.to_string(),
+        );
+        data::merge_scan_metadata(client, scan_id, &metadata).await?;
+        return Err(outcome::TimedOut {
+            scan_id: Some(scan_id),
+            phase: Some(phase),
+            timeout_minutes,
+        }
+        .into());
+    };
+    result
+}
+
+pub(crate) fn sql_template(name: &str) -> &'static str {
+    PROJECT_DIR
+        .get_file(format!("templates/sql/{}", name))
+        .expect("SQL template file not found")
+        .contents_utf8()
+        .expect("Failed to read SQL template as UTF-8")
+}
+
+pub(crate) fn html_template(name: &str) -> &'static str {
+    PROJECT_DIR
+        .get_file(format!("templates/html/{}", name))
+        .expect("HTML template file not found")
+        .contents_utf8()
+        .expect("Failed to read HTML template as UTF-8")
+}
+
+/// Scan `options.data_root` and record its deltas, returning the scan_id
+#[tracing::instrument(skip(client, options, progress))]
+pub async fn run_scan(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    progress: &ProgressReporter,
+) -> anyhow::Result<i32> {
+    options.validate()?;
+    // before any crawl sizes its threads by it
+    crate::fd_limit::raise();
+    crate::resource_usage::watch_open_fds();
+    let path_key_id = options.crawl.path_cipher.as_ref().map(|c| c.key_id());
+    for root in options.roots() {
+        check_root_tenant(client, &root, options.tenant.as_deref()).await?;
+        check_root_path_key(client, &root, path_key_id).await?;
+    }
+    let started_at = chrono::Utc::now();
+    let scan_id = data::start_scan(
+        client,
+        &options.data_root,
+        &options.extra_roots,
+        options.tenant.as_deref(),
+        path_key_id,
+        started_at,
+    )
+    .await?;
+    tracing::info!("🔍 Scan ID: {}", scan_id);
+
+    let output_tsv_file = output_tsv_path(scan_id);
+    tracing::info!("📝 Output TSV file: {}", output_tsv_file.display());
+    progress.emit(ProgressEvent::ScanStarted {
+        scan_id,
+        output_tsv_file: output_tsv_file.clone(),
+    });
+
+    if options.batch_by_top_level_dir {
+        run_batches(client, options, scan_id, progress).await?;
+        return Ok(scan_id);
+    }
+
+    let Some(kind) = options.snapshot_diff else {
+        crawl_scan(client, options, scan_id, &output_tsv_file, None, progress).await?;
+        return Ok(scan_id);
+    };
+    // Taken before the diff or crawl, so the next scan's diff covers
+    // everything that changed after it
+    let marker = match kind
+        .mark(&options.data_root, scan_id, &options.lock_dir)
+        .await
+    {
+        Ok(marker) => marker,
+        Err(e) => {
+            tracing::warn!(
+                "⚠️ No {} snapshot diff for {}, crawling it: {:#}",
+                kind,
+                options.data_root.display(),
+                e
+            );
+            crawl_scan(client, options, scan_id, &output_tsv_file, None, progress).await?;
+            return Ok(scan_id);
+        }
+    };
+    tracing::info!(
+        "📸 Marked {}: {} {}",
+        options.data_root.display(),
+        kind.metadata_key(),
+        marker
+    );
+
+    let previous = match data::get_latest_completed_scan(client, &options.data_root).await? {
+        Some((previous_scan_id, _)) => data::get_scan_metadata(client, previous_scan_id)
+            .await?
+            .remove(kind.metadata_key()),
+        None => None,
+    };
+    let result = async {
+        if let Some(previous) = &previous
+            && run_snapshot_diff(
+                client,
+                options,
+                scan_id,
+                kind,
+                previous,
+                &marker,
+                &output_tsv_file,
+                progress,
+            )
+            .await?
+        {
+            return Ok(());
+        }
+        if previous.is_none() {
+            tracing::info!("📸 No previous {} marker to diff against, crawling", kind);
+        }
+        crawl_scan(
+            client,
+            options,
+            scan_id,
+            &output_tsv_file,
+            Some((kind, &marker)),
+            progress,
+        )
+        .await
+    }
+    .await;
+
+    // the next scan diffs against this scan's marker
+    let stale = match &result {
+        Ok(()) => previous,
+        Err(_) => Some(marker),
+    };
+    if let Some(stale) = stale
+        && let Err(e) = kind.release(&stale).await
+    {
+        tracing::warn!("⚠️ Failed to release {} marker {}: {:#}", kind, stale, e);
+    }
+    result?;
+    Ok(scan_id)
+}
+
+/// Record the entries of `options.data_root` a [`crate::watch::Watcher`] saw
+/// change as a scan of their own, restated from disk, returning the scan_id.
+/// A failed scan is voided, or flagged if its deltas were partly applied.
+#[tracing::instrument(skip(client, options, entries, progress))]
+pub async fn run_watch_scan(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    watcher: &str,
+    entries: snapshot_diff::DiffEntries,
+    progress: &ProgressReporter,
+) -> anyhow::Result<i32> {
+    anyhow::ensure!(
+        options.extra_roots.is_empty(),
+        "A watcher covers a single root"
+    );
+    let path_key_id = options.crawl.path_cipher.as_ref().map(|c| c.key_id());
+    check_root_tenant(client, &options.data_root, options.tenant.as_deref()).await?;
+    check_root_path_key(client, &options.data_root, path_key_id).await?;
+    let scan_id = data::start_scan(
+        client,
+        &options.data_root,
+        &[],
+        options.tenant.as_deref(),
+        path_key_id,
+        chrono::Utc::now(),
+    )
+    .await?;
+    tracing::info!("🔍 Scan ID: {}", scan_id);
+    let output_tsv_file = output_tsv_path(scan_id);
+    progress.emit(ProgressEvent::ScanStarted {
+        scan_id,
+        output_tsv_file: output_tsv_file.clone(),
+    });
+
+    let mut metadata = std::collections::HashMap::new();
+    metadata.insert("watch".to_string(), watcher.to_string());
+    let result = apply_diff_entries(
+        client,
+        options,
+        scan_id,
+        watcher,
+        entries,
+        metadata,
+        &output_tsv_file,
+        progress,
+    )
+    .await;
+    if let Err(e) = result {
+        if output_tsv_file.exists() {
+            remove_tsv_file(&output_tsv_file);
+        }
+        let discarded = async {
+            data::clear_staging(client, scan_id).await?;
+            if data::has_file_changes(client, scan_id).await? {
+                let anomaly = serde_json::json!({ "watch_failed": format!("{:#}", e) });
+                let metadata = data::get_scan_metadata(client, scan_id).await?;
+                data::flag_scan(client, scan_id, anomaly, metadata).await
+            } else {
+                data::void_scan(client, scan_id).await
+            }
+        }
+        .await;
+        if let Err(discard_error) = discarded {
+            tracing::warn!("⚠️ Failed to discard scan {}: {:#}", scan_id, discard_error);
+        }
+        return Err(e);
+    }
+    Ok(scan_id)
+}
+
+/// Scan `options.data_root` into `store` and record its deltas, returning
+/// the scan_id: a plain crawl, staged and applied in one go. What only
+/// [`run_scan`] offers on PostgreSQL (review mode, batches, snapshot diffs,
+/// sanity guards, resumable loads, hot directory records, ...) is left out;
+/// a failed scan is aborted rather than left to resume.
+#[tracing::instrument(skip(store, options, progress))]
+pub async fn run_store_scan(
+    store: &impl db::DeltaStore,
+    options: &ScanOptions,
+    progress: &ProgressReporter,
+) -> anyhow::Result<i32> {
+    options.validate()?;
+    anyhow::ensure!(
+        !options.crawl.record_dirs,
+        "Directories are only recorded by scans of the PostgreSQL pipeline"
+    );
+    anyhow::ensure!(
+        !options.crawl.record_symlinks,
+        "Symlinks are only recorded by scans of the PostgreSQL pipeline"
+    );
+    // without hot directory records, the entries left out would be deleted
+    anyhow::ensure!(
+        options.crawl.max_entries_per_dir.is_none(),
+        "Directories are only capped by scans of the PostgreSQL pipeline"
+    );
+    crate::fd_limit::raise();
+    crate::resource_usage::watch_open_fds();
+    let scan_id = store
+        .start_scan(&options.data_root, chrono::Utc::now())
+        .await?;
+    tracing::info!("🔍 Scan ID: {}", scan_id);
+
+    let output_tsv_file = output_tsv_path(scan_id);
+    tracing::info!("📝 Output TSV file: {}", output_tsv_file.display());
+    progress.emit(ProgressEvent::ScanStarted {
+        scan_id,
+        output_tsv_file: output_tsv_file.clone(),
+    });
+    let result = async {
+        tracing::info!("🔍 Starting directory walk...");
+        let report = run_phase(
+            progress,
+            Phase::Crawl,
+            crawler::walk_directory(
+                options.roots(),
+                options.progress_interval,
+                scan_id,
+                output_tsv_file.clone(),
+                progress.clone(),
+                &options.crawl,
+                pause::PauseSwitch::default(),
+            ),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("Directory walk failed: {}", e))?;
+        let mut metadata = report.metadata;
+        metadata.insert("hostname".to_string(), data::local_hostname());
+        metadata.insert("backend".to_string(), store.backend().to_string());
+
+        let rows = run_phase(
+            progress,
+            Phase::Load,
+            store.load_crawl(scan_id, &output_tsv_file, progress),
+        )
+        .await?;
+        tracing::info!("📥 Staged {} rows", rows);
+        run_phase(progress, Phase::Process, store.apply_deltas(scan_id)).await?;
+        run_phase(
+            progress,
+            Phase::Finalize,
+            store.finalize_scan(scan_id, metadata),
+        )
+        .await
+    }
+    .await;
+    remove_tsv_file(&output_tsv_file);
+    if let Err(e) = &result {
+        let reason = serde_json::json!({ "failed": format!("{:#}", e) });
+        if let Err(abort_error) = store.abort_scan(scan_id, reason).await {
+            tracing::warn!("⚠️ Failed to abort scan {}: {:#}", scan_id, abort_error);
+        }
+    }
+    result?;
+    Ok(scan_id)
+}
+
+/// Temporary file the crawl of `scan_id` is written to, kept until the scan
+/// is processed so an interrupted scan can be resumed from it
+pub(crate) fn output_tsv_path(scan_id: i32) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("scan_{}.tsv", scan_id))
+}
+
+/// Crawl the whole root, then load and process the crawl. With a snapshot
+/// diff `marker`, it is recorded for the next scan to diff against.
+async fn crawl_scan(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    output_tsv_file: &std::path::Path,
+    marker: Option<(SnapshotDiff, &str)>,
+    progress: &ProgressReporter,
+) -> anyhow::Result<()> {
+    if options.stream_load {
+        return stream_scan(client, options, scan_id, marker, progress).await;
+    }
+    let crawl = async {
+        tracing::info!("🔍 Starting directory walk...");
+        let pause = pause::PauseSwitch::default();
+        let report = pause::follow_scan(
+            client,
+            scan_id,
+            options.allowed_hours,
+            &pause,
+            progress,
+            crawler::walk_directory(
+                options.roots(),
+                options.progress_interval,
+                scan_id,
+                output_tsv_file.to_path_buf(),
+                progress.clone(),
+                &options.crawl,
+                pause.clone(),
+            ),
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to walk directory: {}", e);
+            anyhow::anyhow!("Directory walk failed: {}", e)
+        })?;
+        tracing::info!("🔍 Scan completed with ID: {}", scan_id);
+        tracing::info!("✅ Filesystem crawler finished successfully");
+        let metadata = crawl_metadata(client, scan_id, report, marker).await?;
+
+        check_min_expected_files(client, options, scan_id, Some(output_tsv_file), &metadata)
+            .await?;
+        // Persisted so an interrupted scan can be resumed from its TSV file
+        data::set_scan_phase(client, scan_id, data::ScanPhase::Crawled, &metadata).await?;
+        Ok(metadata)
+    };
+    let mut metadata = run_phase(
+        progress,
+        Phase::Crawl,
+        with_phase_timeout(
+            client,
+            scan_id,
+            Phase::Crawl,
+            options.crawl_timeout_minutes,
+            crawl,
+        ),
+    )
+    .await?;
+
+    if options.skip_unchanged
+        && let Some(previous_scan_id) =
+            unchanged_since(client, &options.data_root, &metadata).await?
+    {
+        tracing::info!(
+            "⏭️ Crawl matches scan {}, recording no changes without loading it",
+            previous_scan_id
+        );
+        metadata.insert(
+            "unchanged_since_scan".to_string(),
+            previous_scan_id.to_string(),
+        );
+        data::copy_extension_stats(client, previous_scan_id, scan_id).await?;
+        finalize(client, options, scan_id, metadata, progress).await?;
+        remove_tsv_file(output_tsv_file);
+        return Ok(());
+    }
+
+    process_crawl(
+        client,
+        options,
+        scan_id,
+        output_tsv_file,
+        metadata,
+        progress,
+    )
+    .await?;
+    remove_tsv_file(output_tsv_file);
+    Ok(())
+}
+
+/// Crawl the whole root straight into staging through one COPY, then
+/// process it, writing nothing to disk. The scan's connection is busy with
+/// the COPY until the crawl ends, so the walk does not follow `pause_scan`.
+async fn stream_scan(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    marker: Option<(SnapshotDiff, &str)>,
+    progress: &ProgressReporter,
+) -> anyhow::Result<()> {
+    options.validate()?;
+    options.staging.prepare(client).await?;
+    let crawl = async {
+        tracing::info!("🚰 Starting directory walk, streaming it into staging...");
+        let (pipe_in, pipe_out) = tokio::io::duplex(STREAM_PIPE_BYTES);
+        let walk = crawler::walk_directory(
+            options.roots(),
+            options.progress_interval,
+            scan_id,
+            crawler::CrawlSink::Pipe(pipe_in),
+            progress.clone(),
+            &options.crawl,
+            pause::PauseSwitch::default(),
+        );
+        let pacing = data::LoadPacing {
+            progress_interval: Some(std::time::Duration::from_secs(options.progress_interval)),
+            max_rows_per_second: options.load_max_rows_per_second,
+            checkpoints: None,
+            copy_format: options.copy_format,
+        };
+        let load = async {
+            let load = data::load_tsv_stream(
+                client,
+                tokio::io::BufReader::new(pipe_out),
+                options.staging.table(),
+                &pacing,
+                progress,
+            );
+            if !options.defer_staging_indexes {
+                return load.await;
+            }
+            let (mut stats, index_rebuild) =
+                data::with_deferred_indexes(client, options.staging.table(), load).await?;
+            stats.index_rebuild = index_rebuild;
+            Ok(stats)
+        };
+        let (report, stats) = tokio::try_join!(
+            async {
+                walk.await
+                    .map_err(|e| anyhow::anyhow!("Directory walk failed: {}", e))
+            },
+            load
+        )?;
+        tracing::info!(
+            "🚰 Crawl streamed into staging: {} rows in {:.2?} ({:.2?} waiting on the database)",
+            stats.rows,
+            stats.elapsed,
+            stats.blocked
+        );
+        let mut metadata = crawl_metadata(client, scan_id, report, marker).await?;
+        stats.record(&mut metadata);
+        metadata.insert("stream_load".to_string(), "true".to_string());
+
+        if let Err(e) = check_min_expected_files(client, options, scan_id, None, &metadata).await {
+            data::clear_staging(client, scan_id).await?;
+            return Err(e);
+        }
+        // rows in temporary staging are gone with the connection
+        if options.staging != StagingStrategy::Temporary {
+            data::set_scan_phase(client, scan_id, data::ScanPhase::Loaded, &metadata).await?;
+        }
+        Ok(metadata)
+    };
+    let metadata = run_phase(
+        progress,
+        Phase::Crawl,
+        with_phase_timeout(
+            client,
+            scan_id,
+            Phase::Crawl,
+            options.crawl_timeout_minutes,
+            crawl,
+        ),
+    )
+    .await?;
+    process_loaded(client, options, scan_id, metadata, progress).await
+}
+
+/// Record the hot dirs and errors of a crawl of `scan_id` and complete its metadata
+/// with the host and the snapshot diff `marker`, if any
+async fn crawl_metadata(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    report: crawler::CrawlReport,
+    marker: Option<(SnapshotDiff, &str)>,
+) -> anyhow::Result<std::collections::HashMap<String, String>> {
+    report_hot_dirs(client, scan_id, &report.hot_dirs).await?;
+    report_scan_errors(client, scan_id, &report.errors).await?;
+    let mut metadata = report.metadata;
+
+    // Add Hostname to metadata
+    let hostname = hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "unknown".to_string());
+    metadata.insert("hostname".to_string(), hostname);
+    if let Some((kind, marker)) = marker {
+        metadata.insert(kind.metadata_key().to_string(), marker.to_string());
+    }
+    Ok(metadata)
+}
+
+/// The previous completed scan of `data_root` if a crawl with `metadata`
+/// found the same files: the same `content_sha256` if both crawls were
+/// sorted, otherwise the same file count, total size and newest mtime. The
+/// latter misses changes that keep all three, such as a rename.
+async fn unchanged_since(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+    metadata: &std::collections::HashMap<String, String>,
+) -> anyhow::Result<Option<i32>> {
+    let Some((previous_scan_id, _)) = data::get_latest_completed_scan(client, data_root).await?
+    else {
+        return Ok(None);
+    };
+    let previous = data::get_scan_metadata(client, previous_scan_id).await?;
+    let keys: &[&str] =
+        if metadata.contains_key("content_sha256") && previous.contains_key("content_sha256") {
+            &["content_sha256"]
+        } else {
+            &["total_files_processed", "total_bytes", "max_mtime"]
+        };
+    let unchanged = keys
+        .iter()
+        .all(|key| metadata.get(*key).is_some() && metadata.get(*key) == previous.get(*key));
+    Ok(unchanged.then_some(previous_scan_id))
+}
+
+/// Record the deltas of a scan from the snapshot diff between the previous
+/// scan's marker and this scan's, restating only the touched entries from
+/// disk. Returns false, having changed nothing, if the diff is unavailable.
+///
+/// The sanity guards on the file count are skipped: unlike a crawl of a
+/// half-mounted volume, a diff does not report files as gone unless they are.
+#[allow(clippy::too_many_arguments)]
+async fn run_snapshot_diff(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    kind: SnapshotDiff,
+    previous: &str,
+    marker: &str,
+    output_tsv_file: &std::path::Path,
+    progress: &ProgressReporter,
+) -> anyhow::Result<bool> {
+    tracing::info!("📸 Diffing {} -> {}", previous, marker);
+    let entries = match kind
+        .diff(&options.data_root, previous, marker, &options.lock_dir)
+        .await
+    {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!("⚠️ {} diff unavailable, crawling instead: {:#}", kind, e);
+            return Ok(false);
+        }
+    };
+
+    let mut metadata = std::collections::HashMap::new();
+    metadata.insert("snapshot_diff".to_string(), kind.to_string());
+    metadata.insert("snapshot_diff_from".to_string(), previous.to_string());
+    metadata.insert(kind.metadata_key().to_string(), marker.to_string());
+    apply_diff_entries(
+        client,
+        options,
+        scan_id,
+        &kind.to_string(),
+        entries,
+        metadata,
+        output_tsv_file,
+        progress,
+    )
+    .await?;
+    Ok(true)
+}
+
+/// Restate `entries` of `options.data_root` from disk and apply them as the
+/// deltas of scan `scan_id`, then finalize it. `metadata` tells where the
+/// entries came from, `source` names it in logs.
+#[allow(clippy::too_many_arguments)]
+async fn apply_diff_entries(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    source: &str,
+    mut entries: snapshot_diff::DiffEntries,
+    metadata: std::collections::HashMap<String, String>,
+    output_tsv_file: &std::path::Path,
+    progress: &ProgressReporter,
+) -> anyhow::Result<()> {
+    let (mut metadata, restated) = run_phase(progress, Phase::Diff, async {
+        let start = std::time::Instant::now();
+        let lines = entries.lines;
+        if !entries.rescan_dirs.is_empty() {
+            tracing::info!("📸 Rescanning {} directories", entries.rescan_dirs.len());
+            snapshot_diff::expand_rescan_dirs(
+                client,
+                &options.data_root,
+                &mut entries,
+                options.crawl.path_cipher.as_ref(),
+            )
+            .await?;
+        }
+        let restated = {
+            let data_root = options.data_root.clone();
+            let output_tsv_file = output_tsv_file.to_path_buf();
+            let crawl = options.crawl.clone();
+            tokio::task::spawn_blocking(move || {
+                snapshot_diff::restate(&data_root, &entries, scan_id, &output_tsv_file, &crawl)
+            })
+            .await??
+        };
+        tracing::info!(
+            "📸 {} lines from {}: {} files restated, {} paths and {} directories gone",
+            lines,
+            source,
+            restated.files,
+            restated.removed_paths.len(),
+            restated.removed_dirs.len()
+        );
+
+        let mut metadata = metadata;
+        metadata.insert(
+            "data_root".to_string(),
+            options.data_root.to_string_lossy().to_string(),
+        );
+        metadata.insert("hostname".to_string(), data::local_hostname());
+        metadata.insert("snapshot_diff_lines".to_string(), lines.to_string());
+        metadata.insert(
+            "snapshot_diff_restated_files".to_string(),
+            restated.files.to_string(),
+        );
+        metadata.insert(
+            "snapshot_diff_duration_s".to_string(),
+            start.elapsed().as_secs_f64().to_string(),
+        );
+        // Persisted before anything is applied: recovery must not mistake
+        // this partial crawl for a full one
+        data::set_scan_metadata(client, scan_id, &metadata).await?;
+        Ok((metadata, restated))
+    })
+    .await?;
+
+    options.staging.prepare(client).await?;
+    load_crawl(client, options, scan_id, output_tsv_file, progress)
+        .await?
+        .record(&mut metadata);
+    let deleted = run_phase(
+        progress,
+        Phase::Process,
+        data::delete_removed_paths(
+            client,
+            scan_id,
+            options.staging.table(),
+            &restated.removed_paths,
+            &restated.removed_dirs,
+        ),
+    )
+    .await?;
+    tracing::info!("🗑️ {} tracked files are gone", deleted);
+    let duration = apply_staged(client, options, scan_id, true, progress).await?;
+    metadata.insert(
+        "sql_execution_time_s".to_string(),
+        duration.as_secs_f64().to_string(),
+    );
+    remove_tsv_file(output_tsv_file);
+
+    let files = data::record_extension_stats_from_files(client, scan_id).await?;
+    metadata.insert("total_files_processed".to_string(), files.to_string());
+    finalize(client, options, scan_id, metadata, progress).await
+}
+
+/// Refuse to scan a root overlapping one of another tenant: files seen by
+/// both would move to `tenant` and out of sight of the other. An untenanted
+/// root may be claimed by a tenant.
+pub(crate) async fn check_root_tenant(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+    tenant: Option<&str>,
+) -> anyhow::Result<()> {
+    for (scan_root, owner) in data::get_overlapping_root_tenants(client, data_root).await? {
+        if tenant != Some(owner.as_str()) {
+            anyhow::bail!(
+                "{} overlaps {}, which belongs to tenant '{}'; scan it with --tenant {}",
+                data_root.display(),
+                scan_root,
+                owner,
+                owner
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Refuse to scan a root overlapping one recorded with another path
+/// encryption key, or encrypted when this scan is not (or the reverse): its
+/// files would be recorded twice, once under each form of their paths.
+pub(crate) async fn check_root_path_key(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+    path_key_id: Option<&str>,
+) -> anyhow::Result<()> {
+    for (scan_root, key_id) in data::get_overlapping_root_path_keys(client, data_root).await? {
+        if key_id.as_deref() != path_key_id {
+            anyhow::bail!(
+                "{} overlaps {}, whose paths are {}; scan it with the same path encryption key",
+                data_root.display(),
+                scan_root,
+                match key_id {
+                    Some(key_id) => format!("encrypted with key {}", key_id),
+                    None => "not encrypted".to_string(),
+                }
+            );
+        }
+    }
+    Ok(())
+}
+
+/// One crawl -> load -> process unit of a batched scan
+#[derive(Debug)]
+pub(crate) struct ScanBatch {
+    pub(crate) path: std::path::PathBuf,
+    /// `Some(1)` for the root's own files, `None` for a top-level subtree
+    pub(crate) max_depth: Option<usize>,
+}
+
+/// The root's own files, then each top-level directory (symlinks are not
+/// followed, as in a regular crawl)
+pub(crate) fn top_level_batches(data_root: &std::path::Path) -> anyhow::Result<Vec<ScanBatch>> {
+    let mut dirs = Vec::new();
+    for entry in std::fs::read_dir(data_root)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+
+    let mut batches = vec![ScanBatch {
+        path: data_root.to_path_buf(),
+        max_depth: Some(1),
+    }];
+    batches.extend(dirs.into_iter().map(|path| ScanBatch {
+        path,
+        max_depth: None,
+    }));
+    Ok(batches)
+}
+
+/// Scan a huge root as a series of batches, so the staging table and TSV
+/// file only ever hold one top-level directory. Deletions are deferred to a
+/// final sweep of files no batch has seen; the sanity guards run before it,
+/// against the total file count.
+async fn run_batches(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    progress: &ProgressReporter,
+) -> anyhow::Result<()> {
+    let batches = top_level_batches(&options.data_root)?;
+    tracing::info!(
+        "🧩 Scanning {} in {} batches",
+        options.data_root.display(),
+        batches.len()
+    );
+
+    options.staging.prepare(client).await?;
+    let mut crawl = CrawlTotals::default();
+    let mut load = data::LoadStats::default();
+    let mut sql_execution_time = std::time::Duration::ZERO;
+    for (index, batch) in batches.iter().enumerate() {
+        let output_tsv_file =
+            std::env::temp_dir().join(format!("scan_{}_batch_{}.tsv", scan_id, index));
+        tracing::info!(
+            "🧩 Batch {}/{}: {}{}",
+            index + 1,
+            batches.len(),
+            batch.path.display(),
+            if batch.max_depth.is_some() {
+                " (own files)"
+            } else {
+                ""
+            }
+        );
+        progress.emit(ProgressEvent::BatchStarted {
+            index,
+            total: batches.len(),
+            path: batch.path.clone(),
+            output_tsv_file: output_tsv_file.clone(),
+        });
+
+        let (batch_metadata, batch_load, batch_sql_execution_time) =
+            apply_batch(client, options, scan_id, batch, &output_tsv_file, progress).await?;
+        crawl.add(&batch_metadata, batch.max_depth.is_none());
+        load += batch_load;
+        sql_execution_time += batch_sql_execution_time;
+    }
+
+    let mut metadata = crawl.into_metadata(&options.data_root);
+    metadata.insert("hostname".to_string(), data::local_hostname());
+    metadata.insert("batch_count".to_string(), batches.len().to_string());
+    load.record(&mut metadata);
+    metadata.insert(
+        "sql_execution_time_s".to_string(),
+        sql_execution_time.as_secs_f64().to_string(),
+    );
+    check_min_expected_files(client, options, scan_id, None, &metadata).await?;
+    data::set_scan_metadata(client, scan_id, &metadata).await?;
+
+    run_phase(progress, Phase::Process, async {
+        tracing::info!("🧹 Sweeping files no batch has seen...");
+        let mut params = std::collections::HashMap::new();
+        params.insert("scan_id".to_string(), scan_id.to_string());
+        db::execute_sql_template_str(client, sql_template("sweep_deleted.sql"), Some(params))
+            .await?;
+        Ok(())
+    })
+    .await?;
+
+    finalize(client, options, scan_id, metadata, progress).await
+}
+
+/// Crawl one batch of a batched or sharded scan into `output_tsv_file`,
+/// then load and apply it with deletions deferred. Returns the batch's crawl
+/// metadata, load stats and processing time.
+pub(crate) async fn apply_batch(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    batch: &ScanBatch,
+    output_tsv_file: &std::path::Path,
+    progress: &ProgressReporter,
+) -> anyhow::Result<(
+    std::collections::HashMap<String, String>,
+    data::LoadStats,
+    std::time::Duration,
+)> {
+    let crawl = async {
+        let crawl_options = crawler::CrawlOptions {
+            max_depth: batch.max_depth,
+            scan_root: Some(options.data_root.clone()),
+            ..options.crawl.clone()
+        };
+        let pause = pause::PauseSwitch::default();
+        let report = pause::follow_scan(
+            client,
+            scan_id,
+            options.allowed_hours,
+            &pause,
+            progress,
+            crawler::walk_directory(
+                vec![batch.path.clone()],
+                options.progress_interval,
+                scan_id,
+                output_tsv_file.to_path_buf(),
+                progress.clone(),
+                &crawl_options,
+                pause.clone(),
+            ),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("Directory walk of {} failed: {}", batch.path.display(), e))?;
+        report_hot_dirs(client, scan_id, &report.hot_dirs).await?;
+        report_scan_errors(client, scan_id, &report.errors).await?;
+        Ok(report)
+    };
+    let report = run_phase(
+        progress,
+        Phase::Crawl,
+        with_phase_timeout(
+            client,
+            scan_id,
+            Phase::Crawl,
+            options.crawl_timeout_minutes,
+            crawl,
+        ),
+    )
+    .await?;
+
+    let load = load_crawl(client, options, scan_id, output_tsv_file, progress).await?;
+    let sql_execution_time = apply_staged(client, options, scan_id, true, progress).await?;
+    remove_tsv_file(output_tsv_file);
+    Ok((report.metadata, load, sql_execution_time))
+}
+
+/// Crawl metadata summed over the batches of a batched scan
+#[derive(Debug, Default)]
+pub(crate) struct CrawlTotals {
+    files: f64,
+    seconds: f64,
+    directories: u64,
+    /// entries below the walked directories, for the mean fan-out
+    entries: f64,
+    symlinks: u64,
+    max_depth: usize,
+    hot_dirs: u64,
+    truncated_dirs: u64,
+    /// errors beyond those each batch recorded
+    unrecorded_errors: u64,
+}
+
+impl CrawlTotals {
+    pub(crate) fn add(&mut self, batch: &std::collections::HashMap<String, String>, subtree: bool) {
+        let get = |key: &str| -> f64 { batch.get(key).and_then(|v| v.parse().ok()).unwrap_or(0.0) };
+        self.files += get("total_files_processed");
+        self.seconds += get("crawl_timer_duration_s");
+        self.entries += get("mean_fan_out") * get("directory_count");
+        self.symlinks += get("symlink_count") as u64;
+        self.hot_dirs += get("hot_dirs_count") as u64;
+        self.truncated_dirs += get("truncated_dirs_count") as u64;
+        self.unrecorded_errors += get("scan_errors_unrecorded") as u64;
+        if subtree {
+            // the subtree's root was already counted by the root's own batch,
+            // and its depths are relative to it
+            self.directories += (get("directory_count") as u64).saturating_sub(1);
+            self.max_depth = self.max_depth.max(get("max_depth") as usize + 1);
+        } else {
+            self.directories += get("directory_count") as u64;
+            self.max_depth = self.max_depth.max(get("max_depth") as usize);
+        }
+    }
+
+    pub(crate) fn into_metadata(
+        self,
+        data_root: &std::path::Path,
+    ) -> std::collections::HashMap<String, String> {
+        let mut metadata = std::collections::HashMap::new();
+        metadata.insert(
+            "data_root".to_string(),
+            data_root.to_string_lossy().to_string(),
+        );
+        metadata.insert("total_files_processed".to_string(), self.files.to_string());
+        metadata.insert(
+            "crawl_timer_duration_s".to_string(),
+            self.seconds.to_string(),
+        );
+        metadata.insert(
+            "crawler_files_per_second".to_string(),
+            (self.files / self.seconds.max(f64::EPSILON)).to_string(),
+        );
+        metadata.insert("directory_count".to_string(), self.directories.to_string());
+        metadata.insert("symlink_count".to_string(), self.symlinks.to_string());
+        metadata.insert("max_depth".to_string(), self.max_depth.to_string());
+        metadata.insert(
+            "mean_fan_out".to_string(),
+            if self.directories > 0 {
+                self.entries / self.directories as f64
+            } else {
+                0.0
+            }
+            .to_string(),
+        );
+        metadata.insert("hot_dirs_count".to_string(), self.hot_dirs.to_string());
+        metadata.insert(
+            "truncated_dirs_count".to_string(),
+            self.truncated_dirs.to_string(),
+        );
+        if self.unrecorded_errors > 0 {
+            metadata.insert(
+                "scan_errors_unrecorded".to_string(),
+                self.unrecorded_errors.to_string(),
+            );
+        }
+        metadata
+    }
+}
+
+/// Log the hot directories found by a crawl and record them for the scan
+pub(crate) async fn report_hot_dirs(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    hot_dirs: &[crawler::HotDir],
+) -> anyhow::Result<()> {
+    if hot_dirs.is_empty() {
+        return Ok(());
+    }
+    tracing::warn!("🔥 {} hot directories found:", hot_dirs.len());
+    for hot_dir in hot_dirs.iter().take(10) {
+        tracing::warn!(
+            "   {:>12} entries{} {}",
+            hot_dir.entry_count,
+            if hot_dir.truncated {
+                " (truncated)"
+            } else {
+                ""
+            },
+            hot_dir.path.display()
+        );
+    }
+    data::record_hot_dirs(client, scan_id, hot_dirs).await
+}
+
+/// Log the first entries a crawl failed to read and record them all for the scan
+pub(crate) async fn report_scan_errors(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    errors: &[crawler::ScanError],
+) -> anyhow::Result<()> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    for error in errors.iter().take(10) {
+        tracing::warn!(
+            "⚠️ Failed to read {} ({}): {}",
+            error.path.display(),
+            error.kind,
+            error.message
+        );
+    }
+    data::record_scan_errors(client, scan_id, errors).await
+}
+
+/// Deal with a scan left behind by a run on this host that crashed or failed,
+/// as recorded in its local journal.
+///
+/// A scan whose deltas were already applied is finalized. With `resume`, a
+/// scan whose crawl completed is processed from its TSV file instead of being
+/// discarded. Anything else still running is voided and its staged rows and
+/// TSV file removed. Returns the scan_id if the previous scan was completed.
+#[tracing::instrument(skip(client, options, entry, progress))]
+pub async fn recover_scan(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    entry: &crate::journal::JournalEntry,
+    resume: bool,
+    progress: &ProgressReporter,
+) -> anyhow::Result<Option<i32>> {
+    tracing::warn!(
+        "🩹 Previous run (pid {}, started {}) did not finish: scan {:?}, phase {}",
+        entry.pid,
+        entry.started_at,
+        entry.scan_id,
+        entry
+            .phase
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| "none".to_string())
+    );
+    let Some(scan_id) = entry.scan_id else {
+        // failed before the scan_runs row existed, nothing to clean up
+        return Ok(None);
+    };
+
+    let status = data::get_scan_status(client, scan_id).await?;
+    if status != "running" {
+        tracing::info!("🩹 Scan {} already ended as {}", scan_id, status);
+        return Ok(None);
+    }
+
+    // batches and snapshot diffs stage only part of the root
+    let partial = entry.batched
+        || data::get_scan_metadata(client, scan_id)
+            .await?
+            .contains_key("snapshot_diff");
+    if partial && entry.phase != Some(Phase::Finalize) {
+        // Some batches may be applied, but deletions only happen once all
+        // are; finishing it would take a full rescan anyway
+        data::clear_staging(client, scan_id).await?;
+        remove_leftover_tsv(entry);
+        if data::has_file_changes(client, scan_id).await? {
+            tracing::warn!(
+                "🩹 Scan {} was interrupted with part of its deltas applied; flagging it for rollback",
+                scan_id
+            );
+            let anomaly = serde_json::json!({
+                "interrupted_batches": { "started_at": entry.started_at }
+            });
+            let metadata = data::get_scan_metadata(client, scan_id).await?;
+            data::flag_scan(client, scan_id, anomaly, metadata).await?;
+        } else {
+            tracing::info!("🩹 Voiding interrupted partial scan {}", scan_id);
+            data::void_scan(client, scan_id).await?;
+        }
+        return Ok(None);
+    }
+
+    if entry.phase == Some(Phase::Finalize) || data::has_file_changes(client, scan_id).await? {
+        tracing::info!(
+            "🩹 Deltas of scan {} were already applied, finalizing it",
+            scan_id
+        );
+        let metadata = data::get_scan_metadata(client, scan_id).await?;
+        data::clear_staging(client, scan_id).await?;
+        finalize(client, options, scan_id, metadata, progress).await?;
+        remove_leftover_tsv(entry);
+        return Ok(Some(scan_id));
+    }
+
+    if resume && let Some(phase) = data::get_scan_phase(client, scan_id).await? {
+        tracing::info!("🩹 Resuming scan {} after its {} phase", scan_id, phase);
+        resume_scan(client, options, scan_id, progress).await?;
+        return Ok(Some(scan_id));
+    }
+
+    if let Some(output_tsv_file) = &entry.output_tsv_file
+        && resume
+        && entry.crawl_completed
+        && output_tsv_file.exists()
+    {
+        tracing::info!(
+            "🩹 Resuming scan {} from {}",
+            scan_id,
+            output_tsv_file.display()
+        );
+        // staged rows are kept for the load to resume after its last
+        // committed chunk
+        let metadata = data::get_scan_metadata(client, scan_id).await?;
+        process_crawl(
+            client,
+            options,
+            scan_id,
+            output_tsv_file,
+            metadata,
+            progress,
+        )
+        .await?;
+        remove_tsv_file(output_tsv_file);
+        return Ok(Some(scan_id));
+    }
+
+    tracing::info!("🩹 Voiding interrupted scan {}", scan_id);
+    data::clear_staging(client, scan_id).await?;
+    data::void_scan(client, scan_id).await?;
+    remove_leftover_tsv(entry);
+    Ok(None)
+}
+
+/// End the journaled scan of a run that overran its deadline, or one of its
+/// phases. Like an interrupted batched scan, one with deltas already applied
+/// is flagged for rollback; otherwise its staged rows and crawl output are
+/// dropped and it ends as `aborted`, its `scan_phase` telling how far it got.
+#[tracing::instrument(skip(client, entry))]
+pub async fn abort_scan(
+    client: &tokio_postgres::Client,
+    entry: &crate::journal::JournalEntry,
+    timed_out: &outcome::TimedOut,
+) -> anyhow::Result<()> {
+    let Some(scan_id) = entry.scan_id else {
+        return Ok(());
+    };
+    let status = data::get_scan_status(client, scan_id).await?;
+    if status != "running" {
+        tracing::info!("⏰ Scan {} already ended as {}", scan_id, status);
+        return Ok(());
+    }
+
+    let reason = serde_json::json!({
+        "timed_out": {
+            "timeout": timed_out.phase.map_or("run".to_string(), |p| p.to_string()),
+            "timeout_minutes": timed_out.timeout_minutes,
+            "phase": entry.phase,
+            "completed_phase": data::get_scan_phase(client, scan_id).await?.map(|p| p.to_string()),
+        }
+    });
+    data::clear_staging(client, scan_id).await?;
+    remove_leftover_tsv(entry);
+    if data::has_file_changes(client, scan_id).await? {
+        tracing::warn!(
+            "⏰ Scan {} timed out with deltas applied; flagging it for rollback",
+            scan_id
+        );
+        let metadata = data::get_scan_metadata(client, scan_id).await?;
+        data::flag_scan(client, scan_id, reason, metadata).await?;
+    } else {
+        tracing::warn!("⏰ Scan {} timed out, marking it aborted", scan_id);
+        data::clear_pending_changes(client, scan_id).await?;
+        data::abort_scan(client, scan_id, reason).await?;
+    }
+    Ok(())
+}
+
+/// Promote the pending deltas of a scan staged for review (`--review`), as
+/// left in `pending_file_changes` after review, and clear them; returns how
+/// long that took. The scan is finalized by the caller.
+#[tracing::instrument(skip(client))]
+pub async fn apply_reviewed_scan(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+) -> anyhow::Result<std::time::Duration> {
+    let mut params = std::collections::HashMap::new();
+    params.insert("scan_id".to_string(), scan_id.to_string());
+    // Reviewed scans are always staged in the shared table
+    params.insert(
+        "staging_table".to_string(),
+        StagingStrategy::Logged.table().to_string(),
+    );
+    let start_time = std::time::Instant::now();
+    db::execute_sql_template_str(client, sql_template("apply_pending.sql"), Some(params)).await?;
+    Ok(start_time.elapsed())
+}
+
+/// Continue an interrupted scan after the last phase it completed: load its
+/// preserved crawl output, process its staged rows or finalize it. A scan
+/// interrupted while crawling (or whose crawl output is gone) is crawled
+/// again, since the walk keeps no state to continue from.
+#[tracing::instrument(skip(client, options, progress))]
+pub async fn resume_scan(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    progress: &ProgressReporter,
+) -> anyhow::Result<()> {
+    crate::resource_usage::watch_open_fds();
+    let status = data::get_scan_status(client, scan_id).await?;
+    anyhow::ensure!(
+        status == "running",
+        "Scan {} already ended as {}",
+        scan_id,
+        status
+    );
+    let mut phase = data::get_scan_phase(client, scan_id).await?;
+    if phase < Some(data::ScanPhase::Processed) && data::has_file_changes(client, scan_id).await? {
+        anyhow::bail!(
+            "Scan {} has part of its deltas applied (an interrupted batched or snapshot diff scan) and cannot be resumed; roll it back with `rollback_scan`",
+            scan_id
+        );
+    }
+
+    let output_tsv_file = output_tsv_path(scan_id);
+    if phase == Some(data::ScanPhase::Crawled) && !output_tsv_file.exists() {
+        tracing::warn!(
+            "⚠️ Crawl output {} of scan {} is gone, crawling again",
+            output_tsv_file.display(),
+            scan_id
+        );
+        phase = None;
+    }
+    tracing::info!(
+        "⏯️ Resuming scan {} of {} after phase {}",
+        scan_id,
+        options.data_root.display(),
+        phase
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| "none".to_string())
+    );
+    progress.emit(ProgressEvent::ScanStarted {
+        scan_id,
+        output_tsv_file: output_tsv_file.clone(),
+    });
+
+    let metadata = data::get_scan_metadata(client, scan_id).await?;
+    match phase {
+        None => {
+            data::clear_staging(client, scan_id).await?;
+            crawl_scan(client, options, scan_id, &output_tsv_file, None, progress).await?;
+        }
+        Some(data::ScanPhase::Crawled) => {
+            // staged rows are kept for the load to resume after its last
+            // committed chunk
+            process_crawl(
+                client,
+                options,
+                scan_id,
+                &output_tsv_file,
+                metadata,
+                progress,
+            )
+            .await?;
+        }
+        Some(data::ScanPhase::Loaded) => {
+            // only recorded for loads into the shared staging table
+            let mut options = options.clone();
+            if options.staging == StagingStrategy::Temporary {
+                options.staging = StagingStrategy::default();
+            }
+            process_loaded(client, &options, scan_id, metadata, progress).await?;
+        }
+        Some(data::ScanPhase::Processed) => {
+            data::clear_staging(client, scan_id).await?;
+            finalize(client, options, scan_id, metadata, progress).await?;
+        }
+        Some(data::ScanPhase::Finalized) => {
+            anyhow::bail!("Scan {} is already finalized", scan_id)
+        }
+    }
+    if output_tsv_file.exists() {
+        remove_tsv_file(&output_tsv_file);
+    }
+    Ok(())
+}
+
+fn remove_leftover_tsv(entry: &crate::journal::JournalEntry) {
+    if let Some(output_tsv_file) = &entry.output_tsv_file
+        && output_tsv_file.exists()
+    {
+        remove_tsv_file(output_tsv_file);
+    }
+}
+
+/// Load a crawl TSV written for `scan_id` and compute its deltas: stage them
+/// for review, or apply and finalize the scan
+pub(crate) async fn process_crawl(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    output_tsv_file: &std::path::Path,
+    mut metadata: std::collections::HashMap<String, String>,
+    progress: &ProgressReporter,
+) -> anyhow::Result<()> {
+    options.validate()?;
+    options.staging.prepare(client).await?;
+    load_crawl(client, options, scan_id, output_tsv_file, progress)
+        .await?
+        .record(&mut metadata);
+    let dirs_tsv_file = crate::dir_stats::dirs_tsv_path(output_tsv_file);
+    if options.crawl.record_dirs && dirs_tsv_file.exists() {
+        let dirs = data::load_dirs_tsv_file(client, &dirs_tsv_file).await?;
+        tracing::info!("📁 Loaded {} directories -> staging", dirs);
+    }
+    // rows in temporary staging are gone with the connection
+    if options.staging != StagingStrategy::Temporary {
+        data::set_scan_phase(client, scan_id, data::ScanPhase::Loaded, &metadata).await?;
+    }
+    process_loaded(client, options, scan_id, metadata, progress).await
+}
+
+/// Compute the deltas of a scan's staged rows: stage them for review, or
+/// apply and finalize the scan
+async fn process_loaded(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    mut metadata: std::collections::HashMap<String, String>,
+    progress: &ProgressReporter,
+) -> anyhow::Result<()> {
+    // against the fingerprints tracked before this scan replaces them
+    if metadata.contains_key("sample_files") {
+        let (compared, changed) =
+            data::count_fingerprint_mismatches(client, scan_id, options.staging.table()).await?;
+        crate::sample::record_bitrot_estimate(compared, changed, &mut metadata);
+    }
+    if options.review {
+        let mut params = std::collections::HashMap::new();
+        params.insert("scan_id".to_string(), scan_id.to_string());
+        params.insert(
+            "staging_table".to_string(),
+            options.staging.table().to_string(),
+        );
+        return run_phase(progress, Phase::Review, async {
+            tracing::info!("🔎 Computing pending deltas for review...");
+            db::execute_sql_template_str(client, sql_template("review_staging.sql"), Some(params))
+                .await?;
+
+            let summary = data::get_pending_changes_summary(client, scan_id).await?;
+            tracing::info!("🔎 Pending deltas for scan {}:", scan_id);
+            if summary.is_empty() {
+                tracing::info!("   (no changes)");
+            }
+            for (change_type, count, size_mb) in &summary {
+                tracing::info!(
+                    "   {:<10} {:>12} files {:>14.2} MB",
+                    change_type,
+                    count,
+                    size_mb
+                );
+            }
+            report_largest_new_files(client, options, scan_id, true, &mut metadata).await?;
+            // the scanning process ends here; `apply_scan` keeps these
+            crate::resource_usage::ResourceUsage::current().record(&mut metadata);
+            let inventory = metadata.contains_key("inventory");
+            data::mark_scan_pending_review(client, scan_id, metadata).await?;
+
+            if !inventory {
+                tracing::info!(
+                    "⏸️ Scan {} awaiting review; run `apply_scan --scan-id {}` to promote its deltas",
+                    scan_id,
+                    scan_id
+                );
+            }
+            Ok(())
+        })
+        .await;
+    }
+
+    let duration = apply_staged(client, options, scan_id, false, progress).await?;
+    metadata.insert(
+        "sql_execution_time_s".to_string(),
+        duration.as_secs_f64().to_string(),
+    );
+    if options.crawl.record_dirs {
+        let mut params = std::collections::HashMap::new();
+        params.insert("scan_id".to_string(), scan_id.to_string());
+        db::execute_sql_template_str(
+            client,
+            sql_template("process_staging_dirs.sql"),
+            Some(params),
+        )
+        .await?;
+    }
+    // process_staging_v2.sql recorded the phase; kept for a scan resumed after it
+    data::set_scan_metadata(client, scan_id, &metadata).await?;
+
+    finalize(client, options, scan_id, metadata, progress).await
+}
+
+/// Load a crawl TSV file into the scan's staging table
+pub(crate) async fn load_crawl(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    output_tsv_file: &std::path::Path,
+    progress: &ProgressReporter,
+) -> anyhow::Result<data::LoadStats> {
+    let load = async {
+        let tsv_file = output_tsv_file.to_path_buf();
+        tokio::task::spawn_blocking(move || crate::integrity::verify_checksum_sidecar(&tsv_file))
+            .await??;
+        tracing::info!(
+            "📥 Loading TSV file -> staging: {}",
+            output_tsv_file.display()
+        );
+        let pacing = data::LoadPacing {
+            progress_interval: Some(std::time::Duration::from_secs(options.progress_interval)),
+            max_rows_per_second: options.load_max_rows_per_second,
+            // temporary staging is gone with the connection, nothing to resume
+            checkpoints: (options.load_checkpoint_rows > 0
+                && options.staging != StagingStrategy::Temporary)
+                .then_some(data::LoadCheckpoints {
+                    scan_id,
+                    every_rows: options.load_checkpoint_rows,
+                }),
+            copy_format: options.copy_format,
+        };
+        let load = data::load_tsv_file(
+            client,
+            output_tsv_file.to_path_buf(),
+            options.staging.table(),
+            &pacing,
+            progress,
+        );
+        let stats = if options.defer_staging_indexes {
+            let (mut stats, index_rebuild) =
+                data::with_deferred_indexes(client, options.staging.table(), load).await?;
+            stats.index_rebuild = index_rebuild;
+            stats
+        } else {
+            load.await?
+        };
+        tracing::info!(
+            "📥 TSV file loaded into staging table: {} rows in {:.2?} ({:.0} rows/s, {:.2?} waiting on the database)",
+            stats.rows,
+            stats.elapsed,
+            stats.rows_per_second(),
+            stats.blocked
+        );
+        Ok(stats)
+    };
+    run_phase(
+        progress,
+        Phase::Load,
+        with_phase_timeout(
+            client,
+            scan_id,
+            Phase::Load,
+            options.load_timeout_minutes,
+            load,
+        ),
+    )
+    .await
+}
+
+/// Apply the staged rows of a scan to filesystem.files and clear them,
+/// returning how long processing took. With `defer_deletes` (a batch of a
+/// batched scan) files missing from staging are not deleted.
+pub(crate) async fn apply_staged(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    defer_deletes: bool,
+    progress: &ProgressReporter,
+) -> anyhow::Result<std::time::Duration> {
+    let staging = options.staging;
+    let process = async {
+        let mut params = std::collections::HashMap::new();
+        params.insert("scan_id".to_string(), scan_id.to_string());
+        params.insert("defer_deletes".to_string(), defer_deletes.to_string());
+        params.insert("staging_table".to_string(), staging.table().to_string());
+
+        tracing::info!("📄 Processing staged files...");
+        let start_time = std::time::Instant::now();
+        let sql = crate::fault::inject_process_error(sql_template("process_staging_v2.sql"));
+        db::execute_sql_template_str(client, &sql, Some(params)).await?;
+        let duration = start_time.elapsed();
+        tracing::info!("📄 Processed successfully in {:?}", duration);
+
+        tracing::info!("🗑️ Clearing staging table for scan_id: {}", scan_id);
+        staging.clear(client, scan_id).await?;
+        tracing::info!("🗑️ Staging table cleared for scan_id: {}", scan_id);
+        Ok(duration)
+    };
+    run_phase(
+        progress,
+        Phase::Process,
+        with_phase_timeout(
+            client,
+            scan_id,
+            Phase::Process,
+            options.process_timeout_minutes,
+            process,
+        ),
+    )
+    .await
+}
+
+/// Report on the applied deltas of a scan and mark it completed
+pub(crate) async fn finalize(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    mut metadata: std::collections::HashMap<String, String>,
+    progress: &ProgressReporter,
+) -> anyhow::Result<()> {
+    run_phase(progress, Phase::Finalize, async {
+        report_largest_new_files(client, options, scan_id, false, &mut metadata).await?;
+        report_owner_growth(client, options, scan_id, &mut metadata).await?;
+        if options.merkle_root {
+            let root = integrity::record_merkle_root(client, scan_id).await?;
+            metadata.insert("merkle_root".to_string(), root);
+        }
+        let usage = crate::resource_usage::ResourceUsage::current();
+        tracing::info!(
+            "🧮 Resources used: {:.1?} user and {:.1?} system CPU, {:.1} MB peak RSS, {} open files at most",
+            usage.cpu_user.unwrap_or_default(),
+            usage.cpu_system.unwrap_or_default(),
+            usage.peak_rss_bytes.unwrap_or_default() as f64 / 1e6,
+            usage
+                .open_fds_peak
+                .map_or_else(|| "?".to_string(), |fds| fds.to_string())
+        );
+        usage.record(&mut metadata);
+        tracing::info!("📊 Updating scan results in database...");
+        data::finalize_scan(client, scan_id, metadata).await?;
+        Ok(())
+    })
+    .await?;
+
+    tracing::info!("✅ Scan completed successfully!");
+
+    Ok(())
+}
+
+/// Sanity guard: a crawl that finds far fewer files than expected (e.g. a
+/// half-mounted volume) would otherwise record a wave of false deletions.
+/// Flags the scan and fails if the guard trips.
+pub(crate) async fn check_min_expected_files(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    output_tsv_file: Option<&std::path::Path>,
+    metadata: &std::collections::HashMap<String, String>,
+) -> anyhow::Result<()> {
+    let mut expected_min = options.min_expected_files.unwrap_or(0);
+    if let Some(ratio) = options.min_files_ratio
+        && let Some(previous) =
+            data::get_previous_files_count(client, &options.data_root, scan_id).await?
+    {
+        expected_min = expected_min.max((previous as f64 * ratio).ceil() as u64);
+    }
+    let total_files = metadata
+        .get("total_files_processed")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+    if total_files >= expected_min {
+        return Ok(());
+    }
+
+    tracing::error!(
+        "🚩 Crawl found {} files, expected at least {}; skipping delta processing",
+        total_files,
+        expected_min
+    );
+    let anomaly = serde_json::json!({
+        "low_file_count": {
+            "expected_min": expected_min,
+            "actual": total_files,
+        }
+    });
+    data::flag_scan(client, scan_id, anomaly, metadata.clone()).await?;
+    if let Some(output_tsv_file) = output_tsv_file {
+        tracing::info!(
+            "📝 TSV file kept for inspection: {}",
+            output_tsv_file.display()
+        );
+    }
+    anyhow::bail!(
+        "Scan {} flagged: {} files found, expected at least {}",
+        scan_id,
+        total_files,
+        expected_min
+    );
+}
+
+/// Call out the largest files added by the scan (or pending its review), so a
+/// rogue core dump is named explicitly instead of buried in the aggregate volume
+async fn report_largest_new_files(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    pending: bool,
+    metadata: &mut std::collections::HashMap<String, String>,
+) -> anyhow::Result<()> {
+    if options.largest_new_files == 0 {
+        return Ok(());
+    }
+    let largest =
+        data::get_largest_added_files(client, scan_id, options.largest_new_files as i64, pending)
+            .await?;
+    if largest.is_empty() {
+        return Ok(());
+    }
+
+    let mut alerts = 0;
+    tracing::info!("🐘 Largest new files:");
+    // the scan metadata keeps the paths as recorded
+    for (path, size) in &largest {
+        let path = match &options.crawl.path_cipher {
+            Some(cipher) => cipher.decrypt_path(path),
+            None => path.clone(),
+        };
+        let size_mb = *size as f64 / 1024.0 / 1024.0;
+        if options.large_file_alert_mb.is_some_and(|t| size_mb >= t) {
+            alerts += 1;
+            tracing::warn!("🚨 {:>14.2} MB {}", size_mb, path);
+        } else {
+            tracing::info!("   {:>14.2} MB {}", size_mb, path);
+        }
+    }
+
+    let largest_json: Vec<serde_json::Value> = largest
+        .iter()
+        .map(|(path, size)| serde_json::json!({ "path": path, "size_bytes": size }))
+        .collect();
+    metadata.insert(
+        "largest_new_files".to_string(),
+        serde_json::Value::Array(largest_json).to_string(),
+    );
+    metadata.insert("large_new_files_alerts".to_string(), alerts.to_string());
+    Ok(())
+}
+
+/// Attribute the scan's growth to owners / top-level directories, for chargeback
+async fn report_owner_growth(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    metadata: &mut std::collections::HashMap<String, String>,
+) -> anyhow::Result<()> {
+    let growth = data::get_owner_growth(client, scan_id).await?;
+    if growth.is_empty() {
+        return Ok(());
+    }
+
+    tracing::info!("👥 Growth by owner:");
+    for g in &growth {
+        // top-level directories standing in for owners may be encrypted
+        let owner = match &options.crawl.path_cipher {
+            Some(cipher) => cipher.decrypt_path(&g.owner),
+            None => g.owner.clone(),
+        };
+        tracing::info!(
+            "   {:<24} {:>+14.2} MB (+{} ~{} -{} files)",
+            owner,
+            g.net_change_bytes as f64 / 1024.0 / 1024.0,
+            g.added_files_count,
+            g.modified_files_count,
+            g.removed_files_count
+        );
+    }
+
+    let growth_json: serde_json::Map<String, serde_json::Value> = growth
+        .iter()
+        .map(|g| (g.owner.clone(), serde_json::json!(g.net_change_bytes)))
+        .collect();
+    metadata.insert(
+        "owner_net_change_bytes".to_string(),
+        serde_json::Value::Object(growth_json).to_string(),
+    );
+    Ok(())
+}
+
+/// Remove the temporary TSV file, logging (but not failing on) errors
+pub(crate) fn remove_tsv_file(output_tsv_file: &std::path::Path) {
+    tracing::info!("🗑️ Clearing TSV File: {}", output_tsv_file.display());
+    if let Err(e) = std::fs::remove_file(output_tsv_file) {
+        tracing::warn!("⚠️ Failed to remove temporary TSV file: {}", e);
+    } else {
+        tracing::info!("🗑️ Temporary TSV file removed successfully");
+    }
+    let sidecar = crate::integrity::checksum_sidecar(output_tsv_file);
+    if let Err(e) = std::fs::remove_file(&sidecar)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        tracing::warn!("⚠️ Failed to remove checksum sidecar: {}", e);
+    }
+    let dirs_tsv_file = crate::dir_stats::dirs_tsv_path(output_tsv_file);
+    if let Err(e) = std::fs::remove_file(&dirs_tsv_file)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        tracing::warn!("⚠️ Failed to remove directories TSV file: {}", e);
+    }
+}
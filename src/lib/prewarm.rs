@@ -0,0 +1,41 @@
+/// Threads of the warm-up pass: each one mostly waits on the disk, and the
+/// more reads are queued the better the elevator orders the seeks
+const THREADS: usize = 64;
+
+/// Read every directory below `root` with many threads at once, without
+/// stat'ing its entries, so that the directory blocks and dentries the walk
+/// needs are cached when it starts; the directories `overrides` exclude are
+/// left alone, like the walk does. On spinning disks this turns the walk's
+/// one-seek-at-a-time reads into queued ones the disk can reorder. Idles
+/// while `pause` is set. Returns the number of directories read.
+pub fn warm_directories(
+    root: &std::path::Path,
+    max_depth: Option<usize>,
+    follow_mounts: bool,
+    overrides: &ignore::overrides::Override,
+    pause: &crate::pause::PauseSwitch,
+) -> u64 {
+    let directories = std::sync::atomic::AtomicU64::new(0);
+    let mut builder = ignore::WalkBuilder::new(root);
+    builder
+        .ignore(false)
+        .hidden(false)
+        .git_ignore(false)
+        .max_depth(max_depth)
+        .threads(crate::fd_limit::cap_threads(THREADS, "warm-up"))
+        .same_file_system(!follow_mounts)
+        .overrides(overrides.clone());
+    builder.build_parallel().run(|| {
+        let directories = &directories;
+        Box::new(move |res| {
+            pause.wait();
+            if let Ok(ent) = res
+                && ent.file_type().is_some_and(|ft| ft.is_dir())
+            {
+                directories.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }
+            ignore::WalkState::Continue
+        })
+    });
+    directories.into_inner()
+}
@@ -0,0 +1,25 @@
+/// Run `command` to completion, failing with its stderr if it exits unsuccessfully
+pub(crate) async fn run_command(command: tokio::process::Command) -> anyhow::Result<()> {
+    command_output(command).await.map(|_| ())
+}
+
+/// Run `command` to completion and return its stdout, failing with its stderr
+/// if it exits unsuccessfully
+pub(crate) async fn command_output(
+    mut command: tokio::process::Command,
+) -> anyhow::Result<Vec<u8>> {
+    let program = command.as_std().get_program().to_string_lossy().to_string();
+    let output = command
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
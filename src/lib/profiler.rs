@@ -0,0 +1,108 @@
+use crate::progress::{ProgressEvent, ProgressReporter};
+
+/// Samples per second and thread; off the round 100 so the sampling does not
+/// run in lockstep with periodic work
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const FREQUENCY: i32 = 99;
+/// Phase of the samples taken before the first and between pipeline phases
+const OUTSIDE_PHASES: &str = "other";
+
+/// Phases by the time they started, in order
+type PhaseLog = std::sync::Arc<std::sync::Mutex<Vec<(std::time::SystemTime, String)>>>;
+
+/// CPU profile of a run, sampling every thread and attributing each sample
+/// to the pipeline phase running when it was taken
+pub struct ScanProfiler {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    guard: pprof::ProfilerGuard<'static>,
+    phases: PhaseLog,
+}
+
+impl ScanProfiler {
+    /// Start sampling
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn start() -> anyhow::Result<Self> {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(FREQUENCY)
+            // unwinding through these from the signal handler can deadlock
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .map_err(|e| anyhow::anyhow!("Failed to start the profiler: {}", e))?;
+        Ok(Self {
+            guard,
+            phases: std::sync::Arc::new(std::sync::Mutex::new(vec![(
+                std::time::SystemTime::now(),
+                OUTSIDE_PHASES.to_string(),
+            )])),
+        })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn start() -> anyhow::Result<Self> {
+        anyhow::bail!("Profiling is only supported on Linux and macOS")
+    }
+
+    /// Wrap `inner` so the phases reported through it delimit the profile
+    pub fn reporter(&self, inner: ProgressReporter) -> ProgressReporter {
+        let phases = self.phases.clone();
+        ProgressReporter::from_callback(move |event| {
+            let phase = match event {
+                ProgressEvent::PhaseStarted { phase } => Some(phase.to_string()),
+                ProgressEvent::PhaseCompleted { .. } | ProgressEvent::Error { .. } => {
+                    Some(OUTSIDE_PHASES.to_string())
+                }
+                _ => None,
+            };
+            if let Some(phase) = phase {
+                phases
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push((std::time::SystemTime::now(), phase));
+            }
+            inner.emit(event.clone());
+        })
+    }
+
+    /// Stop sampling and write the profile to `path` as a flame graph SVG,
+    /// with a root frame per phase and a frame per thread below it
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn write_flamegraph(self, path: &std::path::Path) -> anyhow::Result<()> {
+        let phases = self.phases.clone();
+        let report = self
+            .guard
+            .report()
+            .frames_post_processor(move |frames| {
+                let phases = phases.lock().unwrap_or_else(|e| e.into_inner());
+                let phase = phases
+                    .iter()
+                    .rev()
+                    .find(|(started, _)| *started <= frames.sample_timestamp)
+                    .map_or(OUTSIDE_PHASES, |(_, phase)| phase.as_str());
+                // `;` separates frames in the collapsed stacks
+                frames.thread_name = format!("{};{}", phase, frames.thread_name_or_id());
+            })
+            .build()
+            .map_err(|e| anyhow::anyhow!("Failed to build the profile: {}", e))?;
+
+        let mut samples = std::collections::BTreeMap::<&str, isize>::new();
+        for (frames, count) in &report.data {
+            let phase = frames.thread_name.split(';').next().unwrap_or_default();
+            *samples.entry(phase).or_default() += count;
+        }
+        for (phase, count) in samples {
+            tracing::info!("🔬 {}: {} CPU samples", phase, count);
+        }
+
+        let file = std::fs::File::create(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))?;
+        report
+            .flamegraph(std::io::BufWriter::new(file))
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn write_flamegraph(self, _path: &std::path::Path) -> anyhow::Result<()> {
+        unreachable!("profilers cannot be started here")
+    }
+}
@@ -0,0 +1,117 @@
+/// Pipeline phases reported through [`ProgressEvent`]s
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Crawl,
+    /// Restating the entries of a snapshot diff, instead of a crawl
+    Diff,
+    Load,
+    Process,
+    Review,
+    Finalize,
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Phase::Crawl => "crawl",
+            Phase::Diff => "diff",
+            Phase::Load => "load",
+            Phase::Process => "process",
+            Phase::Review => "review",
+            Phase::Finalize => "finalize",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Structured progress of a scan, for GUIs and orchestrators that should not
+/// have to parse the tracing output
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    /// The scan_runs row was created; the crawl is written to `output_tsv_file`
+    ScanStarted {
+        scan_id: i32,
+        output_tsv_file: std::path::PathBuf,
+    },
+    /// A batch of a batched scan started; its crawl is written to `output_tsv_file`
+    BatchStarted {
+        index: usize,
+        total: usize,
+        path: std::path::PathBuf,
+        output_tsv_file: std::path::PathBuf,
+    },
+    /// Emitted by the crawler every progress interval
+    CrawlTick {
+        files: u64,
+        elapsed: std::time::Duration,
+        files_per_second: f64,
+        /// Directory most recently entered by a walker thread
+        current_dir: Option<std::path::PathBuf>,
+    },
+    /// The scan was paused with `pause_scan`; the crawl workers idle
+    CrawlPaused,
+    /// The paused scan was resumed
+    CrawlResumed,
+    /// Emitted while loading the crawl into staging every progress interval
+    LoadTick {
+        rows: u64,
+        elapsed: std::time::Duration,
+        rows_per_second: f64,
+        /// Time spent waiting for the database to accept data so far
+        blocked: std::time::Duration,
+    },
+    PhaseStarted {
+        phase: Phase,
+    },
+    PhaseCompleted {
+        phase: Phase,
+        duration: std::time::Duration,
+    },
+    Error {
+        phase: Phase,
+        message: String,
+    },
+}
+
+pub type ProgressCallback = std::sync::Arc<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+/// Delivers [`ProgressEvent`]s to a user-supplied callback; cheap to clone
+/// into worker threads. The default reporter discards every event.
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    callback: Option<ProgressCallback>,
+}
+
+impl std::fmt::Debug for ProgressReporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProgressReporter")
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+impl ProgressReporter {
+    /// Report every event to `callback`, which may be called from any thread
+    pub fn from_callback(callback: impl Fn(&ProgressEvent) + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Some(std::sync::Arc::new(callback)),
+        }
+    }
+
+    /// Report every event into an unbounded channel
+    pub fn channel() -> (Self, tokio::sync::mpsc::UnboundedReceiver<ProgressEvent>) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let reporter = Self::from_callback(move |event| {
+            // The receiver going away just means nobody is listening anymore
+            let _ = tx.send(event.clone());
+        });
+        (reporter, rx)
+    }
+
+    pub fn emit(&self, event: ProgressEvent) {
+        if let Some(callback) = &self.callback {
+            callback(&event);
+        }
+    }
+}
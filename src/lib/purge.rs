@@ -0,0 +1,283 @@
+use sha2::Digest;
+
+use crate::{data, integrity};
+
+/// Turn a glob over paths into a LIKE pattern: `*` matches any characters,
+/// `/` included, and `?` a single one. A relative pattern matches below any
+/// directory, e.g. `user123/*` every path with a `user123` directory.
+///
+/// With a `cipher`, the names in the pattern are encrypted as the crawler
+/// records them; the pattern must then be relative, since the scan root is
+/// stored as is, and wildcards can only stand for whole names.
+pub fn glob_to_like(
+    glob: &str,
+    cipher: Option<&crate::path_cipher::PathCipher>,
+) -> anyhow::Result<String> {
+    let relative = !glob.starts_with('/');
+    let mut components = Vec::new();
+    for component in glob.split('/') {
+        let wildcard = component.contains(['*', '?']);
+        match cipher {
+            Some(cipher) if !component.is_empty() && !wildcard => {
+                components.push(cipher.encrypt_component(component))
+            }
+            Some(_) if wildcard && component != "*" => anyhow::bail!(
+                "Encrypted names can only be matched whole, not by '{}'",
+                component
+            ),
+            _ => components.push(component.to_string()),
+        }
+    }
+    anyhow::ensure!(
+        relative || cipher.is_none(),
+        "Patterns over encrypted paths must be relative to the scan root; restrict them with --data-root"
+    );
+
+    let mut like = if relative {
+        "%/".to_string()
+    } else {
+        String::new()
+    };
+    for c in components.join("/").chars() {
+        match c {
+            '*' => like.push('%'),
+            '?' => like.push('_'),
+            '%' | '_' | '\\' => {
+                like.push('\\');
+                like.push(c);
+            }
+            c => like.push(c),
+        }
+    }
+    Ok(like)
+}
+
+/// Rows removed by [`purge_paths`]
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct PurgeReport {
+    pub files: u64,
+    pub file_changes: u64,
+    pub pending_file_changes: u64,
+    pub staging_files: u64,
+    pub hot_dirs: u64,
+    /// entries scans failed to read
+    pub scan_errors: u64,
+    /// changes queued for subscribers, delivered or not
+    pub events: u64,
+    /// entries of the `largest_new_files` scan metadata
+    pub largest_new_files: u64,
+    /// Scans whose change set lost rows
+    pub scan_ids: Vec<i32>,
+    /// Scans whose Merkle root was recomputed, as `(scan_id, old, new)`
+    pub merkle_roots: Vec<(i32, String, String)>,
+}
+
+impl PurgeReport {
+    pub fn total(&self) -> u64 {
+        self.files
+            + self.file_changes
+            + self.pending_file_changes
+            + self.staging_files
+            + self.hot_dirs
+            + self.scan_errors
+            + self.events
+            + self.largest_new_files
+    }
+}
+
+/// Remove every trace of the paths matching the LIKE `pattern` (under
+/// `data_root`, if given) from the tracked state and the history of all
+/// scans, in one transaction, and record the purge in the audit log. The
+/// audit entry holds a SHA-256 of `description` rather than the pattern,
+/// which would itself name what was erased.
+///
+/// The Merkle roots of scans whose change set lost rows are recomputed, the
+/// old and new roots being kept in the audit entry. With `dry_run` the
+/// transaction is rolled back and only the counts are returned.
+#[tracing::instrument(skip(client))]
+pub async fn purge_paths(
+    client: &tokio_postgres::Client,
+    pattern: &str,
+    data_root: Option<&std::path::Path>,
+    description: &str,
+    dry_run: bool,
+) -> anyhow::Result<PurgeReport> {
+    client.batch_execute("BEGIN").await?;
+    let result = purge_in_transaction(client, pattern, data_root, description).await;
+    let end = match (&result, dry_run) {
+        (Ok(_), false) => client.batch_execute("COMMIT").await,
+        _ => client.batch_execute("ROLLBACK").await,
+    };
+    let report = result?;
+    end?;
+    Ok(report)
+}
+
+async fn purge_in_transaction(
+    client: &tokio_postgres::Client,
+    pattern: &str,
+    data_root: Option<&std::path::Path>,
+    description: &str,
+) -> anyhow::Result<PurgeReport> {
+    let root_pattern = data_root.map(|root| {
+        let root = root.to_string_lossy();
+        format!(
+            "{}/%",
+            root.trim_end_matches('/')
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        )
+    });
+    let params: [&(dyn tokio_postgres::types::ToSql + Sync); 2] = [&pattern, &root_pattern];
+    let matches = |column: &str| {
+        format!(
+            "{column} LIKE $1 AND ($2::text IS NULL OR {column} LIKE $2)",
+            column = column
+        )
+    };
+
+    let mut report = PurgeReport {
+        files: client
+            .execute(
+                &format!(
+                    "DELETE FROM filesystem.files WHERE {}",
+                    matches("file_path")
+                ),
+                &params,
+            )
+            .await?,
+        ..Default::default()
+    };
+
+    let rows = client
+        .query(
+            &format!(
+                "WITH purged AS (
+                    DELETE FROM filesystem.file_changes WHERE {}
+                    RETURNING scan_id
+                )
+                SELECT scan_id, COUNT(*) FROM purged GROUP BY scan_id ORDER BY scan_id",
+                matches("file_path")
+            ),
+            &params,
+        )
+        .await?;
+    for row in &rows {
+        report.scan_ids.push(row.get(0));
+        report.file_changes += row.get::<_, i64>(1) as u64;
+    }
+
+    report.pending_file_changes = client
+        .execute(
+            &format!(
+                "DELETE FROM filesystem.pending_file_changes WHERE {}",
+                matches("file_path")
+            ),
+            &params,
+        )
+        .await?;
+    report.staging_files = client
+        .execute(
+            &format!(
+                "DELETE FROM filesystem.staging_files WHERE {}",
+                matches("file_path")
+            ),
+            &params,
+        )
+        .await?;
+    // a hot directory may be one of the matched directories itself
+    report.hot_dirs = client
+        .execute(
+            &format!(
+                "DELETE FROM filesystem.hot_dirs WHERE ({}) OR ({})",
+                matches("dir_path"),
+                matches("dir_path || '/'")
+            ),
+            &params,
+        )
+        .await?;
+    report.scan_errors = client
+        .execute(
+            &format!(
+                "DELETE FROM filesystem.scan_errors WHERE ({}) OR ({})",
+                matches("entry_path"),
+                matches("entry_path || '/'")
+            ),
+            &params,
+        )
+        .await?;
+
+    report.events = client
+        .execute(
+            &format!(
+                "DELETE FROM filesystem.event_outbox WHERE {}",
+                matches("(payload->>'path')")
+            ),
+            &params,
+        )
+        .await?;
+
+    // the scan summaries name the largest added files, as a JSON string
+    let row = client
+        .query_one(
+            &format!(
+                "WITH entries AS (
+                    SELECT r.scan_id, e.entry, e.idx, {} AS purged
+                    FROM filesystem.scan_runs AS r,
+                         jsonb_array_elements((r.scan_metadata->>'largest_new_files')::jsonb)
+                             WITH ORDINALITY AS e(entry, idx)
+                    WHERE r.scan_metadata ? 'largest_new_files'
+                ),
+                kept AS (
+                    SELECT scan_id,
+                           COALESCE(jsonb_agg(entry ORDER BY idx) FILTER (WHERE NOT purged), '[]')
+                               AS entries,
+                           COUNT(*) FILTER (WHERE purged) AS purged
+                    FROM entries
+                    GROUP BY scan_id
+                ),
+                updated AS (
+                    UPDATE filesystem.scan_runs AS r
+                    SET scan_metadata = jsonb_set(
+                        r.scan_metadata, '{{largest_new_files}}', to_jsonb(kept.entries::text)
+                    )
+                    FROM kept
+                    WHERE r.scan_id = kept.scan_id AND kept.purged > 0
+                    RETURNING kept.purged
+                )
+                SELECT COALESCE(SUM(purged), 0)::bigint FROM updated",
+                matches("entry->>'path'")
+            ),
+            &params,
+        )
+        .await?;
+    report.largest_new_files = row.get::<_, i64>(0) as u64;
+
+    for (scan_id, old_root) in data::get_merkle_roots(client, None).await? {
+        if report.scan_ids.contains(&scan_id) {
+            let new_root = integrity::record_merkle_root(client, scan_id).await?;
+            report.merkle_roots.push((scan_id, old_root, new_root));
+        }
+    }
+
+    data::audit(
+        client,
+        "paths_purged",
+        None,
+        serde_json::json!({
+            "pattern_sha256": integrity::to_hex(&sha2::Sha256::digest(description.as_bytes())),
+            "data_root": data_root.map(|r| r.to_string_lossy().to_string()),
+            "files": report.files,
+            "file_changes": report.file_changes,
+            "pending_file_changes": report.pending_file_changes,
+            "staging_files": report.staging_files,
+            "hot_dirs": report.hot_dirs,
+            "largest_new_files": report.largest_new_files,
+            "scan_ids": report.scan_ids,
+            "merkle_roots": report.merkle_roots,
+        }),
+    )
+    .await?;
+    Ok(report)
+}
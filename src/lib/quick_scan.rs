@@ -0,0 +1,177 @@
+use crate::crawler::CrawlOptions;
+use crate::data;
+use crate::pipeline::{self, ScanOptions};
+
+/// Rows inserted per statement when recording directory summaries
+const INSERT_CHUNK_ROWS: usize = 10_000;
+
+/// Files directly in one directory, as recorded in `dir_summaries`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirSummary {
+    pub dir_path: String,
+    pub file_count: i64,
+    pub total_size_bytes: i64,
+}
+
+/// Walk `data_root` like a crawl, but only count the files and bytes directly
+/// in each directory instead of writing a line per file. Directories without
+/// files are left out; the result is ordered by path.
+pub async fn summarize_directories(
+    data_root: std::path::PathBuf,
+    options: &CrawlOptions,
+) -> anyhow::Result<Vec<DirSummary>> {
+    let counts = std::sync::Arc::new(dashmap::DashMap::<std::path::PathBuf, (i64, i64)>::new());
+    let counts2 = counts.clone();
+    let max_depth = options.max_depth;
+    let overrides = options.overrides(options.scan_root.as_deref().unwrap_or(&data_root))?;
+    let root = data_root.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let mut builder = ignore::WalkBuilder::new(root);
+        builder
+            .ignore(false)
+            .hidden(false)
+            .git_ignore(false)
+            .max_depth(max_depth)
+            .overrides(overrides);
+
+        builder.build_parallel().run(|| {
+            let counts = counts2.clone();
+            Box::new(move |res| {
+                if let Ok(ent) = res
+                    && ent.file_type().is_some_and(|ft| ft.is_file())
+                    && let Some(parent) = ent.path().parent()
+                    && let Ok(meta) = ent.metadata()
+                {
+                    let mut entry = counts.entry(parent.to_path_buf()).or_insert((0, 0));
+                    entry.0 += 1;
+                    entry.1 += meta.len() as i64;
+                }
+                ignore::WalkState::Continue
+            })
+        });
+    })
+    .await?;
+
+    let scan_root = options.scan_root.as_deref().unwrap_or(&data_root);
+    let mut summaries: Vec<DirSummary> = counts
+        .iter()
+        .map(|entry| DirSummary {
+            dir_path: match &options.path_cipher {
+                Some(cipher) => cipher.encrypt_path(scan_root, entry.key()),
+                None => entry.key().to_string_lossy().to_string(),
+            },
+            file_count: entry.value().0,
+            total_size_bytes: entry.value().1,
+        })
+        .collect();
+    summaries.sort_unstable_by(|a, b| a.dir_path.cmp(&b.dir_path));
+    Ok(summaries)
+}
+
+/// Record the per-directory file counts and sizes of `options.data_root` as a
+/// quick scan, returning its quick_scan_id. Quick scans leave `files`,
+/// `file_changes` and `scan_runs` alone, so they can run between full scans.
+#[tracing::instrument(skip(client, options))]
+pub async fn run_quick_scan(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+) -> anyhow::Result<i32> {
+    pipeline::check_root_tenant(client, &options.data_root, options.tenant.as_deref()).await?;
+    let started_at = chrono::Utc::now();
+    let quick_scan_id: i32 = client
+        .query_one(
+            "INSERT INTO filesystem.quick_scans (scan_root, tenant, hostname, started_at) \
+            VALUES ($1, $2, $3, $4) RETURNING quick_scan_id",
+            &[
+                &options.data_root.to_string_lossy(),
+                &options.tenant,
+                &data::local_hostname(),
+                &started_at,
+            ],
+        )
+        .await?
+        .get(0);
+    tracing::info!(
+        "⚡ Quick scan {} of {}",
+        quick_scan_id,
+        options.data_root.display()
+    );
+
+    let start = std::time::Instant::now();
+    let summaries = summarize_directories(options.data_root.clone(), &options.crawl).await?;
+    let elapsed = start.elapsed().as_secs_f64();
+    let total_files: i64 = summaries.iter().map(|s| s.file_count).sum();
+    let total_size_bytes: i64 = summaries.iter().map(|s| s.total_size_bytes).sum();
+    tracing::info!(
+        "📊 {} files, {} bytes in {} directories ({:.1}s)",
+        total_files,
+        total_size_bytes,
+        summaries.len(),
+        elapsed
+    );
+
+    record_dir_summaries(client, quick_scan_id, &summaries).await?;
+    let metadata = serde_json::json!({
+        "data_root": options.data_root.to_string_lossy(),
+        "crawl_timer_duration_s": elapsed,
+        "directory_count": summaries.len(),
+    });
+    client
+        .execute(
+            "UPDATE filesystem.quick_scans \
+            SET finished_at = now(), total_files = $2, total_size_bytes = $3, scan_metadata = $4 \
+            WHERE quick_scan_id = $1",
+            &[&quick_scan_id, &total_files, &total_size_bytes, &metadata],
+        )
+        .await?;
+    data::audit(
+        client,
+        "quick_scan_completed",
+        None,
+        serde_json::json!({
+            "quick_scan_id": quick_scan_id,
+            "scan_root": options.data_root.to_string_lossy(),
+        }),
+    )
+    .await?;
+    Ok(quick_scan_id)
+}
+
+async fn record_dir_summaries(
+    client: &tokio_postgres::Client,
+    quick_scan_id: i32,
+    summaries: &[DirSummary],
+) -> anyhow::Result<()> {
+    let stmt = client
+        .prepare(
+            "INSERT INTO filesystem.dir_summaries \
+            (quick_scan_id, dir_path, file_count, total_size_bytes) \
+            SELECT $1::int, * FROM unnest($2::text[], $3::bigint[], $4::bigint[])",
+        )
+        .await?;
+    for chunk in summaries.chunks(INSERT_CHUNK_ROWS) {
+        let paths: Vec<&str> = chunk.iter().map(|s| s.dir_path.as_str()).collect();
+        let counts: Vec<i64> = chunk.iter().map(|s| s.file_count).collect();
+        let sizes: Vec<i64> = chunk.iter().map(|s| s.total_size_bytes).collect();
+        client
+            .execute(&stmt, &[&quick_scan_id, &paths, &counts, &sizes])
+            .await?;
+    }
+    Ok(())
+}
+
+/// The quick_scans row of `quick_scan_id`
+pub async fn get_quick_scan_summary(
+    client: &tokio_postgres::Client,
+    quick_scan_id: i32,
+) -> anyhow::Result<serde_json::Value> {
+    let row = client
+        .query_opt(
+            "SELECT to_jsonb(q) FROM filesystem.quick_scans AS q WHERE quick_scan_id = $1",
+            &[&quick_scan_id],
+        )
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Quick scan {} not found", quick_scan_id))?;
+    Ok(row.get(0))
+}
@@ -0,0 +1,114 @@
+/// How often the config file's mtime is checked for changes
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Settings of the long-running modes (`fanotify_watch`, `shard_scan work
+/// --follow`) that take effect without a restart, read from the TOML file
+/// given with `--config`. A setting left out falls back to its command-line
+/// value, also when it is removed from the file later on.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReloadableConfig {
+    /// Log filter in `RUST_LOG` syntax, e.g. `info,fs_delta_tracker=debug`
+    pub log_level: Option<String>,
+    /// `fanotify_watch`: start a new change log once it grows past this size
+    pub max_log_mb: Option<u64>,
+    /// `shard_scan work`: seconds between polls for shards
+    pub poll_interval: Option<u64>,
+    /// `shard_scan work`: report directories with more entries as hot
+    pub hot_dir_threshold: Option<u64>,
+    /// `shard_scan work`: stop recording entries of a directory after this many
+    pub max_entries_per_dir: Option<u64>,
+    /// `shard_scan work`: throttle loading into staging to this many rows per second
+    pub load_max_rows_per_second: Option<u64>,
+}
+
+impl ReloadableConfig {
+    fn read(path: &std::path::Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let config: Self = toml::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?;
+        crate::logging::set_log_filter(config.log_level.as_deref())?;
+        Ok(config)
+    }
+}
+
+/// Rereads a [`ReloadableConfig`] on SIGHUP (on Unix) or when the file's
+/// mtime changes, so a daemon picks up new settings without a restart and
+/// without losing its in-memory state
+pub struct ConfigReloader {
+    path: std::path::PathBuf,
+    modified: Option<std::time::SystemTime>,
+    // an interval rather than a sleep: `changed` may be cancelled by a
+    // shorter `tokio::select!` branch and must not start over each time
+    check: tokio::time::Interval,
+    #[cfg(unix)]
+    hangup: tokio::signal::unix::Signal,
+}
+
+impl ConfigReloader {
+    /// Read the config at `path` and start listening for SIGHUP, which then
+    /// no longer terminates the process. Applies its log level.
+    pub fn new(path: std::path::PathBuf) -> anyhow::Result<(Self, ReloadableConfig)> {
+        let modified = modified(&path);
+        let config = ReloadableConfig::read(&path)?;
+        tracing::info!("⚙️ Loaded config {}", path.display());
+        let mut check = tokio::time::interval(CHECK_INTERVAL);
+        check.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let reloader = Self {
+            check,
+            #[cfg(unix)]
+            hangup: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?,
+            path,
+            modified,
+        };
+        Ok((reloader, config))
+    }
+
+    /// Wait for SIGHUP or a change of the file, and return the config read
+    /// then. An invalid config is logged and skipped, keeping the current
+    /// settings until the file is fixed.
+    pub async fn changed(&mut self) -> ReloadableConfig {
+        loop {
+            #[cfg(unix)]
+            let hangup = tokio::select! {
+                _ = self.hangup.recv() => true,
+                _ = self.check.tick() => false,
+            };
+            #[cfg(not(unix))]
+            let hangup = {
+                self.check.tick().await;
+                false
+            };
+
+            let modified = modified(&self.path);
+            if !hangup && modified == self.modified {
+                continue;
+            }
+            self.modified = modified;
+            match ReloadableConfig::read(&self.path) {
+                Ok(config) => {
+                    tracing::info!(
+                        "⚙️ Reloaded config {} on {}",
+                        self.path.display(),
+                        if hangup { "SIGHUP" } else { "file change" }
+                    );
+                    return config;
+                }
+                Err(e) => tracing::warn!("⚠️ Keeping the current settings: {}", e),
+            }
+        }
+    }
+}
+
+fn modified(path: &std::path::Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Wait for the next config of `reloader`, or forever without one, e.g. as a
+/// `tokio::select!` branch next to a daemon's work
+pub async fn next_config(reloader: Option<&mut ConfigReloader>) -> ReloadableConfig {
+    match reloader {
+        Some(reloader) => reloader.changed().await,
+        None => std::future::pending().await,
+    }
+}
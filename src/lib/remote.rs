@@ -0,0 +1,342 @@
+use hmac::Mac;
+use sha2::Digest;
+
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
+
+/// SHA-256 of an empty body, the payload hash of a signed GET
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+/// Payload hash of a signed PUT streamed without hashing it first
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+/// Largest object S3 accepts in a single PUT
+const MAX_PUT_BYTES: u64 = 5 * 1024 * 1024 * 1024;
+
+/// Where crawl artifacts are uploaded to by the crawl host and read from
+/// centrally: a local path, an `https://` (or `http://`) URL, or an
+/// `s3://bucket/key` object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Local(std::path::PathBuf),
+    Http(String),
+    S3 { bucket: String, key: String },
+}
+
+impl Location {
+    pub fn parse(location: &str) -> anyhow::Result<Self> {
+        if let Some(rest) = location.strip_prefix("s3://") {
+            let (bucket, key) = rest
+                .split_once('/')
+                .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+                .ok_or_else(|| anyhow::anyhow!("Expected s3://bucket/key, got {}", location))?;
+            return Ok(Location::S3 {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+            });
+        }
+        if location.starts_with("https://") || location.starts_with("http://") {
+            return Ok(Location::Http(location.to_string()));
+        }
+        Ok(Location::Local(location.into()))
+    }
+
+    /// Parse a location artifacts are uploaded below: an `s3://bucket/prefix/`
+    /// or a local directory
+    pub fn parse_prefix(location: &str) -> anyhow::Result<Self> {
+        if let Some(bucket) = location.strip_prefix("s3://")
+            && !bucket.trim_end_matches('/').contains('/')
+        {
+            return Ok(Location::S3 {
+                bucket: bucket.trim_end_matches('/').to_string(),
+                key: String::new(),
+            });
+        }
+        match Self::parse(location)? {
+            Location::Http(_) => {
+                anyhow::bail!(
+                    "Uploads go to s3:// prefixes or directories, got {}",
+                    location
+                )
+            }
+            location => Ok(location),
+        }
+    }
+
+    /// Location of `name` below this prefix
+    pub fn join(&self, name: &str) -> Self {
+        match self {
+            Location::Local(dir) => Location::Local(dir.join(name)),
+            Location::Http(url) => {
+                Location::Http(format!("{}/{}", url.trim_end_matches('/'), name))
+            }
+            Location::S3 { bucket, key } if key.is_empty() || key.ends_with('/') => Location::S3 {
+                bucket: bucket.clone(),
+                key: format!("{}{}", key, name),
+            },
+            Location::S3 { bucket, key } => Location::S3 {
+                bucket: bucket.clone(),
+                key: format!("{}/{}", key, name),
+            },
+        }
+    }
+
+    /// Upload a local file to this location, streaming it
+    pub async fn upload(&self, file: &std::path::Path) -> anyhow::Result<()> {
+        let size = tokio::fs::metadata(file).await?.len();
+        let request = match self {
+            Location::Local(path) => {
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::copy(file, path).await?;
+                tracing::info!("📤 Copied {} to {}", file.display(), self);
+                return Ok(());
+            }
+            Location::Http(_) => anyhow::bail!("Uploads over plain HTTP(S) are not supported"),
+            Location::S3 { bucket, key } => {
+                if size > MAX_PUT_BYTES {
+                    anyhow::bail!(
+                        "{} is {} bytes, more than the {} bytes S3 accepts in a single upload",
+                        file.display(),
+                        size,
+                        MAX_PUT_BYTES
+                    );
+                }
+                S3Config::from_env().request(&client()?, reqwest::Method::PUT, bucket, key)?
+            }
+        };
+        tracing::info!(
+            "📤 Uploading {} to {} ({} bytes)",
+            file.display(),
+            self,
+            size
+        );
+        let body = reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(
+            tokio::fs::File::open(file).await?,
+        ));
+        let response = request
+            .header(reqwest::header::CONTENT_LENGTH, size)
+            .body(body)
+            .send()
+            .await?;
+        check_status(self, "Upload", response).await?;
+        Ok(())
+    }
+
+    /// Open the location for a streaming read; remote objects are read as
+    /// they are downloaded, never stored whole
+    pub async fn open(&self) -> anyhow::Result<Box<dyn tokio::io::AsyncRead + Send + Unpin>> {
+        let request = match self {
+            Location::Local(path) => return Ok(Box::new(tokio::fs::File::open(path).await?)),
+            Location::Http(url) => client()?.get(url),
+            Location::S3 { bucket, key } => {
+                S3Config::from_env().request(&client()?, reqwest::Method::GET, bucket, key)?
+            }
+        };
+        tracing::info!("🌐 Downloading {}", self);
+        let response = check_status(self, "Download", request.send().await?).await?;
+        let stream = futures::TryStreamExt::map_err(response.bytes_stream(), std::io::Error::other);
+        Ok(Box::new(tokio_util::io::StreamReader::new(stream)))
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Local(path) => write!(f, "{}", path.display()),
+            // query strings may carry presigned credentials
+            Location::Http(url) => write!(f, "{}", url.split('?').next().unwrap_or(url)),
+            Location::S3 { bucket, key } => write!(f, "s3://{}/{}", bucket, key),
+        }
+    }
+}
+
+/// Fail on an error response, which S3 explains (e.g. `AccessDenied`) in the body
+async fn check_status(
+    location: &Location,
+    action: &str,
+    response: reqwest::Response,
+) -> anyhow::Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let body = body.split_whitespace().collect::<Vec<_>>().join(" ");
+    anyhow::bail!(
+        "{} of {} failed with {}: {}",
+        action,
+        location,
+        status,
+        body.chars().take(300).collect::<String>()
+    )
+}
+
+fn client() -> anyhow::Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .connect_timeout(std::time::Duration::from_secs(30))
+        .build()?)
+}
+
+/// S3 endpoint and credentials, from the standard AWS environment variables.
+///
+/// Requests are signed with Signature V4 when `AWS_ACCESS_KEY_ID` and
+/// `AWS_SECRET_ACCESS_KEY` are set, and sent anonymously otherwise (public
+/// buckets). `AWS_ENDPOINT_URL` points at an S3-compatible store such as
+/// MinIO, addressed path-style.
+#[derive(Debug, Clone)]
+struct S3Config {
+    region: String,
+    endpoint: Option<String>,
+    credentials: Option<(String, String, Option<String>)>,
+}
+
+impl S3Config {
+    fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        S3Config {
+            region: var("AWS_REGION")
+                .or_else(|| var("AWS_DEFAULT_REGION"))
+                .unwrap_or_else(|| "us-east-1".to_string()),
+            endpoint: var("AWS_ENDPOINT_URL_S3").or_else(|| var("AWS_ENDPOINT_URL")),
+            credentials: var("AWS_ACCESS_KEY_ID")
+                .zip(var("AWS_SECRET_ACCESS_KEY"))
+                .map(|(id, secret)| (id, secret, var("AWS_SESSION_TOKEN"))),
+        }
+    }
+
+    /// Build a (signed) GET or PUT of an object; a PUT's body is not hashed
+    fn request(
+        &self,
+        client: &reqwest::Client,
+        method: reqwest::Method,
+        bucket: &str,
+        key: &str,
+    ) -> anyhow::Result<reqwest::RequestBuilder> {
+        let (scheme, host, path) = match &self.endpoint {
+            Some(endpoint) => {
+                let (scheme, host) = endpoint
+                    .trim_end_matches('/')
+                    .split_once("://")
+                    .ok_or_else(|| anyhow::anyhow!("Invalid S3 endpoint {}", endpoint))?;
+                (
+                    scheme.to_string(),
+                    host.to_string(),
+                    format!("/{}/{}", uri_encode(bucket), uri_encode(key)),
+                )
+            }
+            None => (
+                "https".to_string(),
+                format!("{}.s3.{}.amazonaws.com", bucket, self.region),
+                format!("/{}", uri_encode(key)),
+            ),
+        };
+        let request = client.request(method.clone(), format!("{}://{}{}", scheme, host, path));
+        let Some((access_key_id, secret, session_token)) = &self.credentials else {
+            return Ok(request);
+        };
+
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = if method == reqwest::Method::GET {
+            EMPTY_SHA256
+        } else {
+            UNSIGNED_PAYLOAD
+        };
+        let mut headers = vec![
+            ("host", host.clone()),
+            ("x-amz-content-sha256", payload_hash.to_string()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let authorization = sign_v4(
+            &SigningRequest {
+                method: method.as_str(),
+                path: &path,
+                payload_hash,
+                headers: &headers,
+                region: &self.region,
+                amz_date: &amz_date,
+            },
+            access_key_id,
+            secret,
+        );
+
+        let mut request = request.header("authorization", authorization);
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.header(*name, value);
+        }
+        Ok(request)
+    }
+}
+
+/// The parts of a request covered by its signature; `headers` are
+/// lowercase, sorted and include `host`
+struct SigningRequest<'a> {
+    method: &'a str,
+    path: &'a str,
+    payload_hash: &'a str,
+    headers: &'a [(&'a str, String)],
+    region: &'a str,
+    amz_date: &'a str,
+}
+
+/// `Authorization` header of an AWS Signature V4 request to S3 without query string
+fn sign_v4(request: &SigningRequest, access_key_id: &str, secret: &str) -> String {
+    let date = &request.amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, request.region);
+    let signed_headers = request
+        .headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_headers: String = request
+        .headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        request.method, request.path, canonical_headers, signed_headers, request.payload_hash
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        request.amz_date,
+        scope,
+        crate::integrity::to_hex(&sha2::Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let key = [date, request.region, "s3", "aws4_request"]
+        .iter()
+        .fold(format!("AWS4{}", secret).into_bytes(), |key, part| {
+            hmac(&key, part.as_bytes()).to_vec()
+        });
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key_id,
+        scope,
+        signed_headers,
+        crate::integrity::to_hex(&hmac(&key, string_to_sign.as_bytes()))
+    )
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// Percent-encode an object key as S3 expects in the canonical URI: all but
+/// unreserved characters, keeping `/`
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -0,0 +1,113 @@
+/// Open file descriptors are counted this often by [`watch_open_fds`]
+const FD_SAMPLE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Most file descriptors seen open at once by the sampler
+static OPEN_FDS_PEAK: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+static FD_SAMPLER: std::sync::Once = std::sync::Once::new();
+
+/// Resources the process used so far, as reported by the OS; what a
+/// platform does not report is `None`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResourceUsage {
+    pub peak_rss_bytes: Option<u64>,
+    pub cpu_user: Option<std::time::Duration>,
+    pub cpu_system: Option<std::time::Duration>,
+    /// Bytes the process caused to be read from storage (`/proc/self/io`),
+    /// i.e. excluding reads served from the page cache
+    pub read_bytes: Option<u64>,
+    /// High-water mark of open file descriptors, sampled every second
+    /// since [`watch_open_fds`], so short spikes may be missed
+    pub open_fds_peak: Option<u64>,
+}
+
+impl ResourceUsage {
+    /// Usage of the whole process, all threads included, up to now
+    pub fn current() -> Self {
+        let mut usage = Self::default();
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        {
+            let mut rusage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+            // SAFETY: getrusage fills the struct it is given
+            if unsafe { libc::getrusage(libc::RUSAGE_SELF, rusage.as_mut_ptr()) } == 0 {
+                let rusage = unsafe { rusage.assume_init() };
+                let duration = |tv: libc::timeval| {
+                    std::time::Duration::from_secs(tv.tv_sec as u64)
+                        + std::time::Duration::from_micros(tv.tv_usec as u64)
+                };
+                // kilobytes on Linux, bytes on macOS
+                let rss_unit = if cfg!(target_os = "linux") { 1024 } else { 1 };
+                usage.peak_rss_bytes = Some(rusage.ru_maxrss as u64 * rss_unit);
+                usage.cpu_user = Some(duration(rusage.ru_utime));
+                usage.cpu_system = Some(duration(rusage.ru_stime));
+            }
+        }
+        usage.read_bytes = std::fs::read_to_string("/proc/self/io")
+            .ok()
+            .and_then(|io| {
+                io.lines()
+                    .find_map(|line| line.strip_prefix("read_bytes:"))
+                    .and_then(|bytes| bytes.trim().parse().ok())
+            });
+        if sample_open_fds().is_some() {
+            usage.open_fds_peak = Some(OPEN_FDS_PEAK.load(std::sync::atomic::Ordering::Relaxed));
+        }
+        usage
+    }
+
+    /// Record the usage in scan metadata, leaving out what is unknown
+    pub fn record(&self, metadata: &mut std::collections::HashMap<String, String>) {
+        let mut insert = |key: &str, value: Option<String>| {
+            if let Some(value) = value {
+                metadata.insert(key.to_string(), value);
+            }
+        };
+        insert("peak_rss_bytes", self.peak_rss_bytes.map(|v| v.to_string()));
+        insert(
+            "cpu_user_time_s",
+            self.cpu_user.map(|v| v.as_secs_f64().to_string()),
+        );
+        insert(
+            "cpu_system_time_s",
+            self.cpu_system.map(|v| v.as_secs_f64().to_string()),
+        );
+        insert("read_bytes", self.read_bytes.map(|v| v.to_string()));
+        insert("open_fds_peak", self.open_fds_peak.map(|v| v.to_string()));
+    }
+}
+
+/// Start counting the process's open file descriptors in the background,
+/// for [`ResourceUsage::open_fds_peak`]; later calls do nothing
+pub fn watch_open_fds() {
+    FD_SAMPLER.call_once(|| {
+        if sample_open_fds().is_none() {
+            return;
+        }
+        let spawned = std::thread::Builder::new()
+            .name("fd-sampler".to_string())
+            .spawn(|| {
+                loop {
+                    std::thread::sleep(FD_SAMPLE_INTERVAL);
+                    sample_open_fds();
+                }
+            });
+        if let Err(e) = spawned {
+            tracing::warn!("⚠️ Failed to start sampling open file descriptors: {}", e);
+        }
+    });
+}
+
+/// Count the open file descriptors into the high-water mark; `None` where
+/// they cannot be listed
+fn sample_open_fds() -> Option<u64> {
+    let dir = if cfg!(target_os = "linux") {
+        "/proc/self/fd"
+    } else if cfg!(target_os = "macos") {
+        "/dev/fd"
+    } else {
+        return None;
+    };
+    // less the descriptor of the listing itself
+    let open = std::fs::read_dir(dir).ok()?.count().saturating_sub(1) as u64;
+    OPEN_FDS_PEAK.fetch_max(open, std::sync::atomic::Ordering::Relaxed);
+    Some(open)
+}
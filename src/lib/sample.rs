@@ -0,0 +1,197 @@
+use std::io::{Read as _, Seek as _};
+
+/// Bytes read from the start of a sampled file to check its type by
+const SNIFF_BYTES: usize = 512;
+
+/// Leading bytes of a file format, `offset` bytes into the file
+struct Magic {
+    exts: &'static [&'static str],
+    offset: usize,
+    signatures: &'static [&'static [u8]],
+}
+
+/// The formats a sampled file is checked against, by normalized extension:
+/// a file whose contents do not start like its extension says is
+/// truncated, zeroed or otherwise damaged
+const MAGIC: &[Magic] = &[
+    Magic {
+        exts: &["pdf"],
+        offset: 0,
+        signatures: &[b"%PDF-"],
+    },
+    Magic {
+        exts: &["png"],
+        offset: 0,
+        signatures: &[b"\x89PNG\r\n\x1a\n"],
+    },
+    Magic {
+        exts: &["jpg", "jpeg"],
+        offset: 0,
+        signatures: &[b"\xff\xd8\xff"],
+    },
+    Magic {
+        exts: &["gif"],
+        offset: 0,
+        signatures: &[b"GIF87a", b"GIF89a"],
+    },
+    Magic {
+        exts: &["tif", "tiff"],
+        offset: 0,
+        signatures: &[b"II*\0", b"MM\0*"],
+    },
+    Magic {
+        exts: &["zip", "docx", "xlsx", "pptx", "jar"],
+        offset: 0,
+        signatures: &[b"PK\x03\x04", b"PK\x05\x06"],
+    },
+    Magic {
+        exts: &[
+            "gz", "tgz", "tar.gz", "nii.gz", "fastq.gz", "fq.gz", "vcf.gz", "bam",
+        ],
+        offset: 0,
+        signatures: &[b"\x1f\x8b"],
+    },
+    Magic {
+        exts: &["bz2", "tar.bz2"],
+        offset: 0,
+        signatures: &[b"BZh"],
+    },
+    Magic {
+        exts: &["xz", "tar.xz"],
+        offset: 0,
+        signatures: &[b"\xfd7zXZ\0"],
+    },
+    Magic {
+        exts: &["zst", "tar.zst"],
+        offset: 0,
+        signatures: &[b"\x28\xb5\x2f\xfd"],
+    },
+    Magic {
+        exts: &["h5", "hdf5"],
+        offset: 0,
+        signatures: &[b"\x89HDF\r\n\x1a\n"],
+    },
+    Magic {
+        exts: &["fits", "fit"],
+        offset: 0,
+        signatures: &[b"SIMPLE  ="],
+    },
+    Magic {
+        exts: &["dcm"],
+        offset: 128,
+        signatures: &[b"DICM"],
+    },
+];
+
+/// Whether the file at `path` is in the sample of `scan_id` that holds
+/// `fraction` of the files. Drawn anew for every scan, but the same for
+/// every crawl of one, e.g. a resumed one.
+pub fn is_sampled(path: &std::path::Path, scan_id: i32, fraction: f64) -> bool {
+    let draw =
+        xxhash_rust::xxh3::xxh3_64_with_seed(path.as_os_str().as_encoded_bytes(), scan_id as u64);
+    (draw as f64) < fraction * u64::MAX as f64
+}
+
+/// Whether the start of `file` matches the format extension `ext` stands
+/// for; `None` if the extension is not one checked, or the file is empty.
+/// Leaves the file where it found it.
+pub fn sniff(file: &mut std::fs::File, ext: &str) -> std::io::Result<Option<bool>> {
+    let Some(magic) = MAGIC.iter().find(|magic| magic.exts.contains(&ext)) else {
+        return Ok(None);
+    };
+    let start = file.stream_position()?;
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    file.by_ref()
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut head)?;
+    file.seek(std::io::SeekFrom::Start(start))?;
+    if head.is_empty() {
+        return Ok(None);
+    }
+    let head = head.get(magic.offset..).unwrap_or_default();
+    Ok(Some(
+        magic
+            .signatures
+            .iter()
+            .any(|signature| head.starts_with(signature)),
+    ))
+}
+
+/// Deep checks of the sampled files of a crawl, counted lock-free by the
+/// walker threads
+#[derive(Debug, Default)]
+pub struct SampleStats {
+    sampled: std::sync::atomic::AtomicU64,
+    sniffed: std::sync::atomic::AtomicU64,
+    mismatched: std::sync::atomic::AtomicU64,
+}
+
+impl SampleStats {
+    /// Count a sampled file, with the outcome of sniffing it
+    pub fn record(&self, path: &std::path::Path, ext: &str, sniffed: Option<bool>) {
+        use std::sync::atomic::Ordering::Relaxed;
+
+        self.sampled.fetch_add(1, Relaxed);
+        if let Some(matches) = sniffed {
+            self.sniffed.fetch_add(1, Relaxed);
+            if !matches {
+                self.mismatched.fetch_add(1, Relaxed);
+                tracing::warn!(
+                    "🧪 Sampled {} does not look like a .{} file",
+                    path.display(),
+                    ext
+                );
+            }
+        }
+    }
+
+    pub fn insert_into(
+        &self,
+        fraction: f64,
+        metadata: &mut std::collections::HashMap<String, String>,
+    ) {
+        use std::sync::atomic::Ordering::Relaxed;
+
+        metadata.insert("sample_fraction".to_string(), fraction.to_string());
+        metadata.insert(
+            "sample_files".to_string(),
+            self.sampled.load(Relaxed).to_string(),
+        );
+        metadata.insert(
+            "sample_sniffed_files".to_string(),
+            self.sniffed.load(Relaxed).to_string(),
+        );
+        metadata.insert(
+            "sample_type_mismatches".to_string(),
+            self.mismatched.load(Relaxed).to_string(),
+        );
+    }
+}
+
+/// Record the bit-rot estimate of a scan in its metadata: of its hashed
+/// files whose size and mtime are those tracked, and whose tracked
+/// fingerprint was hashed alike, the share whose contents changed anyway
+pub fn record_bitrot_estimate(
+    compared: i64,
+    changed: i64,
+    metadata: &mut std::collections::HashMap<String, String>,
+) {
+    let rate = if compared > 0 {
+        changed as f64 / compared as f64
+    } else {
+        0.0
+    };
+    if changed > 0 {
+        tracing::warn!(
+            "🧪 {} of {} verified files changed contents with size and mtime intact ({:.4}%)",
+            changed,
+            compared,
+            rate * 100.0
+        );
+    } else {
+        tracing::info!("🧪 {} files verified against their fingerprints", compared);
+    }
+    metadata.insert("sample_verified_files".to_string(), compared.to_string());
+    metadata.insert("sample_bitrot_files".to_string(), changed.to_string());
+    metadata.insert("sample_bitrot_rate".to_string(), rate.to_string());
+}
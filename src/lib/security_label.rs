@@ -0,0 +1,87 @@
+/// Mandatory access control label recorded per file, read from the extended
+/// attribute the LSM keeps it in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelSource {
+    /// SELinux context, e.g. `system_u:object_r:etc_t:s0`
+    Selinux,
+    /// SMACK access label
+    Smack,
+}
+
+impl LabelSource {
+    /// Extended attribute holding the label
+    pub fn xattr_name(&self) -> &'static str {
+        match self {
+            LabelSource::Selinux => "security.selinux",
+            LabelSource::Smack => "security.SMACK64",
+        }
+    }
+
+    /// Label of `path` (not following a symlink), or `None` if it has none
+    /// or the filesystem does not support labels
+    pub fn read(&self, path: &std::path::Path) -> Option<String> {
+        read_xattr(path, self.xattr_name())
+    }
+}
+
+impl std::str::FromStr for LabelSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "selinux" => Ok(LabelSource::Selinux),
+            "smack" => Ok(LabelSource::Smack),
+            other => anyhow::bail!("Unknown security label source: {}", other),
+        }
+    }
+}
+
+impl std::fmt::Display for LabelSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LabelSource::Selinux => write!(f, "selinux"),
+            LabelSource::Smack => write!(f, "smack"),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_xattr(path: &std::path::Path, name: &str) -> Option<String> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let name = std::ffi::CString::new(name).ok()?;
+    // labels are short; SELinux contexts stay well below a page
+    let mut value = vec![0u8; 256];
+    loop {
+        // SAFETY: both strings are NUL-terminated and `value` is writable for its length
+        let len = unsafe {
+            libc::lgetxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        if len >= 0 {
+            value.truncate(len as usize);
+            break;
+        }
+        if std::io::Error::last_os_error().raw_os_error() != Some(libc::ERANGE)
+            || value.len() >= 64 * 1024
+        {
+            return None;
+        }
+        value.resize(value.len() * 4, 0);
+    }
+    // the kernel hands SELinux contexts over NUL-terminated
+    while value.last() == Some(&0) {
+        value.pop();
+    }
+    (!value.is_empty()).then(|| String::from_utf8_lossy(&value).into_owned())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_xattr(_path: &std::path::Path, _name: &str) -> Option<String> {
+    None
+}
@@ -0,0 +1,737 @@
+use crate::pipeline::{self, CrawlTotals, ScanBatch, ScanOptions};
+use crate::progress::{Phase, ProgressEvent, ProgressReporter};
+use crate::staging::StagingStrategy;
+use crate::{data, db};
+
+/// How often a worker bumps the heartbeat of the shard it is applying
+const HEARTBEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Timings of the shards summed into the scan's metadata
+const SUMMED_TIMINGS: [&str; 5] = [
+    "load_time_s",
+    "load_blocked_time_s",
+    "load_throttled_time_s",
+    "load_index_rebuild_time_s",
+    "sql_execution_time_s",
+];
+
+/// A shard claimed by this worker
+#[derive(Debug)]
+struct ClaimedShard {
+    scan_id: i32,
+    shard_index: i32,
+    batch_paths: Vec<String>,
+}
+
+/// `host:pid` identifying this worker in `filesystem.scan_shards`
+fn worker_id() -> String {
+    format!("{}:{}", data::local_hostname(), std::process::id())
+}
+
+/// Deal the root's own files and top-level directories round-robin into at
+/// most `shard_count` shards
+pub fn round_robin_shards(
+    data_root: &std::path::Path,
+    shard_count: usize,
+) -> anyhow::Result<Vec<Vec<std::path::PathBuf>>> {
+    anyhow::ensure!(shard_count > 0, "A sharded scan needs at least one shard");
+    let batches = pipeline::top_level_batches(data_root)?;
+    let mut shards = vec![Vec::new(); shard_count.min(batches.len())];
+    let count = shards.len();
+    for (index, batch) in batches.into_iter().enumerate() {
+        shards[index % count].push(batch.path);
+    }
+    Ok(shards)
+}
+
+/// Balanced assignment of a root's top-level directories to shards, written
+/// by `shard_plan` and read by `shard_scan start --plan`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ShardPlan {
+    pub data_root: std::path::PathBuf,
+    /// Directory levels below each top-level directory that were read
+    pub sample_depth: usize,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub shards: Vec<PlannedShard>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PlannedShard {
+    /// Estimated entries below the shard's paths
+    pub estimated_entries: u64,
+    /// Top-level directories of the root; the root itself stands for its
+    /// own files
+    pub paths: Vec<std::path::PathBuf>,
+}
+
+impl ShardPlan {
+    /// Plan `shard_count` shards of `data_root`, estimating the size of each
+    /// top-level directory from its first `sample_depth` levels. The
+    /// directories are dealt largest first to the smallest shard so far,
+    /// so the same tree always gives the same plan.
+    pub fn sample(
+        data_root: &std::path::Path,
+        shard_count: usize,
+        sample_depth: usize,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(shard_count > 0, "A sharded scan needs at least one shard");
+        anyhow::ensure!(sample_depth > 0, "The sample depth must be at least 1");
+        let mut estimates = Vec::new();
+        for batch in pipeline::top_level_batches(data_root)? {
+            let estimate = if batch.max_depth.is_some() {
+                // the root's own files: nothing to extrapolate
+                let mut files = 0;
+                for entry in std::fs::read_dir(data_root)? {
+                    if !entry?.file_type()?.is_dir() {
+                        files += 1;
+                    }
+                }
+                files
+            } else {
+                estimate_entries(&batch.path, sample_depth)?
+            };
+            tracing::debug!("{}: ~{} entries", batch.path.display(), estimate);
+            estimates.push((batch.path, estimate));
+        }
+        estimates.sort_by(|(a_path, a), (b_path, b)| b.cmp(a).then_with(|| a_path.cmp(b_path)));
+
+        let mut shards = vec![
+            PlannedShard {
+                estimated_entries: 0,
+                paths: Vec::new(),
+            };
+            shard_count.min(estimates.len())
+        ];
+        for (path, estimate) in estimates {
+            let smallest = shards
+                .iter_mut()
+                .min_by_key(|shard| shard.estimated_entries)
+                .expect("at least one shard");
+            smallest.estimated_entries += estimate;
+            smallest.paths.push(path);
+        }
+        for shard in &mut shards {
+            shard.paths.sort();
+        }
+        Ok(Self {
+            data_root: data_root.to_path_buf(),
+            sample_depth,
+            created_at: chrono::Utc::now(),
+            shards,
+        })
+    }
+
+    pub fn read(path: &std::path::Path) -> anyhow::Result<Self> {
+        serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|e| anyhow::anyhow!("Invalid shard plan {}: {}", path.display(), e))
+    }
+
+    /// The planned shards of `data_root`, reconciled with its current
+    /// top-level directories: those created since the plan go to the
+    /// smallest shards, and those removed since are dropped
+    pub fn shards_for(
+        &self,
+        data_root: &std::path::Path,
+    ) -> anyhow::Result<Vec<Vec<std::path::PathBuf>>> {
+        anyhow::ensure!(
+            self.data_root == data_root,
+            "The shard plan is of {}, not {}",
+            self.data_root.display(),
+            data_root.display()
+        );
+        let mut unplanned: std::collections::BTreeSet<std::path::PathBuf> =
+            pipeline::top_level_batches(data_root)?
+                .into_iter()
+                .map(|batch| batch.path)
+                .collect();
+        let mut shards: Vec<(u64, Vec<std::path::PathBuf>)> = Vec::new();
+        for shard in &self.shards {
+            let mut paths = Vec::new();
+            for path in &shard.paths {
+                if unplanned.remove(path) {
+                    paths.push(path.clone());
+                } else {
+                    tracing::warn!("⚠️ {} is gone since the plan, dropping it", path.display());
+                }
+            }
+            if !paths.is_empty() {
+                shards.push((shard.estimated_entries, paths));
+            }
+        }
+        anyhow::ensure!(!shards.is_empty(), "No planned shard is left");
+        for path in unplanned {
+            tracing::warn!("⚠️ {} is not in the plan, adding it", path.display());
+            let (_, paths) = shards
+                .iter_mut()
+                .min_by_key(|(estimate, _)| *estimate)
+                .expect("at least one shard");
+            paths.push(path);
+        }
+        Ok(shards.into_iter().map(|(_, paths)| paths).collect())
+    }
+}
+
+/// Rough entry count below `dir`: the entries of its first `depth` levels,
+/// plus, for each directory of the last level read, the mean fan-out of
+/// that level
+fn estimate_entries(dir: &std::path::Path, depth: usize) -> anyhow::Result<u64> {
+    let mut level = vec![dir.to_path_buf()];
+    let mut total = 0;
+    let mut fan_out = 0.0;
+    for _ in 0..depth {
+        let mut entries = 0;
+        let mut next = Vec::new();
+        for dir in &level {
+            // unreadable directories are left to the crawl to report
+            let Ok(read_dir) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in read_dir {
+                let entry = entry?;
+                entries += 1;
+                if entry.file_type()?.is_dir() {
+                    next.push(entry.path());
+                }
+            }
+        }
+        total += entries;
+        fan_out = entries as f64 / level.len().max(1) as f64;
+        level = next;
+        if level.is_empty() {
+            break;
+        }
+    }
+    Ok(total + (level.len() as f64 * fan_out).round() as u64)
+}
+
+/// Start a sharded scan of `options.data_root`, queueing `shards` for
+/// `shard_scan work` on any host to claim. Each shard lists top-level
+/// directories of the root, the root itself standing for its own files.
+/// Returns the scan_id; [`finish_sharded_scan`] completes the scan.
+#[tracing::instrument(skip(client, options, shards))]
+pub async fn start_sharded_scan(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    shards: &[Vec<std::path::PathBuf>],
+) -> anyhow::Result<i32> {
+    options.validate()?;
+    anyhow::ensure!(
+        !options.review && options.snapshot_diff.is_none(),
+        "Sharded scans apply each shard as it goes and cannot be reviewed or snapshot diffed"
+    );
+    anyhow::ensure!(
+        !shards.is_empty(),
+        "A sharded scan needs at least one shard"
+    );
+    for path in shards.iter().flatten() {
+        anyhow::ensure!(
+            path == &options.data_root || path.parent() == Some(options.data_root.as_path()),
+            "{} is not {} or one of its top-level directories",
+            path.display(),
+            options.data_root.display()
+        );
+    }
+
+    pipeline::check_root_tenant(client, &options.data_root, options.tenant.as_deref()).await?;
+    let path_key_id = options.crawl.path_cipher.as_ref().map(|c| c.key_id());
+    pipeline::check_root_path_key(client, &options.data_root, path_key_id).await?;
+    let scan_id = data::start_scan(
+        client,
+        &options.data_root,
+        &[],
+        options.tenant.as_deref(),
+        path_key_id,
+        chrono::Utc::now(),
+    )
+    .await?;
+    tracing::info!("🔍 Scan ID: {}", scan_id);
+
+    let query = "
+        INSERT INTO filesystem.scan_shards (scan_id, shard_index, batch_paths)
+        VALUES ($1, $2, $3)";
+    for (index, paths) in shards.iter().enumerate() {
+        let paths: Vec<String> = paths
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        client
+            .execute(query, &[&scan_id, &(index as i32), &paths])
+            .await?;
+    }
+    data::audit(
+        client,
+        "scan_sharded",
+        Some(scan_id),
+        serde_json::json!({ "shard_count": shards.len() }),
+    )
+    .await?;
+    tracing::info!(
+        "🧩 Queued {} shards of {} for `shard_scan work`",
+        shards.len(),
+        options.data_root.display()
+    );
+    Ok(scan_id)
+}
+
+/// Claim and apply the pending shards of running sharded scans of
+/// `options.data_root` until none is left, returning how many were applied.
+/// The crawl is staged in a temporary table, since other workers apply
+/// shards of the same scan concurrently; deletions are left to the
+/// controller's sweep.
+#[tracing::instrument(skip_all, fields(data_root = %options.data_root.display()))]
+pub async fn work_shards(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    progress: &ProgressReporter,
+) -> anyhow::Result<usize> {
+    let mut options = options.clone();
+    options.staging = StagingStrategy::Temporary;
+    options.validate()?;
+    let path_key_id = options.crawl.path_cipher.as_ref().map(|c| c.key_id());
+    pipeline::check_root_path_key(client, &options.data_root, path_key_id).await?;
+    options.staging.prepare(client).await?;
+
+    let worker = worker_id();
+    let mut applied = 0;
+    loop {
+        if let Some(hours) = options.allowed_hours.filter(|hours| !hours.contains_now()) {
+            tracing::debug!("⏸️ Outside the allowed hours {}, claiming no shards", hours);
+            break;
+        }
+        let Some(shard) = claim_shard(client, &options.data_root, &worker).await? else {
+            break;
+        };
+        tracing::info!(
+            "🧩 Claimed shard {} of scan {} ({} batches)",
+            shard.shard_index,
+            shard.scan_id,
+            shard.batch_paths.len()
+        );
+        let result = tokio::select! {
+            result = apply_shard(client, &options, &shard, progress) => result,
+            e = heartbeat(client, &shard, &worker) => Err(e),
+        };
+        match result {
+            Ok(metadata) => {
+                complete_shard(client, &shard, &worker, &metadata).await?;
+                tracing::info!(
+                    "✅ Shard {} of scan {} applied",
+                    shard.shard_index,
+                    shard.scan_id
+                );
+                applied += 1;
+            }
+            Err(e) => {
+                tracing::error!(
+                    "❌ Shard {} of scan {} failed: {:#}",
+                    shard.shard_index,
+                    shard.scan_id,
+                    e
+                );
+                fail_shard(client, &shard, &worker, &e).await?;
+                return Err(e);
+            }
+        }
+    }
+    Ok(applied)
+}
+
+/// Work the shards of several roots at once, as [`work_shards`] does for
+/// one: the roots' shards are applied one at a time per root, and those of
+/// at most `pool.size()` roots at once, each over a connection of `pool`.
+/// Returns the shards applied or the error of each root, in order.
+#[tracing::instrument(skip_all)]
+pub async fn work_roots(
+    pool: &db::ClientPool,
+    roots: &[ScanOptions],
+    progress: &ProgressReporter,
+) -> Vec<anyhow::Result<usize>> {
+    use futures::StreamExt;
+
+    futures::stream::iter(roots)
+        .map(|options| async move {
+            let client = pool.get().await?;
+            work_shards(&client, options, progress)
+                .await
+                .map_err(|e| e.context(format!("Shards of {} failed", options.data_root.display())))
+        })
+        .buffered(pool.size())
+        .collect()
+        .await
+}
+
+/// Claim the next pending shard of a running scan of `data_root`, skipping
+/// those other workers are claiming
+async fn claim_shard(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+    worker: &str,
+) -> anyhow::Result<Option<ClaimedShard>> {
+    let query = "
+        UPDATE filesystem.scan_shards AS s
+        SET shard_status = 'running',
+            worker = $2,
+            claimed_at = now(),
+            heartbeat_at = now(),
+            error = NULL
+        FROM (
+            SELECT sh.scan_id, sh.shard_index
+            FROM filesystem.scan_shards AS sh
+            JOIN filesystem.scan_runs AS r USING (scan_id)
+            WHERE r.scan_root = $1
+              AND r.scan_status = 'running'
+              AND sh.shard_status = 'pending'
+            ORDER BY sh.scan_id, sh.shard_index
+            LIMIT 1
+            FOR UPDATE OF sh SKIP LOCKED
+        ) AS next
+        WHERE s.scan_id = next.scan_id
+          AND s.shard_index = next.shard_index
+        RETURNING s.scan_id, s.shard_index, s.batch_paths";
+    let row = client
+        .query_opt(query, &[&data_root.to_string_lossy(), &worker])
+        .await?;
+    Ok(row.map(|r| ClaimedShard {
+        scan_id: r.get(0),
+        shard_index: r.get(1),
+        batch_paths: r.get(2),
+    }))
+}
+
+/// Bump the shard's heartbeat until the controller takes it back (e.g.
+/// after a network partition), which is returned as an error
+async fn heartbeat(
+    client: &tokio_postgres::Client,
+    shard: &ClaimedShard,
+    worker: &str,
+) -> anyhow::Error {
+    let query = "
+        UPDATE filesystem.scan_shards
+        SET heartbeat_at = now()
+        WHERE scan_id = $1 AND shard_index = $2 AND worker = $3 AND shard_status = 'running'";
+    loop {
+        tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+        match client
+            .execute(query, &[&shard.scan_id, &shard.shard_index, &worker])
+            .await
+        {
+            Ok(0) => {
+                return anyhow::anyhow!(
+                    "Shard {} of scan {} was requeued by the controller",
+                    shard.shard_index,
+                    shard.scan_id
+                );
+            }
+            Ok(_) => {}
+            Err(e) => return e.into(),
+        }
+    }
+}
+
+/// Crawl, load and apply the batches of a shard, returning its metadata
+async fn apply_shard(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    shard: &ClaimedShard,
+    progress: &ProgressReporter,
+) -> anyhow::Result<std::collections::HashMap<String, String>> {
+    let mut crawl = CrawlTotals::default();
+    let mut load = data::LoadStats::default();
+    let mut sql_execution_time = std::time::Duration::ZERO;
+    for (index, path) in shard.batch_paths.iter().enumerate() {
+        let path = std::path::PathBuf::from(path);
+        let own_files = path == options.data_root;
+        if !own_files && !path.is_dir() {
+            // removed since the shards were planned; the sweep records its
+            // files as deleted
+            tracing::warn!("⚠️ {} is gone, skipping it", path.display());
+            continue;
+        }
+        let output_tsv_file = std::env::temp_dir().join(format!(
+            "scan_{}_shard_{}_batch_{}.tsv",
+            shard.scan_id, shard.shard_index, index
+        ));
+        tracing::info!(
+            "🧩 Batch {}/{}: {}{}",
+            index + 1,
+            shard.batch_paths.len(),
+            path.display(),
+            if own_files { " (own files)" } else { "" }
+        );
+        progress.emit(ProgressEvent::BatchStarted {
+            index,
+            total: shard.batch_paths.len(),
+            path: path.clone(),
+            output_tsv_file: output_tsv_file.clone(),
+        });
+
+        let batch = ScanBatch {
+            path,
+            max_depth: own_files.then_some(1),
+        };
+        let (batch_metadata, batch_load, batch_sql_execution_time) = pipeline::apply_batch(
+            client,
+            options,
+            shard.scan_id,
+            &batch,
+            &output_tsv_file,
+            progress,
+        )
+        .await?;
+        crawl.add(&batch_metadata, !own_files);
+        load += batch_load;
+        sql_execution_time += batch_sql_execution_time;
+    }
+
+    let mut metadata = crawl.into_metadata(&options.data_root);
+    metadata.insert("hostname".to_string(), data::local_hostname());
+    load.record(&mut metadata);
+    metadata.insert(
+        "sql_execution_time_s".to_string(),
+        sql_execution_time.as_secs_f64().to_string(),
+    );
+    Ok(metadata)
+}
+
+async fn complete_shard(
+    client: &tokio_postgres::Client,
+    shard: &ClaimedShard,
+    worker: &str,
+    metadata: &std::collections::HashMap<String, String>,
+) -> anyhow::Result<()> {
+    let query = "
+        UPDATE filesystem.scan_shards
+        SET shard_status = 'completed', finished_at = now(), shard_metadata = $4
+        WHERE scan_id = $1 AND shard_index = $2 AND worker = $3";
+    let updated = client
+        .execute(
+            query,
+            &[
+                &shard.scan_id,
+                &shard.shard_index,
+                &worker,
+                &serde_json::to_value(metadata)?,
+            ],
+        )
+        .await?;
+    if updated == 0 {
+        // applying a shard twice changes nothing, so the other worker's
+        // run stands
+        tracing::warn!(
+            "⚠️ Shard {} of scan {} was requeued while it was applied",
+            shard.shard_index,
+            shard.scan_id
+        );
+    }
+    Ok(())
+}
+
+async fn fail_shard(
+    client: &tokio_postgres::Client,
+    shard: &ClaimedShard,
+    worker: &str,
+    error: &anyhow::Error,
+) -> anyhow::Result<()> {
+    let query = "
+        UPDATE filesystem.scan_shards
+        SET shard_status = 'failed', finished_at = now(), error = $4
+        WHERE scan_id = $1 AND shard_index = $2 AND worker = $3";
+    client
+        .execute(
+            query,
+            &[
+                &shard.scan_id,
+                &shard.shard_index,
+                &worker,
+                &format!("{:#}", error),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Wait for every shard of sharded scan `scan_id` to be applied, then sweep
+/// the files no shard has seen and finalize the scan, as the last steps of
+/// a batched scan. Shards whose worker stopped sending heartbeats for
+/// `stale_after` are requeued; a failed shard fails the scan, which stays
+/// running so that its failed shards can be retried with `retry_failed`.
+#[tracing::instrument(skip(client, options, progress))]
+pub async fn finish_sharded_scan(
+    client: &tokio_postgres::Client,
+    options: &ScanOptions,
+    scan_id: i32,
+    poll_interval: std::time::Duration,
+    stale_after: std::time::Duration,
+    retry_failed: bool,
+    progress: &ProgressReporter,
+) -> anyhow::Result<()> {
+    let status = data::get_scan_status(client, scan_id).await?;
+    anyhow::ensure!(
+        status == "running",
+        "Scan {} is {}, not running",
+        scan_id,
+        status
+    );
+    let (scan_root, _) = data::get_scan_root(client, scan_id).await?;
+    anyhow::ensure!(
+        std::path::Path::new(&scan_root) == options.data_root,
+        "Scan {} is of {}, not {}",
+        scan_id,
+        scan_root,
+        options.data_root.display()
+    );
+    if retry_failed {
+        let requeued = client
+            .execute(
+                "UPDATE filesystem.scan_shards
+                 SET shard_status = 'pending', worker = NULL, error = NULL
+                 WHERE scan_id = $1 AND shard_status = 'failed'",
+                &[&scan_id],
+            )
+            .await?;
+        tracing::info!("🔁 Requeued {} failed shards", requeued);
+    }
+
+    pipeline::run_phase(
+        progress,
+        Phase::Crawl,
+        wait_for_shards(client, scan_id, poll_interval, stale_after),
+    )
+    .await?;
+
+    let rows = client
+        .query(
+            "SELECT worker, shard_metadata
+             FROM filesystem.scan_shards
+             WHERE scan_id = $1
+             ORDER BY shard_index",
+            &[&scan_id],
+        )
+        .await?;
+    let mut crawl = CrawlTotals::default();
+    let mut timings = [0.0_f64; SUMMED_TIMINGS.len()];
+    let mut workers = std::collections::BTreeSet::new();
+    for row in &rows {
+        let worker: Option<String> = row.get(0);
+        let shard: std::collections::HashMap<String, String> = serde_json::from_value(
+            row.get::<_, Option<serde_json::Value>>(1)
+                .unwrap_or_default(),
+        )
+        .unwrap_or_default();
+        // each shard's metadata is relative to the root already
+        crawl.add(&shard, false);
+        for (total, key) in timings.iter_mut().zip(SUMMED_TIMINGS) {
+            *total += shard.get(key).and_then(|v| v.parse().ok()).unwrap_or(0.0);
+        }
+        workers.extend(worker);
+    }
+
+    let mut metadata = crawl.into_metadata(&options.data_root);
+    metadata.insert("hostname".to_string(), data::local_hostname());
+    metadata.insert("shard_count".to_string(), rows.len().to_string());
+    metadata.insert(
+        "shard_workers".to_string(),
+        workers.into_iter().collect::<Vec<_>>().join(","),
+    );
+    for (total, key) in timings.iter().zip(SUMMED_TIMINGS) {
+        metadata.insert(key.to_string(), total.to_string());
+    }
+    let files: f64 = metadata["total_files_processed"].parse().unwrap_or(0.0);
+    metadata.insert(
+        "load_rows_per_second".to_string(),
+        (files / timings[0].max(f64::EPSILON)).to_string(),
+    );
+    pipeline::check_min_expected_files(client, options, scan_id, None, &metadata).await?;
+    data::set_scan_metadata(client, scan_id, &metadata).await?;
+
+    pipeline::run_phase(progress, Phase::Process, async {
+        tracing::info!("🧹 Sweeping files no shard has seen...");
+        let mut params = std::collections::HashMap::new();
+        params.insert("scan_id".to_string(), scan_id.to_string());
+        db::execute_sql_template_str(
+            client,
+            pipeline::sql_template("sweep_deleted.sql"),
+            Some(params),
+        )
+        .await?;
+        // a requeued shard may have added its first batches twice
+        data::record_extension_stats_from_files(client, scan_id).await?;
+        Ok(())
+    })
+    .await?;
+
+    pipeline::finalize(client, options, scan_id, metadata, progress).await
+}
+
+/// Poll the shards of a scan until all are completed, requeueing stale ones
+async fn wait_for_shards(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    poll_interval: std::time::Duration,
+    stale_after: std::time::Duration,
+) -> anyhow::Result<()> {
+    let mut last_completed = None;
+    loop {
+        let requeued = client
+            .query(
+                "UPDATE filesystem.scan_shards
+                 SET shard_status = 'pending', worker = NULL
+                 WHERE scan_id = $1
+                   AND shard_status = 'running'
+                   AND heartbeat_at < now() - $2 * interval '1 second'
+                 RETURNING shard_index",
+                &[&scan_id, &stale_after.as_secs_f64()],
+            )
+            .await?;
+        for row in &requeued {
+            tracing::warn!(
+                "⚠️ Shard {} sent no heartbeat for {:?}, requeued it",
+                row.get::<_, i32>(0),
+                stale_after
+            );
+        }
+
+        let rows = client
+            .query(
+                "SELECT shard_index, shard_status, worker, error
+                 FROM filesystem.scan_shards
+                 WHERE scan_id = $1
+                 ORDER BY shard_index",
+                &[&scan_id],
+            )
+            .await?;
+        anyhow::ensure!(!rows.is_empty(), "Scan {} has no shards", scan_id);
+        if let Some(failed) = rows.iter().find(|r| r.get::<_, String>(1) == "failed") {
+            anyhow::bail!(
+                "Shard {} of scan {} failed on {}: {}; retry it with `shard_scan finish --scan-id {} --retry-failed`",
+                failed.get::<_, i32>(0),
+                scan_id,
+                failed.get::<_, Option<String>>(2).unwrap_or_default(),
+                failed.get::<_, Option<String>>(3).unwrap_or_default(),
+                scan_id
+            );
+        }
+        let completed = rows
+            .iter()
+            .filter(|r| r.get::<_, String>(1) == "completed")
+            .count();
+        if last_completed != Some(completed) {
+            let running = rows
+                .iter()
+                .filter(|r| r.get::<_, String>(1) == "running")
+                .count();
+            tracing::info!(
+                "🧩 Scan {}: {}/{} shards completed, {} running",
+                scan_id,
+                completed,
+                rows.len(),
+                running
+            );
+            last_completed = Some(completed);
+        }
+        if completed == rows.len() {
+            return Ok(());
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
@@ -0,0 +1,131 @@
+use crate::process::run_command;
+
+/// Tool used to produce and check detached signatures of exports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureMethod {
+    Gpg,
+    Minisign,
+}
+
+impl SignatureMethod {
+    /// File extension of the detached signature
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SignatureMethod::Gpg => "asc",
+            SignatureMethod::Minisign => "minisig",
+        }
+    }
+
+    /// Guess the method from a signature file's extension
+    pub fn from_signature_path(path: &std::path::Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "asc" | "sig" | "gpg" => Some(SignatureMethod::Gpg),
+            "minisig" => Some(SignatureMethod::Minisign),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for SignatureMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gpg" => Ok(SignatureMethod::Gpg),
+            "minisign" => Ok(SignatureMethod::Minisign),
+            other => anyhow::bail!("Unknown signature method: {}", other),
+        }
+    }
+}
+
+impl std::fmt::Display for SignatureMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureMethod::Gpg => write!(f, "gpg"),
+            SignatureMethod::Minisign => write!(f, "minisign"),
+        }
+    }
+}
+
+/// Default location of the detached signature of `file`
+pub fn signature_path(file: &std::path::Path, method: SignatureMethod) -> std::path::PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".");
+    name.push(method.extension());
+    std::path::PathBuf::from(name)
+}
+
+/// Write a detached signature of `file` next to it and return its path.
+///
+/// `key` is the GPG key ID to sign with (default key if omitted) or the path
+/// of the minisign secret key (required).
+#[tracing::instrument]
+pub async fn sign(
+    file: &std::path::Path,
+    method: SignatureMethod,
+    key: Option<&str>,
+) -> anyhow::Result<std::path::PathBuf> {
+    let signature = signature_path(file, method);
+    let mut command;
+    match method {
+        SignatureMethod::Gpg => {
+            command = tokio::process::Command::new("gpg");
+            command.args(["--batch", "--yes", "--armor", "--detach-sign"]);
+            if let Some(key) = key {
+                command.arg("--local-user").arg(key);
+            }
+            command.arg("--output").arg(&signature).arg(file);
+        }
+        SignatureMethod::Minisign => {
+            let key = key.ok_or_else(|| anyhow::anyhow!("minisign requires a secret key path"))?;
+            command = tokio::process::Command::new("minisign");
+            command
+                .arg("-S")
+                .arg("-s")
+                .arg(key)
+                .arg("-m")
+                .arg(file)
+                .arg("-x")
+                .arg(&signature);
+        }
+    }
+    run_command(command).await?;
+    Ok(signature)
+}
+
+/// Check the detached `signature` of `file`, failing if it does not verify.
+///
+/// `public_key` is the path of the minisign public key (required); GPG uses
+/// the keys already in the caller's keyring.
+#[tracing::instrument]
+pub async fn verify(
+    file: &std::path::Path,
+    signature: &std::path::Path,
+    method: SignatureMethod,
+    public_key: Option<&std::path::Path>,
+) -> anyhow::Result<()> {
+    let mut command;
+    match method {
+        SignatureMethod::Gpg => {
+            command = tokio::process::Command::new("gpg");
+            command
+                .args(["--batch", "--verify"])
+                .arg(signature)
+                .arg(file);
+        }
+        SignatureMethod::Minisign => {
+            let public_key =
+                public_key.ok_or_else(|| anyhow::anyhow!("minisign requires a public key path"))?;
+            command = tokio::process::Command::new("minisign");
+            command
+                .arg("-V")
+                .arg("-p")
+                .arg(public_key)
+                .arg("-m")
+                .arg(file)
+                .arg("-x")
+                .arg(signature);
+        }
+    }
+    run_command(command).await
+}
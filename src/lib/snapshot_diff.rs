@@ -0,0 +1,440 @@
+use std::io::Write as _;
+
+use crate::process::command_output;
+
+/// Filesystem change tracking a scan can use instead of walking the whole
+/// root
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotDiff {
+    /// `zfs diff` between a snapshot taken by the previous scan and one taken
+    /// by this scan
+    Zfs,
+    /// `btrfs subvolume find-new` since the generation recorded by the
+    /// previous scan
+    Btrfs,
+    /// The NTFS change journal since the USN recorded by the previous scan,
+    /// see [`crate::usn_journal`]
+    Usn,
+    /// The changes logged by `fanotify_watch` since the offset recorded by
+    /// the previous scan, see [`crate::fanotify`]
+    Fanotify,
+    /// The directories the macOS FSEvents log reports changes in since the
+    /// event ID recorded by the previous scan, see [`crate::fsevents`]
+    Fsevents,
+}
+
+impl std::str::FromStr for SnapshotDiff {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zfs" => Ok(SnapshotDiff::Zfs),
+            "btrfs" => Ok(SnapshotDiff::Btrfs),
+            "usn" => Ok(SnapshotDiff::Usn),
+            "fanotify" => Ok(SnapshotDiff::Fanotify),
+            "fsevents" => Ok(SnapshotDiff::Fsevents),
+            other => anyhow::bail!("Unknown snapshot diff source: {}", other),
+        }
+    }
+}
+
+impl std::fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotDiff::Zfs => write!(f, "zfs"),
+            SnapshotDiff::Btrfs => write!(f, "btrfs"),
+            SnapshotDiff::Usn => write!(f, "usn"),
+            SnapshotDiff::Fanotify => write!(f, "fanotify"),
+            SnapshotDiff::Fsevents => write!(f, "fsevents"),
+        }
+    }
+}
+
+/// Prefix of the names of the snapshots taken by scans
+const ZFS_SNAPSHOT_PREFIX: &str = "fs-delta-tracker-";
+
+impl SnapshotDiff {
+    /// Scan metadata key holding the marker the next scan diffs against
+    pub fn metadata_key(&self) -> &'static str {
+        match self {
+            SnapshotDiff::Zfs => "zfs_snapshot",
+            SnapshotDiff::Btrfs => "btrfs_transid",
+            SnapshotDiff::Usn => "usn_journal",
+            SnapshotDiff::Fanotify => "fanotify_log",
+            SnapshotDiff::Fsevents => "fsevents_id",
+        }
+    }
+
+    /// Record the current state of `data_root` for the next scan to diff
+    /// against: a ZFS snapshot named after `scan_id`, the Btrfs generation,
+    /// the next USN of the NTFS journal, the end of the fanotify change log
+    /// in `lock_dir` or the current FSEvents event ID. Fails if `data_root` is not on such a filesystem, or no
+    /// watcher is running for it.
+    #[tracing::instrument]
+    pub async fn mark(
+        &self,
+        data_root: &std::path::Path,
+        scan_id: i32,
+        lock_dir: &std::path::Path,
+    ) -> anyhow::Result<String> {
+        match self {
+            SnapshotDiff::Zfs => {
+                let mut command = tokio::process::Command::new("zfs");
+                command.args(["list", "-H", "-o", "name"]).arg(data_root);
+                let dataset = String::from_utf8_lossy(&command_output(command).await?)
+                    .trim()
+                    .to_string();
+
+                // changes in child datasets do not show in their parent's diff
+                let mut command = tokio::process::Command::new("zfs");
+                command.args([
+                    "list",
+                    "-H",
+                    "-r",
+                    "-t",
+                    "filesystem",
+                    "-o",
+                    "name",
+                    &dataset,
+                ]);
+                let datasets = String::from_utf8_lossy(&command_output(command).await?)
+                    .lines()
+                    .count();
+                anyhow::ensure!(
+                    datasets == 1,
+                    "ZFS dataset {} of {} has child datasets, whose changes its diff would miss",
+                    dataset,
+                    data_root.display()
+                );
+
+                let snapshot = format!("{}@{}{}", dataset, ZFS_SNAPSHOT_PREFIX, scan_id);
+                let mut command = tokio::process::Command::new("zfs");
+                command.args(["snapshot", &snapshot]);
+                command_output(command).await?;
+                Ok(snapshot)
+            }
+            SnapshotDiff::Btrfs => {
+                // find-new lists paths relative to the subvolume
+                let mut command = tokio::process::Command::new("btrfs");
+                command.args(["subvolume", "show"]).arg(data_root);
+                command_output(command).await.map_err(|e| {
+                    anyhow::anyhow!("{} is not a Btrfs subvolume: {}", data_root.display(), e)
+                })?;
+
+                // no file is newer than the largest generation, so only the
+                // marker line is printed
+                let mut command = tokio::process::Command::new("btrfs");
+                command
+                    .args(["subvolume", "find-new"])
+                    .arg(data_root)
+                    .arg(u64::MAX.to_string());
+                let output = String::from_utf8_lossy(&command_output(command).await?).to_string();
+                output
+                    .lines()
+                    .find_map(|line| line.strip_prefix("transid marker was "))
+                    .map(|transid| transid.trim().to_string())
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "btrfs subvolume find-new printed no transid marker for {}",
+                            data_root.display()
+                        )
+                    })
+            }
+            SnapshotDiff::Usn => crate::usn_journal::mark(data_root).await,
+            SnapshotDiff::Fanotify => crate::fanotify::mark(lock_dir, data_root),
+            SnapshotDiff::Fsevents => crate::fsevents::mark(data_root),
+        }
+    }
+
+    /// Drop a marker that no scan will diff against anymore (a snapshot
+    /// taken by a scan; generations, USNs, log offsets and event IDs need no
+    /// cleanup)
+    #[tracing::instrument]
+    pub async fn release(&self, marker: &str) -> anyhow::Result<()> {
+        match self {
+            SnapshotDiff::Zfs => {
+                // never destroy a snapshot this tool did not take
+                let ours = marker
+                    .split_once('@')
+                    .is_some_and(|(_, name)| name.starts_with(ZFS_SNAPSHOT_PREFIX));
+                anyhow::ensure!(ours, "{} is not a snapshot taken by a scan", marker);
+                let mut command = tokio::process::Command::new("zfs");
+                command.args(["destroy", marker]);
+                command_output(command).await?;
+                Ok(())
+            }
+            SnapshotDiff::Btrfs
+            | SnapshotDiff::Usn
+            | SnapshotDiff::Fanotify
+            | SnapshotDiff::Fsevents => Ok(()),
+        }
+    }
+
+    /// The entries of `data_root` changed between the `previous` and
+    /// `current` markers
+    #[tracing::instrument]
+    pub async fn diff(
+        &self,
+        data_root: &std::path::Path,
+        previous: &str,
+        current: &str,
+        lock_dir: &std::path::Path,
+    ) -> anyhow::Result<DiffEntries> {
+        let mut entries = DiffEntries::default();
+        match self {
+            SnapshotDiff::Zfs => {
+                let mut command = tokio::process::Command::new("zfs");
+                command.args(["diff", "-F", "-H", previous, current]);
+                let output = command_output(command).await?;
+                for line in String::from_utf8_lossy(&output).lines() {
+                    entries.add_zfs_line(data_root, line)?;
+                }
+            }
+            SnapshotDiff::Btrfs => {
+                let mut command = tokio::process::Command::new("btrfs");
+                command
+                    .args(["subvolume", "find-new"])
+                    .arg(data_root)
+                    .arg(previous);
+                let output = command_output(command).await?;
+                for line in String::from_utf8_lossy(&output).lines() {
+                    entries.add_btrfs_line(data_root, line);
+                }
+            }
+            SnapshotDiff::Usn => {
+                return crate::usn_journal::diff(data_root, previous, current).await;
+            }
+            SnapshotDiff::Fanotify => {
+                return crate::fanotify::diff(lock_dir, data_root, previous, current);
+            }
+            SnapshotDiff::Fsevents => {
+                let data_root = data_root.to_path_buf();
+                let (previous, current) = (previous.to_string(), current.to_string());
+                // replaying the log runs a CoreFoundation run loop
+                return tokio::task::spawn_blocking(move || {
+                    crate::fsevents::diff(&data_root, &previous, &current)
+                })
+                .await?;
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Entries of a root touched between two markers, to be restated from disk
+#[derive(Debug, Clone, Default)]
+pub struct DiffEntries {
+    /// Non-directory entries created, modified, removed or renamed (both names)
+    pub paths: std::collections::BTreeSet<std::path::PathBuf>,
+    /// Directories created or renamed into place, whose whole subtree is new
+    pub new_dirs: std::collections::BTreeSet<std::path::PathBuf>,
+    /// Directories removed or renamed away, whose recorded files are gone
+    pub old_dirs: std::collections::BTreeSet<std::path::PathBuf>,
+    /// Directories whose entries changed, unnamed; see [`expand_rescan_dirs`]
+    pub rescan_dirs: std::collections::BTreeSet<std::path::PathBuf>,
+    /// Lines of the diff
+    pub lines: u64,
+}
+
+/// Decode the `\NNNN` octal escapes `zfs diff` writes for spaces, backslashes
+/// and non-printable bytes of names
+fn unescape_zfs_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(digits) = path.get(i + 1..i + 5)
+            && let std::result::Result::Ok(byte) = u8::from_str_radix(digits, 8)
+        {
+            out.push(byte);
+            i += 5;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+impl DiffEntries {
+    /// Add a line of `zfs diff -F -H`: change (`-`, `+`, `M` or `R`), entry
+    /// type (`/` for directories) and path, then the new path of a rename
+    fn add_zfs_line(&mut self, data_root: &std::path::Path, line: &str) -> anyhow::Result<()> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let (change, kind, path) = match fields.as_slice() {
+            [change, kind, path, ..] => (
+                *change,
+                *kind,
+                std::path::PathBuf::from(unescape_zfs_path(path)),
+            ),
+            _ => anyhow::bail!("Unexpected zfs diff line: {}", line),
+        };
+        let new_path = fields
+            .get(3)
+            .map(|path| std::path::PathBuf::from(unescape_zfs_path(path)));
+        self.lines += 1;
+
+        let under_root = |path: &std::path::Path| path.starts_with(data_root) && path != data_root;
+        match (change, kind == "/") {
+            // a directory's own changes are listed with its entries'
+            ("M", true) => {}
+            ("+", true) if under_root(&path) => {
+                self.new_dirs.insert(path);
+            }
+            ("-", true) if under_root(&path) => {
+                self.old_dirs.insert(path);
+            }
+            ("R", true) => {
+                if under_root(&path) {
+                    self.old_dirs.insert(path);
+                }
+                if let Some(new_path) = new_path.filter(|p| under_root(p)) {
+                    self.new_dirs.insert(new_path);
+                }
+            }
+            (_, false) => {
+                if under_root(&path) {
+                    self.paths.insert(path);
+                }
+                if let Some(new_path) = new_path.filter(|p| under_root(p)) {
+                    self.paths.insert(new_path);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Add a line of `btrfs subvolume find-new`: `inode N file offset N len N
+    /// disk start N offset N gen N flags FLAGS path`, the path relative to
+    /// the subvolume
+    fn add_btrfs_line(&mut self, data_root: &std::path::Path, line: &str) {
+        if !line.starts_with("inode ") {
+            return;
+        }
+        self.lines += 1;
+        if let Some(path) = line.splitn(17, ' ').nth(16) {
+            self.paths.insert(data_root.join(path));
+        }
+    }
+}
+
+/// Turn the `rescan_dirs` of `entries` into the entries to restate, by
+/// comparing each directory's entries on disk with those recorded: its files
+/// and recorded files are restated, its subdirectories holding no recorded
+/// file are new and its recorded subdirectories no longer on disk are gone.
+/// Subdirectories on disk and recorded are left alone, their own changes
+/// being reported separately.
+pub async fn expand_rescan_dirs(
+    client: &tokio_postgres::Client,
+    data_root: &std::path::Path,
+    entries: &mut DiffEntries,
+    path_cipher: Option<&crate::path_cipher::PathCipher>,
+) -> anyhow::Result<()> {
+    let record = |path: &std::path::Path| match path_cipher {
+        Some(cipher) => cipher.encrypt_path(data_root, path),
+        None => path.to_string_lossy().to_string(),
+    };
+    let disk_path = |recorded: &str| {
+        std::path::PathBuf::from(match path_cipher {
+            Some(cipher) => cipher.decrypt_path(recorded),
+            None => recorded.to_string(),
+        })
+    };
+
+    for dir in std::mem::take(&mut entries.rescan_dirs) {
+        let (files, subdirs) = crate::data::get_recorded_children(client, &record(&dir)).await?;
+        entries
+            .paths
+            .extend(files.iter().map(|file| disk_path(file)));
+        let recorded: std::collections::HashSet<std::path::PathBuf> =
+            subdirs.iter().map(|subdir| disk_path(subdir)).collect();
+
+        let mut on_disk = std::collections::HashSet::new();
+        if let std::result::Result::Ok(read_dir) = std::fs::read_dir(&dir) {
+            for entry in read_dir.flatten() {
+                let path = entry.path();
+                // symlinks are not followed, as in a crawl
+                match entry.file_type() {
+                    std::result::Result::Ok(ft) if ft.is_dir() => {
+                        if !recorded.contains(&path) {
+                            entries.new_dirs.insert(path.clone());
+                        }
+                        on_disk.insert(path);
+                    }
+                    _ => {
+                        entries.paths.insert(path);
+                    }
+                }
+            }
+        }
+        for subdir in recorded {
+            if !on_disk.contains(&subdir) {
+                entries.old_dirs.insert(subdir);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Outcome of [`restate`]
+#[derive(Debug, Clone, Default)]
+pub struct Restated {
+    /// Regular files written to the TSV file
+    pub files: u64,
+    /// Recorded paths of entries that are no longer regular files
+    pub removed_paths: Vec<String>,
+    /// Recorded paths of directories whose files are gone, unless restated
+    pub removed_dirs: Vec<String>,
+}
+
+/// Write the crawl TSV lines of the regular files among `entries` (and in
+/// their new directories) to `output_tsv_file`, as the crawler would, and
+/// collect the recorded paths of those that are gone
+pub fn restate(
+    data_root: &std::path::Path,
+    entries: &DiffEntries,
+    scan_id: i32,
+    output_tsv_file: &std::path::Path,
+    options: &crate::crawler::CrawlOptions,
+) -> anyhow::Result<Restated> {
+    let mut paths = entries.paths.clone();
+    for dir in &entries.new_dirs {
+        // symlinks are not followed, as in a crawl
+        for entry in ignore::WalkBuilder::new(dir)
+            .standard_filters(false)
+            .build()
+            .flatten()
+        {
+            if entry.file_type().is_some_and(|ft| ft.is_file()) {
+                paths.insert(entry.into_path());
+            }
+        }
+    }
+
+    let record = |path: &std::path::Path| match &options.path_cipher {
+        Some(cipher) => cipher.encrypt_path(data_root, path),
+        None => path.to_string_lossy().to_string(),
+    };
+    let hash_policy = options.hash_policy(data_root);
+    let mut out = std::io::BufWriter::new(std::fs::File::create(output_tsv_file)?);
+    let mut restated = Restated::default();
+    for path in &paths {
+        match std::fs::symlink_metadata(path) {
+            std::result::Result::Ok(meta) if meta.is_file() => {
+                out.write_all(
+                    crate::crawler::tsv_line(path, &meta, scan_id, data_root, options, hash_policy)
+                        .as_bytes(),
+                )?;
+                restated.files += 1;
+            }
+            _ => restated.removed_paths.push(record(path)),
+        }
+    }
+    out.flush()?;
+
+    // a directory renamed away and back is restated by the walk above
+    restated.removed_dirs = entries.old_dirs.iter().map(|dir| record(dir)).collect();
+    Ok(restated)
+}
@@ -0,0 +1,294 @@
+use crate::db::{Backend, DeltaStore};
+use crate::pipeline::sql_template;
+use rusqlite::OptionalExtension as _;
+
+/// Staged rows between the progress lines of a load
+const LOAD_LOG_EVERY_ROWS: u64 = 1_000_000;
+
+/// A SQLite file holding the tables of a plain scan (see
+/// `templates/sql/sqlite/init_db.sql`), created on first use. A single
+/// connection, used from blocking threads.
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    path: std::path::PathBuf,
+    connection: std::sync::Arc<std::sync::Mutex<rusqlite::Connection>>,
+}
+
+impl SqliteStore {
+    /// Open the SQLite file at `path`, creating and initializing it as needed
+    pub fn open(path: &std::path::Path) -> anyhow::Result<Self> {
+        let connection = rusqlite::Connection::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+        // another scan of the same file waits instead of failing
+        connection.busy_timeout(std::time::Duration::from_secs(60))?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+        connection.execute_batch(sql_template("sqlite/init_db.sql"))?;
+        tracing::info!("🗄️ Opened SQLite store {}", path.display());
+        Ok(SqliteStore {
+            path: path.to_path_buf(),
+            connection: std::sync::Arc::new(std::sync::Mutex::new(connection)),
+        })
+    }
+
+    /// The SQLite file, for logs
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Run `f` on the connection on a blocking thread
+    async fn with_connection<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut rusqlite::Connection) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection
+                .lock()
+                .map_err(|_| anyhow::anyhow!("SQLite connection poisoned by a panic"))?;
+            f(&mut connection)
+        })
+        .await?
+    }
+}
+
+/// Now, in the RFC 3339 form timestamps are stored in
+fn now() -> String {
+    chrono::Utc::now().to_rfc3339()
+}
+
+/// Insert the lines of crawl TSV `tsv_file` into staging_files, in one
+/// transaction
+fn stage_tsv_file(
+    connection: &mut rusqlite::Connection,
+    tsv_file: &std::path::Path,
+) -> anyhow::Result<u64> {
+    use std::io::BufRead;
+
+    let reader = std::io::BufReader::new(std::fs::File::open(tsv_file)?);
+    let transaction = connection.transaction()?;
+    let mut rows = 0;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT INTO staging_files (file_name, file_type, file_path, file_size_bytes, \
+            file_mtime, security_label, file_fingerprint, scan_id) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )?;
+        for line in reader.lines() {
+            let line = line?;
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [
+                name,
+                file_type,
+                path,
+                size,
+                mtime,
+                label,
+                fingerprint,
+                // always `\N`: symlinks are not recorded into SQLite
+                _symlink_target,
+                // ownership is only tracked in PostgreSQL
+                _mode,
+                _uid,
+                _gid,
+                scan_id,
+            ] = fields.as_slice()
+            else {
+                anyhow::bail!("Malformed crawl line in {}: {:?}", tsv_file.display(), line);
+            };
+            insert.execute(rusqlite::params![
+                crate::tsv::field(name),
+                crate::tsv::field(file_type),
+                crate::tsv::field(path),
+                size.parse::<i64>()?,
+                mtime,
+                crate::tsv::field(label),
+                crate::tsv::field(fingerprint),
+                scan_id.parse::<i32>()?,
+            ])?;
+            rows += 1;
+            if rows % LOAD_LOG_EVERY_ROWS == 0 {
+                tracing::info!("📥 Staged {} rows", rows);
+            }
+        }
+    }
+    transaction.commit()?;
+    Ok(rows)
+}
+
+impl DeltaStore for SqliteStore {
+    fn backend(&self) -> Backend {
+        Backend::Sqlite
+    }
+
+    async fn start_scan(
+        &self,
+        scan_root: &std::path::Path,
+        started_at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<i32> {
+        tracing::info!(
+            "Starting scan for root: {} at {}",
+            scan_root.display(),
+            started_at
+        );
+        let scan_root = scan_root.to_string_lossy().into_owned();
+        let scan_id = self
+            .with_connection(move |connection| {
+                connection.execute(
+                    "INSERT INTO scan_runs (scan_root, hostname, started_at) VALUES (?1, ?2, ?3)",
+                    rusqlite::params![
+                        scan_root,
+                        crate::data::local_hostname(),
+                        started_at.to_rfc3339()
+                    ],
+                )?;
+                Ok(i32::try_from(connection.last_insert_rowid())?)
+            })
+            .await?;
+        tracing::info!("Scan started with ID: {}", scan_id);
+        Ok(scan_id)
+    }
+
+    async fn load_crawl(
+        &self,
+        _scan_id: i32,
+        tsv_file: &std::path::Path,
+        _progress: &crate::progress::ProgressReporter,
+    ) -> anyhow::Result<u64> {
+        let tsv_file = tsv_file.to_path_buf();
+        self.with_connection(move |connection| stage_tsv_file(connection, &tsv_file))
+            .await
+    }
+
+    async fn apply_deltas(&self, scan_id: i32) -> anyhow::Result<()> {
+        let sql =
+            sql_template("sqlite/process_staging.sql").replace(":scan_id", &scan_id.to_string());
+        self.with_connection(move |connection| {
+            connection.execute_batch(&sql).inspect_err(|_| {
+                // leave the connection usable after a failed statement
+                connection.execute_batch("ROLLBACK").ok();
+            })?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn finalize_scan(
+        &self,
+        scan_id: i32,
+        mut metadata: std::collections::HashMap<String, String>,
+    ) -> anyhow::Result<()> {
+        let completed_at = now();
+        let counts = self
+            .with_connection(move |connection| {
+                let mut query = connection.prepare(
+                    "SELECT change_type, COUNT(*), \
+                    COALESCE(SUM(ABS(COALESCE(new_size_bytes, 0) - COALESCE(old_size_bytes, 0))), 0) \
+                    FROM file_changes WHERE scan_id = ?1 GROUP BY change_type",
+                )?;
+                let counts = query
+                    .query_map([scan_id], |row| {
+                        Ok((row.get::<_, String>(0)?, (row.get::<_, i64>(1)?, row.get::<_, i64>(2)?)))
+                    })?
+                    .collect::<rusqlite::Result<std::collections::HashMap<_, _>>>()?;
+                Ok(counts)
+            })
+            .await?;
+        let count = |change_type: &str| counts.get(change_type).map_or(0, |(count, _)| *count);
+        let data_mb = |change_type: &str| {
+            counts
+                .get(change_type)
+                .map_or(0.0, |(_, bytes)| *bytes as f64 / 1024.0 / 1024.0)
+        };
+        if count("relabeled") > 0 {
+            metadata.insert(
+                "relabeled_files_count".to_string(),
+                count("relabeled").to_string(),
+            );
+        }
+        let total_paths = metadata
+            .get("total_files_processed")
+            .and_then(|total| total.parse::<i64>().ok())
+            .unwrap_or(0);
+        let metadata_json = serde_json::to_string(&metadata)?;
+        let (added, modified, deleted) = (count("added"), count("modified"), count("deleted"));
+        let (added_mb, modified_mb, deleted_mb) =
+            (data_mb("added"), data_mb("modified"), data_mb("deleted"));
+        let finished_at = completed_at.clone();
+        self.with_connection(move |connection| {
+            connection.execute(
+                "UPDATE scan_runs \
+                SET finished_at = ?1, total_paths_count = ?2, added_files_count = ?3, \
+                modified_files_count = ?4, removed_files_count = ?5, new_data_mb = ?6, \
+                modified_data_mb = ?7, deleted_data_mb = ?8, scan_metadata = ?9, \
+                scan_status = 'completed' \
+                WHERE scan_id = ?10",
+                rusqlite::params![
+                    finished_at,
+                    total_paths,
+                    added,
+                    modified,
+                    deleted,
+                    added_mb,
+                    modified_mb,
+                    deleted_mb,
+                    metadata_json,
+                    scan_id
+                ],
+            )?;
+            Ok(())
+        })
+        .await?;
+
+        metadata.insert("scan_id".to_string(), scan_id.to_string());
+        metadata.insert("completed_at".to_string(), completed_at);
+        metadata.insert("added_files_count".to_string(), added.to_string());
+        metadata.insert("modified_files_count".to_string(), modified.to_string());
+        metadata.insert("removed_files_count".to_string(), deleted.to_string());
+        tracing::info!("📊 Scan metadata:\n{:#?}", metadata);
+        Ok(())
+    }
+
+    async fn abort_scan(&self, scan_id: i32, reason: serde_json::Value) -> anyhow::Result<()> {
+        let reason = reason.to_string();
+        self.with_connection(move |connection| {
+            connection.execute(
+                "UPDATE scan_runs \
+                SET finished_at = ?1, scan_status = 'aborted', \
+                anomaly_flags = json_patch(COALESCE(anomaly_flags, '{}'), ?2) \
+                WHERE scan_id = ?3",
+                rusqlite::params![now(), reason, scan_id],
+            )?;
+            connection.execute("DELETE FROM staging_files WHERE scan_id = ?1", [scan_id])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn scan_summary(&self, scan_id: i32) -> anyhow::Result<serde_json::Value> {
+        let summary = self
+            .with_connection(move |connection| {
+                let summary: Option<String> = connection
+                    .query_row(
+                        "SELECT json_object(\
+                        'scan_id', scan_id, 'scan_root', scan_root, 'hostname', hostname, \
+                        'started_at', started_at, 'finished_at', finished_at, \
+                        'total_paths_count', total_paths_count, \
+                        'added_files_count', added_files_count, \
+                        'modified_files_count', modified_files_count, \
+                        'removed_files_count', removed_files_count, \
+                        'new_data_mb', new_data_mb, 'modified_data_mb', modified_data_mb, \
+                        'deleted_data_mb', deleted_data_mb, 'scan_status', scan_status, \
+                        'anomaly_flags', json(anomaly_flags), \
+                        'scan_metadata', json(scan_metadata)) \
+                        FROM scan_runs WHERE scan_id = ?1",
+                        [scan_id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                Ok(summary)
+            })
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Scan {} not found", scan_id))?;
+        Ok(serde_json::from_str(&summary)?)
+    }
+}
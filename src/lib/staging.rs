@@ -0,0 +1,120 @@
+/// Where crawl rows are staged before processing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StagingStrategy {
+    /// The shared filesystem.staging_files as a regular, WAL-logged table:
+    /// survives a database crash and is replicated
+    Logged,
+    /// The shared filesystem.staging_files as an UNLOGGED table: no WAL for
+    /// staging loads, but emptied by a database crash and not replicated
+    #[default]
+    Unlogged,
+    /// A temporary table private to the scan's session: no WAL and no
+    /// contention with other scans, but gone with the connection, so it
+    /// cannot hold rows for review
+    Temporary,
+}
+
+impl std::str::FromStr for StagingStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "logged" => Ok(StagingStrategy::Logged),
+            "unlogged" => Ok(StagingStrategy::Unlogged),
+            "temporary" => Ok(StagingStrategy::Temporary),
+            other => anyhow::bail!("Unknown staging strategy: {}", other),
+        }
+    }
+}
+
+impl std::fmt::Display for StagingStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StagingStrategy::Logged => write!(f, "logged"),
+            StagingStrategy::Unlogged => write!(f, "unlogged"),
+            StagingStrategy::Temporary => write!(f, "temporary"),
+        }
+    }
+}
+
+impl StagingStrategy {
+    /// Table the crawl rows are loaded into and processed from
+    pub fn table(&self) -> &'static str {
+        match self {
+            StagingStrategy::Logged | StagingStrategy::Unlogged => "filesystem.staging_files",
+            StagingStrategy::Temporary => "pg_temp.staging_files",
+        }
+    }
+
+    /// Set the persistence of the shared staging table, as done by
+    /// `initialize_db`. A temporary strategy keeps it unlogged, since it then
+    /// only serves as the template of the per-scan tables and for review.
+    #[tracing::instrument(skip(client))]
+    pub async fn configure(&self, client: &tokio_postgres::Client) -> anyhow::Result<()> {
+        let persistence = match self {
+            StagingStrategy::Logged => "LOGGED",
+            StagingStrategy::Unlogged | StagingStrategy::Temporary => "UNLOGGED",
+        };
+        client
+            .batch_execute(&format!(
+                "ALTER TABLE filesystem.staging_files SET {}",
+                persistence
+            ))
+            .await?;
+        Ok(())
+    }
+
+    /// Make the staging table ready on this connection: create the
+    /// session's temporary table, or warn if the shared one does not have
+    /// the requested persistence (changing it rewrites the table and blocks
+    /// other scans, so that is left to an operator).
+    #[tracing::instrument(skip(client))]
+    pub async fn prepare(&self, client: &tokio_postgres::Client) -> anyhow::Result<()> {
+        if *self == StagingStrategy::Temporary {
+            client
+                .batch_execute(
+                    "CREATE TEMPORARY TABLE IF NOT EXISTS staging_files
+                     (LIKE filesystem.staging_files INCLUDING ALL)",
+                )
+                .await?;
+            return Ok(());
+        }
+
+        let persistence: i8 = client
+            .query_one(
+                "SELECT relpersistence FROM pg_class
+                 WHERE oid = 'filesystem.staging_files'::regclass",
+                &[],
+            )
+            .await?
+            .get(0);
+        let actual = if persistence == b'u' as i8 {
+            StagingStrategy::Unlogged
+        } else {
+            StagingStrategy::Logged
+        };
+        if actual != *self {
+            tracing::warn!(
+                "⚠️ Staging table is {} but {} staging was requested; change it with `ALTER TABLE filesystem.staging_files SET {}` while no scan is running",
+                actual,
+                self,
+                self.to_string().to_uppercase()
+            );
+        }
+        Ok(())
+    }
+
+    /// Delete the staged rows of a scan and the checkpoint of their load
+    #[tracing::instrument(skip(client))]
+    pub async fn clear(&self, client: &tokio_postgres::Client, scan_id: i32) -> anyhow::Result<()> {
+        let query = format!("DELETE FROM {} WHERE scan_id = $1", self.table());
+        client.execute(&query, &[&scan_id]).await?;
+        client
+            .execute(
+                "DELETE FROM filesystem.load_checkpoints WHERE scan_id = $1",
+                &[&scan_id],
+            )
+            .await?;
+        Ok(())
+    }
+}
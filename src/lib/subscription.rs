@@ -0,0 +1,505 @@
+/// Change types a subscription can filter on
+pub const CHANGE_TYPES: [&str; 5] = [
+    "added",
+    "modified",
+    "deleted",
+    "relabeled",
+    "permissions_changed",
+];
+
+/// Largest NOTIFY payload PostgreSQL accepts, in bytes
+const MAX_PAYLOAD: usize = 7999;
+
+/// A downstream consumer of the changes of completed scans, sent the changes
+/// matching its filters on the channel named after it, or POSTed to its
+/// webhook
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Subscription {
+    /// Also the NOTIFY channel: lowercase letters, digits and `_`
+    pub consumer: String,
+    /// Only changes of this path or of paths below it
+    pub path_prefix: Option<String>,
+    /// Only changes of these types, see [`CHANGE_TYPES`]
+    pub change_types: Option<Vec<String>>,
+    /// POST the changes here instead of sending them with NOTIFY
+    pub webhook_url: Option<String>,
+}
+
+impl Subscription {
+    /// Check the consumer can be LISTENed to as is and the filters are known
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let valid = self.consumer.len() <= 63
+            && self
+                .consumer
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+            && self
+                .consumer
+                .bytes()
+                .next()
+                .is_some_and(|b| !b.is_ascii_digit());
+        anyhow::ensure!(
+            valid,
+            "Consumer {:?} is not a channel name: use lowercase letters, digits and _, at most 63 and not starting with a digit",
+            self.consumer
+        );
+        for change_type in self.change_types.iter().flatten() {
+            anyhow::ensure!(
+                CHANGE_TYPES.contains(&change_type.as_str()),
+                "Unknown change type {}, expected one of {}",
+                change_type,
+                CHANGE_TYPES.join(", ")
+            );
+        }
+        if let Some(url) = &self.webhook_url {
+            anyhow::ensure!(
+                url.starts_with("https://") || url.starts_with("http://"),
+                "Expected an https:// webhook URL, got {}",
+                url
+            );
+        }
+        Ok(())
+    }
+}
+
+/// How [`deliver_events`] delivers queued changes
+#[derive(Debug, Clone)]
+pub struct DeliveryOptions {
+    /// Events claimed per transaction
+    pub batch_size: i64,
+    /// Failed attempts after which an event is dead-lettered
+    pub max_attempts: i32,
+    /// Days delivered events are kept before they are deleted
+    pub retain_days: i32,
+}
+
+impl Default for DeliveryOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 500,
+            max_attempts: 10,
+            retain_days: 7,
+        }
+    }
+}
+
+/// Events handled by [`deliver_events`]
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct DeliveryStats {
+    pub delivered: u64,
+    /// failed attempts, to be retried
+    pub failed: u64,
+    pub dead_lettered: u64,
+}
+
+/// Queued events of a consumer, as [`consumer_events`] counts them
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ConsumerEvents {
+    pub consumer: String,
+    /// waiting for their scan to complete, or for their next attempt
+    pub pending: i64,
+    pub delivered: i64,
+    pub dead_lettered: i64,
+    /// error of the latest failed attempt
+    pub last_error: Option<String>,
+}
+
+/// Create or replace the subscription of a consumer
+#[tracing::instrument(skip(client))]
+pub async fn set_subscription(
+    client: &tokio_postgres::Client,
+    subscription: &Subscription,
+) -> anyhow::Result<()> {
+    subscription.validate()?;
+    let path_prefix =
+        subscription
+            .path_prefix
+            .as_deref()
+            .map(|prefix| match prefix.trim_end_matches('/') {
+                "" => "/",
+                prefix => prefix,
+            });
+    client
+        .execute(
+            "INSERT INTO filesystem.change_subscriptions
+                 (consumer, path_prefix, change_types, webhook_url)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (consumer) DO UPDATE
+             SET path_prefix = EXCLUDED.path_prefix,
+                 change_types = EXCLUDED.change_types,
+                 webhook_url = EXCLUDED.webhook_url",
+            &[
+                &subscription.consumer,
+                &path_prefix,
+                &subscription.change_types,
+                &subscription.webhook_url,
+            ],
+        )
+        .await?;
+    crate::data::audit(
+        client,
+        "subscription_set",
+        None,
+        serde_json::json!({
+            "consumer": subscription.consumer,
+            "path_prefix": path_prefix,
+            "change_types": subscription.change_types,
+            "webhook_url": subscription.webhook_url,
+        }),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Remove the subscription of a consumer and its queued events, returning
+/// whether it had one
+#[tracing::instrument(skip(client))]
+pub async fn remove_subscription(
+    client: &tokio_postgres::Client,
+    consumer: &str,
+) -> anyhow::Result<bool> {
+    let removed = client
+        .execute(
+            "DELETE FROM filesystem.change_subscriptions WHERE consumer = $1",
+            &[&consumer],
+        )
+        .await?;
+    if removed > 0 {
+        crate::data::audit(
+            client,
+            "subscription_removed",
+            None,
+            serde_json::json!({ "consumer": consumer }),
+        )
+        .await?;
+    }
+    Ok(removed > 0)
+}
+
+/// All subscriptions, by consumer
+#[tracing::instrument(skip(client))]
+pub async fn list_subscriptions(
+    client: &tokio_postgres::Client,
+) -> anyhow::Result<Vec<Subscription>> {
+    let rows = client
+        .query(
+            "SELECT consumer, path_prefix, change_types, webhook_url
+             FROM filesystem.change_subscriptions
+             ORDER BY consumer",
+            &[],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| Subscription {
+            consumer: row.get(0),
+            path_prefix: row.get(1),
+            change_types: row.get(2),
+            webhook_url: row.get(3),
+        })
+        .collect())
+}
+
+/// Queued events of each consumer, by consumer
+#[tracing::instrument(skip(client))]
+pub async fn consumer_events(
+    client: &tokio_postgres::Client,
+) -> anyhow::Result<Vec<ConsumerEvents>> {
+    let rows = client
+        .query(
+            "SELECT s.consumer,
+                    count(o.event_id) FILTER (WHERE o.delivered_at IS NULL
+                                              AND o.dead_lettered_at IS NULL),
+                    count(o.delivered_at),
+                    count(o.dead_lettered_at),
+                    (SELECT last_error
+                     FROM filesystem.event_outbox
+                     WHERE consumer = s.consumer AND last_error IS NOT NULL
+                     ORDER BY event_id DESC
+                     LIMIT 1)
+             FROM filesystem.change_subscriptions AS s
+             LEFT JOIN filesystem.event_outbox AS o USING (consumer)
+             GROUP BY s.consumer
+             ORDER BY s.consumer",
+            &[],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| ConsumerEvents {
+            consumer: row.get(0),
+            pending: row.get(1),
+            delivered: row.get(2),
+            dead_lettered: row.get(3),
+            last_error: row.get(4),
+        })
+        .collect())
+}
+
+/// Queue the dead-lettered events of `consumer` for delivery again,
+/// returning how many
+#[tracing::instrument(skip(client))]
+pub async fn requeue_dead_letters(
+    client: &tokio_postgres::Client,
+    consumer: &str,
+) -> anyhow::Result<u64> {
+    let requeued = client
+        .execute(
+            "UPDATE filesystem.event_outbox
+             SET dead_lettered_at = NULL, attempts = 0, next_attempt_at = now()
+             WHERE consumer = $1 AND dead_lettered_at IS NOT NULL",
+            &[&consumer],
+        )
+        .await?;
+    if requeued > 0 {
+        crate::data::audit(
+            client,
+            "events_requeued",
+            None,
+            serde_json::json!({ "consumer": consumer, "events": requeued }),
+        )
+        .await?;
+    }
+    Ok(requeued)
+}
+
+/// Deliver the queued events of completed scans that are due, oldest first,
+/// until none is left, and delete delivered events past their retention.
+///
+/// Each batch is claimed, delivered and marked in one transaction, skipping
+/// events other workers hold. A NOTIFY is only sent when that transaction
+/// commits, so it goes out exactly once; a webhook batch is POSTed again if
+/// the worker dies before marking it, so webhooks should drop `event_id`s
+/// they have already seen. A failed batch is retried after a backoff
+/// doubling from 30 seconds up to an hour, and dead-lettered after
+/// `max_attempts`.
+#[tracing::instrument(skip(client, options))]
+pub async fn deliver_events(
+    client: &tokio_postgres::Client,
+    options: &DeliveryOptions,
+) -> anyhow::Result<DeliveryStats> {
+    let http = reqwest::Client::builder()
+        .connect_timeout(std::time::Duration::from_secs(10))
+        .timeout(std::time::Duration::from_secs(60))
+        .build()?;
+    let mut stats = DeliveryStats::default();
+    loop {
+        client.batch_execute("BEGIN").await?;
+        let result = deliver_batch(client, &http, options, &mut stats).await;
+        let end = match &result {
+            Ok(_) => client.batch_execute("COMMIT").await,
+            Err(_) => client.batch_execute("ROLLBACK").await,
+        };
+        let claimed = result?;
+        end?;
+        if claimed < options.batch_size.max(1) as usize {
+            break;
+        }
+    }
+    client
+        .execute(
+            "DELETE FROM filesystem.event_outbox
+             WHERE delivered_at < now() - make_interval(days => $1)",
+            &[&options.retain_days],
+        )
+        .await?;
+    Ok(stats)
+}
+
+/// Claim, deliver and mark a batch of events, returning how many were claimed
+async fn deliver_batch(
+    client: &tokio_postgres::Client,
+    http: &reqwest::Client,
+    options: &DeliveryOptions,
+    stats: &mut DeliveryStats,
+) -> anyhow::Result<usize> {
+    let rows = client
+        .query(
+            "SELECT o.event_id, o.consumer, s.webhook_url,
+                    (o.payload || jsonb_build_object('event_id', o.event_id))::text
+             FROM filesystem.event_outbox AS o
+             JOIN filesystem.change_subscriptions AS s USING (consumer)
+             JOIN filesystem.scan_runs AS r USING (scan_id)
+             WHERE o.delivered_at IS NULL
+               AND o.dead_lettered_at IS NULL
+               AND o.next_attempt_at <= now()
+               AND r.scan_status = 'completed'
+             ORDER BY o.event_id
+             LIMIT $1
+             FOR UPDATE OF o SKIP LOCKED",
+            &[&options.batch_size.max(1)],
+        )
+        .await?;
+
+    // by consumer, each in the order the events were queued
+    let mut batches = std::collections::BTreeMap::<_, Vec<(i64, String)>>::new();
+    for row in &rows {
+        batches
+            .entry((row.get::<_, String>(1), row.get::<_, Option<String>>(2)))
+            .or_default()
+            .push((row.get(0), row.get(3)));
+    }
+    for ((consumer, webhook_url), events) in batches {
+        let (sendable, oversized): (Vec<_>, Vec<_>) = match webhook_url {
+            Some(_) => (events, Vec::new()),
+            None => events
+                .into_iter()
+                .partition(|(_, payload)| payload.len() <= MAX_PAYLOAD),
+        };
+        if !oversized.is_empty() {
+            tracing::warn!(
+                "⚠️ Dead-lettering {} events of {} too long for NOTIFY",
+                oversized.len(),
+                consumer
+            );
+            let ids: Vec<i64> = oversized.iter().map(|(id, _)| *id).collect();
+            client
+                .execute(
+                    "UPDATE filesystem.event_outbox
+                     SET dead_lettered_at = now(), last_error = 'too long for NOTIFY'
+                     WHERE event_id = ANY($1)",
+                    &[&ids],
+                )
+                .await?;
+            stats.dead_lettered += ids.len() as u64;
+        }
+        if sendable.is_empty() {
+            continue;
+        }
+
+        let ids: Vec<i64> = sendable.iter().map(|(id, _)| *id).collect();
+        let payloads: Vec<String> = sendable.into_iter().map(|(_, payload)| payload).collect();
+        let sent = match &webhook_url {
+            // sent when the transaction commits, with the events marked
+            None => client
+                .query(
+                    "SELECT pg_notify($1, payload)
+                     FROM unnest($2::text[]) WITH ORDINALITY AS p(payload, n)
+                     ORDER BY n",
+                    &[&consumer, &payloads],
+                )
+                .await
+                .map(|_| ())
+                .map_err(anyhow::Error::from),
+            Some(url) => post_events(http, url, &consumer, &payloads).await,
+        };
+        match sent {
+            Ok(()) => {
+                client
+                    .execute(
+                        "UPDATE filesystem.event_outbox
+                         SET delivered_at = now(), attempts = attempts + 1, last_error = NULL
+                         WHERE event_id = ANY($1)",
+                        &[&ids],
+                    )
+                    .await?;
+                stats.delivered += ids.len() as u64;
+            }
+            Err(e) => {
+                let error = format!("{:#}", e);
+                tracing::warn!(
+                    "⚠️ Failed to deliver {} events to {}: {}",
+                    ids.len(),
+                    consumer,
+                    error
+                );
+                let dead = client
+                    .query(
+                        "UPDATE filesystem.event_outbox
+                         SET attempts = attempts + 1,
+                             last_error = $2,
+                             next_attempt_at = now() + least(
+                                 interval '30 seconds' * power(2, attempts),
+                                 interval '1 hour'
+                             ),
+                             dead_lettered_at = CASE
+                                 WHEN attempts + 1 >= $3 THEN now()
+                             END
+                         WHERE event_id = ANY($1)
+                         RETURNING dead_lettered_at IS NOT NULL",
+                        &[&ids, &error, &options.max_attempts],
+                    )
+                    .await?
+                    .iter()
+                    .filter(|row| row.get::<_, bool>(0))
+                    .count() as u64;
+                stats.failed += ids.len() as u64 - dead;
+                stats.dead_lettered += dead;
+            }
+        }
+    }
+    Ok(rows.len())
+}
+
+/// POST `payloads`, JSON objects, to the webhook of `consumer`
+async fn post_events(
+    http: &reqwest::Client,
+    url: &str,
+    consumer: &str,
+    payloads: &[String],
+) -> anyhow::Result<()> {
+    let body = format!(
+        "{{\"consumer\":{},\"events\":[{}]}}",
+        serde_json::to_string(consumer)?,
+        payloads.join(",")
+    );
+    let response = http
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!(
+            "{} answered {}: {}",
+            url,
+            status,
+            body.chars().take(300).collect::<String>()
+        );
+    }
+    Ok(())
+}
+
+/// Listen on the channel of `consumer` over a connection of its own,
+/// returning the payloads of its notifications as they arrive. The receiver
+/// closes when the connection fails; the connection closes at the first
+/// notification after the receiver is dropped.
+pub async fn listen(
+    database_url: &str,
+    consumer: &str,
+) -> anyhow::Result<tokio::sync::mpsc::UnboundedReceiver<String>> {
+    use futures::StreamExt;
+
+    let (client, mut connection) =
+        tokio_postgres::connect(database_url, tokio_postgres::NoTls).await?;
+    let (sender, payloads) = tokio::sync::mpsc::unbounded_channel();
+    let (hand_over, client_slot) = tokio::sync::oneshot::channel();
+    // notifications arrive as messages of the connection
+    tokio::spawn(async move {
+        // the client once listening: the connection closes with it
+        let _client = client_slot;
+        let mut messages = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(tokio_postgres::AsyncMessage::Notification(notification)) => {
+                    if sender.send(notification.payload().to_string()).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!("❌ Database connection failed: {}", e);
+                    return;
+                }
+            }
+        }
+    });
+    // validated as a plain identifier when the subscription was set
+    client
+        .batch_execute(&format!("LISTEN {}", consumer))
+        .await?;
+    let _ = hand_over.send(client);
+    Ok(payloads)
+}
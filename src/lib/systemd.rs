@@ -0,0 +1,85 @@
+use sd_notify::NotifyState;
+
+use crate::progress::{ProgressEvent, ProgressReporter};
+
+/// Send `states` to systemd; a no-op unless the process runs under a
+/// `Type=notify` unit (i.e. `NOTIFY_SOCKET` is set)
+fn notify(states: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, states) {
+        tracing::debug!("sd_notify failed: {}", e);
+    }
+}
+
+/// Tell systemd start-up is complete
+pub fn notify_ready(status: &str) {
+    notify(&[NotifyState::Ready, NotifyState::Status(status)]);
+}
+
+/// Update the status line shown by `systemctl status`
+pub fn notify_status(status: &str) {
+    notify(&[NotifyState::Status(status)]);
+}
+
+/// Tell systemd the service is shutting down
+pub fn notify_stopping() {
+    notify(&[NotifyState::Stopping]);
+}
+
+/// Ping the watchdog at half the unit's `WatchdogSec=` for as long as the
+/// async runtime keeps making progress, so a wedged process gets restarted.
+/// Returns `None` if the watchdog is not enabled.
+pub fn spawn_watchdog() -> Option<tokio::task::JoinHandle<()>> {
+    let mut usec = 0;
+    if !sd_notify::watchdog_enabled(false, &mut usec) {
+        return None;
+    }
+    let period = std::time::Duration::from_micros(usec / 2);
+    tracing::info!("🐶 systemd watchdog enabled, pinging every {:?}", period);
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            notify(&[NotifyState::Watchdog]);
+        }
+    }))
+}
+
+/// Progress reporter mirroring scan phases into the systemd status line
+pub fn status_reporter() -> ProgressReporter {
+    ProgressReporter::from_callback(|event| match event {
+        ProgressEvent::PhaseStarted { phase } => notify_status(&format!("{}...", phase)),
+        ProgressEvent::CrawlTick {
+            files,
+            files_per_second,
+            ..
+        } => notify_status(&format!(
+            "crawl: {} files ({:.0} files/s)",
+            files, files_per_second
+        )),
+        ProgressEvent::CrawlPaused => notify_status("crawl: paused"),
+        ProgressEvent::CrawlResumed => notify_status("crawl: resumed"),
+        ProgressEvent::LoadTick {
+            rows,
+            rows_per_second,
+            ..
+        } => notify_status(&format!(
+            "load: {} rows ({:.0} rows/s)",
+            rows, rows_per_second
+        )),
+        ProgressEvent::Error { phase, message } => {
+            notify_status(&format!("{} failed: {}", phase, message))
+        }
+        ProgressEvent::BatchStarted {
+            index, total, path, ..
+        } => notify_status(&format!(
+            "batch {}/{}: {}",
+            index + 1,
+            total,
+            path.display()
+        )),
+        ProgressEvent::ScanStarted { scan_id, .. } => {
+            notify_status(&format!("scan {} started", scan_id))
+        }
+        ProgressEvent::PhaseCompleted { .. } => {}
+    })
+}
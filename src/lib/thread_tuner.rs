@@ -0,0 +1,219 @@
+/// Walker threads an adaptive crawl starts with, without a previous choice
+pub const START_THREADS: usize = 4;
+/// Walker threads spawned by an adaptive crawl, of which the tuner lets a
+/// varying number stat files at once
+pub const MAX_THREADS: usize = 64;
+/// Stat throughput is measured over windows this long
+const WINDOW: std::time::Duration = std::time::Duration::from_secs(2);
+/// More threads must raise the throughput by this fraction to be kept
+const MIN_GAIN: f64 = 0.10;
+/// Fewer threads may lower the throughput by this fraction and still be kept
+const MAX_LOSS: f64 = 0.05;
+/// Windows spent at the knee before probing again, as the tree changes
+const REPROBE_AFTER: u32 = 30;
+
+/// Caps how many walker threads stat files at once
+#[derive(Debug)]
+pub(crate) struct ConcurrencyGate {
+    limit: std::sync::atomic::AtomicUsize,
+    active: std::sync::Mutex<usize>,
+    freed: std::sync::Condvar,
+}
+
+/// A walker thread's turn, ending when dropped
+pub(crate) struct GatePass<'a>(&'a ConcurrencyGate);
+
+impl ConcurrencyGate {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit: std::sync::atomic::AtomicUsize::new(limit.max(1)),
+            active: std::sync::Mutex::new(0),
+            freed: std::sync::Condvar::new(),
+        }
+    }
+
+    /// Wait until fewer threads than the limit hold a pass
+    pub(crate) fn enter(&self) -> GatePass<'_> {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        while *active >= self.limit() {
+            active = self.freed.wait(active).unwrap_or_else(|e| e.into_inner());
+        }
+        *active += 1;
+        GatePass(self)
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    fn set_limit(&self, limit: usize) {
+        let _active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        self.limit
+            .store(limit.max(1), std::sync::atomic::Ordering::Relaxed);
+        self.freed.notify_all();
+    }
+}
+
+/// Walker threads allowed to stat files at once across all the crawls
+/// sharing the budget, e.g. the concurrent scans of a host
+#[derive(Debug, Clone)]
+pub struct IoBudget(std::sync::Arc<ConcurrencyGate>);
+
+impl IoBudget {
+    pub fn new(threads: usize) -> Self {
+        Self(std::sync::Arc::new(ConcurrencyGate::new(threads)))
+    }
+
+    pub fn threads(&self) -> usize {
+        self.0.limit()
+    }
+
+    /// Wait for a share of the budget, held until the pass is dropped
+    pub(crate) fn enter(&self) -> GatePass<'_> {
+        self.0.enter()
+    }
+}
+
+impl Drop for GatePass<'_> {
+    fn drop(&mut self) {
+        let mut active = self.0.active.lock().unwrap_or_else(|e| e.into_inner());
+        *active -= 1;
+        self.0.freed.notify_one();
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Phase {
+    /// Measuring the current limit before probing from it
+    Measure,
+    /// Doubling the threads while the throughput grows
+    Up,
+    /// Trimming threads the throughput does not need
+    Down,
+    /// At the knee, for this many windows
+    Settled(u32),
+}
+
+/// Hill climbing over the walker threads: double them while that raises
+/// the stat throughput, then trim the ones it does without, which leaves
+/// the knee of the throughput curve of the storage behind the root
+#[derive(Debug)]
+struct ThreadTuner {
+    limit: usize,
+    /// Threads spawned, beyond which the limit is not raised
+    max: usize,
+    /// Last accepted limit and the best throughput seen at it
+    accepted: (usize, f64),
+    phase: Phase,
+}
+
+impl ThreadTuner {
+    fn new(limit: usize, max: usize) -> Self {
+        Self {
+            limit,
+            max,
+            accepted: (limit, 0.0),
+            phase: Phase::Measure,
+        }
+    }
+
+    /// Limit for the next window, given the files per second of the last
+    fn next(&mut self, rate: f64) -> usize {
+        let (accepted, best) = self.accepted;
+        let up = (self.limit * 2).min(self.max);
+        let down = self.limit - (self.limit / 4).max(1).min(self.limit - 1);
+        self.phase = match self.phase {
+            Phase::Measure => {
+                self.accepted = (self.limit, rate);
+                let settled = up == self.limit;
+                self.limit = up;
+                if settled {
+                    Phase::Settled(0)
+                } else {
+                    Phase::Up
+                }
+            }
+            Phase::Up if rate > best * (1.0 + MIN_GAIN) => {
+                self.accepted = (self.limit, rate);
+                let settled = up == self.limit;
+                self.limit = up;
+                if settled {
+                    Phase::Settled(0)
+                } else {
+                    Phase::Up
+                }
+            }
+            Phase::Up => {
+                // the doubling did not pay: try below the last accepted limit
+                self.limit = accepted - (accepted / 4).max(1).min(accepted - 1);
+                if self.limit == accepted {
+                    Phase::Settled(0)
+                } else {
+                    Phase::Down
+                }
+            }
+            Phase::Down if rate >= best * (1.0 - MAX_LOSS) => {
+                self.accepted = (self.limit, best.max(rate));
+                let settled = down == self.limit;
+                self.limit = down;
+                if settled {
+                    Phase::Settled(0)
+                } else {
+                    Phase::Down
+                }
+            }
+            Phase::Down => {
+                self.limit = accepted;
+                Phase::Settled(0)
+            }
+            Phase::Settled(windows) if windows + 1 >= REPROBE_AFTER => Phase::Measure,
+            Phase::Settled(windows) => Phase::Settled(windows + 1),
+        };
+        self.limit
+    }
+
+    /// The limit the tuning arrived at
+    fn chosen(&self) -> usize {
+        match self.phase {
+            Phase::Up | Phase::Down => self.accepted.0,
+            Phase::Measure | Phase::Settled(_) => self.limit,
+        }
+    }
+}
+
+/// Tune `gate` every window from the growth of `files` until `stop` is
+/// dropped, skipping windows the walk spends paused, without going beyond
+/// the `max` threads spawned. Returns the limit chosen.
+pub(crate) fn run(
+    gate: &ConcurrencyGate,
+    max: usize,
+    files: &std::sync::atomic::AtomicU64,
+    pause: &crate::pause::PauseSwitch,
+    stop: crossbeam_channel::Receiver<()>,
+) -> usize {
+    let mut tuner = ThreadTuner::new(gate.limit(), max);
+    let mut last = files.load(std::sync::atomic::Ordering::Relaxed);
+    let mut last_t = std::time::Instant::now();
+    while let Err(crossbeam_channel::RecvTimeoutError::Timeout) = stop.recv_timeout(WINDOW) {
+        let now = std::time::Instant::now();
+        let total = files.load(std::sync::atomic::Ordering::Relaxed);
+        let rate = (total - last) as f64 / now.duration_since(last_t).as_secs_f64();
+        (last, last_t) = (total, now);
+        // a paused walk stats nothing, whatever the threads
+        if pause.is_paused() || total == 0 {
+            continue;
+        }
+        let before = gate.limit();
+        let limit = tuner.next(rate);
+        if limit != before {
+            tracing::debug!(
+                "🎛️ {} walker threads at {:.1} f/s, trying {}",
+                before,
+                rate,
+                limit
+            );
+            gate.set_limit(limit);
+        }
+    }
+    tuner.chosen()
+}
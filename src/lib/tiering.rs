@@ -0,0 +1,193 @@
+use futures::StreamExt;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyConfig {
+    /// Days since the file was last modified
+    min_age_days: u32,
+    #[serde(default)]
+    min_size_bytes: i64,
+    /// Globs over recorded paths a file must match one of; all files if empty
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    max_files: Option<u64>,
+    max_bytes: Option<i64>,
+}
+
+/// Which current files are candidates for moving to a colder tier, read from
+/// a TOML file:
+///
+/// ```toml
+/// min_age_days = 365
+/// min_size_bytes = 1048576
+/// include = ["/data/projects/**"]
+/// exclude = ["**/.snapshot/**"]
+/// max_bytes = 10995116277760
+/// ```
+///
+/// Candidates are taken oldest first until `max_files` or `max_bytes` is
+/// reached.
+#[derive(Debug, Clone)]
+pub struct TieringPolicy {
+    config: PolicyConfig,
+    include: globset::GlobSet,
+    exclude: globset::GlobSet,
+}
+
+fn glob_set(patterns: &[String]) -> anyhow::Result<globset::GlobSet> {
+    let mut set = globset::GlobSetBuilder::new();
+    for pattern in patterns {
+        set.add(
+            globset::GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| anyhow::anyhow!("Invalid pattern {}: {}", pattern, e))?,
+        );
+    }
+    Ok(set.build()?)
+}
+
+impl TieringPolicy {
+    pub fn from_file(path: &std::path::Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let config: PolicyConfig = toml::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Invalid policy {}: {}", path.display(), e))?;
+        Ok(TieringPolicy {
+            include: glob_set(&config.include)?,
+            exclude: glob_set(&config.exclude)?,
+            config,
+        })
+    }
+
+    fn selects(&self, file_path: &str) -> bool {
+        (self.config.include.is_empty() || self.include.is_match(file_path))
+            && !self.exclude.is_match(file_path)
+    }
+}
+
+/// How [`export_tiering`] writes candidates, for the archive tool reading them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TieringFormat {
+    /// One path per line, e.g. for `lfs hsm_archive --filelist` or
+    /// `dsmc archive -filelist=`; paths containing a newline are left out
+    Paths,
+    /// NUL-terminated paths, for `xargs -0 dmput` and the like
+    Paths0,
+    /// `path,size_bytes,mtime` with a header row
+    Csv,
+}
+
+impl std::str::FromStr for TieringFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "paths" => Ok(TieringFormat::Paths),
+            "paths0" => Ok(TieringFormat::Paths0),
+            "csv" => Ok(TieringFormat::Csv),
+            other => anyhow::bail!("Unknown tiering format: {}", other),
+        }
+    }
+}
+
+/// Candidates written and left out by [`export_tiering`]
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct TieringStats {
+    pub files: u64,
+    pub bytes: i64,
+    /// paths containing a newline, which `paths` cannot hold
+    pub skipped: u64,
+    /// whether `max_files` or `max_bytes` cut the list short
+    pub truncated: bool,
+}
+
+/// Quote a CSV field if it holds a separator, quote or line break
+fn csv_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        std::borrow::Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        std::borrow::Cow::Borrowed(field)
+    }
+}
+
+/// Write the current files selected by `policy` to `out`, oldest mtime first.
+/// Scans record no access times, so the age of a file is that of its mtime.
+#[tracing::instrument(skip(client, policy))]
+pub async fn export_tiering(
+    client: &tokio_postgres::Client,
+    policy: &TieringPolicy,
+    format: TieringFormat,
+    out: &std::path::Path,
+) -> anyhow::Result<TieringStats> {
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(i64::from(policy.config.min_age_days));
+    let query = "
+        SELECT file_path, file_size_bytes, file_mtime
+        FROM filesystem.files
+        WHERE file_mtime < $1 AND file_size_bytes >= $2 AND symlink_target IS NULL
+        ORDER BY file_mtime, file_path";
+    let rows = client
+        .query_raw(
+            query,
+            [
+                &cutoff as &(dyn tokio_postgres::types::ToSql + Sync),
+                &policy.config.min_size_bytes,
+            ],
+        )
+        .await?;
+    futures::pin_mut!(rows);
+
+    let terminator = if format == TieringFormat::Paths0 {
+        b'\0'
+    } else {
+        b'\n'
+    };
+    let mut file = tokio::io::BufWriter::new(tokio::fs::File::create(out).await?);
+    if format == TieringFormat::Csv {
+        file.write_all(b"path,size_bytes,mtime\n").await?;
+    }
+    let mut stats = TieringStats::default();
+    while let Some(row) = rows.next().await {
+        let row = row?;
+        let path: &str = row.get(0);
+        let size: i64 = row.get(1);
+        if !policy.selects(path) {
+            continue;
+        }
+        if format == TieringFormat::Paths && path.contains('\n') {
+            stats.skipped += 1;
+            continue;
+        }
+        if policy
+            .config
+            .max_files
+            .is_some_and(|max| stats.files >= max)
+            || policy
+                .config
+                .max_bytes
+                .is_some_and(|max| stats.bytes + size > max)
+        {
+            stats.truncated = true;
+            break;
+        }
+        match format {
+            TieringFormat::Paths | TieringFormat::Paths0 => {
+                file.write_all(path.as_bytes()).await?;
+                file.write_all(&[terminator]).await?;
+            }
+            TieringFormat::Csv => {
+                let mtime: chrono::DateTime<chrono::Utc> = row.get(2);
+                let line = format!("{},{},{}\n", csv_field(path), size, mtime.to_rfc3339());
+                file.write_all(line.as_bytes()).await?;
+            }
+        }
+        stats.files += 1;
+        stats.bytes += size;
+    }
+    file.flush().await?;
+
+    Ok(stats)
+}
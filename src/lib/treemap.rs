@@ -0,0 +1,566 @@
+use futures::StreamExt;
+
+/// Name of the node holding the files directly in a directory that also has
+/// subdirectories, so that leaves add up to the directory's size
+const OWN_FILES_NODE: &str = "(files)";
+
+/// A directory of a treemap, in the hierarchical format of d3 and
+/// d3-flame-graph: `value` is the size of the whole subtree, and the leaves'
+/// values add up to their ancestors'.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TreeNode<T = FileTotals> {
+    pub name: String,
+    #[serde(flatten)]
+    pub totals: T,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreeNode<T>>,
+}
+
+/// What a treemap sizes its nodes by, summed up the tree
+pub trait Totals: Default + Clone {
+    /// Size of the node
+    fn value(&self) -> i64;
+    fn add(&mut self, other: &Self);
+}
+
+/// Current files below a node
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct FileTotals {
+    /// Bytes of the files below this node
+    pub value: i64,
+    /// Number of files below this node
+    pub files: i64,
+}
+
+impl Totals for FileTotals {
+    fn value(&self) -> i64 {
+        self.value
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.value += other.value;
+        self.files += other.files;
+    }
+}
+
+/// Changes of a scan below a node, sized by the bytes they touched: the new
+/// size of added and modified files, the old size of deleted ones
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ChangeTotals {
+    pub value: i64,
+    pub changes: i64,
+    pub added_bytes: i64,
+    pub modified_bytes: i64,
+    pub deleted_bytes: i64,
+}
+
+impl Totals for ChangeTotals {
+    fn value(&self) -> i64 {
+        self.value
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.value += other.value;
+        self.changes += other.changes;
+        self.added_bytes += other.added_bytes;
+        self.modified_bytes += other.modified_bytes;
+        self.deleted_bytes += other.deleted_bytes;
+    }
+}
+
+/// How [`build_treemap`] and [`build_delta_tree`] shape the tree
+#[derive(Debug, Clone)]
+pub struct TreemapOptions {
+    /// Levels below the root; deeper directories are counted in their
+    /// ancestor at this depth
+    pub max_depth: usize,
+    /// Largest children kept per directory; the others are merged into one
+    pub max_children: usize,
+    /// Key the scan's paths are encrypted with
+    pub path_cipher: Option<crate::path_cipher::PathCipher>,
+}
+
+impl Default for TreemapOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 8,
+            max_children: 100,
+            path_cipher: None,
+        }
+    }
+}
+
+/// Directory being aggregated, before totals are summed up the tree
+#[derive(Default)]
+struct DirTotals<T> {
+    own: T,
+    has_own: bool,
+    children: std::collections::HashMap<String, DirTotals<T>>,
+}
+
+impl<T: Totals> DirTotals<T> {
+    fn into_node(self, name: String, max_children: usize) -> TreeNode<T> {
+        let mut children: Vec<TreeNode<T>> = self
+            .children
+            .into_iter()
+            .map(|(name, dir)| dir.into_node(name, max_children))
+            .collect();
+        if !children.is_empty() && self.has_own {
+            children.push(TreeNode {
+                name: OWN_FILES_NODE.to_string(),
+                totals: self.own.clone(),
+                children: Vec::new(),
+            });
+        }
+        children.sort_by(|a, b| {
+            b.totals
+                .value()
+                .cmp(&a.totals.value())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        if children.len() > max_children.max(1) {
+            let rest = children.split_off(max_children.max(1) - 1);
+            let mut totals = T::default();
+            for child in &rest {
+                totals.add(&child.totals);
+            }
+            children.push(TreeNode {
+                name: format!("({} more)", rest.len()),
+                totals,
+                children: Vec::new(),
+            });
+        }
+        // a directory's own files are either all of it or one of its children
+        let totals = if children.is_empty() {
+            self.own
+        } else {
+            let mut totals = T::default();
+            for child in &children {
+                totals.add(&child.totals);
+            }
+            totals
+        };
+        TreeNode {
+            name,
+            totals,
+            children,
+        }
+    }
+}
+
+/// Fold the per-directory rows of `query` (taking `scan_id` as `$1`) into a
+/// tree below the root of `scan_id`; `row_totals` reads a row's directory
+/// and totals
+async fn build_tree<T: Totals>(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    options: &TreemapOptions,
+    query: &str,
+    row_totals: impl Fn(&tokio_postgres::Row) -> (String, T),
+) -> anyhow::Result<TreeNode<T>> {
+    let (scan_root, path_key_id) = crate::data::get_scan_root(client, scan_id).await?;
+    let cipher = crate::path_cipher::cipher_for_scan(
+        scan_id,
+        path_key_id.as_deref(),
+        options.path_cipher.as_ref(),
+    )?;
+
+    let rows = client.query_raw(query, [&scan_id]).await?;
+    futures::pin_mut!(rows);
+
+    let root = scan_root.trim_end_matches('/');
+    let mut tree = DirTotals::<T>::default();
+    let mut dirs = 0u64;
+    while let Some(row) = rows.next().await {
+        let (dir, totals) = row_totals(&row?);
+        let relative = dir.strip_prefix(root).unwrap_or(&dir);
+        let mut node = &mut tree;
+        for component in relative
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .take(options.max_depth)
+        {
+            let name = match cipher {
+                Some(cipher) => cipher
+                    .decrypt_component(component)
+                    .unwrap_or_else(|| component.to_string()),
+                None => component.to_string(),
+            };
+            node = node.children.entry(name).or_default();
+        }
+        node.own.add(&totals);
+        node.has_own = true;
+        dirs += 1;
+    }
+    tracing::info!("🗺️ Rolled up {} directories under {}", dirs, scan_root);
+
+    Ok(tree.into_node(scan_root, options.max_children))
+}
+
+/// Build the directory tree of the current files under the root of
+/// `scan_id`, sized from per-directory rollups of `filesystem.files`
+#[tracing::instrument(skip(client, options))]
+pub async fn build_treemap(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    options: &TreemapOptions,
+) -> anyhow::Result<TreeNode> {
+    let query = "
+        SELECT f.parent_dir, COUNT(*), SUM(f.file_size_bytes)::BIGINT
+        FROM filesystem.files AS f
+        JOIN filesystem.scan_runs AS r ON r.scan_id = $1
+        WHERE f.file_path LIKE r.scan_root || '/%'
+        GROUP BY f.parent_dir";
+    build_tree(client, scan_id, options, query, |row| {
+        (
+            row.get(0),
+            FileTotals {
+                value: row.get(2),
+                files: row.get(1),
+            },
+        )
+    })
+    .await
+}
+
+/// Build the directory tree of the changes recorded by `scan_id`, sized by
+/// the bytes they touched
+#[tracing::instrument(skip(client, options))]
+pub async fn build_delta_tree(
+    client: &tokio_postgres::Client,
+    scan_id: i32,
+    options: &TreemapOptions,
+) -> anyhow::Result<TreeNode<ChangeTotals>> {
+    let query = "
+        SELECT parent_dir, change_type, COUNT(*),
+               SUM(COALESCE(CASE change_type
+                   WHEN 'deleted' THEN old_size_bytes
+                   ELSE new_size_bytes
+               END, 0))::BIGINT
+        FROM filesystem.file_changes
+        WHERE scan_id = $1
+        GROUP BY parent_dir, change_type";
+    build_tree(client, scan_id, options, query, |row| {
+        let bytes: i64 = row.get(3);
+        let mut totals = ChangeTotals {
+            value: bytes,
+            changes: row.get(2),
+            ..Default::default()
+        };
+        match row.get::<_, &str>(1) {
+            "added" => totals.added_bytes = bytes,
+            "deleted" => totals.deleted_bytes = bytes,
+            _ => totals.modified_bytes = bytes,
+        }
+        (row.get(0), totals)
+    })
+    .await
+}
+
+/// Render a tree as a self-contained HTML page: a zoomable icicle chart
+/// (a flame graph of disk usage) that needs no network access to view
+pub fn render_html(tree: &TreeNode, title: &str) -> anyhow::Result<String> {
+    // `</script>` inside a name must not end the embedding script element
+    let json = serde_json::to_string(tree)?.replace("</", "<\\/");
+    let title = title
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    Ok(crate::pipeline::html_template("treemap.html")
+        .replace("{{TITLE}}", &title)
+        .replace("{{TREE_JSON}}", &json))
+}
+
+/// Colors of the change types in a delta image, blended by their bytes
+const ADDED_RGB: (f64, f64, f64) = (44.0, 160.0, 44.0);
+const MODIFIED_RGB: (f64, f64, f64) = (240.0, 170.0, 30.0);
+const DELETED_RGB: (f64, f64, f64) = (214.0, 39.0, 40.0);
+/// Height of a directory's label strip, and of the image's title and legend
+const LABEL_HEIGHT: f64 = 14.0;
+const HEADER_HEIGHT: f64 = 44.0;
+
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+/// Lay out `values` (sorted largest first) in `rect` as a squarified
+/// treemap, keeping rectangles close to square so small ones stay visible
+fn squarify(values: &[f64], mut rect: Rect) -> Vec<Rect> {
+    let total: f64 = values.iter().sum();
+    if total <= 0.0 || rect.w <= 0.0 || rect.h <= 0.0 {
+        return vec![
+            Rect {
+                w: 0.0,
+                h: 0.0,
+                ..rect
+            };
+            values.len()
+        ];
+    }
+    let scale = rect.w * rect.h / total;
+    let areas: Vec<f64> = values.iter().map(|v| v * scale).collect();
+    // worst aspect ratio of a row of areas laid along `side`
+    let worst = |row: &[f64], side: f64| {
+        let sum: f64 = row.iter().sum();
+        let min = row.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = row.iter().cloned().fold(0.0_f64, f64::max);
+        if sum <= 0.0 || min <= 0.0 {
+            return f64::INFINITY;
+        }
+        (side * side * max / (sum * sum)).max(sum * sum / (side * side * min))
+    };
+
+    let mut out = Vec::with_capacity(areas.len());
+    let mut start = 0;
+    while start < areas.len() {
+        let side = rect.w.min(rect.h);
+        let mut end = start + 1;
+        while end < areas.len()
+            && worst(&areas[start..=end], side) <= worst(&areas[start..end], side)
+        {
+            end += 1;
+        }
+        let row_area: f64 = areas[start..end].iter().sum();
+        if rect.w >= rect.h {
+            // a column along the left edge
+            let thickness = row_area / rect.h;
+            let mut y = rect.y;
+            for area in &areas[start..end] {
+                let h = if thickness > 0.0 {
+                    area / thickness
+                } else {
+                    0.0
+                };
+                out.push(Rect {
+                    x: rect.x,
+                    y,
+                    w: thickness,
+                    h,
+                });
+                y += h;
+            }
+            rect.x += thickness;
+            rect.w -= thickness;
+        } else {
+            // a row along the top edge
+            let thickness = row_area / rect.w;
+            let mut x = rect.x;
+            for area in &areas[start..end] {
+                let w = if thickness > 0.0 {
+                    area / thickness
+                } else {
+                    0.0
+                };
+                out.push(Rect {
+                    x,
+                    y: rect.y,
+                    w,
+                    h: thickness,
+                });
+                x += w;
+            }
+            rect.y += thickness;
+            rect.h -= thickness;
+        }
+        start = end;
+    }
+    out
+}
+
+fn human_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value.abs() >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Fill color of a node: the change type colors blended by their bytes,
+/// lighter for directories than for the leaves drawn over them
+fn change_color(totals: &ChangeTotals, lighten: f64) -> String {
+    let total = (totals.added_bytes + totals.modified_bytes + totals.deleted_bytes) as f64;
+    let (r, g, b) = if total > 0.0 {
+        let mix = |pick: fn(&(f64, f64, f64)) -> f64| {
+            (pick(&ADDED_RGB) * totals.added_bytes as f64
+                + pick(&MODIFIED_RGB) * totals.modified_bytes as f64
+                + pick(&DELETED_RGB) * totals.deleted_bytes as f64)
+                / total
+        };
+        (mix(|c| c.0), mix(|c| c.1), mix(|c| c.2))
+    } else {
+        (160.0, 160.0, 160.0)
+    };
+    let light = |c: f64| (c + (255.0 - c) * lighten).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", light(r), light(g), light(b))
+}
+
+fn change_summary(totals: &ChangeTotals) -> String {
+    format!(
+        "+{} added, ~{} modified, -{} deleted ({} changes)",
+        human_bytes(totals.added_bytes),
+        human_bytes(totals.modified_bytes),
+        human_bytes(totals.deleted_bytes),
+        totals.changes
+    )
+}
+
+fn draw_delta_node(
+    svg: &mut String,
+    node: &TreeNode<ChangeTotals>,
+    path: &str,
+    rect: Rect,
+    depth: usize,
+) {
+    use std::fmt::Write;
+    if rect.w < 1.0 || rect.h < 1.0 {
+        return;
+    }
+    let path = if depth == 0 {
+        node.name.clone()
+    } else {
+        format!("{}/{}", path, node.name)
+    };
+    let is_dir = !node.children.is_empty();
+    let _ = write!(
+        svg,
+        r##"<g><title>{}&#10;{}</title><rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}" stroke="#fff"/>"##,
+        xml_escape(&path),
+        xml_escape(&change_summary(&node.totals)),
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        change_color(&node.totals, if is_dir { 0.7 } else { 0.0 })
+    );
+    let labelled = rect.w > 40.0 && rect.h > LABEL_HEIGHT + 2.0;
+    if labelled {
+        let label = if is_dir {
+            node.name.clone()
+        } else {
+            format!("{} ({})", node.name, human_bytes(node.totals.value))
+        };
+        // roughly 6px per character at this font size
+        let fit = ((rect.w - 6.0) / 6.0) as usize;
+        let label: String = if label.chars().count() > fit {
+            label
+                .chars()
+                .take(fit.saturating_sub(1))
+                .chain(['…'])
+                .collect()
+        } else {
+            label
+        };
+        let _ = write!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}">{}</text>"#,
+            rect.x + 3.0,
+            rect.y + 11.0,
+            xml_escape(&label)
+        );
+    }
+    svg.push_str("</g>\n");
+
+    if is_dir {
+        let header = if labelled { LABEL_HEIGHT } else { 0.0 };
+        let inner = Rect {
+            x: rect.x + 2.0,
+            y: rect.y + 2.0 + header,
+            w: rect.w - 4.0,
+            h: rect.h - 4.0 - header,
+        };
+        let values: Vec<f64> = node
+            .children
+            .iter()
+            .map(|c| c.totals.value.max(0) as f64)
+            .collect();
+        for (child, child_rect) in node.children.iter().zip(squarify(&values, inner)) {
+            draw_delta_node(svg, child, &path, child_rect, depth + 1);
+        }
+    }
+}
+
+/// Render the changes of a scan as an SVG treemap: areas are the bytes the
+/// changes touched, colors blend green (added), amber (modified) and red
+/// (deleted) by bytes, and hovering a box names its directory and totals
+pub fn render_delta_svg(
+    tree: &TreeNode<ChangeTotals>,
+    title: &str,
+    width: u32,
+    height: u32,
+) -> String {
+    use std::fmt::Write;
+    let (width, height) = (width as f64, height as f64);
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}" font-family="sans-serif" font-size="11">"#,
+        width, height
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect width="100%" height="100%" fill="#fff"/><text x="4" y="16" font-size="14" font-weight="bold">{}</text><text x="4" y="34">{}</text>"##,
+        xml_escape(title),
+        xml_escape(&change_summary(&tree.totals))
+    );
+    for (i, (label, (r, g, b))) in [
+        ("added", ADDED_RGB),
+        ("modified", MODIFIED_RGB),
+        ("deleted", DELETED_RGB),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let x = width - 250.0 + i as f64 * 82.0;
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{:.1}" y="24" width="12" height="12" fill="rgb({},{},{})"/><text x="{:.1}" y="34">{}</text>"#,
+            x,
+            r,
+            g,
+            b,
+            x + 16.0,
+            label
+        );
+    }
+    if tree.totals.value > 0 {
+        draw_delta_node(
+            &mut svg,
+            tree,
+            "",
+            Rect {
+                x: 0.0,
+                y: HEADER_HEIGHT,
+                w: width,
+                h: height - HEADER_HEIGHT,
+            },
+            0,
+        );
+    } else {
+        let _ = writeln!(
+            svg,
+            r##"<text x="{:.1}" y="{:.1}" text-anchor="middle" fill="#888">No changes</text>"##,
+            width / 2.0,
+            (height + HEADER_HEIGHT) / 2.0
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
@@ -0,0 +1,114 @@
+use std::borrow::Cow;
+
+/// Options of a `COPY ... FROM STDIN` loading the TSVs written by [`Row`]:
+/// COPY's text format, with tabs, newlines, carriage returns and backslashes
+/// in fields escaped, so that names holding them load as they are on disk
+pub const COPY_OPTIONS: &str = "FORMAT text, DELIMITER E'\\t'";
+
+/// A NULL field
+pub const NULL: &str = "\\N";
+
+/// A line of a TSV under construction. Fields are escaped rather than quoted
+/// as a CSV writer (`QuoteStyle::Necessary`) would: a quoted field keeps its
+/// newlines, so a record could span lines, while the crawl TSV is chunked,
+/// checkpointed, sorted and diffed line by line; and CSV has no NULL apart
+/// from an empty field.
+#[derive(Debug, Default)]
+pub struct Row(String);
+
+impl Row {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a text field, escaped
+    pub fn field(mut self, field: &str) -> Self {
+        self.separate();
+        self.0.push_str(&escape_field(field));
+        self
+    }
+
+    /// Append a field, `\N` if `None`
+    pub fn nullable(self, field: Option<&str>) -> Self {
+        match field {
+            Some(field) => self.field(field),
+            None => self.raw(NULL),
+        }
+    }
+
+    /// Append a field that cannot need escaping, e.g. a number
+    pub fn value(self, value: impl std::fmt::Display) -> Self {
+        self.raw(&value.to_string())
+    }
+
+    /// Append a value like [`value`](Self::value), `\N` if `None`
+    pub fn nullable_value(self, value: Option<impl std::fmt::Display>) -> Self {
+        match value {
+            Some(value) => self.value(value),
+            None => self.raw(NULL),
+        }
+    }
+
+    /// The line, newline-terminated
+    pub fn finish(mut self) -> String {
+        self.0.push('\n');
+        self.0
+    }
+
+    fn raw(mut self, field: &str) -> Self {
+        self.separate();
+        self.0.push_str(field);
+        self
+    }
+
+    fn separate(&mut self) {
+        if !self.0.is_empty() {
+            self.0.push('\t');
+        }
+    }
+}
+
+/// Escape a field for COPY's text format
+pub fn escape_field(field: &str) -> Cow<'_, str> {
+    if !field.contains(['\\', '\t', '\n', '\r']) {
+        return Cow::Borrowed(field);
+    }
+    let mut escaped = String::with_capacity(field.len() + 8);
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// The field `escape_field` escaped, for readers of the TSVs other than COPY
+pub fn unescape_field(field: &str) -> Cow<'_, str> {
+    if !field.contains('\\') {
+        return Cow::Borrowed(field);
+    }
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('t') => unescaped.push('\t'),
+                Some('n') => unescaped.push('\n'),
+                Some('r') => unescaped.push('\r'),
+                Some(other) => unescaped.push(other),
+                None => unescaped.push('\\'),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    Cow::Owned(unescaped)
+}
+
+/// A field of a TSV line, unescaped; `None` for `\N`
+pub fn field(field: &str) -> Option<String> {
+    (field != NULL).then(|| unescape_field(field).into_owned())
+}
@@ -0,0 +1,185 @@
+use crate::process::command_output;
+use crate::snapshot_diff::DiffEntries;
+
+const USN_REASON_FILE_CREATE: u64 = 0x0000_0100;
+const USN_REASON_FILE_DELETE: u64 = 0x0000_0200;
+const USN_REASON_RENAME_OLD_NAME: u64 = 0x0000_1000;
+const USN_REASON_RENAME_NEW_NAME: u64 = 0x0000_2000;
+const FILE_ATTRIBUTE_DIRECTORY: u64 = 0x10;
+
+/// Volume whose change journal covers `data_root`: its drive (`D:`), or the
+/// root itself, which must then be a mount point or volume name
+fn volume_of(data_root: &std::path::Path) -> String {
+    let root = data_root.to_string_lossy();
+    let root = root.strip_prefix(r"\\?\").unwrap_or(&root);
+    match root.as_bytes() {
+        [letter, b':', ..] if letter.is_ascii_alphabetic() => root[..2].to_string(),
+        _ => root.to_string(),
+    }
+}
+
+fn parse_hex(value: &str) -> Option<u64> {
+    let value = value.trim().trim_matches('"');
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    u64::from_str_radix(digits, 16).ok()
+}
+
+/// The journal ID and next USN of the volume of `data_root`, as
+/// `<journal id>:<next usn>`, from `fsutil usn queryjournal`
+pub async fn mark(data_root: &std::path::Path) -> anyhow::Result<String> {
+    let mut command = tokio::process::Command::new("fsutil");
+    command
+        .args(["usn", "queryjournal"])
+        .arg(volume_of(data_root));
+    let output = String::from_utf8_lossy(&command_output(command).await?).to_string();
+    let field = |name: &str| {
+        output.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == name).then(|| parse_hex(value)).flatten()
+        })
+    };
+    match (field("Usn Journal ID"), field("Next Usn")) {
+        (Some(journal_id), Some(next_usn)) => Ok(format!("{:x}:{:x}", journal_id, next_usn)),
+        _ => anyhow::bail!(
+            "fsutil usn queryjournal printed no journal ID or next USN for {}",
+            data_root.display()
+        ),
+    }
+}
+
+fn parse_marker(marker: &str) -> anyhow::Result<(u64, u64)> {
+    marker
+        .split_once(':')
+        .and_then(|(journal_id, usn)| Some((parse_hex(journal_id)?, parse_hex(usn)?)))
+        .ok_or_else(|| anyhow::anyhow!("Invalid USN journal marker: {}", marker))
+}
+
+/// Split a line of `fsutil`'s CSV output into fields, honouring quotes
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Directory holding the file with ID `file_id`, from
+/// `fsutil file queryfilenamebyid`; `None` if it no longer exists
+async fn resolve_file_id(volume: &str, file_id: &str) -> Option<std::path::PathBuf> {
+    let mut command = tokio::process::Command::new("fsutil");
+    command
+        .args(["file", "queryfilenamebyid"])
+        .arg(format!("{}\\", volume.trim_end_matches('\\')))
+        .arg(file_id);
+    let output = command_output(command).await.ok()?;
+    // "A random link name to this file is \\?\D:\share\dir"
+    let output = String::from_utf8_lossy(&output);
+    let (_, path) = output.trim().rsplit_once(" is ")?;
+    Some(std::path::PathBuf::from(
+        path.strip_prefix(r"\\?\").unwrap_or(path),
+    ))
+}
+
+/// The entries of `data_root` named by the change journal records since the
+/// `previous` marker, from `fsutil usn readjournal`. Fails if the journal
+/// was recreated since, or has already discarded those records.
+///
+/// Records only name an entry and its parent directory's file ID, which is
+/// resolved to the directory's current path; records of entries whose
+/// directory is gone are covered by the record of the directory's deletion.
+pub async fn diff(
+    data_root: &std::path::Path,
+    previous: &str,
+    current: &str,
+) -> anyhow::Result<DiffEntries> {
+    let (previous_journal, previous_usn) = parse_marker(previous)?;
+    let (current_journal, _) = parse_marker(current)?;
+    anyhow::ensure!(
+        previous_journal == current_journal,
+        "The change journal was recreated since the previous scan ({:x} -> {:x})",
+        previous_journal,
+        current_journal
+    );
+
+    let volume = volume_of(data_root);
+    let mut command = tokio::process::Command::new("fsutil");
+    command
+        .args(["usn", "readjournal"])
+        .arg(&volume)
+        .arg(format!("startusn=0x{:x}", previous_usn))
+        .arg("csv");
+    let output = String::from_utf8_lossy(&command_output(command).await?).to_string();
+
+    let mut lines = output.lines().skip_while(|line| !line.starts_with("Usn,"));
+    let header = csv_fields(
+        lines
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("fsutil usn readjournal printed no CSV header"))?,
+    );
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| h.trim() == name)
+            .ok_or_else(|| anyhow::anyhow!("fsutil usn readjournal has no '{}' column", name))
+    };
+    let (name_column, reason_column, attributes_column, parent_column) = (
+        column("File name")?,
+        column("Reason #")?,
+        column("File attributes #")?,
+        column("Parent file ID")?,
+    );
+
+    let mut entries = DiffEntries::default();
+    let mut directories = std::collections::HashMap::<String, Option<std::path::PathBuf>>::new();
+    for line in lines.filter(|line| !line.trim().is_empty()) {
+        let fields = csv_fields(line);
+        let field = |index: usize| fields.get(index).map(String::as_str).unwrap_or_default();
+        let (Some(reason), Some(attributes)) = (
+            parse_hex(field(reason_column)),
+            parse_hex(field(attributes_column)),
+        ) else {
+            anyhow::bail!("Unexpected fsutil usn readjournal line: {}", line);
+        };
+        entries.lines += 1;
+
+        let parent_id = field(parent_column).to_string();
+        let parent = match directories.get(&parent_id) {
+            Some(parent) => parent.clone(),
+            None => {
+                let parent = resolve_file_id(&volume, &parent_id).await;
+                directories.insert(parent_id, parent.clone());
+                parent
+            }
+        };
+        let Some(path) = parent.map(|parent| parent.join(field(name_column))) else {
+            continue;
+        };
+        if !path.starts_with(data_root) || path == data_root {
+            continue;
+        }
+
+        if attributes & FILE_ATTRIBUTE_DIRECTORY == 0 {
+            entries.paths.insert(path);
+        } else if reason & (USN_REASON_FILE_DELETE | USN_REASON_RENAME_OLD_NAME) != 0 {
+            entries.old_dirs.insert(path);
+        } else if reason & (USN_REASON_FILE_CREATE | USN_REASON_RENAME_NEW_NAME) != 0 {
+            entries.new_dirs.insert(path);
+        }
+    }
+    Ok(entries)
+}
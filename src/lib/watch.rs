@@ -0,0 +1,259 @@
+use crate::snapshot_diff::DiffEntries;
+use notify::Watcher as _;
+use notify::event::{CreateKind, EventKind, ModifyKind, RemoveKind, RenameMode};
+
+/// Events a [`Watcher`] holds for the next batch; past this many the batch
+/// is reported [`WatchBatch::Lost`] rather than waiting on memory
+pub const MAX_PENDING_EVENTS: usize = 16_384;
+
+/// What a [`Watcher`] saw change under its root in a settle window
+#[derive(Debug)]
+pub enum WatchBatch {
+    /// Entries to restate, as a snapshot diff would report them
+    Changes(DiffEntries),
+    /// Events were lost, so the root must be crawled
+    Lost(String),
+}
+
+/// An entry as the watcher's handler reports it
+enum Event {
+    Entry(std::path::PathBuf),
+    NewDir(std::path::PathBuf),
+    OldDir(std::path::PathBuf),
+    Lost(String),
+}
+
+/// Changes under a root as they happen, through `notify`: inotify watches on
+/// each of its directories on Linux, FSEvents on macOS, ReadDirectoryChangesW
+/// on Windows. Dropping it stops the backend's thread and closes its handles.
+pub struct Watcher {
+    kind: &'static str,
+    events: tokio::sync::mpsc::Receiver<Event>,
+    /// Set by the handler when `events` was full and an event was dropped
+    overflowed: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    _watcher: notify::RecommendedWatcher,
+}
+
+/// The directories known below a root, as `notify` does not tell whether an
+/// entry renamed away or removed without a trace was one
+struct Handler {
+    kind: &'static str,
+    root: std::path::PathBuf,
+    dirs: std::collections::HashSet<std::path::PathBuf>,
+    events: tokio::sync::mpsc::Sender<Event>,
+    overflowed: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl Handler {
+    /// Remember `dir` and the directories below it; symlinks are not
+    /// followed, as in a crawl
+    fn add_tree(&mut self, dir: &std::path::Path) {
+        for entry in ignore::WalkBuilder::new(dir)
+            .standard_filters(false)
+            .build()
+            .flatten()
+        {
+            if entry.file_type().is_some_and(|t| t.is_dir()) {
+                self.dirs.insert(entry.into_path());
+            }
+        }
+    }
+
+    fn remove_tree(&mut self, dir: &std::path::Path) {
+        self.dirs.retain(|path| !path.starts_with(dir));
+    }
+
+    /// An entry that appeared at `path`
+    fn appeared(&mut self, path: std::path::PathBuf) -> Event {
+        let is_dir = std::fs::symlink_metadata(&path).is_ok_and(|meta| meta.is_dir());
+        if is_dir {
+            // entries created before the backend watched it are restated by
+            // the walk of the new directory
+            self.add_tree(&path);
+            Event::NewDir(path)
+        } else {
+            self.remove_tree(&path);
+            Event::Entry(path)
+        }
+    }
+
+    /// An entry that left `path`, removed or renamed away
+    fn left(&mut self, path: std::path::PathBuf, was_dir: bool) -> Event {
+        if path == self.root {
+            return Event::Lost(format!("{} is gone", self.root.display()));
+        }
+        if was_dir || self.dirs.contains(&path) {
+            self.remove_tree(&path);
+            Event::OldDir(path)
+        } else {
+            Event::Entry(path)
+        }
+    }
+
+    fn classify(&mut self, event: notify::Event) -> Vec<Event> {
+        if event.need_rescan() {
+            return vec![Event::Lost(format!("the {} queue overflowed", self.kind))];
+        }
+        let mut out = Vec::new();
+        for path in event.paths {
+            out.push(match event.kind {
+                // opens and closes change nothing; a rename's names are
+                // reported on their own as well
+                EventKind::Access(_) | EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                    continue;
+                }
+                EventKind::Create(CreateKind::Folder) => {
+                    self.add_tree(&path);
+                    Event::NewDir(path)
+                }
+                EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                    self.appeared(path)
+                }
+                EventKind::Remove(kind) => self.left(path, kind == RemoveKind::Folder),
+                EventKind::Modify(ModifyKind::Name(RenameMode::From)) => self.left(path, false),
+                // FSEvents reports either name of a rename alike
+                EventKind::Modify(ModifyKind::Name(_)) if path.symlink_metadata().is_ok() => {
+                    self.appeared(path)
+                }
+                EventKind::Modify(ModifyKind::Name(_)) => self.left(path, false),
+                // changes to a directory itself are not tracked
+                _ if self.dirs.contains(&path) => continue,
+                _ => Event::Entry(path),
+            });
+        }
+        out
+    }
+
+    fn send(&self, event: Event) {
+        if self.events.try_send(event).is_err() {
+            self.overflowed
+                .store(true, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    fn handle(&mut self, event: notify::Result<notify::Event>) {
+        #[cfg(feature = "testing")]
+        while crate::fault::FaultPoint::InotifyStall.armed().is_some() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let events = match event {
+            Ok(event) => self.classify(event),
+            Err(e) => vec![Event::Lost(format!("{} failed: {}", self.kind, e))],
+        };
+        for event in events {
+            self.send(event);
+        }
+    }
+}
+
+/// The name of the backend `notify` watches with here
+fn kind() -> &'static str {
+    match notify::RecommendedWatcher::kind() {
+        notify::WatcherKind::Inotify => "inotify",
+        notify::WatcherKind::Fsevent => "fsevents",
+        notify::WatcherKind::Kqueue => "kqueue",
+        notify::WatcherKind::ReadDirectoryChangesWatcher => "readdirectorychanges",
+        _ => "poll",
+    }
+}
+
+impl Watcher {
+    /// Start watching `data_root`; changes made from now on are reported
+    pub fn start(data_root: &std::path::Path) -> anyhow::Result<Self> {
+        let (sender, events) = tokio::sync::mpsc::channel(MAX_PENDING_EVENTS);
+        let overflowed = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let mut handler = Handler {
+            kind: kind(),
+            root: data_root.to_path_buf(),
+            dirs: Default::default(),
+            events: sender,
+            overflowed: overflowed.clone(),
+        };
+        handler.add_tree(data_root);
+        let dirs = handler.dirs.len();
+
+        let config = notify::Config::default().with_follow_symlinks(false);
+        let mut watcher =
+            notify::RecommendedWatcher::new(move |event| handler.handle(event), config)?;
+        watcher
+            .watch(data_root, notify::RecursiveMode::Recursive)
+            .map_err(|e| match e.kind {
+                notify::ErrorKind::MaxFilesWatch => anyhow::anyhow!(
+                    "Out of inotify watches under {}; raise fs.inotify.max_user_watches",
+                    data_root.display()
+                ),
+                _ => anyhow::anyhow!("Failed to watch {}: {}", data_root.display(), e),
+            })?;
+        tracing::info!(
+            "👀 Watching {} directories under {}",
+            dirs,
+            data_root.display()
+        );
+        Ok(Watcher {
+            kind: kind(),
+            events,
+            overflowed,
+            _watcher: watcher,
+        })
+    }
+
+    /// `inotify`, `fsevents`, `readdirectorychanges`, ...
+    pub fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    /// Wait for a change, then gather the changes of the following `settle`
+    /// window, so that a burst of writes is restated once
+    pub async fn next_batch(&mut self, settle: std::time::Duration) -> anyhow::Result<WatchBatch> {
+        let mut entries = DiffEntries::default();
+        let mut lost = None;
+        let mut deadline = None;
+        loop {
+            let event = match deadline {
+                None => self.events.recv().await,
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline, self.events.recv()).await {
+                        Ok(event) => event,
+                        Err(_) => break,
+                    }
+                }
+            };
+            let Some(event) = event else {
+                anyhow::bail!("The {} watcher stopped", self.kind);
+            };
+            deadline.get_or_insert_with(|| tokio::time::Instant::now() + settle);
+            entries.lines += 1;
+            match event {
+                Event::Entry(path) => {
+                    entries.paths.insert(path);
+                }
+                // the walk of a new directory covers those created below it
+                Event::NewDir(path)
+                    if !path
+                        .ancestors()
+                        .skip(1)
+                        .any(|dir| entries.new_dirs.contains(dir)) =>
+                {
+                    entries.new_dirs.insert(path);
+                }
+                Event::NewDir(_) => {}
+                Event::OldDir(path) => {
+                    entries.old_dirs.insert(path);
+                }
+                Event::Lost(reason) => lost = Some(reason),
+            }
+        }
+        if self
+            .overflowed
+            .swap(false, std::sync::atomic::Ordering::Relaxed)
+        {
+            lost.get_or_insert_with(|| {
+                format!("more than {} events were pending", MAX_PENDING_EVENTS)
+            });
+        }
+        Ok(match lost {
+            Some(reason) => WatchBatch::Lost(reason),
+            None => WatchBatch::Changes(entries),
+        })
+    }
+}
@@ -0,0 +1,143 @@
+//! Scan changes pushed to a REST data catalog served by the test. The scan
+//! test needs PostgreSQL's `initdb` and `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test catalog -- --include-ignored
+//! ```
+
+mod common;
+
+use common::EphemeralDb;
+use fs_delta_tracker::catalog::{self, Catalog, SyncOptions};
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[test]
+fn catalogs_are_parsed() {
+    assert_eq!(
+        "irods:/tempZone/home/proj/".parse::<Catalog>().unwrap(),
+        Catalog::Irods {
+            collection: "/tempZone/home/proj".to_string()
+        }
+    );
+    assert_eq!(
+        "https://catalog.example.org/api/changes"
+            .parse::<Catalog>()
+            .unwrap()
+            .to_string(),
+        "https://catalog.example.org/api/changes"
+    );
+    assert!("irods:tempZone/home".parse::<Catalog>().is_err());
+    assert!("/tempZone/home".parse::<Catalog>().is_err());
+}
+
+/// Serve `requests` POSTs on `listener`, answering 200, and return their
+/// JSON bodies
+async fn receive(listener: tokio::net::TcpListener, requests: usize) -> Vec<serde_json::Value> {
+    let mut bodies = Vec::new();
+    for _ in 0..requests {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        let body = loop {
+            let n = socket.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length: usize = head
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse().unwrap())
+                    })
+                    .unwrap();
+                if body.len() >= length {
+                    break body.to_string();
+                }
+            }
+        };
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        bodies.push(serde_json::from_str(&body).unwrap());
+    }
+    bodies
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn changes_are_pushed_in_batches_once() {
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new()
+        .prefix("catalog")
+        .tempdir()
+        .unwrap();
+    let root_path = root.path().display().to_string();
+    std::fs::write(root.path().join("a.txt"), "a").unwrap();
+    std::fs::write(root.path().join("b.txt"), "b").unwrap();
+    let options = ScanOptions::new(root.path().to_path_buf());
+    pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+    std::fs::remove_file(root.path().join("a.txt")).unwrap();
+    std::fs::write(root.path().join("b.txt"), "bigger").unwrap();
+    std::fs::write(root.path().join("c.txt"), "c").unwrap();
+    pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let catalog: Catalog = format!("http://{}/changes", listener.local_addr().unwrap())
+        .parse()
+        .unwrap();
+    let scans = catalog::unsynced_scans(&db.client, &catalog, Some(&root_path))
+        .await
+        .unwrap();
+    assert_eq!(scans.len(), 2);
+
+    let server = tokio::spawn(receive(listener, 2));
+    let sync = SyncOptions {
+        batch_size: 2,
+        ..SyncOptions::default()
+    };
+    let stats = catalog::sync_scan(&db.client, scans[1], &catalog, &sync)
+        .await
+        .unwrap();
+    assert_eq!((stats.added, stats.modified, stats.removed), (1, 1, 1));
+    let changes: Vec<(String, String, i64)> = server
+        .await
+        .unwrap()
+        .iter()
+        .flat_map(|body| {
+            assert_eq!(body["scan_id"], scans[1]);
+            body["changes"].as_array().unwrap().clone()
+        })
+        .map(|change| {
+            (
+                change["change_type"].as_str().unwrap().to_string(),
+                change["path"]
+                    .as_str()
+                    .unwrap()
+                    .replace(&root_path, "$ROOT"),
+                change["size_bytes"].as_i64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            ("deleted".to_string(), "$ROOT/a.txt".to_string(), 1),
+            ("modified".to_string(), "$ROOT/b.txt".to_string(), 6),
+            ("added".to_string(), "$ROOT/c.txt".to_string(), 1),
+        ]
+    );
+    assert_eq!(
+        catalog::unsynced_scans(&db.client, &catalog, Some(&root_path))
+            .await
+            .unwrap(),
+        [scans[0]]
+    );
+}
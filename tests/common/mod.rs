@@ -0,0 +1,110 @@
+//! Helpers shared by the integration tests, each test binary using some
+
+#![allow(dead_code)]
+
+use fs_delta_tracker::crawler::{self, CrawlOptions, CrawlReport};
+use fs_delta_tracker::dir_stats;
+use fs_delta_tracker::embedded_db::EmbeddedPostgres;
+use fs_delta_tracker::pause::PauseSwitch;
+use fs_delta_tracker::progress::ProgressReporter;
+
+/// The mtime [`write_file`] is mostly given, in seconds since the epoch
+pub const MTIME: u64 = 1_700_000_000;
+
+/// An embedded PostgreSQL cluster in a temporary directory with a client
+/// connected to the tracker's database, stopped and removed when dropped.
+///
+/// Needs `initdb` and `pg_ctl` on `PATH` (or in `PG_BIN_DIR`), which refuse
+/// to run as root.
+pub struct EphemeralDb {
+    pub client: tokio_postgres::Client,
+    /// For connections of their own, e.g. to LISTEN on
+    pub database_url: String,
+    // dropped in this order: the server stops before its directory goes
+    _embedded: EmbeddedPostgres,
+    _dir: tempfile::TempDir,
+}
+
+impl EphemeralDb {
+    /// Start the cluster; must be called within a Tokio runtime that keeps
+    /// running while the client is used
+    pub async fn start() -> anyhow::Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix("fs_delta_tracker_db")
+            .tempdir()?;
+        let bin_dir = std::env::var_os("PG_BIN_DIR").map(std::path::PathBuf::from);
+        let embedded = EmbeddedPostgres::start(dir.path(), 5432, bin_dir.as_deref()).await?;
+        let database_url = embedded.database_url();
+        let (client, connection) =
+            tokio_postgres::connect(&database_url, tokio_postgres::NoTls).await?;
+        tokio::spawn(connection);
+        Ok(Self {
+            client,
+            database_url,
+            _embedded: embedded,
+            _dir: dir,
+        })
+    }
+}
+
+/// Create the file at `path` and its parents, `size` bytes long with an
+/// mtime of `mtime` seconds since the epoch
+pub fn write_file(path: &std::path::Path, size: u64, mtime: u64) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let file = std::fs::File::create(path).unwrap();
+    file.set_len(size).unwrap();
+    file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime))
+        .unwrap();
+}
+
+/// Write `policy` to `policy.toml` in `dir`, returning its path
+pub fn write_policy(dir: &std::path::Path, policy: &str) -> std::path::PathBuf {
+    let path = dir.join("policy.toml");
+    std::fs::write(&path, policy).unwrap();
+    path
+}
+
+/// What a crawl of a single root wrote
+pub struct Crawl {
+    pub report: CrawlReport,
+    /// Lines of the crawl TSV, in the order written
+    pub lines: Vec<String>,
+    /// Lines of the directories TSV, empty unless directories were recorded
+    pub dir_lines: Vec<String>,
+}
+
+impl Crawl {
+    /// The fields of each line of the crawl TSV
+    pub fn fields(&self) -> impl Iterator<Item = Vec<&str>> {
+        self.lines.iter().map(|line| line.split('\t').collect())
+    }
+}
+
+/// Crawl `root` with `options` on a single thread into a TSV of its own
+pub async fn crawl(root: &std::path::Path, options: &CrawlOptions) -> Crawl {
+    let out = tempfile::tempdir().unwrap();
+    let tsv = out.path().join("crawl.tsv");
+    let report = crawler::walk_directory(
+        vec![root.to_path_buf()],
+        30,
+        1,
+        tsv.clone(),
+        ProgressReporter::default(),
+        options,
+        PauseSwitch::default(),
+    )
+    .await
+    .unwrap();
+    let read_lines = |path: &std::path::Path| -> Vec<String> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => contents.lines().map(str::to_string).collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => panic!("{}: {}", path.display(), e),
+        }
+    };
+    Crawl {
+        report,
+        lines: read_lines(&tsv),
+        dir_lines: read_lines(&dir_stats::dirs_tsv_path(&tsv)),
+    }
+}
@@ -0,0 +1,117 @@
+//! Scans of several roots at once on one host: crawls sharing an I/O
+//! budget, and a shard worker applying the shards of several roots over a
+//! shared connection pool. The worker test needs PostgreSQL's `initdb` and
+//! `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test concurrent_roots -- --include-ignored
+//! ```
+
+mod common;
+
+use common::EphemeralDb;
+use fs_delta_tracker::crawler::{self, CrawlOptions};
+use fs_delta_tracker::db::ClientPool;
+use fs_delta_tracker::pause::PauseSwitch;
+use fs_delta_tracker::pipeline::ScanOptions;
+use fs_delta_tracker::progress::ProgressReporter;
+use fs_delta_tracker::shard;
+use fs_delta_tracker::thread_tuner::IoBudget;
+
+/// A root of `dirs` top-level directories holding `files` files each
+fn tree(dirs: usize, files: usize) -> tempfile::TempDir {
+    let root = tempfile::Builder::new()
+        .prefix("concurrent_roots")
+        .tempdir()
+        .unwrap();
+    for dir in 0..dirs {
+        let dir = root.path().join(format!("dir{}", dir));
+        std::fs::create_dir(&dir).unwrap();
+        for file in 0..files {
+            std::fs::write(dir.join(format!("{}.dat", file)), "x").unwrap();
+        }
+    }
+    root
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn crawls_share_an_io_budget() {
+    let roots = [tree(4, 50), tree(4, 50), tree(4, 50)];
+    let out = tempfile::tempdir().unwrap();
+    let options = CrawlOptions {
+        io_budget: Some(IoBudget::new(1)),
+        ..CrawlOptions::default()
+    };
+    let crawls = roots.iter().enumerate().map(|(i, root)| {
+        crawler::walk_directory(
+            vec![root.path().to_path_buf()],
+            30,
+            i as i32 + 1,
+            out.path().join(format!("crawl{}.tsv", i)),
+            ProgressReporter::default(),
+            &options,
+            PauseSwitch::default(),
+        )
+    });
+    for report in futures::future::join_all(crawls).await {
+        assert_eq!(report.unwrap().metadata["total_files_processed"], "200");
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn workers_apply_the_shards_of_several_roots() {
+    let db = EphemeralDb::start().await.unwrap();
+    let trees = [tree(3, 5), tree(2, 4), tree(1, 3)];
+    let mut roots = Vec::new();
+    let mut scans = Vec::new();
+    for tree in &trees {
+        let options = ScanOptions::new(tree.path().to_path_buf());
+        let shards = shard::round_robin_shards(&options.data_root, 2).unwrap();
+        scans.push(
+            shard::start_sharded_scan(&db.client, &options, &shards)
+                .await
+                .unwrap(),
+        );
+        roots.push(options);
+    }
+
+    let pool = ClientPool::new(&db.database_url, 2);
+    let applied: Vec<usize> = shard::work_roots(&pool, &roots, &ProgressReporter::default())
+        .await
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(applied, [2, 2, 2]);
+
+    for (options, scan_id) in roots.iter().zip(scans) {
+        shard::finish_sharded_scan(
+            &db.client,
+            options,
+            scan_id,
+            std::time::Duration::from_millis(100),
+            std::time::Duration::from_secs(600),
+            false,
+            &ProgressReporter::default(),
+        )
+        .await
+        .unwrap();
+    }
+    let rows = db
+        .client
+        .query(
+            "SELECT scan_status, added_files_count FROM filesystem.scan_runs ORDER BY scan_id",
+            &[],
+        )
+        .await
+        .unwrap();
+    let scans: Vec<(String, i64)> = rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+    assert_eq!(
+        scans,
+        [
+            ("completed".to_string(), 15),
+            ("completed".to_string(), 8),
+            ("completed".to_string(), 3)
+        ]
+    );
+}
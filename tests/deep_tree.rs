@@ -0,0 +1,89 @@
+//! Crawls of a synthetic tree nested 10k directories deep, its paths far
+//! beyond `PATH_MAX`: the dirfd walker must record every file, where the
+//! parallel walker loses the subtrees it cannot name.
+//!
+//! The tree is built by changing into each new level, which no other test
+//! of this binary may race with; keep it to this one test.
+
+mod common;
+
+use common::{Crawl, crawl};
+use fs_delta_tracker::content_hash::HashAlgorithm;
+use fs_delta_tracker::crawler::{CrawlOptions, WalkerBackend};
+
+/// Nesting of the tree; with 2-byte components its deepest path is five
+/// times PATH_MAX on Linux
+const DEPTH: usize = 10_000;
+
+/// A sibling directory holding a file is added every this many levels, so
+/// that the walk climbs back up to directories it had to close
+const SIBLING_EVERY: usize = 1_000;
+
+/// Nest `DEPTH` directories `d` below `root` with a file `bottom` in the
+/// deepest, and a directory `s` holding a file `f` next to every
+/// `SIBLING_EVERY`th `d`. Built level by level from inside, as the paths are
+/// too long to name.
+fn build_deep_tree(root: &std::path::Path) {
+    let cwd = std::env::current_dir().unwrap();
+    std::env::set_current_dir(root).unwrap();
+    for level in 0..DEPTH {
+        if level % SIBLING_EVERY == 0 {
+            std::fs::create_dir("s").unwrap();
+            std::fs::write("s/f", level.to_string()).unwrap();
+        }
+        std::fs::create_dir("d").unwrap();
+        std::env::set_current_dir("d").unwrap();
+    }
+    std::fs::write("bottom", "bottom").unwrap();
+    std::env::set_current_dir(cwd).unwrap();
+}
+
+#[tokio::test]
+async fn dirfd_walker_crawls_beyond_path_max() {
+    let base = tempfile::tempdir().unwrap();
+    build_deep_tree(base.path());
+    let files = DEPTH / SIBLING_EVERY + 1;
+
+    let Crawl { lines, report, .. } = crawl(
+        base.path(),
+        &CrawlOptions {
+            walker: WalkerBackend::Dirfd,
+            content_hash: Some(HashAlgorithm::Xxh3),
+            ..CrawlOptions::default()
+        },
+    )
+    .await;
+    assert_eq!(lines.len(), files);
+    let bottom: Vec<&str> = lines
+        .iter()
+        .find(|line| line.starts_with("bottom\t"))
+        .expect("the deepest file is recorded")
+        .split('\t')
+        .collect();
+    let expected = base.path().join("d/".repeat(DEPTH)).join("bottom");
+    assert_eq!(bottom[2], expected.display().to_string());
+    assert_eq!(bottom[3], "6");
+    // read through its directory's descriptor
+    assert!(bottom[6].starts_with("xxh3:"), "{:?}", bottom);
+    assert_eq!(report.metadata["max_depth"], (DEPTH + 1).to_string());
+    assert_eq!(
+        report.metadata["directory_count"],
+        (DEPTH + files).to_string()
+    );
+    assert_eq!(report.metadata["walker"], "dirfd");
+
+    // only what lies within PATH_MAX
+    let Crawl { lines, .. } = crawl(base.path(), &CrawlOptions::default()).await;
+    assert!(lines.len() < files, "{} lines", lines.len());
+
+    let Crawl { lines, .. } = crawl(
+        base.path(),
+        &CrawlOptions {
+            walker: WalkerBackend::Dirfd,
+            max_depth: Some(SIBLING_EVERY + 2),
+            ..CrawlOptions::default()
+        },
+    )
+    .await;
+    assert_eq!(lines.len(), 2);
+}
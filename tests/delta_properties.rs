@@ -0,0 +1,268 @@
+//! Property-based check of delta correctness: random before/after trees are
+//! scanned through the full pipeline into an ephemeral embedded PostgreSQL,
+//! and the change set of the second scan must be exactly the diff of the two
+//! trees.
+//!
+//! Needs PostgreSQL's `initdb` and `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test delta_properties -- --ignored
+//! ```
+//!
+//! Half of the cases crawl with `sort_output` and `skip_unchanged`, so that
+//! an unchanged tree is recorded without loading it; of the others, half
+//! stream their crawl into staging instead of loading it from a file. Any case may
+//! load in COPY's binary format. `PROPTEST_CASES` overrides the number of generated
+//! cases.
+
+mod common;
+
+use common::EphemeralDb;
+use fs_delta_tracker::data::CopyFormat;
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+
+/// Size and mtime (in whole seconds, the crawl's resolution) of a file
+type FileState = (i64, i64);
+
+/// Files of a tree by their path relative to its root
+type Tree = BTreeMap<String, FileState>;
+
+/// A delta as recorded in `file_changes`: change type, old and new size and mtime
+type Change = (String, Option<i64>, Option<i64>, Option<i64>, Option<i64>);
+
+/// Paths drawn from a small space so that before and after trees overlap.
+/// Directories carry no extension and files always one, so a path is never
+/// both; names include characters the TSV has to escape.
+fn path() -> impl Strategy<Value = String> {
+    (
+        proptest::collection::vec(proptest::sample::select(vec!["a", "b", "c d"]), 0..3),
+        proptest::sample::select(vec!["x", "y", "tab\there", "back\\slash", "new\nline", "ü"]),
+        proptest::sample::select(vec![".txt", ".tar.gz", ".DAT"]),
+    )
+        .prop_map(|(dirs, name, ext)| {
+            let mut path: String = dirs.iter().map(|dir| format!("{}/", dir)).collect();
+            path.push_str(name);
+            path.push_str(ext);
+            path
+        })
+}
+
+/// Sizes and mtimes from a few values, so that files present in both trees
+/// are often unchanged
+fn tree() -> impl Strategy<Value = Tree> {
+    proptest::collection::btree_map(
+        path(),
+        (0..3i64, (0..3i64).prop_map(|k| 1_600_000_000 + k * 86_400)),
+        0..24,
+    )
+}
+
+/// Before and after trees, sometimes the same to exercise `skip_unchanged`
+fn trees() -> impl Strategy<Value = (Tree, Tree)> {
+    prop_oneof![
+        3 => (tree(), tree()),
+        1 => tree().prop_map(|tree| (tree.clone(), tree)),
+    ]
+}
+
+/// The change set taking `before` to `after`, keyed by relative path
+fn expected_changes(before: &Tree, after: &Tree) -> BTreeMap<String, Change> {
+    let mut changes = BTreeMap::new();
+    for (path, &(old_size, old_mtime)) in before {
+        match after.get(path) {
+            None => {
+                changes.insert(
+                    path.clone(),
+                    (
+                        "deleted".to_string(),
+                        Some(old_size),
+                        None,
+                        Some(old_mtime),
+                        None,
+                    ),
+                );
+            }
+            Some(&(new_size, new_mtime)) if (new_size, new_mtime) != (old_size, old_mtime) => {
+                changes.insert(
+                    path.clone(),
+                    (
+                        "modified".to_string(),
+                        Some(old_size),
+                        Some(new_size),
+                        Some(old_mtime),
+                        Some(new_mtime),
+                    ),
+                );
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, &(new_size, new_mtime)) in after {
+        if !before.contains_key(path) {
+            changes.insert(
+                path.clone(),
+                (
+                    "added".to_string(),
+                    None,
+                    Some(new_size),
+                    None,
+                    Some(new_mtime),
+                ),
+            );
+        }
+    }
+    changes
+}
+
+/// Replace the contents of `root` with `tree`
+fn materialize(root: &std::path::Path, tree: &Tree) -> std::io::Result<()> {
+    if root.exists() {
+        std::fs::remove_dir_all(root)?;
+    }
+    std::fs::create_dir_all(root)?;
+    for (path, &(size, mtime)) in tree {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let file = std::fs::File::create(&path)?;
+        file.set_len(size as u64)?;
+        file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime as u64))?;
+    }
+    Ok(())
+}
+
+/// Path of a recorded file relative to `root`
+fn relative(root: &std::path::Path, file_path: &str) -> String {
+    std::path::Path::new(file_path)
+        .strip_prefix(root)
+        .unwrap_or_else(|_| panic!("{} recorded outside of {}", file_path, root.display()))
+        .to_string_lossy()
+        .to_string()
+}
+
+fn timestamp(mtime: Option<chrono::DateTime<chrono::Utc>>) -> Option<i64> {
+    mtime.map(|mtime| mtime.timestamp())
+}
+
+async fn recorded_changes(
+    client: &tokio_postgres::Client,
+    root: &std::path::Path,
+    scan_id: i32,
+) -> anyhow::Result<BTreeMap<String, Change>> {
+    let rows = client
+        .query(
+            "SELECT file_path, change_type, old_size_bytes, new_size_bytes, old_mtime, new_mtime
+             FROM filesystem.file_changes
+             WHERE scan_id = $1",
+            &[&scan_id],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            (
+                relative(root, row.get(0)),
+                (
+                    row.get(1),
+                    row.get(2),
+                    row.get(3),
+                    timestamp(row.get(4)),
+                    timestamp(row.get(5)),
+                ),
+            )
+        })
+        .collect())
+}
+
+async fn recorded_files(
+    client: &tokio_postgres::Client,
+    root: &std::path::Path,
+) -> anyhow::Result<Tree> {
+    let prefix = format!("{}/", root.display());
+    let rows = client
+        .query(
+            "SELECT file_path, file_size_bytes, file_mtime
+             FROM filesystem.files
+             WHERE starts_with(file_path, $1)",
+            &[&prefix],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let mtime: chrono::DateTime<chrono::Utc> = row.get(2);
+            (relative(root, row.get(0)), (row.get(1), mtime.timestamp()))
+        })
+        .collect())
+}
+
+#[test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+fn deltas_match_tree_diff() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let db = runtime.block_on(EphemeralDb::start()).unwrap();
+    let client = &db.client;
+    let roots = tempfile::Builder::new()
+        .prefix("delta_properties")
+        .tempdir()
+        .unwrap();
+    let cases = std::sync::atomic::AtomicUsize::new(0);
+
+    let mut runner = proptest::test_runner::TestRunner::new(ProptestConfig {
+        failure_persistence: None,
+        ..ProptestConfig::with_cases(32)
+    });
+    runner
+        .run(
+            &(trees(), any::<bool>(), any::<bool>(), any::<bool>()),
+            |((before, after), skip_unchanged, stream_load, binary)| {
+                // A root per case, so cases never see each other's files
+                let case = cases.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let root = roots.path().join(format!("root{}", case));
+                let mut options = ScanOptions::new(root.clone());
+                // sorted crawls are compared by checksum, which never misses a change
+                options.skip_unchanged = skip_unchanged;
+                options.crawl.sort_output = skip_unchanged;
+                options.stream_load = stream_load && !skip_unchanged;
+                if binary {
+                    options.copy_format = CopyFormat::Binary;
+                }
+                let progress = ProgressReporter::default();
+
+                let (changes, files, skipped) = runtime
+                    .block_on(async {
+                        materialize(&root, &before)?;
+                        let first = pipeline::run_scan(client, &options, &progress).await?;
+                        let initial = recorded_changes(client, &root, first).await?;
+                        let all_added = expected_changes(&Tree::new(), &before);
+                        anyhow::ensure!(initial == all_added, "first scan recorded {:?}", initial);
+
+                        materialize(&root, &after)?;
+                        let second = pipeline::run_scan(client, &options, &progress).await?;
+                        let skipped: bool = client
+                            .query_one(
+                                "SELECT scan_metadata ? 'unchanged_since_scan'
+                                 FROM filesystem.scan_runs
+                                 WHERE scan_id = $1",
+                                &[&second],
+                            )
+                            .await?
+                            .get(0);
+                        Ok((
+                            recorded_changes(client, &root, second).await?,
+                            recorded_files(client, &root).await?,
+                            skipped,
+                        ))
+                    })
+                    .map_err(|e| TestCaseError::fail(format!("{:#}", e)))?;
+
+                prop_assert_eq!(changes, expected_changes(&before, &after));
+                prop_assert_eq!(skipped, skip_unchanged && before == after);
+                prop_assert_eq!(files, after);
+                Ok(())
+            },
+        )
+        .unwrap();
+}
@@ -0,0 +1,63 @@
+//! Directories recorded by crawls (`--record-dirs`): entries counted where
+//! they are, files and bytes summed up the tree, by both walkers.
+
+mod common;
+
+use fs_delta_tracker::crawler::{CrawlOptions, WalkerBackend};
+
+/// `(path, entry_count, file_count, total_size_bytes)` of the directories a
+/// crawl of `root` recorded, `$ROOT` standing for `root`
+async fn crawl_dirs(root: &std::path::Path, walker: WalkerBackend) -> Vec<(String, u64, u64, u64)> {
+    let crawl = common::crawl(
+        root,
+        &CrawlOptions {
+            walker,
+            record_dirs: true,
+            ..CrawlOptions::default()
+        },
+    )
+    .await;
+    let dirs: Vec<_> = crawl
+        .dir_lines
+        .iter()
+        .map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            assert_eq!(fields.len(), 6, "{:?}", line);
+            assert_eq!(fields[5], "1");
+            (
+                fields[0].replace(&root.display().to_string(), "$ROOT"),
+                fields[1].parse().unwrap(),
+                fields[2].parse().unwrap(),
+                fields[3].parse().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        crawl.report.metadata["directories_recorded"],
+        dirs.len().to_string()
+    );
+    dirs
+}
+
+#[tokio::test]
+async fn directories_sum_their_subtrees() {
+    let base = tempfile::tempdir().unwrap();
+    let root = base.path().join("data");
+    std::fs::create_dir_all(root.join("a/b")).unwrap();
+    std::fs::create_dir_all(root.join("empty")).unwrap();
+    std::fs::write(root.join("top.txt"), [0; 1]).unwrap();
+    std::fs::write(root.join("a/one.txt"), [0; 10]).unwrap();
+    std::fs::write(root.join("a/b/two.txt"), [0; 100]).unwrap();
+    std::fs::write(root.join("a/b/three.txt"), [0; 1000]).unwrap();
+
+    let dir = |path: &str, entries, files, bytes| (path.to_string(), entries, files, bytes);
+    let expected = [
+        dir("$ROOT", 3, 4, 1111),
+        dir("$ROOT/a", 2, 3, 1110),
+        dir("$ROOT/a/b", 2, 2, 1100),
+        dir("$ROOT/empty", 0, 0, 0),
+    ];
+    assert_eq!(crawl_dirs(&root, WalkerBackend::Parallel).await, expected);
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    assert_eq!(crawl_dirs(&root, WalkerBackend::Dirfd).await, expected);
+}
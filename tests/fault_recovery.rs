@@ -0,0 +1,141 @@
+//! Recovery from injected faults (`fault::FaultPoint`): a scan whose load
+//! loses its connection resumes after the last committed checkpoint, and a
+//! watcher whose inotify queue overflows reports its events lost. Needs a
+//! build with the `testing` feature; the scan test also PostgreSQL's `initdb`
+//! and `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --features testing --test fault_recovery -- --include-ignored
+//! ```
+
+#[cfg(feature = "testing")]
+mod common;
+
+use fs_delta_tracker::fault::{self, FaultPoint};
+
+#[test]
+fn malformed_fault_lists_are_refused() {
+    assert_eq!(
+        fault::parse(" copy_disconnect=5000, process_error ,").unwrap(),
+        [
+            (FaultPoint::CopyDisconnect, 5000),
+            (FaultPoint::ProcessError, 0)
+        ]
+    );
+    assert!(fault::parse("").unwrap().is_empty());
+    assert!(fault::parse("copy_disconnect=many").is_err());
+    assert!(fault::parse("disk_full=3").is_err());
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn interrupted_loads_resume_from_their_checkpoint() {
+    use common::EphemeralDb;
+    use fs_delta_tracker::pipeline::{self, ScanOptions};
+    use fs_delta_tracker::progress::ProgressReporter;
+
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new()
+        .prefix("fault_recovery")
+        .tempdir()
+        .unwrap();
+    for i in 0..25 {
+        std::fs::write(root.path().join(format!("{}.dat", i)), "x").unwrap();
+    }
+    let mut options = ScanOptions::new(root.path().to_path_buf());
+    options.load_checkpoint_rows = 10;
+
+    let guard = fault::arm(FaultPoint::CopyDisconnect, 15);
+    let error = pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+        .await
+        .unwrap_err();
+    drop(guard);
+    assert!(
+        format!("{:#}", error).contains("injected fault"),
+        "{:#}",
+        error
+    );
+    assert_eq!(FaultPoint::CopyDisconnect.armed(), None);
+
+    let scan_id: i32 = db
+        .client
+        .query_one(
+            "SELECT scan_id FROM filesystem.scan_runs WHERE scan_status = 'running'",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    // the first chunk of 10 rows is committed, the next 5 are rolled back
+    let checkpointed: i64 = db
+        .client
+        .query_one(
+            "SELECT rows_loaded FROM filesystem.load_checkpoints WHERE scan_id = $1",
+            &[&scan_id],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(checkpointed, 10);
+
+    pipeline::resume_scan(&db.client, &options, scan_id, &ProgressReporter::default())
+        .await
+        .unwrap();
+
+    let (status, added): (String, i64) = db
+        .client
+        .query_one(
+            "SELECT scan_status, added_files_count FROM filesystem.scan_runs WHERE scan_id = $1",
+            &[&scan_id],
+        )
+        .await
+        .map(|row| (row.get(0), row.get(1)))
+        .unwrap();
+    assert_eq!((status.as_str(), added), ("completed", 25));
+    let mut paths: Vec<String> = db
+        .client
+        .query("SELECT file_path FROM filesystem.files", &[])
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    paths.sort();
+    let mut expected: Vec<String> = (0..25)
+        .map(|i| format!("{}/{}.dat", root.path().display(), i))
+        .collect();
+    expected.sort();
+    assert_eq!(paths, expected);
+}
+
+#[cfg(all(feature = "testing", target_os = "linux"))]
+#[tokio::test]
+async fn overflowed_watches_report_their_events_lost() {
+    use fs_delta_tracker::watch::{WatchBatch, Watcher};
+
+    let queued: usize = std::fs::read_to_string("/proc/sys/fs/inotify/max_queued_events")
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    let root = tempfile::tempdir().unwrap();
+    let guard = fault::arm(FaultPoint::InotifyStall, 0);
+    let mut watcher = Watcher::start(root.path()).unwrap();
+    for i in 0..=queued {
+        std::fs::File::create(root.path().join(i.to_string())).unwrap();
+    }
+    drop(guard);
+
+    let batch = tokio::time::timeout(
+        std::time::Duration::from_secs(30),
+        watcher.next_batch(std::time::Duration::from_millis(300)),
+    )
+    .await
+    .expect("no batch within 30s")
+    .unwrap();
+    match batch {
+        WatchBatch::Lost(reason) => assert_eq!(reason, "the inotify queue overflowed"),
+        WatchBatch::Changes(entries) => panic!("{} events, none lost", entries.lines),
+    }
+}
@@ -0,0 +1,186 @@
+//! Golden-file tests of the record serializers: a tree of adversarial file
+//! names (tabs, newlines, backslashes, emoji, invalid UTF-8, a 4000-char path)
+//! is crawled and exported, and the output must match `tests/golden/` byte
+//! for byte once the temporary root is replaced by `$ROOT`.
+//!
+//! After an intended format change, rewrite the golden files with
+//! `UPDATE_GOLDEN=1` and review their diff. The export test needs
+//! PostgreSQL's `initdb` and `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! UPDATE_GOLDEN=1 cargo test --test golden_serialization -- --include-ignored
+//! ```
+
+mod common;
+
+use common::EphemeralDb;
+use fs_delta_tracker::crawler::{self, CrawlOptions};
+use fs_delta_tracker::export::{self, ExportKind};
+use fs_delta_tracker::pause::PauseSwitch;
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+use std::os::unix::ffi::OsStrExt as _;
+
+/// Scan id the crawl is run under
+const SCAN_ID: i32 = 7;
+
+/// mtime of every fixture file
+const MTIME: u64 = 1_700_000_000;
+
+/// Relative paths of the fixture files, as raw bytes so that one can be
+/// invalid UTF-8
+fn fixture_paths(long_path: bool) -> Vec<Vec<u8>> {
+    let mut paths: Vec<Vec<u8>> = [
+        "plain.txt",
+        "tab\there.txt",
+        "new\nline.txt",
+        "carriage\rreturn.txt",
+        "back\\slash.txt",
+        "trailing\\",
+        "quote\"and,comma.csv",
+        "emoji 🚀.tar.gz",
+        "Ünïcödé.TXT",
+        "no_extension",
+        ".hidden.conf",
+        "dir with spaces/nested\ttab/file.log",
+    ]
+    .iter()
+    .map(|path| path.as_bytes().to_vec())
+    .collect();
+    paths.push(b"invalid-\xff\xfe.bin".to_vec());
+    if !long_path {
+        return paths;
+    }
+
+    // 16 components of 250 characters, each below NAME_MAX; pseudo-random
+    // so that the path does not compress below PostgreSQL's index row limit
+    let mut long = Vec::new();
+    let mut state = 1u32;
+    for _ in 0..16 {
+        for _ in 0..249 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            long.push(b'a' + ((state >> 16) % 26) as u8);
+        }
+        long.push(b'/');
+    }
+    long.extend_from_slice(b"long.txt");
+    paths.push(long);
+    paths
+}
+
+/// Create the fixture tree under `root`, file `i` holding `i` bytes
+fn materialize(root: &std::path::Path, long_path: bool) -> std::io::Result<()> {
+    for (size, path) in fixture_paths(long_path).iter().enumerate() {
+        let path = root.join(std::ffi::OsStr::from_bytes(path));
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let file = std::fs::File::create(&path)?;
+        file.set_len(size as u64)?;
+        file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(MTIME))?;
+    }
+    Ok(())
+}
+
+fn fixture_root(long_path: bool) -> tempfile::TempDir {
+    let root = tempfile::Builder::new()
+        .prefix("golden_serialization")
+        .tempdir()
+        .unwrap();
+    materialize(root.path(), long_path).unwrap();
+    root
+}
+
+/// `output` with the temporary `root` replaced by `$ROOT`
+fn normalize_root(output: &[u8], root: &std::path::Path) -> Vec<u8> {
+    let root = root.as_os_str().as_bytes();
+    let mut normalized = Vec::with_capacity(output.len());
+    let mut rest = output;
+    while let Some(at) = rest.windows(root.len()).position(|w| w == root) {
+        normalized.extend_from_slice(&rest[..at]);
+        normalized.extend_from_slice(b"$ROOT");
+        rest = &rest[at + root.len()..];
+    }
+    normalized.extend_from_slice(rest);
+    normalized
+}
+
+/// Compare `actual` with `tests/golden/<name>`, or rewrite it with `UPDATE_GOLDEN`
+fn assert_golden(name: &str, actual: &[u8]) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read(&path)
+        .unwrap_or_else(|e| panic!("{}: {} (run with UPDATE_GOLDEN=1)", path.display(), e));
+    assert!(
+        actual == expected.as_slice(),
+        "{} differs from the golden file:\n{}",
+        name,
+        String::from_utf8_lossy(actual)
+    );
+}
+
+#[tokio::test]
+async fn crawl_tsv_matches_golden() {
+    let root = fixture_root(true);
+    let out = tempfile::tempdir().unwrap();
+    let tsv = out.path().join("crawl.tsv");
+    crawler::walk_directory(
+        vec![root.path().to_path_buf()],
+        30,
+        SCAN_ID,
+        tsv.clone(),
+        ProgressReporter::default(),
+        &CrawlOptions {
+            sort_output: true,
+            ..CrawlOptions::default()
+        },
+        PauseSwitch::default(),
+    )
+    .await
+    .unwrap();
+
+    // one line per file, whatever its name
+    let written = normalize_root(&std::fs::read(&tsv).unwrap(), root.path());
+    assert_eq!(
+        written.split_inclusive(|&b| b == b'\n').count(),
+        fixture_paths(true).len()
+    );
+    assert_golden("crawl.tsv", &written);
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn snapshot_export_matches_golden() {
+    let db = EphemeralDb::start().await.unwrap();
+    // Without the 4000-char path: the file_path indexes cannot hold keys
+    // over 2704 bytes that do not compress
+    let root = fixture_root(false);
+    let scan_id = pipeline::run_scan(
+        &db.client,
+        &ScanOptions::new(root.path().to_path_buf()),
+        &ProgressReporter::default(),
+    )
+    .await
+    .unwrap();
+
+    // NUL-terminated fields survive any file name, so the records can be
+    // sorted independently of the cluster's collation
+    let out = tempfile::tempdir().unwrap();
+    let export = out.path().join("snapshot.null");
+    db.client
+        .batch_execute("SET TIME ZONE 'UTC'")
+        .await
+        .unwrap();
+    export::export_scan(&db.client, scan_id, ExportKind::Snapshot, &export, true)
+        .await
+        .unwrap();
+    let written = normalize_root(&std::fs::read(&export).unwrap(), root.path());
+    let fields: Vec<&[u8]> = written.split_inclusive(|&b| b == 0).collect();
+    let mut records: Vec<Vec<u8>> = fields.chunks(5).map(|record| record.concat()).collect();
+    records[1..].sort();
+    assert_eq!(records.len(), fixture_paths(false).len() + 1);
+    assert_golden("snapshot_export.null", &records.concat());
+}
@@ -0,0 +1,81 @@
+//! Crawls hashing contents with a hash cache: files whose device, inode,
+//! size and mtime are unchanged since an earlier crawl are not read again.
+
+#![cfg(unix)]
+
+mod common;
+
+use fs_delta_tracker::content_hash::HashAlgorithm;
+use fs_delta_tracker::crawler::{CrawlOptions, WalkerBackend};
+use fs_delta_tracker::hash_cache::HashCache;
+
+/// Crawl `root` with `options`; the fingerprints by file name, and the
+/// cache hits and misses recorded
+async fn fingerprints(
+    root: &std::path::Path,
+    options: &CrawlOptions,
+) -> (Vec<(String, String)>, String, String) {
+    let crawl = common::crawl(root, options).await;
+    let mut fingerprints: Vec<(String, String)> = crawl
+        .fields()
+        .map(|fields| (fields[0].to_string(), fields[6].to_string()))
+        .collect();
+    fingerprints.sort();
+    (
+        fingerprints,
+        crawl.report.metadata["hash_cache_hits"].clone(),
+        crawl.report.metadata["hash_cache_misses"].clone(),
+    )
+}
+
+/// Overwrite the file at `path` with `contents` of the same size, keeping its
+/// mtime, as only reading it again would tell
+fn overwrite_in_place(path: &std::path::Path, contents: &str) {
+    let mtime = std::fs::metadata(path).unwrap().modified().unwrap();
+    std::fs::write(path, contents).unwrap();
+    let file = std::fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(mtime).unwrap();
+}
+
+#[tokio::test]
+async fn unchanged_files_are_not_hashed_again() {
+    for walker in [WalkerBackend::Parallel, WalkerBackend::Dirfd] {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("a.txt"), "first").unwrap();
+        std::fs::write(root.path().join("b.txt"), "other").unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache_path = cache_dir.path().join("hashes.sqlite");
+        let options = CrawlOptions {
+            content_hash: Some(HashAlgorithm::Xxh3),
+            hash_cache: Some(HashCache::open(&cache_path).unwrap()),
+            walker,
+            ..CrawlOptions::default()
+        };
+
+        let (hashed, hits, misses) = fingerprints(root.path(), &options).await;
+        assert_eq!((hits.as_str(), misses.as_str()), ("0", "2"), "{:?}", walker);
+
+        // a cache reopened by a later run; the stale fingerprint shows the
+        // file was not read
+        let options = CrawlOptions {
+            hash_cache: Some(HashCache::open(&cache_path).unwrap()),
+            ..options
+        };
+        overwrite_in_place(&root.path().join("a.txt"), "FIRST");
+        let (cached, hits, misses) = fingerprints(root.path(), &options).await;
+        assert_eq!(cached, hashed, "{:?}", walker);
+        assert_eq!((hits.as_str(), misses.as_str()), ("2", "0"), "{:?}", walker);
+
+        // a new mtime has it read again
+        let a = std::fs::File::options()
+            .write(true)
+            .open(root.path().join("a.txt"))
+            .unwrap();
+        a.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        let (rehashed, hits, misses) = fingerprints(root.path(), &options).await;
+        assert_ne!(rehashed[0].1, hashed[0].1, "{:?}", walker);
+        assert_eq!(rehashed[1], hashed[1], "{:?}", walker);
+        assert_eq!((hits.as_str(), misses.as_str()), ("1", "1"), "{:?}", walker);
+    }
+}
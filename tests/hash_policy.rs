@@ -0,0 +1,166 @@
+//! Which files a crawl hashing contents reads, after the hashing policy of
+//! the filesystem type of its root and those given per root.
+
+mod common;
+
+use fs_delta_tracker::content_hash::{HashAlgorithm, HashPolicy, RootHashPolicy, SMALL_FILE_BYTES};
+use fs_delta_tracker::crawler::CrawlOptions;
+use fs_delta_tracker::fs_type::{self, TuningTable};
+
+/// A root with a small and a big file
+fn tree() -> tempfile::TempDir {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::write(root.path().join("small.txt"), "contents").unwrap();
+    let big = std::fs::File::create(root.path().join("big.bin")).unwrap();
+    big.set_len(SMALL_FILE_BYTES + 1).unwrap();
+    root
+}
+
+/// Crawl `root` hashing with `options`; the files hashed, by name, and the
+/// policy recorded
+async fn hashed_files(
+    root: &std::path::Path,
+    options: &CrawlOptions,
+) -> (Vec<String>, Option<String>) {
+    let crawl = common::crawl(root, options).await;
+    let mut hashed: Vec<String> = crawl
+        .fields()
+        .filter(|fields| fields[6] != "\\N")
+        .map(|fields| fields[0].to_string())
+        .collect();
+    hashed.sort();
+    (hashed, crawl.report.metadata.get("hash_policy").cloned())
+}
+
+#[test]
+fn network_filesystems_hash_less() {
+    let table = TuningTable::default();
+    assert_eq!(table.get("nfs").hash_policy, Some(HashPolicy::SmallFiles));
+    assert_eq!(
+        table.get("lustre").hash_policy,
+        Some(HashPolicy::SmallFiles)
+    );
+    assert_eq!(
+        table.get("cifs").hash_policy,
+        Some(HashPolicy::MetadataOnly)
+    );
+    assert_eq!(table.get("ext4").hash_policy, Some(HashPolicy::Full));
+    assert_eq!(
+        "small-files".parse::<HashPolicy>().unwrap(),
+        HashPolicy::SmallFiles
+    );
+    assert!("some".parse::<HashPolicy>().is_err());
+}
+
+#[tokio::test]
+async fn crawls_hash_by_the_policy_of_their_filesystem() {
+    let root = tree();
+    let Some(fs_type) = fs_type::detect(root.path()) else {
+        // no type to tune by on this platform
+        return;
+    };
+    let cases = [
+        (None, vec!["big.bin", "small.txt"], "full"),
+        (Some("small_files"), vec!["small.txt"], "small_files"),
+        (Some("metadata_only"), vec![], "metadata_only"),
+    ];
+    for (policy, expected, recorded) in cases {
+        let tuning = tempfile::NamedTempFile::new().unwrap();
+        if let Some(policy) = policy {
+            std::fs::write(
+                tuning.path(),
+                format!("[\"{}\"]\nhash_policy = \"{}\"\n", fs_type, policy),
+            )
+            .unwrap();
+        }
+        let options = CrawlOptions {
+            content_hash: Some(HashAlgorithm::Xxh3),
+            fs_tuning: TuningTable::from_file(tuning.path()).unwrap(),
+            ..CrawlOptions::default()
+        };
+        let (hashed, policy) = hashed_files(root.path(), &options).await;
+        assert_eq!(hashed, expected, "{}", recorded);
+        assert_eq!(policy.as_deref(), Some(recorded));
+    }
+}
+
+#[test]
+fn policies_given_per_root_win_over_the_tuned_one() {
+    let policies: Vec<RootHashPolicy> = ["small_files", "/data=full", "/data/nfs=metadata_only"]
+        .iter()
+        .map(|policy| policy.parse().unwrap())
+        .collect();
+    let resolve = |root: &str, tuned| RootHashPolicy::resolve(&policies, root.as_ref(), tuned);
+    // the closest root given
+    assert_eq!(
+        resolve("/data/nfs/a", HashPolicy::Full),
+        HashPolicy::MetadataOnly
+    );
+    assert_eq!(
+        resolve("/data/b", HashPolicy::MetadataOnly),
+        HashPolicy::Full
+    );
+    // otherwise the tuned policy, lowered to the one given for every root
+    assert_eq!(resolve("/home", HashPolicy::Full), HashPolicy::SmallFiles);
+    assert_eq!(
+        resolve("/home", HashPolicy::MetadataOnly),
+        HashPolicy::MetadataOnly
+    );
+    assert_eq!(
+        RootHashPolicy::resolve(&[], "/home".as_ref(), HashPolicy::SmallFiles),
+        HashPolicy::SmallFiles
+    );
+    assert_eq!(
+        "/odd=name=full".parse::<RootHashPolicy>().unwrap(),
+        RootHashPolicy {
+            root: Some("/odd=name".into()),
+            policy: HashPolicy::Full
+        }
+    );
+    assert!("/data=often".parse::<RootHashPolicy>().is_err());
+}
+
+#[tokio::test]
+async fn crawls_hash_by_the_policy_given_for_their_root() {
+    let root = tree();
+    let given = |policy: &str| {
+        vec![
+            format!("{}={}", root.path().display(), policy)
+                .parse()
+                .unwrap(),
+        ]
+    };
+    let cases = [
+        (
+            given("metadata_only"),
+            SMALL_FILE_BYTES,
+            vec![],
+            "metadata_only",
+        ),
+        (
+            given("small_files"),
+            SMALL_FILE_BYTES,
+            vec!["small.txt"],
+            "small_files",
+        ),
+        // "contents" is 8 bytes
+        (given("small_files"), 4, vec![], "small_files"),
+        (
+            vec!["small_files".parse().unwrap()],
+            SMALL_FILE_BYTES + 1,
+            vec!["big.bin", "small.txt"],
+            "small_files",
+        ),
+    ];
+    for (hash_policies, small_file_bytes, expected, recorded) in cases {
+        let options = CrawlOptions {
+            content_hash: Some(HashAlgorithm::Xxh3),
+            hash_policies,
+            small_file_bytes,
+            ..CrawlOptions::default()
+        };
+        let (hashed, policy) = hashed_files(root.path(), &options).await;
+        assert_eq!(hashed, expected, "{} {}", recorded, small_file_bytes);
+        assert_eq!(policy.as_deref(), Some(recorded));
+    }
+}
@@ -0,0 +1,121 @@
+//! Hot directories: reported past `hot_dir_threshold`, capped at
+//! `max_entries_per_dir`, with the tracked files below a capped directory
+//! kept rather than deleted. The scan test needs PostgreSQL's `initdb` and
+//! `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test hot_dirs -- --include-ignored
+//! ```
+
+mod common;
+
+use common::{Crawl, EphemeralDb, crawl};
+use fs_delta_tracker::crawler::{CrawlOptions, WalkerBackend};
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+
+/// `big/` with `count` files and `small.txt` beside it
+fn write_tree(root: &std::path::Path, count: usize) {
+    std::fs::create_dir_all(root.join("big")).unwrap();
+    for i in 0..count {
+        std::fs::write(root.join(format!("big/{}.txt", i)), "x").unwrap();
+    }
+    std::fs::write(root.join("small.txt"), "x").unwrap();
+}
+
+#[tokio::test]
+async fn directories_are_only_counted_when_asked() {
+    let root = tempfile::tempdir().unwrap();
+    write_tree(root.path(), 4);
+
+    for walker in [WalkerBackend::Parallel, WalkerBackend::Dirfd] {
+        let options = CrawlOptions {
+            walker,
+            ..CrawlOptions::default()
+        };
+        let Crawl { report, lines, .. } = crawl(root.path(), &options).await;
+        assert!(report.hot_dirs.is_empty(), "{:?}", walker);
+        assert_eq!(lines.len(), 5, "{:?}", walker);
+
+        let options = CrawlOptions {
+            hot_dir_threshold: Some(3),
+            ..options
+        };
+        let Crawl { report, lines, .. } = crawl(root.path(), &options).await;
+        let hot: Vec<_> = report
+            .hot_dirs
+            .iter()
+            .map(|d| (d.path.clone(), d.entry_count, d.truncated))
+            .collect();
+        assert_eq!(hot, [(root.path().join("big"), 4, false)], "{:?}", walker);
+        assert_eq!(lines.len(), 5, "{:?}", walker);
+
+        let options = CrawlOptions {
+            hot_dir_threshold: None,
+            max_entries_per_dir: Some(2),
+            ..options
+        };
+        let Crawl { report, lines, .. } = crawl(root.path(), &options).await;
+        let truncated: Vec<_> = report
+            .hot_dirs
+            .iter()
+            .map(|d| (d.path.clone(), d.truncated))
+            .collect();
+        assert_eq!(truncated, [(root.path().join("big"), true)], "{:?}", walker);
+        assert_eq!(lines.len(), 3, "{:?}", walker);
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn files_below_capped_directories_are_not_deleted() {
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new()
+        .prefix("hot_dirs")
+        .tempdir()
+        .unwrap();
+    let root_path = root.path().display().to_string();
+    write_tree(root.path(), 4);
+    let mut options = ScanOptions::new(root.path().to_path_buf());
+    let progress = ProgressReporter::default();
+    pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap();
+
+    std::fs::remove_file(root.path().join("small.txt")).unwrap();
+    options.crawl.max_entries_per_dir = Some(2);
+    let scan_id = pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap();
+
+    let changes: Vec<(String, String)> = db
+        .client
+        .query(
+            "SELECT change_type, file_path FROM filesystem.file_changes WHERE scan_id = $1",
+            &[&scan_id],
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| {
+            (
+                row.get(0),
+                row.get::<_, String>(1).replace(&root_path, "$ROOT"),
+            )
+        })
+        .collect();
+    assert_eq!(
+        changes,
+        [("deleted".to_string(), "$ROOT/small.txt".to_string())]
+    );
+    let tracked: i64 = db
+        .client
+        .query_one(
+            "SELECT COUNT(*) FROM filesystem.files WHERE file_path LIKE $1",
+            &[&format!("{}/big/%", root_path)],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(tracked, 4);
+}
@@ -0,0 +1,236 @@
+//! Integrity policies checked against the changes of a real scan. The scan
+//! test needs PostgreSQL's `initdb` and `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test integrity_policy -- --include-ignored
+//! ```
+
+mod common;
+
+use common::{EphemeralDb, write_file, write_policy};
+use fs_delta_tracker::content_hash::HashAlgorithm;
+use fs_delta_tracker::integrity_policy::{Attribute, IntegrityPolicy, Severity};
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+
+#[test]
+fn unknown_attributes_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_policy(
+        dir.path(),
+        r#"
+        [[rules]]
+        pattern = "/etc/**"
+        attributes = ["size", "acl"]
+        severity = "high"
+        "#,
+    );
+    let err = IntegrityPolicy::from_file(&path).unwrap_err();
+    assert!(
+        err.to_string().contains("Unknown monitored attribute"),
+        "{:#}",
+        err
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn changes_monitored_by_first_matching_rule_are_violations() {
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new()
+        .prefix("integrity_policy")
+        .tempdir()
+        .unwrap();
+    let root_path = root.path().display().to_string();
+    let policy = write_policy(
+        root.path(),
+        &format!(
+            r#"
+            [[rules]]
+            pattern = "{root}/conf/*.log"
+            attributes = ["existence"]
+            severity = "low"
+
+            [[rules]]
+            pattern = "{root}/conf/**"
+            attributes = ["existence", "size"]
+            severity = "critical"
+            "#,
+            root = root_path
+        ),
+    );
+    let policy = IntegrityPolicy::from_file(&policy).unwrap();
+    write_file(&root.path().join("conf/app.conf"), 1, 1_700_000_000);
+    write_file(&root.path().join("conf/touched.conf"), 1, 1_700_000_000);
+    write_file(&root.path().join("conf/app.log"), 1, 1_700_000_000);
+    write_file(&root.path().join("data/free.bin"), 1, 1_700_000_000);
+
+    let options = ScanOptions::new(root.path().to_path_buf());
+    let progress = ProgressReporter::default();
+    pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap();
+    write_file(&root.path().join("conf/app.conf"), 2, 1_700_000_000);
+    write_file(&root.path().join("conf/touched.conf"), 1, 1_800_000_000);
+    write_file(&root.path().join("conf/app.log"), 5, 1_800_000_000);
+    write_file(&root.path().join("conf/new.conf"), 1, 1_700_000_000);
+    std::fs::remove_file(root.path().join("data/free.bin")).unwrap();
+    let scan_id = pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap();
+
+    let report = policy.evaluate(&db.client, scan_id).await.unwrap();
+    assert_eq!(report.changes_checked, 5);
+    let violations: Vec<(String, &str, Vec<Attribute>, Severity)> = report
+        .violations
+        .iter()
+        .map(|v| {
+            (
+                v.file_path.replace(&root_path, "$ROOT"),
+                v.change_type.as_str(),
+                v.changed.clone(),
+                v.severity,
+            )
+        })
+        .collect();
+    assert_eq!(
+        violations,
+        [
+            (
+                "$ROOT/conf/app.conf".to_string(),
+                "modified",
+                vec![Attribute::Size],
+                Severity::Critical
+            ),
+            (
+                "$ROOT/conf/new.conf".to_string(),
+                "added",
+                vec![Attribute::Existence],
+                Severity::Critical
+            ),
+        ]
+    );
+    assert_eq!(report.highest_severity, Some(Severity::Critical));
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn hashed_scans_report_content_changes_despite_mtimes() {
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new()
+        .prefix("integrity_policy")
+        .tempdir()
+        .unwrap();
+    let root_path = root.path().display().to_string();
+    let policy = write_policy(
+        root.path(),
+        &format!(
+            r#"
+            [[rules]]
+            pattern = "{root}/bin/*"
+            attributes = ["hash"]
+            severity = "high"
+            "#,
+            root = root_path
+        ),
+    );
+    let policy = IntegrityPolicy::from_file(&policy).unwrap();
+    let write = |name: &str, contents: &[u8], mtime: u64| {
+        let path = root.path().join("bin").join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime))
+            .unwrap();
+    };
+    write("tampered", b"original", 1_700_000_000);
+    write("touched", b"original", 1_700_000_000);
+
+    let mut options = ScanOptions::new(root.path().to_path_buf());
+    options.crawl.content_hash = Some(HashAlgorithm::Blake3);
+    let progress = ProgressReporter::default();
+    pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap();
+    write("tampered", b"replaced", 1_700_000_000);
+    write("touched", b"original", 1_800_000_000);
+    let scan_id = pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap();
+
+    let report = policy.evaluate(&db.client, scan_id).await.unwrap();
+    assert_eq!(report.changes_checked, 1);
+    let violations: Vec<(String, Vec<Attribute>)> = report
+        .violations
+        .iter()
+        .map(|v| (v.file_path.replace(&root_path, "$ROOT"), v.changed.clone()))
+        .collect();
+    assert_eq!(
+        violations,
+        [("$ROOT/bin/tampered".to_string(), vec![Attribute::Hash])]
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn scans_recording_ownership_report_permission_changes() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new()
+        .prefix("integrity_policy")
+        .tempdir()
+        .unwrap();
+    let root_path = root.path().display().to_string();
+    let policy = write_policy(
+        root.path(),
+        &format!(
+            r#"
+            [[rules]]
+            pattern = "{root}/ssh/*"
+            attributes = ["perms", "owner"]
+            severity = "critical"
+            "#,
+            root = root_path
+        ),
+    );
+    let policy = IntegrityPolicy::from_file(&policy).unwrap();
+    let key = root.path().join("ssh/id_ed25519");
+    write_file(&key, 1, 1_700_000_000);
+    std::fs::set_permissions(&key, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+    let mut options = ScanOptions::new(root.path().to_path_buf());
+    options.crawl.record_ownership = true;
+    let progress = ProgressReporter::default();
+    pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap();
+    std::fs::set_permissions(&key, std::fs::Permissions::from_mode(0o644)).unwrap();
+    let scan_id = pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap();
+
+    let report = policy.evaluate(&db.client, scan_id).await.unwrap();
+    let violations: Vec<(String, &str, Vec<Attribute>)> = report
+        .violations
+        .iter()
+        .map(|v| {
+            (
+                v.file_path.replace(&root_path, "$ROOT"),
+                v.change_type.as_str(),
+                v.changed.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        violations,
+        [(
+            "$ROOT/ssh/id_ed25519".to_string(),
+            "permissions_changed",
+            vec![Attribute::Perms]
+        )]
+    );
+}
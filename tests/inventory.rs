@@ -0,0 +1,156 @@
+//! A CSV inventory imported as a scan, its deltas against a real scan of the
+//! root being its discrepancies. The scan test needs PostgreSQL's `initdb` and
+//! `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test inventory -- --include-ignored
+//! ```
+
+mod common;
+
+use common::EphemeralDb;
+use fs_delta_tracker::inventory::{self, InventoryMapping};
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+
+fn write_mapping(dir: &std::path::Path, mapping: &str) -> std::path::PathBuf {
+    let path = dir.join("map.toml");
+    std::fs::write(&path, mapping).unwrap();
+    path
+}
+
+#[test]
+fn incomplete_mappings_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_mapping(
+        dir.path(),
+        "path = \"Path\"\nsize = \"Size\"\nmtime = \"Modified\"\nfingerprint = \"MD5\"\n",
+    );
+    let err = InventoryMapping::from_file(&path).unwrap_err();
+    assert!(
+        err.to_string().contains("fingerprint_algorithm"),
+        "{:#}",
+        err
+    );
+
+    let path = write_mapping(dir.path(), "path = \"Path\"\nsize = \"Size\"\n");
+    let err = InventoryMapping::from_file(&path).unwrap_err();
+    assert!(err.to_string().contains("mtime"), "{:#}", err);
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn discrepancies_are_staged_for_review() {
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new()
+        .prefix("inventory")
+        .tempdir()
+        .unwrap();
+    let root_path = root.path().display().to_string();
+    let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+    for (name, contents) in [
+        ("same.txt", "same"),
+        ("sub/grown.txt", "x"),
+        ("gone.txt", ""),
+    ] {
+        let path = root.path().join("data").join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+    let options = ScanOptions::new(root.path().join("data"));
+    pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+
+    let csv = root.path().join("inventory.csv");
+    std::fs::write(
+        &csv,
+        "\u{feff}Path;Type;Size;Modified\r\n\
+         /vol/same.txt;file;4;1700000000\r\n\
+         /vol/sub/grown.txt;file;10;1700000000\r\n\
+         /vol/sub;dir;0;1700000000\r\n\
+         \"/vol/new;\"\"odd\"\"\nname.txt\";file;7;1700000000\r\n\
+         /other/x.txt;file;1;1700000000\r\n",
+    )
+    .unwrap();
+    let mapping = write_mapping(
+        root.path(),
+        &format!(
+            r#"
+            delimiter = ";"
+            path = "Path"
+            size = "Size"
+            mtime = "Modified"
+            mtime_format = "epoch"
+            type = "Type"
+            file_types = ["file"]
+            rewrite_prefix = {{ from = "/vol", to = "{root}/data" }}
+            "#,
+            root = root_path
+        ),
+    );
+    let mapping = InventoryMapping::from_file(&mapping).unwrap();
+    let (scan_id, stats) = inventory::import_inventory(
+        &db.client,
+        &csv,
+        &mapping,
+        &options,
+        &ProgressReporter::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        (stats.files, stats.other_types, stats.outside_root),
+        (3, 1, 1)
+    );
+
+    let status: String = db
+        .client
+        .query_one(
+            "SELECT scan_status FROM filesystem.scan_runs WHERE scan_id = $1",
+            &[&scan_id],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(status, "pending_review");
+    let changes: Vec<(String, String)> = db
+        .client
+        .query(
+            "SELECT change_type, file_path FROM filesystem.pending_file_changes
+             WHERE scan_id = $1 ORDER BY file_path",
+            &[&scan_id],
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| {
+            (
+                row.get(0),
+                row.get::<_, String>(1).replace(&root_path, "$ROOT"),
+            )
+        })
+        .collect();
+    let change = |change_type: &str, path: &str| (change_type.to_string(), path.to_string());
+    assert_eq!(
+        changes,
+        [
+            change("deleted", "$ROOT/data/gone.txt"),
+            change("added", "$ROOT/data/new;\"odd\"\nname.txt"),
+            change("modified", "$ROOT/data/sub/grown.txt"),
+        ]
+    );
+    let tracked: i64 = db
+        .client
+        .query_one("SELECT count(*) FROM filesystem.files", &[])
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(tracked, 3);
+}
@@ -0,0 +1,160 @@
+//! The database-less mode end to end: two runs over a changing tree, the
+//! second's delta file must hold exactly the changes in between.
+
+mod common;
+
+use common::{MTIME, write_file};
+use fs_delta_tracker::local_state;
+use fs_delta_tracker::pipeline::ScanOptions;
+use fs_delta_tracker::progress::ProgressReporter;
+
+/// Delta lines of `delta_file` without its header, `$ROOT` standing for `root`
+fn delta_lines(delta_file: &std::path::Path, root: &std::path::Path) -> Vec<String> {
+    std::fs::read_to_string(delta_file)
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(|line| line.replace(&root.display().to_string(), "$ROOT"))
+        .collect()
+}
+
+#[tokio::test]
+async fn second_run_records_changes_since_first() {
+    let root = tempfile::tempdir().unwrap();
+    let state_dir = tempfile::tempdir().unwrap();
+    let options = ScanOptions::new(root.path().to_path_buf());
+    let progress = ProgressReporter::default();
+    write_file(&root.path().join("kept.txt"), 1, MTIME);
+    write_file(&root.path().join("grown.txt"), 1, MTIME);
+    write_file(&root.path().join("dir/gone\there.txt"), 1, MTIME);
+
+    let first = local_state::run_local_scan(&options, state_dir.path(), &progress)
+        .await
+        .unwrap();
+    assert_eq!((first.state.run, first.counts.added), (1, 3));
+
+    write_file(&root.path().join("grown.txt"), 5, MTIME);
+    std::fs::remove_dir_all(root.path().join("dir")).unwrap();
+    write_file(&root.path().join("new.txt"), 2, MTIME);
+    let second = local_state::run_local_scan(&options, state_dir.path(), &progress)
+        .await
+        .unwrap();
+
+    assert_eq!(second.state.run, 2);
+    assert_eq!(second.state.total_files, 3);
+    assert_eq!(
+        delta_lines(&second.delta_file, root.path()),
+        [
+            "$ROOT/dir/gone\\there.txt\tdeleted\t1\t\\N\t2023-11-14T22:13:20+00:00\t\\N\ttxt",
+            "$ROOT/grown.txt\tmodified\t1\t5\t2023-11-14T22:13:20+00:00\t2023-11-14T22:13:20+00:00\ttxt",
+            "$ROOT/new.txt\tadded\t\\N\t2\t\\N\t2023-11-14T22:13:20+00:00\t\\N",
+        ]
+    );
+    assert_eq!(
+        local_state::read_state(state_dir.path())
+            .unwrap()
+            .unwrap()
+            .run,
+        2
+    );
+}
+
+#[tokio::test]
+async fn state_of_another_root_is_refused() {
+    let root = tempfile::tempdir().unwrap();
+    let other = tempfile::tempdir().unwrap();
+    let state_dir = tempfile::tempdir().unwrap();
+    let progress = ProgressReporter::default();
+    local_state::run_local_scan(
+        &ScanOptions::new(root.path().to_path_buf()),
+        state_dir.path(),
+        &progress,
+    )
+    .await
+    .unwrap();
+
+    let err = local_state::run_local_scan(
+        &ScanOptions::new(other.path().to_path_buf()),
+        state_dir.path(),
+        &progress,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("holds the state of"), "{:#}", err);
+}
+
+#[test]
+fn label_changes_alone_are_relabels() {
+    let line = |path: &str, label: &str| {
+        format!(
+            "{path}\ttxt\t/r/{path}\t1\t2023-11-14T22:13:20+00:00\t{label}\t1\n",
+            path = path,
+            label = label
+        )
+    };
+    // a snapshot of a run recording no labels ends with the scan_id
+    let previous = [
+        line("a.txt", "system_u:object_r:etc_t:s0"),
+        line("b.txt", "system_u:object_r:etc_t:s0"),
+        line("c.txt", "\\N"),
+        "d.txt\ttxt\t/r/d.txt\t1\t2023-11-14T22:13:20+00:00\t1\n".to_string(),
+    ]
+    .concat();
+    let current = [
+        line("a.txt", "system_u:object_r:etc_t:s0"),
+        line("b.txt", "system_u:object_r:shadow_t:s0"),
+        line("c.txt", "system_u:object_r:etc_t:s0"),
+        line("d.txt", "system_u:object_r:etc_t:s0"),
+    ]
+    .concat();
+
+    let mut out = Vec::new();
+    let counts =
+        local_state::diff_crawls(previous.as_bytes(), current.as_bytes(), &mut out).unwrap();
+    assert_eq!((counts.modified, counts.relabeled), (0, 1));
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "/r/b.txt\trelabeled\t1\t1\t2023-11-14T22:13:20+00:00\t2023-11-14T22:13:20+00:00\ttxt\n"
+    );
+}
+
+#[test]
+fn fingerprints_of_one_algorithm_decide_modifications() {
+    let line = |path: &str, mtime: &str, fingerprint: &str| {
+        format!(
+            "{path}\ttxt\t/r/{path}\t1\t{mtime}\t\\N\t{fingerprint}\t1\n",
+            path = path,
+            mtime = mtime,
+            fingerprint = fingerprint
+        )
+    };
+    let (t1, t2) = ("2023-11-14T22:13:20+00:00", "2024-01-01T00:00:00+00:00");
+    let previous = [
+        line("a.txt", t1, "xxh3:00000000000000aa"),
+        line("b.txt", t1, "xxh3:00000000000000aa"),
+        line("c.txt", t1, "xxh3:00000000000000aa"),
+        line("d.txt", t1, "\\N"),
+    ]
+    .concat();
+    let current = [
+        // touched only
+        line("a.txt", t2, "xxh3:00000000000000aa"),
+        // rewritten in place, mtime kept
+        line("b.txt", t1, "xxh3:00000000000000bb"),
+        // hashed otherwise: fall back to the mtime
+        line("c.txt", t1, "sha256:bb"),
+        line("d.txt", t2, "xxh3:00000000000000aa"),
+    ]
+    .concat();
+
+    let mut out = Vec::new();
+    let counts =
+        local_state::diff_crawls(previous.as_bytes(), current.as_bytes(), &mut out).unwrap();
+    assert_eq!(counts.modified, 2);
+    let paths: Vec<&str> = std::str::from_utf8(&out)
+        .unwrap()
+        .lines()
+        .map(|l| l.split('\t').next().unwrap())
+        .collect();
+    assert_eq!(paths, ["/r/b.txt", "/r/d.txt"]);
+}
@@ -0,0 +1,100 @@
+//! Scans of several roots sharing one scan_id. Needs PostgreSQL's `initdb`
+//! and `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test multi_root -- --ignored
+//! ```
+
+mod common;
+
+use common::{EphemeralDb, MTIME, write_file};
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn roots_share_one_change_set() {
+    let db = EphemeralDb::start().await.unwrap();
+    let base = tempfile::Builder::new()
+        .prefix("multi_root")
+        .tempdir()
+        .unwrap();
+    let (home, srv, other) = (
+        base.path().join("home"),
+        base.path().join("srv"),
+        base.path().join("other"),
+    );
+    write_file(&home.join("a.txt"), 1, MTIME);
+    write_file(&srv.join("b.txt"), 1, MTIME);
+    write_file(&srv.join("gone.txt"), 1, MTIME);
+    write_file(&other.join("c.txt"), 1, MTIME);
+
+    let progress = ProgressReporter::default();
+    // tracked by a scan of its own, which the scans of the other two must not touch
+    pipeline::run_scan(&db.client, &ScanOptions::new(other.clone()), &progress)
+        .await
+        .unwrap();
+    let mut options = ScanOptions::new(home.clone());
+    options.extra_roots = vec![srv.clone(), home.join("sub")];
+    let err = pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("overlap"), "{:#}", err);
+    options.extra_roots = vec![srv.clone()];
+    pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap();
+    write_file(&home.join("a.txt"), 2, MTIME);
+    write_file(&srv.join("new.txt"), 1, MTIME);
+    std::fs::remove_file(srv.join("gone.txt")).unwrap();
+    let scan_id = pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap();
+
+    let row = db
+        .client
+        .query_one(
+            "SELECT scan_root, scan_roots FROM filesystem.scan_runs WHERE scan_id = $1",
+            &[&scan_id],
+        )
+        .await
+        .unwrap();
+    let (scan_root, scan_roots): (String, Vec<String>) = (row.get(0), row.get(1));
+    assert_eq!(scan_root, home.display().to_string());
+    assert_eq!(
+        scan_roots,
+        [home.display().to_string(), srv.display().to_string()]
+    );
+
+    let base_path = base.path().display().to_string();
+    let changes: Vec<(String, String)> = db
+        .client
+        .query(
+            "SELECT file_path, change_type FROM filesystem.file_changes
+             WHERE scan_id = $1 ORDER BY file_path",
+            &[&scan_id],
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| (r.get::<_, String>(0).replace(&base_path, "$BASE"), r.get(1)))
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            ("$BASE/home/a.txt".to_string(), "modified".to_string()),
+            ("$BASE/srv/gone.txt".to_string(), "deleted".to_string()),
+            ("$BASE/srv/new.txt".to_string(), "added".to_string()),
+        ]
+    );
+    let tracked: i64 = db
+        .client
+        .query_one(
+            "SELECT COUNT(*) FROM filesystem.files WHERE file_path = $1",
+            &[&other.join("c.txt").display().to_string()],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(tracked, 1);
+}
@@ -0,0 +1,154 @@
+//! Permission bits, owners and groups recorded with `record_ownership`, and
+//! their changes reported as `permissions_changed`. The scan test needs
+//! PostgreSQL's `initdb` and `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test ownership -- --include-ignored
+//! ```
+
+#![cfg(unix)]
+
+mod common;
+
+use std::os::unix::fs::{MetadataExt as _, PermissionsExt as _};
+
+use common::EphemeralDb;
+use fs_delta_tracker::crawler::{CrawlOptions, WalkerBackend};
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+
+/// `(file name, mode, uid, gid)` of the lines a crawl of `root` wrote, by name
+async fn ownership(root: &std::path::Path, options: &CrawlOptions) -> Vec<[String; 4]> {
+    let mut lines: Vec<_> = common::crawl(root, options)
+        .await
+        .fields()
+        .map(|fields| [0, 8, 9, 10].map(|i| fields[i].to_string()))
+        .collect();
+    lines.sort();
+    lines
+}
+
+#[tokio::test]
+async fn either_walker_records_ownership_when_asked() {
+    let root = tempfile::tempdir().unwrap();
+    let path = root.path().join("script.sh");
+    std::fs::write(&path, "#!/bin/sh").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o4750)).unwrap();
+    let meta = std::fs::metadata(&path).unwrap();
+    let expected = [[
+        "script.sh".to_string(),
+        0o4750.to_string(),
+        meta.uid().to_string(),
+        meta.gid().to_string(),
+    ]];
+
+    for walker in [WalkerBackend::Parallel, WalkerBackend::Dirfd] {
+        let options = CrawlOptions {
+            walker,
+            ..CrawlOptions::default()
+        };
+        assert_eq!(
+            ownership(root.path(), &options).await,
+            [["script.sh", "\\N", "\\N", "\\N"].map(String::from)],
+            "{:?}",
+            walker
+        );
+        let options = CrawlOptions {
+            record_ownership: true,
+            ..options
+        };
+        assert_eq!(
+            ownership(root.path(), &options).await,
+            expected.clone(),
+            "{:?}",
+            walker
+        );
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn mode_changes_are_permission_changes() {
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new()
+        .prefix("ownership")
+        .tempdir()
+        .unwrap();
+    let root_path = root.path().display().to_string();
+    let chmod = |name: &str, mode: u32| {
+        std::fs::set_permissions(
+            root.path().join(name),
+            std::fs::Permissions::from_mode(mode),
+        )
+        .unwrap()
+    };
+    std::fs::write(root.path().join("key.pem"), "secret").unwrap();
+    std::fs::write(root.path().join("other.txt"), "text").unwrap();
+    chmod("key.pem", 0o600);
+    chmod("other.txt", 0o644);
+
+    // a first scan without ownership, whose NULLs are no change
+    let mut options = ScanOptions::new(root.path().to_path_buf());
+    let progress = ProgressReporter::default();
+    pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap();
+    options.crawl.record_ownership = true;
+    let scan_id = pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap();
+    let changes = db
+        .client
+        .query(
+            "SELECT 1 FROM filesystem.file_changes WHERE scan_id = $1",
+            &[&scan_id],
+        )
+        .await
+        .unwrap();
+    assert!(changes.is_empty());
+
+    chmod("key.pem", 0o644);
+    let scan_id = pipeline::run_scan(&db.client, &options, &progress)
+        .await
+        .unwrap();
+    // the owner and group recorded alongside, unchanged
+    let changes: Vec<(String, String, Option<i32>, Option<i32>)> = db
+        .client
+        .query(
+            "SELECT file_path, change_type, old_mode, new_mode
+             FROM filesystem.file_changes
+             WHERE scan_id = $1 AND old_uid = new_uid AND old_gid = new_gid",
+            &[&scan_id],
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| {
+            (
+                row.get::<_, String>(0).replace(&root_path, "$ROOT"),
+                row.get(1),
+                row.get(2),
+                row.get(3),
+            )
+        })
+        .collect();
+    assert_eq!(
+        changes,
+        [(
+            "$ROOT/key.pem".to_string(),
+            "permissions_changed".to_string(),
+            Some(0o600),
+            Some(0o644)
+        )]
+    );
+    let mode: Option<i32> = db
+        .client
+        .query_one(
+            "SELECT file_mode FROM filesystem.files WHERE file_path = $1",
+            &[&format!("{}/key.pem", root_path)],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(mode, Some(0o644));
+}
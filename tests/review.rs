@@ -0,0 +1,123 @@
+//! Scans run with `--review`: their deltas wait in `pending_file_changes`
+//! until promoted as reviewed, a pending row deleted in between being left
+//! unapplied. Needs PostgreSQL's `initdb` and `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test review -- --include-ignored
+//! ```
+
+mod common;
+
+use common::{EphemeralDb, MTIME, write_file};
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn reviewed_deltas_are_promoted_as_left() {
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new().prefix("review").tempdir().unwrap();
+    for name in ["kept.txt", "grown.txt", "rejected.txt", "gone.txt"] {
+        write_file(&root.path().join(name), 1, MTIME);
+    }
+    let mut options = ScanOptions::new(root.path().to_path_buf());
+    pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+
+    write_file(&root.path().join("grown.txt"), 3, MTIME + 60);
+    write_file(&root.path().join("rejected.txt"), 3, MTIME + 60);
+    write_file(&root.path().join("new.txt"), 2, MTIME);
+    std::fs::remove_file(root.path().join("gone.txt")).unwrap();
+    options.review = true;
+    let scan_id = pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+    db.client
+        .execute(
+            "DELETE FROM filesystem.pending_file_changes WHERE file_path LIKE '%/rejected.txt'",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    pipeline::apply_reviewed_scan(&db.client, scan_id)
+        .await
+        .unwrap();
+
+    let root_path = root.path().display().to_string();
+    let changes: Vec<(String, String, Option<String>)> = db
+        .client
+        .query(
+            "SELECT change_type, file_path, old_file_type FROM filesystem.file_changes
+             WHERE scan_id = $1 ORDER BY file_path",
+            &[&scan_id],
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| {
+            (
+                row.get(0),
+                row.get::<_, String>(1).replace(&root_path, "$ROOT"),
+                row.get(2),
+            )
+        })
+        .collect();
+    let change = |change_type: &str, path: &str, old_type: Option<&str>| {
+        (
+            change_type.to_string(),
+            path.to_string(),
+            old_type.map(str::to_string),
+        )
+    };
+    assert_eq!(
+        changes,
+        [
+            change("deleted", "$ROOT/gone.txt", Some("txt")),
+            change("modified", "$ROOT/grown.txt", Some("txt")),
+            change("added", "$ROOT/new.txt", None),
+        ]
+    );
+
+    let pending: i64 = db
+        .client
+        .query_one(
+            "SELECT count(*) FROM filesystem.pending_file_changes WHERE scan_id = $1",
+            &[&scan_id],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(pending, 0);
+
+    // the rejected modification left its file as tracked before
+    let files: Vec<(String, i64, bool)> = db
+        .client
+        .query(
+            "SELECT file_path, file_size_bytes, last_seen_scan = $1
+             FROM filesystem.files ORDER BY file_path",
+            &[&scan_id],
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| {
+            (
+                row.get::<_, String>(0).replace(&root_path, "$ROOT"),
+                row.get(1),
+                row.get(2),
+            )
+        })
+        .collect();
+    let file = |path: &str, size, seen| (path.to_string(), size, seen);
+    assert_eq!(
+        files,
+        [
+            file("$ROOT/grown.txt", 3, true),
+            file("$ROOT/kept.txt", 1, true),
+            file("$ROOT/new.txt", 2, true),
+            file("$ROOT/rejected.txt", 1, false),
+        ]
+    );
+}
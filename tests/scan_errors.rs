@@ -0,0 +1,157 @@
+//! Entries the walker fails to read: reported by the crawl instead of being
+//! dropped, and recorded in `filesystem.scan_errors` by a scan. The scan test
+//! needs PostgreSQL's `initdb` and `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test scan_errors -- --include-ignored
+//! ```
+
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+
+use common::EphemeralDb;
+use fs_delta_tracker::crawler::{self, CrawlOptions, WalkerBackend};
+use fs_delta_tracker::pause::PauseSwitch;
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+
+/// A tree with a symlink loop and a directory the scanning user cannot
+/// list; whether it really cannot, as root can
+fn failing_tree(root: &std::path::Path) -> bool {
+    std::fs::create_dir_all(root.join("dir/locked")).unwrap();
+    std::fs::write(root.join("dir/file.txt"), "contents").unwrap();
+    std::fs::write(root.join("dir/locked/hidden.txt"), "contents").unwrap();
+    std::os::unix::fs::symlink("..", root.join("dir/up")).unwrap();
+    std::fs::set_permissions(
+        root.join("dir/locked"),
+        std::fs::Permissions::from_mode(0o000),
+    )
+    .unwrap();
+    std::fs::read_dir(root.join("dir/locked")).is_err()
+}
+
+/// Let the tree be removed again
+fn unlock(root: &std::path::Path) {
+    std::fs::set_permissions(
+        root.join("dir/locked"),
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+}
+
+#[tokio::test]
+async fn walk_errors_are_reported() {
+    let root = tempfile::tempdir().unwrap();
+    let locked = failing_tree(root.path());
+    let out = tempfile::tempdir().unwrap();
+
+    let backends = [
+        (WalkerBackend::Parallel, true),
+        (WalkerBackend::Parallel, false),
+        (WalkerBackend::Dirfd, false),
+    ];
+    for (walker, follow_symlinks) in backends {
+        let options = CrawlOptions {
+            walker,
+            follow_symlinks,
+            ..CrawlOptions::default()
+        };
+        let report = crawler::walk_directory(
+            vec![root.path().to_path_buf()],
+            30,
+            1,
+            out.path().join("crawl.tsv"),
+            ProgressReporter::default(),
+            &options,
+            PauseSwitch::default(),
+        )
+        .await
+        .unwrap();
+
+        let mut errors: Vec<(String, String)> = report
+            .errors
+            .iter()
+            .map(|error| {
+                (
+                    error.kind.clone(),
+                    error
+                        .path
+                        .display()
+                        .to_string()
+                        .replace(&root.path().display().to_string(), "$ROOT"),
+                )
+            })
+            .collect();
+        errors.sort();
+        let mut expected = Vec::new();
+        if locked {
+            expected.push((
+                "permission_denied".to_string(),
+                "$ROOT/dir/locked".to_string(),
+            ));
+        }
+        if follow_symlinks {
+            expected.insert(0, ("loop".to_string(), "$ROOT/dir/up".to_string()));
+        }
+        assert_eq!(errors, expected, "{:?}", walker);
+        assert_eq!(
+            report.metadata["scan_errors"],
+            expected.len().to_string(),
+            "{:?}",
+            walker
+        );
+    }
+    unlock(root.path());
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn scans_record_their_errors() {
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new()
+        .prefix("scan_errors")
+        .tempdir()
+        .unwrap();
+    let locked = failing_tree(root.path());
+    let mut options = ScanOptions::new(root.path().to_path_buf());
+    options.crawl.follow_symlinks = true;
+    let scan_id = pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+    unlock(root.path());
+
+    let rows = db
+        .client
+        .query(
+            "SELECT error_kind, entry_path FROM filesystem.scan_errors
+             WHERE scan_id = $1 ORDER BY error_kind",
+            &[&scan_id],
+        )
+        .await
+        .unwrap();
+    let errors: Vec<(String, String)> = rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+    let root_path = root.path().display().to_string();
+    let mut expected = vec![("loop".to_string(), format!("{}/dir/up", root_path))];
+    if locked {
+        expected.push((
+            "permission_denied".to_string(),
+            format!("{}/dir/locked", root_path),
+        ));
+    }
+    assert_eq!(errors, expected);
+
+    let metadata: serde_json::Value = db
+        .client
+        .query_one(
+            "SELECT scan_metadata FROM filesystem.scan_runs WHERE scan_id = $1",
+            &[&scan_id],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(metadata["scan_errors"], expected.len().to_string());
+    let by_kind: serde_json::Value =
+        serde_json::from_str(metadata["scan_errors_by_kind"].as_str().unwrap()).unwrap();
+    assert_eq!(by_kind["loop"], 1);
+}
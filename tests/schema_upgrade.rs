@@ -0,0 +1,110 @@
+//! `upgrade_db` on a database created by the first release's `init_db.sql`
+//! (`tests/fixtures/baseline_init_db.sql`): its scans and files survive, and
+//! the next scan runs on the upgraded schema. Needs PostgreSQL's `initdb` and
+//! `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test schema_upgrade -- --include-ignored
+//! ```
+
+mod common;
+
+use common::EphemeralDb;
+use fs_delta_tracker::db;
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn baseline_databases_upgrade_and_scan() {
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new()
+        .prefix("schema_upgrade")
+        .tempdir()
+        .unwrap();
+    std::fs::write(root.path().join("kept.txt"), "a").unwrap();
+    let root_path = root.path().display().to_string();
+
+    db.client
+        .batch_execute("DROP SCHEMA filesystem CASCADE")
+        .await
+        .unwrap();
+    db.client
+        .batch_execute(include_str!("fixtures/baseline_init_db.sql"))
+        .await
+        .unwrap();
+    // a finished scan tracking a file since deleted, and an abandoned one
+    db.client
+        .execute(
+            "INSERT INTO filesystem.scan_runs (scan_root, finished_at) VALUES ($1, now()), ($1, NULL)",
+            &[&root_path],
+        )
+        .await
+        .unwrap();
+    db.client
+        .execute(
+            "INSERT INTO filesystem.files
+                 (file_name, file_type, file_size_bytes, file_path, file_mtime, last_seen_scan)
+             VALUES ('gone.txt', 'txt', 1, $1, now(), 1)",
+            &[&format!("{}/gone.txt", root_path)],
+        )
+        .await
+        .unwrap();
+
+    db::upgrade_schema(&db.client).await.unwrap();
+    // re-running changes nothing
+    db::upgrade_schema(&db.client).await.unwrap();
+
+    let statuses: Vec<String> = db
+        .client
+        .query(
+            "SELECT scan_status FROM filesystem.scan_runs ORDER BY scan_id",
+            &[],
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    assert_eq!(statuses, ["completed", "aborted"]);
+
+    let options = ScanOptions::new(root.path().to_path_buf());
+    let scan_id = pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+    let changes: Vec<(String, String)> = db
+        .client
+        .query(
+            "SELECT change_type, file_path FROM filesystem.file_changes
+             WHERE scan_id = $1 ORDER BY file_path",
+            &[&scan_id],
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| {
+            (
+                row.get(0),
+                row.get::<_, String>(1).replace(&root_path, "$ROOT"),
+            )
+        })
+        .collect();
+    let change = |change_type: &str, path: &str| (change_type.to_string(), path.to_string());
+    assert_eq!(
+        changes,
+        [
+            change("deleted", "$ROOT/gone.txt"),
+            change("added", "$ROOT/kept.txt"),
+        ]
+    );
+    let status: String = db
+        .client
+        .query_one(
+            "SELECT scan_status FROM filesystem.scan_runs WHERE scan_id = $1",
+            &[&scan_id],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(status, "completed");
+}
@@ -0,0 +1,95 @@
+//! The SQLite backend end to end: scans of a changing tree recorded in a
+//! single file, with no database server.
+
+mod common;
+
+use common::{MTIME, write_file};
+use fs_delta_tracker::db::DeltaStore as _;
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+use fs_delta_tracker::sqlite_store::SqliteStore;
+
+async fn scan(store: &SqliteStore, root: &std::path::Path) -> serde_json::Value {
+    let mut options = ScanOptions::new(root.to_path_buf());
+    options.lock_dir = root.parent().unwrap().join("locks");
+    let scan_id = pipeline::run_store_scan(store, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+    store.scan_summary(scan_id).await.unwrap()
+}
+
+/// `(file_path, change_type)` of the deltas of `scan_id`, `$ROOT` standing for `root`
+fn changes(
+    db_path: &std::path::Path,
+    scan_id: i64,
+    root: &std::path::Path,
+) -> Vec<(String, String)> {
+    let connection = rusqlite::Connection::open(db_path).unwrap();
+    let mut query = connection
+        .prepare(
+            "SELECT file_path, change_type FROM file_changes WHERE scan_id = ?1 ORDER BY file_path",
+        )
+        .unwrap();
+    query
+        .query_map([scan_id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .map(|change| {
+            let (path, change_type): (String, String) = change.unwrap();
+            (
+                path.replace(&root.display().to_string(), "$ROOT"),
+                change_type,
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn second_scan_records_changes_since_first() {
+    let base = tempfile::tempdir().unwrap();
+    let root = base.path().join("data");
+    // a root sharing the prefix of the scanned one, left alone by its scans
+    let sibling = base.path().join("data2");
+    let db_path = base.path().join("deltas.db");
+    let store = SqliteStore::open(&db_path).unwrap();
+    write_file(&root.join("kept.txt"), 1, MTIME);
+    write_file(&root.join("grown.txt"), 1, MTIME);
+    write_file(&root.join("dir/gone\there.txt"), 1, MTIME);
+    write_file(&sibling.join("other.txt"), 1, MTIME);
+    scan(&store, &sibling).await;
+
+    let first = scan(&store, &root).await;
+    assert_eq!(first["scan_status"], "completed");
+    assert_eq!(first["added_files_count"], 3);
+    assert_eq!(first["scan_metadata"]["backend"], "sqlite");
+
+    write_file(&root.join("grown.txt"), 5, MTIME);
+    std::fs::remove_dir_all(root.join("dir")).unwrap();
+    write_file(&root.join("new.txt"), 2, MTIME);
+    let second = scan(&store, &root).await;
+    assert_eq!(second["total_paths_count"], 3);
+    assert_eq!(
+        (
+            &second["added_files_count"],
+            &second["modified_files_count"],
+            &second["removed_files_count"]
+        ),
+        (&1.into(), &1.into(), &1.into())
+    );
+    let scan_id = second["scan_id"].as_i64().unwrap();
+    let change = |path: &str, change_type: &str| (path.to_string(), change_type.to_string());
+    assert_eq!(
+        changes(&db_path, scan_id, &root),
+        [
+            change("$ROOT/dir/gone\there.txt", "deleted"),
+            change("$ROOT/grown.txt", "modified"),
+            change("$ROOT/new.txt", "added"),
+        ]
+    );
+
+    // reopening finds the schema in place
+    let store = SqliteStore::open(&db_path).unwrap();
+    let third = scan(&store, &root).await;
+    assert_eq!(third["added_files_count"], 0);
+    assert_eq!(third["removed_files_count"], 0);
+    assert_eq!(scan(&store, &sibling).await["removed_files_count"], 0);
+}
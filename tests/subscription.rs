@@ -0,0 +1,209 @@
+//! Subscribed consumers notified of the slice of a scan's changes matching
+//! their filters, through an outbox retrying failed deliveries. The database
+//! tests need PostgreSQL's `initdb` and
+//! `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test subscription -- --include-ignored
+//! ```
+
+mod common;
+
+use common::EphemeralDb;
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+use fs_delta_tracker::subscription::{self, DeliveryOptions, Subscription};
+
+fn subscription(consumer: &str, path_prefix: Option<&str>, change_types: &[&str]) -> Subscription {
+    Subscription {
+        consumer: consumer.to_string(),
+        path_prefix: path_prefix.map(str::to_string),
+        change_types: (!change_types.is_empty())
+            .then(|| change_types.iter().map(|t| t.to_string()).collect()),
+        webhook_url: None,
+    }
+}
+
+#[test]
+fn consumers_must_be_channel_names() {
+    for consumer in ["archive", "_hsm_2", "a".repeat(63).as_str()] {
+        subscription(consumer, None, &[]).validate().unwrap();
+    }
+    for consumer in [
+        "",
+        "Archive",
+        "2nd",
+        "hsm-tier",
+        "a; NOTIFY x",
+        &"a".repeat(64),
+    ] {
+        assert!(
+            subscription(consumer, None, &[]).validate().is_err(),
+            "{:?}",
+            consumer
+        );
+    }
+    let mut hook = subscription("archive", None, &[]);
+    hook.webhook_url = Some("ftp://catalog.example.org/changes".to_string());
+    assert!(hook.validate().is_err());
+    let err = subscription("archive", None, &["renamed"])
+        .validate()
+        .unwrap_err();
+    assert!(err.to_string().contains("renamed"), "{:#}", err);
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn consumers_receive_their_slice() {
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new()
+        .prefix("subscription")
+        .tempdir()
+        .unwrap();
+    let root_path = root.path().display().to_string();
+    for name in ["raw/a.dat", "raw/b.dat", "rawer/c.dat", "docs/d.txt"] {
+        let path = root.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "x").unwrap();
+    }
+    let options = ScanOptions::new(root.path().to_path_buf());
+    pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+
+    let prefix = format!("{}/raw/", root_path);
+    for subscription in [
+        subscription("raw_removals", Some(&prefix), &["deleted"]),
+        subscription("everything", None, &[]),
+        subscription("idle", None, &[]),
+    ] {
+        subscription::set_subscription(&db.client, &subscription)
+            .await
+            .unwrap();
+    }
+    subscription::remove_subscription(&db.client, "idle")
+        .await
+        .unwrap();
+    let consumers: Vec<_> = subscription::list_subscriptions(&db.client)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|subscription| (subscription.consumer, subscription.path_prefix))
+        .collect();
+    assert_eq!(
+        consumers,
+        [
+            ("everything".to_string(), None),
+            (
+                "raw_removals".to_string(),
+                Some(format!("{}/raw", root_path))
+            )
+        ]
+    );
+
+    let mut raw_removals = subscription::listen(&db.database_url, "raw_removals")
+        .await
+        .unwrap();
+    let mut everything = subscription::listen(&db.database_url, "everything")
+        .await
+        .unwrap();
+    std::fs::remove_file(root.path().join("raw/a.dat")).unwrap();
+    std::fs::remove_file(root.path().join("rawer/c.dat")).unwrap();
+    std::fs::write(root.path().join("raw/new.dat"), "y").unwrap();
+    let scan_id = pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+
+    let received = |payloads: &mut tokio::sync::mpsc::UnboundedReceiver<String>, n| {
+        let mut changes = Vec::new();
+        for _ in 0..n {
+            let payload = payloads.try_recv().expect("a notification is missing");
+            let change: serde_json::Value = serde_json::from_str(&payload).unwrap();
+            assert_eq!(change["scan_id"], scan_id);
+            changes.push(format!(
+                "{} {}",
+                change["change_type"].as_str().unwrap(),
+                change["path"]
+                    .as_str()
+                    .unwrap()
+                    .replace(&root_path, "$ROOT")
+            ));
+        }
+        assert!(payloads.try_recv().is_err(), "more than {} changes", n);
+        changes
+    };
+    // notifications reach the listening connections shortly after the commit
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    assert_eq!(received(&mut raw_removals, 1), ["deleted $ROOT/raw/a.dat"]);
+    assert_eq!(
+        received(&mut everything, 3),
+        [
+            "deleted $ROOT/raw/a.dat",
+            "added $ROOT/raw/new.dat",
+            "deleted $ROOT/rawer/c.dat"
+        ]
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn failed_deliveries_are_retried_then_dead_lettered() {
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new().prefix("outbox").tempdir().unwrap();
+    std::fs::write(root.path().join("a.dat"), "x").unwrap();
+    // a port nothing listens on
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let mut hook = subscription("catalog", None, &[]);
+    hook.webhook_url = Some(format!("http://127.0.0.1:{}/changes", port));
+    subscription::set_subscription(&db.client, &hook)
+        .await
+        .unwrap();
+
+    let options = ScanOptions::new(root.path().to_path_buf());
+    pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+    let events = &subscription::consumer_events(&db.client).await.unwrap()[0];
+    assert_eq!((events.pending, events.delivered), (1, 0));
+    assert!(events.last_error.is_some());
+
+    // due again only after its backoff
+    let delivery = DeliveryOptions {
+        max_attempts: 2,
+        ..Default::default()
+    };
+    let stats = subscription::deliver_events(&db.client, &delivery)
+        .await
+        .unwrap();
+    assert_eq!(stats.failed + stats.dead_lettered, 0);
+    db.client
+        .batch_execute("UPDATE filesystem.event_outbox SET next_attempt_at = now()")
+        .await
+        .unwrap();
+    let stats = subscription::deliver_events(&db.client, &delivery)
+        .await
+        .unwrap();
+    assert_eq!(stats.dead_lettered, 1);
+
+    assert_eq!(
+        subscription::requeue_dead_letters(&db.client, "catalog")
+            .await
+            .unwrap(),
+        1
+    );
+    let events = &subscription::consumer_events(&db.client).await.unwrap()[0];
+    assert_eq!((events.pending, events.dead_lettered), (1, 0));
+    subscription::remove_subscription(&db.client, "catalog")
+        .await
+        .unwrap();
+    assert!(
+        subscription::consumer_events(&db.client)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
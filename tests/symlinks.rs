@@ -0,0 +1,70 @@
+//! Symlinks in a crawl: skipped by default, descended into with
+//! `follow_symlinks`, recorded with their target with `record_symlinks`.
+
+mod common;
+
+use fs_delta_tracker::crawler::{CrawlOptions, WalkerBackend};
+
+/// `(path, symlink_target)` of the lines a crawl of `root` wrote, by path,
+/// `$ROOT` standing for `root`
+async fn targets(root: &std::path::Path, options: &CrawlOptions) -> Vec<(String, String)> {
+    let mut lines: Vec<_> = common::crawl(root, options)
+        .await
+        .fields()
+        .map(|fields| {
+            assert_eq!(fields.len(), 12, "{:?}", fields);
+            (
+                fields[2].replace(&root.display().to_string(), "$ROOT"),
+                fields[7].to_string(),
+            )
+        })
+        .collect();
+    lines.sort();
+    lines
+}
+
+#[tokio::test]
+async fn symlinks_are_skipped_followed_or_recorded() {
+    let base = tempfile::tempdir().unwrap();
+    let root = base.path().join("data");
+    std::fs::create_dir_all(root.join("dir")).unwrap();
+    std::fs::write(root.join("dir/file.txt"), "contents").unwrap();
+    std::os::unix::fs::symlink("dir/file.txt", root.join("file-link")).unwrap();
+    std::os::unix::fs::symlink("dir", root.join("dir-link")).unwrap();
+    std::os::unix::fs::symlink("missing", root.join("dangling")).unwrap();
+
+    let line = |path: &str, target: &str| (path.to_string(), target.to_string());
+    assert_eq!(
+        targets(&root, &CrawlOptions::default()).await,
+        [line("$ROOT/dir/file.txt", "\\N")]
+    );
+    assert_eq!(
+        targets(
+            &root,
+            &CrawlOptions {
+                follow_symlinks: true,
+                ..CrawlOptions::default()
+            }
+        )
+        .await,
+        [
+            line("$ROOT/dir-link/file.txt", "\\N"),
+            line("$ROOT/dir/file.txt", "\\N"),
+            line("$ROOT/file-link", "\\N"),
+        ]
+    );
+    let recorded = [
+        line("$ROOT/dangling", "missing"),
+        line("$ROOT/dir-link", "dir"),
+        line("$ROOT/dir/file.txt", "\\N"),
+        line("$ROOT/file-link", "dir/file.txt"),
+    ];
+    for walker in [WalkerBackend::Parallel, WalkerBackend::Dirfd] {
+        let options = CrawlOptions {
+            record_symlinks: true,
+            walker,
+            ..CrawlOptions::default()
+        };
+        assert_eq!(targets(&root, &options).await, recorded);
+    }
+}
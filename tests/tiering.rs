@@ -0,0 +1,74 @@
+//! Tiering candidates exported from the current files of a real scan. The
+//! scan test needs PostgreSQL's `initdb` and `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test tiering -- --include-ignored
+//! ```
+
+mod common;
+
+use common::{EphemeralDb, write_file, write_policy};
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+use fs_delta_tracker::tiering::{self, TieringFormat, TieringPolicy};
+
+#[test]
+fn access_times_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_policy(dir.path(), "min_age_days = 30\nmin_atime_days = 30\n");
+    let err = TieringPolicy::from_file(&path).unwrap_err();
+    assert!(err.to_string().contains("min_atime_days"), "{:#}", err);
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn oldest_selected_files_are_listed_up_to_the_limit() {
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new()
+        .prefix("tiering")
+        .tempdir()
+        .unwrap();
+    let root_path = root.path().display().to_string();
+    write_file(&root.path().join("data/old.bin"), 2000, 1_500_000_000);
+    write_file(
+        &root.path().join("data/older,\"x\".bin"),
+        3000,
+        1_400_000_000,
+    );
+    write_file(&root.path().join("data/small.bin"), 10, 1_400_000_000);
+    write_file(&root.path().join("data/scratch.tmp"), 4000, 1_300_000_000);
+    write_file(&root.path().join("data/oldest.bin"), 5000, 1_200_000_000);
+    write_file(&root.path().join("data/fresh.bin"), 5000, 4_000_000_000);
+    let options = ScanOptions::new(root.path().to_path_buf());
+    pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+
+    let policy = write_policy(
+        root.path(),
+        &format!(
+            r#"
+            min_age_days = 365
+            min_size_bytes = 1000
+            include = ["{root}/**"]
+            exclude = ["**/*.tmp"]
+            max_bytes = 9000
+            "#,
+            root = root_path
+        ),
+    );
+    let policy = TieringPolicy::from_file(&policy).unwrap();
+    let out = root.path().join("candidates.csv");
+    let stats = tiering::export_tiering(&db.client, &policy, TieringFormat::Csv, &out)
+        .await
+        .unwrap();
+    assert_eq!((stats.files, stats.bytes, stats.truncated), (2, 8000, true));
+    assert_eq!(
+        std::fs::read_to_string(&out)
+            .unwrap()
+            .replace(&root_path, "$ROOT"),
+        "path,size_bytes,mtime\n\
+         $ROOT/data/oldest.bin,5000,2008-01-10T21:20:00+00:00\n\
+         \"$ROOT/data/older,\"\"x\"\".bin\",3000,2014-05-13T16:53:20+00:00\n"
+    );
+}
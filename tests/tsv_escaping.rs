@@ -0,0 +1,118 @@
+//! File names made of the characters the crawl TSV has to escape (tabs,
+//! newlines, carriage returns, backslashes, a literal `\N`) must come out of
+//! a scan exactly as they are on disk, and never split or merge lines. The
+//! property test scans through the SQLite backend, which unescapes the fields
+//! like COPY does, so that no database server is needed; the COPY test loads
+//! them into PostgreSQL, which needs its `initdb` and `pg_ctl` (see
+//! [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test tsv_escaping -- --include-ignored
+//! ```
+
+mod common;
+
+use common::EphemeralDb;
+use fs_delta_tracker::data::CopyFormat;
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+use fs_delta_tracker::sqlite_store::SqliteStore;
+use proptest::prelude::*;
+use std::collections::BTreeSet;
+
+/// File names from fragments around the escape characters; never `.` or `..`
+fn name() -> impl Strategy<Value = String> {
+    proptest::collection::vec(
+        proptest::sample::select(vec!["a", "\t", "\n", "\r", "\\", "\\N", "N", "t", " ", "ü"]),
+        1..6,
+    )
+    .prop_map(|parts| format!("{}.txt", parts.concat()))
+}
+
+/// Scan `root` into a fresh SQLite store and return the tracked paths,
+/// relative to `root`
+async fn tracked_paths(root: &std::path::Path) -> BTreeSet<String> {
+    let db_path = root.parent().unwrap().join("deltas.db");
+    let store = SqliteStore::open(&db_path).unwrap();
+    let mut options = ScanOptions::new(root.to_path_buf());
+    options.lock_dir = root.parent().unwrap().join("locks");
+    pipeline::run_store_scan(&store, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+    let connection = rusqlite::Connection::open(&db_path).unwrap();
+    let mut query = connection.prepare("SELECT file_path FROM files").unwrap();
+    let prefix = format!("{}/", root.display());
+    query
+        .query_map([], |row| row.get::<_, String>(0))
+        .unwrap()
+        .map(|path| path.unwrap().strip_prefix(&prefix).unwrap().to_string())
+        .collect()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn escaped_names_survive_a_scan(names in proptest::collection::btree_set(name(), 1..8)) {
+        let base = tempfile::tempdir().unwrap();
+        let root = base.path().join("data");
+        std::fs::create_dir_all(root.join("sub\tdir\n")).unwrap();
+        let mut expected = BTreeSet::new();
+        for (i, name) in names.iter().enumerate() {
+            // every other file one level down, below a name that needs escaping too
+            let path = if i % 2 == 0 { name.clone() } else { format!("sub\tdir\n/{}", name) };
+            std::fs::write(root.join(&path), name).unwrap();
+            expected.insert(path);
+        }
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        prop_assert_eq!(runtime.block_on(tracked_paths(&root)), expected);
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn escaped_names_survive_copy() {
+    let names = [
+        "tab\there.txt",
+        "new\nline.txt",
+        "carriage\rreturn.txt",
+        "back\\slash.txt",
+        "\\N",
+        "all\t\n\r\\.txt",
+    ];
+    for copy_format in [CopyFormat::Text, CopyFormat::Binary] {
+        let db = EphemeralDb::start().await.unwrap();
+        let base = tempfile::Builder::new()
+            .prefix("tsv_escaping")
+            .tempdir()
+            .unwrap();
+        let root = base.path().join("data");
+        std::fs::create_dir_all(root.join("sub\tdir\n")).unwrap();
+        let mut expected = BTreeSet::new();
+        for name in names {
+            for path in [name.to_string(), format!("sub\tdir\n/{}", name)] {
+                std::fs::write(root.join(&path), name).unwrap();
+                expected.insert((name.to_string(), path));
+            }
+        }
+        let mut options = ScanOptions::new(root.clone());
+        options.copy_format = copy_format;
+        pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+            .await
+            .unwrap();
+
+        let prefix = format!("{}/", root.display());
+        let recorded: BTreeSet<(String, String)> = db
+            .client
+            .query("SELECT file_name, file_path FROM filesystem.files", &[])
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| {
+                let path: String = row.get(1);
+                (row.get(0), path.strip_prefix(&prefix).unwrap().to_string())
+            })
+            .collect();
+        assert_eq!(recorded, expected, "{:?}", copy_format);
+    }
+}
@@ -0,0 +1,214 @@
+//! A watched root: inotify events, through `notify`, gathered into batches, each recorded as a
+//! scan of the entries it names. The scan test needs PostgreSQL's `initdb`
+//! and `pg_ctl` (see [`EphemeralDb`]):
+//!
+//! ```bash
+//! cargo test --test watch -- --include-ignored
+//! ```
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use common::EphemeralDb;
+use fs_delta_tracker::pipeline::{self, ScanOptions};
+use fs_delta_tracker::progress::ProgressReporter;
+use fs_delta_tracker::snapshot_diff::DiffEntries;
+use fs_delta_tracker::watch::{self, WatchBatch, Watcher};
+
+const SETTLE: std::time::Duration = std::time::Duration::from_millis(300);
+
+async fn next_changes(watcher: &mut Watcher) -> DiffEntries {
+    match tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        watcher.next_batch(SETTLE),
+    )
+    .await
+    .expect("no batch within 10s")
+    .unwrap()
+    {
+        WatchBatch::Changes(entries) => entries,
+        WatchBatch::Lost(reason) => panic!("events lost: {}", reason),
+    }
+}
+
+#[tokio::test]
+async fn bursts_are_gathered_into_one_batch() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::create_dir(root.path().join("old")).unwrap();
+    std::fs::write(root.path().join("kept.txt"), "a").unwrap();
+    let mut watcher = Watcher::start(root.path()).unwrap();
+    assert_eq!(watcher.kind(), "inotify");
+
+    std::fs::write(root.path().join("kept.txt"), "ab").unwrap();
+    std::fs::write(root.path().join("kept.txt"), "abc").unwrap();
+    std::fs::create_dir_all(root.path().join("new/deep")).unwrap();
+    std::fs::remove_dir(root.path().join("old")).unwrap();
+    let entries = next_changes(&mut watcher).await;
+    assert_eq!(
+        entries.paths.into_iter().collect::<Vec<_>>(),
+        [root.path().join("kept.txt")]
+    );
+    assert_eq!(
+        entries.new_dirs.into_iter().collect::<Vec<_>>(),
+        [root.path().join("new")]
+    );
+    assert_eq!(
+        entries.old_dirs.into_iter().collect::<Vec<_>>(),
+        [root.path().join("old")]
+    );
+
+    // the directory created in the previous batch is watched too
+    std::fs::write(root.path().join("new/deep/file.txt"), "x").unwrap();
+    let entries = next_changes(&mut watcher).await;
+    assert_eq!(
+        entries.paths.into_iter().collect::<Vec<_>>(),
+        [root.path().join("new/deep/file.txt")]
+    );
+}
+
+#[tokio::test]
+async fn renames_are_followed() {
+    let root = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(root.path().join("dir/sub")).unwrap();
+    std::fs::create_dir(root.path().join("leaving")).unwrap();
+    std::fs::write(root.path().join("before.txt"), "a").unwrap();
+    std::fs::write(outside.path().join("arriving.txt"), "a").unwrap();
+    let mut watcher = Watcher::start(root.path()).unwrap();
+
+    std::fs::rename(
+        root.path().join("before.txt"),
+        root.path().join("after.txt"),
+    )
+    .unwrap();
+    std::fs::rename(root.path().join("dir"), root.path().join("moved")).unwrap();
+    std::fs::rename(root.path().join("leaving"), outside.path().join("left")).unwrap();
+    std::fs::rename(
+        outside.path().join("arriving.txt"),
+        root.path().join("arrived.txt"),
+    )
+    .unwrap();
+    let entries = next_changes(&mut watcher).await;
+    assert_eq!(
+        entries.paths.into_iter().collect::<Vec<_>>(),
+        [
+            root.path().join("after.txt"),
+            root.path().join("arrived.txt"),
+            root.path().join("before.txt"),
+        ]
+    );
+    assert_eq!(
+        entries.new_dirs.into_iter().collect::<Vec<_>>(),
+        [root.path().join("moved")]
+    );
+    assert_eq!(
+        entries.old_dirs.into_iter().collect::<Vec<_>>(),
+        [root.path().join("dir"), root.path().join("leaving")]
+    );
+
+    // the watches follow the renamed directory under its new name, and no
+    // longer report the one moved out
+    std::fs::write(outside.path().join("left/ignored.txt"), "x").unwrap();
+    std::fs::write(root.path().join("moved/sub/file.txt"), "x").unwrap();
+    let entries = next_changes(&mut watcher).await;
+    assert_eq!(
+        entries.paths.into_iter().collect::<Vec<_>>(),
+        [root.path().join("moved/sub/file.txt")]
+    );
+    assert!(entries.new_dirs.is_empty() && entries.old_dirs.is_empty());
+}
+
+#[tokio::test]
+async fn unread_events_past_the_limit_are_reported_lost() {
+    let root = tempfile::tempdir().unwrap();
+    let mut watcher = Watcher::start(root.path()).unwrap();
+    // symlinks, one inotify event each, so the kernel's queue keeps up
+    for i in 0..=watch::MAX_PENDING_EVENTS {
+        std::os::unix::fs::symlink("target", root.path().join(i.to_string())).unwrap();
+    }
+    // let the handler take them all before any is read
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+
+    let batch = tokio::time::timeout(
+        std::time::Duration::from_secs(30),
+        watcher.next_batch(SETTLE),
+    )
+    .await
+    .expect("no batch within 30s")
+    .unwrap();
+    match batch {
+        WatchBatch::Lost(reason) => assert_eq!(
+            reason,
+            format!(
+                "more than {} events were pending",
+                watch::MAX_PENDING_EVENTS
+            )
+        ),
+        WatchBatch::Changes(entries) => panic!("{} events, none lost", entries.lines),
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs PostgreSQL's initdb and pg_ctl, run with --ignored"]
+async fn batches_are_recorded_as_scans() {
+    let db = EphemeralDb::start().await.unwrap();
+    let root = tempfile::Builder::new().prefix("watch").tempdir().unwrap();
+    std::fs::write(root.path().join("gone.txt"), "a").unwrap();
+    std::fs::write(root.path().join("grown.txt"), "a").unwrap();
+    let options = ScanOptions::new(root.path().to_path_buf());
+    let mut watcher = Watcher::start(root.path()).unwrap();
+    pipeline::run_scan(&db.client, &options, &ProgressReporter::default())
+        .await
+        .unwrap();
+
+    std::fs::remove_file(root.path().join("gone.txt")).unwrap();
+    std::fs::write(root.path().join("grown.txt"), "abc").unwrap();
+    std::fs::create_dir(root.path().join("sub")).unwrap();
+    std::fs::write(root.path().join("sub/new.txt"), "x").unwrap();
+    let entries = next_changes(&mut watcher).await;
+    let scan_id = pipeline::run_watch_scan(
+        &db.client,
+        &options,
+        watcher.kind(),
+        entries,
+        &ProgressReporter::default(),
+    )
+    .await
+    .unwrap();
+
+    let root_path = root.path().display().to_string();
+    let changes: Vec<(String, String)> = db
+        .client
+        .query(
+            "SELECT change_type, file_path FROM filesystem.file_changes
+             WHERE scan_id = $1 ORDER BY file_path",
+            &[&scan_id],
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| {
+            (
+                row.get(0),
+                row.get::<_, String>(1).replace(&root_path, "$ROOT"),
+            )
+        })
+        .collect();
+    let change = |change_type: &str, path: &str| (change_type.to_string(), path.to_string());
+    assert_eq!(
+        changes,
+        [
+            change("deleted", "$ROOT/gone.txt"),
+            change("modified", "$ROOT/grown.txt"),
+            change("added", "$ROOT/sub/new.txt"),
+        ]
+    );
+    let tracked: i64 = db
+        .client
+        .query_one("SELECT count(*) FROM filesystem.files", &[])
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(tracked, 2);
+}